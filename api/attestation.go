@@ -0,0 +1,37 @@
+package api
+
+import (
+	"encoding/json"
+)
+
+// IASResponse is the answer of IAS to the verification of a quote of AttestationChallenge, with the parts of its
+// HTTP headers the signature is checked with
+type IASResponse struct {
+	// Report is the body of the response, exactly as it was signed
+	Report string `json:"report"`
+	// Signature is the base64 encoded X-IASReport-Signature header
+	Signature string `json:"signature"`
+	// SigningCert is the PEM certificate of the X-IASReport-Signing-Certificate header, URL-decoded
+	SigningCert string `json:"signing_cert"`
+}
+
+// AttestationVerdict is the result of a successful VerifyAttestationResponse. Verifiers must still compare
+// MrEnclave and MrSigner with the measurements they trust.
+type AttestationVerdict struct {
+	QuoteStatus string   `json:"quote_status"`
+	AdvisoryIDs []string `json:"advisory_ids"`
+	// MrEnclave is the hex encoded measurement of the enclave
+	MrEnclave string `json:"mrenclave"`
+	// MrSigner is the hex encoded hash of the key the enclave is signed with
+	MrSigner string `json:"mrsigner"`
+	// VerifiedAt is when IAS verified the quote, in seconds since the unix epoch
+	VerifiedAt uint64 `json:"verified_at"`
+}
+
+func parseAttestationVerdict(data []byte) (AttestationVerdict, error) {
+	var verdict AttestationVerdict
+	if err := json.Unmarshal(data, &verdict); err != nil {
+		return AttestationVerdict{}, err
+	}
+	return verdict, nil
+}
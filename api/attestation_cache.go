@@ -0,0 +1,124 @@
+package api
+
+import (
+	"crypto/sha256"
+	"encoding/hex"
+	"encoding/json"
+	"io/ioutil"
+	"os"
+	"path/filepath"
+	"sync"
+	"time"
+)
+
+// AttestationCache keeps the answers of attestation services, e.g. the collateral of a QuoteProvider with its
+// certificate chains, so a service being unreachable for a while does not block the node. Entries are kept in
+// memory and, if the cache has a directory, in one file per entry there, so they survive restarts.
+//
+// An entry is fresh for the TTL its user fetches it with. Past that it is fetched again, but kept as a fallback:
+// if the service fails, the stale entry is used for up to MaxStale longer.
+type AttestationCache struct {
+	dir string
+	now func() time.Time
+
+	mu      sync.Mutex
+	entries map[string]attestationCacheEntry
+	// notBefore makes entries fetched until then stale, see ForceRefresh
+	notBefore time.Time
+}
+
+type attestationCacheEntry struct {
+	Key       string          `json:"key"`
+	Value     json.RawMessage `json:"value"`
+	FetchedAt time.Time       `json:"fetched_at"`
+}
+
+// NewAttestationCache returns a cache persisted in dir, which is created if needed. An empty dir keeps the entries
+// in memory only.
+func NewAttestationCache(dir string) (*AttestationCache, error) {
+	if dir != "" {
+		if err := os.MkdirAll(dir, 0700); err != nil {
+			return nil, err
+		}
+	}
+	return &AttestationCache{dir: dir, now: time.Now, entries: make(map[string]attestationCacheEntry)}, nil
+}
+
+// ForceRefresh makes all entries stale, so they are fetched again on their next use. They remain the fallback
+// if the service fails meanwhile.
+func (c *AttestationCache) ForceRefresh() {
+	c.mu.Lock()
+	defer c.mu.Unlock()
+	c.notBefore = c.now()
+}
+
+func (c *AttestationCache) path(key string) string {
+	hash := sha256.Sum256([]byte(key))
+	return filepath.Join(c.dir, hex.EncodeToString(hash[:])+".json")
+}
+
+func (c *AttestationCache) get(key string) (attestationCacheEntry, bool) {
+	c.mu.Lock()
+	defer c.mu.Unlock()
+	if entry, ok := c.entries[key]; ok {
+		return entry, true
+	}
+	if c.dir == "" {
+		return attestationCacheEntry{}, false
+	}
+	data, err := ioutil.ReadFile(c.path(key))
+	if err != nil {
+		return attestationCacheEntry{}, false
+	}
+	var entry attestationCacheEntry
+	// unreadable files are fetched again and overwritten
+	if err := json.Unmarshal(data, &entry); err != nil || entry.Key != key {
+		return attestationCacheEntry{}, false
+	}
+	c.entries[key] = entry
+	return entry, true
+}
+
+func (c *AttestationCache) put(key string, value []byte) error {
+	entry := attestationCacheEntry{Key: key, Value: value, FetchedAt: c.now()}
+	c.mu.Lock()
+	defer c.mu.Unlock()
+	c.entries[key] = entry
+	if c.dir == "" {
+		return nil
+	}
+	data, err := json.Marshal(entry)
+	if err != nil {
+		return err
+	}
+	// written to a temporary file first, so a crash does not leave a truncated entry
+	tmp := c.path(key) + ".tmp"
+	if err := ioutil.WriteFile(tmp, data, 0600); err != nil {
+		return err
+	}
+	return os.Rename(tmp, c.path(key))
+}
+
+// fetch returns the value of key, calling fetchValue if it is not fresh for ttl. If fetchValue fails, a stale
+// value is returned for up to maxStale past ttl.
+func (c *AttestationCache) fetch(key string, ttl time.Duration, maxStale time.Duration, fetchValue func() ([]byte, error)) ([]byte, error) {
+	entry, cached := c.get(key)
+	c.mu.Lock()
+	now, notBefore := c.now(), c.notBefore
+	c.mu.Unlock()
+	age := now.Sub(entry.FetchedAt)
+	if cached && age < ttl && entry.FetchedAt.After(notBefore) {
+		return entry.Value, nil
+	}
+
+	value, err := fetchValue()
+	if err != nil {
+		if cached && age < ttl+maxStale {
+			return entry.Value, nil
+		}
+		return nil, err
+	}
+	// failing to persist the value still keeps it in memory
+	_ = c.put(key, value)
+	return value, nil
+}
@@ -0,0 +1,79 @@
+package api
+
+import (
+	"errors"
+	"io/ioutil"
+	"os"
+	"testing"
+	"time"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestAttestationCachePersists(t *testing.T) {
+	tmpdir, err := ioutil.TempDir("", "go-cosmwasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tmpdir)
+
+	cache, err := NewAttestationCache(tmpdir)
+	require.NoError(t, err)
+	value, err := cache.fetch("collateral/00906ea10000/processor", time.Hour, 0, func() ([]byte, error) {
+		return []byte(`{"tcb_info":{}}`), nil
+	})
+	require.NoError(t, err)
+	require.Equal(t, `{"tcb_info":{}}`, string(value))
+
+	// a restarted node reads the entry from disk
+	reopened, err := NewAttestationCache(tmpdir)
+	require.NoError(t, err)
+	value, err = reopened.fetch("collateral/00906ea10000/processor", time.Hour, 0, func() ([]byte, error) {
+		return nil, errors.New("fetched again")
+	})
+	require.NoError(t, err)
+	require.Equal(t, `{"tcb_info":{}}`, string(value))
+}
+
+func TestAttestationCacheOutagesAndRefresh(t *testing.T) {
+	now := time.Unix(1600000000, 0)
+	cache, err := NewAttestationCache("")
+	require.NoError(t, err)
+	cache.now = func() time.Time { return now }
+	fetches := 0
+	outage := false
+	fetch := func() ([]byte, error) {
+		if outage {
+			return nil, errors.New("service unavailable")
+		}
+		fetches++
+		return []byte{'0' + byte(fetches)}, nil
+	}
+
+	value, err := cache.fetch("key", time.Minute, time.Hour, fetch)
+	require.NoError(t, err)
+	require.Equal(t, "1", string(value))
+
+	// stale entries are used while the service fails, until maxStale ran out
+	outage = true
+	now = now.Add(30 * time.Minute)
+	value, err = cache.fetch("key", time.Minute, time.Hour, fetch)
+	require.NoError(t, err)
+	require.Equal(t, "1", string(value))
+	now = now.Add(31 * time.Minute)
+	_, err = cache.fetch("key", time.Minute, time.Hour, fetch)
+	require.Error(t, err)
+
+	outage = false
+	value, err = cache.fetch("key", time.Minute, time.Hour, fetch)
+	require.NoError(t, err)
+	require.Equal(t, "2", string(value))
+
+	// a forced refresh fetches fresh entries again
+	cache.ForceRefresh()
+	now = now.Add(time.Second)
+	value, err = cache.fetch("key", time.Minute, time.Hour, fetch)
+	require.NoError(t, err)
+	require.Equal(t, "3", string(value))
+	value, err = cache.fetch("key", time.Minute, time.Hour, fetch)
+	require.NoError(t, err)
+	require.Equal(t, "3", string(value))
+}
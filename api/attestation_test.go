@@ -0,0 +1,32 @@
+package api
+
+import (
+	"encoding/json"
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestIASResponseJSON(t *testing.T) {
+	response := IASResponse{Report: `{"id":"1"}`, Signature: "c2ln", SigningCert: "-----BEGIN CERTIFICATE-----"}
+	bz, err := json.Marshal(response)
+	require.NoError(t, err)
+	require.Equal(t, `{"report":"{\"id\":\"1\"}","signature":"c2ln","signing_cert":"-----BEGIN CERTIFICATE-----"}`, string(bz))
+}
+
+func TestParseAttestationVerdict(t *testing.T) {
+	data := []byte(`{"quote_status":"SW_HARDENING_NEEDED","advisory_ids":["INTEL-SA-00334"],` +
+		`"mrenclave":"0707","mrsigner":"0808","verified_at":1591619696}`)
+	verdict, err := parseAttestationVerdict(data)
+	require.NoError(t, err)
+	require.Equal(t, AttestationVerdict{
+		QuoteStatus: "SW_HARDENING_NEEDED",
+		AdvisoryIDs: []string{"INTEL-SA-00334"},
+		MrEnclave:   "0707",
+		MrSigner:    "0808",
+		VerifiedAt:  1591619696,
+	}, verdict)
+
+	_, err = parseAttestationVerdict([]byte(`[]`))
+	require.Error(t, err)
+}
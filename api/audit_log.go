@@ -0,0 +1,21 @@
+package api
+
+import "encoding/json"
+
+// AuditEntry is a privileged operation recorded in the audit log, see ReadAuditLog
+type AuditEntry struct {
+	// TimeMs is when the operation returned, in milliseconds since the unix epoch
+	TimeMs uint64 `json:"time_ms"`
+	// Operation is the name of the operation, e.g. "init_node"
+	Operation string `json:"operation"`
+	// ParamsHash is the hex encoded SHA-256 of the parameters, each prefixed with its length as big endian uint64
+	ParamsHash string `json:"params_hash"`
+	// Error is why the operation failed, nil if it succeeded
+	Error *string `json:"error"`
+}
+
+func parseAuditLog(data []byte) ([]AuditEntry, error) {
+	var entries []AuditEntry
+	err := json.Unmarshal(data, &entries)
+	return entries, err
+}
@@ -0,0 +1,19 @@
+package api
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestParseAuditLog(t *testing.T) {
+	data := []byte(`[{"time_ms":1600000000000,"operation":"init_bootstrap","params_hash":"e3b0","error":null},` +
+		`{"time_ms":1600000000001,"operation":"init_node","params_hash":"2a2a","error":"Enclave error"}]`)
+	entries, err := parseAuditLog(data)
+	require.NoError(t, err)
+	require.Len(t, entries, 2)
+	require.Equal(t, "init_bootstrap", entries[0].Operation)
+	require.Nil(t, entries[0].Error)
+	require.Equal(t, uint64(1600000000001), entries[1].TimeMs)
+	require.Equal(t, "Enclave error", *entries[1].Error)
+}
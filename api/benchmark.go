@@ -0,0 +1,39 @@
+package api
+
+import (
+	"encoding/json"
+)
+
+// BenchmarkKind selects what RunBenchmark measures
+type BenchmarkKind int32
+
+const (
+	BenchmarkDbGet        BenchmarkKind = 0
+	BenchmarkDbSet        BenchmarkKind = 1
+	BenchmarkIteratorNext BenchmarkKind = 2
+	BenchmarkQuery        BenchmarkKind = 3
+	BenchmarkAPICall      BenchmarkKind = 4
+	// BenchmarkEmptyEcall measures the cheapest call into the enclave, which does not touch Go at all
+	BenchmarkEmptyEcall BenchmarkKind = 5
+)
+
+// BenchmarkReport is the outcome of RunBenchmark
+type BenchmarkReport struct {
+	// Kind is the snake_case name of the benchmark, e.g. "db_get"
+	Kind         string `json:"kind"`
+	Iterations   uint32 `json:"iterations"`
+	TotalNanos   uint64 `json:"total_nanos"`
+	NanosPerCall uint64 `json:"nanos_per_call"`
+	// GasPerCall is the average gas reported by the callbacks
+	GasPerCall uint64 `json:"gas_per_call"`
+	// Errors counts the calls which failed. They are timed like the others.
+	Errors uint32 `json:"errors"`
+}
+
+func parseBenchmarkReport(data []byte) (*BenchmarkReport, error) {
+	var report BenchmarkReport
+	if err := json.Unmarshal(data, &report); err != nil {
+		return nil, err
+	}
+	return &report, nil
+}
@@ -0,0 +1,16 @@
+package api
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestParseBenchmarkReport(t *testing.T) {
+	data := []byte(`{"kind":"db_get","iterations":10,"total_nanos":5000,"nanos_per_call":500,"gas_per_call":1000,"errors":0}`)
+	report, err := parseBenchmarkReport(data)
+	require.NoError(t, err)
+	require.Equal(t, "db_get", report.Kind)
+	require.Equal(t, uint64(500), report.NanosPerCall)
+	require.Equal(t, uint64(1000), report.GasPerCall)
+}
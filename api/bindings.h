@@ -9,13 +9,118 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+/**
+ * What `run_benchmark` measures
+ *
+ */
+enum BenchmarkKind {
+  BenchmarkKind_DbGet = 0,
+  BenchmarkKind_DbSet = 1,
+  BenchmarkKind_IteratorNext = 2,
+  BenchmarkKind_Query = 3,
+  BenchmarkKind_ApiCall = 4,
+  /**
+   * The cheapest call into the enclave, which does not touch Go at all
+   */
+  BenchmarkKind_EmptyEcall = 5,
+};
+typedef int32_t BenchmarkKind;
+
 enum ErrnoValue {
   ErrnoValue_Success = 0,
   ErrnoValue_Other = 1,
   ErrnoValue_OutOfGas = 2,
+  ErrnoValue_CallDepthExceeded = 3,
+  ErrnoValue_ResponseTooLarge = 4,
+  ErrnoValue_UnsupportedFeatures = 5,
+  ErrnoValue_Timeout = 6,
+  ErrnoValue_Throttled = 7,
+  ErrnoValue_InvalidEvents = 8,
+  ErrnoValue_EventLimitExceeded = 9,
+  ErrnoValue_CallDenied = 10,
+  ErrnoValue_ContractPaused = 11,
+  ErrnoValue_IllegalReentrancy = 12,
+  ErrnoValue_IteratorLimitExceeded = 13,
+  ErrnoValue_InvalidMsg = 14,
+  ErrnoValue_TcbOutdated = 15,
+  ErrnoValue_CapabilityDenied = 16,
 };
 typedef int32_t ErrnoValue;
 
+/**
+ * How the exported entries are encoded
+ *
+ */
+enum ExportMode {
+  /**
+   * Entries are exported as stored, i.e. encrypted with the contract's current state key
+   */
+  ExportMode_Ciphertext = 0,
+  /**
+   * The enclave re-encrypts every entry before it leaves the library
+   */
+  ExportMode_Reencrypt = 1,
+  /**
+   * Only the keys are exported, with empty values, e.g. to count or list the entries
+   */
+  ExportMode_Keys = 2,
+};
+typedef int32_t ExportMode;
+
+/**
+ * The outcome of `init_node`, telling the node operator what to fix if registration failed.
+ *
+ */
+enum InitNodeResult {
+  InitNodeResult_Success = 0,
+  /**
+   * The master certificate or the encrypted seed is malformed
+   */
+  InitNodeResult_InvalidInput = 1,
+  /**
+   * The master certificate is not a valid certificate of the network
+   */
+  InitNodeResult_InvalidMasterCert = 2,
+  /**
+   * The master certificate was issued by a different enclave build
+   */
+  InitNodeResult_MrEnclaveMismatch = 3,
+  /**
+   * The master certificate was issued by an enclave signed with a different key
+   */
+  InitNodeResult_MrSignerMismatch = 4,
+  /**
+   * The encrypted seed was not encrypted to this node's registration key
+   */
+  InitNodeResult_SeedDecryptionFailed = 5,
+  /**
+   * The sealed node files were created by another enclave or on another machine
+   */
+  InitNodeResult_SealedFileMismatch = 6,
+  /**
+   * Any other failure of the enclave or the SGX sdk
+   */
+  InitNodeResult_EnclaveError = 7,
+  /**
+   * The loaded enclave is not the one this library was built with
+   */
+  InitNodeResult_LibraryMismatch = 8,
+};
+typedef int32_t InitNodeResult;
+
+/**
+ * What the items of an iterator contain
+ *
+ */
+enum IteratorMode {
+  IteratorMode_KeysAndValues = 0,
+  /**
+   * Values are not transported and come back empty, for scans which only need the keys
+   */
+  IteratorMode_KeysOnly = 1,
+};
+typedef int32_t IteratorMode;
+
 /**
  * This enum gives names to the status codes returned from Go callbacks to Rust.
  *
@@ -75,6 +180,16 @@ typedef struct iterator_t {
 
 typedef struct Iterator_vtable {
   int32_t (*next_db)(iterator_t, gas_meter_t*, uint64_t*, Buffer*, Buffer*, Buffer*);
+  /**
+   * Like `next_db`, but must not touch the gas meter and may be called from another thread.
+   * Go reports the gas of the item instead, which is only charged once the contract consumes it.
+   * Iterators which set this are read ahead on a background thread.
+   */
+  int32_t (*prefetch_db)(iterator_t, uint64_t*, Buffer*, Buffer*, Buffer*);
+  /**
+   * Optional. Continues the iteration at the given key, see `SeekableIterator::seek`.
+   */
+  int32_t (*seek_db)(iterator_t, gas_meter_t*, uint64_t*, Buffer, Buffer*);
 } Iterator_vtable;
 
 typedef struct GoIter {
@@ -83,17 +198,38 @@ typedef struct GoIter {
   Iterator_vtable vtable;
 } GoIter;
 
+typedef struct DB DB;
+
+typedef struct BlockContext {
+  uint64_t height;
+  /**
+   * Seconds since the UNIX epoch
+   */
+  uint64_t time;
+} BlockContext;
+
 typedef struct DB_vtable {
   int32_t (*read_db)(db_t*, gas_meter_t*, uint64_t*, Buffer, Buffer*, Buffer*);
   int32_t (*write_db)(db_t*, gas_meter_t*, uint64_t*, Buffer, Buffer, Buffer*);
   int32_t (*remove_db)(db_t*, gas_meter_t*, uint64_t*, Buffer, Buffer*);
-  int32_t (*scan_db)(db_t*, gas_meter_t*, uint64_t*, Buffer, Buffer, int32_t, GoIter*, Buffer*);
+  int32_t (*scan_db)(db_t*, gas_meter_t*, uint64_t*, Buffer, Buffer, int32_t, int32_t, GoIter*, Buffer*);
+  int32_t (*resolve_contract)(db_t*, gas_meter_t*, uint64_t*, Buffer, Buffer*, Buffer*, DB*, Buffer*);
+  int32_t (*scan_prefix)(db_t*, gas_meter_t*, uint64_t*, Buffer, uint32_t, int32_t, int32_t, GoIter*, Buffer*);
+  int32_t (*delete_range)(db_t*, gas_meter_t*, uint64_t*, Buffer, Buffer, Buffer*);
+  int32_t (*set_block_context)(db_t*, BlockContext, Buffer*);
+  int32_t (*gas_consumed)(db_t*, gas_meter_t*, uint64_t, Buffer*);
+  int32_t (*authorize_call)(db_t*, Buffer, Buffer, Buffer, bool*, Buffer*);
+  int32_t (*call_result)(db_t*, Buffer, Buffer, Buffer, bool, Buffer*);
 } DB_vtable;
 
 typedef struct DB {
   gas_meter_t *gas_meter;
   db_t *state;
   DB_vtable vtable;
+  /**
+   * Optional. If set, `state` is the parent store and we keep all keys inside of this prefix
+   */
+  Buffer namespace;
 } DB;
 
 typedef struct api_t {
@@ -116,6 +252,7 @@ typedef struct querier_t {
 
 typedef struct Querier_vtable {
   int32_t (*query_external)(const querier_t*, uint64_t, uint64_t*, Buffer, Buffer*, Buffer*);
+  int32_t (*set_block_context)(const querier_t*, BlockContext, Buffer*);
 } Querier_vtable;
 
 typedef struct GoQuerier {
@@ -123,20 +260,388 @@ typedef struct GoQuerier {
   Querier_vtable vtable;
 } GoQuerier;
 
+/**
+ * The number of invocations of each import
+ */
+typedef struct HostCallCounts {
+  uint64_t db_read;
+  uint64_t db_write;
+  uint64_t db_remove;
+  /**
+   * Only the scans of this library remove ranges or seek, so these stay 0 for contracts, see `DB::range_keys`
+   */
+  uint64_t db_remove_range;
+  uint64_t db_scan;
+  uint64_t db_next;
+  uint64_t db_seek;
+  uint64_t query_chain;
+  uint64_t canonicalize_address;
+  uint64_t humanize_address;
+} HostCallCounts;
+
+/**
+ * Details of the gas used by a contract call, on top of the total reported in `gas_used`
+ */
+typedef struct GasReport {
+  /**
+   * The gas reported by the storage and querier callbacks
+   */
+  uint64_t used_externally;
+  /**
+   * The part of `used_externally` spent on deleting storage entries, which the chain may refund
+   */
+  uint64_t refundable;
+  /**
+   * The part of `used_externally` spent on reading storage entries
+   */
+  uint64_t reads;
+  /**
+   * The part of `used_externally` spent on writing storage entries
+   */
+  uint64_t writes;
+  /**
+   * The part of `used_externally` spent on opening and advancing storage iterators
+   */
+  uint64_t iterator_steps;
+  /**
+   * The part of `used_externally` spent on queries to other modules and contracts
+   */
+  uint64_t queries;
+  /**
+   * The gas reported by the address conversion callbacks. The VM charges it like wasm execution,
+   * so it is not part of `used_externally`.
+   */
+  uint64_t api_calls;
+  /**
+   * The gas charged from the fallback schedule on top of the gas reported by the callbacks.
+   * It is part of `gas_used`.
+   */
+  uint64_t fallback;
+  /**
+   * The version of the wasm cost table the VM charged execution with, see `wasm_costs`
+   */
+  uint64_t schedule_version;
+  /**
+   * The most memory an instance of the call had, in 64 KiB pages, including the instances of its internal
+   * calls, see `max_memory_pages`
+   */
+  uint32_t peak_memory_pages;
+  /**
+   * How often the call and its internal calls invoked each host import, see `host_calls`
+   */
+  HostCallCounts host_calls;
+} GasReport;
+
+/**
+ * What Go learns from a checkpoint token
+ */
+typedef struct GasCheckpointInfo {
+  /**
+   * The gas limit of the whole transaction, as passed to the first call
+   */
+  uint64_t gas_limit;
+  /**
+   * The gas consumed by all calls folded into the checkpoint
+   */
+  uint64_t consumed;
+  /**
+   * The gas limit for the next call
+   */
+  uint64_t remaining;
+  /**
+   * The number of calls folded into the checkpoint
+   */
+  uint32_t calls;
+} GasCheckpointInfo;
+
+/**
+ * A fingerprint of the functions and types exported by this library.
+ *
+ * It equals the `ABI_FINGERPRINT` defined in the header generated alongside the library,
+ * so a caller compiled against another header can detect the mismatch before calling anything else.
+ */
+uint64_t abi_fingerprint(void);
+
 Buffer allocate_rust(const uint8_t *ptr, uintptr_t length);
 
+/**
+ * Returns a quote of the enclave committing to the node's registration keys and `nonce`, for verifiers like
+ * bridges which attest the node live, see `attestation`.
+ *
+ * `nonce` must be 32 bytes picked by the verifier, who sends the quote to IAS and checks the answer with
+ * `verify_attestation_response`. Creating the quote follows the policy of `set_enclave_call_policy`.
+ */
+Buffer attestation_challenge(Buffer nonce, Buffer *err);
+
+/**
+ * Counts up to `limit` storage entries of the contract with `contract_key` per key version, in ascending key
+ * order. `key_history` is the concatenation of the 32 byte ids of all codes the contract ran, oldest first,
+ * ending with its current code. See `state_keys`.
+ *
+ * Returns a JSON-encoded page. Pass its `next` token as `start_after` to audit the following page;
+ * `next` is null once the storage is exhausted.
+ */
+Buffer audit_state_keys(Buffer contract_key,
+                        DB db,
+                        Buffer key_history,
+                        Buffer start_after,
+                        uint32_t limit,
+                        Buffer *err);
+
+/**
+ * Runs the `begin_block` export of a contract registered for it, see `block_hooks`.
+ *
+ * `block` is the JSON `BlockMeta` of the block which begins, and replaces the block of `params`. The call runs
+ * with the budget `system_gas_limit` of the consensus params, like `handle_system`.
+ */
+Buffer begin_block(cache_t *cache,
+                   Buffer code_id,
+                   Buffer params,
+                   Buffer block,
+                   DB db,
+                   GoApi api,
+                   GoQuerier querier,
+                   uint64_t *gas_used,
+                   GasReport *gas_report,
+                   Buffer call_id,
+                   Buffer *err);
+
+/**
+ * Derives the consensus seed from the shares of a bootstrap ceremony, in place of `init_bootstrap`.
+ *
+ * `shares` are at least `threshold` shares returned by `create_seed_share` on the participants of `ceremony`,
+ * each prefixed with its length as big endian u32. Every participant combining shares of the same ceremony
+ * gets the same seed, and the same bootstrap public key is returned.
+ */
+Buffer combine_seed_shares(Buffer ceremony, Buffer shares, Buffer *err);
+
 Buffer create(cache_t *cache, Buffer wasm, Buffer *err);
 
 bool create_attestation_report(Buffer *err);
 
+/**
+ * Like `create_attestation_report`, but for version 2 of the seed exchange, which has forward secrecy.
+ *
+ * The enclave generates an ephemeral key for this registration and puts it into the certificate, and
+ * the bootstrap node encrypts the seed with an ECDH secret of it instead of the registration key.
+ * The ephemeral key only lives in enclave memory, so `init_node` must run before the node restarts.
+ */
+bool create_attestation_report_v2(Buffer *err);
+
+/**
+ * Returns a statement of the enclave binding the node's registration keys to an attestation report, as
+ * JSON-encoded `KeyAttestation`, for wallets and bridges verifying they talk to a genuine enclave.
+ *
+ * `challenge` must be 32 bytes picked by the verifier, see `key_attestation`. Like
+ * `create_attestation_report`, this goes through IAS and follows the policy of `set_enclave_call_policy`.
+ */
+Buffer create_key_attestation(Buffer challenge, Buffer *err);
+
+/**
+ * Stores a code like `create`, as a plaintext code whose contracts run without encryption, see `plaintext`.
+ *
+ * The mode of a code is fixed when it is first stored, so this fails for codes stored with `create` before.
+ */
+Buffer create_plaintext(cache_t *cache, Buffer wasm, Buffer *err);
+
+/**
+ * Contributes this node to a bootstrap ceremony of several nodes, see `combine_seed_shares`.
+ *
+ * `ceremony` is JSON like `{"id":"secret-4","threshold":2,"participants":["<base64 key>",..]}`, listing the
+ * seed exchange public keys of all participating enclaves. Returns this node's share, sealed to the other
+ * participants, which must be handed to them.
+ */
+Buffer create_seed_share(Buffer ceremony, Buffer *err);
+
+/**
+ * Decrypts the answer of a query for gateways relaying queries of authenticated users, see `envelope`.
+ *
+ * `ephemeral_secret` and `nonce` must be the ones the query message was encrypted with. `ciphertext` is the
+ * result of the query, and the answer of the contract is returned in plaintext.
+ */
+Buffer decrypt_query_response(Buffer node_pubkey,
+                              Buffer ephemeral_secret,
+                              Buffer nonce,
+                              Buffer ciphertext,
+                              Buffer *err);
+
+/**
+ * Decrypts `ciphertext`, which a contract returned in response to the message sent in `envelope`.
+ *
+ * `ephemeral_secret` must be the secret used to produce `envelope` with `encrypt_msg`.
+ */
+Buffer decrypt_response(Buffer node_pubkey,
+                        Buffer ephemeral_secret,
+                        Buffer envelope,
+                        Buffer ciphertext,
+                        Buffer *err);
+
+/**
+ * Derives the address of the contract `creator` instantiates from the code `checksum` with `salt`, see
+ * `contract_address`.
+ *
+ * `creator` is a canonical address and `salt` 1 to 64 bytes. If `msg` is not empty, the address also depends
+ * on it, so the contract can only be instantiated at the address with exactly this init message.
+ */
+Buffer derive_contract_address(Buffer checksum, Buffer creator, Buffer salt, Buffer msg, Buffer *err);
+
+/**
+ * Takes the enclave's log lines buffered since the last call, as JSON like
+ * `{"lines":[{"time_ms":1600000000000,"level":"info","message":"..","call_id":null}],"dropped":0}`.
+ * `call_id` is the id passed to the contract call which logged the line, and `dropped` counts
+ * the lines lost because the buffer was full. There are no lines if `init_enclave_log` was not called.
+ */
+Buffer drain_enclave_log(Buffer *err);
+
+/**
+ * Encrypts `plaintext` for the node with `node_pubkey`, the way clients send messages to secret contracts.
+ *
+ * `ephemeral_secret` (the client's x25519 secret key) and `nonce` must be 32 random bytes each.
+ * Returns the envelope `nonce || ephemeral public key || ciphertext`.
+ */
+Buffer encrypt_msg(Buffer node_pubkey,
+                   Buffer ephemeral_secret,
+                   Buffer nonce,
+                   Buffer plaintext,
+                   Buffer *err);
+
+/**
+ * Runs the `end_block` export of a contract registered for it, like `begin_block`
+ */
+Buffer end_block(cache_t *cache,
+                 Buffer code_id,
+                 Buffer params,
+                 Buffer block,
+                 DB db,
+                 GoApi api,
+                 GoQuerier querier,
+                 uint64_t *gas_used,
+                 GasReport *gas_report,
+                 Buffer call_id,
+                 Buffer *err);
+
+/**
+ * The versions of the env schema `params` may name in their `schema_version` field, as a JSON array, oldest
+ * first. See `env_encoding`.
+ */
+Buffer env_schema_versions(Buffer *err);
+
+/**
+ * The version of the fixed error messages, e.g. the ones errors from the OS and the cache are mapped to and the
+ * reasons of malformed call messages.
+ *
+ * Error messages can end up in transaction results, so all validators must run a library with the same version.
+ */
+uint32_t error_templates_version(void);
+
+/**
+ * Produces an archive of stored codes in the format of `import_codes`, e.g. for genesis export.
+ *
+ * `checksums_filter` is a concatenation of 32 byte checksums selecting the codes. If it is empty, the archive
+ * contains all stored codes.
+ */
+Buffer export_codes(cache_t *cache, Buffer checksums_filter, Buffer *err);
+
+/**
+ * Exports up to `limit` storage entries of the contract with `contract_key`, in ascending key order.
+ *
+ * Returns a JSON-encoded page. Pass its `next` token as `start_after` to get the following page;
+ * `next` is null once the storage is exhausted. Unlike `query`, this bypasses the contract entirely.
+ */
+Buffer export_contract_state(Buffer contract_key,
+                             DB db,
+                             Buffer start_after,
+                             uint32_t limit,
+                             int32_t mode,
+                             Buffer *err);
+
+/**
+ * Produces the state-sync payload of this node: the enclave's exportable state, the codes in the cache and
+ * which of them are plaintext codes.
+ *
+ * A node restoring the payload with `restore_snapshot` must `create` all of the listed codes first.
+ */
+Buffer export_snapshot(cache_t *cache, Buffer *err);
+
+/**
+ * Adds a finished contract call to the gas checkpoint `previous` of its transaction and returns the new checkpoint.
+ *
+ * `gas_used` and `report` are what the call returned. If `previous` is empty, this starts the checkpoint of a
+ * transaction with `gas_limit`, which is ignored otherwise. Fails with out of gas once the calls consumed more
+ * than the limit of the transaction.
+ */
+Buffer export_gas_checkpoint(Buffer previous,
+                             uint64_t gas_limit,
+                             uint64_t gas_used,
+                             GasReport report,
+                             Buffer *err);
+
 void free_rust(Buffer buf);
 
 Buffer get_code(cache_t *cache, Buffer id, Buffer *err);
 
+/**
+ * Returns the execution statistics of every code run by the cache as JSON, the codes which took the most time first.
+ *
+ * With `reset`, the counters start over afterwards, so every call returns the statistics since the previous one.
+ */
+Buffer get_code_stats(cache_t *cache, bool reset, Buffer *err);
+
+/**
+ * Returns the MRENCLAVE of the loaded enclave and the one this library was built for, as JSON like
+ * `{"expected":"<hex>","actual":"<hex>","matches":true}`. `expected` is null if the build does not check it.
+ */
+Buffer get_enclave_pairing(Buffer *err);
+
+/**
+ * Encrypts the consensus seed for the node which made the attestation certificate `cert`.
+ *
+ * The version of the seed exchange is taken from the certificate, see `create_attestation_report_v2`.
+ */
 Buffer get_encrypted_seed(Buffer cert, Buffer *err);
 
+/**
+ * Returns the JSON `ExecutorStats` of the calls of `handle_async` and `query_async`: the calls running and
+ * queued, and the calls run and rejected so far
+ */
+Buffer get_executor_stats(Buffer *err);
+
 Buffer get_health_check(Buffer *err);
 
+/**
+ * Returns the diagnostics of the last enclave call failing in the SGX SDK or contract execution failing in
+ * the VM, as JSON-encoded `CrashReport`, for attaching to bug reports. Empty if nothing failed since startup.
+ */
+Buffer get_last_crash_report(Buffer *err);
+
+/**
+ * Returns the counters of the compiled modules of the cache as JSON: the format they are compiled in, the
+ * modules removed at startup as they were compiled in another format, and how many of them were compiled
+ * again since, see `module_format`
+ */
+Buffer get_module_stats(cache_t *cache, Buffer *err);
+
+/**
+ * Returns the public registration keys of a node which already ran `key_gen`, as JSON-encoded `RegistrationKeys`
+ */
+Buffer get_registration_keys(Buffer *err);
+
+/**
+ * Returns the shadow execution counters of the cache as JSON, see the `shadow_execution` cache option.
+ *
+ * The counters are all zero, and `enabled` is false, if the cache does not run shadow execution.
+ */
+Buffer get_shadow_stats(cache_t *cache, Buffer *err);
+
+/**
+ * Reads the enclave's trusted clock.
+ *
+ * Returns the number of seconds since an arbitrary reference point and writes the nonce identifying
+ * that reference point to `time_source`. Two readings may only be compared if their time sources are
+ * equal. Fails if the platform does not provide trusted time.
+ */
+uint64_t get_trusted_time(Buffer *time_source, Buffer *err);
+
 Buffer handle(cache_t *cache,
               Buffer code_id,
               Buffer params,
@@ -145,14 +650,111 @@ Buffer handle(cache_t *cache,
               GoApi api,
               GoQuerier querier,
               uint64_t gas_limit,
+              uint32_t call_depth,
               uint64_t *gas_used,
+              GasReport *gas_report,
+              Buffer call_id,
               Buffer *err);
 
+/**
+ * Starts `handle` on the worker thread of the `executor` and returns the handle of the call, see `async_calls`.
+ *
+ * The call is done once `on_done` is called with the handle or `poll_async_call` returns true. Go then gets its
+ * result, gas used and gas report from `take_async_result`. Returns 0 and sets `err` if the call cannot start.
+ * The calls of the executor run one at a time in the order they were started, so the state changes of a block
+ * apply in its order as long as Go starts its calls in that order.
+ */
+uint64_t handle_async(cache_t *cache,
+                      Buffer code_id,
+                      Buffer params,
+                      Buffer msg,
+                      DB db,
+                      GoApi api,
+                      GoQuerier querier,
+                      uint64_t gas_limit,
+                      uint32_t call_depth,
+                      Buffer call_id,
+                      void (*on_done)(uint64_t),
+                      Buffer *err);
+
+/**
+ * Executes a chain-owned contract for the chain itself, e.g. from BeginBlock or EndBlock.
+ *
+ * Like `handle`, but the call is not limited by the gas a user paid for. It runs at depth 0 with the fixed budget
+ * `system_gas_limit` of the consensus params instead, so the contract stays bounded, and fails with the `OutOfGas`
+ * errno when it exhausts the budget. Go should not charge the gas to any meter, but `gas_used` and `gas_report`
+ * are set as usual.
+ */
+Buffer handle_system(cache_t *cache,
+                     Buffer code_id,
+                     Buffer params,
+                     Buffer msg,
+                     DB db,
+                     GoApi api,
+                     GoQuerier querier,
+                     uint64_t *gas_used,
+                     GasReport *gas_report,
+                     Buffer call_id,
+                     Buffer *err);
+
+/**
+ * Stores all codes of an archive in one call, e.g. at genesis. See `code_archive` for the format.
+ *
+ * Returns JSON listing the checksums of the imported codes and of the ones which were stored already.
+ * Nothing is stored if any checksum of the archive does not match its wasm.
+ */
+Buffer import_codes(cache_t *cache, Buffer archive, Buffer *err);
+
+/**
+ * Reads the gas a transaction consumed so far, and the gas limit for its next contract call, from a gas checkpoint.
+ */
+GasCheckpointInfo import_gas_checkpoint(Buffer checkpoint, Buffer *err);
+
+/**
+ * Records the privileged operations of the node in `audit.log` in `data_dir` from now on, see `audit_log`.
+ *
+ * Entries are appended to those already in the file. Calling it again moves the log to another data dir.
+ */
+bool init_audit_log(Buffer data_dir, Buffer *err);
+
 Buffer init_bootstrap(Buffer *err);
 
-cache_t *init_cache(Buffer data_dir, Buffer supported_features, uintptr_t _cache_size, Buffer *err);
+/**
+ * Like `init_bootstrap`, but derives the consensus seed from `seed` instead of the enclave's randomness.
+ *
+ * Only available with the `test-keys` feature, for reproducible test networks.
+ */
+#if defined(TEST_KEYS)
+Buffer init_bootstrap_from_seed(Buffer seed, Buffer *err);
+#endif
+
+cache_t *init_cache(Buffer data_dir,
+                    Buffer supported_features,
+                    Buffer options,
+                    uintptr_t _cache_size,
+                    Buffer *err);
+
+/**
+ * Buffers the log lines of the enclave instead of printing them to stdout, see `drain_enclave_log`.
+ *
+ * `options` is JSON like `{"level":"debug","max_lines":1000,"max_line_len":1024}`, where omitted fields take
+ * these defaults except for `level`, which defaults to `info`. Calling it again changes the options.
+ */
+bool init_enclave_log(Buffer options, Buffer *err);
+
+/**
+ * Sets up logging. Go calls this when loading the library, before calling anything else.
+ *
+ * This used to run as a constructor function, but the linker drops those when linking the static library.
+ */
+void init_logger(void);
 
-bool init_node(Buffer master_cert, Buffer encrypted_seed, Buffer *err);
+/**
+ * Initializes the node with the network's master certificate and the consensus seed encrypted to it.
+ *
+ * On failure, the returned code tells what went wrong and `err` explains how to fix it.
+ */
+InitNodeResult init_node(Buffer master_cert, Buffer encrypted_seed, Buffer *err);
 
 Buffer instantiate(cache_t *cache,
                    Buffer contract_id,
@@ -162,11 +764,58 @@ Buffer instantiate(cache_t *cache,
                    GoApi api,
                    GoQuerier querier,
                    uint64_t gas_limit,
+                   uint32_t call_depth,
                    uint64_t *gas_used,
+                   GasReport *gas_report,
+                   Buffer call_id,
                    Buffer *err);
 
+/**
+ * Like `instantiate`, but the contract address in `params` must be the one derived from `salt`, see
+ * `contract_address`. With `fix_msg`, the address also depends on `msg`.
+ *
+ * Go derives the address with `derive_contract_address` beforehand, to set up the contract's store.
+ */
+Buffer instantiate2(cache_t *cache,
+                    Buffer contract_id,
+                    Buffer params,
+                    Buffer msg,
+                    DB db,
+                    GoApi api,
+                    GoQuerier querier,
+                    uint64_t gas_limit,
+                    uint32_t call_depth,
+                    uint64_t *gas_used,
+                    GasReport *gas_report,
+                    Buffer salt,
+                    bool fix_msg,
+                    Buffer call_id,
+                    Buffer *err);
+
+/**
+ * Generates the node's registration keys and returns their public parts as JSON-encoded `RegistrationKeys`
+ */
 Buffer key_gen(Buffer *err);
 
+/**
+ * Like `key_gen`, but derives the node's registration key from `seed` instead of the enclave's randomness.
+ *
+ * Only available with the `test-keys` feature, for reproducible test networks.
+ */
+#if defined(TEST_KEYS)
+Buffer key_gen_from_seed(Buffer seed, Buffer *err);
+#endif
+
+/**
+ * Migrates a contract to the code `contract_id`.
+ *
+ * `key_history` is the concatenation of the 32 byte ids of the codes the contract ran before, oldest first,
+ * so the enclave can read the state encrypted with their keys, see `state_keys`. The contract key in
+ * `params` must have been issued for the last of them. It may be empty for contracts without encrypted state.
+ *
+ * `auth` is the JSON `MigrateAuth` of the migration, or empty for a migration by the sender in `params`. The
+ * enclave only runs it if governance runs it or the sender is the admin it recorded, see `migrate_auth`.
+ */
 Buffer migrate(cache_t *cache,
                Buffer contract_id,
                Buffer params,
@@ -175,9 +824,28 @@ Buffer migrate(cache_t *cache,
                GoApi api,
                GoQuerier querier,
                uint64_t gas_limit,
+               uint32_t call_depth,
                uint64_t *gas_used,
+               GasReport *gas_report,
+               Buffer key_history,
+               Buffer auth,
+               Buffer call_id,
                Buffer *err);
 
+/**
+ * Whether the call `handle` started by `handle_async` or `query_async` is done. Sets `err` for unknown handles.
+ */
+bool poll_async_call(uint64_t handle, Buffer *err);
+
+/**
+ * `block` is the block whose state the query reads, which Go may pin to a past height for historical queries.
+ * It is handed to the DB and the querier before the contract runs, so the queries the contract makes read the
+ * same state as its storage. A height of 0 means the latest state.
+ *
+ * With `unmetered` set, the query runs without a gas limit, ignoring `gas_limit`, and reports no gas: `gas_used`
+ * and `gas_report` are left at 0. Unmetered queries fail with `Error::CallDenied` unless the cache was created
+ * with `allow_unmetered_queries`.
+ */
 Buffer query(cache_t *cache,
              Buffer code_id,
              Buffer msg,
@@ -185,11 +853,124 @@ Buffer query(cache_t *cache,
              GoApi api,
              GoQuerier querier,
              uint64_t gas_limit,
+             uint32_t call_depth,
+             BlockContext block,
+             bool unmetered,
              uint64_t *gas_used,
+             GasReport *gas_report,
+             Buffer call_id,
              Buffer *err);
 
 /**
- * frees a cache reference
+ * Starts `query` on the worker thread of the `executor` and returns the handle of the call, like `handle_async`
+ */
+uint64_t query_async(cache_t *cache,
+                     Buffer code_id,
+                     Buffer msg,
+                     DB db,
+                     GoApi api,
+                     GoQuerier querier,
+                     uint64_t gas_limit,
+                     uint32_t call_depth,
+                     Buffer call_id,
+                     void (*on_done)(uint64_t),
+                     Buffer *err);
+
+/**
+ * Runs the JSON list of `BatchQuery`s against the state of `db`, which must resolve the contracts, and returns
+ * the JSON list of their `QueryOutcome`s, see `query_batch`. A query failing does not fail the batch. `block`
+ * is the block of the snapshot `db` reads, as for `query`. The queries share `gas_limit`, and `gas_used` and
+ * `gas_report` cover all of them.
+ */
+Buffer query_batch(cache_t *cache,
+                   Buffer queries,
+                   DB db,
+                   GoApi api,
+                   GoQuerier querier,
+                   uint64_t gas_limit,
+                   BlockContext block,
+                   uint64_t *gas_used,
+                   GasReport *gas_report,
+                   Buffer call_id,
+                   Buffer *err);
+
+/**
+ * Queries the contract of the route `alias` with its message rendered with the JSON object `args`, see
+ * `query_routes`. `db` must resolve the contract, as for `query_batch`, and the other arguments are as for
+ * `query`. Fails with `Error::CallDenied` if the contract no longer runs the code of the route.
+ */
+Buffer query_by_alias(cache_t *cache,
+                      Buffer alias,
+                      Buffer args,
+                      DB db,
+                      GoApi api,
+                      GoQuerier querier,
+                      uint64_t gas_limit,
+                      BlockContext block,
+                      uint64_t *gas_used,
+                      GasReport *gas_report,
+                      Buffer call_id,
+                      Buffer *err);
+
+/**
+ * Runs `query` on the contract at `contract_addr`, whose code is `code_id`, and keeps its result for queries
+ * with the same contract, code and message at the same height, see `query_cache`. The other arguments are as
+ * for `query`. A reused result sets `gas_used` to the gas its query used, and leaves `gas_report` at 0.
+ *
+ * The queries of contracts must use `query`, as their gas is part of consensus and the store charges nothing
+ * for reused results.
+ */
+Buffer query_cached(cache_t *cache,
+                    Buffer contract_addr,
+                    Buffer code_id,
+                    Buffer msg,
+                    DB db,
+                    GoApi api,
+                    GoQuerier querier,
+                    uint64_t gas_limit,
+                    BlockContext block,
+                    uint64_t *gas_used,
+                    GasReport *gas_report,
+                    Buffer call_id,
+                    Buffer *err);
+
+/**
+ * Returns all entries of the audit log as a JSON list like
+ * `[{"time_ms":1600000000000,"operation":"init_node","params_hash":"<hex>","error":null}]`, oldest first.
+ * `params_hash` is the SHA-256 of the parameters, each prefixed with its length as big endian u64. The list
+ * is empty if `init_audit_log` was not called.
+ */
+Buffer read_audit_log(Buffer *err);
+
+/**
+ * Has the enclave create a new attestation report and returns the JSON-encoded `RegistrationRefresh` the node
+ * registers again with, after its platform was updated, see `tcb_recovery`.
+ *
+ * Fails with the `TcbOutdated` errno if IAS still tells that the platform must be updated. Like
+ * `create_attestation_report`, this goes through IAS and follows the policy of `set_enclave_call_policy`.
+ */
+Buffer refresh_registration(Buffer *err);
+
+/**
+ * Registers a wasm cost table which charges the calls from a block height on, see `wasm_costs`.
+ *
+ * `schedule` is JSON like `{"activation_height":1000000,"costs":{"default":1,"instructions":{"i64.div_u":8}}}`.
+ * Schedules are not persisted, so Go must register them again after restarting the node, before blocks at or
+ * above their heights run. Registering a schedule again does nothing.
+ */
+bool register_gas_schedule(cache_t *cache, Buffer schedule, Buffer *err);
+
+/**
+ * Registers memory owned by Go, into which large results of `query` and `get_code` are written.
+ *
+ * Such results have a capacity of 0 and must be released with `release_shared` instead of `free_rust`.
+ * Pass a null buffer to unregister the region. Go must keep the memory alive, and must not unregister it
+ * while results are unreleased.
+ */
+bool register_shared_region(cache_t *cache, Buffer region, Buffer *err);
+
+/**
+ * frees a cache reference, once the calls of `handle_async` and `query_async` using it are done
  *
  * # Safety
  *
@@ -197,3 +978,158 @@ Buffer query(cache_t *cache,
  * and cannot be called on any other pointer.
  */
 void release_cache(cache_t *cache);
+
+/**
+ * Releases a result which was written into the shared region
+ */
+void release_shared(cache_t *cache, Buffer buf);
+
+/**
+ * Replaces the enclave with the signed enclave binary at `path`, without restarting the node.
+ *
+ * Waits until the running contract calls and enclave calls returned, and refuses new ones meanwhile.
+ * The new enclave must be signed with the same key as the old one to unseal the node's keys and seed.
+ */
+bool reload_enclave(cache_t *cache, Buffer path, Buffer *err);
+
+/**
+ * Restores a payload produced by `export_snapshot` on another node
+ */
+bool restore_snapshot(cache_t *cache, Buffer payload, Buffer *err);
+
+/**
+ * Times `iterations` calls of the given kind from inside the library, e.g. `db.get` through the Go
+ * callbacks, and returns a JSON-encoded report.
+ *
+ * Chains can use this to calibrate the gas charged for callbacks on their hardware. The storage
+ * benchmarks write and remove a single key, so `db` should be a scratch store.
+ */
+Buffer run_benchmark(int32_t kind,
+                     uint32_t iterations,
+                     DB db,
+                     GoApi api,
+                     GoQuerier querier,
+                     Buffer *err);
+
+/**
+ * Hands the randomness seed of the block at `height` to the enclave.
+ *
+ * Must be called before executing the block's first contract call. The enclave derives the random
+ * bytes it gives each contract from this seed, the contract address and the message, so they are
+ * the same on every node but cannot be chosen by the contract or its caller.
+ */
+bool set_block_entropy(uint64_t height, Buffer entropy, Buffer *err);
+
+/**
+ * Pauses or resumes all contracts of the code `checksum`, e.g. for an emergency stop by governance.
+ *
+ * Calls of paused codes fail with the `ContractPaused` errno before an instance is created, internal calls
+ * included. Pausing is not persisted: Go must pause the codes again after restarting the node.
+ */
+bool set_code_paused(cache_t *cache, Buffer checksum, bool paused, Buffer *err);
+
+/**
+ * Replaces the consensus params of the chain with the JSON object `params`, see `consensus_params`.
+ *
+ * Go must call this with the params in the state of the chain once the cache is created, and again whenever
+ * they change, before the next call. Until then every call fails. Changing whether NaNs are canonicalized drops
+ * the compiled modules, see `update_supported_features`.
+ */
+bool set_consensus_params(cache_t *cache, Buffer params, Buffer *err);
+
+/**
+ * Sets the timeout and retries of `attestation_challenge`, `create_attestation_report`,
+ * `create_key_attestation`, `get_encrypted_seed` and `init_bootstrap`.
+ *
+ * `policy` is JSON like `{"timeout_ms":30000,"retries":3,"backoff_ms":500}`, where omitted fields are 0.
+ * A timeout of 0 waits forever. Calls which time out fail with the `Timeout` errno and can be retried.
+ */
+bool set_enclave_call_policy(Buffer policy, Buffer *err);
+
+/**
+ * Sets how the calls of `handle_async` and `query_async` are queued, see `executor`.
+ *
+ * `options` is JSON like `{"max_queued":1000}`. The queue limit defaults to none. Calls over the limit fail with
+ * the `Throttled` errno.
+ */
+bool set_executor_options(Buffer options, Buffer *err);
+
+/**
+ * Limits the queries started by Go, see `query_limits`.
+ *
+ * `limits` is JSON like `{"per_second":100,"burst":200,"max_concurrent":8}`, where omitted fields are 0,
+ * which disables the limit. Queries over the limits fail with the `Throttled` errno.
+ */
+bool set_query_limits(Buffer limits, Buffer *err);
+
+/**
+ * Replaces the routes of `query_by_alias` with the JSON object of `QueryRoute`s by alias, see `query_routes`.
+ * Routes are not persisted, so Go must set them again after restarting the node.
+ */
+bool set_query_routes(cache_t *cache, Buffer routes, Buffer *err);
+
+/**
+ * Sets the unlock key of the operator, which the enclave mixes into the sealing of the node's keys and seed,
+ * see `unlock_key`.
+ *
+ * Must be called before `init_node` and before the first contract call, each time the node starts, with at
+ * least 16 bytes. Setting the same key again does nothing, and another key fails until the node restarts.
+ */
+bool set_unlock_key(Buffer key, Buffer *err);
+
+/**
+ * Returns the result of the call `handle` once it is done, like `handle` or `query` would, and forgets the handle.
+ *
+ * `gas_used` and `gas_report` are set as by the synchronous call. Fails if the call is still running.
+ */
+Buffer take_async_result(uint64_t handle, uint64_t *gas_used, GasReport *gas_report, Buffer *err);
+
+/**
+ * Replaces the features contracts may require, given as comma separated list like in `init_cache`.
+ *
+ * Lets a chain upgrade enable a new capability without re-creating the cache. The modules compiled in
+ * memory are dropped, so the next call of every code is slower.
+ */
+bool update_supported_features(cache_t *cache, Buffer supported_features, Buffer *err);
+
+/**
+ * Checks that `wasm` has the (32 byte) checksum `expected`, which is its code id, e.g. when importing codes from genesis.
+ *
+ * Returns false with no error set if the checksum does not match.
+ */
+bool validate_checksum(Buffer wasm, Buffer expected, Buffer *err);
+
+/**
+ * Checks the answer of IAS to a quote of `attestation_challenge`, see `attestation`.
+ *
+ * `keys` are the 64 bytes of the node and seed exchange public keys the verifier expects, and `response` is
+ * JSON like `{"report":"<body>","signature":"<base64>","signing_cert":"<PEM>"}`. Reports older than
+ * `max_age_secs` are rejected, unless it is 0. Returns the JSON-encoded `AttestationVerdict`, which tells the
+ * measurements of the enclave the verifier must compare with the ones it trusts. Reports of platforms lacking
+ * updates fail with the `TcbOutdated` errno.
+ */
+Buffer verify_attestation_response(Buffer nonce,
+                                   Buffer keys,
+                                   Buffer quote,
+                                   Buffer response,
+                                   uint64_t max_age_secs,
+                                   Buffer *err);
+
+/**
+ * Checks that `contract_key` was issued by the enclave for the contract at `contract_addr` running
+ * the code with the (32 byte) `code_hash`.
+ *
+ * Returns false with no error set if the key is well-formed but does not match.
+ */
+bool verify_contract_key(Buffer contract_addr, Buffer code_hash, Buffer contract_key, Buffer *err);
+
+/**
+ * Verifies a query permit for the contract at `contract_addr` and returns the canonical address of its signer.
+ *
+ * This performs the same checks as the contracts do: the permit must list `contract_addr` in its
+ * allowed tokens, and its signature must be valid for the amino sign doc of its params.
+ */
+Buffer verify_permit(Buffer permit, Buffer contract_addr, Buffer fee_denom, Buffer *err);
+
+
+#define ABI_FINGERPRINT 0x1cbf454e59ae0ee0ULL
@@ -0,0 +1,33 @@
+package api
+
+import (
+	"encoding/binary"
+	"encoding/json"
+)
+
+// BootstrapCeremony describes a bootstrap of several nodes which together generate the consensus seed,
+// see CreateSeedShare and CombineSeedShares. All participants must use the same ceremony.
+type BootstrapCeremony struct {
+	// ID distinguishes the ceremony from earlier ones of the same participants, e.g. the chain id
+	ID string `json:"id"`
+	// Threshold is how many shares are combined into the seed. It must be at least 2.
+	Threshold uint32 `json:"threshold"`
+	// Participants are the seed exchange public keys of the participating enclaves, see GetRegistrationKeys
+	Participants [][]byte `json:"participants"`
+}
+
+func (c BootstrapCeremony) marshal() ([]byte, error) {
+	return json.Marshal(c)
+}
+
+// frameShares prefixes every share with its length as big endian uint32
+func frameShares(shares [][]byte) []byte {
+	var out []byte
+	for _, share := range shares {
+		var length [4]byte
+		binary.BigEndian.PutUint32(length[:], uint32(len(share)))
+		out = append(out, length[:]...)
+		out = append(out, share...)
+	}
+	return out
+}
@@ -0,0 +1,24 @@
+package api
+
+import (
+	"bytes"
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestBootstrapCeremonyJSON(t *testing.T) {
+	ceremony := BootstrapCeremony{
+		ID:           "secret-4",
+		Threshold:    2,
+		Participants: [][]byte{bytes.Repeat([]byte{1}, 32), bytes.Repeat([]byte{2}, 32)},
+	}
+	bz, err := ceremony.marshal()
+	require.NoError(t, err)
+	require.Equal(t, `{"id":"secret-4","threshold":2,"participants":["AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=","AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI="]}`, string(bz))
+}
+
+func TestFrameShares(t *testing.T) {
+	require.Empty(t, frameShares(nil))
+	require.Equal(t, []byte{0, 0, 0, 2, 'a', 'b', 0, 0, 0, 1, 'c'}, frameShares([][]byte{[]byte("ab"), []byte("c")}))
+}
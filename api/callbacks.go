@@ -9,26 +9,53 @@ package api
 typedef GoResult (*read_db_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *val, Buffer *errOut);
 typedef GoResult (*write_db_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer val, Buffer *errOut);
 typedef GoResult (*remove_db_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *errOut);
-typedef GoResult (*scan_db_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, int32_t order, GoIter *out, Buffer *errOut);
+typedef GoResult (*scan_db_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, int32_t order, int32_t mode, GoIter *out, Buffer *errOut);
+typedef GoResult (*scan_prefix_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer prefix, uint32_t limit, int32_t order, int32_t mode, GoIter *out, Buffer *errOut);
+typedef GoResult (*delete_range_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, Buffer *errOut);
+typedef GoResult (*set_block_context_fn)(db_t *ptr, BlockContext block, Buffer *errOut);
+typedef GoResult (*gas_consumed_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t total, Buffer *errOut);
+typedef GoResult (*authorize_call_fn)(db_t *ptr, Buffer codeID, Buffer sender, Buffer entryPoint, bool *allowed, Buffer *errOut);
+typedef GoResult (*call_result_fn)(db_t *ptr, Buffer codeID, Buffer entryPoint, Buffer result, bool plaintext, Buffer *errOut);
+typedef GoResult (*resolve_contract_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer addr, Buffer *codeID, Buffer *contractKey, DB *out, Buffer *errOut);
 // iterator
 typedef GoResult (*next_db_fn)(iterator_t idx, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer *key, Buffer *val, Buffer *errOut);
+typedef GoResult (*prefetch_db_fn)(iterator_t idx, uint64_t *used_gas, Buffer *key, Buffer *val, Buffer *errOut);
+typedef GoResult (*seek_db_fn)(iterator_t idx, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *errOut);
 // and api
 typedef GoResult (*humanize_address_fn)(api_t *ptr, Buffer canon, Buffer *human, Buffer *errOut, uint64_t *used_gas);
 typedef GoResult (*canonicalize_address_fn)(api_t *ptr, Buffer human, Buffer *canon, Buffer *errOut, uint64_t *used_gas);
 typedef GoResult (*query_external_fn)(querier_t *ptr, uint64_t gas_limit, uint64_t *used_gas, Buffer request, Buffer *result, Buffer *errOut);
+typedef GoResult (*querier_set_block_context_fn)(querier_t *ptr, BlockContext block, Buffer *errOut);
+// async calls
+typedef void (*async_call_done_fn)(uint64_t handle);
 
 // forward declarations (db)
 GoResult cGet_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *val, Buffer *errOut);
 GoResult cSet_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer val, Buffer *errOut);
 GoResult cDelete_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *errOut);
-GoResult cScan_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, int32_t order, GoIter *out, Buffer *errOut);
+GoResult cScan_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, int32_t order, int32_t mode, GoIter *out, Buffer *errOut);
+GoResult cScanPrefix_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer prefix, uint32_t limit, int32_t order, int32_t mode, GoIter *out, Buffer *errOut);
+GoResult cDeleteRange_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, Buffer *errOut);
+GoResult cSetBlockContext_cgo(db_t *ptr, BlockContext block, Buffer *errOut);
+GoResult cGasConsumed_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t total, Buffer *errOut);
+GoResult cAuthorizeCall_cgo(db_t *ptr, Buffer codeID, Buffer sender, Buffer entryPoint, bool *allowed, Buffer *errOut);
+GoResult cCallResult_cgo(db_t *ptr, Buffer codeID, Buffer entryPoint, Buffer result, bool plaintext, Buffer *errOut);
+GoResult cResolveContract_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer addr, Buffer *codeID, Buffer *contractKey, DB *out, Buffer *errOut);
 // iterator
 GoResult cNext_cgo(iterator_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer *key, Buffer *val, Buffer *errOut);
+GoResult cPrefetch_cgo(iterator_t *ptr, uint64_t *used_gas, Buffer *key, Buffer *val, Buffer *errOut);
+GoResult cSeek_cgo(iterator_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *errOut);
 // api
 GoResult cHumanAddress_cgo(api_t *ptr, Buffer canon, Buffer *human, Buffer *errOut, uint64_t *used_gas);
 GoResult cCanonicalAddress_cgo(api_t *ptr, Buffer human, Buffer *canon, Buffer *errOut, uint64_t *used_gas);
 // and querier
 GoResult cQueryExternal_cgo(querier_t *ptr, uint64_t gas_limit, uint64_t *used_gas, Buffer request, Buffer *result, Buffer *errOut);
+GoResult cQuerierSetBlockContext_cgo(querier_t *ptr, BlockContext block, Buffer *errOut);
+// and async calls
+void cAsyncCallDone_cgo(uint64_t handle);
+
+// go_handle turns a handle of goHandles into the pointer handed to the Rust side in place of a Go pointer
+static void *go_handle(uintptr_t handle) { return (void *)handle; }
 
 
 */
@@ -39,6 +66,7 @@ import (
 	"fmt"
 	"log"
 	"reflect"
+	"sync"
 	"unsafe"
 
 	dbm "github.com/tendermint/tm-db"
@@ -85,6 +113,14 @@ type GasMeter interface {
 	GasConsumed() Gas
 }
 
+// GasProgressMeter can optionally be implemented by the GasMeter to follow the gas of a running contract call,
+// see CacheOptions.GasSyncInterval
+type GasProgressMeter interface {
+	// ContractGasProgress receives the gas the call charged through its callbacks so far. The gas of wasm
+	// execution is only known when the call returns. Returning false ends the call with out of gas.
+	ContractGasProgress(charged uint64) bool
+}
+
 /****** DB ********/
 
 // KVStore copies a subset of types from cosmos-sdk
@@ -107,17 +143,121 @@ type KVStore interface {
 	ReverseIterator(start, end []byte) dbm.Iterator
 }
 
+// ContractResolver can optionally be implemented by the KVStore passed to Instantiate and Handle.
+// If it is, fund-less wasm execute messages returned by the contract are executed directly
+// by the Rust side, without returning to Go in between.
+type ContractResolver interface {
+	// ResolveContract returns the code id, contract key and prefixed store of the contract with the given address.
+	// It should return a nil store if the address is not a known contract.
+	ResolveContract(contractAddr string) (codeID []byte, contractKey string, store KVStore, err error)
+}
+
+// RangeDeleter can optionally be implemented by the KVStore to remove a whole range of keys at once.
+// Otherwise ranges are removed key by key.
+type RangeDeleter interface {
+	// DeleteRange removes all keys in [start, end). Nil bounds are unbounded.
+	DeleteRange(start, end []byte)
+}
+
+// deleteRange removes all keys in [start, end) from kv
+func deleteRange(kv KVStore, start, end []byte) {
+	if deleter, ok := kv.(RangeDeleter); ok {
+		deleter.DeleteRange(start, end)
+		return
+	}
+	// collect the keys first, as stores do not support writes while iterating
+	iter := kv.Iterator(start, end)
+	var keys [][]byte
+	for ; iter.Valid(); iter.Next() {
+		keys = append(keys, iter.Key())
+	}
+	iter.Close()
+	for _, k := range keys {
+		kv.Delete(k)
+	}
+}
+
+// NamespacedStore can optionally be implemented by the KVStore of a contract, typically a prefix store.
+// The Rust side is then handed the parent store and applies the prefix to every key itself, checking
+// that no key read back lies outside of it.
+type NamespacedStore interface {
+	Namespace() (parent KVStore, prefix []byte)
+}
+
+// BlockContext is the block of the contract call running on a store
+type BlockContext struct {
+	Height uint64 `json:"height"`
+	// Time is in seconds since the UNIX epoch
+	Time uint64 `json:"time"`
+}
+
+// BlockContextStore can optionally be implemented by the KVStore to learn the block of a contract call
+// before any of its storage operations, e.g. for height-scoped reads or gas pricing.
+type BlockContextStore interface {
+	SetBlockContext(block BlockContext)
+}
+
+// BlockContextQuerier can optionally be implemented by the Querier to learn the block a query passed to Query is
+// pinned to, before the contract runs. Serving the queries of the contract at the same height keeps historical
+// queries consistent, and a custom query can tell the contract the height it is queried at.
+type BlockContextQuerier interface {
+	SetBlockContext(block BlockContext)
+}
+
+// CallAuthorizer can optionally be implemented by the KVStore to allow or deny contract calls before they run,
+// e.g. for allowlists of codes or circuit breakers. Internal calls are authorized by the store of the callee.
+type CallAuthorizer interface {
+	// AuthorizeCall returns an error to deny the call of the entryPoint ("init", "handle", "migrate" or "query")
+	// of the code codeID by sender, which is empty for queries. The call then fails with types.CallDeniedError.
+	AuthorizeCall(codeID []byte, sender string, entryPoint string) error
+}
+
+// CallResultStore can optionally be implemented by the KVStore to receive the results of successful contract calls,
+// e.g. for an indexer running next to the node. Internal calls report to the store of the callee.
+type CallResultStore interface {
+	// CallResult gets the result of a call of the entryPoint ("init", "handle" or "migrate") of the code codeID.
+	// It is plaintext JSON for plaintext codes and encrypted for the sender otherwise. Results are reported as
+	// soon as the contract ran, so they must be discarded if the transaction fails later on.
+	CallResult(codeID []byte, entryPoint string, result []byte, plaintext bool)
+}
+
+// PrefetchStore can optionally be implemented by the KVStore to hide the latency of long range scans.
+// Iterators it returns are read ahead on a background thread while the contract processes earlier items.
+type PrefetchStore interface {
+	// PrefetchIterator and PrefetchReverseIterator are like Iterator and ReverseIterator, but must not
+	// consume gas and must be safe to advance concurrently with other operations on the store. They must
+	// iterate over a snapshot of the store taken when they are opened: the contract may write to the store
+	// while the iterator is read ahead, and an iterator seeing the writes would return items depending on how
+	// far it was read, which differs between nodes.
+	PrefetchIterator(start, end []byte) dbm.Iterator
+	PrefetchReverseIterator(start, end []byte) dbm.Iterator
+	// IteratorNextGas is the gas of reading one item from a prefetch iterator. It is charged when
+	// the contract consumes the item, so it must only depend on the item.
+	IteratorNextGas(key, value []byte) uint64
+}
+
 var db_vtable = C.DB_vtable{
-	read_db:   (C.read_db_fn)(C.cGet_cgo),
-	write_db:  (C.write_db_fn)(C.cSet_cgo),
-	remove_db: (C.remove_db_fn)(C.cDelete_cgo),
-	scan_db:   (C.scan_db_fn)(C.cScan_cgo),
+	read_db:           (C.read_db_fn)(C.cGet_cgo),
+	write_db:          (C.write_db_fn)(C.cSet_cgo),
+	remove_db:         (C.remove_db_fn)(C.cDelete_cgo),
+	scan_db:           (C.scan_db_fn)(C.cScan_cgo),
+	resolve_contract:  (C.resolve_contract_fn)(C.cResolveContract_cgo),
+	scan_prefix:       (C.scan_prefix_fn)(C.cScanPrefix_cgo),
+	delete_range:      (C.delete_range_fn)(C.cDeleteRange_cgo),
+	set_block_context: (C.set_block_context_fn)(C.cSetBlockContext_cgo),
+	gas_consumed:      (C.gas_consumed_fn)(C.cGasConsumed_cgo),
+	authorize_call:    (C.authorize_call_fn)(C.cAuthorizeCall_cgo),
+	call_result:       (C.call_result_fn)(C.cCallResult_cgo),
 }
 
 type DBState struct {
 	Store KVStore
 	// IteratorStackID is used to lookup the proper stack frame for iterators associated with this DB (iterator.go)
 	IteratorStackID uint64
+	// Block is the block of the contract call running on this DB, as parsed by the Rust side
+	Block BlockContext
+	// namespace is the prefix the Rust side applies to all keys of Store, see NamespacedStore
+	namespace []byte
 }
 
 // use this to create C.DB in two steps, so the pointer lives as long as the calling stack
@@ -125,6 +265,15 @@ type DBState struct {
 //   db := buildDB(&state, &gasMeter)
 //   // then pass db into some FFI function
 func buildDBState(kv KVStore, counter uint64) DBState {
+	if namespaced, ok := kv.(NamespacedStore); ok {
+		if parent, prefix := namespaced.Namespace(); len(prefix) > 0 {
+			return DBState{
+				Store:           parent,
+				IteratorStackID: counter,
+				namespace:       prefix,
+			}
+		}
+	}
 	return DBState{
 		Store:           kv,
 		IteratorStackID: counter,
@@ -138,11 +287,128 @@ func buildDB(state *DBState, gm *GasMeter) C.DB {
 		gas_meter: (*C.gas_meter_t)(unsafe.Pointer(gm)),
 		state:     (*C.db_t)(unsafe.Pointer(state)),
 		vtable:    db_vtable,
+		namespace: sendSlice(state.namespace),
 	}
 }
 
+// goHandles holds the Go values the Rust side refers to after the cgo call handing them over returned, which the
+// cgo pointer rules forbid for Go pointers. The Rust side gets a handle in place of the pointer. Handles are odd,
+// unlike the pointers passed for the duration of a call, so the callbacks can tell them apart, see dbState.
+var goHandles = make(map[uintptr]interface{})
+var lastGoHandle uintptr = 1
+var goHandlesMutex sync.Mutex
+
+// storeGoHandle adds value to goHandles and returns its handle
+func storeGoHandle(value interface{}) uintptr {
+	goHandlesMutex.Lock()
+	defer goHandlesMutex.Unlock()
+
+	lastGoHandle += 2
+	goHandles[lastGoHandle] = value
+	return lastGoHandle
+}
+
+// goHandlePointer returns the pointer the Rust side gets for handle
+func goHandlePointer(handle uintptr) unsafe.Pointer {
+	return C.go_handle(C.uintptr_t(handle))
+}
+
+// loadGoHandle returns the value behind ptr, which is either a Go pointer or a handle of goHandles. ok is false
+// for Go pointers, and value is nil for released handles.
+func loadGoHandle(ptr unsafe.Pointer) (value interface{}, ok bool) {
+	handle := uintptr(ptr)
+	if handle&1 == 0 {
+		return nil, false
+	}
+	goHandlesMutex.Lock()
+	defer goHandlesMutex.Unlock()
+	return goHandles[handle], true
+}
+
+func releaseGoHandle(handle uintptr) {
+	goHandlesMutex.Lock()
+	defer goHandlesMutex.Unlock()
+	delete(goHandles, handle)
+}
+
+// resolvedState is the state of the DB of a contract resolved by cResolveContract
+type resolvedState struct {
+	handle uintptr
+	// namespace is the copy of the namespace of the state handed to the Rust side
+	namespace C.Buffer
+}
+
+// resolvedFrames lists the states resolved in each frame of the iteratorStack. They live until the end of the
+// contract runtime which resolved them, like its iterators.
+var resolvedFrames = make(map[uint64][]resolvedState)
+var resolvedFramesMutex sync.Mutex
+
+// storeResolvedState adds state to goHandles and returns its handle and a copy of its namespace for a C.DB
+func storeResolvedState(state *DBState) (*C.db_t, C.Buffer) {
+	handle := storeGoHandle(state)
+	namespace := sendSlice(state.namespace)
+
+	resolvedFramesMutex.Lock()
+	defer resolvedFramesMutex.Unlock()
+	resolvedFrames[state.IteratorStackID] = append(resolvedFrames[state.IteratorStackID], resolvedState{handle, namespace})
+	return (*C.db_t)(goHandlePointer(handle)), namespace
+}
+
+// releaseResolvedStates removes the states resolved in the frame counter of the iteratorStack, see endContract
+func releaseResolvedStates(counter uint64) {
+	resolvedFramesMutex.Lock()
+	defer resolvedFramesMutex.Unlock()
+
+	for _, resolved := range resolvedFrames[counter] {
+		freeAfterSend(resolved.namespace)
+		releaseGoHandle(resolved.handle)
+	}
+	delete(resolvedFrames, counter)
+}
+
+// dbState returns the state behind the state pointer of a DB, which is a handle of goHandles for resolved DBs and
+// asynchronous calls
+func dbState(ptr *C.db_t) *DBState {
+	if value, ok := loadGoHandle(unsafe.Pointer(ptr)); ok {
+		state, _ := value.(*DBState)
+		return state
+	}
+	return (*DBState)(unsafe.Pointer(ptr))
+}
+
+// dbGasMeter returns the gas meter behind the gas meter pointer of a DB, which is a handle of goHandles for
+// asynchronous calls
+func dbGasMeter(ptr *C.gas_meter_t) GasMeter {
+	if value, ok := loadGoHandle(unsafe.Pointer(ptr)); ok {
+		return *value.(*GasMeter)
+	}
+	return *(*GasMeter)(unsafe.Pointer(ptr))
+}
+
 var iterator_vtable = C.Iterator_vtable{
 	next_db: (C.next_db_fn)(C.cNext_cgo),
+	seek_db: (C.seek_db_fn)(C.cSeek_cgo),
+}
+
+var prefetch_iterator_vtable = C.Iterator_vtable{
+	next_db:     (C.next_db_fn)(C.cNext_cgo),
+	prefetch_db: (C.prefetch_db_fn)(C.cPrefetch_cgo),
+	seek_db:     (C.seek_db_fn)(C.cSeek_cgo),
+}
+
+// keysOnlyIterator returns empty values, so they are not copied over to Rust
+type keysOnlyIterator struct {
+	dbm.Iterator
+}
+
+func (keysOnlyIterator) Value() []byte {
+	return []byte{}
+}
+
+// prefetchIterator is an iterator of a PrefetchStore, along with the gas function of the store
+type prefetchIterator struct {
+	dbm.Iterator
+	nextGas func(key, value []byte) uint64
 }
 
 // contract: original pointer/struct referenced must live longer than C.DB struct
@@ -163,8 +429,8 @@ func cGet(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *u64, key C.Buffer, val
 		return C.GoResult_BadArgument
 	}
 
-	gm := *(*GasMeter)(unsafe.Pointer(gasMeter))
-	kv := *(*KVStore)(unsafe.Pointer(ptr))
+	gm := dbGasMeter(gasMeter)
+	kv := dbState(ptr).Store
 	k := receiveSlice(key)
 
 	gasBefore := gm.GasConsumed()
@@ -192,8 +458,8 @@ func cSet(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, key C.Buffe
 		return C.GoResult_BadArgument
 	}
 
-	gm := *(*GasMeter)(unsafe.Pointer(gasMeter))
-	kv := *(*KVStore)(unsafe.Pointer(ptr))
+	gm := dbGasMeter(gasMeter)
+	kv := dbState(ptr).Store
 	k := receiveSlice(key)
 	v := receiveSlice(val)
 
@@ -213,8 +479,8 @@ func cDelete(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, key C.Bu
 		return C.GoResult_BadArgument
 	}
 
-	gm := *(*GasMeter)(unsafe.Pointer(gasMeter))
-	kv := *(*KVStore)(unsafe.Pointer(ptr))
+	gm := dbGasMeter(gasMeter)
+	kv := dbState(ptr).Store
 	k := receiveSlice(key)
 
 	gasBefore := gm.GasConsumed()
@@ -225,17 +491,109 @@ func cDelete(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, key C.Bu
 	return C.GoResult_Ok
 }
 
+//export cSetBlockContext
+func cSetBlockContext(ptr *C.db_t, block C.BlockContext, errOut *C.Buffer) (ret C.GoResult) {
+	defer recoverPanic(&ret)
+	if ptr == nil {
+		// we received an invalid pointer
+		return C.GoResult_BadArgument
+	}
+
+	state := dbState(ptr)
+	state.Block = BlockContext{
+		Height: uint64(block.height),
+		Time:   uint64(block.time),
+	}
+	if store, ok := state.Store.(BlockContextStore); ok {
+		store.SetBlockContext(state.Block)
+	}
+	return C.GoResult_Ok
+}
+
+//export cGasConsumed
+func cGasConsumed(ptr *C.db_t, gasMeter *C.gas_meter_t, total C.uint64_t, errOut *C.Buffer) (ret C.GoResult) {
+	defer recoverPanic(&ret)
+	if ptr == nil || gasMeter == nil {
+		// we received an invalid pointer
+		return C.GoResult_BadArgument
+	}
+
+	gm := dbGasMeter(gasMeter)
+	if progress, ok := gm.(GasProgressMeter); ok && !progress.ContractGasProgress(uint64(total)) {
+		return C.GoResult_OutOfGas
+	}
+	return C.GoResult_Ok
+}
+
+//export cAuthorizeCall
+func cAuthorizeCall(ptr *C.db_t, codeID C.Buffer, sender C.Buffer, entryPoint C.Buffer, allowed *C.bool, errOut *C.Buffer) (ret C.GoResult) {
+	defer recoverPanic(&ret)
+	if ptr == nil || allowed == nil || errOut == nil {
+		// we received an invalid pointer
+		return C.GoResult_BadArgument
+	}
+
+	store, ok := dbState(ptr).Store.(CallAuthorizer)
+	if !ok {
+		return C.GoResult_Ok
+	}
+	err := store.AuthorizeCall(receiveSlice(codeID), string(receiveSlice(sender)), string(receiveSlice(entryPoint)))
+	if err != nil {
+		*allowed = C.bool(false)
+		*errOut = allocateRust([]byte(err.Error()))
+	}
+	return C.GoResult_Ok
+}
+
+//export cCallResult
+func cCallResult(ptr *C.db_t, codeID C.Buffer, entryPoint C.Buffer, result C.Buffer, plaintext C.bool, errOut *C.Buffer) (ret C.GoResult) {
+	defer recoverPanic(&ret)
+	if ptr == nil {
+		// we received an invalid pointer
+		return C.GoResult_BadArgument
+	}
+
+	if store, ok := dbState(ptr).Store.(CallResultStore); ok {
+		store.CallResult(receiveSlice(codeID), string(receiveSlice(entryPoint)), receiveSlice(result), bool(plaintext))
+	}
+	return C.GoResult_Ok
+}
+
+//export cDeleteRange
+func cDeleteRange(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, start C.Buffer, end C.Buffer, errOut *C.Buffer) (ret C.GoResult) {
+	defer recoverPanic(&ret)
+	if ptr == nil || gasMeter == nil || usedGas == nil {
+		// we received an invalid pointer
+		return C.GoResult_BadArgument
+	}
+
+	gm := dbGasMeter(gasMeter)
+	kv := dbState(ptr).Store
+	// handle null as well as data
+	var s, e []byte
+	if start.ptr != nil {
+		s = receiveSlice(start)
+	}
+	if end.ptr != nil {
+		e = receiveSlice(end)
+	}
+
+	gasBefore := gm.GasConsumed()
+	deleteRange(kv, s, e)
+	gasAfter := gm.GasConsumed()
+	*usedGas = (C.uint64_t)(gasAfter - gasBefore)
+
+	return C.GoResult_Ok
+}
+
 //export cScan
-func cScan(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, start C.Buffer, end C.Buffer, order i32, out *C.GoIter, errOut *C.Buffer) (ret C.GoResult) {
+func cScan(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, start C.Buffer, end C.Buffer, order i32, mode i32, out *C.GoIter, errOut *C.Buffer) (ret C.GoResult) {
 	defer recoverPanic(&ret)
 	if ptr == nil || gasMeter == nil || usedGas == nil || out == nil {
 		// we received an invalid pointer
 		return C.GoResult_BadArgument
 	}
 
-	gm := *(*GasMeter)(unsafe.Pointer(gasMeter))
-	state := (*DBState)(unsafe.Pointer(ptr))
-	kv := state.Store
 	// handle null as well as data
 	var s, e []byte
 	if start.ptr != nil {
@@ -244,22 +602,105 @@ func cScan(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, start C.Bu
 	if end.ptr != nil {
 		e = receiveSlice(end)
 	}
+	return openIterator(ptr, gasMeter, usedGas, s, e, 0, order, mode, out)
+}
 
-	var iter dbm.Iterator
-	gasBefore := gm.GasConsumed()
-	switch order {
-	case 1: // Ascending
-		iter = kv.Iterator(s, e)
-	case 2: // Descending
-		iter = kv.ReverseIterator(s, e)
+//export cScanPrefix
+func cScanPrefix(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, prefix C.Buffer, limit C.uint32_t, order i32, mode i32, out *C.GoIter, errOut *C.Buffer) (ret C.GoResult) {
+	defer recoverPanic(&ret)
+	if ptr == nil || gasMeter == nil || usedGas == nil || out == nil {
+		// we received an invalid pointer
+		return C.GoResult_BadArgument
+	}
+
+	p := receiveSlice(prefix)
+	return openIterator(ptr, gasMeter, usedGas, p, prefixEnd(p), uint32(limit), order, mode, out)
+}
+
+// openIterator opens an iterator over [start, end) for cScan and cScanPrefix. A limit of 0 means no limit.
+func openIterator(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, s, e []byte, limit uint32, order i32, mode i32, out *C.GoIter) C.GoResult {
+	gm := dbGasMeter(gasMeter)
+	state := dbState(ptr)
+	kv := state.Store
+
+	prefetch, prefetching := kv.(PrefetchStore)
+	var open func(start, end []byte) dbm.Iterator
+	switch {
+	case order == 1 && prefetching: // Ascending
+		open = prefetch.PrefetchIterator
+	case order == 2 && prefetching: // Descending
+		open = prefetch.PrefetchReverseIterator
+	case order == 1:
+		open = kv.Iterator
+	case order == 2:
+		open = kv.ReverseIterator
 	default:
 		return C.GoResult_BadArgument
 	}
+	if mode == C.IteratorMode_KeysOnly {
+		openAll := open
+		open = func(start, end []byte) dbm.Iterator {
+			return keysOnlyIterator{openAll(start, end)}
+		}
+	}
+	if limit > 0 {
+		// the limit counts from the last seek
+		openUnlimited := open
+		open = func(start, end []byte) dbm.Iterator {
+			return &limitIterator{Iterator: openUnlimited(start, end), remaining: limit}
+		}
+	}
+
+	gasBefore := gm.GasConsumed()
+	iter := newSeekIterator(open, s, e, order == 1)
+	gasAfter := gm.GasConsumed()
+	*usedGas = (C.uint64_t)(gasAfter - gasBefore)
+
+	if prefetching {
+		out.state = buildIterator(state.IteratorStackID, prefetchIterator{iter, prefetch.IteratorNextGas})
+		out.vtable = prefetch_iterator_vtable
+	} else {
+		out.state = buildIterator(state.IteratorStackID, iter)
+		out.vtable = iterator_vtable
+	}
+	return C.GoResult_Ok
+}
+
+//export cResolveContract
+func cResolveContract(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, addr C.Buffer, codeID *C.Buffer, contractKey *C.Buffer, out *C.DB, errOut *C.Buffer) (ret C.GoResult) {
+	defer recoverPanic(&ret)
+	if ptr == nil || gasMeter == nil || usedGas == nil || codeID == nil || contractKey == nil || out == nil {
+		// we received an invalid pointer
+		return C.GoResult_BadArgument
+	}
+
+	gm := dbGasMeter(gasMeter)
+	state := dbState(ptr)
+	resolver, ok := state.Store.(ContractResolver)
+	if !ok {
+		// internal calls are opt-in. Leaving the outputs empty tells Rust to return the messages to us
+		return C.GoResult_Ok
+	}
+	a := string(receiveSlice(addr))
+
+	gasBefore := gm.GasConsumed()
+	id, key, store, err := resolver.ResolveContract(a)
 	gasAfter := gm.GasConsumed()
 	*usedGas = (C.uint64_t)(gasAfter - gasBefore)
+	if err != nil {
+		*errOut = allocateRust([]byte(err.Error()))
+		return C.GoResult_Other
+	}
+	if store == nil || len(id) == 0 {
+		return C.GoResult_Ok
+	}
 
-	out.state = buildIterator(state.IteratorStackID, iter)
-	out.vtable = iterator_vtable
+	// iterators of the resolved contract live in the same stack frame as the ones of the caller
+	resolved := buildDBState(store, state.IteratorStackID)
+	*codeID = allocateRust(id)
+	*contractKey = allocateRust([]byte(key))
+	out.state, out.namespace = storeResolvedState(&resolved)
+	out.vtable = db_vtable
 	return C.GoResult_Ok
 }
 
@@ -277,7 +718,7 @@ func cNext(ref C.iterator_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, key *
 		return C.GoResult_BadArgument
 	}
 
-	gm := *(*GasMeter)(unsafe.Pointer(gasMeter))
+	gm := dbGasMeter(gasMeter)
 	iter := retrieveIterator(uint64(ref.db_counter), uint64(ref.iterator_index))
 	if !iter.Valid() {
 		// end of iterator, return as no-op, nil key is considered end
@@ -300,6 +741,64 @@ func cNext(ref C.iterator_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, key *
 	return C.GoResult_Ok
 }
 
+//export cPrefetch
+func cPrefetch(ref C.iterator_t, usedGas *C.uint64_t, key *C.Buffer, val *C.Buffer, errOut *C.Buffer) (ret C.GoResult) {
+	// called from a background thread of the Rust side, so this must not touch the gas meter
+	defer recoverPanic(&ret)
+	if ref.db_counter == 0 || usedGas == nil || key == nil || val == nil {
+		// we received an invalid pointer
+		return C.GoResult_BadArgument
+	}
+
+	iter, ok := retrieveIterator(uint64(ref.db_counter), uint64(ref.iterator_index)).(prefetchIterator)
+	if !ok {
+		return C.GoResult_BadArgument
+	}
+	if !iter.Valid() {
+		// end of iterator, return as no-op, nil key is considered end
+		return C.GoResult_Ok
+	}
+
+	k := iter.Key()
+	v := iter.Value()
+	iter.Next()
+	*usedGas = (C.uint64_t)(iter.nextGas(k, v))
+
+	if k != nil {
+		*key = allocateRust(k)
+		*val = allocateRust(v)
+	}
+	return C.GoResult_Ok
+}
+
+//export cSeek
+func cSeek(ref C.iterator_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, key C.Buffer, errOut *C.Buffer) (ret C.GoResult) {
+	defer recoverPanic(&ret)
+	if ref.db_counter == 0 || gasMeter == nil || usedGas == nil {
+		// we received an invalid pointer
+		return C.GoResult_BadArgument
+	}
+
+	gm := dbGasMeter(gasMeter)
+	var iter *seekIterator
+	switch it := retrieveIterator(uint64(ref.db_counter), uint64(ref.iterator_index)).(type) {
+	case *seekIterator:
+		iter = it
+	case prefetchIterator:
+		iter = it.Iterator.(*seekIterator)
+	default:
+		return C.GoResult_BadArgument
+	}
+	k := receiveSlice(key)
+
+	gasBefore := gm.GasConsumed()
+	iter.Seek(k)
+	gasAfter := gm.GasConsumed()
+	*usedGas = (C.uint64_t)(gasAfter - gasBefore)
+
+	return C.GoResult_Ok
+}
+
 /***** GoAPI *******/
 
 type HumanizeAddress func([]byte) (string, uint64, error)
@@ -324,6 +823,14 @@ func buildAPI(api *GoAPI) C.GoApi {
 	}
 }
 
+// goAPI returns the api behind the state pointer of a GoApi, which is a handle of goHandles for asynchronous calls
+func goAPI(ptr *C.api_t) *GoAPI {
+	if value, ok := loadGoHandle(unsafe.Pointer(ptr)); ok {
+		return value.(*GoAPI)
+	}
+	return (*GoAPI)(unsafe.Pointer(ptr))
+}
+
 //export cHumanAddress
 func cHumanAddress(ptr *C.api_t, canon C.Buffer, human *C.Buffer, errOut *C.Buffer, used_gas *u64) (ret C.GoResult) {
 	defer recoverPanic(&ret)
@@ -331,7 +838,7 @@ func cHumanAddress(ptr *C.api_t, canon C.Buffer, human *C.Buffer, errOut *C.Buff
 		// we received an invalid pointer
 		return C.GoResult_BadArgument
 	}
-	api := (*GoAPI)(unsafe.Pointer(ptr))
+	api := goAPI(ptr)
 	c := receiveSlice(canon)
 	h, cost, err := api.HumanAddress(c)
 	*used_gas = u64(cost)
@@ -356,7 +863,7 @@ func cCanonicalAddress(ptr *C.api_t, human C.Buffer, canon *C.Buffer, errOut *C.
 		return C.GoResult_BadArgument
 	}
 
-	api := (*GoAPI)(unsafe.Pointer(ptr))
+	api := goAPI(ptr)
 	h := string(receiveSlice(human))
 	c, cost, err := api.CanonicalAddress(h)
 	*used_gas = u64(cost)
@@ -377,7 +884,8 @@ func cCanonicalAddress(ptr *C.api_t, human C.Buffer, canon *C.Buffer, errOut *C.
 /****** Go Querier ********/
 
 var querier_vtable = C.Querier_vtable{
-	query_external: (C.query_external_fn)(C.cQueryExternal_cgo),
+	query_external:    (C.query_external_fn)(C.cQueryExternal_cgo),
+	set_block_context: (C.querier_set_block_context_fn)(C.cQuerierSetBlockContext_cgo),
 }
 
 // contract: original pointer/struct referenced must live longer than C.GoQuerier struct
@@ -389,6 +897,15 @@ func buildQuerier(q *Querier) C.GoQuerier {
 	}
 }
 
+// goQuerier returns the querier behind the state pointer of a GoQuerier, which is a handle of goHandles for
+// asynchronous calls
+func goQuerier(ptr *C.querier_t) Querier {
+	if value, ok := loadGoHandle(unsafe.Pointer(ptr)); ok {
+		return *value.(*Querier)
+	}
+	return *(*Querier)(unsafe.Pointer(ptr))
+}
+
 //export cQueryExternal
 func cQueryExternal(ptr *C.querier_t, gasLimit C.uint64_t, usedGas *C.uint64_t, request C.Buffer, result *C.Buffer, errOut *C.Buffer) (ret C.GoResult) {
 	defer recoverPanic(&ret)
@@ -398,7 +915,7 @@ func cQueryExternal(ptr *C.querier_t, gasLimit C.uint64_t, usedGas *C.uint64_t,
 	}
 
 	// query the data
-	querier := *(*Querier)(unsafe.Pointer(ptr))
+	querier := goQuerier(ptr)
 	req := receiveSlice(request)
 
 	gasBefore := querier.GasConsumed()
@@ -415,3 +932,36 @@ func cQueryExternal(ptr *C.querier_t, gasLimit C.uint64_t, usedGas *C.uint64_t,
 	*result = allocateRust(bz)
 	return C.GoResult_Ok
 }
+
+//export cQuerierSetBlockContext
+func cQuerierSetBlockContext(ptr *C.querier_t, block C.BlockContext, errOut *C.Buffer) (ret C.GoResult) {
+	defer recoverPanic(&ret)
+	if ptr == nil {
+		// we received an invalid pointer
+		return C.GoResult_BadArgument
+	}
+
+	querier := goQuerier(ptr)
+	if q, ok := querier.(BlockContextQuerier); ok {
+		q.SetBlockContext(BlockContext{
+			Height: uint64(block.height),
+			Time:   uint64(block.time),
+		})
+	}
+	return C.GoResult_Ok
+}
+
+/****** Async calls ********/
+
+var asyncCallDone = (C.async_call_done_fn)(C.cAsyncCallDone_cgo)
+
+//export cAsyncCallDone
+func cAsyncCallDone(handle C.uint64_t) {
+	asyncCallsMutex.Lock()
+	call, ok := asyncCalls[u64(handle)]
+	delete(asyncCalls, u64(handle))
+	asyncCallsMutex.Unlock()
+	if ok {
+		close(call.done)
+	}
+}
@@ -10,14 +10,26 @@ package api
 GoResult cSet(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer val, Buffer *errOut);
 GoResult cGet(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *val, Buffer *errOut);
 GoResult cDelete(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *errOut);
-GoResult cScan(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, int32_t order, GoIter *out, Buffer *errOut);
+GoResult cScan(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, int32_t order, int32_t mode, GoIter *out, Buffer *errOut);
+GoResult cScanPrefix(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer prefix, uint32_t limit, int32_t order, int32_t mode, GoIter *out, Buffer *errOut);
+GoResult cDeleteRange(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, Buffer *errOut);
+GoResult cSetBlockContext(db_t *ptr, BlockContext block, Buffer *errOut);
+GoResult cGasConsumed(db_t *ptr, gas_meter_t *gas_meter, uint64_t total, Buffer *errOut);
+GoResult cAuthorizeCall(db_t *ptr, Buffer codeID, Buffer sender, Buffer entryPoint, bool *allowed, Buffer *errOut);
+GoResult cCallResult(db_t *ptr, Buffer codeID, Buffer entryPoint, Buffer result, bool plaintext, Buffer *errOut);
+GoResult cResolveContract(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer addr, Buffer *codeID, Buffer *contractKey, DB *out, Buffer *errOut);
 // imports (iterator)
 GoResult cNext(iterator_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer *key, Buffer *val, Buffer *errOut);
+GoResult cPrefetch(iterator_t *ptr, uint64_t *used_gas, Buffer *key, Buffer *val, Buffer *errOut);
+GoResult cSeek(iterator_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *errOut);
 // imports (api)
 GoResult cHumanAddress(api_t *ptr, Buffer canon, Buffer *human, Buffer *errOut, uint64_t *used_gas);
 GoResult cCanonicalAddress(api_t *ptr, Buffer human, Buffer *canon, Buffer *errOut, uint64_t *used_gas);
 // imports (querier)
 GoResult cQueryExternal(querier_t *ptr, uint64_t gas_limit, uint64_t *used_gas, Buffer request, Buffer *result, Buffer *errOut);
+GoResult cQuerierSetBlockContext(querier_t *ptr, BlockContext block, Buffer *errOut);
+// imports (async calls)
+void cAsyncCallDone(uint64_t handle);
 
 // Gateway functions (db)
 GoResult cGet_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *val, Buffer *errOut) {
@@ -29,14 +41,41 @@ GoResult cSet_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer
 GoResult cDelete_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *errOut) {
 	return cDelete(ptr, gas_meter, used_gas, key, errOut);
 }
-GoResult cScan_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, int32_t order, GoIter *out, Buffer *errOut) {
-	return cScan(ptr, gas_meter, used_gas, start, end, order, out, errOut);
+GoResult cScan_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, int32_t order, int32_t mode, GoIter *out, Buffer *errOut) {
+	return cScan(ptr, gas_meter, used_gas, start, end, order, mode, out, errOut);
+}
+GoResult cScanPrefix_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer prefix, uint32_t limit, int32_t order, int32_t mode, GoIter *out, Buffer *errOut) {
+	return cScanPrefix(ptr, gas_meter, used_gas, prefix, limit, order, mode, out, errOut);
+}
+GoResult cDeleteRange_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, Buffer *errOut) {
+	return cDeleteRange(ptr, gas_meter, used_gas, start, end, errOut);
+}
+GoResult cSetBlockContext_cgo(db_t *ptr, BlockContext block, Buffer *errOut) {
+	return cSetBlockContext(ptr, block, errOut);
+}
+GoResult cGasConsumed_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t total, Buffer *errOut) {
+	return cGasConsumed(ptr, gas_meter, total, errOut);
+}
+GoResult cAuthorizeCall_cgo(db_t *ptr, Buffer codeID, Buffer sender, Buffer entryPoint, bool *allowed, Buffer *errOut) {
+	return cAuthorizeCall(ptr, codeID, sender, entryPoint, allowed, errOut);
+}
+GoResult cCallResult_cgo(db_t *ptr, Buffer codeID, Buffer entryPoint, Buffer result, bool plaintext, Buffer *errOut) {
+	return cCallResult(ptr, codeID, entryPoint, result, plaintext, errOut);
+}
+GoResult cResolveContract_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer addr, Buffer *codeID, Buffer *contractKey, DB *out, Buffer *errOut) {
+	return cResolveContract(ptr, gas_meter, used_gas, addr, codeID, contractKey, out, errOut);
 }
 
 // Gateway functions (iterator)
 GoResult cNext_cgo(iterator_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer *key, Buffer *val, Buffer *errOut) {
 	return cNext(ptr, gas_meter, used_gas, key, val, errOut);
 }
+GoResult cPrefetch_cgo(iterator_t *ptr, uint64_t *used_gas, Buffer *key, Buffer *val, Buffer *errOut) {
+	return cPrefetch(ptr, used_gas, key, val, errOut);
+}
+GoResult cSeek_cgo(iterator_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *errOut) {
+	return cSeek(ptr, gas_meter, used_gas, key, errOut);
+}
 
 // Gateway functions (api)
 GoResult cCanonicalAddress_cgo(api_t *ptr, Buffer human, Buffer *canon, Buffer *errOut, uint64_t *used_gas) {
@@ -50,6 +89,14 @@ GoResult cHumanAddress_cgo(api_t *ptr, Buffer canon, Buffer *human, Buffer *errO
 GoResult cQueryExternal_cgo(querier_t *ptr, uint64_t gas_limit, uint64_t *used_gas, Buffer request, Buffer *result, Buffer *errOut) {
     return cQueryExternal(ptr, gas_limit, used_gas, request, result, errOut);
 }
+GoResult cQuerierSetBlockContext_cgo(querier_t *ptr, BlockContext block, Buffer *errOut) {
+    return cQuerierSetBlockContext(ptr, block, errOut);
+}
+
+// Gateway functions (async calls)
+void cAsyncCallDone_cgo(uint64_t handle) {
+    cAsyncCallDone(handle);
+}
 */
 import "C"
 
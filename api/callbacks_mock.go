@@ -99,6 +99,11 @@ type GasMeter interface {
 	IsOutOfGas() bool
 }
 
+// GasProgressMeter can optionally be implemented by the GasMeter to follow the gas of a running contract call
+type GasProgressMeter interface {
+	ContractGasProgress(charged uint64) bool
+}
+
 //
 ///****** DB ********/
 //
@@ -111,6 +116,28 @@ type KVStore interface {
 	Delete(key []byte)
 }
 
+// NamespacedStore can optionally be implemented by the KVStore of a contract, typically a prefix store
+type NamespacedStore interface {
+	Namespace() (parent KVStore, prefix []byte)
+}
+
+// BlockContext is the block of the contract call running on a store
+type BlockContext struct {
+	Height uint64 `json:"height"`
+	// Time is in seconds since the UNIX epoch
+	Time uint64 `json:"time"`
+}
+
+// BlockContextStore can optionally be implemented by the KVStore to learn the block of a contract call
+type BlockContextStore interface {
+	SetBlockContext(block BlockContext)
+}
+
+// BlockContextQuerier can optionally be implemented by the Querier to learn the block a query is pinned to
+type BlockContextQuerier interface {
+	SetBlockContext(block BlockContext)
+}
+
 //
 //	// Iterator over a domain of keys in ascending order. End is exclusive.
 //	// Start must be less than end, or the Iterator is invalid.
@@ -409,3 +436,6 @@ type GoAPI struct {
 //	*result = allocateRust(bz)
 //	return C.GoResult_Ok
 //}
+
+// releaseResolvedStates is a no-op, as contracts cannot be resolved without the library
+func releaseResolvedStates(counter uint64) {}
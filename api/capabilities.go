@@ -0,0 +1,25 @@
+package api
+
+// Capability is a group of host imports whose use can be denied to the contracts of a code, see
+// ConsensusParams.CodeCapabilities. Storage reads are always allowed.
+type Capability string
+
+const (
+	// CapabilityStorageWrite covers writing and removing storage entries
+	CapabilityStorageWrite Capability = "storage_write"
+	// CapabilityIterators covers opening storage iterators
+	CapabilityIterators Capability = "iterators"
+	// CapabilityQueries covers querying the chain, other contracts included
+	CapabilityQueries Capability = "queries"
+	// CapabilityAddressAPI covers canonicalizing and humanizing addresses
+	CapabilityAddressAPI Capability = "address_api"
+)
+
+// CodeCapabilities restricts the host capabilities of the contracts of a code. At most one of Allow and Deny
+// may be set, and leaving both nil lifts the restriction. An empty, non-nil Allow denies every capability.
+type CodeCapabilities struct {
+	// Allow lists the only capabilities the contracts may use
+	Allow []Capability `json:"allow"`
+	// Deny lists the capabilities the contracts may not use
+	Deny []Capability `json:"deny"`
+}
@@ -0,0 +1,19 @@
+package api
+
+import (
+	"encoding/json"
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestCodeCapabilitiesJSON(t *testing.T) {
+	bz, err := json.Marshal(CodeCapabilities{Deny: []Capability{CapabilityIterators, CapabilityQueries}})
+	require.NoError(t, err)
+	require.Equal(t, `{"allow":null,"deny":["iterators","queries"]}`, string(bz))
+
+	// allowing nothing must not read as no restriction
+	bz, err = json.Marshal(CodeCapabilities{Allow: []Capability{}})
+	require.NoError(t, err)
+	require.Equal(t, `{"allow":[],"deny":null}`, string(bz))
+}
@@ -0,0 +1,21 @@
+package api
+
+import "encoding/json"
+
+// CodeArchiveMagic starts every archive of ImportCodes and ExportCodes. Each code follows as its 32 byte checksum,
+// the length of its wasm as big endian uint32, and the wasm.
+const CodeArchiveMagic = "cwcodes\x01"
+
+// ImportSummary is the result of ImportCodes
+type ImportSummary struct {
+	// Imported are the hex checksums of the codes stored by the import
+	Imported []string `json:"imported"`
+	// Existing are the hex checksums of the codes which were stored already
+	Existing []string `json:"existing"`
+}
+
+func parseImportSummary(data []byte) (ImportSummary, error) {
+	var summary ImportSummary
+	err := json.Unmarshal(data, &summary)
+	return summary, err
+}
@@ -0,0 +1,35 @@
+package api
+
+import "encoding/json"
+
+// CodeStats are the execution statistics of one entry point of one code, see GetCodeStats
+type CodeStats struct {
+	// CodeID is the code id in hex
+	CodeID string `json:"code_id"`
+	// EntryPoint is one of "init", "handle", "migrate" and "query"
+	EntryPoint string `json:"entry_point"`
+	// Calls is the number of executions, including the internal calls of other contracts
+	Calls uint64 `json:"calls"`
+	// Failures is the number of executions which failed in the VM, e.g. because they ran out of gas.
+	// Errors returned by the contract itself are not counted.
+	Failures    uint64  `json:"failures"`
+	FailureRate float64 `json:"failure_rate"`
+	// AverageGas is the average of the wasm gas plus the gas reported by the storage and querier callbacks
+	AverageGas        uint64 `json:"average_gas"`
+	AverageDurationUs uint64 `json:"average_duration_us"`
+	TotalDurationUs   uint64 `json:"total_duration_us"`
+	// MaxOpenIterators is the most storage iterators an execution had open at once, see
+	// ConsensusParams.MaxOpenIterators
+	MaxOpenIterators uint32 `json:"max_open_iterators"`
+	// MaxMemoryPages is the most memory an execution's instance had, in 64 KiB pages, see
+	// ConsensusParams.MaxMemoryPages
+	MaxMemoryPages uint32 `json:"max_memory_pages"`
+	// HostCalls counts how often all executions together invoked each host import
+	HostCalls HostCallCounts `json:"host_calls"`
+}
+
+func parseCodeStats(data []byte) ([]CodeStats, error) {
+	var stats []CodeStats
+	err := json.Unmarshal(data, &stats)
+	return stats, err
+}
@@ -0,0 +1,36 @@
+package api
+
+import "encoding/json"
+
+// CrashReport holds the diagnostics of an enclave call which failed in the SGX SDK, or of a contract execution
+// which failed in the VM for another reason than running out of gas, see GetLastCrashReport
+type CrashReport struct {
+	// TimeMs is when the failure happened, in milliseconds since the unix epoch
+	TimeMs uint64 `json:"time_ms"`
+	// Call is the failed call, e.g. "handle" or "get_encrypted_seed"
+	Call string `json:"call"`
+	// CodeID is the code id in hex, or empty if no contract failed
+	CodeID   string `json:"code_id"`
+	GasLimit uint64 `json:"gas_limit"`
+	// GasUsed is the gas used in wasm until the failure
+	GasUsed uint64 `json:"gas_used"`
+	// GasReport is the gas reported by the callbacks until the failure
+	GasReport GasReport `json:"gas_report"`
+	Error     string    `json:"error"`
+	// EnclaveStatus is "ok", or why the health check of the enclave failed after the failure
+	EnclaveStatus string `json:"enclave_status"`
+	// Log holds the last log lines of the library before the failure, oldest first
+	Log []string `json:"log"`
+}
+
+// parseCrashReport returns nil if there is no report
+func parseCrashReport(data []byte) (*CrashReport, error) {
+	if len(data) == 0 {
+		return nil, nil
+	}
+	var report CrashReport
+	if err := json.Unmarshal(data, &report); err != nil {
+		return nil, err
+	}
+	return &report, nil
+}
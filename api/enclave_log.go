@@ -0,0 +1,26 @@
+package api
+
+import "encoding/json"
+
+// EnclaveLogLine is a log line of the enclave, see DrainEnclaveLog
+type EnclaveLogLine struct {
+	// TimeMs is when the line was received, in milliseconds since the unix epoch
+	TimeMs uint64 `json:"time_ms"`
+	// Level is the level in lower case, e.g. "info"
+	Level   string `json:"level"`
+	Message string `json:"message"`
+}
+
+// EnclaveLogDrain holds the enclave's log lines buffered since the last DrainEnclaveLog
+type EnclaveLogDrain struct {
+	// Lines are the buffered lines, oldest first
+	Lines []EnclaveLogLine `json:"lines"`
+	// Dropped is the number of lines lost because the buffer was full
+	Dropped uint64 `json:"dropped"`
+}
+
+func parseEnclaveLogDrain(data []byte) (EnclaveLogDrain, error) {
+	var drain EnclaveLogDrain
+	err := json.Unmarshal(data, &drain)
+	return drain, err
+}
@@ -0,0 +1,19 @@
+package api
+
+import "encoding/json"
+
+// EnclavePairing compares the MRENCLAVE of the loaded enclave with the one the library was built for, see
+// GetEnclavePairing. InitCache and LoadSeedToEnclave fail if they do not match.
+type EnclavePairing struct {
+	// Expected is the hex MRENCLAVE the library was built for, or empty if the build does not check it
+	Expected string `json:"expected"`
+	// Actual is the hex MRENCLAVE of the loaded enclave
+	Actual  string `json:"actual"`
+	Matches bool   `json:"matches"`
+}
+
+func parseEnclavePairing(data []byte) (EnclavePairing, error) {
+	var pairing EnclavePairing
+	err := json.Unmarshal(data, &pairing)
+	return pairing, err
+}
@@ -0,0 +1,21 @@
+package api
+
+import "encoding/json"
+
+// ExecutorStats are the calls of HandleAsync and QueryAsync running and queued, see GetExecutorStats
+type ExecutorStats struct {
+	// Running is the number of calls being run, at most 1
+	Running uint32 `json:"running"`
+	// Queued is the number of calls waiting for the worker
+	Queued uint32 `json:"queued"`
+	// Completed is the number of calls run since the library was loaded
+	Completed uint64 `json:"completed"`
+	// Rejected is the number of calls which failed because the queue was full
+	Rejected uint64 `json:"rejected"`
+}
+
+func parseExecutorStats(data []byte) (ExecutorStats, error) {
+	var stats ExecutorStats
+	err := json.Unmarshal(data, &stats)
+	return stats, err
+}
@@ -0,0 +1,42 @@
+package api
+
+import (
+	"fmt"
+	"strings"
+
+	"github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+)
+
+const unsupportedFeaturesPrefix = "Contract requires unsupported features: "
+
+// parseUnsupportedFeatures reads the feature lists from the message of an UnsupportedFeatures error of the Rust side
+func parseUnsupportedFeatures(msg string) error {
+	var missing, required, supported string
+	rest := strings.TrimPrefix(msg, unsupportedFeaturesPrefix)
+	if rest == msg || !strings.HasSuffix(rest, ")") {
+		return fmt.Errorf("%s", msg)
+	}
+	rest = strings.TrimSuffix(rest, ")")
+	parts := strings.SplitN(rest, " (required: ", 2)
+	if len(parts) != 2 {
+		return fmt.Errorf("%s", msg)
+	}
+	missing = parts[0]
+	parts = strings.SplitN(parts[1], "; supported: ", 2)
+	if len(parts) != 2 {
+		return fmt.Errorf("%s", msg)
+	}
+	required, supported = parts[0], parts[1]
+	return types.UnsupportedFeaturesError{
+		Missing:   splitFeatures(missing),
+		Required:  splitFeatures(required),
+		Supported: splitFeatures(supported),
+	}
+}
+
+func splitFeatures(list string) []string {
+	if list == "" {
+		return nil
+	}
+	return strings.Split(list, ", ")
+}
@@ -0,0 +1,30 @@
+package api
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/require"
+
+	"github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+)
+
+func TestParseUnsupportedFeatures(t *testing.T) {
+	msg := "Contract requires unsupported features: ibc, stargate (required: ibc, staking, stargate; supported: iterator, staking)"
+	err := parseUnsupportedFeatures(msg)
+	require.Equal(t, types.UnsupportedFeaturesError{
+		Missing:   []string{"ibc", "stargate"},
+		Required:  []string{"ibc", "staking", "stargate"},
+		Supported: []string{"iterator", "staking"},
+	}, err)
+	require.Equal(t, msg, err.Error())
+
+	err = parseUnsupportedFeatures("Contract requires unsupported features: stargate (required: stargate; supported: )")
+	require.Equal(t, types.UnsupportedFeaturesError{
+		Missing:  []string{"stargate"},
+		Required: []string{"stargate"},
+	}, err)
+
+	// other messages are kept as they are
+	err = parseUnsupportedFeatures("something else")
+	require.EqualError(t, err, "something else")
+}
@@ -0,0 +1,62 @@
+package api
+
+// GasReport breaks down the gas used by a contract call, on top of the total returned alongside it
+type GasReport struct {
+	// UsedExternally is the gas reported by the storage and querier callbacks
+	UsedExternally uint64 `json:"used_externally"`
+	// Refundable is the part of UsedExternally spent on deleting storage entries, which the chain may refund
+	Refundable uint64 `json:"refundable"`
+	// Reads is the part of UsedExternally spent on reading storage entries
+	Reads uint64 `json:"reads"`
+	// Writes is the part of UsedExternally spent on writing storage entries
+	Writes uint64 `json:"writes"`
+	// IteratorSteps is the part of UsedExternally spent on opening and advancing storage iterators
+	IteratorSteps uint64 `json:"iterator_steps"`
+	// Queries is the part of UsedExternally spent on queries to other modules and contracts
+	Queries uint64 `json:"queries"`
+	// APICalls is the gas reported by the address conversion callbacks. It is charged like wasm execution,
+	// so it is not part of UsedExternally.
+	APICalls uint64 `json:"api_calls"`
+	// Fallback is the gas charged from the fallback schedule on top of the gas reported by the callbacks.
+	// It is part of the total gas used.
+	Fallback uint64 `json:"fallback"`
+	// ScheduleVersion identifies the wasm cost table the execution was charged with, see CacheOptions.WasmCosts
+	// and RegisterGasSchedule. Nodes reporting different versions disagree on the gas of wasm execution.
+	ScheduleVersion uint64 `json:"schedule_version"`
+	// PeakMemoryPages is the most memory an instance of the call had, in 64 KiB pages, including the instances
+	// of its internal calls. Calls reaching ConsensusParams.MaxMemoryPages fail.
+	PeakMemoryPages uint32 `json:"peak_memory_pages"`
+	// HostCalls counts how often the call and its internal calls invoked each host import
+	HostCalls HostCallCounts `json:"host_calls"`
+}
+
+// HostCallCounts are the invocations of each host import of contracts, for calibrating gas schedules and
+// optimizing contracts. Contracts cannot remove ranges or seek, so DbRemoveRange and DbSeek stay 0.
+type HostCallCounts struct {
+	DbRead              uint64 `json:"db_read"`
+	DbWrite             uint64 `json:"db_write"`
+	DbRemove            uint64 `json:"db_remove"`
+	DbRemoveRange       uint64 `json:"db_remove_range"`
+	DbScan              uint64 `json:"db_scan"`
+	DbNext              uint64 `json:"db_next"`
+	DbSeek              uint64 `json:"db_seek"`
+	QueryChain          uint64 `json:"query_chain"`
+	CanonicalizeAddress uint64 `json:"canonicalize_address"`
+	HumanizeAddress     uint64 `json:"humanize_address"`
+}
+
+// GasCheckpoint is an opaque token carrying the gas a transaction consumed across the contract calls of its
+// submessages. Start it with a nil checkpoint in ExportGasCheckpoint after the first call of the transaction.
+type GasCheckpoint []byte
+
+// GasCheckpointInfo is what ImportGasCheckpoint reads from a GasCheckpoint
+type GasCheckpointInfo struct {
+	// GasLimit is the gas limit of the whole transaction, as passed with the first call
+	GasLimit uint64
+	// Consumed is the gas consumed by all calls in the checkpoint, including the gas reported by the callbacks
+	Consumed uint64
+	// Remaining is the gas limit for the next call of the transaction
+	Remaining uint64
+	// Calls is the number of calls in the checkpoint
+	Calls uint32
+}
@@ -36,10 +36,12 @@ func popFrame(counter uint64) frame {
 	return remove
 }
 
-// endContract is called at the end of a contract runtime to remove one item from the IteratorStack
+// endContract is called at the end of a contract runtime to remove one item from the IteratorStack, along with
+// the states of the contracts resolved in it
 func endContract(counter uint64) {
 	// we pull popFrame in another function so we don't hold the mutex while cleaning up the popped frame
 	remove := popFrame(counter)
+	releaseResolvedStates(counter)
 	// free all iterators in the frame when we release it
 	for _, iter := range remove {
 		iter.Close()
@@ -66,3 +68,32 @@ func retrieveIterator(dbCounter uint64, index uint64) dbm.Iterator {
 	defer iteratorStackMutex.Unlock()
 	return iteratorStack[dbCounter][index-1]
 }
+
+// limitIterator becomes invalid after yielding `remaining` items
+type limitIterator struct {
+	dbm.Iterator
+	remaining uint32
+}
+
+func (it *limitIterator) Valid() bool {
+	return it.remaining > 0 && it.Iterator.Valid()
+}
+
+func (it *limitIterator) Next() {
+	it.remaining--
+	it.Iterator.Next()
+}
+
+// prefixEnd returns the smallest key greater than all keys starting with prefix, or nil if there is none
+func prefixEnd(prefix []byte) []byte {
+	end := append([]byte{}, prefix...)
+	for len(end) > 0 {
+		last := len(end) - 1
+		if end[last] < 0xff {
+			end[last]++
+			return end
+		}
+		end = end[:last]
+	}
+	return nil
+}
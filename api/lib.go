@@ -7,8 +7,13 @@ package api
 import "C"
 
 import (
+	"bytes"
+	"encoding/json"
 	"fmt"
+	"sync"
 	"syscall"
+	"time"
+	"unsafe"
 
 	"github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
 )
@@ -27,6 +32,42 @@ type Cache struct {
 	ptr *C.cache_t
 }
 
+func init() {
+	if err := CheckAbi(); err != nil {
+		panic(err)
+	}
+	C.init_logger()
+}
+
+// CheckAbi fails if the library was built from other sources than the bindings.h we were compiled with.
+// Calling into such a library may corrupt memory, so we refuse to start instead.
+func CheckAbi() error {
+	expected := uint64(C.ABI_FINGERPRINT)
+	actual := uint64(C.abi_fingerprint())
+	if actual != expected {
+		return fmt.Errorf("libgo_cosmwasm ABI fingerprint %#016x does not match bindings.h (%#016x), rebuild the library and the Go code together", actual, expected)
+	}
+	return nil
+}
+
+// ErrorTemplatesVersion is the version of the fixed error messages of the library, which must be the same on all validators
+func ErrorTemplatesVersion() uint32 {
+	return uint32(C.error_templates_version())
+}
+
+// EnvSchemaVersions returns the versions of types.Env the library accepts, oldest first. Envs name their version
+// in SchemaVersion, and should use the newest one both the wrapper and the library know.
+func EnvSchemaVersions() ([]uint32, error) {
+	errmsg := C.Buffer{}
+	res, err := C.env_schema_versions(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	var versions []uint32
+	err = json.Unmarshal(receiveVector(res), &versions)
+	return versions, err
+}
+
 func HealthCheck() ([]byte, error) {
 	errmsg := C.Buffer{}
 
@@ -47,6 +88,44 @@ func InitBootstrap() ([]byte, error) {
 	return receiveVector(res), nil
 }
 
+// CreateSeedShare contributes this node to a bootstrap ceremony. The returned share must be handed to the other
+// participants, which combine it with theirs in CombineSeedShares.
+func CreateSeedShare(ceremony BootstrapCeremony) ([]byte, error) {
+	ceremonyBin, err := ceremony.marshal()
+	if err != nil {
+		return nil, err
+	}
+	ceremonySlice := sendSlice(ceremonyBin)
+	defer freeAfterSend(ceremonySlice)
+	errmsg := C.Buffer{}
+
+	res, err := C.create_seed_share(ceremonySlice, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// CombineSeedShares derives the consensus seed from at least ceremony.Threshold shares of the participants,
+// in place of InitBootstrap. It returns the bootstrap public key, which is the same on all participants.
+func CombineSeedShares(ceremony BootstrapCeremony, shares [][]byte) ([]byte, error) {
+	ceremonyBin, err := ceremony.marshal()
+	if err != nil {
+		return nil, err
+	}
+	ceremonySlice := sendSlice(ceremonyBin)
+	defer freeAfterSend(ceremonySlice)
+	sharesSlice := sendSlice(frameShares(shares))
+	defer freeAfterSend(sharesSlice)
+	errmsg := C.Buffer{}
+
+	res, err := C.combine_seed_shares(ceremonySlice, sharesSlice, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
 func LoadSeedToEnclave(masterCert []byte, seed []byte) (bool, error) {
 	pkSlice := sendSlice(masterCert)
 	defer freeAfterSend(pkSlice)
@@ -54,33 +133,511 @@ func LoadSeedToEnclave(masterCert []byte, seed []byte) (bool, error) {
 	defer freeAfterSend(seedSlice)
 	errmsg := C.Buffer{}
 
-	_, err := C.init_node(pkSlice, seedSlice, &errmsg)
+	res, err := C.init_node(pkSlice, seedSlice, &errmsg)
+	if res != C.InitNodeResult_Success {
+		return false, types.InitNodeError{Code: types.InitNodeCode(res), Msg: string(receiveVector(errmsg))}
+	}
 	if err != nil {
 		return false, errorWithMessage(err, errmsg)
 	}
 	return true, nil
 }
 
+// SetBlockEntropy hands the randomness seed of the block at height to the enclave.
+// It must be called before the first contract call of that block.
+func SetBlockEntropy(height uint64, entropy []byte) error {
+	e := sendSlice(entropy)
+	defer freeAfterSend(e)
+	errmsg := C.Buffer{}
+
+	_, err := C.set_block_entropy(u64(height), e, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// SetUnlockKey sets the secret the enclave mixes into the sealing of the node's keys and seed, so a copy of the
+// data dir cannot be used without it. It must be called with the same key, of at least 16 bytes, each time the
+// node starts, before InitNode and the first contract call.
+func SetUnlockKey(key []byte) error {
+	k := sendSlice(key)
+	defer freeAfterSend(k)
+	errmsg := C.Buffer{}
+
+	_, err := C.set_unlock_key(k, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// TrustedTime is a reading of the enclave's trusted clock
+type TrustedTime struct {
+	// Seconds elapsed since a reference point identified by Source
+	Seconds uint64
+	// Source identifies the reference point. Readings with different sources cannot be compared.
+	Source []byte
+}
+
+// GetTrustedTime reads the enclave's monotonic clock, which is independent of the host clock.
+// Use it e.g. to check the freshness of attestation reports or the lifetime of certificates.
+// It fails on platforms without trusted time support.
+func GetTrustedTime() (TrustedTime, error) {
+	source := C.Buffer{}
+	errmsg := C.Buffer{}
+
+	seconds, err := C.get_trusted_time(&source, &errmsg)
+	if err != nil {
+		return TrustedTime{}, errorWithMessage(err, errmsg)
+	}
+	return TrustedTime{Seconds: uint64(seconds), Source: receiveVector(source)}, nil
+}
+
+// VerifyPermit checks that permit is validly signed and allows querying the contract at contractAddr.
+// Wallets sign permits with a zero fee in the fee denom of the chain, feeDenom, e.g. "uscrt".
+// It returns the canonical address of the signer.
+func VerifyPermit(permit []byte, contractAddr string, feeDenom string) ([]byte, error) {
+	p := sendSlice(permit)
+	defer freeAfterSend(p)
+	addr := sendSlice([]byte(contractAddr))
+	defer freeAfterSend(addr)
+	denom := sendSlice([]byte(feeDenom))
+	defer freeAfterSend(denom)
+	errmsg := C.Buffer{}
+
+	signer, err := C.verify_permit(p, addr, denom, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(signer), nil
+}
+
+// VerifyContractKey checks that contractKey was issued by the enclave for the contract at contractAddr
+// running the code with codeHash. Use it to detect tampered contract key entries.
+func VerifyContractKey(contractAddr string, codeHash []byte, contractKey []byte) (bool, error) {
+	addr := sendSlice([]byte(contractAddr))
+	defer freeAfterSend(addr)
+	hash := sendSlice(codeHash)
+	defer freeAfterSend(hash)
+	key := sendSlice(contractKey)
+	defer freeAfterSend(key)
+	errmsg := C.Buffer{}
+
+	valid, err := C.verify_contract_key(addr, hash, key, &errmsg)
+	if err != nil {
+		return false, errorWithMessage(err, errmsg)
+	}
+	return bool(valid), nil
+}
+
+// ExportGasCheckpoint adds a finished contract call, with the gasUsed and report it returned, to the checkpoint
+// of its transaction. A nil previous starts the checkpoint of a transaction with gasLimit, which is ignored
+// otherwise. It fails with types.OutOfGasError once the calls consumed more than the limit of the transaction.
+func ExportGasCheckpoint(previous GasCheckpoint, gasLimit uint64, gasUsed uint64, report GasReport) (GasCheckpoint, error) {
+	prev := sendSlice(previous)
+	defer freeAfterSend(prev)
+	errmsg := C.Buffer{}
+
+	res, err := C.export_gas_checkpoint(prev, u64(gasLimit), u64(gasUsed), sendGasReport(report), &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// ImportGasCheckpoint reads the gas a transaction consumed so far, and the gas limit for its next call, from checkpoint
+func ImportGasCheckpoint(checkpoint GasCheckpoint) (GasCheckpointInfo, error) {
+	cp := sendSlice(checkpoint)
+	defer freeAfterSend(cp)
+	errmsg := C.Buffer{}
+
+	info, err := C.import_gas_checkpoint(cp, &errmsg)
+	if err != nil {
+		return GasCheckpointInfo{}, errorWithMessage(err, errmsg)
+	}
+	return GasCheckpointInfo{
+		GasLimit:  uint64(info.gas_limit),
+		Consumed:  uint64(info.consumed),
+		Remaining: uint64(info.remaining),
+		Calls:     uint32(info.calls),
+	}, nil
+}
+
+// ValidateChecksum checks that wasm has the checksum expected, which is its code id, e.g. when importing codes from
+// genesis. It returns false without an error if the checksum does not match.
+func ValidateChecksum(wasm []byte, expected []byte) (bool, error) {
+	w := sendSlice(wasm)
+	defer freeAfterSend(w)
+	e := sendSlice(expected)
+	defer freeAfterSend(e)
+	errmsg := C.Buffer{}
+
+	valid, err := C.validate_checksum(w, e, &errmsg)
+	if err != nil {
+		return false, errorWithMessage(err, errmsg)
+	}
+	return bool(valid), nil
+}
+
+// DeriveContractAddress returns the canonical address of the contract creator instantiates from the code checksum
+// with salt, like instantiate2 of wasmd. creator is a canonical address and salt 1 to 64 bytes. If msg is not
+// empty, the address also depends on it, so it can only be used with exactly this init message.
+func DeriveContractAddress(checksum []byte, creator []byte, salt []byte, msg []byte) ([]byte, error) {
+	c := sendSlice(checksum)
+	defer freeAfterSend(c)
+	cr := sendSlice(creator)
+	defer freeAfterSend(cr)
+	s := sendSlice(salt)
+	defer freeAfterSend(s)
+	m := sendSlice(msg)
+	defer freeAfterSend(m)
+	errmsg := C.Buffer{}
+
+	res, err := C.derive_contract_address(c, cr, s, m, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// EncryptMsg encrypts plaintext for the node with nodePubKey, the way clients send messages to secret contracts.
+// ephemeralSecret and nonce must be 32 random bytes each, and must not be reused.
+// It returns the envelope nonce || ephemeral public key || ciphertext.
+func EncryptMsg(nodePubKey []byte, ephemeralSecret []byte, nonce []byte, plaintext []byte) ([]byte, error) {
+	pk := sendSlice(nodePubKey)
+	defer freeAfterSend(pk)
+	secret := sendSlice(ephemeralSecret)
+	defer freeAfterSend(secret)
+	n := sendSlice(nonce)
+	defer freeAfterSend(n)
+	p := sendSlice(plaintext)
+	defer freeAfterSend(p)
+	errmsg := C.Buffer{}
+
+	res, err := C.encrypt_msg(pk, secret, n, p, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// DecryptResponse decrypts ciphertext, which a contract returned in response to the message sent in envelope.
+// ephemeralSecret must be the secret envelope was produced with.
+func DecryptResponse(nodePubKey []byte, ephemeralSecret []byte, envelope []byte, ciphertext []byte) ([]byte, error) {
+	pk := sendSlice(nodePubKey)
+	defer freeAfterSend(pk)
+	secret := sendSlice(ephemeralSecret)
+	defer freeAfterSend(secret)
+	e := sendSlice(envelope)
+	defer freeAfterSend(e)
+	c := sendSlice(ciphertext)
+	defer freeAfterSend(c)
+	errmsg := C.Buffer{}
+
+	res, err := C.decrypt_response(pk, secret, e, c, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// DecryptQueryResponse decrypts the answer of a query, so trusted gateways can serve decrypted responses to the
+// users they authenticated. ephemeralSecret and nonce must be the ones the query message was encrypted with, and
+// ciphertext is the result of the query. It returns the answer of the contract.
+func DecryptQueryResponse(nodePubKey []byte, ephemeralSecret []byte, nonce []byte, ciphertext []byte) ([]byte, error) {
+	pk := sendSlice(nodePubKey)
+	defer freeAfterSend(pk)
+	secret := sendSlice(ephemeralSecret)
+	defer freeAfterSend(secret)
+	n := sendSlice(nonce)
+	defer freeAfterSend(n)
+	c := sendSlice(ciphertext)
+	defer freeAfterSend(c)
+	errmsg := C.Buffer{}
+
+	res, err := C.decrypt_query_response(pk, secret, n, c, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
 type Querier = types.Querier
 
-func InitCache(dataDir string, supportedFeatures string, cacheSize uint64) (Cache, error) {
+func InitCache(dataDir string, supportedFeatures string, cacheSize uint64, options CacheOptions) (Cache, error) {
+	optionsBin, err := json.Marshal(options)
+	if err != nil {
+		return Cache{}, err
+	}
 	dir := sendSlice([]byte(dataDir))
 	defer freeAfterSend(dir)
 	features := sendSlice([]byte(supportedFeatures))
 	defer freeAfterSend(features)
+	opts := sendSlice(optionsBin)
+	defer freeAfterSend(opts)
 	errmsg := C.Buffer{}
 
-	ptr, err := C.init_cache(dir, features, usize(cacheSize), &errmsg)
+	ptr, err := C.init_cache(dir, features, opts, usize(cacheSize), &errmsg)
 	if err != nil {
 		return Cache{}, errorWithMessage(err, errmsg)
 	}
 	return Cache{ptr: ptr}, nil
 }
 
+// ReleaseCache frees the cache. It waits until the calls of HandleAsync and QueryAsync using the cache are done.
 func ReleaseCache(cache Cache) {
 	C.release_cache(cache.ptr)
 }
 
+// UpdateSupportedFeatures replaces the features contracts may require, in the format of InitCache, so a chain
+// upgrade can enable a new capability without re-creating the cache. Stored codes stay available, but their
+// compiled modules are dropped, so the next call of every code is slower.
+func UpdateSupportedFeatures(cache Cache, supportedFeatures string) error {
+	features := sendSlice([]byte(supportedFeatures))
+	defer freeAfterSend(features)
+	errmsg := C.Buffer{}
+
+	_, err := C.update_supported_features(cache.ptr, features, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// SetConsensusParams replaces the consensus params of the cache with the ones in the chain's state. The keeper must
+// call it once the cache is created and whenever the params change, before the next contract call. Until then
+// every call fails.
+func SetConsensusParams(cache Cache, params ConsensusParams) error {
+	bz, err := json.Marshal(params)
+	if err != nil {
+		return err
+	}
+	p := sendSlice(bz)
+	defer freeAfterSend(p)
+	errmsg := C.Buffer{}
+
+	_, err = C.set_consensus_params(cache.ptr, p, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// SetCodePaused pauses or resumes all contracts of the code with the checksum, e.g. for an emergency stop by
+// governance. Calls of paused codes fail with types.ContractPausedError, internal calls included. Pausing is not
+// persisted, so the keeper must pause the codes again when the node starts.
+func SetCodePaused(cache Cache, checksum []byte, paused bool) error {
+	cs := sendSlice(checksum)
+	defer freeAfterSend(cs)
+	errmsg := C.Buffer{}
+
+	_, err := C.set_code_paused(cache.ptr, cs, C.bool(paused), &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// ExportSnapshot produces the state-sync extension payload of this node.
+// The payload lists the checksums of all codes in the cache, see SnapshotCodes, and which of them are paused.
+// Compiled modules are not part of it; the restoring node compiles every code on its first use.
+func ExportSnapshot(cache Cache) ([]byte, error) {
+	errmsg := C.Buffer{}
+	res, err := C.export_snapshot(cache.ptr, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// GetShadowStats returns the shadow execution counters of the cache, see CacheOptions.ShadowExecution
+func GetShadowStats(cache Cache) (ShadowStats, error) {
+	errmsg := C.Buffer{}
+	res, err := C.get_shadow_stats(cache.ptr, &errmsg)
+	if err != nil {
+		return ShadowStats{}, errorWithMessage(err, errmsg)
+	}
+	return parseShadowStats(receiveVector(res))
+}
+
+// GetModuleStats returns the format the compiled modules of the cache are compiled in, how many modules of
+// another format were removed when the cache was created, and how many of them were compiled again since
+func GetModuleStats(cache Cache) (ModuleStats, error) {
+	errmsg := C.Buffer{}
+	res, err := C.get_module_stats(cache.ptr, &errmsg)
+	if err != nil {
+		return ModuleStats{}, errorWithMessage(err, errmsg)
+	}
+	return parseModuleStats(receiveVector(res))
+}
+
+// GetCodeStats returns the execution statistics of every code run by the cache, the codes which took the most
+// time first. With reset, the counters start over, so the next call returns the statistics since this one.
+func GetCodeStats(cache Cache, reset bool) ([]CodeStats, error) {
+	errmsg := C.Buffer{}
+	res, err := C.get_code_stats(cache.ptr, C.bool(reset), &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return parseCodeStats(receiveVector(res))
+}
+
+// RestoreSnapshot restores a payload produced by ExportSnapshot on another node, pausing the codes paused there.
+// All codes listed in the payload must have been stored with Create before.
+func RestoreSnapshot(cache Cache, payload []byte) error {
+	p := sendSlice(payload)
+	defer freeAfterSend(p)
+	errmsg := C.Buffer{}
+	_, err := C.restore_snapshot(cache.ptr, p, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// ExportContractState returns up to limit storage entries of the contract with contractKey, in ascending
+// key order, without executing the contract. Pass nil as startAfter for the first page and the Next of the
+// previous page afterwards.
+func ExportContractState(
+	contractKey []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	startAfter []byte,
+	limit uint32,
+	mode ExportMode,
+) (*StatePage, error) {
+	key := sendSlice(contractKey)
+	defer freeAfterSend(key)
+	start := sendSlice(startAfter)
+	defer freeAfterSend(start)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	errmsg := C.Buffer{}
+
+	res, err := C.export_contract_state(key, db, start, u32(limit), i32(mode), &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return parseStatePage(receiveVector(res))
+}
+
+// AuditStateKeys counts up to limit storage entries of the contract with contractKey per key version, in
+// ascending key order. keyHistory lists all codes the contract ran, oldest first, ending with its current code.
+// Pass nil as startAfter for the first page and the Next of the previous page afterwards.
+func AuditStateKeys(
+	contractKey []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	keyHistory [][]byte,
+	startAfter []byte,
+	limit uint32,
+) (*KeyAudit, error) {
+	key := sendSlice(contractKey)
+	defer freeAfterSend(key)
+	h := sendSlice(bytes.Join(keyHistory, nil))
+	defer freeAfterSend(h)
+	start := sendSlice(startAfter)
+	defer freeAfterSend(start)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	errmsg := C.Buffer{}
+
+	res, err := C.audit_state_keys(key, db, h, start, u32(limit), &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return parseKeyAudit(receiveVector(res))
+}
+
+// RunBenchmark times iterations calls of the given kind from inside the library, to calibrate callback gas costs.
+// The storage benchmarks write and remove a single key, so store should be a scratch store.
+func RunBenchmark(
+	kind BenchmarkKind,
+	iterations uint32,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+) (*BenchmarkReport, error) {
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	errmsg := C.Buffer{}
+
+	res, err := C.run_benchmark(i32(kind), u32(iterations), db, a, q, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return parseBenchmarkReport(receiveVector(res))
+}
+
+// SharedRegion is memory outside of the Go heap into which the library writes large results,
+// so they can be used in place instead of being copied. See RegisterSharedRegion.
+type SharedRegion struct {
+	ptr  unsafe.Pointer
+	size int
+}
+
+// NewSharedRegion allocates a region of size bytes. It must be freed with Free once it is unregistered.
+func NewSharedRegion(size int) *SharedRegion {
+	return &SharedRegion{ptr: C.malloc(C.size_t(size)), size: size}
+}
+
+// Free releases the memory of the region
+func (r *SharedRegion) Free() {
+	C.free(r.ptr)
+	r.ptr = nil
+}
+
+// RegisterSharedRegion lets the cache write large results of queries and GetCode into region.
+// Pass nil to unregister the current region. A region must stay allocated while it is registered,
+// and must not be unregistered while a SharedResult in it has not been released.
+func RegisterSharedRegion(cache Cache, region *SharedRegion) error {
+	buf := C.Buffer{}
+	if region != nil {
+		buf = C.Buffer{ptr: u8_ptr(region.ptr), len: usize(region.size), cap: usize(region.size)}
+	}
+	errmsg := C.Buffer{}
+	_, err := C.register_shared_region(cache.ptr, buf, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// SharedResult is a result which may point into the cache's shared region
+type SharedResult struct {
+	// Data is only valid until Release is called
+	Data  []byte
+	cache Cache
+	buf   C.Buffer
+}
+
+// Release returns the memory of the result to the shared region
+func (r *SharedResult) Release() {
+	if r.cache.ptr != nil {
+		C.release_shared(r.cache.ptr, r.buf)
+		r.cache = Cache{}
+	}
+	r.Data = nil
+}
+
 func Create(cache Cache, wasm []byte) ([]byte, error) {
 	code := sendSlice(wasm)
 	defer freeAfterSend(code)
@@ -92,34 +649,512 @@ func Create(cache Cache, wasm []byte) ([]byte, error) {
 	return receiveVector(id), nil
 }
 
-func GetCode(cache Cache, code_id []byte) ([]byte, error) {
-	id := sendSlice(code_id)
-	defer freeAfterSend(id)
+// CreatePlaintext stores wasm like Create, as a plaintext code. The enclave neither encrypts the state of its
+// contracts nor their messages and results, and their messages must be plaintext JSON. A code stored with
+// Create cannot become plaintext, nor the other way around.
+func CreatePlaintext(cache Cache, wasm []byte) ([]byte, error) {
+	code := sendSlice(wasm)
+	defer freeAfterSend(code)
+	errmsg := C.Buffer{}
+	id, err := C.create_plaintext(cache.ptr, code, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(id), nil
+}
+
+// ExportCodes produces an archive of the codes with the given checksums in the format of ImportCodes, e.g. for
+// genesis export, instead of calling GetCode for every code. Without checksums, it contains all stored codes.
+func ExportCodes(cache Cache, checksums [][]byte) ([]byte, error) {
+	f := sendSlice(bytes.Join(checksums, nil))
+	defer freeAfterSend(f)
+	errmsg := C.Buffer{}
+	res, err := C.export_codes(cache.ptr, f, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveResult(cache, res), nil
+}
+
+// ImportCodes stores all codes of an archive in one call, which is much faster than calling Create for every
+// code, e.g. in InitGenesis. See CodeArchiveMagic for the format. Nothing is stored if any checksum of the
+// archive does not match its wasm.
+func ImportCodes(cache Cache, archive []byte) (ImportSummary, error) {
+	a := sendSlice(archive)
+	defer freeAfterSend(a)
+	errmsg := C.Buffer{}
+	res, err := C.import_codes(cache.ptr, a, &errmsg)
+	if err != nil {
+		return ImportSummary{}, errorWithMessage(err, errmsg)
+	}
+	return parseImportSummary(receiveVector(res))
+}
+
+func GetCode(cache Cache, code_id []byte) ([]byte, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	errmsg := C.Buffer{}
+	code, err := C.get_code(cache.ptr, id, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveResult(cache, code), nil
+}
+
+func Instantiate(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+	m := sendSlice(msg)
+	defer freeAfterSend(m)
+	cid := sendSlice([]byte(opts.CallID))
+	defer freeAfterSend(cid)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	var report C.GasReport
+	errmsg := C.Buffer{}
+
+	res, err := C.instantiate(cache.ptr, id, p, m, db, a, q, u64(gasLimit), u32(opts.CallDepth), &gasUsed, &report, cid, &errmsg)
+	if opts.GasReport != nil {
+		*opts.GasReport = receiveGasReport(report)
+	}
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), uint64(gasUsed), nil
+}
+
+// Instantiate2 is like Instantiate, but the contract address in params must be the one
+// DeriveContractAddress returns for the code, the sender, salt and, if fixMsg, msg
+func Instantiate2(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	salt []byte,
+	fixMsg bool,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+	m := sendSlice(msg)
+	defer freeAfterSend(m)
+	s := sendSlice(salt)
+	defer freeAfterSend(s)
+	cid := sendSlice([]byte(opts.CallID))
+	defer freeAfterSend(cid)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	var report C.GasReport
+	errmsg := C.Buffer{}
+
+	res, err := C.instantiate2(cache.ptr, id, p, m, db, a, q, u64(gasLimit), u32(opts.CallDepth), &gasUsed, &report, s, C.bool(fixMsg), cid, &errmsg)
+	if opts.GasReport != nil {
+		*opts.GasReport = receiveGasReport(report)
+	}
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), uint64(gasUsed), nil
+}
+
+func Handle(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+	m := sendSlice(msg)
+	defer freeAfterSend(m)
+	cid := sendSlice([]byte(opts.CallID))
+	defer freeAfterSend(cid)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	var report C.GasReport
+	errmsg := C.Buffer{}
+
+	res, err := C.handle(cache.ptr, id, p, m, db, a, q, u64(gasLimit), u32(opts.CallDepth), &gasUsed, &report, cid, &errmsg)
+	if opts.GasReport != nil {
+		*opts.GasReport = receiveGasReport(report)
+	}
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), uint64(gasUsed), nil
+}
+
+// HandleSystem executes a chain-owned contract for the chain itself, e.g. from BeginBlock or EndBlock. Unlike
+// Handle it takes no gas limit: the call runs at depth 0 with the budget ConsensusParams.SystemGasLimit, and fails
+// with types.OutOfGasError if it exhausts it. The gas used should not be charged to any user, so store should not
+// consume gas either.
+func HandleSystem(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+	m := sendSlice(msg)
+	defer freeAfterSend(m)
+	cid := sendSlice([]byte(opts.CallID))
+	defer freeAfterSend(cid)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	var report C.GasReport
+	errmsg := C.Buffer{}
+
+	res, err := C.handle_system(cache.ptr, id, p, m, db, a, q, &gasUsed, &report, cid, &errmsg)
+	if opts.GasReport != nil {
+		*opts.GasReport = receiveGasReport(report)
+	}
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), uint64(gasUsed), nil
+}
+
+// BeginBlock runs the begin_block export of a contract registered for it. block is the JSON types.BlockInfo of the
+// block which begins, and replaces the block of params; the contract is the sender and sends no funds. Like
+// HandleSystem, the call is bounded by ConsensusParams.SystemGasLimit.
+func BeginBlock(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	block []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	return blockHook(cache, true, code_id, params, block, gasMeter, store, api, querier, opts)
+}
+
+// EndBlock runs the end_block export of a contract registered for it, like BeginBlock.
+func EndBlock(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	block []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	return blockHook(cache, false, code_id, params, block, gasMeter, store, api, querier, opts)
+}
+
+func blockHook(
+	cache Cache,
+	begin bool,
+	code_id []byte,
+	params []byte,
+	block []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+	b := sendSlice(block)
+	defer freeAfterSend(b)
+	cid := sendSlice([]byte(opts.CallID))
+	defer freeAfterSend(cid)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	var report C.GasReport
+	errmsg := C.Buffer{}
+
+	var res C.Buffer
+	var err error
+	if begin {
+		res, err = C.begin_block(cache.ptr, id, p, b, db, a, q, &gasUsed, &report, cid, &errmsg)
+	} else {
+		res, err = C.end_block(cache.ptr, id, p, b, db, a, q, &gasUsed, &report, cid, &errmsg)
+	}
+	if opts.GasReport != nil {
+		*opts.GasReport = receiveGasReport(report)
+	}
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), uint64(gasUsed), nil
+}
+
+// Migrate runs the migration of a contract to the code code_id. keyHistory lists the codes the contract ran
+// before, oldest first, and ends with the code it is migrated from. The enclave reads the contract's state
+// encrypted with the keys of all of them. It may be empty for contracts without encrypted state.
+// The enclave fails the migration unless auth allows the sender in params to run it, see MigrateAuth.
+func Migrate(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	keyHistory [][]byte,
+	auth MigrateAuth,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	authBin, err := json.Marshal(auth)
+	if err != nil {
+		return nil, 0, err
+	}
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+	m := sendSlice(msg)
+	defer freeAfterSend(m)
+	h := sendSlice(bytes.Join(keyHistory, nil))
+	defer freeAfterSend(h)
+	au := sendSlice(authBin)
+	defer freeAfterSend(au)
+	cid := sendSlice([]byte(opts.CallID))
+	defer freeAfterSend(cid)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	var report C.GasReport
+	errmsg := C.Buffer{}
+
+	res, err := C.migrate(cache.ptr, id, p, m, db, a, q, u64(gasLimit), u32(opts.CallDepth), &gasUsed, &report, h, au, cid, &errmsg)
+	if opts.GasReport != nil {
+		*opts.GasReport = receiveGasReport(report)
+	}
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), uint64(gasUsed), nil
+}
+
+func Query(
+	cache Cache,
+	code_id []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	res, gasUsed, err := query(cache, code_id, msg, gasMeter, store, api, querier, gasLimit, false, opts)
+	if err != nil {
+		return nil, gasUsed, err
+	}
+	return receiveResult(cache, res), gasUsed, nil
+}
+
+// QueryUnmetered is like Query, but runs without a gas limit and reports no gas, for read-only tooling like
+// analytics on archive nodes. It fails with types.CallDeniedError unless the cache was created with
+// CacheOptions.AllowUnmeteredQueries. The store and querier may still charge the gas meter. opts.GasReport is
+// not filled in.
+func QueryUnmetered(
+	cache Cache,
+	code_id []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	opts CallOptions,
+) ([]byte, error) {
+	opts.GasReport = nil
+	res, _, err := query(cache, code_id, msg, gasMeter, store, api, querier, 0, true, opts)
+	if err != nil {
+		return nil, err
+	}
+	return receiveResult(cache, res), nil
+}
+
+// QueryShared is like Query, but a large result is returned in place in the cache's shared region.
+// The caller must call Release on the result once it is done with the data.
+func QueryShared(
+	cache Cache,
+	code_id []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) (SharedResult, uint64, error) {
+	res, gasUsed, err := query(cache, code_id, msg, gasMeter, store, api, querier, gasLimit, false, opts)
+	if err != nil {
+		return SharedResult{}, gasUsed, err
+	}
+	if !isShared(res) {
+		return SharedResult{Data: receiveVector(res)}, gasUsed, nil
+	}
+	data := (*[1 << 30]byte)(unsafe.Pointer(res.ptr))[:res.len:res.len]
+	return SharedResult{Data: data, cache: cache, buf: res}, gasUsed, nil
+}
+
+func query(
+	cache Cache,
+	code_id []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	unmetered bool,
+	opts CallOptions,
+) (C.Buffer, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	m := sendSlice(msg)
+	defer freeAfterSend(m)
+	cid := sendSlice([]byte(opts.CallID))
+	defer freeAfterSend(cid)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	var report C.GasReport
 	errmsg := C.Buffer{}
-	code, err := C.get_code(cache.ptr, id, &errmsg)
-	if err != nil {
-		return nil, errorWithMessage(err, errmsg)
+	b := C.BlockContext{height: u64(opts.Block.Height), time: u64(opts.Block.Time)}
+
+	res, err := C.query(cache.ptr, id, m, db, a, q, u64(gasLimit), u32(opts.CallDepth), b, C.bool(unmetered), &gasUsed, &report, cid, &errmsg)
+	if opts.GasReport != nil {
+		*opts.GasReport = receiveGasReport(report)
 	}
-	return receiveVector(code), nil
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
+		return C.Buffer{}, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return res, uint64(gasUsed), nil
 }
 
-func Instantiate(
+// QueryCached is like Query for the contract at contractAddr, but reuses the result of the same query at the
+// same height, if the cache keeps results, see CacheOptions.QueryCacheSize. A reused result reports the gas its
+// query used and an empty opts.GasReport, and the store is not called. Queries nested in contract calls must use
+// Query, as their gas is part of consensus.
+func QueryCached(
 	cache Cache,
+	contractAddr string,
 	code_id []byte,
-	params []byte,
 	msg []byte,
 	gasMeter *GasMeter,
 	store KVStore,
 	api *GoAPI,
 	querier *Querier,
 	gasLimit uint64,
+	opts CallOptions,
 ) ([]byte, uint64, error) {
+	addr := sendSlice([]byte(contractAddr))
+	defer freeAfterSend(addr)
 	id := sendSlice(code_id)
 	defer freeAfterSend(id)
-	p := sendSlice(params)
-	defer freeAfterSend(p)
 	m := sendSlice(msg)
 	defer freeAfterSend(m)
+	cid := sendSlice([]byte(opts.CallID))
+	defer freeAfterSend(cid)
 
 	// set up a new stack frame to handle iterators
 	counter := startContract()
@@ -130,33 +1165,42 @@ func Instantiate(
 	a := buildAPI(api)
 	q := buildQuerier(querier)
 	var gasUsed u64
+	var report C.GasReport
 	errmsg := C.Buffer{}
+	b := C.BlockContext{height: u64(opts.Block.Height), time: u64(opts.Block.Time)}
 
-	res, err := C.instantiate(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg)
+	res, err := C.query_cached(cache.ptr, addr, id, m, db, a, q, u64(gasLimit), b, &gasUsed, &report, cid, &errmsg)
+	if opts.GasReport != nil {
+		*opts.GasReport = receiveGasReport(report)
+	}
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
 		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
 	}
-	return receiveVector(res), uint64(gasUsed), nil
+	return receiveResult(cache, res), uint64(gasUsed), nil
 }
 
-func Handle(
+// QueryBatch runs several queries against the state of store, so their results are consistent with each other
+// even if the chain moves on in between. store must implement ContractResolver, and should be a snapshot of a
+// single height, which opts.Block describes as for Query. A query failing does not fail the batch but sets the
+// Error of its result. The queries share gasLimit, and the gas used and opts.GasReport cover all of them.
+func QueryBatch(
 	cache Cache,
-	code_id []byte,
-	params []byte,
-	msg []byte,
+	queries []BatchQuery,
 	gasMeter *GasMeter,
 	store KVStore,
 	api *GoAPI,
 	querier *Querier,
 	gasLimit uint64,
-) ([]byte, uint64, error) {
-	id := sendSlice(code_id)
-	defer freeAfterSend(id)
-	p := sendSlice(params)
-	defer freeAfterSend(p)
-	m := sendSlice(msg)
-	defer freeAfterSend(m)
+	opts CallOptions,
+) ([]BatchQueryResult, uint64, error) {
+	bz, err := json.Marshal(queries)
+	if err != nil {
+		return nil, 0, err
+	}
+	qs := sendSlice(bz)
+	defer freeAfterSend(qs)
+	cid := sendSlice([]byte(opts.CallID))
+	defer freeAfterSend(cid)
 
 	// set up a new stack frame to handle iterators
 	counter := startContract()
@@ -167,33 +1211,82 @@ func Handle(
 	a := buildAPI(api)
 	q := buildQuerier(querier)
 	var gasUsed u64
+	var report C.GasReport
 	errmsg := C.Buffer{}
+	b := C.BlockContext{height: u64(opts.Block.Height), time: u64(opts.Block.Time)}
 
-	res, err := C.handle(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg)
+	res, err := C.query_batch(cache.ptr, qs, db, a, q, u64(gasLimit), b, &gasUsed, &report, cid, &errmsg)
+	if opts.GasReport != nil {
+		*opts.GasReport = receiveGasReport(report)
+	}
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
 		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
 	}
-	return receiveVector(res), uint64(gasUsed), nil
+	results, err := parseBatchQueryResults(receiveResult(cache, res))
+	return results, uint64(gasUsed), err
 }
 
-func Migrate(
+// RegisterGasSchedule makes costs charge wasm execution from the block at activationHeight on. Schedules are
+// not persisted, so they must be registered again when the node starts, before blocks at or above their
+// heights run. Registering a schedule again does nothing.
+func RegisterGasSchedule(cache Cache, activationHeight uint64, costs WasmCosts) error {
+	bz, err := json.Marshal(gasSchedule{ActivationHeight: activationHeight, Costs: costs})
+	if err != nil {
+		return err
+	}
+	s := sendSlice(bz)
+	defer freeAfterSend(s)
+	errmsg := C.Buffer{}
+
+	_, err = C.register_gas_schedule(cache.ptr, s, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// SetQueryRoutes replaces the routes of QueryByAlias. Routes are not persisted, so they must be set again when
+// the node starts.
+func SetQueryRoutes(cache Cache, routes map[string]QueryRoute) error {
+	bz, err := json.Marshal(routes)
+	if err != nil {
+		return err
+	}
+	r := sendSlice(bz)
+	defer freeAfterSend(r)
+	errmsg := C.Buffer{}
+
+	_, err = C.set_query_routes(cache.ptr, r, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+func QueryByAlias(
 	cache Cache,
-	code_id []byte,
-	params []byte,
-	msg []byte,
+	alias string,
+	args map[string]interface{},
 	gasMeter *GasMeter,
 	store KVStore,
 	api *GoAPI,
 	querier *Querier,
 	gasLimit uint64,
+	opts CallOptions,
 ) ([]byte, uint64, error) {
-	id := sendSlice(code_id)
-	defer freeAfterSend(id)
-	p := sendSlice(params)
-	defer freeAfterSend(p)
-	m := sendSlice(msg)
-	defer freeAfterSend(m)
+	if args == nil {
+		args = map[string]interface{}{}
+	}
+	bz, err := json.Marshal(args)
+	if err != nil {
+		return nil, 0, err
+	}
+	al := sendSlice([]byte(alias))
+	defer freeAfterSend(al)
+	ar := sendSlice(bz)
+	defer freeAfterSend(ar)
+	cid := sendSlice([]byte(opts.CallID))
+	defer freeAfterSend(cid)
 
 	// set up a new stack frame to handle iterators
 	counter := startContract()
@@ -204,60 +1297,248 @@ func Migrate(
 	a := buildAPI(api)
 	q := buildQuerier(querier)
 	var gasUsed u64
+	var report C.GasReport
 	errmsg := C.Buffer{}
+	b := C.BlockContext{height: u64(opts.Block.Height), time: u64(opts.Block.Time)}
 
-	res, err := C.migrate(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg)
+	res, err := C.query_by_alias(cache.ptr, al, ar, db, a, q, u64(gasLimit), b, &gasUsed, &report, cid, &errmsg)
+	if opts.GasReport != nil {
+		*opts.GasReport = receiveGasReport(report)
+	}
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
 		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
 	}
-	return receiveVector(res), uint64(gasUsed), nil
+	return receiveResult(cache, res), uint64(gasUsed), nil
 }
 
-func Query(
+// AsyncCall is a contract call started by HandleAsync or QueryAsync, which runs without blocking a goroutine.
+type AsyncCall struct {
+	handle  u64
+	done    chan struct{}
+	dbState *DBState
+	// the DB, api and querier the Rust side uses until the call is done. Their states are handles of goHandles,
+	// as the Rust side keeps them after HandleAsync or QueryAsync returned.
+	db      C.DB
+	api     C.GoApi
+	querier C.GoQuerier
+	handles []uintptr
+}
+
+var (
+	asyncCallsMutex sync.Mutex
+	// asyncCalls are the running calls by their handle, closed by cAsyncCallDone
+	asyncCalls = map[u64]*AsyncCall{}
+)
+
+// HandleAsync queues Handle on the worker thread of the library and returns at once, see SetExecutorOptions.
+// The states of store, api and querier must not be modified by others until the call is done. The worker runs the
+// calls one at a time in the order they were started, so the calls of a block must be started in its order, and
+// Handle must not be called while they run. opts.GasReport is not used, the gas report of the call is taken by
+// Result.
+func HandleAsync(
 	cache Cache,
 	code_id []byte,
+	params []byte,
 	msg []byte,
 	gasMeter *GasMeter,
 	store KVStore,
 	api *GoAPI,
 	querier *Querier,
 	gasLimit uint64,
-) ([]byte, uint64, error) {
+	opts CallOptions,
+) (*AsyncCall, error) {
 	id := sendSlice(code_id)
 	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
 	m := sendSlice(msg)
 	defer freeAfterSend(m)
+	cid := sendSlice([]byte(opts.CallID))
+	defer freeAfterSend(cid)
 
-	// set up a new stack frame to handle iterators
-	counter := startContract()
-	defer endContract(counter)
+	call := newAsyncCall(store, gasMeter, api, querier)
+	errmsg := C.Buffer{}
 
-	dbState := buildDBState(store, counter)
-	db := buildDB(&dbState, gasMeter)
-	a := buildAPI(api)
-	q := buildQuerier(querier)
+	// the call may be done before we know its handle, so cAsyncCallDone waits for it
+	asyncCallsMutex.Lock()
+	defer asyncCallsMutex.Unlock()
+	handle, err := C.handle_async(cache.ptr, id, p, m, call.db, call.api, call.querier, u64(gasLimit), u32(opts.CallDepth), cid, asyncCallDone, &errmsg)
+	return call.started(handle, err, errmsg)
+}
+
+// QueryAsync queues Query on the worker thread of the library and returns at once, like HandleAsync.
+// opts.Block and opts.GasReport are not used, the gas report of the call is taken by Result.
+func QueryAsync(
+	cache Cache,
+	code_id []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) (*AsyncCall, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	m := sendSlice(msg)
+	defer freeAfterSend(m)
+	cid := sendSlice([]byte(opts.CallID))
+	defer freeAfterSend(cid)
+
+	call := newAsyncCall(store, gasMeter, api, querier)
+	errmsg := C.Buffer{}
+
+	// the call may be done before we know its handle, so cAsyncCallDone waits for it
+	asyncCallsMutex.Lock()
+	defer asyncCallsMutex.Unlock()
+	handle, err := C.query_async(cache.ptr, id, m, call.db, call.api, call.querier, u64(gasLimit), u32(opts.CallDepth), cid, asyncCallDone, &errmsg)
+	return call.started(handle, err, errmsg)
+}
+
+func newAsyncCall(store KVStore, gasMeter *GasMeter, api *GoAPI, querier *Querier) *AsyncCall {
+	// set up a new stack frame to handle iterators, released once the result is taken
+	dbState := buildDBState(store, startContract())
+	call := &AsyncCall{
+		done:    make(chan struct{}),
+		dbState: &dbState,
+	}
+	call.db = C.DB{
+		gas_meter: (*C.gas_meter_t)(call.store(gasMeter)),
+		state:     (*C.db_t)(call.store(&dbState)),
+		vtable:    db_vtable,
+		namespace: sendSlice(dbState.namespace),
+	}
+	call.api = C.GoApi{state: (*C.api_t)(call.store(api)), vtable: api_vtable}
+	call.querier = C.GoQuerier{state: (*C.querier_t)(call.store(querier)), vtable: querier_vtable}
+	return call
+}
+
+// store adds value to goHandles until the call is released
+func (c *AsyncCall) store(value interface{}) unsafe.Pointer {
+	handle := storeGoHandle(value)
+	c.handles = append(c.handles, handle)
+	return goHandlePointer(handle)
+}
+
+// release frees what the call handed to the Rust side, once the Rust side is done with it
+func (c *AsyncCall) release() {
+	endContract(c.dbState.IteratorStackID)
+	freeAfterSend(c.db.namespace)
+	for _, handle := range c.handles {
+		releaseGoHandle(handle)
+	}
+}
+
+// started registers the call with handle. The caller holds asyncCallsMutex.
+func (c *AsyncCall) started(handle u64, err error, errmsg C.Buffer) (*AsyncCall, error) {
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		c.release()
+		return nil, errorWithMessage(err, errmsg)
+	}
+	c.handle = handle
+	asyncCalls[handle] = c
+	return c, nil
+}
+
+// Done is closed once the call is done.
+func (c *AsyncCall) Done() <-chan struct{} {
+	return c.done
+}
+
+// Result waits until the call is done and returns what Handle or Query would have returned. It must be called
+// exactly once for every call, so the library can forget it.
+func (c *AsyncCall) Result(gasReport *GasReport) ([]byte, uint64, error) {
+	<-c.done
+	defer c.release()
 	var gasUsed u64
+	var report C.GasReport
 	errmsg := C.Buffer{}
 
-	res, err := C.query(cache.ptr, id, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg)
+	res, err := C.take_async_result(c.handle, &gasUsed, &report, &errmsg)
+	if gasReport != nil {
+		*gasReport = receiveGasReport(report)
+	}
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
 		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
 	}
 	return receiveVector(res), uint64(gasUsed), nil
 }
 
 // KeyGen Send KeyGen request to enclave
-func KeyGen() ([]byte, error) {
+func KeyGen() (RegistrationKeys, error) {
 	errmsg := C.Buffer{}
 	res, err := C.key_gen(&errmsg)
+	if err != nil {
+		return RegistrationKeys{}, errorWithMessage(err, errmsg)
+	}
+	return parseRegistrationKeys(receiveVector(res))
+}
+
+// GetRegistrationKeys returns the public registration keys of a node which already ran KeyGen
+func GetRegistrationKeys() (RegistrationKeys, error) {
+	errmsg := C.Buffer{}
+	res, err := C.get_registration_keys(&errmsg)
+	if err != nil {
+		return RegistrationKeys{}, errorWithMessage(err, errmsg)
+	}
+	return parseRegistrationKeys(receiveVector(res))
+}
+
+// CreateKeyAttestation returns a statement of the enclave binding the registration keys of the node to an
+// attestation report, for wallets and bridges verifying they talk to a genuine enclave. challenge must be 32
+// bytes picked by the verifier, so the statement cannot be replayed.
+func CreateKeyAttestation(challenge []byte) (KeyAttestation, error) {
+	c := sendSlice(challenge)
+	defer freeAfterSend(c)
+	errmsg := C.Buffer{}
+
+	res, err := C.create_key_attestation(c, &errmsg)
+	if err != nil {
+		return KeyAttestation{}, errorWithMessage(err, errmsg)
+	}
+	return parseKeyAttestation(receiveVector(res))
+}
+
+// AttestationChallenge returns a quote of the enclave committing to the registration keys of the node and nonce,
+// for verifiers like bridges which attest the node live. nonce must be 32 bytes picked by the verifier, who
+// sends the quote to IAS itself and checks the answer with VerifyAttestationResponse.
+func AttestationChallenge(nonce []byte) ([]byte, error) {
+	n := sendSlice(nonce)
+	defer freeAfterSend(n)
+	errmsg := C.Buffer{}
+
+	res, err := C.attestation_challenge(n, &errmsg)
 	if err != nil {
 		return nil, errorWithMessage(err, errmsg)
 	}
 	return receiveVector(res), nil
 }
 
+// VerifyAttestationResponse checks the answer of IAS to a quote of AttestationChallenge: its signature, that it is
+// for quote and not older than maxAge, unless that is 0, and that the quote commits to keys and nonce.
+func VerifyAttestationResponse(nonce []byte, keys RegistrationKeys, quote []byte, response IASResponse, maxAge time.Duration) (AttestationVerdict, error) {
+	responseBin, err := json.Marshal(response)
+	if err != nil {
+		return AttestationVerdict{}, err
+	}
+	n := sendSlice(nonce)
+	defer freeAfterSend(n)
+	k := sendSlice(append(append([]byte{}, keys.NodePubKey.Value...), keys.SeedExchangePubKey.Value...))
+	defer freeAfterSend(k)
+	q := sendSlice(quote)
+	defer freeAfterSend(q)
+	r := sendSlice(responseBin)
+	defer freeAfterSend(r)
+	errmsg := C.Buffer{}
+
+	res, err := C.verify_attestation_response(n, k, q, r, u64(maxAge/time.Second), &errmsg)
+	if err != nil {
+		return AttestationVerdict{}, errorWithMessage(err, errmsg)
+	}
+	return parseAttestationVerdict(receiveVector(res))
+}
+
 // KeyGen Seng KeyGen request to enclave
 func CreateAttestationReport() (bool, error) {
 	errmsg := C.Buffer{}
@@ -268,6 +1549,32 @@ func CreateAttestationReport() (bool, error) {
 	return true, nil
 }
 
+// CreateAttestationReportV2 is CreateAttestationReport for version 2 of the seed exchange, which has forward
+// secrecy. The certificate carries an ephemeral key which only lives in enclave memory, so LoadSeedToEnclave
+// must be called with the encrypted seed before the node restarts.
+func CreateAttestationReportV2() (bool, error) {
+	errmsg := C.Buffer{}
+	_, err := C.create_attestation_report_v2(&errmsg)
+	if err != nil {
+		return false, errorWithMessage(err, errmsg)
+	}
+	return true, nil
+}
+
+// RefreshRegistration has the enclave create a new attestation report, after the platform of the node was updated
+// because of a types.TcbOutdatedError, and returns the payload the node registers again with. It fails with
+// types.TcbOutdatedError if IAS still tells that the platform lacks updates.
+func RefreshRegistration() (RegistrationRefresh, error) {
+	errmsg := C.Buffer{}
+	res, err := C.refresh_registration(&errmsg)
+	if err != nil {
+		return RegistrationRefresh{}, errorWithMessage(err, errmsg)
+	}
+	return parseRegistrationRefresh(receiveVector(res))
+}
+
+// GetEncryptedSeed encrypts the consensus seed for the node which made cert. The seed exchange version is taken
+// from the certificate, see SeedExchangeVersion.
 func GetEncryptedSeed(cert []byte) ([]byte, error) {
 	errmsg := C.Buffer{}
 	certSlice := sendSlice(cert)
@@ -279,6 +1586,226 @@ func GetEncryptedSeed(cert []byte) ([]byte, error) {
 	return receiveVector(res), nil
 }
 
+// ReloadEnclave replaces the enclave with the signed enclave binary at path, without restarting the node. It waits
+// for the running contract and enclave calls, and calls made meanwhile fail. The new enclave must be signed with
+// the same key as the old one, and must not be reloaded while the node registers. The cache is reopened, so it
+// drops the modules compiled by the old enclave.
+func ReloadEnclave(cache Cache, path string) error {
+	pathSlice := sendSlice([]byte(path))
+	defer freeAfterSend(pathSlice)
+	errmsg := C.Buffer{}
+
+	_, err := C.reload_enclave(cache.ptr, pathSlice, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// GetEnclavePairing tells whether the loaded enclave is the one this library was built for.
+// A mismatch usually means the enclave .so was not updated together with the library.
+func GetEnclavePairing() (EnclavePairing, error) {
+	errmsg := C.Buffer{}
+	res, err := C.get_enclave_pairing(&errmsg)
+	if err != nil {
+		return EnclavePairing{}, errorWithMessage(err, errmsg)
+	}
+	return parseEnclavePairing(receiveVector(res))
+}
+
+// GetLastCrashReport returns the diagnostics of the last enclave call failing in the SGX SDK or contract execution
+// failing in the VM, for attaching to bug reports. It returns nil if nothing failed since the node started.
+func GetLastCrashReport() (*CrashReport, error) {
+	errmsg := C.Buffer{}
+	res, err := C.get_last_crash_report(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return parseCrashReport(receiveVector(res))
+}
+
+// SetEnclaveCallPolicy sets the timeout and retries of AttestationChallenge, CreateAttestationReport,
+// CreateKeyAttestation, GetEncryptedSeed and InitBootstrap.
+// A call which times out keeps running in the enclave, and calls of the same kind fail with types.TimeoutError
+// until it has finished.
+func SetEnclaveCallPolicy(policy EnclaveCallPolicy) error {
+	policyBin, err := policy.marshal()
+	if err != nil {
+		return err
+	}
+	policySlice := sendSlice(policyBin)
+	defer freeAfterSend(policySlice)
+	errmsg := C.Buffer{}
+
+	_, err = C.set_enclave_call_policy(policySlice, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// SetQueryLimits limits the queries started by Query and QueryShared. Queries over the limits fail at once with
+// types.ThrottledError. Queries of contracts by other contracts are never limited.
+func SetQueryLimits(limits QueryLimits) error {
+	limitsBin, err := json.Marshal(limits)
+	if err != nil {
+		return err
+	}
+	limitsSlice := sendSlice(limitsBin)
+	defer freeAfterSend(limitsSlice)
+	errmsg := C.Buffer{}
+
+	_, err = C.set_query_limits(limitsSlice, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// SetExecutorOptions sets how many calls of HandleAsync and QueryAsync may wait for the worker thread of the
+// library, which runs them one at a time. Calls over the queue limit fail at once with types.ThrottledError.
+func SetExecutorOptions(options ExecutorOptions) error {
+	optionsBin, err := json.Marshal(options)
+	if err != nil {
+		return err
+	}
+	optionsSlice := sendSlice(optionsBin)
+	defer freeAfterSend(optionsSlice)
+	errmsg := C.Buffer{}
+
+	_, err = C.set_executor_options(optionsSlice, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// GetExecutorStats returns the calls of HandleAsync and QueryAsync running and queued, for the metrics of the
+// node.
+func GetExecutorStats() (ExecutorStats, error) {
+	errmsg := C.Buffer{}
+	res, err := C.get_executor_stats(&errmsg)
+	if err != nil {
+		return ExecutorStats{}, errorWithMessage(err, errmsg)
+	}
+	return parseExecutorStats(receiveVector(res))
+}
+
+// InitEnclaveLog makes the enclave's log lines go to a buffer instead of stdout, from which DrainEnclaveLog
+// takes them. Calling it again changes the options of the buffer.
+func InitEnclaveLog(options EnclaveLogOptions) error {
+	optionsBin, err := json.Marshal(options)
+	if err != nil {
+		return err
+	}
+	optionsSlice := sendSlice(optionsBin)
+	defer freeAfterSend(optionsSlice)
+	errmsg := C.Buffer{}
+
+	_, err = C.init_enclave_log(optionsSlice, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// DrainEnclaveLog takes the enclave's log lines buffered since the last call. There are none if InitEnclaveLog
+// was not called.
+func DrainEnclaveLog() (EnclaveLogDrain, error) {
+	errmsg := C.Buffer{}
+	res, err := C.drain_enclave_log(&errmsg)
+	if err != nil {
+		return EnclaveLogDrain{}, errorWithMessage(err, errmsg)
+	}
+	return parseEnclaveLogDrain(receiveVector(res))
+}
+
+// InitAuditLog records the privileged operations of the node, like InitBootstrap and InitNode, in audit.log in
+// dataDir from now on. Entries are appended to the ones already in the file, which is never truncated.
+func InitAuditLog(dataDir string) error {
+	d := sendSlice([]byte(dataDir))
+	defer freeAfterSend(d)
+	errmsg := C.Buffer{}
+
+	_, err := C.init_audit_log(d, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// ReadAuditLog returns all entries of the audit log, oldest first. There are none if InitAuditLog was not called.
+func ReadAuditLog() ([]AuditEntry, error) {
+	errmsg := C.Buffer{}
+	res, err := C.read_audit_log(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return parseAuditLog(receiveVector(res))
+}
+
+func receiveGasReport(report C.GasReport) GasReport {
+	return GasReport{
+		UsedExternally:  uint64(report.used_externally),
+		Refundable:      uint64(report.refundable),
+		Reads:           uint64(report.reads),
+		Writes:          uint64(report.writes),
+		IteratorSteps:   uint64(report.iterator_steps),
+		Queries:         uint64(report.queries),
+		APICalls:        uint64(report.api_calls),
+		Fallback:        uint64(report.fallback),
+		ScheduleVersion: uint64(report.schedule_version),
+		PeakMemoryPages: uint32(report.peak_memory_pages),
+		HostCalls:       receiveHostCallCounts(report.host_calls),
+	}
+}
+
+func receiveHostCallCounts(counts C.HostCallCounts) HostCallCounts {
+	return HostCallCounts{
+		DbRead:              uint64(counts.db_read),
+		DbWrite:             uint64(counts.db_write),
+		DbRemove:            uint64(counts.db_remove),
+		DbRemoveRange:       uint64(counts.db_remove_range),
+		DbScan:              uint64(counts.db_scan),
+		DbNext:              uint64(counts.db_next),
+		DbSeek:              uint64(counts.db_seek),
+		QueryChain:          uint64(counts.query_chain),
+		CanonicalizeAddress: uint64(counts.canonicalize_address),
+		HumanizeAddress:     uint64(counts.humanize_address),
+	}
+}
+
+func sendGasReport(report GasReport) C.GasReport {
+	return C.GasReport{
+		used_externally:   u64(report.UsedExternally),
+		refundable:        u64(report.Refundable),
+		reads:             u64(report.Reads),
+		writes:            u64(report.Writes),
+		iterator_steps:    u64(report.IteratorSteps),
+		queries:           u64(report.Queries),
+		api_calls:         u64(report.APICalls),
+		fallback:          u64(report.Fallback),
+		schedule_version:  u64(report.ScheduleVersion),
+		peak_memory_pages: u32(report.PeakMemoryPages),
+		host_calls:        sendHostCallCounts(report.HostCalls),
+	}
+}
+
+func sendHostCallCounts(counts HostCallCounts) C.HostCallCounts {
+	return C.HostCallCounts{
+		db_read:              u64(counts.DbRead),
+		db_write:             u64(counts.DbWrite),
+		db_remove:            u64(counts.DbRemove),
+		db_remove_range:      u64(counts.DbRemoveRange),
+		db_scan:              u64(counts.DbScan),
+		db_next:              u64(counts.DbNext),
+		db_seek:              u64(counts.DbSeek),
+		query_chain:          u64(counts.QueryChain),
+		canonicalize_address: u64(counts.CanonicalizeAddress),
+		humanize_address:     u64(counts.HumanizeAddress),
+	}
+}
+
 /**** To error module ***/
 
 func errorWithMessage(err error, b C.Buffer) error {
@@ -287,6 +1814,48 @@ func errorWithMessage(err error, b C.Buffer) error {
 		return types.OutOfGasError{}
 	}
 	msg := receiveVector(b)
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 3 {
+		return types.CallDepthExceededError{Msg: string(msg)}
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 4 {
+		return types.ResponseTooLargeError{Msg: string(msg)}
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 5 {
+		return parseUnsupportedFeatures(string(msg))
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 6 {
+		return types.TimeoutError{Msg: string(msg)}
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 7 {
+		return types.ThrottledError{Msg: string(msg)}
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 8 {
+		return types.InvalidEventsError{Msg: string(msg)}
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 9 {
+		return types.EventLimitExceededError{Msg: string(msg)}
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 10 {
+		return types.CallDeniedError{Msg: string(msg)}
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 11 {
+		return types.ContractPausedError{Msg: string(msg)}
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 12 {
+		return types.IllegalReentrancyError{Msg: string(msg)}
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 13 {
+		return types.IteratorLimitExceededError{Msg: string(msg)}
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 14 {
+		return types.InvalidMsgError{Msg: string(msg)}
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 15 {
+		return types.TcbOutdatedError{Msg: string(msg)}
+	}
+	if errno, ok := err.(syscall.Errno); ok && int(errno) == 16 {
+		return types.CapabilityDeniedError{Msg: string(msg)}
+	}
 	if msg == nil {
 		return err
 	}
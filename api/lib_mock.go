@@ -9,7 +9,10 @@ package api
 
 // import "C"
 import (
+	"errors"
 	//"fmt"
+	"time"
+
 	"github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
 )
 
@@ -29,6 +32,18 @@ import (
 
 type Cache struct{}
 
+func CheckAbi() error {
+	return nil
+}
+
+func ErrorTemplatesVersion() uint32 {
+	return 0
+}
+
+func EnvSchemaVersions() ([]uint32, error) {
+	return []uint32{1}, nil
+}
+
 func HealthCheck() ([]byte, error) {
 	return nil, nil
 }
@@ -44,6 +59,14 @@ func InitBootstrap() ([]byte, error) {
 	return nil, nil
 }
 
+func CreateSeedShare(ceremony BootstrapCeremony) ([]byte, error) {
+	return nil, nil
+}
+
+func CombineSeedShares(ceremony BootstrapCeremony, shares [][]byte) ([]byte, error) {
+	return nil, nil
+}
+
 func LoadSeedToEnclave(masterCert []byte, seed []byte) (bool, error) {
 	//pkSlice := sendSlice(masterCert)
 	//defer freeAfterSend(pkSlice)
@@ -58,9 +81,63 @@ func LoadSeedToEnclave(masterCert []byte, seed []byte) (bool, error) {
 	return true, nil
 }
 
+func SetBlockEntropy(height uint64, entropy []byte) error {
+	return nil
+}
+
+func SetUnlockKey(key []byte) error {
+	return nil
+}
+
+type TrustedTime struct {
+	Seconds uint64
+	Source  []byte
+}
+
+func GetTrustedTime() (TrustedTime, error) {
+	return TrustedTime{}, nil
+}
+
+func VerifyPermit(permit []byte, contractAddr string, feeDenom string) ([]byte, error) {
+	return nil, nil
+}
+
+// VerifyContractKey needs the enclave, so the mock cannot vouch for any key
+func VerifyContractKey(contractAddr string, codeHash []byte, contractKey []byte) (bool, error) {
+	return false, errors.New("VerifyContractKey is not supported without the enclave")
+}
+
+func ExportGasCheckpoint(previous GasCheckpoint, gasLimit uint64, gasUsed uint64, report GasReport) (GasCheckpoint, error) {
+	return nil, nil
+}
+
+func ImportGasCheckpoint(checkpoint GasCheckpoint) (GasCheckpointInfo, error) {
+	return GasCheckpointInfo{}, nil
+}
+
+func ValidateChecksum(wasm []byte, expected []byte) (bool, error) {
+	return true, nil
+}
+
+func DeriveContractAddress(checksum []byte, creator []byte, salt []byte, msg []byte) ([]byte, error) {
+	return nil, nil
+}
+
+func EncryptMsg(nodePubKey []byte, ephemeralSecret []byte, nonce []byte, plaintext []byte) ([]byte, error) {
+	return nil, nil
+}
+
+func DecryptResponse(nodePubKey []byte, ephemeralSecret []byte, envelope []byte, ciphertext []byte) ([]byte, error) {
+	return nil, nil
+}
+
+func DecryptQueryResponse(nodePubKey []byte, ephemeralSecret []byte, nonce []byte, ciphertext []byte) ([]byte, error) {
+	return nil, nil
+}
+
 type Querier = types.Querier
 
-func InitCache(dataDir string, supportedFeatures string, cacheSize uint64) (Cache, error) {
+func InitCache(dataDir string, supportedFeatures string, cacheSize uint64, options CacheOptions) (Cache, error) {
 	//dir := sendSlice([]byte(dataDir))
 	//defer freeAfterSend(dir)
 	//features := sendSlice([]byte(supportedFeatures))
@@ -78,6 +155,166 @@ func ReleaseCache(cache Cache) {
 	//C.release_cache(cache.ptr)
 }
 
+func UpdateSupportedFeatures(cache Cache, supportedFeatures string) error {
+	return nil
+}
+
+func SetConsensusParams(cache Cache, params ConsensusParams) error {
+	return nil
+}
+
+func SetCodePaused(cache Cache, checksum []byte, paused bool) error {
+	return nil
+}
+
+func ExportSnapshot(cache Cache) ([]byte, error) {
+	return nil, nil
+}
+
+func GetShadowStats(cache Cache) (ShadowStats, error) {
+	return ShadowStats{}, nil
+}
+
+func GetModuleStats(cache Cache) (ModuleStats, error) {
+	return ModuleStats{}, nil
+}
+
+func GetCodeStats(cache Cache, reset bool) ([]CodeStats, error) {
+	return nil, nil
+}
+
+func RestoreSnapshot(cache Cache, payload []byte) error {
+	return nil
+}
+
+func ExportContractState(
+	contractKey []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	startAfter []byte,
+	limit uint32,
+	mode ExportMode,
+) (*StatePage, error) {
+	return nil, nil
+}
+
+func AuditStateKeys(
+	contractKey []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	keyHistory [][]byte,
+	startAfter []byte,
+	limit uint32,
+) (*KeyAudit, error) {
+	return nil, nil
+}
+
+func RunBenchmark(
+	kind BenchmarkKind,
+	iterations uint32,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+) (*BenchmarkReport, error) {
+	return nil, nil
+}
+
+type SharedRegion struct{}
+
+func NewSharedRegion(size int) *SharedRegion {
+	return &SharedRegion{}
+}
+
+func (r *SharedRegion) Free() {}
+
+func RegisterSharedRegion(cache Cache, region *SharedRegion) error {
+	return nil
+}
+
+type SharedResult struct {
+	Data []byte
+}
+
+func (r *SharedResult) Release() {}
+
+func QueryShared(
+	cache Cache,
+	code_id []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store *KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) (SharedResult, uint64, error) {
+	return SharedResult{}, 0, nil
+}
+
+func QueryCached(
+	cache Cache,
+	contractAddr string,
+	code_id []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store *KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
+func QueryBatch(
+	cache Cache,
+	queries []BatchQuery,
+	gasMeter *GasMeter,
+	store *KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) ([]BatchQueryResult, uint64, error) {
+	return nil, 0, nil
+}
+
+func RegisterGasSchedule(cache Cache, activationHeight uint64, costs WasmCosts) error {
+	return nil
+}
+
+func SetQueryRoutes(cache Cache, routes map[string]QueryRoute) error {
+	return nil
+}
+
+func QueryByAlias(
+	cache Cache,
+	alias string,
+	args map[string]interface{},
+	gasMeter *GasMeter,
+	store *KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
+func QueryUnmetered(
+	cache Cache,
+	code_id []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store *KVStore,
+	api *GoAPI,
+	querier *Querier,
+	opts CallOptions,
+) ([]byte, error) {
+	return nil, nil
+}
+
 func Create(cache Cache, wasm []byte) ([]byte, error) {
 	//code := sendSlice(wasm)
 	//defer freeAfterSend(code)
@@ -90,6 +327,18 @@ func Create(cache Cache, wasm []byte) ([]byte, error) {
 	return nil, nil
 }
 
+func CreatePlaintext(cache Cache, wasm []byte) ([]byte, error) {
+	return nil, nil
+}
+
+func ExportCodes(cache Cache, checksums [][]byte) ([]byte, error) {
+	return nil, nil
+}
+
+func ImportCodes(cache Cache, archive []byte) (ImportSummary, error) {
+	return ImportSummary{}, nil
+}
+
 func GetCode(cache Cache, code_id []byte) ([]byte, error) {
 	//id := sendSlice(code_id)
 	//defer freeAfterSend(id)
@@ -112,6 +361,7 @@ func Instantiate(
 	api *GoAPI,
 	querier *Querier,
 	gasLimit uint64,
+	opts CallOptions,
 ) ([]byte, uint64, error) {
 	//id := sendSlice(code_id)
 	//defer freeAfterSend(id)
@@ -133,6 +383,23 @@ func Instantiate(
 	return nil, 0, nil
 }
 
+func Instantiate2(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store *KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	salt []byte,
+	fixMsg bool,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
 func Handle(
 	cache Cache,
 	code_id []byte,
@@ -143,6 +410,7 @@ func Handle(
 	api *GoAPI,
 	querier *Querier,
 	gasLimit uint64,
+	opts CallOptions,
 ) ([]byte, uint64, error) {
 	//id := sendSlice(code_id)
 	//defer freeAfterSend(id)
@@ -164,6 +432,87 @@ func Handle(
 	return nil, 0, nil
 }
 
+func HandleSystem(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store *KVStore,
+	api *GoAPI,
+	querier *Querier,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
+func BeginBlock(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	block []byte,
+	gasMeter *GasMeter,
+	store *KVStore,
+	api *GoAPI,
+	querier *Querier,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
+func EndBlock(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	block []byte,
+	gasMeter *GasMeter,
+	store *KVStore,
+	api *GoAPI,
+	querier *Querier,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
+type AsyncCall struct{}
+
+func HandleAsync(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store *KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) (*AsyncCall, error) {
+	return nil, nil
+}
+
+func QueryAsync(
+	cache Cache,
+	code_id []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store *KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) (*AsyncCall, error) {
+	return nil, nil
+}
+
+func (c *AsyncCall) Done() <-chan struct{} {
+	return nil
+}
+
+func (c *AsyncCall) Result(gasReport *GasReport) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
 func Migrate(
 	cache Cache,
 	code_id []byte,
@@ -174,6 +523,9 @@ func Migrate(
 	api *GoAPI,
 	querier *Querier,
 	gasLimit uint64,
+	keyHistory [][]byte,
+	auth MigrateAuth,
+	opts CallOptions,
 ) ([]byte, uint64, error) {
 	//id := sendSlice(code_id)
 	//defer freeAfterSend(id)
@@ -204,6 +556,7 @@ func Query(
 	api *GoAPI,
 	querier *Querier,
 	gasLimit uint64,
+	opts CallOptions,
 ) ([]byte, uint64, error) {
 	//id := sendSlice(code_id)
 	//defer freeAfterSend(id)
@@ -224,16 +577,36 @@ func Query(
 }
 
 // KeyGen Send KeyGen request to enclave
-func KeyGen() ([]byte, error) {
+func KeyGen() (RegistrationKeys, error) {
 	//errmsg := C.Buffer{}
 	//res, err := C.key_gen(&errmsg)
 	//if err != nil {
 	//	return nil, errorWithMessage(err, errmsg)
 	//}
 	//return receiveVector(res), nil
+	return RegistrationKeys{}, nil
+}
+
+func GetRegistrationKeys() (RegistrationKeys, error) {
+	return RegistrationKeys{}, nil
+}
+
+func CreateKeyAttestation(challenge []byte) (KeyAttestation, error) {
+	return KeyAttestation{}, nil
+}
+
+func RefreshRegistration() (RegistrationRefresh, error) {
+	return RegistrationRefresh{}, nil
+}
+
+func AttestationChallenge(nonce []byte) ([]byte, error) {
 	return nil, nil
 }
 
+func VerifyAttestationResponse(nonce []byte, keys RegistrationKeys, quote []byte, response IASResponse, maxAge time.Duration) (AttestationVerdict, error) {
+	return AttestationVerdict{}, nil
+}
+
 // KeyGen Seng KeyGen request to enclave
 func CreateAttestationReport() (bool, error) {
 	//errmsg := C.Buffer{}
@@ -244,6 +617,10 @@ func CreateAttestationReport() (bool, error) {
 	return true, nil
 }
 
+func CreateAttestationReportV2() (bool, error) {
+	return true, nil
+}
+
 func GetEncryptedSeed(cert []byte) ([]byte, error) {
 	//errmsg := C.Buffer{}
 	//certSlice := sendSlice(cert)
@@ -256,6 +633,50 @@ func GetEncryptedSeed(cert []byte) ([]byte, error) {
 	return nil, nil
 }
 
+func ReloadEnclave(cache Cache, path string) error {
+	return nil
+}
+
+func GetEnclavePairing() (EnclavePairing, error) {
+	return EnclavePairing{Matches: true}, nil
+}
+
+func InitEnclaveLog(options EnclaveLogOptions) error {
+	return nil
+}
+
+func DrainEnclaveLog() (EnclaveLogDrain, error) {
+	return EnclaveLogDrain{}, nil
+}
+
+func InitAuditLog(dataDir string) error {
+	return nil
+}
+
+func ReadAuditLog() ([]AuditEntry, error) {
+	return nil, nil
+}
+
+func GetLastCrashReport() (*CrashReport, error) {
+	return nil, nil
+}
+
+func SetEnclaveCallPolicy(policy EnclaveCallPolicy) error {
+	return nil
+}
+
+func SetExecutorOptions(options ExecutorOptions) error {
+	return nil
+}
+
+func GetExecutorStats() (ExecutorStats, error) {
+	return ExecutorStats{}, nil
+}
+
+func SetQueryLimits(limits QueryLimits) error {
+	return nil
+}
+
 /**** To error module ***/
 
 //func errorWithMessage(err error, b C.Buffer) error {
@@ -1,7 +1,13 @@
 package api
 
 import (
+	"bytes"
+	"crypto/sha256"
+	"encoding/base64"
+	"encoding/binary"
+	"encoding/hex"
 	"encoding/json"
+	"fmt"
 	"io/ioutil"
 	"os"
 	"testing"
@@ -15,25 +21,81 @@ import (
 
 const DEFAULT_FEATURES = "staking"
 
+func TestCheckAbi(t *testing.T) {
+	require.NoError(t, CheckAbi())
+}
+
+func TestErrorTemplatesVersion(t *testing.T) {
+	require.Equal(t, uint32(2), ErrorTemplatesVersion())
+}
+
+func TestEnvSchemaVersions(t *testing.T) {
+	versions, err := EnvSchemaVersions()
+	require.NoError(t, err)
+	require.Equal(t, []uint32{1}, versions)
+}
+
+func TestGasCheckpoint(t *testing.T) {
+	checkpoint, err := ExportGasCheckpoint(nil, 1000, 100, GasReport{UsedExternally: 50})
+	require.NoError(t, err)
+	checkpoint, err = ExportGasCheckpoint(checkpoint, 850, 200, GasReport{UsedExternally: 100})
+	require.NoError(t, err)
+
+	info, err := ImportGasCheckpoint(checkpoint)
+	require.NoError(t, err)
+	require.Equal(t, GasCheckpointInfo{GasLimit: 1000, Consumed: 450, Remaining: 550, Calls: 2}, info)
+
+	_, err = ExportGasCheckpoint(checkpoint, info.Remaining, 600, GasReport{})
+	require.Equal(t, types.OutOfGasError{}, err)
+
+	_, err = ImportGasCheckpoint(GasCheckpoint("foo"))
+	require.Error(t, err)
+}
+
 func TestInitAndReleaseCache(t *testing.T) {
 	dataDir := "/foo"
-	_, err := InitCache(dataDir, DEFAULT_FEATURES, 3)
+	_, err := InitCache(dataDir, DEFAULT_FEATURES, 3, CacheOptions{})
 	require.Error(t, err)
 
 	tmpdir, err := ioutil.TempDir("", "go-cosmwasm")
 	require.NoError(t, err)
 	defer os.RemoveAll(tmpdir)
 
-	cache, err := InitCache(tmpdir, DEFAULT_FEATURES, 3)
+	cache, err := InitCache(tmpdir, DEFAULT_FEATURES, 3, CacheOptions{})
 	require.NoError(t, err)
 	ReleaseCache(cache)
 }
 
+func TestCallsFailWithoutConsensusParams(t *testing.T) {
+	tmpdir, err := ioutil.TempDir("", "go-cosmwasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tmpdir)
+	cache, err := InitCache(tmpdir, DEFAULT_FEATURES, 3, CacheOptions{})
+	require.NoError(t, err)
+	defer ReleaseCache(cache)
+
+	wasm, err := ioutil.ReadFile("./testdata/hackatom.wasm")
+	require.NoError(t, err)
+	_, err = Create(cache, wasm)
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "Consensus params not set")
+
+	require.Error(t, SetConsensusParams(cache, ConsensusParams{FloatPolicy: "deny"}))
+	require.NoError(t, SetConsensusParams(cache, ConsensusParams{MaxCallDepth: 3}))
+	_, err = Create(cache, wasm)
+	require.NoError(t, err)
+}
+
 func withCache(t *testing.T) (Cache, func()) {
+	return withCacheOptions(t, CacheOptions{})
+}
+
+func withCacheOptions(t *testing.T, options CacheOptions) (Cache, func()) {
 	tmpdir, err := ioutil.TempDir("", "go-cosmwasm")
 	require.NoError(t, err)
-	cache, err := InitCache(tmpdir, DEFAULT_FEATURES, 3)
+	cache, err := InitCache(tmpdir, DEFAULT_FEATURES, 3, options)
 	require.NoError(t, err)
+	require.NoError(t, SetConsensusParams(cache, ConsensusParams{}))
 
 	cleanup := func() {
 		os.RemoveAll(tmpdir)
@@ -42,6 +104,24 @@ func withCache(t *testing.T) (Cache, func()) {
 	return cache, cleanup
 }
 
+func TestGetShadowStatsDisabled(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	stats, err := GetShadowStats(cache)
+	require.NoError(t, err)
+	require.Equal(t, ShadowStats{}, stats)
+}
+
+func TestGetCodeStatsEmpty(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	stats, err := GetCodeStats(cache, true)
+	require.NoError(t, err)
+	require.Empty(t, stats)
+}
+
 func TestCreateAndGet(t *testing.T) {
 	cache, cleanup := withCache(t)
 	defer cleanup()
@@ -57,6 +137,277 @@ func TestCreateAndGet(t *testing.T) {
 	require.Equal(t, wasm, code)
 }
 
+func TestCreatePlaintext(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	hackatom, err := ioutil.ReadFile("./testdata/hackatom.wasm")
+	require.NoError(t, err)
+	queue, err := ioutil.ReadFile("./testdata/queue.wasm")
+	require.NoError(t, err)
+
+	id, err := CreatePlaintext(cache, hackatom)
+	require.NoError(t, err)
+	again, err := CreatePlaintext(cache, hackatom)
+	require.NoError(t, err)
+	require.Equal(t, id, again)
+
+	// the mode of a code cannot change
+	_, err = Create(cache, hackatom)
+	require.Error(t, err)
+	_, err = Create(cache, queue)
+	require.NoError(t, err)
+	_, err = CreatePlaintext(cache, queue)
+	require.Error(t, err)
+}
+
+func TestCreateFailsWithUnsupportedFeatures(t *testing.T) {
+	tmpdir, err := ioutil.TempDir("", "go-cosmwasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tmpdir)
+	cache, err := InitCache(tmpdir, "iterator", 3, CacheOptions{})
+	require.NoError(t, err)
+	defer ReleaseCache(cache)
+	require.NoError(t, SetConsensusParams(cache, ConsensusParams{}))
+
+	wasm, err := ioutil.ReadFile("./testdata/reflect.wasm")
+	require.NoError(t, err)
+	_, err = Create(cache, wasm)
+	require.Equal(t, types.UnsupportedFeaturesError{
+		Missing:   []string{"staking"},
+		Required:  []string{"staking"},
+		Supported: []string{"iterator"},
+	}, err)
+
+	require.NoError(t, UpdateSupportedFeatures(cache, "iterator,staking"))
+	_, err = Create(cache, wasm)
+	require.NoError(t, err)
+}
+
+func TestUpdateSupportedFeatures(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	wasm, err := ioutil.ReadFile("./testdata/hackatom.wasm")
+	require.NoError(t, err)
+	id, err := Create(cache, wasm)
+	require.NoError(t, err)
+
+	err = UpdateSupportedFeatures(cache, "staking,stargate")
+	require.NoError(t, err)
+
+	// codes stored before the update stay available
+	code, err := GetCode(cache, id)
+	require.NoError(t, err)
+	require.Equal(t, wasm, code)
+}
+
+func TestImportCodes(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	hackatom, err := ioutil.ReadFile("./testdata/hackatom.wasm")
+	require.NoError(t, err)
+	queue, err := ioutil.ReadFile("./testdata/queue.wasm")
+	require.NoError(t, err)
+	existing, err := Create(cache, queue)
+	require.NoError(t, err)
+
+	archive := bytes.NewBufferString(CodeArchiveMagic)
+	var ids []string
+	for _, wasm := range [][]byte{hackatom, queue} {
+		checksum := sha256.Sum256(wasm)
+		ids = append(ids, hex.EncodeToString(checksum[:]))
+		archive.Write(checksum[:])
+		binary.Write(archive, binary.BigEndian, uint32(len(wasm)))
+		archive.Write(wasm)
+	}
+
+	summary, err := ImportCodes(cache, archive.Bytes())
+	require.NoError(t, err)
+	require.Equal(t, ImportSummary{Imported: ids[:1], Existing: []string{hex.EncodeToString(existing)}}, summary)
+
+	id, err := hex.DecodeString(ids[0])
+	require.NoError(t, err)
+	code, err := GetCode(cache, id)
+	require.NoError(t, err)
+	require.Equal(t, hackatom, code)
+
+	// a wrong checksum fails the whole import
+	corrupt := archive.Bytes()
+	corrupt[len(corrupt)-1] ^= 1
+	_, err = ImportCodes(cache, corrupt)
+	require.Error(t, err)
+}
+
+func TestExportCodes(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	hackatom, err := ioutil.ReadFile("./testdata/hackatom.wasm")
+	require.NoError(t, err)
+	hackatomID, err := Create(cache, hackatom)
+	require.NoError(t, err)
+	queue, err := ioutil.ReadFile("./testdata/queue.wasm")
+	require.NoError(t, err)
+	queueID, err := Create(cache, queue)
+	require.NoError(t, err)
+
+	archive, err := ExportCodes(cache, [][]byte{queueID})
+	require.NoError(t, err)
+	expected := bytes.NewBufferString(CodeArchiveMagic)
+	expected.Write(queueID)
+	binary.Write(expected, binary.BigEndian, uint32(len(queue)))
+	expected.Write(queue)
+	require.Equal(t, expected.Bytes(), archive)
+
+	// without a filter, all codes end up in the archive
+	archive, err = ExportCodes(cache, nil)
+	require.NoError(t, err)
+	other, otherCleanup := withCache(t)
+	defer otherCleanup()
+	summary, err := ImportCodes(other, archive)
+	require.NoError(t, err)
+	require.ElementsMatch(t, []string{hex.EncodeToString(hackatomID), hex.EncodeToString(queueID)}, summary.Imported)
+
+	_, err = ExportCodes(cache, [][]byte{queueID[:31]})
+	require.Error(t, err)
+}
+
+func TestValidateChecksum(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	wasm, err := ioutil.ReadFile("./testdata/hackatom.wasm")
+	require.NoError(t, err)
+	id, err := Create(cache, wasm)
+	require.NoError(t, err)
+
+	valid, err := ValidateChecksum(wasm, id)
+	require.NoError(t, err)
+	require.True(t, valid)
+
+	valid, err = ValidateChecksum(append(wasm, 0), id)
+	require.NoError(t, err)
+	require.False(t, valid)
+
+	_, err = ValidateChecksum(wasm, id[:31])
+	require.Error(t, err)
+}
+
+func TestGetEnclavePairing(t *testing.T) {
+	pairing, err := GetEnclavePairing()
+	require.NoError(t, err)
+	require.True(t, pairing.Matches)
+	require.Len(t, pairing.Actual, 64)
+}
+
+func TestInitEnclaveLog(t *testing.T) {
+	err := InitEnclaveLog(EnclaveLogOptions{Level: "verbose"})
+	require.Error(t, err)
+
+	err = InitEnclaveLog(EnclaveLogOptions{Level: "debug", MaxLines: 100})
+	require.NoError(t, err)
+	drain, err := DrainEnclaveLog()
+	require.NoError(t, err)
+	require.True(t, len(drain.Lines) <= 100)
+}
+
+func TestAuditLogRecordsPausedCodes(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+	id := createTestContract(t, cache)
+	dataDir, err := ioutil.TempDir("", "go-cosmwasm-audit")
+	require.NoError(t, err)
+	defer os.RemoveAll(dataDir)
+
+	require.NoError(t, InitAuditLog(dataDir))
+	require.NoError(t, SetCodePaused(cache, id, true))
+	entries, err := ReadAuditLog()
+	require.NoError(t, err)
+	require.Len(t, entries, 1)
+	require.Equal(t, "set_code_paused", entries[0].Operation)
+	require.Nil(t, entries[0].Error)
+
+	// each parameter is hashed with its length
+	var params []byte
+	for _, param := range [][]byte{id, {1}} {
+		params = append(params, make([]byte, 8)...)
+		binary.BigEndian.PutUint64(params[len(params)-8:], uint64(len(param)))
+		params = append(params, param...)
+	}
+	hash := sha256.Sum256(params)
+	require.Equal(t, hex.EncodeToString(hash[:]), entries[0].ParamsHash)
+}
+
+func TestSetUnlockKeyRejectsShortKeys(t *testing.T) {
+	err := SetUnlockKey([]byte("hunter2"))
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "unlock_key")
+}
+
+func TestRegisterGasSchedule(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	costs := WasmCosts{Instructions: map[string]uint64{"i64.div_u": 8}}
+	require.NoError(t, RegisterGasSchedule(cache, 1000, costs))
+	// registering it again, as after a restart
+	require.NoError(t, RegisterGasSchedule(cache, 1000, costs))
+
+	err := RegisterGasSchedule(cache, 1000, WasmCosts{Default: 2})
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "another gas schedule")
+	err = RegisterGasSchedule(cache, 2000, WasmCosts{Instructions: map[string]uint64{"i64.pow": 8}})
+	require.Error(t, err)
+}
+
+func TestReloadEnclaveFailsForMissingFile(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	err := ReloadEnclave(cache, "/nonexistent/librust_cosmwasm_enclave.signed.so")
+	require.Error(t, err)
+}
+
+func TestSetEnclaveCallPolicy(t *testing.T) {
+	err := SetEnclaveCallPolicy(EnclaveCallPolicy{Timeout: 30 * time.Second, Retries: 3, Backoff: time.Second})
+	require.NoError(t, err)
+	err = SetEnclaveCallPolicy(EnclaveCallPolicy{})
+	require.NoError(t, err)
+}
+
+func TestSetQueryLimits(t *testing.T) {
+	err := SetQueryLimits(QueryLimits{PerSecond: 100, Burst: 200, MaxConcurrent: 8})
+	require.NoError(t, err)
+	err = SetQueryLimits(QueryLimits{})
+	require.NoError(t, err)
+}
+
+func TestExecutorOptions(t *testing.T) {
+	err := SetExecutorOptions(ExecutorOptions{MaxQueued: 100})
+	require.NoError(t, err)
+	stats, err := GetExecutorStats()
+	require.NoError(t, err)
+	assert.True(t, stats.Running <= 1)
+
+	err = SetExecutorOptions(ExecutorOptions{})
+	require.NoError(t, err)
+}
+
+func TestSeedSharesRejectInvalidCeremonies(t *testing.T) {
+	participants := [][]byte{bytes.Repeat([]byte{1}, 32), bytes.Repeat([]byte{2}, 32)}
+
+	_, err := CreateSeedShare(BootstrapCeremony{ID: "secret-4", Threshold: 1, Participants: participants})
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "threshold must be between 2 and the number of participants")
+
+	ceremony := BootstrapCeremony{ID: "secret-4", Threshold: 2, Participants: participants}
+	_, err = CombineSeedShares(ceremony, [][]byte{[]byte("share"), []byte("share")})
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "expected at least 2 distinct shares, got 1")
+}
+
 func TestCreateFailsWithBadData(t *testing.T) {
 	cache, cleanup := withCache(t)
 	defer cleanup()
@@ -109,7 +460,7 @@ func TestInstantiate(t *testing.T) {
 	require.NoError(t, err)
 	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
 
-	res, cost, err := Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000)
+	res, cost, err := Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
 	assert.Equal(t, uint64(0x109a0), cost)
@@ -121,6 +472,41 @@ func TestInstantiate(t *testing.T) {
 	require.Equal(t, 0, len(resp.Ok.Messages))
 }
 
+func TestInstantiateRejectsInvalidCallID(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	gasMeter := NewMockGasMeter(100000000)
+	igasMeter := GasMeter(gasMeter)
+	store := NewLookup(gasMeter)
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+	params, err := json.Marshal(mockEnv("creator"))
+	require.NoError(t, err)
+
+	_, _, err = Instantiate(cache, make([]byte, 32), params, []byte(`{}`), &igasMeter, store, api, &querier, 100000000, CallOptions{CallID: "two words"})
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "call_id")
+}
+
+func TestInstantiate2RejectsOtherAddress(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	gasMeter := NewMockGasMeter(100000000)
+	igasMeter := GasMeter(gasMeter)
+	store := NewLookup(gasMeter)
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+	// the mock env has a fixed contract address, not the one derived from the salt
+	params, err := json.Marshal(mockEnv("creator"))
+	require.NoError(t, err)
+
+	_, _, err = Instantiate2(cache, make([]byte, 32), params, []byte(`{}`), &igasMeter, store, api, &querier, 100000000, []byte("a"), false, CallOptions{})
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "params")
+}
+
 func TestHandle(t *testing.T) {
 	t.SkipNow()
 	cache, cleanup := withCache(t)
@@ -140,7 +526,7 @@ func TestHandle(t *testing.T) {
 	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
 
 	start := time.Now()
-	res, cost, err := Instantiate(cache, id, params, msg, &igasMeter1, store, api, &querier, 100000000)
+	res, cost, err := Instantiate(cache, id, params, msg, &igasMeter1, store, api, &querier, 100000000, CallOptions{})
 	diff := time.Now().Sub(start)
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
@@ -154,7 +540,7 @@ func TestHandle(t *testing.T) {
 	params, err = json.Marshal(mockEnv("fred"))
 	require.NoError(t, err)
 	start = time.Now()
-	res, cost, err = Handle(cache, id, params, []byte(`{"release":{}}`), &igasMeter2, store, api, &querier, 100000000)
+	res, cost, err = Handle(cache, id, params, []byte(`{"release":{}}`), &igasMeter2, store, api, &querier, 100000000, CallOptions{})
 	diff = time.Now().Sub(start)
 	require.NoError(t, err)
 	assert.Equal(t, uint64(0x19c40), cost)
@@ -178,6 +564,153 @@ func TestHandle(t *testing.T) {
 	assert.Equal(t, expectedData, resp.Ok.Data)
 }
 
+func TestHandleSystemIsBounded(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+	require.NoError(t, SetConsensusParams(cache, ConsensusParams{SystemGasLimit: 1_000_000}))
+	id := createTestContract(t, cache)
+
+	gasMeter := NewMockGasMeter(100000000)
+	igasMeter := GasMeter(gasMeter)
+	store := NewLookup(gasMeter)
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+	params, err := json.Marshal(mockEnv("creator"))
+	require.NoError(t, err)
+	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
+	_, _, err = Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+
+	params, err = json.Marshal(mockEnv("fred"))
+	require.NoError(t, err)
+	_, gasUsed, err := HandleSystem(cache, id, params, []byte(`{"release":{}}`), &igasMeter, store, api, &querier, CallOptions{})
+	require.NoError(t, err)
+	require.NotZero(t, gasUsed)
+
+	// the budget stops endless loops
+	_, gasUsed, err = HandleSystem(cache, id, params, []byte(`{"cpu_loop":{}}`), &igasMeter, store, api, &querier, CallOptions{})
+	require.Error(t, err)
+	assert.Equal(t, uint64(1_000_000), gasUsed)
+}
+
+func TestBlockHooks(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+	id := createTestContract(t, cache)
+
+	gasMeter := NewMockGasMeter(100000000)
+	igasMeter := GasMeter(gasMeter)
+	store := NewLookup(gasMeter)
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+	params, err := json.Marshal(mockEnv("creator"))
+	require.NoError(t, err)
+	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
+	_, _, err = Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+
+	block, err := json.Marshal(types.BlockInfo{Height: 2, Time: 1578939744, ChainID: "foobar"})
+	require.NoError(t, err)
+	// hackatom exports no block hooks
+	_, _, err = BeginBlock(cache, id, params, block, &igasMeter, store, api, &querier, CallOptions{})
+	require.Error(t, err)
+	_, _, err = EndBlock(cache, id, params, block, &igasMeter, store, api, &querier, CallOptions{})
+	require.Error(t, err)
+
+	// a block must have a height
+	block, err = json.Marshal(types.BlockInfo{ChainID: "foobar"})
+	require.NoError(t, err)
+	_, _, err = BeginBlock(cache, id, params, block, &igasMeter, store, api, &querier, CallOptions{})
+	require.Error(t, err)
+	assert.Contains(t, err.Error(), "height")
+}
+
+func TestHandleAsync(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+	id := createTestContract(t, cache)
+
+	gasMeter := NewMockGasMeter(100000000)
+	igasMeter := GasMeter(gasMeter)
+	store := NewLookup(gasMeter)
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+	params, err := json.Marshal(mockEnv("creator"))
+	require.NoError(t, err)
+	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
+	_, _, err = Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+
+	params, err = json.Marshal(mockEnv("fred"))
+	require.NoError(t, err)
+	loop, err := HandleAsync(cache, id, params, []byte(`{"cpu_loop":{}}`), &igasMeter, store, api, &querier, 1_000_000, CallOptions{})
+	require.NoError(t, err)
+	release, err := HandleAsync(cache, id, params, []byte(`{"release":{}}`), &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+
+	var report GasReport
+	res, gasUsed, err := release.Result(&report)
+	require.NoError(t, err)
+	require.NotZero(t, gasUsed)
+	var resp types.HandleResult
+	require.NoError(t, json.Unmarshal(res, &resp))
+	require.Nil(t, resp.Err)
+
+	// the calls run in the order they were started
+	select {
+	case <-loop.Done():
+	default:
+		t.Fatal("the call started first is still running")
+	}
+	_, gasUsed, err = loop.Result(nil)
+	require.IsType(t, types.OutOfGasError{}, err)
+	assert.Equal(t, uint64(1_000_000), gasUsed)
+
+	// invalid arguments fail before the call starts
+	_, err = HandleAsync(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000, CallOptions{CallID: "not a call id"})
+	require.Error(t, err)
+}
+
+func TestQueryAsync(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+	id := createTestContract(t, cache)
+
+	gasMeter := NewMockGasMeter(100000000)
+	igasMeter := GasMeter(gasMeter)
+	store := NewLookup(gasMeter)
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+	params, err := json.Marshal(mockEnv("creator"))
+	require.NoError(t, err)
+	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
+	_, _, err = Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+
+	query := []byte(`{"verifier":{}}`)
+	starved, err := QueryAsync(cache, id, query, &igasMeter, store, api, &querier, 1, CallOptions{})
+	require.NoError(t, err)
+	verifier, err := QueryAsync(cache, id, query, &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+
+	var report GasReport
+	res, gasUsed, err := verifier.Result(&report)
+	require.NoError(t, err)
+	require.NotZero(t, gasUsed)
+	var qres types.QueryResponse
+	require.NoError(t, json.Unmarshal(res, &qres))
+	require.Nil(t, qres.Err, "%v", qres.Err)
+	require.Equal(t, `{"verifier":"fred"}`, string(qres.Ok))
+
+	<-starved.Done()
+	_, _, err = starved.Result(nil)
+	require.IsType(t, types.OutOfGasError{}, err)
+
+	// invalid arguments fail before the call starts
+	_, err = QueryAsync(cache, id, query, &igasMeter, store, api, &querier, 100000000, CallOptions{CallID: "not a call id"})
+	require.Error(t, err)
+}
+
 func TestHandleCpuLoop(t *testing.T) {
 	cache, cleanup := withCache(t)
 	defer cleanup()
@@ -196,7 +729,7 @@ func TestHandleCpuLoop(t *testing.T) {
 	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
 
 	start := time.Now()
-	res, cost, err := Instantiate(cache, id, params, msg, &igasMeter1, store, api, &querier, 100000000)
+	res, cost, err := Instantiate(cache, id, params, msg, &igasMeter1, store, api, &querier, 100000000, CallOptions{})
 	diff := time.Now().Sub(start)
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
@@ -211,7 +744,7 @@ func TestHandleCpuLoop(t *testing.T) {
 	params, err = json.Marshal(mockEnv("fred"))
 	require.NoError(t, err)
 	start = time.Now()
-	res, cost, err = Handle(cache, id, params, []byte(`{"cpu_loop":{}}`), &igasMeter2, store, api, &querier, maxGas)
+	res, cost, err = Handle(cache, id, params, []byte(`{"cpu_loop":{}}`), &igasMeter2, store, api, &querier, maxGas, CallOptions{})
 	diff = time.Now().Sub(start)
 	require.Error(t, err)
 	assert.Equal(t, cost, maxGas)
@@ -236,7 +769,7 @@ func TestHandleStorageLoop(t *testing.T) {
 
 	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
 
-	res, cost, err := Instantiate(cache, id, params, msg, &igasMeter1, store, api, &querier, maxGas)
+	res, cost, err := Instantiate(cache, id, params, msg, &igasMeter1, store, api, &querier, maxGas, CallOptions{})
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
 
@@ -247,7 +780,7 @@ func TestHandleStorageLoop(t *testing.T) {
 	params, err = json.Marshal(mockEnv("fred"))
 	require.NoError(t, err)
 	start := time.Now()
-	res, cost, err = Handle(cache, id, params, []byte(`{"storage_loop":{}}`), &igasMeter2, store, api, &querier, maxGas)
+	res, cost, err = Handle(cache, id, params, []byte(`{"storage_loop":{}}`), &igasMeter2, store, api, &querier, maxGas, CallOptions{})
 	diff := time.Now().Sub(start)
 	require.Error(t, err)
 	t.Logf("StorageLoop Time (%d gas): %s\n", cost, diff)
@@ -276,7 +809,7 @@ func TestHandleUserErrorsInApiCalls(t *testing.T) {
 
 	defaultApi := NewMockAPI()
 	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
-	res, _, err := Instantiate(cache, id, params, msg, &igasMeter1, store, defaultApi, &querier, maxGas)
+	res, _, err := Instantiate(cache, id, params, msg, &igasMeter1, store, defaultApi, &querier, maxGas, CallOptions{})
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
 
@@ -286,11 +819,145 @@ func TestHandleUserErrorsInApiCalls(t *testing.T) {
 	params, err = json.Marshal(mockEnv("fred"))
 	require.NoError(t, err)
 	failingApi := NewMockFailureAPI()
-	res, _, err = Handle(cache, id, params, []byte(`{"user_errors_in_api_calls":{}}`), &igasMeter2, store, failingApi, &querier, maxGas)
+	res, _, err = Handle(cache, id, params, []byte(`{"user_errors_in_api_calls":{}}`), &igasMeter2, store, failingApi, &querier, maxGas, CallOptions{})
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
 }
 
+// denyingStore denies all calls of one sender
+type denyingStore struct {
+	*Lookup
+	sender string
+}
+
+func (s denyingStore) AuthorizeCall(codeID []byte, sender string, entryPoint string) error {
+	if sender == s.sender {
+		return fmt.Errorf("%s may not call %s", sender, entryPoint)
+	}
+	return nil
+}
+
+func TestHandleDeniedByTheStore(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+	id := createTestContract(t, cache)
+
+	gasMeter := NewMockGasMeter(100000000)
+	igasMeter := GasMeter(gasMeter)
+	store := denyingStore{Lookup: NewLookup(gasMeter), sender: "mallory"}
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+	params, err := json.Marshal(mockEnv("creator"))
+	require.NoError(t, err)
+	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
+	_, _, err = Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+
+	params, err = json.Marshal(mockEnv("mallory"))
+	require.NoError(t, err)
+	_, _, err = Handle(cache, id, params, []byte(`{"release":{}}`), &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.IsType(t, types.CallDeniedError{}, err)
+	require.Equal(t, "Call of handle denied: mallory may not call handle", err.Error())
+}
+
+// recordingStore records the results of the calls on it
+type recordingStore struct {
+	*Lookup
+	results *[]string
+}
+
+func (s recordingStore) CallResult(codeID []byte, entryPoint string, result []byte, plaintext bool) {
+	*s.results = append(*s.results, entryPoint)
+}
+
+func TestCallResultsAreReported(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+	id := createTestContract(t, cache)
+
+	gasMeter := NewMockGasMeter(100000000)
+	igasMeter := GasMeter(gasMeter)
+	var results []string
+	store := recordingStore{Lookup: NewLookup(gasMeter), results: &results}
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+	params, err := json.Marshal(mockEnv("creator"))
+	require.NoError(t, err)
+	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
+	_, _, err = Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+
+	params, err = json.Marshal(mockEnv("fred"))
+	require.NoError(t, err)
+	_, _, err = Handle(cache, id, params, []byte(`{"release":{}}`), &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+	require.Equal(t, []string{"init", "handle"}, results)
+}
+
+func TestHandleOfPausedCode(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+	id := createTestContract(t, cache)
+
+	gasMeter := NewMockGasMeter(100000000)
+	igasMeter := GasMeter(gasMeter)
+	store := NewLookup(gasMeter)
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+	params, err := json.Marshal(mockEnv("creator"))
+	require.NoError(t, err)
+	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
+	_, _, err = Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+
+	require.NoError(t, SetCodePaused(cache, id, true))
+	params, err = json.Marshal(mockEnv("fred"))
+	require.NoError(t, err)
+	_, _, err = Handle(cache, id, params, []byte(`{"release":{}}`), &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.IsType(t, types.ContractPausedError{}, err)
+
+	require.NoError(t, SetCodePaused(cache, id, false))
+	_, _, err = Handle(cache, id, params, []byte(`{"release":{}}`), &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+
+	require.Error(t, SetCodePaused(cache, []byte("too short"), true))
+}
+
+func TestInstantiateWithDeniedCapability(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+	id := createTestContract(t, cache)
+
+	gasMeter := NewMockGasMeter(100000000)
+	igasMeter := GasMeter(gasMeter)
+	store := NewLookup(gasMeter)
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+	params, err := json.Marshal(mockEnv("creator"))
+	require.NoError(t, err)
+	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
+
+	restrict := func(capabilities CodeCapabilities) error {
+		codes := map[string]CodeCapabilities{hex.EncodeToString(id): capabilities}
+		return SetConsensusParams(cache, ConsensusParams{CodeCapabilities: codes})
+	}
+
+	// init stores its config
+	require.NoError(t, restrict(CodeCapabilities{Deny: []Capability{CapabilityStorageWrite}}))
+	_, _, err = Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.IsType(t, types.CapabilityDeniedError{}, err)
+
+	require.NoError(t, restrict(CodeCapabilities{Deny: []Capability{CapabilityIterators}}))
+	_, _, err = Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+
+	require.NoError(t, restrict(CodeCapabilities{}))
+	both := CodeCapabilities{Allow: []Capability{CapabilityQueries}, Deny: []Capability{CapabilityIterators}}
+	require.Error(t, restrict(both))
+	codes := map[string]CodeCapabilities{"not hex": {}}
+	require.Error(t, SetConsensusParams(cache, ConsensusParams{CodeCapabilities: codes}))
+}
+
 func TestMigrate(t *testing.T) {
 	t.SkipNow()
 	cache, cleanup := withCache(t)
@@ -308,13 +975,13 @@ func TestMigrate(t *testing.T) {
 	require.NoError(t, err)
 	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
 
-	res, _, err := Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000)
+	res, _, err := Instantiate(cache, id, params, msg, &igasMeter, store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
 
 	// verifier is fred
 	query := []byte(`{"verifier":{}}`)
-	data, _, err := Query(cache, id, query, &igasMeter, store, api, &querier, 100000000)
+	data, _, err := Query(cache, id, query, &igasMeter, store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	var qres types.QueryResponse
 	err = json.Unmarshal(data, &qres)
@@ -326,11 +993,11 @@ func TestMigrate(t *testing.T) {
 	// we use the same code blob as we are testing hackatom self-migration
 	params, err = json.Marshal(mockEnv("fred"))
 	require.NoError(t, err)
-	res, _, err = Migrate(cache, id, params, []byte(`{"verifier":"alice"}`), &igasMeter, store, api, &querier, 100000000)
+	res, _, err = Migrate(cache, id, params, []byte(`{"verifier":"alice"}`), &igasMeter, store, api, &querier, 100000000, nil, MigrateAuth{Admin: "fred"}, CallOptions{})
 	require.NoError(t, err)
 
 	// should update verifier to alice
-	data, _, err = Query(cache, id, query, &igasMeter, store, api, &querier, 100000000)
+	data, _, err = Query(cache, id, query, &igasMeter, store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	var qres2 types.QueryResponse
 	err = json.Unmarshal(data, &qres2)
@@ -339,6 +1006,27 @@ func TestMigrate(t *testing.T) {
 	require.Equal(t, string(qres2.Ok), `{"verifier":"alice"}`)
 }
 
+func TestMigrateLeavesTheAuthToTheEnclave(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	gasMeter := NewMockGasMeter(100000000)
+	igasMeter := GasMeter(gasMeter)
+	store := NewLookup(gasMeter)
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+	params, err := json.Marshal(mockEnv("fred"))
+	require.NoError(t, err)
+	msg := []byte(`{"verifier":"alice"}`)
+
+	// the library does not compare the sender with the admin, so the missing code is what fails
+	for _, auth := range []MigrateAuth{{Admin: "bob"}, {}} {
+		_, _, err = Migrate(cache, make([]byte, 32), params, msg, &igasMeter, store, api, &querier, 100000000, nil, auth, CallOptions{})
+		require.Error(t, err)
+		require.NotContains(t, err.Error(), "Unauthorized")
+	}
+}
+
 func TestMultipleInstances(t *testing.T) {
 	t.SkipNow()
 	cache, cleanup := withCache(t)
@@ -354,7 +1042,7 @@ func TestMultipleInstances(t *testing.T) {
 	params, err := json.Marshal(mockEnv("regen"))
 	require.NoError(t, err)
 	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
-	res, cost, err := Instantiate(cache, id, params, msg, &igasMeter1, store1, api, &querier, 100000000)
+	res, cost, err := Instantiate(cache, id, params, msg, &igasMeter1, store1, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
 	// we now count wasm gas charges and db writes
@@ -367,7 +1055,7 @@ func TestMultipleInstances(t *testing.T) {
 	params, err = json.Marshal(mockEnv("chorus"))
 	require.NoError(t, err)
 	msg = []byte(`{"verifier": "mary", "beneficiary": "sue"}`)
-	res, cost, err = Instantiate(cache, id, params, msg, &igasMeter2, store2, api, &querier, 100000000)
+	res, cost, err = Instantiate(cache, id, params, msg, &igasMeter2, store2, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
 	assert.Equal(t, uint64(0x1093d), cost)
@@ -431,7 +1119,7 @@ func exec(t *testing.T, cache Cache, id []byte, signer types.HumanAddress, store
 	igasMeter := GasMeter(gasMeter)
 	params, err := json.Marshal(mockEnv(signer))
 	require.NoError(t, err)
-	res, cost, err := Handle(cache, id, params, []byte(`{"release":{}}`), &igasMeter, store, api, &querier, 100000000)
+	res, cost, err := Handle(cache, id, params, []byte(`{"release":{}}`), &igasMeter, store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	assert.Equal(t, gasExpected, cost)
 
@@ -456,7 +1144,7 @@ func TestQuery(t *testing.T) {
 	params, err := json.Marshal(mockEnv("creator"))
 	require.NoError(t, err)
 	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
-	_, _, err = Instantiate(cache, id, params, msg, &igasMeter1, store, api, &querier, 100000000)
+	_, _, err = Instantiate(cache, id, params, msg, &igasMeter1, store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 
 	// invalid query
@@ -464,7 +1152,7 @@ func TestQuery(t *testing.T) {
 	igasMeter2 := GasMeter(gasMeter2)
 	store.SetGasMeter(gasMeter2)
 	query := []byte(`{"Raw":{"val":"config"}}`)
-	data, _, err := Query(cache, id, query, &igasMeter2, store, api, &querier, 100000000)
+	data, _, err := Query(cache, id, query, &igasMeter2, store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	var badResp types.QueryResponse
 	err = json.Unmarshal(data, &badResp)
@@ -481,7 +1169,7 @@ func TestQuery(t *testing.T) {
 	igasMeter3 := GasMeter(gasMeter3)
 	store.SetGasMeter(gasMeter3)
 	query = []byte(`{"verifier":{}}`)
-	data, _, err = Query(cache, id, query, &igasMeter3, store, api, &querier, 100000000)
+	data, _, err = Query(cache, id, query, &igasMeter3, store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	var qres types.QueryResponse
 	err = json.Unmarshal(data, &qres)
@@ -490,6 +1178,42 @@ func TestQuery(t *testing.T) {
 	require.Equal(t, string(qres.Ok), `{"verifier":"fred"}`)
 }
 
+func TestQueryUnmetered(t *testing.T) {
+	t.SkipNow()
+	cache, cleanup := withCacheOptions(t, CacheOptions{AllowUnmeteredQueries: true})
+	defer cleanup()
+	id := createTestContract(t, cache)
+
+	gasMeter1 := NewMockGasMeter(100000000)
+	igasMeter1 := GasMeter(gasMeter1)
+	store := NewLookup(gasMeter1)
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{types.NewCoin(100, "ATOM")})
+	params, err := json.Marshal(mockEnv("creator"))
+	require.NoError(t, err)
+	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
+	_, _, err = Instantiate(cache, id, params, msg, &igasMeter1, store, api, &querier, 100000000, CallOptions{})
+	require.NoError(t, err)
+
+	gasMeter2 := NewMockGasMeter(100000000)
+	igasMeter2 := GasMeter(gasMeter2)
+	store.SetGasMeter(gasMeter2)
+	query := []byte(`{"verifier":{}}`)
+	data, err := QueryUnmetered(cache, id, query, &igasMeter2, store, api, &querier, CallOptions{})
+	require.NoError(t, err)
+	var qres types.QueryResponse
+	err = json.Unmarshal(data, &qres)
+	require.NoError(t, err)
+	require.Nil(t, qres.Err, "%v", qres.Err)
+	require.Equal(t, string(qres.Ok), `{"verifier":"fred"}`)
+
+	// caches must allow unmetered queries
+	metered, cleanupMetered := withCache(t)
+	defer cleanupMetered()
+	_, err = QueryUnmetered(metered, id, query, &igasMeter2, store, api, &querier, CallOptions{})
+	require.IsType(t, types.CallDeniedError{}, err)
+}
+
 func TestQueueIterator(t *testing.T) {
 	t.SkipNow()
 	cache, cleanup := withCache(t)
@@ -505,27 +1229,27 @@ func TestQueueIterator(t *testing.T) {
 	require.NoError(t, err)
 	msg := []byte(`{}`)
 
-	res, _, err := Instantiate(cache, id, params, msg, &gasMeter1, &store, api, &querier, 100000000)
+	res, _, err := Instantiate(cache, id, params, msg, &gasMeter1, &store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
 
 	// push 17
 	gasMeter2 := NewMockGasMeter(100000000)
 	push := []byte(`{"enqueue":{"value":17}}`)
-	res, _, err = Handle(cache, id, params, push, &gasMeter2, &store, api, &querier, 100000000)
+	res, _, err = Handle(cache, id, params, push, &gasMeter2, &store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
 	// push 22
 	gasMeter3 := NewMockGasMeter(100000000)
 	push = []byte(`{"enqueue":{"value":22}}`)
-	res, _, err = Handle(cache, id, params, push, &gasMeter3, &store, api, &querier, 100000000)
+	res, _, err = Handle(cache, id, params, push, &gasMeter3, &store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
 
 	// query the sum
 	gasMeter4 := NewMockGasMeter(100000000)
 	query := []byte(`{"sum":{}}`)
-	data, _, err := Query(cache, id, query, &gasMeter4, &store, api, &querier, 100000000)
+	data, _, err := Query(cache, id, query, &gasMeter4, &store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	var qres types.QueryResponse
 	err = json.Unmarshal(data, &qres)
@@ -536,7 +1260,7 @@ func TestQueueIterator(t *testing.T) {
 	// query reduce (multiple iterators at once)
 	gasMeter5 := NewMockGasMeter(100000000)
 	query = []byte(`{"reducer":{}}`)
-	data, _, err = Query(cache, id, query, &gasMeter5, &store, api, &querier, 100000000)
+	data, _, err = Query(cache, id, query, &gasMeter5, &store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	var reduced types.QueryResponse
 	err = json.Unmarshal(data, &reduced)
@@ -562,7 +1286,7 @@ func TestHackatomQuerier(t *testing.T) {
 	// make a valid query to the other address
 	query := []byte(`{"other_balance":{"address":"foobar"}}`)
 	// TODO The query happens before the contract is initialized. How is this legal?
-	data, _, err := Query(cache, id, query, &igasMeter, store, api, &querier, 100000000)
+	data, _, err := Query(cache, id, query, &igasMeter, store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	var qres types.QueryResponse
 	err = json.Unmarshal(data, &qres)
@@ -593,7 +1317,7 @@ func TestCustomReflectQuerier(t *testing.T) {
 
 	// make a valid query to the other address
 	query := []byte(`{"reflect_custom":{"text":"small Frys :)"}}`)
-	data, _, err := Query(cache, id, query, &igasMeter, store, api, &querier, 100000000)
+	data, _, err := Query(cache, id, query, &igasMeter, store, api, &querier, 100000000, CallOptions{})
 	require.NoError(t, err)
 	var qres types.QueryResponse
 	err = json.Unmarshal(data, &qres)
@@ -604,3 +1328,60 @@ func TestCustomReflectQuerier(t *testing.T) {
 	err = json.Unmarshal(qres.Ok, &response)
 	require.Equal(t, response.Msg, "SMALL FRYS :)")
 }
+
+func TestDeriveContractAddress(t *testing.T) {
+	checksum, err := hex.DecodeString("13a1fc994cc6d1c81b746ee0c0ff6f90043875e0bf1d9be6b7d779fc978dc2a5")
+	require.NoError(t, err)
+	creator, err := hex.DecodeString("9999999999aaaaaaaaaabbbbbbbbbbcccccccccc")
+	require.NoError(t, err)
+
+	addr, err := DeriveContractAddress(checksum, creator, []byte("a"), nil)
+	require.NoError(t, err)
+	require.Equal(t, "5e865d3e45ad3e961f77fd77d46543417ced44d924dc3e079b5415ff6775f847", hex.EncodeToString(addr))
+
+	withMsg, err := DeriveContractAddress(checksum, creator, []byte("a"), []byte(`{}`))
+	require.NoError(t, err)
+	require.NotEqual(t, addr, withMsg)
+
+	_, err = DeriveContractAddress(checksum, creator, nil, nil)
+	require.Error(t, err)
+}
+
+func TestEncryptMsgAndDecryptResponse(t *testing.T) {
+	nodePubKey := bytes.Repeat([]byte{7}, 32)
+	secret := bytes.Repeat([]byte{9}, 32)
+	nonce := bytes.Repeat([]byte{1}, 32)
+	msg := []byte(`{"release":{}}`)
+
+	envelope, err := EncryptMsg(nodePubKey, secret, nonce, msg)
+	require.NoError(t, err)
+	require.Equal(t, nonce, envelope[:32])
+	ciphertext := envelope[64:]
+	assert.NotEqual(t, msg, ciphertext)
+
+	// responses are encrypted with the same key as the message
+	plaintext, err := DecryptResponse(nodePubKey, secret, envelope, ciphertext)
+	require.NoError(t, err)
+	require.Equal(t, msg, plaintext)
+
+	_, err = EncryptMsg(nodePubKey[:31], secret, nonce, msg)
+	require.Error(t, err)
+}
+
+func TestDecryptQueryResponse(t *testing.T) {
+	nodePubKey := bytes.Repeat([]byte{7}, 32)
+	secret := bytes.Repeat([]byte{9}, 32)
+	nonce := bytes.Repeat([]byte{1}, 32)
+	answer := []byte(`{"balance":{"amount":"42"}}`)
+
+	// the enclave encrypts the base64 of the answer of a query
+	encoded := []byte(base64.StdEncoding.EncodeToString(answer))
+	envelope, err := EncryptMsg(nodePubKey, secret, nonce, encoded)
+	require.NoError(t, err)
+	plaintext, err := DecryptQueryResponse(nodePubKey, secret, nonce, envelope[64:])
+	require.NoError(t, err)
+	require.Equal(t, answer, plaintext)
+
+	_, err = DecryptQueryResponse(nodePubKey, secret, bytes.Repeat([]byte{2}, 32), envelope[64:])
+	require.Error(t, err)
+}
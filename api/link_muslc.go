@@ -2,5 +2,8 @@
 
 package api
 
-// #cgo LDFLAGS: -Wl,-rpath,${SRCDIR} -L${SRCDIR} -lgo_cosmwasm_muslc
+// The static library bundles the enclave bridge, but the SGX runtime is only available as shared libraries,
+// so these are the only dynamic dependencies left.
+
+// #cgo LDFLAGS: -Wl,-rpath,${SRCDIR} -L${SRCDIR} -lgo_cosmwasm_muslc -lsgx_urts -lsgx_uae_service -lm
 import "C"
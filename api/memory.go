@@ -60,6 +60,21 @@ func receiveVector(b C.Buffer) []byte {
 	return res
 }
 
+// isShared tells whether b was written into the cache's shared region instead of being allocated by Rust
+func isShared(b C.Buffer) bool {
+	return b.cap == 0 && b.len > 0
+}
+
+// receiveResult is like receiveVector, but also accepts results written into the cache's shared region
+func receiveResult(cache Cache, b C.Buffer) []byte {
+	if !isShared(b) {
+		return receiveVector(b)
+	}
+	res := C.GoBytes(unsafe.Pointer(b.ptr), cint(b.len))
+	C.release_shared(cache.ptr, b)
+	return res
+}
+
 // Copy the contents of a vector that was allocated on the Rust side.
 // Unlike receiveVector, we do not free it, because it will be manually
 // freed on the Rust side after control returns to it.
@@ -0,0 +1,35 @@
+package api
+
+import "encoding/json"
+
+// ModuleFormat is what the compiled modules of a cache depend on besides their wasm. Modules compiled in
+// another format are removed when the cache is created, and compiled again on their next use.
+type ModuleFormat struct {
+	Version uint32 `json:"version"`
+	// LibraryVersion is the version of the library, which pins the VM and its compiler
+	LibraryVersion string `json:"library_version"`
+	// Backend is "sgx" or "vanilla"
+	Backend string `json:"backend"`
+	// Enclave is the hex MRENCLAVE of the enclave compiling the modules, empty without SGX
+	Enclave string `json:"enclave,omitempty"`
+	// CanonicalizeNaNs is whether the compiled code canonicalizes NaNs, see ConsensusParams.FloatPolicy
+	CanonicalizeNaNs bool `json:"canonicalize_nans"`
+}
+
+// ModuleStats are the counters of the compiled modules of a cache
+type ModuleStats struct {
+	Format ModuleFormat `json:"format"`
+	// Mmap is whether the VM maps the modules from disk, see CacheOptions.MmapModules
+	Mmap bool `json:"mmap"`
+	// Invalidated is the number of modules removed when the cache was created, as they were compiled in
+	// another format
+	Invalidated uint64 `json:"invalidated"`
+	// Recompiled is how many of the removed modules were compiled again since
+	Recompiled uint64 `json:"recompiled"`
+}
+
+func parseModuleStats(data []byte) (ModuleStats, error) {
+	var stats ModuleStats
+	err := json.Unmarshal(data, &stats)
+	return stats, err
+}
@@ -0,0 +1,31 @@
+package api
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestParseModuleStats(t *testing.T) {
+	data := []byte(`{"format":{"version":1,"library_version":"0.10.0","backend":"sgx","enclave":"ab01",` +
+		`"canonicalize_nans":true},"mmap":true,"invalidated":12,"recompiled":3}`)
+	stats, err := parseModuleStats(data)
+	require.NoError(t, err)
+	require.Equal(t, ModuleStats{
+		Format: ModuleFormat{
+			Version:          1,
+			LibraryVersion:   "0.10.0",
+			Backend:          "sgx",
+			Enclave:          "ab01",
+			CanonicalizeNaNs: true,
+		},
+		Mmap:        true,
+		Invalidated: 12,
+		Recompiled:  3,
+	}, stats)
+
+	stats, err = parseModuleStats([]byte(`{"format":{"version":1,"library_version":"0.10.0",` +
+		`"backend":"vanilla","enclave":null,"canonicalize_nans":false},"invalidated":0,"recompiled":0}`))
+	require.NoError(t, err)
+	require.Equal(t, "", stats.Format.Enclave)
+}
@@ -0,0 +1,52 @@
+package api
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+type namespacedLookup struct {
+	*Lookup
+	parent *Lookup
+	prefix []byte
+}
+
+func (n namespacedLookup) Namespace() (KVStore, []byte) {
+	return n.parent, n.prefix
+}
+
+func TestBuildDBStateUsesNamespace(t *testing.T) {
+	parent := NewLookup(NewMockGasMeter(100000000))
+	store := namespacedLookup{NewLookup(NewMockGasMeter(100000000)), parent, []byte("contract")}
+
+	state := buildDBState(store, 1)
+	require.Equal(t, parent, state.Store)
+	require.Equal(t, []byte("contract"), state.namespace)
+
+	// without a prefix, there is nothing to enforce
+	store.prefix = nil
+	state = buildDBState(store, 1)
+	require.Equal(t, store, state.Store)
+	require.Nil(t, state.namespace)
+}
+
+func TestResolvedStatesLiveUntilTheEndOfTheirFrame(t *testing.T) {
+	parent := NewLookup(NewMockGasMeter(100000000))
+	store := namespacedLookup{NewLookup(NewMockGasMeter(100000000)), parent, []byte("contract")}
+	counter := startContract()
+
+	state := buildDBState(store, counter)
+	ptr, namespace := storeResolvedState(&state)
+	require.True(t, dbState(ptr) == &state)
+	require.Equal(t, []byte("contract"), receiveSlice(namespace))
+	other := buildDBState(parent, counter)
+	otherPtr, _ := storeResolvedState(&other)
+	require.True(t, dbState(otherPtr) == &other)
+	require.NotEqual(t, ptr, otherPtr)
+
+	endContract(counter)
+	require.Nil(t, dbState(ptr))
+	require.Nil(t, dbState(otherPtr))
+	require.NotContains(t, resolvedFrames, counter)
+}
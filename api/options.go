@@ -0,0 +1,224 @@
+package api
+
+import (
+	"encoding/json"
+	"time"
+)
+
+// CacheOptions are node level settings passed to the Rust side when creating a cache. The settings deciding the
+// results of contract calls are ConsensusParams instead. Zero values select the defaults of the Rust side.
+type CacheOptions struct {
+	// ShadowExecution runs plaintext calls in the upstream VM too and logs divergences from the enclave,
+	// see GetShadowStats. It is meant for canary nodes and requires a library built with the shadow feature.
+	ShadowExecution bool `json:"shadow_execution,omitempty"`
+	// GasSyncInterval makes a running call report the gas charged through its callbacks to the GasMeter
+	// whenever it grew by this much, if the GasMeter implements GasProgressMeter. 0 disables the reports.
+	GasSyncInterval uint64 `json:"gas_sync_interval,omitempty"`
+	// VerifyWasmOnLoad hashes the stored wasm of a code against its checksum before it is loaded, so a file
+	// corrupted or tampered with on disk fails the call instead of running. Verified files are only hashed again
+	// when their size or modification time changes.
+	VerifyWasmOnLoad bool `json:"verify_wasm_on_load,omitempty"`
+	// AllowUnmeteredQueries lets QueryUnmetered run queries without a gas limit, for tooling on archive nodes.
+	// Validators and public query nodes must leave it unset, as an unmetered query runs for as long as it likes.
+	AllowUnmeteredQueries bool `json:"allow_unmetered_queries,omitempty"`
+	// WasmCosts replaces the gas charged per wasm instruction, until a schedule of RegisterGasSchedule
+	// activates. Its version is reported as GasReport.ScheduleVersion. All nodes of a chain must use the same
+	// costs. Libraries built without SGX fail to create a cache with costs other than the defaults.
+	WasmCosts *WasmCosts `json:"wasm_costs,omitempty"`
+	// MmapModules lets the VM map compiled modules from disk instead of deserializing them into memory, which
+	// speeds up the first call of each code after a restart and saves memory on nodes with many codes. Backends
+	// which cannot map modules keep deserializing, see ModuleStats.Mmap.
+	MmapModules bool `json:"mmap_modules,omitempty"`
+	// WarmUpModules is how many of the codes instantiated most recently before a restart are loaded or compiled
+	// in the background when the cache is created, so the first block does not stall on them. At most 256, 0
+	// disables the warm-up.
+	WarmUpModules uint32 `json:"warm_up_modules,omitempty"`
+	// QueryCacheSize is how many bytes of query results QueryCached, QueryByAlias and QueryBatch reuse for
+	// queries with the same contract, code and message at the same height. 0 disables the cache.
+	QueryCacheSize uint64 `json:"query_cache_size,omitempty"`
+}
+
+// ConsensusParams are the settings deciding the gas, errors and results of contract calls, which must be the same
+// on every node. The keeper reads them from the chain's state and passes them to SetConsensusParams once the cache
+// is created and whenever governance changes them. Zero values select the defaults of the Rust side.
+type ConsensusParams struct {
+	// MaxCallDepth is the deepest nesting of contract calls that is executed, 10 by default. The outermost call
+	// has depth 0.
+	MaxCallDepth uint32 `json:"max_call_depth,omitempty"`
+	// MaxResponseSize is the largest result of a handle or query call, in bytes, passed back from a contract
+	MaxResponseSize uint64 `json:"max_response_size,omitempty"`
+	// GasPolicy decides how the gas reported by the callbacks is charged
+	GasPolicy GasPolicy `json:"gas_policy,omitempty"`
+	// FallbackGas is the gas charged for callbacks which do not meter themselves, depending on GasPolicy.
+	// Unset costs keep the defaults of the Rust side.
+	FallbackGas *FallbackGasSchedule `json:"fallback_gas,omitempty"`
+	// MaxMemoryPages is the most memory a contract instance may have, in 64 KiB pages, 256 (16 MiB) by default.
+	// Codes declaring more initial memory fail to instantiate.
+	MaxMemoryPages uint32 `json:"max_memory_pages,omitempty"`
+	// NormalizeEvents checks the log attributes of instantiate, handle and migrate results and returns them in
+	// canonical form: repeated attributes are dropped and object fields sorted. Invalid attributes fail the call
+	// with types.InvalidEventsError.
+	NormalizeEvents bool `json:"normalize_events,omitempty"`
+	// EventLimits caps the events of instantiate, handle and migrate results. Results over a cap fail the call
+	// with types.EventLimitExceededError.
+	EventLimits *EventLimits `json:"event_limits,omitempty"`
+	// SystemGasLimit is the gas budget of a call of HandleSystem, 10^10 by default
+	SystemGasLimit uint64 `json:"system_gas_limit,omitempty"`
+	// MaxOpenIterators caps the storage iterators one contract execution may have open at once, 0 for no cap.
+	// Calls over the cap fail with types.IteratorLimitExceededError.
+	MaxOpenIterators uint32 `json:"max_open_iterators,omitempty"`
+	// MaxMsgSize is the largest message of a call, in bytes, 0 for no limit. Larger messages fail the call with
+	// types.InvalidMsgError before the contract runs.
+	MaxMsgSize uint64 `json:"max_msg_size,omitempty"`
+	// FloatPolicy decides whether contracts may use floats. Codes it rejects fail to be stored, and codes stored
+	// before fail to be instantiated. Changing whether NaNs are canonicalized makes every code compile again.
+	FloatPolicy FloatPolicy `json:"float_policy,omitempty"`
+	// CodeCapabilities restricts the host capabilities of the contracts of codes by their hex encoded checksum,
+	// e.g. to contain codes uploaded without governance. Calls using a denied capability fail with
+	// types.CapabilityDeniedError, internal calls and nested queries included.
+	CodeCapabilities map[string]CodeCapabilities `json:"code_capabilities,omitempty"`
+}
+
+// EventLimits are caps on the events of a contract result. Zero values disable a cap.
+// Every attribute with the key "contract_address" starts a new event.
+type EventLimits struct {
+	// MaxEvents is how many events a result may have
+	MaxEvents uint32 `json:"max_events,omitempty"`
+	// MaxAttributes is how many attributes a result may have, in all its events
+	MaxAttributes uint32 `json:"max_attributes,omitempty"`
+	// MaxKeyLen is the longest key of an attribute, in bytes
+	MaxKeyLen uint32 `json:"max_key_len,omitempty"`
+	// MaxValueLen is the longest value of an attribute, in bytes
+	MaxValueLen uint32 `json:"max_value_len,omitempty"`
+}
+
+// WasmCosts is the gas charged per wasm instruction
+type WasmCosts struct {
+	// Default is the cost of the instructions without a cost in Instructions, 1 if unset
+	Default uint64 `json:"default,omitempty"`
+	// Instructions are costs by instruction name in the text format, like "i64.div_u". Unknown names fail the
+	// creation of the cache.
+	Instructions map[string]uint64 `json:"instructions,omitempty"`
+}
+
+// gasSchedule is the argument of RegisterGasSchedule
+type gasSchedule struct {
+	ActivationHeight uint64    `json:"activation_height"`
+	Costs            WasmCosts `json:"costs"`
+}
+
+// GasPolicy decides how the gas reported by the callbacks is charged
+type GasPolicy string
+
+const (
+	// GasPolicyTrustGo charges exactly the gas reported by the callbacks. This is the default.
+	GasPolicyTrustGo GasPolicy = "trust_go"
+	// GasPolicyFallback charges from the fallback schedule whenever a callback reports zero gas
+	GasPolicyFallback GasPolicy = "fallback"
+	// GasPolicyMaxOfBoth charges the larger of the reported gas and the fallback schedule
+	GasPolicyMaxOfBoth GasPolicy = "max_of_both"
+)
+
+// FloatPolicy decides whether contracts may use floats, whose NaNs can differ between machines
+type FloatPolicy string
+
+const (
+	// FloatPolicyReject rejects codes with any float value or operation
+	FloatPolicyReject FloatPolicy = "reject"
+	// FloatPolicyCanonicalizeNaN allows floats, with the VM canonicalizing the NaNs float operations produce.
+	// Libraries built without SGX fail to set this policy.
+	FloatPolicyCanonicalizeNaN FloatPolicy = "canonicalize_nan"
+	// FloatPolicyAllow allows floats as they are. This is the default.
+	FloatPolicyAllow FloatPolicy = "allow"
+)
+
+// FallbackGasSchedule is the gas charged per callback by the fallback gas policies
+type FallbackGasSchedule struct {
+	Read         uint64 `json:"read,omitempty"`
+	Write        uint64 `json:"write,omitempty"`
+	Remove       uint64 `json:"remove,omitempty"`
+	IteratorStep uint64 `json:"iterator_step,omitempty"`
+	Query        uint64 `json:"query,omitempty"`
+	APICall      uint64 `json:"api_call,omitempty"`
+}
+
+// EnclaveCallPolicy bounds the enclave calls which depend on services outside the node: CreateAttestationReport,
+// GetEncryptedSeed and InitBootstrap. See SetEnclaveCallPolicy.
+type EnclaveCallPolicy struct {
+	// Timeout fails a call with types.TimeoutError if it has not returned by then. 0 waits forever.
+	Timeout time.Duration
+	// Retries is how often a call failing in the SGX SDK, e.g. because the AESM service is unavailable, is retried.
+	// Errors returned by the enclave are not retried.
+	Retries uint32
+	// Backoff is the wait before the first retry. It doubles with every retry.
+	Backoff time.Duration
+}
+
+func (p EnclaveCallPolicy) marshal() ([]byte, error) {
+	return json.Marshal(struct {
+		TimeoutMs uint64 `json:"timeout_ms"`
+		Retries   uint32 `json:"retries"`
+		BackoffMs uint64 `json:"backoff_ms"`
+	}{
+		TimeoutMs: uint64(p.Timeout / time.Millisecond),
+		Retries:   p.Retries,
+		BackoffMs: uint64(p.Backoff / time.Millisecond),
+	})
+}
+
+// ExecutorOptions set how the calls of HandleAsync and QueryAsync are queued, see SetExecutorOptions.
+type ExecutorOptions struct {
+	// MaxQueued is how many calls may wait for the worker. 0 disables the limit.
+	MaxQueued uint32 `json:"max_queued"`
+}
+
+// QueryLimits protect the enclave from floods of queries, see SetQueryLimits. Zero values disable a limit.
+type QueryLimits struct {
+	// PerSecond is how many queries may start per second on average
+	PerSecond uint32 `json:"per_second"`
+	// Burst is how many queries may start at once after a quiet period. 0 allows PerSecond.
+	Burst uint32 `json:"burst"`
+	// MaxConcurrent is how many queries may wait for the cache or run at the same time. The cache runs one call
+	// at a time, so this bounds the queue of queries behind it, including the queued calls of QueryAsync.
+	MaxConcurrent uint32 `json:"max_concurrent"`
+}
+
+// MigrateAuth tells Migrate why a migration may run. The enclave runs it if Governance is set, or if the sender
+// of the migration is the admin it recorded for the contract, which must be the Admin. Contracts without an
+// admin can only be migrated by governance.
+type MigrateAuth struct {
+	// Admin is the admin the keeper stores for the contract, or empty if it has none
+	Admin string `json:"admin,omitempty"`
+	// Governance is set if a governance proposal runs the migration
+	Governance bool `json:"governance"`
+}
+
+// CallOptions are the settings of a contract call besides its code, message, state and gas limit. The zero value
+// is a call from a transaction at the latest state, without a gas report or correlation id.
+type CallOptions struct {
+	// CallDepth is 0 for calls coming from a transaction, and n + 1 for messages dispatched by a contract called at
+	// depth n. Calls deeper than ConsensusParams.MaxCallDepth fail with types.CallDepthExceededError. Calls which
+	// always run at depth 0, e.g. HandleSystem, QueryCached, QueryBatch and QueryByAlias, ignore it.
+	CallDepth uint32
+	// Block is the block whose state the store of a query serves, e.g. a past height for historical queries, or zero
+	// for the latest state. It is passed to the store and the querier if they implement BlockContextStore and
+	// BlockContextQuerier, so the queries the contract makes read the same state as its storage. Only queries use it.
+	Block BlockContext
+	// GasReport is filled in with the breakdown of the gas the call used, if it is not nil
+	GasReport *GasReport
+	// CallID is an optional correlation id of the call, e.g. the transaction hash, which the library adds to its log
+	// lines, the enclave's log lines and the crash reports of the call. It must be printable ASCII without spaces,
+	// and at most 128 bytes long.
+	CallID string
+}
+
+// EnclaveLogOptions configure the buffer the enclave's log lines are kept in, see InitEnclaveLog.
+// Zero values select the defaults of the Rust side.
+type EnclaveLogOptions struct {
+	// Level is the least severe level kept, one of "error", "warn", "info" (the default), "debug" and "trace"
+	Level string `json:"level,omitempty"`
+	// MaxLines is how many lines the buffer holds until the oldest are dropped, 1000 by default
+	MaxLines uint32 `json:"max_lines,omitempty"`
+	// MaxLineLen is the length in bytes longer lines are cut off at, 1024 by default
+	MaxLineLen uint32 `json:"max_line_len,omitempty"`
+}
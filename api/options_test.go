@@ -0,0 +1,175 @@
+package api
+
+import (
+	"encoding/json"
+	"testing"
+	"time"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestCacheOptionsJSON(t *testing.T) {
+	bz, err := json.Marshal(CacheOptions{})
+	require.NoError(t, err)
+	require.Equal(t, `{}`, string(bz))
+
+	bz, err = json.Marshal(CacheOptions{ShadowExecution: true})
+	require.NoError(t, err)
+	require.Equal(t, `{"shadow_execution":true}`, string(bz))
+
+	bz, err = json.Marshal(CacheOptions{GasSyncInterval: 100000})
+	require.NoError(t, err)
+	require.Equal(t, `{"gas_sync_interval":100000}`, string(bz))
+
+	bz, err = json.Marshal(CacheOptions{VerifyWasmOnLoad: true})
+	require.NoError(t, err)
+	require.Equal(t, `{"verify_wasm_on_load":true}`, string(bz))
+
+	bz, err = json.Marshal(CacheOptions{AllowUnmeteredQueries: true})
+	require.NoError(t, err)
+	require.Equal(t, `{"allow_unmetered_queries":true}`, string(bz))
+
+	bz, err = json.Marshal(CacheOptions{WasmCosts: &WasmCosts{Instructions: map[string]uint64{"i64.div_u": 40}}})
+	require.NoError(t, err)
+	require.Equal(t, `{"wasm_costs":{"instructions":{"i64.div_u":40}}}`, string(bz))
+
+	bz, err = json.Marshal(CacheOptions{MmapModules: true})
+	require.NoError(t, err)
+	require.Equal(t, `{"mmap_modules":true}`, string(bz))
+
+	bz, err = json.Marshal(CacheOptions{WarmUpModules: 64})
+	require.NoError(t, err)
+	require.Equal(t, `{"warm_up_modules":64}`, string(bz))
+
+	bz, err = json.Marshal(CacheOptions{QueryCacheSize: 1 << 20})
+	require.NoError(t, err)
+	require.Equal(t, `{"query_cache_size":1048576}`, string(bz))
+}
+
+func TestConsensusParamsJSON(t *testing.T) {
+	bz, err := json.Marshal(ConsensusParams{})
+	require.NoError(t, err)
+	require.Equal(t, `{}`, string(bz))
+
+	bz, err = json.Marshal(ConsensusParams{
+		GasPolicy:   GasPolicyFallback,
+		FallbackGas: &FallbackGasSchedule{Read: 5},
+	})
+	require.NoError(t, err)
+	require.Equal(t, `{"gas_policy":"fallback","fallback_gas":{"read":5}}`, string(bz))
+
+	bz, err = json.Marshal(ConsensusParams{MaxMemoryPages: 1024, NormalizeEvents: true})
+	require.NoError(t, err)
+	require.Equal(t, `{"max_memory_pages":1024,"normalize_events":true}`, string(bz))
+
+	bz, err = json.Marshal(ConsensusParams{EventLimits: &EventLimits{MaxAttributes: 100, MaxValueLen: 256}})
+	require.NoError(t, err)
+	require.Equal(t, `{"event_limits":{"max_attributes":100,"max_value_len":256}}`, string(bz))
+
+	bz, err = json.Marshal(ConsensusParams{FloatPolicy: FloatPolicyCanonicalizeNaN})
+	require.NoError(t, err)
+	require.Equal(t, `{"float_policy":"canonicalize_nan"}`, string(bz))
+}
+
+func TestEnclaveCallPolicyJSON(t *testing.T) {
+	bz, err := EnclaveCallPolicy{}.marshal()
+	require.NoError(t, err)
+	require.Equal(t, `{"timeout_ms":0,"retries":0,"backoff_ms":0}`, string(bz))
+
+	bz, err = EnclaveCallPolicy{Timeout: 30 * time.Second, Retries: 3, Backoff: 500 * time.Millisecond}.marshal()
+	require.NoError(t, err)
+	require.Equal(t, `{"timeout_ms":30000,"retries":3,"backoff_ms":500}`, string(bz))
+}
+
+func TestParseShadowStats(t *testing.T) {
+	stats, err := parseShadowStats([]byte(`{"enabled":true,"calls":7,"divergences":1,"skipped":2}`))
+	require.NoError(t, err)
+	require.Equal(t, ShadowStats{Enabled: true, Calls: 7, Divergences: 1, Skipped: 2}, stats)
+}
+
+func TestParseCodeStats(t *testing.T) {
+	stats, err := parseCodeStats([]byte(`[{"code_id":"aa","entry_point":"handle","calls":2,"failures":1,"failure_rate":0.5,"average_gas":1500,"average_duration_us":200,"total_duration_us":400,"max_open_iterators":3,"max_memory_pages":20,"host_calls":{"db_read":5,"query_chain":1}}]`))
+	require.NoError(t, err)
+	require.Equal(t, []CodeStats{{
+		CodeID:            "aa",
+		EntryPoint:        "handle",
+		Calls:             2,
+		Failures:          1,
+		FailureRate:       0.5,
+		AverageGas:        1500,
+		AverageDurationUs: 200,
+		TotalDurationUs:   400,
+		MaxOpenIterators:  3,
+		MaxMemoryPages:    20,
+		HostCalls:         HostCallCounts{DbRead: 5, QueryChain: 1},
+	}}, stats)
+}
+
+func TestParseEnclavePairing(t *testing.T) {
+	pairing, err := parseEnclavePairing([]byte(`{"expected":null,"actual":"ab01","matches":true}`))
+	require.NoError(t, err)
+	require.Equal(t, EnclavePairing{Actual: "ab01", Matches: true}, pairing)
+
+	pairing, err = parseEnclavePairing([]byte(`{"expected":"cd02","actual":"ab01","matches":false}`))
+	require.NoError(t, err)
+	require.Equal(t, EnclavePairing{Expected: "cd02", Actual: "ab01"}, pairing)
+}
+
+func TestParseCrashReport(t *testing.T) {
+	report, err := parseCrashReport(nil)
+	require.NoError(t, err)
+	require.Nil(t, report)
+
+	data := []byte(`{"time_ms":1600000000000,"call":"handle","code_id":"ab01","gas_limit":100000,"gas_used":1234,` +
+		`"gas_report":{"used_externally":50,"refundable":0,"reads":30,"writes":20,"iterator_steps":0,"queries":0,` +
+		`"api_calls":0,"fallback":0},"error":"Runtime error: unreachable","enclave_status":"ok",` +
+		`"log":["1600000000000 INFO  [go_cosmwasm] Loading <path>"]}`)
+	report, err = parseCrashReport(data)
+	require.NoError(t, err)
+	require.Equal(t, &CrashReport{
+		TimeMs:        1600000000000,
+		Call:          "handle",
+		CodeID:        "ab01",
+		GasLimit:      100000,
+		GasUsed:       1234,
+		GasReport:     GasReport{UsedExternally: 50, Reads: 30, Writes: 20},
+		Error:         "Runtime error: unreachable",
+		EnclaveStatus: "ok",
+		Log:           []string{"1600000000000 INFO  [go_cosmwasm] Loading <path>"},
+	}, report)
+}
+
+func TestQueryLimitsJSON(t *testing.T) {
+	bz, err := json.Marshal(QueryLimits{PerSecond: 100, MaxConcurrent: 8})
+	require.NoError(t, err)
+	require.Equal(t, `{"per_second":100,"burst":0,"max_concurrent":8}`, string(bz))
+}
+
+func TestMigrateAuthJSON(t *testing.T) {
+	bz, err := json.Marshal(MigrateAuth{Admin: "secret1admin"})
+	require.NoError(t, err)
+	require.Equal(t, `{"admin":"secret1admin","governance":false}`, string(bz))
+
+	bz, err = json.Marshal(MigrateAuth{Governance: true})
+	require.NoError(t, err)
+	require.Equal(t, `{"governance":true}`, string(bz))
+}
+
+func TestEnclaveLogOptionsJSON(t *testing.T) {
+	bz, err := json.Marshal(EnclaveLogOptions{})
+	require.NoError(t, err)
+	require.Equal(t, `{}`, string(bz))
+
+	bz, err = json.Marshal(EnclaveLogOptions{Level: "debug", MaxLines: 100})
+	require.NoError(t, err)
+	require.Equal(t, `{"level":"debug","max_lines":100}`, string(bz))
+}
+
+func TestParseEnclaveLogDrain(t *testing.T) {
+	drain, err := parseEnclaveLogDrain([]byte(`{"lines":[{"time_ms":1600000000000,"level":"warn","message":"slow ocall"}],"dropped":3}`))
+	require.NoError(t, err)
+	require.Equal(t, EnclaveLogDrain{
+		Lines:   []EnclaveLogLine{{TimeMs: 1600000000000, Level: "warn", Message: "slow ocall"}},
+		Dropped: 3,
+	}, drain)
+}
@@ -0,0 +1,23 @@
+package api
+
+import "encoding/json"
+
+// BatchQuery is one query of QueryBatch
+type BatchQuery struct {
+	ContractAddr string `json:"contract_addr"`
+	Msg          []byte `json:"msg"`
+}
+
+// BatchQueryResult is the outcome of one query of QueryBatch. Exactly one of Ok and Error is set.
+type BatchQueryResult struct {
+	// Ok is the result of the query, like the result of Query
+	Ok []byte `json:"ok,omitempty"`
+	// Error is why the query failed, e.g. because the contract is unknown or the batch ran out of gas
+	Error string `json:"error,omitempty"`
+}
+
+func parseBatchQueryResults(data []byte) ([]BatchQueryResult, error) {
+	var results []BatchQueryResult
+	err := json.Unmarshal(data, &results)
+	return results, err
+}
@@ -0,0 +1,18 @@
+package api
+
+import (
+	"encoding/json"
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestBatchQueryJSON(t *testing.T) {
+	bz, err := json.Marshal([]BatchQuery{{ContractAddr: "secret1a", Msg: []byte(`{}`)}})
+	require.NoError(t, err)
+	require.Equal(t, `[{"contract_addr":"secret1a","msg":"e30="}]`, string(bz))
+
+	results, err := parseBatchQueryResults([]byte(`[{"ok":"e30="},{"error":"Ran out of gas"}]`))
+	require.NoError(t, err)
+	require.Equal(t, []BatchQueryResult{{Ok: []byte(`{}`)}, {Error: "Ran out of gas"}}, results)
+}
@@ -0,0 +1,12 @@
+package api
+
+import "encoding/json"
+
+// QueryRoute is the contract and message template QueryByAlias queries under an alias
+type QueryRoute struct {
+	ContractAddr string `json:"contract_addr"`
+	// Checksum is the hex encoded code ID the contract must run, so a migrated contract fails the query
+	Checksum string `json:"checksum"`
+	// Msg is the JSON message, in which a string like "{{owner}}" is replaced by the argument owner
+	Msg json.RawMessage `json:"msg"`
+}
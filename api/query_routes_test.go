@@ -0,0 +1,22 @@
+package api
+
+import (
+	"encoding/json"
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestQueryRouteJSON(t *testing.T) {
+	routes := map[string]QueryRoute{
+		"balance": {
+			ContractAddr: "secret1token",
+			Checksum:     "2a2a",
+			Msg:          json.RawMessage(`{"balance":{"address":"{{owner}}"}}`),
+		},
+	}
+	bz, err := json.Marshal(routes)
+	require.NoError(t, err)
+	expected := `{"balance":{"contract_addr":"secret1token","checksum":"2a2a","msg":{"balance":{"address":"{{owner}}"}}}}`
+	require.Equal(t, expected, string(bz))
+}
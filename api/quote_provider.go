@@ -0,0 +1,218 @@
+package api
+
+import (
+	"encoding/hex"
+	"encoding/json"
+	"fmt"
+	"io/ioutil"
+	"net/http"
+	"net/url"
+	"strings"
+	"time"
+)
+
+// QuoteProviderKind selects where the collateral of DCAP quotes is fetched from
+type QuoteProviderKind string
+
+const (
+	// QuoteProviderIntelPCS fetches from the Provisioning Certification Service of Intel. URL may be left empty.
+	QuoteProviderIntelPCS QuoteProviderKind = "intel_pcs"
+	// QuoteProviderPCCS fetches from a Provisioning Certificate Caching Service run on premises, at URL
+	QuoteProviderPCCS QuoteProviderKind = "pccs"
+	// QuoteProviderAzure fetches from the collateral cache of Azure confidential computing, which the Azure DCAP
+	// client and Microsoft Azure Attestation use. URL may be left empty.
+	QuoteProviderAzure QuoteProviderKind = "azure"
+)
+
+const (
+	intelPCSURL = "https://api.trustedservices.intel.com"
+	azureURL    = "https://global.acccache.azure.net"
+	// certificationPath is the version 4 API all providers serve
+	certificationPath = "/sgx/certification/v4"
+	// intelRootCACRLURL is where Intel publishes the CRL of its SGX root CA, which PCS does not serve
+	intelRootCACRLURL = "https://certificates.trustedservices.intel.com/IntelSGXRootCA.der"
+)
+
+// QuoteProviderConfig selects and configures the quote provider, so the same binary works across hosting
+// environments. See NewQuoteProvider.
+type QuoteProviderConfig struct {
+	Provider QuoteProviderKind
+	// URL overrides the address of the provider. It is required for QuoteProviderPCCS.
+	URL string
+	// APIKey is sent as Ocp-Apim-Subscription-Key, as Intel PCS asks for
+	APIKey string
+	// CollateralTTL is how long fetched collateral is reused. 0 fetches it on every call.
+	CollateralTTL time.Duration
+	// MaxStale is how much longer than CollateralTTL collateral is reused while the provider fails
+	MaxStale time.Duration
+	// Cache keeps the collateral, e.g. on disk to survive restarts. Collateral is kept in memory if it is nil and
+	// CollateralTTL is set.
+	Cache *AttestationCache
+	// Timeout bounds each request to the provider, 30 seconds by default
+	Timeout time.Duration
+}
+
+// Collateral is what a verifier of a DCAP quote needs besides the quote: the TCB levels of the platform, the
+// identity of the quoting enclave and the revocation lists, each with the PEM chain of the certificates it is
+// signed with.
+type Collateral struct {
+	TCBInfo               json.RawMessage `json:"tcb_info"`
+	TCBInfoIssuerChain    string          `json:"tcb_info_issuer_chain"`
+	QEIdentity            json.RawMessage `json:"qe_identity"`
+	QEIdentityIssuerChain string          `json:"qe_identity_issuer_chain"`
+	PCKCRL                []byte          `json:"pck_crl"`
+	PCKCRLIssuerChain     string          `json:"pck_crl_issuer_chain"`
+	RootCACRL             []byte          `json:"root_ca_crl"`
+}
+
+// QuoteProvider fetches the collateral of DCAP quotes
+type QuoteProvider interface {
+	// GetCollateral returns the collateral of the platforms with fmspc, whose PCK certificates are issued by the
+	// CA ca, "processor" or "platform"
+	GetCollateral(fmspc []byte, ca string) (Collateral, error)
+}
+
+// NewQuoteProvider returns the provider selected by config, caching collateral for config.CollateralTTL
+func NewQuoteProvider(config QuoteProviderConfig) (QuoteProvider, error) {
+	baseURL := config.URL
+	rootCACRLURL := ""
+	switch config.Provider {
+	case QuoteProviderIntelPCS:
+		if baseURL == "" {
+			baseURL = intelPCSURL
+		}
+		rootCACRLURL = intelRootCACRLURL
+	case QuoteProviderPCCS:
+		if baseURL == "" {
+			return nil, fmt.Errorf("the quote provider %s requires a URL", config.Provider)
+		}
+	case QuoteProviderAzure:
+		if baseURL == "" {
+			baseURL = azureURL
+		}
+	default:
+		return nil, fmt.Errorf("unknown quote provider %q", config.Provider)
+	}
+	timeout := config.Timeout
+	if timeout == 0 {
+		timeout = 30 * time.Second
+	}
+	var provider QuoteProvider = &httpQuoteProvider{
+		baseURL:      strings.TrimSuffix(baseURL, "/") + certificationPath,
+		rootCACRLURL: rootCACRLURL,
+		apiKey:       config.APIKey,
+		client:       &http.Client{Timeout: timeout},
+	}
+	cache := config.Cache
+	if cache == nil && config.CollateralTTL > 0 {
+		// never fails without a directory
+		cache, _ = NewAttestationCache("")
+	}
+	if cache != nil {
+		provider = &cachingQuoteProvider{inner: provider, cache: cache, ttl: config.CollateralTTL, maxStale: config.MaxStale}
+	}
+	return provider, nil
+}
+
+// httpQuoteProvider fetches collateral from a server of the PCS API
+type httpQuoteProvider struct {
+	baseURL string
+	// rootCACRLURL is where the CRL of the root CA is fetched from, if the server does not serve it
+	rootCACRLURL string
+	apiKey       string
+	client       *http.Client
+}
+
+// get returns the body of the response to target and its URL-encoded header issuerHeader, if not empty
+func (p *httpQuoteProvider) get(target string, issuerHeader string) ([]byte, string, error) {
+	req, err := http.NewRequest(http.MethodGet, target, nil)
+	if err != nil {
+		return nil, "", err
+	}
+	if p.apiKey != "" {
+		req.Header.Set("Ocp-Apim-Subscription-Key", p.apiKey)
+	}
+	res, err := p.client.Do(req)
+	if err != nil {
+		return nil, "", err
+	}
+	defer res.Body.Close()
+	body, err := ioutil.ReadAll(res.Body)
+	if err != nil {
+		return nil, "", err
+	}
+	if res.StatusCode != http.StatusOK {
+		return nil, "", fmt.Errorf("quote provider answered %s to %s", res.Status, target)
+	}
+	if issuerHeader == "" {
+		return body, "", nil
+	}
+	chain, err := unescapeIssuerChain(res.Header.Get(issuerHeader))
+	if err != nil {
+		return nil, "", fmt.Errorf("invalid %s header: %v", issuerHeader, err)
+	}
+	return body, chain, nil
+}
+
+func unescapeIssuerChain(header string) (string, error) {
+	if header == "" {
+		return "", fmt.Errorf("missing")
+	}
+	return url.QueryUnescape(header)
+}
+
+func (p *httpQuoteProvider) GetCollateral(fmspc []byte, ca string) (Collateral, error) {
+	if ca != "processor" && ca != "platform" {
+		return Collateral{}, fmt.Errorf("unknown PCK CA %q", ca)
+	}
+	var c Collateral
+	tcbInfo, chain, err := p.get(p.baseURL+"/tcb?fmspc="+hex.EncodeToString(fmspc), "TCB-Info-Issuer-Chain")
+	if err != nil {
+		return Collateral{}, err
+	}
+	c.TCBInfo, c.TCBInfoIssuerChain = tcbInfo, chain
+	qeIdentity, chain, err := p.get(p.baseURL+"/qe/identity", "SGX-Enclave-Identity-Issuer-Chain")
+	if err != nil {
+		return Collateral{}, err
+	}
+	c.QEIdentity, c.QEIdentityIssuerChain = qeIdentity, chain
+	c.PCKCRL, c.PCKCRLIssuerChain, err = p.get(p.baseURL+"/pckcrl?ca="+ca+"&encoding=der", "SGX-PCK-CRL-Issuer-Chain")
+	if err != nil {
+		return Collateral{}, err
+	}
+	rootCACRLURL := p.rootCACRLURL
+	if rootCACRLURL == "" {
+		rootCACRLURL = p.baseURL + "/rootcacrl"
+	}
+	c.RootCACRL, _, err = p.get(rootCACRLURL, "")
+	if err != nil {
+		return Collateral{}, err
+	}
+	return c, nil
+}
+
+// cachingQuoteProvider reuses the collateral of another provider for ttl
+type cachingQuoteProvider struct {
+	inner    QuoteProvider
+	cache    *AttestationCache
+	ttl      time.Duration
+	maxStale time.Duration
+}
+
+func (p *cachingQuoteProvider) GetCollateral(fmspc []byte, ca string) (Collateral, error) {
+	key := "collateral/" + hex.EncodeToString(fmspc) + "/" + ca
+	// the cache lock is not held while fetching, so a slow provider does not block other platforms
+	data, err := p.cache.fetch(key, p.ttl, p.maxStale, func() ([]byte, error) {
+		collateral, err := p.inner.GetCollateral(fmspc, ca)
+		if err != nil {
+			return nil, err
+		}
+		return json.Marshal(collateral)
+	})
+	if err != nil {
+		return Collateral{}, err
+	}
+	var collateral Collateral
+	err = json.Unmarshal(data, &collateral)
+	return collateral, err
+}
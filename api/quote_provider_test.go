@@ -0,0 +1,109 @@
+package api
+
+import (
+	"net/http"
+	"net/http/httptest"
+	"net/url"
+	"testing"
+	"time"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestNewQuoteProviderChecksConfig(t *testing.T) {
+	_, err := NewQuoteProvider(QuoteProviderConfig{Provider: QuoteProviderPCCS})
+	require.Error(t, err)
+	_, err = NewQuoteProvider(QuoteProviderConfig{Provider: "sgx_default"})
+	require.Error(t, err)
+
+	provider, err := NewQuoteProvider(QuoteProviderConfig{Provider: QuoteProviderIntelPCS})
+	require.NoError(t, err)
+	require.Equal(t, intelPCSURL+certificationPath, provider.(*httpQuoteProvider).baseURL)
+	provider, err = NewQuoteProvider(QuoteProviderConfig{Provider: QuoteProviderAzure, CollateralTTL: time.Hour})
+	require.NoError(t, err)
+	require.Equal(t, azureURL+certificationPath, provider.(*cachingQuoteProvider).inner.(*httpQuoteProvider).baseURL)
+}
+
+func TestPCCSCollateral(t *testing.T) {
+	chain := "-----BEGIN CERTIFICATE-----\nMII=\n-----END CERTIFICATE-----\n"
+	var requests []string
+	server := httptest.NewServer(http.HandlerFunc(func(w http.ResponseWriter, r *http.Request) {
+		requests = append(requests, r.URL.RequestURI())
+		require.Equal(t, "key", r.Header.Get("Ocp-Apim-Subscription-Key"))
+		switch r.URL.Path {
+		case "/sgx/certification/v4/tcb":
+			w.Header().Set("TCB-Info-Issuer-Chain", url.QueryEscape(chain))
+			w.Write([]byte(`{"tcbInfo":{}}`))
+		case "/sgx/certification/v4/qe/identity":
+			w.Header().Set("SGX-Enclave-Identity-Issuer-Chain", url.QueryEscape(chain))
+			w.Write([]byte(`{"enclaveIdentity":{}}`))
+		case "/sgx/certification/v4/pckcrl":
+			w.Header().Set("SGX-PCK-CRL-Issuer-Chain", url.QueryEscape(chain))
+			w.Write([]byte("pck crl"))
+		case "/sgx/certification/v4/rootcacrl":
+			w.Write([]byte("root crl"))
+		default:
+			w.WriteHeader(http.StatusNotFound)
+		}
+	}))
+	defer server.Close()
+
+	config := QuoteProviderConfig{Provider: QuoteProviderPCCS, URL: server.URL + "/", APIKey: "key", CollateralTTL: time.Hour}
+	provider, err := NewQuoteProvider(config)
+	require.NoError(t, err)
+	collateral, err := provider.GetCollateral([]byte{0x00, 0x90, 0x6e, 0xa1, 0x00, 0x00}, "processor")
+	require.NoError(t, err)
+	require.Equal(t, Collateral{
+		TCBInfo:               []byte(`{"tcbInfo":{}}`),
+		TCBInfoIssuerChain:    chain,
+		QEIdentity:            []byte(`{"enclaveIdentity":{}}`),
+		QEIdentityIssuerChain: chain,
+		PCKCRL:                []byte("pck crl"),
+		PCKCRLIssuerChain:     chain,
+		RootCACRL:             []byte("root crl"),
+	}, collateral)
+	require.Equal(t, "/sgx/certification/v4/tcb?fmspc=00906ea10000", requests[0])
+
+	// cached until the TTL ran out
+	_, err = provider.GetCollateral([]byte{0x00, 0x90, 0x6e, 0xa1, 0x00, 0x00}, "processor")
+	require.NoError(t, err)
+	require.Len(t, requests, 4)
+
+	_, err = provider.GetCollateral([]byte{0x00, 0x90, 0x6e, 0xa1, 0x00, 0x00}, "unknown")
+	require.Error(t, err)
+}
+
+func TestCachingQuoteProviderExpires(t *testing.T) {
+	fetches := 0
+	inner := quoteProviderFunc(func(fmspc []byte, ca string) (Collateral, error) {
+		fetches++
+		return Collateral{RootCACRL: []byte{byte(fetches)}}, nil
+	})
+	now := time.Unix(1600000000, 0)
+	cache, err := NewAttestationCache("")
+	require.NoError(t, err)
+	cache.now = func() time.Time { return now }
+	provider := &cachingQuoteProvider{inner: inner, cache: cache, ttl: time.Minute}
+
+	first, err := provider.GetCollateral([]byte{1}, "platform")
+	require.NoError(t, err)
+	now = now.Add(59 * time.Second)
+	cached, err := provider.GetCollateral([]byte{1}, "platform")
+	require.NoError(t, err)
+	require.Equal(t, first, cached)
+	// other platforms are fetched separately
+	_, err = provider.GetCollateral([]byte{2}, "platform")
+	require.NoError(t, err)
+	require.Equal(t, 2, fetches)
+
+	now = now.Add(time.Second)
+	refetched, err := provider.GetCollateral([]byte{1}, "platform")
+	require.NoError(t, err)
+	require.Equal(t, []byte{3}, refetched.RootCACRL)
+}
+
+type quoteProviderFunc func(fmspc []byte, ca string) (Collateral, error)
+
+func (f quoteProviderFunc) GetCollateral(fmspc []byte, ca string) (Collateral, error) {
+	return f(fmspc, ca)
+}
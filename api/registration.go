@@ -0,0 +1,102 @@
+package api
+
+import (
+	"crypto/sha256"
+	"encoding/json"
+	"fmt"
+)
+
+// RegistrationKeysVersion is the version of the RegistrationKeys format this package understands
+const RegistrationKeysVersion = 1
+
+// PublicKey is a typed public key
+type PublicKey struct {
+	Type  string `json:"type"`
+	Value []byte `json:"value"`
+}
+
+// RegistrationKeys are the public keys a node registers with the network
+type RegistrationKeys struct {
+	Version uint32 `json:"version"`
+	// NodePubKey is the key transactions are encrypted to
+	NodePubKey PublicKey `json:"node_pubkey"`
+	// SeedExchangePubKey is the key the bootstrap node encrypts the consensus seed to when the node registers
+	SeedExchangePubKey PublicKey `json:"seed_exchange_pubkey"`
+}
+
+func parseRegistrationKeys(data []byte) (RegistrationKeys, error) {
+	var keys RegistrationKeys
+	if err := json.Unmarshal(data, &keys); err != nil {
+		return RegistrationKeys{}, err
+	}
+	if keys.Version != RegistrationKeysVersion {
+		return RegistrationKeys{}, fmt.Errorf("unsupported registration keys version %d", keys.Version)
+	}
+	return keys, nil
+}
+
+// RegistrationRefreshVersion is the version of the RegistrationRefresh format this package understands
+const RegistrationRefreshVersion = 1
+
+// RegistrationRefresh is what a node registers again with after its platform was updated, see RefreshRegistration
+type RegistrationRefresh struct {
+	Version uint32           `json:"version"`
+	Keys    RegistrationKeys `json:"keys"`
+	// Certificate is the DER certificate signed by the enclave, which contains the new attestation report
+	Certificate []byte `json:"certificate"`
+	// QuoteStatus is the status IAS gave the quote of the new report
+	QuoteStatus string   `json:"quote_status"`
+	AdvisoryIDs []string `json:"advisory_ids"`
+}
+
+func parseRegistrationRefresh(data []byte) (RegistrationRefresh, error) {
+	var refresh RegistrationRefresh
+	if err := json.Unmarshal(data, &refresh); err != nil {
+		return RegistrationRefresh{}, err
+	}
+	if refresh.Version != RegistrationRefreshVersion {
+		return RegistrationRefresh{}, fmt.Errorf("unsupported registration refresh version %d", refresh.Version)
+	}
+	return refresh, nil
+}
+
+// KeyAttestationVersion is the version of the KeyAttestation format this package understands
+const KeyAttestationVersion = 1
+
+// keyAttestationDomain separates the report data of key attestations from the one of registration
+const keyAttestationDomain = "secret-key-attestation-v1"
+
+// KeyAttestation is a statement of the enclave binding the registration keys of the node to an attestation
+// report, see CreateKeyAttestation
+type KeyAttestation struct {
+	Version uint32           `json:"version"`
+	Keys    RegistrationKeys `json:"keys"`
+	// Challenge is the challenge of the verifier the statement was made for
+	Challenge []byte `json:"challenge"`
+	// ReportData is the hex encoded report data the attestation report of Certificate commits to
+	ReportData string `json:"report_data"`
+	// Certificate is the DER certificate signed by the enclave, which contains the attestation report
+	Certificate []byte `json:"certificate"`
+}
+
+// KeyAttestationReportData is the report data of a statement for keys and challenge. Verifiers recompute it
+// instead of trusting the ReportData of a statement.
+func KeyAttestationReportData(keys RegistrationKeys, challenge []byte) []byte {
+	h := sha256.New()
+	h.Write([]byte(keyAttestationDomain))
+	h.Write(keys.NodePubKey.Value)
+	h.Write(keys.SeedExchangePubKey.Value)
+	h.Write(challenge)
+	return h.Sum(nil)
+}
+
+func parseKeyAttestation(data []byte) (KeyAttestation, error) {
+	var attestation KeyAttestation
+	if err := json.Unmarshal(data, &attestation); err != nil {
+		return KeyAttestation{}, err
+	}
+	if attestation.Version != KeyAttestationVersion {
+		return KeyAttestation{}, fmt.Errorf("unsupported key attestation version %d", attestation.Version)
+	}
+	return attestation, nil
+}
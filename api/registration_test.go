@@ -0,0 +1,53 @@
+package api
+
+import (
+	"bytes"
+	"encoding/hex"
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestParseRegistrationKeys(t *testing.T) {
+	data := []byte(`{"version":1,"node_pubkey":{"type":"x25519","value":"AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE="},"seed_exchange_pubkey":{"type":"x25519","value":"AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI="}}`)
+	keys, err := parseRegistrationKeys(data)
+	require.NoError(t, err)
+	require.Equal(t, "x25519", keys.NodePubKey.Type)
+	require.Equal(t, bytes.Repeat([]byte{1}, 32), keys.NodePubKey.Value)
+	require.Equal(t, "x25519", keys.SeedExchangePubKey.Type)
+	require.Equal(t, bytes.Repeat([]byte{2}, 32), keys.SeedExchangePubKey.Value)
+
+	_, err = parseRegistrationKeys([]byte(`{"version":2}`))
+	require.Error(t, err)
+}
+
+func TestParseRegistrationRefresh(t *testing.T) {
+	keys := `{"version":1,"node_pubkey":{"type":"x25519","value":"AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE="},"seed_exchange_pubkey":{"type":"x25519","value":"AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI="}}`
+	data := []byte(`{"version":1,"keys":` + keys + `,"certificate":"ZGVy","quote_status":"SW_HARDENING_NEEDED","advisory_ids":["INTEL-SA-00334"]}`)
+	refresh, err := parseRegistrationRefresh(data)
+	require.NoError(t, err)
+	require.Equal(t, bytes.Repeat([]byte{1}, 32), refresh.Keys.NodePubKey.Value)
+	require.Equal(t, []byte("der"), refresh.Certificate)
+	require.Equal(t, "SW_HARDENING_NEEDED", refresh.QuoteStatus)
+	require.Equal(t, []string{"INTEL-SA-00334"}, refresh.AdvisoryIDs)
+
+	_, err = parseRegistrationRefresh([]byte(`{"version":2}`))
+	require.Error(t, err)
+}
+
+func TestParseKeyAttestation(t *testing.T) {
+	keys := `{"version":1,"node_pubkey":{"type":"x25519","value":"AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE="},"seed_exchange_pubkey":{"type":"x25519","value":"AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI="}}`
+	data := []byte(`{"version":1,"keys":` + keys + `,"challenge":"AwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwM=",` +
+		`"report_data":"d9b5990b63520275bc393cf0eb5afd90df222936904dd1e770ed2bac8d8be2f3","certificate":"ZGVy"}`)
+	attestation, err := parseKeyAttestation(data)
+	require.NoError(t, err)
+	require.Equal(t, bytes.Repeat([]byte{3}, 32), attestation.Challenge)
+	require.Equal(t, []byte("der"), attestation.Certificate)
+
+	// verifiers recompute the report data like the enclave
+	reportData := KeyAttestationReportData(attestation.Keys, attestation.Challenge)
+	require.Equal(t, attestation.ReportData, hex.EncodeToString(reportData))
+
+	_, err = parseKeyAttestation([]byte(`{"version":2}`))
+	require.Error(t, err)
+}
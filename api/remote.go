@@ -0,0 +1,578 @@
+// +build !secretcli
+
+package api
+
+import (
+	"bytes"
+	"encoding/binary"
+	"encoding/json"
+	"fmt"
+	"io"
+	"log"
+	"net"
+	"reflect"
+
+	dbm "github.com/tendermint/tm-db"
+
+	"github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+)
+
+// The GoResult values of callback replies, see the GoResult enum of bindings.h
+const (
+	remoteOk       int32 = 0
+	remotePanic    int32 = 1
+	remoteOutOfGas int32 = 3
+	remoteOther    int32 = 4
+	remoteUser     int32 = 5
+)
+
+// maxRemoteFrameSize mirrors MAX_FRAME_SIZE of the server
+const maxRemoteFrameSize = 64 * 1024 * 1024
+
+// RemoteVM runs contracts in a cosmwasm-server process (built with the "server" feature of the Rust library)
+// instead of in this process, so an enclave crash does not take down the node.
+// Every call runs on its own connection. The server runs the calls one at a time, but lets the queries a
+// contract makes through the Querier run while the call waits for them.
+type RemoteVM struct {
+	socket string
+	idle   chan net.Conn
+}
+
+// NewRemoteVM returns a client of the server listening on the Unix socket at socket.
+// Up to maxIdle connections are kept open between calls.
+func NewRemoteVM(socket string, maxIdle int) *RemoteVM {
+	return &RemoteVM{
+		socket: socket,
+		idle:   make(chan net.Conn, maxIdle),
+	}
+}
+
+// Close closes the idle connections. Calls which are still running close theirs when they finish.
+func (r *RemoteVM) Close() {
+	for {
+		select {
+		case conn := <-r.idle:
+			conn.Close()
+		default:
+			return
+		}
+	}
+}
+
+func (r *RemoteVM) Create(wasm []byte) ([]byte, error) {
+	res, err := r.call(remoteRequest{Create: &remoteCreate{Wasm: wasm}}, nil)
+	if err != nil {
+		return nil, err
+	}
+	return res.Data, nil
+}
+
+func (r *RemoteVM) GetCode(codeID []byte) ([]byte, error) {
+	res, err := r.call(remoteRequest{GetCode: &remoteGetCode{CodeID: codeID}}, nil)
+	if err != nil {
+		return nil, err
+	}
+	return res.Data, nil
+}
+
+// SetConsensusParams hands the consensus params to the server, see api.SetConsensusParams. The server does not
+// keep them across restarts, so the keeper must set them again whenever it reconnects to a new server process.
+func (r *RemoteVM) SetConsensusParams(params ConsensusParams) error {
+	bz, err := json.Marshal(params)
+	if err != nil {
+		return err
+	}
+	_, err = r.call(remoteRequest{SetConsensusParams: &remoteSetConsensusParams{Params: bz}}, nil)
+	return err
+}
+
+func (r *RemoteVM) Instantiate(
+	codeID []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	call := &remoteContractCall{CodeID: codeID, Params: params, Msg: msg, GasLimit: gasLimit, CallDepth: opts.CallDepth, CallID: opts.CallID}
+	env := &remoteEnv{gasMeter: *gasMeter, store: store, api: api, querier: *querier}
+	return r.contractCall(remoteRequest{Instantiate: call}, env, opts.GasReport)
+}
+
+func (r *RemoteVM) Handle(
+	codeID []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	call := &remoteContractCall{CodeID: codeID, Params: params, Msg: msg, GasLimit: gasLimit, CallDepth: opts.CallDepth, CallID: opts.CallID}
+	env := &remoteEnv{gasMeter: *gasMeter, store: store, api: api, querier: *querier}
+	return r.contractCall(remoteRequest{Handle: call}, env, opts.GasReport)
+}
+
+func (r *RemoteVM) Migrate(
+	codeID []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	keyHistory [][]byte,
+	auth MigrateAuth,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	authBin, err := json.Marshal(auth)
+	if err != nil {
+		return nil, 0, err
+	}
+	call := &remoteContractCall{CodeID: codeID, Params: params, Msg: msg, GasLimit: gasLimit, CallDepth: opts.CallDepth, KeyHistory: bytes.Join(keyHistory, nil), Auth: authBin, CallID: opts.CallID}
+	env := &remoteEnv{gasMeter: *gasMeter, store: store, api: api, querier: *querier}
+	return r.contractCall(remoteRequest{Migrate: call}, env, opts.GasReport)
+}
+
+func (r *RemoteVM) Query(
+	codeID []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	query := &remoteQuery{CodeID: codeID, Msg: msg, GasLimit: gasLimit, CallDepth: opts.CallDepth, CallID: opts.CallID, Block: opts.Block}
+	env := &remoteEnv{gasMeter: *gasMeter, store: store, api: api, querier: *querier}
+	env.setBlockContext(opts.Block)
+	return r.contractCall(remoteRequest{Query: query}, env, opts.GasReport)
+}
+
+// QueryUnmetered runs a query without a gas limit, like api.QueryUnmetered
+func (r *RemoteVM) QueryUnmetered(
+	codeID []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	opts CallOptions,
+) ([]byte, error) {
+	query := &remoteQuery{CodeID: codeID, Msg: msg, CallDepth: opts.CallDepth, CallID: opts.CallID, Block: opts.Block, Unmetered: true}
+	env := &remoteEnv{gasMeter: *gasMeter, store: store, api: api, querier: *querier}
+	env.setBlockContext(opts.Block)
+	res, _, err := r.contractCall(remoteRequest{Query: query}, env, nil)
+	return res, err
+}
+
+func (r *RemoteVM) contractCall(req remoteRequest, env *remoteEnv, gasReport *GasReport) ([]byte, uint64, error) {
+	res, err := r.call(req, env)
+	if res != nil && gasReport != nil {
+		*gasReport = res.GasReport.toGasReport()
+	}
+	if err != nil {
+		if res != nil {
+			return nil, res.GasUsed, err
+		}
+		return nil, 0, err
+	}
+	return res.Data, res.GasUsed, nil
+}
+
+// call sends req and answers the callbacks of the server from env until the response arrives.
+// It returns the response along with its error, if the operation failed.
+func (r *RemoteVM) call(req remoteRequest, env *remoteEnv) (*remoteResponse, error) {
+	conn, err := r.conn()
+	if err != nil {
+		return nil, err
+	}
+	if env != nil {
+		defer env.close()
+	}
+	if err := writeRemoteFrame(conn, remoteClientMessage{Request: &req}); err != nil {
+		conn.Close()
+		return nil, err
+	}
+	for {
+		var msg remoteServerMessage
+		if err := readRemoteFrame(conn, &msg); err != nil {
+			conn.Close()
+			return nil, err
+		}
+		switch {
+		case msg.Response != nil:
+			r.release(conn)
+			return msg.Response, msg.Response.err()
+		case msg.Callback != nil && env != nil:
+			reply := env.answer(msg.Callback)
+			if err := writeRemoteFrame(conn, remoteClientMessage{Reply: &reply}); err != nil {
+				conn.Close()
+				return nil, err
+			}
+		default:
+			conn.Close()
+			return nil, fmt.Errorf("unexpected message from the contract server")
+		}
+	}
+}
+
+func (r *RemoteVM) conn() (net.Conn, error) {
+	select {
+	case conn := <-r.idle:
+		return conn, nil
+	default:
+		return net.Dial("unix", r.socket)
+	}
+}
+
+func (r *RemoteVM) release(conn net.Conn) {
+	select {
+	case r.idle <- conn:
+	default:
+		conn.Close()
+	}
+}
+
+func writeRemoteFrame(w io.Writer, msg interface{}) error {
+	data, err := json.Marshal(msg)
+	if err != nil {
+		return err
+	}
+	if len(data) > maxRemoteFrameSize {
+		return fmt.Errorf("message of %d bytes exceeds the frame limit", len(data))
+	}
+	frame := make([]byte, 4+len(data))
+	binary.BigEndian.PutUint32(frame, uint32(len(data)))
+	copy(frame[4:], data)
+	_, err = w.Write(frame)
+	return err
+}
+
+func readRemoteFrame(r io.Reader, msg interface{}) error {
+	var header [4]byte
+	if _, err := io.ReadFull(r, header[:]); err != nil {
+		return err
+	}
+	size := binary.BigEndian.Uint32(header[:])
+	if size > maxRemoteFrameSize {
+		return fmt.Errorf("message of %d bytes exceeds the frame limit", size)
+	}
+	data := make([]byte, size)
+	if _, err := io.ReadFull(r, data); err != nil {
+		return err
+	}
+	return json.Unmarshal(data, msg)
+}
+
+/**** the wire format, see protocol.rs ****/
+
+type remoteClientMessage struct {
+	Request *remoteRequest `json:"request,omitempty"`
+	Reply   *remoteReply   `json:"reply,omitempty"`
+}
+
+type remoteServerMessage struct {
+	Callback *remoteCallback `json:"callback,omitempty"`
+	Response *remoteResponse `json:"response,omitempty"`
+}
+
+type remoteRequest struct {
+	Create             *remoteCreate             `json:"create,omitempty"`
+	GetCode            *remoteGetCode            `json:"get_code,omitempty"`
+	SetConsensusParams *remoteSetConsensusParams `json:"set_consensus_params,omitempty"`
+	Instantiate        *remoteContractCall       `json:"instantiate,omitempty"`
+	Handle             *remoteContractCall       `json:"handle,omitempty"`
+	Migrate            *remoteContractCall       `json:"migrate,omitempty"`
+	Query              *remoteQuery              `json:"query,omitempty"`
+}
+
+type remoteCreate struct {
+	Wasm []byte `json:"wasm"`
+}
+
+type remoteGetCode struct {
+	CodeID []byte `json:"code_id"`
+}
+
+type remoteSetConsensusParams struct {
+	Params []byte `json:"params"`
+}
+
+type remoteContractCall struct {
+	CodeID     []byte `json:"code_id"`
+	Params     []byte `json:"params"`
+	Msg        []byte `json:"msg"`
+	GasLimit   uint64 `json:"gas_limit"`
+	CallDepth  uint32 `json:"call_depth"`
+	KeyHistory []byte `json:"key_history,omitempty"`
+	Auth       []byte `json:"auth,omitempty"`
+	CallID     string `json:"call_id,omitempty"`
+}
+
+type remoteQuery struct {
+	CodeID    []byte `json:"code_id"`
+	Msg       []byte `json:"msg"`
+	GasLimit  uint64 `json:"gas_limit"`
+	CallDepth uint32 `json:"call_depth"`
+	CallID    string       `json:"call_id,omitempty"`
+	Block     BlockContext `json:"block"`
+	Unmetered bool         `json:"unmetered,omitempty"`
+}
+
+type remoteResponse struct {
+	Data      []byte          `json:"data"`
+	Error     *string         `json:"error"`
+	Errno     int             `json:"errno"`
+	GasUsed   uint64          `json:"gas_used"`
+	GasReport remoteGasReport `json:"gas_report"`
+}
+
+// err converts a failed response like errorWithMessage
+func (r *remoteResponse) err() error {
+	if r.Error == nil {
+		return nil
+	}
+	switch r.Errno {
+	case 2:
+		return types.OutOfGasError{}
+	case 3:
+		return types.CallDepthExceededError{Msg: *r.Error}
+	case 4:
+		return types.ResponseTooLargeError{Msg: *r.Error}
+	case 5:
+		return parseUnsupportedFeatures(*r.Error)
+	case 6:
+		return types.TimeoutError{Msg: *r.Error}
+	case 7:
+		return types.ThrottledError{Msg: *r.Error}
+	case 8:
+		return types.InvalidEventsError{Msg: *r.Error}
+	case 9:
+		return types.EventLimitExceededError{Msg: *r.Error}
+	case 10:
+		return types.CallDeniedError{Msg: *r.Error}
+	case 11:
+		return types.ContractPausedError{Msg: *r.Error}
+	case 12:
+		return types.IllegalReentrancyError{Msg: *r.Error}
+	case 13:
+		return types.IteratorLimitExceededError{Msg: *r.Error}
+	case 14:
+		return types.InvalidMsgError{Msg: *r.Error}
+	case 15:
+		return types.TcbOutdatedError{Msg: *r.Error}
+	case 16:
+		return types.CapabilityDeniedError{Msg: *r.Error}
+	default:
+		return fmt.Errorf("%s", *r.Error)
+	}
+}
+
+type remoteGasReport struct {
+	UsedExternally  uint64         `json:"used_externally"`
+	Refundable      uint64         `json:"refundable"`
+	Reads           uint64         `json:"reads"`
+	Writes          uint64         `json:"writes"`
+	IteratorSteps   uint64         `json:"iterator_steps"`
+	Queries         uint64         `json:"queries"`
+	APICalls        uint64         `json:"api_calls"`
+	Fallback        uint64         `json:"fallback"`
+	ScheduleVersion uint64         `json:"schedule_version"`
+	PeakMemoryPages uint32         `json:"peak_memory_pages"`
+	HostCalls       HostCallCounts `json:"host_calls"`
+}
+
+func (r remoteGasReport) toGasReport() GasReport {
+	return GasReport{
+		UsedExternally:  r.UsedExternally,
+		Refundable:      r.Refundable,
+		Reads:           r.Reads,
+		Writes:          r.Writes,
+		IteratorSteps:   r.IteratorSteps,
+		Queries:         r.Queries,
+		APICalls:        r.APICalls,
+		Fallback:        r.Fallback,
+		ScheduleVersion: r.ScheduleVersion,
+		PeakMemoryPages: r.PeakMemoryPages,
+		HostCalls:       r.HostCalls,
+	}
+}
+
+type remoteCallback struct {
+	Read         *remoteKey          `json:"read"`
+	Write        *remoteWrite        `json:"write"`
+	Remove       *remoteKey          `json:"remove"`
+	Scan         *remoteScan         `json:"scan"`
+	Next         *remoteNext         `json:"next"`
+	Humanize     *remoteHumanize     `json:"humanize"`
+	Canonicalize *remoteCanonicalize `json:"canonicalize"`
+	Query        *remoteQueryRequest `json:"query"`
+}
+
+type remoteKey struct {
+	Key []byte `json:"key"`
+}
+
+type remoteWrite struct {
+	Key   []byte `json:"key"`
+	Value []byte `json:"value"`
+}
+
+type remoteScan struct {
+	Start []byte `json:"start"`
+	End   []byte `json:"end"`
+	Order int32  `json:"order"`
+	Mode  int32  `json:"mode"`
+}
+
+type remoteNext struct {
+	Iterator uint64 `json:"iterator"`
+}
+
+type remoteHumanize struct {
+	Canonical []byte `json:"canonical"`
+}
+
+type remoteCanonicalize struct {
+	Human []byte `json:"human"`
+}
+
+type remoteQueryRequest struct {
+	Request  []byte `json:"request"`
+	GasLimit uint64 `json:"gas_limit"`
+}
+
+// remoteReply leaves Key and Value without omitempty: a nil slice encodes a missing value, an empty one an empty value
+type remoteReply struct {
+	Result   int32   `json:"result"`
+	Error    *string `json:"error,omitempty"`
+	GasUsed  uint64  `json:"gas_used"`
+	Key      []byte  `json:"key"`
+	Value    []byte  `json:"value"`
+	Iterator uint64  `json:"iterator"`
+}
+
+func errorReply(result int32, err error) remoteReply {
+	msg := err.Error()
+	return remoteReply{Result: result, Error: &msg}
+}
+
+/**** callbacks ****/
+
+// remoteEnv answers the callbacks of one contract call, like the cgo callbacks do for local calls
+type remoteEnv struct {
+	gasMeter  GasMeter
+	store     KVStore
+	api       *GoAPI
+	querier   Querier
+	iterators []dbm.Iterator
+}
+
+func (e *remoteEnv) answer(cb *remoteCallback) (reply remoteReply) {
+	defer func() {
+		// the same panics recoverPanic handles for the cgo callbacks
+		if rec := recover(); rec != nil {
+			if reflect.TypeOf(rec).Name() == "ErrorOutOfGas" {
+				reply = remoteReply{Result: remoteOutOfGas}
+				return
+			}
+			log.Printf("Panic in Go callback: %#v\n", rec)
+			reply = remoteReply{Result: remotePanic}
+		}
+	}()
+
+	gasBefore := e.gasMeter.GasConsumed()
+	switch {
+	case cb.Read != nil:
+		reply.Value = e.store.Get(cb.Read.Key)
+	case cb.Write != nil:
+		e.store.Set(cb.Write.Key, cb.Write.Value)
+	case cb.Remove != nil:
+		e.store.Delete(cb.Remove.Key)
+	case cb.Scan != nil:
+		var iter dbm.Iterator
+		switch cb.Scan.Order {
+		case 1: // Ascending
+			iter = e.store.Iterator(cb.Scan.Start, cb.Scan.End)
+		case 2: // Descending
+			iter = e.store.ReverseIterator(cb.Scan.Start, cb.Scan.End)
+		default:
+			return errorReply(remoteOther, fmt.Errorf("invalid order %d", cb.Scan.Order))
+		}
+		if cb.Scan.Mode == 1 { // IteratorMode::KeysOnly
+			iter = keysOnlyIterator{iter}
+		}
+		e.iterators = append(e.iterators, iter)
+		reply.Iterator = uint64(len(e.iterators) - 1)
+	case cb.Next != nil:
+		if cb.Next.Iterator >= uint64(len(e.iterators)) {
+			return errorReply(remoteOther, fmt.Errorf("unknown iterator %d", cb.Next.Iterator))
+		}
+		iter := e.iterators[cb.Next.Iterator]
+		if iter.Valid() {
+			reply.Key = iter.Key()
+			reply.Value = iter.Value()
+			iter.Next()
+		}
+	case cb.Humanize != nil:
+		human, cost, err := e.api.HumanAddress(cb.Humanize.Canonical)
+		if err != nil {
+			reply = errorReply(remoteUser, err)
+		} else {
+			reply.Value = []byte(human)
+		}
+		reply.GasUsed = cost
+		return reply
+	case cb.Canonicalize != nil:
+		canon, cost, err := e.api.CanonicalAddress(string(cb.Canonicalize.Human))
+		if err != nil {
+			reply = errorReply(remoteUser, err)
+		} else {
+			reply.Value = canon
+		}
+		reply.GasUsed = cost
+		return reply
+	case cb.Query != nil:
+		queryBefore := e.querier.GasConsumed()
+		res := types.RustQuery(e.querier, cb.Query.Request, cb.Query.GasLimit)
+		bz, err := json.Marshal(res)
+		if err != nil {
+			return errorReply(remoteOther, err)
+		}
+		reply.Value = bz
+		reply.GasUsed = e.querier.GasConsumed() - queryBefore
+		return reply
+	default:
+		return errorReply(remoteOther, fmt.Errorf("unknown callback"))
+	}
+	reply.GasUsed = e.gasMeter.GasConsumed() - gasBefore
+	reply.Result = remoteOk
+	return reply
+}
+
+// setBlockContext hands the block of a query to the store and the querier, which the server cannot reach
+// through callbacks
+func (e *remoteEnv) setBlockContext(block BlockContext) {
+	if store, ok := e.store.(BlockContextStore); ok {
+		store.SetBlockContext(block)
+	}
+	if querier, ok := e.querier.(BlockContextQuerier); ok {
+		querier.SetBlockContext(block)
+	}
+}
+
+// close releases the iterators of the call
+func (e *remoteEnv) close() {
+	for _, iter := range e.iterators {
+		iter.Close()
+	}
+	e.iterators = nil
+}
@@ -0,0 +1,138 @@
+package api
+
+import (
+	"io/ioutil"
+	"net"
+	"os"
+	"path/filepath"
+	"testing"
+
+	"github.com/stretchr/testify/require"
+
+	"github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+)
+
+// fakeServer answers one request on listener with the given callbacks and response, and sends the replies it got to replies
+func fakeServer(t *testing.T, listener net.Listener, callbacks []remoteCallback, response remoteResponse, replies chan<- remoteReply) {
+	conn, err := listener.Accept()
+	require.NoError(t, err)
+	defer conn.Close()
+
+	var req remoteClientMessage
+	require.NoError(t, readRemoteFrame(conn, &req))
+	for i := range callbacks {
+		require.NoError(t, writeRemoteFrame(conn, remoteServerMessage{Callback: &callbacks[i]}))
+		var reply remoteClientMessage
+		require.NoError(t, readRemoteFrame(conn, &reply))
+		replies <- *reply.Reply
+	}
+	require.NoError(t, writeRemoteFrame(conn, remoteServerMessage{Response: &response}))
+	close(replies)
+}
+
+func TestRemoteVMAnswersCallbacks(t *testing.T) {
+	dir, err := ioutil.TempDir("", "remote")
+	require.NoError(t, err)
+	defer os.RemoveAll(dir)
+	socket := filepath.Join(dir, "server.sock")
+	listener, err := net.Listen("unix", socket)
+	require.NoError(t, err)
+	defer listener.Close()
+
+	callbacks := []remoteCallback{
+		{Write: &remoteWrite{Key: []byte("foo"), Value: []byte("bar")}},
+		{Read: &remoteKey{Key: []byte("foo")}},
+		{Read: &remoteKey{Key: []byte("missing")}},
+	}
+	data := []byte(`{"ok":{}}`)
+	response := remoteResponse{Data: data, GasUsed: 1234, GasReport: remoteGasReport{Reads: 2, Writes: 1}}
+	replies := make(chan remoteReply, len(callbacks))
+	go fakeServer(t, listener, callbacks, response, replies)
+
+	vm := NewRemoteVM(socket, 1)
+	defer vm.Close()
+	gasMeter := NewMockGasMeter(100000000)
+	var igasMeter GasMeter = gasMeter
+	store := NewLookup(gasMeter)
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+	var gasReport GasReport
+
+	res, cost, err := vm.Handle([]byte("code"), []byte("{}"), []byte("{}"), &igasMeter, store, api, &querier, 100000000, CallOptions{CallDepth: 1, GasReport: &gasReport})
+	require.NoError(t, err)
+	require.Equal(t, data, res)
+	require.Equal(t, uint64(1234), cost)
+	require.Equal(t, GasReport{Reads: 2, Writes: 1}, gasReport)
+
+	var got []remoteReply
+	for reply := range replies {
+		got = append(got, reply)
+	}
+	require.Len(t, got, 3)
+	require.Equal(t, remoteOk, got[0].Result)
+	require.Equal(t, []byte("bar"), got[1].Value)
+	require.Nil(t, got[2].Value)
+	require.Equal(t, []byte("bar"), store.Get([]byte("foo")))
+}
+
+func TestRemoteVMReturnsErrors(t *testing.T) {
+	dir, err := ioutil.TempDir("", "remote")
+	require.NoError(t, err)
+	defer os.RemoveAll(dir)
+	socket := filepath.Join(dir, "server.sock")
+	listener, err := net.Listen("unix", socket)
+	require.NoError(t, err)
+	defer listener.Close()
+
+	msg := "Ran out of gas"
+	response := remoteResponse{Error: &msg, Errno: 2, GasUsed: 500}
+	go fakeServer(t, listener, nil, response, make(chan remoteReply))
+
+	vm := NewRemoteVM(socket, 1)
+	defer vm.Close()
+	gasMeter := NewMockGasMeter(100000000)
+	var igasMeter GasMeter = gasMeter
+	store := NewLookup(gasMeter)
+	api := NewMockAPI()
+	querier := DefaultQuerier(mockContractAddr, types.Coins{})
+
+	_, cost, err := vm.Query([]byte("code"), []byte("{}"), &igasMeter, store, api, &querier, 500, CallOptions{CallDepth: 1})
+	require.Equal(t, types.OutOfGasError{}, err)
+	require.Equal(t, uint64(500), cost)
+}
+
+type blockQuerier struct {
+	Querier
+	block BlockContext
+}
+
+func (q *blockQuerier) SetBlockContext(block BlockContext) {
+	q.block = block
+}
+
+func TestRemoteVMPinsQueryBlock(t *testing.T) {
+	dir, err := ioutil.TempDir("", "remote")
+	require.NoError(t, err)
+	defer os.RemoveAll(dir)
+	socket := filepath.Join(dir, "server.sock")
+	listener, err := net.Listen("unix", socket)
+	require.NoError(t, err)
+	defer listener.Close()
+
+	response := remoteResponse{Data: []byte(`{"ok":{}}`)}
+	go fakeServer(t, listener, nil, response, make(chan remoteReply))
+
+	vm := NewRemoteVM(socket, 1)
+	defer vm.Close()
+	gasMeter := NewMockGasMeter(100000000)
+	var igasMeter GasMeter = gasMeter
+	store := NewLookup(gasMeter)
+	api := NewMockAPI()
+	bq := &blockQuerier{Querier: DefaultQuerier(mockContractAddr, types.Coins{})}
+	var querier Querier = bq
+
+	block := BlockContext{Height: 12345, Time: 1600000000}
+	_, _, err = vm.Query([]byte("code"), []byte("{}"), &igasMeter, store, api, &querier, 500, CallOptions{CallDepth: 1, Block: block})
+	require.NoError(t, err)
+	require.Equal(t, block, bq.block)
+}
@@ -0,0 +1,23 @@
+package api
+
+import "fmt"
+
+// The lengths of the parts of an encrypted seed, see SeedExchangeVersion
+const (
+	seedCiphertextLen  = 48
+	ephemeralPubkeyLen = 32
+)
+
+// SeedExchangeVersion tells which version of the seed exchange produced an encrypted seed of GetEncryptedSeed.
+// Version 1 encrypts the seed to the registration key of the node, version 2 (see CreateAttestationReportV2)
+// to an ephemeral key, so seeds exchanged earlier stay secret if the registration key leaks.
+func SeedExchangeVersion(encryptedSeed []byte) (int, error) {
+	switch {
+	case len(encryptedSeed) == seedCiphertextLen:
+		return 1, nil
+	case len(encryptedSeed) == 1+ephemeralPubkeyLen+seedCiphertextLen && encryptedSeed[0] == 2:
+		return 2, nil
+	default:
+		return 0, fmt.Errorf("not an encrypted seed of a known version (%d bytes)", len(encryptedSeed))
+	}
+}
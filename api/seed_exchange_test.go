@@ -0,0 +1,23 @@
+package api
+
+import (
+	"bytes"
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestSeedExchangeVersion(t *testing.T) {
+	version, err := SeedExchangeVersion(bytes.Repeat([]byte{7}, 48))
+	require.NoError(t, err)
+	require.Equal(t, 1, version)
+
+	version, err = SeedExchangeVersion(append([]byte{2}, bytes.Repeat([]byte{7}, 80)...))
+	require.NoError(t, err)
+	require.Equal(t, 2, version)
+
+	_, err = SeedExchangeVersion(append([]byte{3}, bytes.Repeat([]byte{7}, 80)...))
+	require.Error(t, err)
+	_, err = SeedExchangeVersion(nil)
+	require.Error(t, err)
+}
@@ -0,0 +1,47 @@
+package api
+
+import (
+	"bytes"
+
+	dbm "github.com/tendermint/tm-db"
+)
+
+// seekIterator can move within the domain it was opened with, by reopening the underlying iterator.
+// This spares Rust from opening a new iterator when it seeks one of its own scans; contracts cannot seek.
+type seekIterator struct {
+	dbm.Iterator
+	open      func(start, end []byte) dbm.Iterator
+	start     []byte
+	end       []byte
+	ascending bool
+}
+
+func newSeekIterator(open func(start, end []byte) dbm.Iterator, start, end []byte, ascending bool) *seekIterator {
+	return &seekIterator{
+		Iterator:  open(start, end),
+		open:      open,
+		start:     start,
+		end:       end,
+		ascending: ascending,
+	}
+}
+
+// Seek continues the iteration at the first item at or after key in iteration order,
+// without leaving the domain of the iterator
+func (it *seekIterator) Seek(key []byte) {
+	it.Iterator.Close()
+	if it.ascending {
+		start := key
+		if bytes.Compare(start, it.start) < 0 {
+			start = it.start
+		}
+		it.Iterator = it.open(start, it.end)
+		return
+	}
+	// the end is exclusive, so the smallest key after key is needed to include key itself
+	end := append(append([]byte{}, key...), 0)
+	if it.end != nil && bytes.Compare(end, it.end) > 0 {
+		end = it.end
+	}
+	it.Iterator = it.open(it.start, end)
+}
@@ -0,0 +1,65 @@
+package api
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/require"
+	dbm "github.com/tendermint/tm-db"
+)
+
+func seekKeys(it dbm.Iterator) []string {
+	var keys []string
+	for ; it.Valid(); it.Next() {
+		keys = append(keys, string(it.Key()))
+	}
+	return keys
+}
+
+func TestSeekIteratorStaysInDomain(t *testing.T) {
+	store := NewLookup(NewMockGasMeter(100000000))
+	for _, k := range []string{"a", "b", "c", "d", "e"} {
+		store.Set([]byte(k), []byte(k))
+	}
+
+	it := newSeekIterator(store.Iterator, []byte("b"), []byte("e"), true)
+	it.Seek([]byte("c"))
+	require.Equal(t, []string{"c", "d"}, seekKeys(it))
+	it.Seek([]byte("a"))
+	require.Equal(t, []string{"b", "c", "d"}, seekKeys(it))
+	it.Close()
+
+	it = newSeekIterator(store.ReverseIterator, []byte("b"), nil, false)
+	it.Seek([]byte("c"))
+	require.Equal(t, []string{"c", "b"}, seekKeys(it))
+	it.Seek([]byte("z"))
+	require.Equal(t, []string{"e", "d", "c", "b"}, seekKeys(it))
+	it.Close()
+}
+
+func TestPrefixEnd(t *testing.T) {
+	require.Equal(t, []byte("ac"), prefixEnd([]byte("ab")))
+	require.Equal(t, []byte{2}, prefixEnd([]byte{1, 0xff}))
+	require.Nil(t, prefixEnd([]byte{0xff, 0xff}))
+	require.Nil(t, prefixEnd(nil))
+}
+
+func TestLimitIterator(t *testing.T) {
+	store := NewLookup(NewMockGasMeter(100000000))
+	for _, k := range []string{"pa", "pb", "pc", "q"} {
+		store.Set([]byte(k), []byte(k))
+	}
+
+	it := &limitIterator{Iterator: store.Iterator([]byte("p"), prefixEnd([]byte("p"))), remaining: 2}
+	require.Equal(t, []string{"pa", "pb"}, seekKeys(it))
+	it.Close()
+}
+
+func TestDeleteRange(t *testing.T) {
+	store := NewLookup(NewMockGasMeter(100000000))
+	for _, k := range []string{"a", "b", "c", "d"} {
+		store.Set([]byte(k), []byte(k))
+	}
+
+	deleteRange(store, []byte("b"), []byte("d"))
+	require.Equal(t, []string{"a", "d"}, seekKeys(store.Iterator(nil, nil)))
+}
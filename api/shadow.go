@@ -0,0 +1,21 @@
+package api
+
+import "encoding/json"
+
+// ShadowStats are the counters of shadow execution, see CacheOptions.ShadowExecution
+type ShadowStats struct {
+	// Enabled is whether the cache runs shadow execution at all
+	Enabled bool `json:"enabled"`
+	// Calls is the number of calls which ran in both VMs
+	Calls uint64 `json:"calls"`
+	// Divergences is the number of calls which ended differently in the upstream VM. Details are logged.
+	Divergences uint64 `json:"divergences"`
+	// Skipped is the number of calls which could not run in the upstream VM
+	Skipped uint64 `json:"skipped"`
+}
+
+func parseShadowStats(data []byte) (ShadowStats, error) {
+	var stats ShadowStats
+	err := json.Unmarshal(data, &stats)
+	return stats, err
+}
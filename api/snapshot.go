@@ -0,0 +1,29 @@
+package api
+
+import (
+	"encoding/hex"
+	"encoding/json"
+)
+
+// snapshotPayload mirrors the payload produced by ExportSnapshot
+type snapshotPayload struct {
+	Codes []string `json:"codes"`
+}
+
+// SnapshotCodes returns the checksums of the codes which must be stored before payload can be restored.
+// Use it to decide which code blobs to include in a state-sync snapshot.
+func SnapshotCodes(payload []byte) ([][]byte, error) {
+	var p snapshotPayload
+	if err := json.Unmarshal(payload, &p); err != nil {
+		return nil, err
+	}
+	codes := make([][]byte, 0, len(p.Codes))
+	for _, code := range p.Codes {
+		checksum, err := hex.DecodeString(code)
+		if err != nil {
+			return nil, err
+		}
+		codes = append(codes, checksum)
+	}
+	return codes, nil
+}
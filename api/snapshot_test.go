@@ -0,0 +1,16 @@
+package api
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestSnapshotCodes(t *testing.T) {
+	codes, err := SnapshotCodes([]byte(`{"version":1,"enclave_state":"","codes":["0102","ff"]}`))
+	require.NoError(t, err)
+	require.Equal(t, [][]byte{{1, 2}, {0xff}}, codes)
+
+	_, err = SnapshotCodes([]byte(`{"codes":["xyz"]}`))
+	require.Error(t, err)
+}
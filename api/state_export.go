@@ -0,0 +1,57 @@
+package api
+
+import (
+	"encoding/json"
+)
+
+// ExportMode selects how ExportContractState encodes the exported entries
+type ExportMode int32
+
+const (
+	// ExportCiphertext exports the entries as stored, encrypted with the contract's current state key
+	ExportCiphertext ExportMode = 0
+	// ExportReencrypt lets the enclave re-encrypt every entry before it is exported
+	ExportReencrypt ExportMode = 1
+	// ExportKeys only exports the keys, with empty values
+	ExportKeys ExportMode = 2
+)
+
+// StateEntry is a single exported storage entry
+type StateEntry struct {
+	Key   []byte `json:"key"`
+	Value []byte `json:"value"`
+}
+
+// StatePage is one page of a contract's storage, as returned by ExportContractState
+type StatePage struct {
+	Entries []StateEntry `json:"entries"`
+	// Next is the startAfter of the next page, or nil if the storage is exhausted
+	Next []byte `json:"next"`
+}
+
+func parseStatePage(data []byte) (*StatePage, error) {
+	var page StatePage
+	if err := json.Unmarshal(data, &page); err != nil {
+		return nil, err
+	}
+	return &page, nil
+}
+
+// KeyAudit counts the entries of one page of a contract's storage per key version, as returned by
+// AuditStateKeys
+type KeyAudit struct {
+	// Versions has the number of entries encrypted with the key of each code of the history, oldest first
+	Versions []uint64 `json:"versions"`
+	// Unknown is the number of entries no key of the history decrypts
+	Unknown uint64 `json:"unknown"`
+	// Next is the startAfter of the next page, or nil if the storage is exhausted
+	Next []byte `json:"next"`
+}
+
+func parseKeyAudit(data []byte) (*KeyAudit, error) {
+	var audit KeyAudit
+	if err := json.Unmarshal(data, &audit); err != nil {
+		return nil, err
+	}
+	return &audit, nil
+}
@@ -0,0 +1,27 @@
+package api
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestParseStatePage(t *testing.T) {
+	page, err := parseStatePage([]byte(`{"entries":[{"key":"YQ==","value":"Yg=="}],"next":"YQ=="}`))
+	require.NoError(t, err)
+	require.Equal(t, []StateEntry{{Key: []byte("a"), Value: []byte("b")}}, page.Entries)
+	require.Equal(t, []byte("a"), page.Next)
+
+	page, err = parseStatePage([]byte(`{"entries":[],"next":null}`))
+	require.NoError(t, err)
+	require.Empty(t, page.Entries)
+	require.Nil(t, page.Next)
+}
+
+func TestParseKeyAudit(t *testing.T) {
+	audit, err := parseKeyAudit([]byte(`{"versions":[3,0,12],"unknown":1,"next":"YQ=="}`))
+	require.NoError(t, err)
+	require.Equal(t, []uint64{3, 0, 12}, audit.Versions)
+	require.Equal(t, uint64(1), audit.Unknown)
+	require.Equal(t, []byte("a"), audit.Next)
+}
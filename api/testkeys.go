@@ -0,0 +1,38 @@
+// +build testkeys,!secretcli
+
+package api
+
+// #cgo CFLAGS: -DTEST_KEYS
+// #include <stdlib.h>
+// #include "bindings.h"
+import "C"
+
+// These functions are only available if the library was built with the `test-keys` feature
+// (`make build CARGO_FEATURES="backtraces test-keys"`). Build with `-tags testkeys` to use them.
+// Keys derived this way are only as secret as the seed, so never use them outside of test networks.
+
+// InitBootstrapFromSeed is like InitBootstrap, but derives the consensus seed from seed (32 bytes)
+func InitBootstrapFromSeed(seed []byte) ([]byte, error) {
+	s := sendSlice(seed)
+	defer freeAfterSend(s)
+	errmsg := C.Buffer{}
+
+	res, err := C.init_bootstrap_from_seed(s, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// KeyGenFromSeed is like KeyGen, but derives the node's registration key from seed (32 bytes)
+func KeyGenFromSeed(seed []byte) (RegistrationKeys, error) {
+	s := sendSlice(seed)
+	defer freeAfterSend(s)
+	errmsg := C.Buffer{}
+
+	res, err := C.key_gen_from_seed(s, &errmsg)
+	if err != nil {
+		return RegistrationKeys{}, errorWithMessage(err, errmsg)
+	}
+	return parseRegistrationKeys(receiveVector(res))
+}
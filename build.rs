@@ -1,13 +1,67 @@
 use std::env;
+use std::fs;
+use std::path::Path;
+
+const HEADER_PATH: &str = "./api/bindings.h";
+
+/// FNV-1a over the tokens of `header`, so comments and formatting do not change the fingerprint
+fn abi_fingerprint(header: &str) -> u64 {
+    let mut code = String::new();
+    let mut rest = header;
+    while let Some(start) = rest.find("/*") {
+        code.push_str(&rest[..start]);
+        rest = match rest[start..].find("*/") {
+            Some(end) => &rest[start + end + 2..],
+            None => "",
+        };
+    }
+    code.push_str(rest);
+    let tokens: Vec<&str> = code.split_whitespace().collect();
+
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in tokens.join(" ").bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Writes `contents` to `path` unless it is there already, so we don't trigger needless rebuilds
+fn write_if_changed(path: &Path, contents: &str) {
+    if fs::read_to_string(path).ok().as_deref() != Some(contents) {
+        fs::write(path, contents).expect("Unable to write file");
+    }
+}
 
 fn main() {
     let is_sim = env::var("SGX_MODE").unwrap_or_else(|_| "HW".to_string());
     let sdk_dir = env::var("SGX_SDK").unwrap_or_else(|_| "/opt/intel/sgxsdk".to_string());
     let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
 
+    // The header and the library carry the same fingerprint of the exported surface,
+    // which Go compares on startup to detect a header which does not match the library.
+    let mut header = Vec::new();
     cbindgen::generate(crate_dir)
         .expect("Unable to generate bindings")
-        .write_to_file("./api/bindings.h");
+        .write(&mut header);
+    let header = String::from_utf8(header).expect("Bindings are not UTF-8");
+    let fingerprint = abi_fingerprint(&header);
+    let header = format!(
+        "{}\n#define ABI_FINGERPRINT {:#018x}ULL\n",
+        header, fingerprint
+    );
+    write_if_changed(Path::new(HEADER_PATH), &header);
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let fingerprint = format!("const ABI_FINGERPRINT: u64 = {:#018x};\n", fingerprint);
+    write_if_changed(
+        &Path::new(&out_dir).join("abi_fingerprint.rs"),
+        &fingerprint,
+    );
+
+    // Vanilla builds run contracts outside of SGX, and don't link the enclave or the SGX runtime
+    if env::var("CARGO_FEATURE_VANILLA").is_ok() {
+        return;
+    }
 
     println!("cargo:rustc-link-search=native=./lib");
     println!("cargo:rustc-link-lib=static=Enclave_u");
@@ -0,0 +1,25 @@
+//! Attestation certificates, encrypted seeds and block entropy, as passed to the enclave.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use go_cosmwasm::testing::view;
+use go_cosmwasm::{free_rust, get_encrypted_seed, init_node, set_block_entropy, Buffer};
+
+mod common;
+
+fuzz_target!(|input: (Vec<u8>, Vec<u8>, u64)| {
+    let (cert, seed, height) = input;
+
+    let mut err = Buffer::default();
+    free_rust(get_encrypted_seed(view(&cert), Some(&mut err)));
+    common::check_error(err);
+
+    let mut err = Buffer::default();
+    init_node(view(&cert), view(&seed), Some(&mut err));
+    common::check_error(err);
+
+    let mut err = Buffer::default();
+    set_block_entropy(height, view(&seed), Some(&mut err));
+    common::check_error(err);
+});
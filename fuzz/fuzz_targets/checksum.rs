@@ -0,0 +1,23 @@
+//! Code ids and contract keys, as passed to `get_code` and `verify_contract_key`.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use go_cosmwasm::testing::view;
+use go_cosmwasm::{verify_contract_key, Buffer};
+
+mod common;
+
+fuzz_target!(|input: (Vec<u8>, Vec<u8>, Vec<u8>)| {
+    let (code_id, contract_addr, contract_key) = input;
+    common::with_harness(|harness, _| common::check(harness.get_code(&code_id)));
+
+    let mut err = Buffer::default();
+    verify_contract_key(
+        view(&contract_addr),
+        view(&code_id),
+        view(&contract_key),
+        Some(&mut err),
+    );
+    common::check_error(err);
+});
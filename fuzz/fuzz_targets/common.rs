@@ -0,0 +1,57 @@
+//! Shared setup of the fuzz targets.
+//!
+//! Every argument is checked by the library before use, so malformed input must come back as a typed
+//! error. A panic caught by one of the `catch_unwind`s around the extern "C" functions is a bug, and
+//! crashes the target.
+
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+
+use go_cosmwasm::testing::Harness;
+use go_cosmwasm::Buffer;
+
+static HACKATOM: &[u8] = include_bytes!("../../api/testdata/hackatom.wasm");
+
+pub const FEATURES: &str = "staking";
+
+// the message of `Error::Panic`
+const PANIC_MSG: &str = "Caught Panic";
+
+thread_local! {
+    static HARNESS: RefCell<Option<(Harness, Vec<u8>)>> = RefCell::new(None);
+}
+
+/// A directory private to this fuzzing process
+pub fn data_dir(name: &str) -> String {
+    let dir =
+        std::env::temp_dir().join(format!("go-cosmwasm-fuzz-{}-{}", name, std::process::id()));
+    dir.to_str().unwrap().to_string()
+}
+
+/// Runs `f` with a harness which has the hackatom contract stored, and its code id
+pub fn with_harness<F: FnOnce(&mut Harness, &[u8])>(f: F) {
+    HARNESS.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        if cell.is_none() {
+            let mut harness = Harness::new(&data_dir("harness"), FEATURES, b"").unwrap();
+            let code_id = harness.store_code(HACKATOM).unwrap();
+            *cell = Some((harness, code_id));
+        }
+        let (harness, code_id) = cell.as_mut().unwrap();
+        f(harness, code_id)
+    })
+}
+
+/// Fails on errors which only a caught panic produces
+pub fn check<T>(result: Result<T, String>) {
+    if let Err(msg) = result {
+        assert!(!msg.starts_with(PANIC_MSG), "{}", msg);
+    }
+}
+
+/// Takes the error written by an extern "C" function and checks it like `check`
+pub fn check_error(err: Buffer) {
+    let msg = unsafe { err.consume() };
+    check::<()>(Err(String::from_utf8_lossy(&msg).into_owned()));
+}
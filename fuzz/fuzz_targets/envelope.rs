@@ -0,0 +1,37 @@
+//! Encryption envelopes and query permits, which are parsed outside of the enclave.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use go_cosmwasm::testing::view;
+use go_cosmwasm::{decrypt_response, encrypt_msg, free_rust, verify_permit, Buffer};
+
+mod common;
+
+fuzz_target!(|input: (Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>)| {
+    let (node_pubkey, secret, envelope, data) = input;
+
+    let mut err = Buffer::default();
+    free_rust(encrypt_msg(
+        view(&node_pubkey),
+        view(&secret),
+        view(&envelope),
+        view(&data),
+        Some(&mut err),
+    ));
+    common::check_error(err);
+
+    let mut err = Buffer::default();
+    free_rust(decrypt_response(
+        view(&node_pubkey),
+        view(&secret),
+        view(&envelope),
+        view(&data),
+        Some(&mut err),
+    ));
+    common::check_error(err);
+
+    let mut err = Buffer::default();
+    free_rust(verify_permit(view(&data), view(&envelope), Some(&mut err)));
+    common::check_error(err);
+});
@@ -0,0 +1,24 @@
+//! The supported features CSV and the options of `init_cache`.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use go_cosmwasm::testing::view;
+use go_cosmwasm::{init_cache, release_cache, Buffer};
+
+mod common;
+
+fuzz_target!(|input: (Vec<u8>, Vec<u8>)| {
+    let (features, options) = input;
+    let dir = common::data_dir("features");
+    let mut err = Buffer::default();
+    let cache = init_cache(
+        view(dir.as_bytes()),
+        view(&features),
+        view(&options),
+        0,
+        Some(&mut err),
+    );
+    common::check_error(err);
+    release_cache(cache);
+});
@@ -0,0 +1,16 @@
+//! The env and message of contract calls.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+mod common;
+
+fuzz_target!(|input: (Vec<u8>, Vec<u8>)| {
+    let (params, msg) = input;
+    common::with_harness(|harness, code_id| {
+        common::check(harness.instantiate(code_id, &params, &msg));
+        common::check(harness.handle(code_id, &params, &msg));
+        common::check(harness.migrate(code_id, &params, &msg));
+        common::check(harness.query(code_id, &msg));
+    });
+});
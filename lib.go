@@ -17,6 +17,21 @@ type WasmCode []byte
 // KVStore is a reference to some sub-kvstore that is valid for one instance of a code
 type KVStore = api.KVStore
 
+// ContractResolver can be implemented by a KVStore to let contract-to-contract calls skip the round trip through Go
+type ContractResolver = api.ContractResolver
+
+// NamespacedStore can be implemented by a contract's KVStore to let the Rust side enforce the contract's key prefix
+type NamespacedStore = api.NamespacedStore
+
+// BlockContextStore can be implemented by a KVStore to learn the block of a contract call before it runs
+type BlockContextStore = api.BlockContextStore
+
+// BlockContext is the block passed to a BlockContextStore or BlockContextQuerier
+type BlockContext = api.BlockContext
+
+// BlockContextQuerier can be implemented by a Querier to learn the block a query is pinned to before it runs
+type BlockContextQuerier = api.BlockContextQuerier
+
 // GoAPI is a reference to some "precompiles", go callbacks
 type GoAPI = api.GoAPI
 
@@ -26,6 +41,50 @@ type Querier = types.Querier
 // GasMeter is a read-only version of the sdk gas meter
 type GasMeter = api.GasMeter
 
+// MigrateAuth tells Migrate why a migration may run
+type MigrateAuth = api.MigrateAuth
+
+// CallOptions are the call depth, block, gas report and correlation id of a contract call
+type CallOptions = api.CallOptions
+
+// GasReport breaks down the gas used by a contract call, e.g. to refund storage deletions.
+// Contract calls fill it in if passed a non-nil pointer.
+type GasReport = api.GasReport
+
+// CacheOptions are node level settings, such as the size of the query cache
+type CacheOptions = api.CacheOptions
+
+// ShadowStats are the counters of shadow execution, see CacheOptions.ShadowExecution
+type ShadowStats = api.ShadowStats
+
+// ModuleStats tell how many compiled modules were removed after an upgrade and compiled again, see GetModuleStats
+type ModuleStats = api.ModuleStats
+
+// ConsensusParams are the settings deciding the results of contract calls, which must be the same on every node,
+// see SetConsensusParams
+type ConsensusParams = api.ConsensusParams
+
+// GasPolicy decides how the gas reported by the Go callbacks is charged, see ConsensusParams
+type GasPolicy = api.GasPolicy
+
+// BatchQuery is one query of QueryBatch
+type BatchQuery = api.BatchQuery
+
+// BatchQueryResult is the outcome of one query of QueryBatch
+type BatchQueryResult = api.BatchQueryResult
+
+// QueryRoute is the target of an alias of QueryByAlias
+type QueryRoute = api.QueryRoute
+
+// Capability is a group of host imports which can be denied to the contracts of a code
+type Capability = api.Capability
+
+// CodeCapabilities restricts the host capabilities of the contracts of a code, see ConsensusParams
+type CodeCapabilities = api.CodeCapabilities
+
+// WasmCosts is the gas charged per wasm instruction, see CacheOptions and RegisterGasSchedule
+type WasmCosts = api.WasmCosts
+
 // Wasmer is the main entry point to this library.
 // You should create an instance with it's own subdirectory to manage state inside,
 // and call it for all cosmwasm code related actions.
@@ -39,7 +98,13 @@ type Wasmer struct {
 // They allow popular contracts to be executed very rapidly (no loading overhead),
 // but require ~32-64MB each in memory usage.
 func NewWasmer(dataDir string, supportedFeatures string, cacheSize uint64) (*Wasmer, error) {
-	cache, err := api.InitCache(dataDir, supportedFeatures, cacheSize)
+	return NewWasmerWithOptions(dataDir, supportedFeatures, cacheSize, CacheOptions{})
+}
+
+// NewWasmerWithOptions is like NewWasmer, but lets the node override the default CacheOptions.
+// The settings affecting the results of contract calls are set with SetConsensusParams instead.
+func NewWasmerWithOptions(dataDir string, supportedFeatures string, cacheSize uint64, options CacheOptions) (*Wasmer, error) {
+	cache, err := api.InitCache(dataDir, supportedFeatures, cacheSize, options)
 	if err != nil {
 		return nil, err
 	}
@@ -51,6 +116,78 @@ func (w *Wasmer) Cleanup() {
 	api.ReleaseCache(w.cache)
 }
 
+// SetBlockEntropy hands the randomness seed of the block at height to the enclave, which derives the
+// random bytes available to contracts from it. It must be called before the first contract call of the
+// block, with the same entropy on every node.
+func (w *Wasmer) SetBlockEntropy(height uint64, entropy []byte) error {
+	return api.SetBlockEntropy(height, entropy)
+}
+
+// ExportSnapshot produces the enclave related part of a state-sync snapshot. Besides the payload,
+// a snapshot must contain the code of every checksum returned by api.SnapshotCodes(payload).
+func (w *Wasmer) ExportSnapshot() ([]byte, error) {
+	return api.ExportSnapshot(w.cache)
+}
+
+// GetShadowStats returns how many calls ran in both VMs and how many of them diverged,
+// if the Wasmer was created with CacheOptions.ShadowExecution
+func (w *Wasmer) GetShadowStats() (ShadowStats, error) {
+	return api.GetShadowStats(w.cache)
+}
+
+// GetModuleStats returns the format the compiled modules are compiled in, how many modules compiled by another
+// version of the library or enclave were removed at startup, and how many of them were compiled again since
+func (w *Wasmer) GetModuleStats() (ModuleStats, error) {
+	return api.GetModuleStats(w.cache)
+}
+
+// RestoreSnapshot restores a payload produced by ExportSnapshot. All codes of the snapshot must be
+// stored with Create before. The codes paused on the exporting node are paused on this one.
+func (w *Wasmer) RestoreSnapshot(payload []byte) error {
+	return api.RestoreSnapshot(w.cache, payload)
+}
+
+// ExportContractState returns up to limit storage entries of the contract with contractKey for genesis export.
+// Pass nil as startAfter for the first page and the Next of the previous page afterwards, until Next is nil.
+func (w *Wasmer) ExportContractState(
+	contractKey []byte,
+	store KVStore,
+	gasMeter GasMeter,
+	startAfter []byte,
+	limit uint32,
+	mode api.ExportMode,
+) (*api.StatePage, error) {
+	return api.ExportContractState(contractKey, &gasMeter, store, startAfter, limit, mode)
+}
+
+// AuditStateKeys counts up to limit storage entries of the contract with contractKey per key version, e.g. to
+// check whether a migrated contract still has entries encrypted with the key of an earlier code. keyHistory
+// lists all codes the contract ran, oldest first, ending with its current code.
+func (w *Wasmer) AuditStateKeys(
+	contractKey []byte,
+	store KVStore,
+	gasMeter GasMeter,
+	keyHistory []CodeID,
+	startAfter []byte,
+	limit uint32,
+) (*api.KeyAudit, error) {
+	return api.AuditStateKeys(contractKey, &gasMeter, store, codeIDs(keyHistory), startAfter, limit)
+}
+
+// RunBenchmark times iterations calls of the given kind, e.g. storage reads through the callbacks,
+// so the chain can calibrate the gas it charges for them on this machine.
+// The storage benchmarks write and remove a single key, so store should be a scratch store.
+func (w *Wasmer) RunBenchmark(
+	kind api.BenchmarkKind,
+	iterations uint32,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+) (*api.BenchmarkReport, error) {
+	return api.RunBenchmark(kind, iterations, &gasMeter, store, &goapi, &querier)
+}
+
 // Create will compile the wasm code, and store the resulting pre-compile
 // as well as the original code. Both can be referenced later via CodeID
 // This must be done one time for given code, after which it can be
@@ -65,6 +202,30 @@ func (w *Wasmer) Create(code WasmCode) (CodeID, error) {
 	return api.Create(w.cache, code)
 }
 
+// CreatePlaintext is like Create, but stores a plaintext code, for contracts which must be transparent, e.g.
+// for governance or registries. Their state, messages and results are not encrypted, and messages to them must
+// be plaintext JSON.
+//
+// The mode is part of the chain's state: the keeper must record it with the code, store the code with
+// CreatePlaintext again when it restores a node, and not migrate contracts between codes of different modes.
+func (w *Wasmer) CreatePlaintext(code WasmCode) (CodeID, error) {
+	return api.CreatePlaintext(w.cache, code)
+}
+
+// SetConsensusParams hands the consensus params in the chain's state to the Wasmer. It must be called before the
+// first contract call, after every restart of the node, and whenever governance changes the params. Calls fail
+// until the params are set, so a node cannot run with params of its own.
+func (w *Wasmer) SetConsensusParams(params ConsensusParams) error {
+	return api.SetConsensusParams(w.cache, params)
+}
+
+// SetCodePaused pauses or resumes all contracts of the code, e.g. for an emergency stop by governance. Calls of
+// paused codes fail with types.ContractPausedError on every node. Pausing is not persisted, so the keeper must
+// pause the codes again when the node starts. Snapshots carry the paused codes, see RestoreSnapshot.
+func (w *Wasmer) SetCodePaused(code CodeID, paused bool) error {
+	return api.SetCodePaused(w.cache, code, paused)
+}
+
 // GetCode will load the original wasm code for the given code id.
 // This will only succeed if that code id was previously returned from
 // a call to Create.
@@ -93,27 +254,65 @@ func (w *Wasmer) Instantiate(
 	querier Querier,
 	gasMeter GasMeter,
 	gasLimit uint64,
+	opts CallOptions,
 ) (*types.InitResponse, []byte, uint64, error) {
 	paramBin, err := json.Marshal(env)
 	if err != nil {
 		return nil, nil, 0, err
 	}
-	data, gasUsed, err := api.Instantiate(w.cache, code, paramBin, initMsg, &gasMeter, store, &goapi, &querier, gasLimit)
+	data, gasUsed, err := api.Instantiate(w.cache, code, paramBin, initMsg, &gasMeter, store, &goapi, &querier, gasLimit, opts)
 	if err != nil {
 		return nil, nil, gasUsed, err
 	}
+	resp, key, err := parseInitResult(data)
+	return resp, key, gasUsed, err
+}
 
+// Instantiate2 is like Instantiate, but creates the contract at an address which the creator can predict.
+// env.Contract.Address must be the one api.DeriveContractAddress returns for the checksum of the code, the
+// canonical address of env.Message.Sender and salt, and also initMsg if fixMsg is set. Otherwise the call fails
+// before the contract runs.
+//
+// The caller derives the address first, to fail early if a contract exists there already and to set up the
+// contract's store.
+func (w *Wasmer) Instantiate2(
+	code CodeID,
+	env types.Env,
+	initMsg []byte,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+	salt []byte,
+	fixMsg bool,
+	opts CallOptions,
+) (*types.InitResponse, []byte, uint64, error) {
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, nil, 0, err
+	}
+	data, gasUsed, err := api.Instantiate2(w.cache, code, paramBin, initMsg, &gasMeter, store, &goapi, &querier, gasLimit, salt, fixMsg, opts)
+	if err != nil {
+		return nil, nil, gasUsed, err
+	}
+	resp, key, err := parseInitResult(data)
+	return resp, key, gasUsed, err
+}
+
+// parseInitResult splits the output of an instantiation into the contract's response and its key
+func parseInitResult(data []byte) (*types.InitResponse, []byte, error) {
 	key := data[0:64]
 	var resp types.InitResult
-	err = json.Unmarshal(data[64:], &resp)
+	err := json.Unmarshal(data[64:], &resp)
 	if err != nil {
-		return nil, nil, gasUsed, err
+		return nil, nil, err
 	}
 
 	if resp.Err != nil {
-		return nil, nil, gasUsed, fmt.Errorf("%v", resp.Err)
+		return nil, nil, fmt.Errorf("%v", resp.Err)
 	}
-	return resp.Ok, key, gasUsed, nil
+	return resp.Ok, key, nil
 }
 
 // Execute calls a given contract. Since the only difference between contracts with the same CodeID is the
@@ -122,6 +321,9 @@ func (w *Wasmer) Instantiate(
 //
 // The caller is responsible for passing the correct `store` (which must have been initialized exactly once),
 // and setting the env with relevent info on this instance (address, balance, etc)
+//
+// opts.CallDepth is 0 for calls coming from a transaction, and n + 1 for messages dispatched by a contract
+// called at depth n, see CallOptions.
 func (w *Wasmer) Execute(
 	code CodeID,
 	env types.Env,
@@ -131,13 +333,131 @@ func (w *Wasmer) Execute(
 	querier Querier,
 	gasMeter GasMeter,
 	gasLimit uint64,
+	opts CallOptions,
+) (*types.HandleResponse, uint64, error) {
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, 0, err
+	}
+
+	data, gasUsed, err := api.Handle(w.cache, code, paramBin, executeMsg, &gasMeter, store, &goapi, &querier, gasLimit, opts)
+	if err != nil {
+		return nil, gasUsed, err
+	}
+
+	var resp types.HandleResult
+	err = json.Unmarshal(data, &resp)
+
+	if err != nil {
+		return nil, gasUsed, err
+	}
+
+	if resp.Err != nil {
+		return nil, gasUsed, fmt.Errorf("%v", resp.Err)
+	}
+
+	return resp.Ok, gasUsed, nil
+}
+
+// ExecuteSystem executes a chain-owned contract for the chain itself, e.g. from BeginBlock or EndBlock. It is like
+// Execute, but takes no gas limit: the call is bounded by ConsensusParams.SystemGasLimit instead, and the gas it used
+// should not be charged to anyone.
+func (w *Wasmer) ExecuteSystem(
+	code CodeID,
+	env types.Env,
+	executeMsg []byte,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	opts CallOptions,
+) (*types.HandleResponse, uint64, error) {
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, 0, err
+	}
+
+	data, gasUsed, err := api.HandleSystem(w.cache, code, paramBin, executeMsg, &gasMeter, store, &goapi, &querier, opts)
+	if err != nil {
+		return nil, gasUsed, err
+	}
+
+	var resp types.HandleResult
+	err = json.Unmarshal(data, &resp)
+
+	if err != nil {
+		return nil, gasUsed, err
+	}
+
+	if resp.Err != nil {
+		return nil, gasUsed, fmt.Errorf("%v", resp.Err)
+	}
+
+	return resp.Ok, gasUsed, nil
+}
+
+// BeginBlock runs the begin_block export of a contract registered for it at the beginning of block. env is the env
+// of the contract, its block and message are replaced. Like ExecuteSystem, the call is bounded by
+// ConsensusParams.SystemGasLimit and the gas it used should not be charged to anyone.
+func (w *Wasmer) BeginBlock(
+	code CodeID,
+	env types.Env,
+	block types.BlockInfo,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	opts CallOptions,
+) (*types.HandleResponse, uint64, error) {
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, 0, err
+	}
+	blockBin, err := json.Marshal(block)
+	if err != nil {
+		return nil, 0, err
+	}
+
+	data, gasUsed, err := api.BeginBlock(w.cache, code, paramBin, blockBin, &gasMeter, store, &goapi, &querier, opts)
+	if err != nil {
+		return nil, gasUsed, err
+	}
+
+	var resp types.HandleResult
+	err = json.Unmarshal(data, &resp)
+
+	if err != nil {
+		return nil, gasUsed, err
+	}
+
+	if resp.Err != nil {
+		return nil, gasUsed, fmt.Errorf("%v", resp.Err)
+	}
+
+	return resp.Ok, gasUsed, nil
+}
+
+// EndBlock runs the end_block export of a contract registered for it at the end of block, like BeginBlock.
+func (w *Wasmer) EndBlock(
+	code CodeID,
+	env types.Env,
+	block types.BlockInfo,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	opts CallOptions,
 ) (*types.HandleResponse, uint64, error) {
 	paramBin, err := json.Marshal(env)
 	if err != nil {
 		return nil, 0, err
 	}
+	blockBin, err := json.Marshal(block)
+	if err != nil {
+		return nil, 0, err
+	}
 
-	data, gasUsed, err := api.Handle(w.cache, code, paramBin, executeMsg, &gasMeter, store, &goapi, &querier, gasLimit)
+	data, gasUsed, err := api.EndBlock(w.cache, code, paramBin, blockBin, &gasMeter, store, &goapi, &querier, opts)
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -159,6 +479,9 @@ func (w *Wasmer) Execute(
 // Query allows a client to execute a contract-specific query. If the result is not empty, it should be
 // valid json-encoded data to return to the client.
 // The meaning of path and data can be determined by the code. Path is the suffix of the abci.QueryRequest.Path
+//
+// opts.Block is the block whose state the store serves, e.g. a past height for historical queries, or zero for
+// the latest state, see CallOptions.
 func (w *Wasmer) Query(
 	code CodeID,
 	queryMsg []byte,
@@ -167,8 +490,9 @@ func (w *Wasmer) Query(
 	querier Querier,
 	gasMeter GasMeter,
 	gasLimit uint64,
+	opts CallOptions,
 ) ([]byte, uint64, error) {
-	data, gasUsed, err := api.Query(w.cache, code, queryMsg, &gasMeter, store, &goapi, &querier, gasLimit)
+	data, gasUsed, err := api.Query(w.cache, code, queryMsg, &gasMeter, store, &goapi, &querier, gasLimit, opts)
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -184,12 +508,124 @@ func (w *Wasmer) Query(
 	return resp.Ok, gasUsed, nil
 }
 
+// QueryUnmetered is like Query, but runs without a gas limit and reports no gas. The cache must have been
+// created with AllowUnmeteredQueries, which is meant for analytics tooling on archive nodes.
+func (w *Wasmer) QueryUnmetered(
+	code CodeID,
+	queryMsg []byte,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	opts CallOptions,
+) ([]byte, error) {
+	data, err := api.QueryUnmetered(w.cache, code, queryMsg, &gasMeter, store, &goapi, &querier, opts)
+	if err != nil {
+		return nil, err
+	}
+
+	var resp types.QueryResponse
+	err = json.Unmarshal(data, &resp)
+	if err != nil {
+		return nil, err
+	}
+	if resp.Err != nil {
+		return nil, fmt.Errorf("%v", resp.Err)
+	}
+	return resp.Ok, nil
+}
+
+// QueryCached is like Query for the contract at contractAddr, but reuses the result of the same query at the
+// same height if the cache was created with QueryCacheSize, so hot public queries do not run the contract
+// again. opts.Block must be the committed height the store reads. Queries the contracts make must use Query, as the
+// store charges no gas for reused results.
+func (w *Wasmer) QueryCached(
+	contractAddr string,
+	code CodeID,
+	queryMsg []byte,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	data, gasUsed, err := api.QueryCached(w.cache, contractAddr, code, queryMsg, &gasMeter, store, &goapi, &querier, gasLimit, opts)
+	if err != nil {
+		return nil, gasUsed, err
+	}
+
+	var resp types.QueryResponse
+	err = json.Unmarshal(data, &resp)
+	if err != nil {
+		return nil, gasUsed, err
+	}
+	if resp.Err != nil {
+		return nil, gasUsed, fmt.Errorf("%v", resp.Err)
+	}
+	return resp.Ok, gasUsed, nil
+}
+
+// QueryBatch runs several queries against one snapshot of the state, so dashboards aggregating over contracts
+// see a consistent state. store must implement ContractResolver and read a single height, which opts.Block
+// describes. The results are in the order of the queries, and a failed query only sets the Error of its result.
+// The Ok of a result is the raw result of the contract, which has not been checked for a contract error.
+func (w *Wasmer) QueryBatch(
+	queries []BatchQuery,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+	opts CallOptions,
+) ([]BatchQueryResult, uint64, error) {
+	return api.QueryBatch(w.cache, queries, &gasMeter, store, &goapi, &querier, gasLimit, opts)
+}
+
+// RegisterGasSchedule reprices wasm execution from the block at activationHeight on, e.g. in the upgrade
+// handler of a governance proposal. Every node switches at the same block, whenever it was upgraded. Schedules
+// are not persisted, so the keeper must register them again when the node starts.
+func (w *Wasmer) RegisterGasSchedule(activationHeight uint64, costs WasmCosts) error {
+	return api.RegisterGasSchedule(w.cache, activationHeight, costs)
+}
+
+// SetQueryRoutes replaces the well-known queries of QueryByAlias by alias, so infrastructure can query e.g. the
+// token registry without hardcoding its address. Routes are not persisted, so they must be set again when the
+// node starts.
+func (w *Wasmer) SetQueryRoutes(routes map[string]QueryRoute) error {
+	return api.SetQueryRoutes(w.cache, routes)
+}
+
+// QueryByAlias queries the contract of the route alias with its message rendered with args. store must implement
+// ContractResolver, as for QueryBatch. The query fails with types.CallDeniedError if the contract no longer runs
+// the code of the route.
+func (w *Wasmer) QueryByAlias(
+	alias string,
+	args map[string]interface{},
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+	opts CallOptions,
+) ([]byte, uint64, error) {
+	return api.QueryByAlias(w.cache, alias, args, &gasMeter, store, &goapi, &querier, gasLimit, opts)
+}
+
 // Migrate will migrate an existing contract to a new code binary.
 // This takes storage of the data from the original contract and the CodeID of the new contract that should
 // replace it. This allows it to run a migration step if needed, or return an error if unable to migrate
 // the given data.
 //
 // MigrateMsg has some data on how to perform the migration.
+//
+// keyHistory lists the codes the contract ran before, oldest first, ending with the code it is migrated from.
+// The enclave keeps reading the state encrypted with their keys, and encrypts what the contract writes with the
+// key of the new code. env.ContractKey must be the key issued for the last of them. The keeper has to keep the
+// history of every contract, and may pass none for contracts without encrypted state.
+//
+// auth is the admin the keeper stores for the contract and whether governance runs the migration. The migration
+// fails unless governance runs it or env.Message.Sender is the admin, even if the keeper checked that already.
 func (w *Wasmer) Migrate(
 	code CodeID,
 	env types.Env,
@@ -199,12 +635,15 @@ func (w *Wasmer) Migrate(
 	querier Querier,
 	gasMeter GasMeter,
 	gasLimit uint64,
+	keyHistory []CodeID,
+	auth MigrateAuth,
+	opts CallOptions,
 ) (*types.MigrateResponse, uint64, error) {
 	paramBin, err := json.Marshal(env)
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.Migrate(w.cache, code, paramBin, migrateMsg, &gasMeter, store, &goapi, &querier, gasLimit)
+	data, gasUsed, err := api.Migrate(w.cache, code, paramBin, migrateMsg, &gasMeter, store, &goapi, &querier, gasLimit, codeIDs(keyHistory), auth, opts)
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -219,3 +658,12 @@ func (w *Wasmer) Migrate(
 	}
 	return resp.Ok, gasUsed, nil
 }
+
+// codeIDs converts code ids for the api package
+func codeIDs(ids []CodeID) [][]byte {
+	out := make([][]byte, len(ids))
+	for i, id := range ids {
+		out[i] = id
+	}
+	return out
+}
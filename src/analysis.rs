@@ -0,0 +1,546 @@
+//! A static analysis of wasm codes, reading only the sections which tell what a contract exports and needs,
+//! and whether its code uses floats
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+
+/// The entry points the VM calls, and whether a contract must export them
+const ENTRY_POINTS: &[(&str, bool)] = &[
+    ("init", true),
+    ("handle", true),
+    ("query", true),
+    ("migrate", false),
+    ("allocate", true),
+    ("deallocate", true),
+];
+
+const REQUIRES_PREFIX: &str = "requires_";
+const VERSION_PREFIX: &str = "cosmwasm_vm_version_";
+
+/// The value types `f32` and `f64`
+const FLOAT_TYPES: &[u8] = &[0x7d, 0x7c];
+
+/// What a wasm code exports and imports
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Analysis {
+    /// The sha256 of the code in hex, which is its code id
+    pub checksum: String,
+    pub size: usize,
+    /// The interface version from the `cosmwasm_vm_version_*` export
+    pub interface_version: Option<String>,
+    /// The entry points of `ENTRY_POINTS` the code exports
+    pub entry_points: Vec<String>,
+    /// The entry points the VM requires but the code does not export
+    pub missing_entry_points: Vec<String>,
+    /// The features from the `requires_*` exports, which the node must support
+    pub required_features: Vec<String>,
+    /// Every export, by name
+    pub exports: Vec<String>,
+    /// Every import, as `module.name`
+    pub imports: Vec<String>,
+    /// The initial size of the memory the code defines or imports, in 64 KiB pages
+    pub memory_pages: Option<u32>,
+    /// Whether the code has float values or operations anywhere, see `float_policy`
+    pub uses_floats: bool,
+}
+
+pub fn analyze(wasm: &[u8]) -> Result<Analysis, Error> {
+    let mut reader = Reader { data: wasm, pos: 0 };
+    if reader.bytes(4)? != b"\0asm" {
+        return Err(reader.error("not a wasm module"));
+    }
+    if reader.bytes(4)? != [1, 0, 0, 0] {
+        return Err(reader.error("unsupported wasm version"));
+    }
+
+    let mut analysis = Analysis {
+        checksum: hex::encode(Sha256::digest(wasm)),
+        size: wasm.len(),
+        ..Analysis::default()
+    };
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let size = reader.leb()? as usize;
+        let mut section = Reader {
+            data: reader.bytes(size)?,
+            pos: 0,
+        };
+        match id {
+            1 => analysis.uses_floats |= types_use_floats(&mut section)?,
+            2 => {
+                let (imports, memory_pages, uses_floats) = read_imports(&mut section)?;
+                analysis.imports = imports;
+                analysis.memory_pages = analysis.memory_pages.or(memory_pages);
+                analysis.uses_floats |= uses_floats;
+            }
+            5 => analysis.memory_pages = read_memories(&mut section)?.or(analysis.memory_pages),
+            6 => analysis.uses_floats |= globals_use_floats(&mut section)?,
+            7 => analysis.exports = read_exports(&mut section)?,
+            10 => analysis.uses_floats |= code_uses_floats(&mut section)?,
+            _ => {}
+        }
+    }
+
+    for (name, required) in ENTRY_POINTS {
+        if analysis.exports.iter().any(|export| export == name) {
+            analysis.entry_points.push(name.to_string());
+        } else if *required {
+            analysis.missing_entry_points.push(name.to_string());
+        }
+    }
+    for export in &analysis.exports {
+        if let Some(feature) = export.strip_prefix(REQUIRES_PREFIX) {
+            analysis.required_features.push(feature.to_string());
+        } else if let Some(version) = export.strip_prefix(VERSION_PREFIX) {
+            analysis.interface_version = Some(version.to_string());
+        }
+    }
+    Ok(analysis)
+}
+
+/// The imports, the initial pages of an imported memory, and whether a float global is imported
+fn read_imports(section: &mut Reader) -> Result<(Vec<String>, Option<u32>, bool), Error> {
+    let count = section.leb()?;
+    let mut imports = Vec::new();
+    let mut memory_pages = None;
+    let mut uses_floats = false;
+    for _ in 0..count {
+        let module = section.name()?;
+        let name = section.name()?;
+        match section.byte()? {
+            // function: type index
+            0 => {
+                section.leb()?;
+            }
+            // table: element type and limits
+            1 => {
+                section.byte()?;
+                section.limits()?;
+            }
+            // memory: limits
+            2 => memory_pages = Some(section.limits()?),
+            // global: value type and mutability
+            3 => {
+                uses_floats |= FLOAT_TYPES.contains(&section.bytes(2)?[0]);
+            }
+            kind => return Err(section.error(format!("unknown import kind {}", kind))),
+        }
+        imports.push(format!("{}.{}", module, name));
+    }
+    Ok((imports, memory_pages, uses_floats))
+}
+
+/// The initial pages of the first memory the code defines. Wasm allows at most one.
+fn read_memories(section: &mut Reader) -> Result<Option<u32>, Error> {
+    let count = section.leb()?;
+    let mut memory_pages = None;
+    for _ in 0..count {
+        let initial = section.limits()?;
+        memory_pages = memory_pages.or(Some(initial));
+    }
+    Ok(memory_pages)
+}
+
+/// Whether a function type has float parameters or results
+fn types_use_floats(section: &mut Reader) -> Result<bool, Error> {
+    let count = section.leb()?;
+    let mut uses_floats = false;
+    for _ in 0..count {
+        if section.byte()? != 0x60 {
+            return Err(section.error("unknown type form"));
+        }
+        // parameters and results
+        for _ in 0..2 {
+            let len = section.leb()? as usize;
+            uses_floats |= section.bytes(len)?.iter().any(|t| FLOAT_TYPES.contains(t));
+        }
+    }
+    Ok(uses_floats)
+}
+
+/// Whether a global of the code is a float
+fn globals_use_floats(section: &mut Reader) -> Result<bool, Error> {
+    let count = section.leb()?;
+    let mut uses_floats = false;
+    for _ in 0..count {
+        // value type and mutability, then the initializer, which is a constant expression
+        uses_floats |= FLOAT_TYPES.contains(&section.bytes(2)?[0]);
+        loop {
+            match section.byte()? {
+                END => break,
+                SIMD_PREFIX => return Ok(true),
+                opcode => uses_floats |= instruction_uses_floats(section, opcode)?,
+            }
+        }
+    }
+    Ok(uses_floats)
+}
+
+/// Whether a function body has float locals or instructions
+fn code_uses_floats(section: &mut Reader) -> Result<bool, Error> {
+    let count = section.leb()?;
+    let mut uses_floats = false;
+    for _ in 0..count {
+        let size = section.leb()? as usize;
+        let mut body = Reader {
+            data: section.bytes(size)?,
+            pos: 0,
+        };
+        let local_groups = body.leb()?;
+        for _ in 0..local_groups {
+            body.leb()?;
+            uses_floats |= FLOAT_TYPES.contains(&body.byte()?);
+        }
+        while !body.is_empty() {
+            let opcode = body.byte()?;
+            uses_floats |= instruction_uses_floats(&mut body, opcode)?;
+        }
+    }
+    Ok(uses_floats)
+}
+
+const END: u8 = 0x0b;
+const SIMD_PREFIX: u8 = 0xfd;
+
+/// Reads the immediates of the instruction `opcode` and tells whether it operates on floats. Covers the MVP
+/// and the sign extension, saturating truncation, bulk memory and reference types proposals. SIMD counts as
+/// using floats, as most of it works on float lanes.
+fn instruction_uses_floats(reader: &mut Reader, opcode: u8) -> Result<bool, Error> {
+    let uses_floats = match opcode {
+        // unreachable, nop, else, end, return, drop, select, ref.is_null
+        0x00 | 0x01 | 0x05 | END | 0x0f | 0x1a | 0x1b | 0xd1 => false,
+        // block, loop, if: an empty block type, a value type, or a type index
+        0x02..=0x04 => {
+            let block_type = reader.byte()?;
+            if block_type & 0x80 != 0 {
+                reader.sleb()?;
+            }
+            FLOAT_TYPES.contains(&block_type)
+        }
+        // br, br_if, call, local.*, global.*, table.get, table.set, ref.func
+        0x0c | 0x0d | 0x10 | 0x20..=0x26 | 0xd2 => {
+            reader.leb()?;
+            false
+        }
+        // br_table: the labels and the default label
+        0x0e => {
+            let count = reader.leb()?;
+            for _ in 0..=count {
+                reader.leb()?;
+            }
+            false
+        }
+        // call_indirect: type and table index
+        0x11 => {
+            reader.leb()?;
+            reader.leb()?;
+            false
+        }
+        // select with value types
+        0x1c => {
+            let len = reader.leb()? as usize;
+            reader.bytes(len)?.iter().any(|t| FLOAT_TYPES.contains(t))
+        }
+        // loads and stores: alignment and offset
+        0x28..=0x3e => {
+            reader.leb()?;
+            reader.leb()?;
+            // f32.load, f64.load, f32.store, f64.store
+            matches!(opcode, 0x2a | 0x2b | 0x38 | 0x39)
+        }
+        // memory.size, memory.grow: the memory index
+        0x3f | 0x40 => {
+            reader.byte()?;
+            false
+        }
+        0x41 | 0x42 => {
+            reader.sleb()?;
+            false
+        }
+        // f32.const, f64.const
+        0x43 => {
+            reader.bytes(4)?;
+            true
+        }
+        0x44 => {
+            reader.bytes(8)?;
+            true
+        }
+        // integer comparisons
+        0x45..=0x5a => false,
+        // float comparisons
+        0x5b..=0x66 => true,
+        // integer arithmetic
+        0x67..=0x8a => false,
+        // float arithmetic
+        0x8b..=0xa6 => true,
+        // i32.wrap_i64, i64.extend_i32_s, i64.extend_i32_u
+        0xa7 | 0xac | 0xad => false,
+        // the other conversions, which all take or produce floats
+        0xa8..=0xbf => true,
+        // sign extension
+        0xc0..=0xc4 => false,
+        // ref.null: the reference type
+        0xd0 => {
+            reader.byte()?;
+            false
+        }
+        0xfc => match reader.leb()? {
+            // saturating truncation of floats
+            0..=7 => true,
+            // memory.init: data index and memory index
+            8 => {
+                reader.leb()?;
+                reader.byte()?;
+                false
+            }
+            // data.drop, elem.drop, table.grow, table.size, table.fill
+            9 | 13 | 15..=17 => {
+                reader.leb()?;
+                false
+            }
+            // memory.copy: two memory indices
+            10 => {
+                reader.bytes(2)?;
+                false
+            }
+            // memory.fill
+            11 => {
+                reader.byte()?;
+                false
+            }
+            // table.init, table.copy
+            12 | 14 => {
+                reader.leb()?;
+                reader.leb()?;
+                false
+            }
+            op => return Err(reader.error(format!("unknown instruction 0xfc {}", op))),
+        },
+        SIMD_PREFIX => {
+            // the instructions after this cannot be decoded without decoding SIMD
+            reader.pos = reader.data.len();
+            true
+        }
+        _ => return Err(reader.error(format!("unknown instruction 0x{:02x}", opcode))),
+    };
+    Ok(uses_floats)
+}
+
+fn read_exports(section: &mut Reader) -> Result<Vec<String>, Error> {
+    let count = section.leb()?;
+    let mut exports = Vec::new();
+    for _ in 0..count {
+        exports.push(section.name()?);
+        // kind and index
+        section.byte()?;
+        section.leb()?;
+    }
+    Ok(exports)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error<S: ToString>(&self, msg: S) -> Error {
+        Error::invalid_arg("wasm", msg)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() - self.pos < len {
+            return Err(self.error("unexpected end of the module"));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// An unsigned LEB128 integer, as wasm encodes sizes and indices
+    fn leb(&mut self) -> Result<u32, Error> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(self.error("integer too long"))
+    }
+
+    /// A signed LEB128 integer, which is only skipped
+    fn sleb(&mut self) -> Result<(), Error> {
+        for _ in 0..10 {
+            if self.byte()? & 0x80 == 0 {
+                return Ok(());
+            }
+        }
+        Err(self.error("integer too long"))
+    }
+
+    fn name(&mut self) -> Result<String, Error> {
+        let len = self.leb()? as usize;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.error("name is not UTF-8"))
+    }
+
+    /// Limits of a table or memory, returning the initial size
+    fn limits(&mut self) -> Result<u32, Error> {
+        let has_max = self.byte()? & 1 == 1;
+        let initial = self.leb()?;
+        if has_max {
+            self.leb()?;
+        }
+        Ok(initial)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(s: &str) -> Vec<u8> {
+        let mut out = vec![s.len() as u8];
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    fn section(id: u8, body: Vec<u8>) -> Vec<u8> {
+        let mut out = vec![id, body.len() as u8];
+        out.extend(body);
+        out
+    }
+
+    /// A module importing `env.db_read` and a memory, and exporting the given functions
+    fn module(exports: &[&str]) -> Vec<u8> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        let mut imports = vec![2];
+        imports.extend(name("env"));
+        imports.extend(name("db_read"));
+        imports.extend(&[0, 0]);
+        imports.extend(name("env"));
+        imports.extend(name("memory"));
+        imports.extend(&[2, 1, 1, 16]);
+        wasm.extend(section(2, imports));
+        // a custom section, which is skipped
+        wasm.extend(section(0, name("producers")));
+        let mut body = vec![exports.len() as u8];
+        for export in exports {
+            body.extend(name(export));
+            body.extend(&[0, 0]);
+        }
+        wasm.extend(section(7, body));
+        wasm
+    }
+
+    #[test]
+    fn analyze_works() {
+        let wasm = module(&[
+            "init",
+            "handle",
+            "query",
+            "allocate",
+            "deallocate",
+            "requires_staking",
+            "cosmwasm_vm_version_3",
+        ]);
+        let analysis = analyze(&wasm).unwrap();
+        assert_eq!(analysis.size, wasm.len());
+        assert_eq!(analysis.checksum.len(), 64);
+        assert_eq!(analysis.interface_version, Some("3".to_string()));
+        assert_eq!(
+            analysis.entry_points,
+            vec!["init", "handle", "query", "allocate", "deallocate"]
+        );
+        assert!(analysis.missing_entry_points.is_empty());
+        assert_eq!(analysis.required_features, vec!["staking"]);
+        assert_eq!(analysis.imports, vec!["env.db_read", "env.memory"]);
+        assert_eq!(analysis.exports.len(), 7);
+        assert_eq!(analysis.memory_pages, Some(1));
+    }
+
+    #[test]
+    fn analyze_reports_missing_entry_points() {
+        let analysis = analyze(&module(&["init", "migrate", "allocate"])).unwrap();
+        assert_eq!(analysis.entry_points, vec!["init", "migrate", "allocate"]);
+        assert_eq!(
+            analysis.missing_entry_points,
+            vec!["handle", "query", "deallocate"]
+        );
+        assert_eq!(analysis.interface_version, None);
+    }
+
+    #[test]
+    fn analyze_reads_defined_memory() {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        // one memory of 17 pages without maximum
+        wasm.extend(section(5, vec![1, 0, 17]));
+        assert_eq!(analyze(&wasm).unwrap().memory_pages, Some(17));
+        assert_eq!(analyze(b"\0asm\x01\0\0\0").unwrap().memory_pages, None);
+    }
+
+    /// A module with one function of type `() -> result`, whose body has the given locals and instructions
+    fn function(result: u8, locals: &[u8], instructions: &[u8]) -> Vec<u8> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.extend(section(1, vec![1, 0x60, 0, 1, result]));
+        wasm.extend(section(3, vec![1, 0]));
+        let mut body = vec![locals.len() as u8];
+        for local in locals {
+            body.extend(&[1, *local]);
+        }
+        body.extend(instructions);
+        body.push(END);
+        let mut code = vec![1, body.len() as u8];
+        code.extend(body);
+        wasm.extend(section(10, code));
+        wasm
+    }
+
+    #[test]
+    fn analyze_detects_floats() {
+        // i32.const 1, i32.const 2, i32.add inside a block, and a load with alignment and offset
+        let ints = function(
+            0x7f,
+            &[0x7e],
+            &[
+                0x02, 0x40, 0x41, 1, 0x41, 2, 0x6a, 0x1a, END, 0x41, 0, 0x28, 2, 8,
+            ],
+        );
+        assert!(!analyze(&ints).unwrap().uses_floats);
+        assert!(!analyze(&module(&["init"])).unwrap().uses_floats);
+
+        // f32.const 1.0, i32.trunc_f32_s
+        let op = function(0x7f, &[], &[0x43, 0, 0, 0x80, 0x3f, 0xa8]);
+        assert!(analyze(&op).unwrap().uses_floats);
+        let local = function(0x7f, &[0x7c], &[0x41, 0]);
+        assert!(analyze(&local).unwrap().uses_floats);
+        let signature = function(0x7d, &[], &[0x00]);
+        assert!(analyze(&signature).unwrap().uses_floats);
+        let saturating = function(0x7f, &[], &[0x41, 0, 0xfc, 0]);
+        assert!(analyze(&saturating).unwrap().uses_floats);
+
+        let unknown = function(0x7f, &[], &[0xff]);
+        assert!(analyze(&unknown).is_err());
+    }
+
+    #[test]
+    fn analyze_rejects_invalid_modules() {
+        assert!(analyze(b"some invalid data").is_err());
+        let mut truncated = module(&["init"]);
+        truncated.pop();
+        assert!(analyze(&truncated).is_err());
+    }
+}
@@ -1,8 +1,11 @@
-use cosmwasm_sgx_vm::{Api, FfiError, FfiResult, GasInfo};
 use cosmwasm_std::{Binary, CanonicalAddr, HumanAddr};
 
+use crate::capabilities::{self, Capability};
 use crate::error::GoResult;
+use crate::gas_report;
+use crate::host_calls::HostCall;
 use crate::memory::Buffer;
+use crate::vm::{Api, FfiError, FfiResult, GasInfo};
 
 // this represents something passed in from the caller side of FFI
 // in this case a struct with go function pointers
@@ -38,8 +41,11 @@ unsafe impl Send for GoApi {}
 
 impl Api for GoApi {
     fn canonical_address(&self, human: &HumanAddr) -> FfiResult<CanonicalAddr> {
+        if let Err(e) = capabilities::require(Capability::AddressApi) {
+            return (Err(e), GasInfo::free());
+        }
         let human_bytes = human.as_str().as_bytes();
-        let human_bytes = Buffer::from_vec(human_bytes.to_vec());
+        let human_bytes = Buffer::temp(human_bytes);
         let mut output = Buffer::default();
         let mut err = Buffer::default();
         let mut used_gas = 0_u64;
@@ -51,8 +57,8 @@ impl Api for GoApi {
             &mut used_gas as *mut u64,
         )
         .into();
-        let gas_info = GasInfo::with_cost(used_gas);
-        let _human = unsafe { human_bytes.consume() };
+        let gas_info = gas_report::record_host_call(HostCall::CanonicalizeAddress, used_gas);
+        unsafe { human_bytes.release_temp() };
 
         // return complete error message (reading from buffer for GoResult::Other)
         let default = || format!("Failed to canonicalize the address: {}", human);
@@ -73,8 +79,11 @@ impl Api for GoApi {
     }
 
     fn human_address(&self, canonical: &CanonicalAddr) -> FfiResult<HumanAddr> {
+        if let Err(e) = capabilities::require(Capability::AddressApi) {
+            return (Err(e), GasInfo::free());
+        }
         let canonical_bytes = canonical.as_slice();
-        let canonical_buf = Buffer::from_vec(canonical_bytes.to_vec());
+        let canonical_buf = Buffer::temp(canonical_bytes);
         let mut output = Buffer::default();
         let mut err = Buffer::default();
         let mut used_gas = 0_u64;
@@ -86,8 +95,8 @@ impl Api for GoApi {
             &mut used_gas as *mut u64,
         )
         .into();
-        let gas_info = GasInfo::with_cost(used_gas);
-        let _canonical = unsafe { canonical_buf.consume() };
+        let gas_info = gas_report::record_host_call(HostCall::HumanizeAddress, used_gas);
+        unsafe { canonical_buf.release_temp() };
 
         // return complete error message (reading from buffer for GoResult::Other)
         let default = || format!("Failed to humanize the address: {}", canonical);
@@ -0,0 +1,256 @@
+//! Contract calls which return at once, so Go need not block an OS thread in cgo for each of them.
+//!
+//! `handle_async` and `query_async` queue a call on the `executor` and return a handle for it, which is never 0.
+//! Go learns the call is done either through the optional `on_done` callback, which we call with the handle from
+//! the thread of the call, or by polling `poll_async_call`. Either way it then takes the result, the gas used and
+//! the gas report with `take_async_result`, which forgets the handle. Calling back into the library from `on_done`
+//! is not supported: it should only hand the handle over, e.g. to a channel. The calls run in the order they were
+//! started, so the state changes of a block apply in its order.
+//!
+//! The arguments of a call are copied before it starts, but its DB, API and querier are used until it is done,
+//! so Go must keep their states alive until then. The results of calls nobody takes stay in memory.
+//! `release_cache` waits until the calls using the cache are done, see `wait_for`.
+
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+use lazy_static::lazy_static;
+
+use crate::error::Error;
+use crate::executor;
+use crate::gas_report::GasReport;
+
+const HANDLE_ARG: &str = "handle";
+
+/// Called by the thread of a call with its handle once it is done
+pub type OnDone = extern "C" fn(u64);
+
+/// What a call returns to Go once it is done
+pub struct Outcome {
+    pub result: Result<Vec<u8>, Error>,
+    pub gas_used: u64,
+    pub gas_report: GasReport,
+}
+
+impl Outcome {
+    fn panic() -> Self {
+        Outcome {
+            result: Err(Error::panic()),
+            gas_used: 0,
+            gas_report: GasReport::default(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Calls {
+    last_handle: u64,
+    /// The calls started and not yet taken, with their outcome once they are done
+    outcomes: HashMap<u64, Option<Outcome>>,
+    /// The number of calls not yet done by the cache they use
+    pending: HashMap<usize, usize>,
+}
+
+impl Calls {
+    fn done(&mut self, cache: usize) {
+        if let Some(count) = self.pending.get_mut(&cache) {
+            *count -= 1;
+            if *count == 0 {
+                self.pending.remove(&cache);
+            }
+        }
+    }
+}
+
+fn lock(calls: &Mutex<Calls>) -> MutexGuard<'_, Calls> {
+    // a panic while holding the lock does not leave the calls inconsistent
+    calls.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+lazy_static! {
+    static ref CALLS: Mutex<Calls> = Mutex::new(Calls::default());
+    /// Signalled whenever a call is done
+    static ref CALL_DONE: Condvar = Condvar::new();
+}
+
+/// Queues `call`, which uses the cache at the address `cache`, on the executor and returns its handle
+pub fn start<F>(cache: usize, call: F, on_done: Option<OnDone>) -> Result<u64, Error>
+where
+    F: FnOnce() -> Outcome + Send + 'static,
+{
+    let handle = {
+        let mut calls = lock(&CALLS);
+        calls.last_handle += 1;
+        let handle = calls.last_handle;
+        calls.outcomes.insert(handle, None);
+        *calls.pending.entry(cache).or_insert(0) += 1;
+        handle
+    };
+    let queued = executor::submit(move || {
+        let outcome = catch_unwind(AssertUnwindSafe(call)).unwrap_or_else(|_| Outcome::panic());
+        {
+            let mut calls = lock(&CALLS);
+            calls.outcomes.insert(handle, Some(outcome));
+            calls.done(cache);
+        }
+        CALL_DONE.notify_all();
+        if let Some(on_done) = on_done {
+            on_done(handle);
+        }
+    });
+    if let Err(e) = queued {
+        let mut calls = lock(&CALLS);
+        calls.outcomes.remove(&handle);
+        calls.done(cache);
+        return Err(e);
+    }
+    Ok(handle)
+}
+
+/// Waits until the calls using the cache at the address `cache` are done, so it can be released. Their
+/// outcomes stay until they are taken.
+pub fn wait_for(cache: usize) {
+    let mut calls = lock(&CALLS);
+    while calls.pending.contains_key(&cache) {
+        calls = CALL_DONE
+            .wait(calls)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+}
+
+/// Whether the call with `handle` is done
+pub fn is_done(handle: u64) -> Result<bool, Error> {
+    match lock(&CALLS).outcomes.get(&handle) {
+        Some(outcome) => Ok(outcome.is_some()),
+        None => Err(Error::invalid_arg(HANDLE_ARG, "unknown call")),
+    }
+}
+
+/// Takes the outcome of the call with `handle` once it is done, and forgets the handle
+pub fn take(handle: u64) -> Result<Outcome, Error> {
+    let mut calls = lock(&CALLS);
+    match calls.outcomes.get(&handle) {
+        Some(Some(_)) => {}
+        Some(None) => return Err(Error::invalid_arg(HANDLE_ARG, "the call is still running")),
+        None => return Err(Error::invalid_arg(HANDLE_ARG, "unknown call")),
+    }
+    Ok(calls.outcomes.remove(&handle).flatten().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// The handle `on_done` was called with last
+    static DONE: AtomicU64 = AtomicU64::new(0);
+
+    extern "C" fn on_done(handle: u64) {
+        DONE.store(handle, Ordering::SeqCst);
+    }
+
+    fn outcome(result: Result<Vec<u8>, Error>) -> Outcome {
+        Outcome {
+            result,
+            gas_used: 42,
+            gas_report: GasReport::default(),
+        }
+    }
+
+    fn wait(handle: u64) {
+        while !is_done(handle).unwrap() {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn start_runs_the_call() {
+        let (tx, rx) = channel();
+        let handle = start(
+            1,
+            move || {
+                rx.recv().unwrap();
+                outcome(Ok(b"result".to_vec()))
+            },
+            None,
+        )
+        .unwrap();
+        assert_ne!(handle, 0);
+        assert!(!is_done(handle).unwrap());
+        assert!(take(handle).is_err());
+
+        tx.send(()).unwrap();
+        wait(handle);
+        let done = take(handle).unwrap();
+        assert_eq!(done.result.unwrap(), b"result");
+        assert_eq!(done.gas_used, 42);
+        // the handle is forgotten
+        assert!(is_done(handle).is_err());
+        assert!(take(handle).is_err());
+    }
+
+    #[test]
+    fn start_calls_on_done() {
+        let handle = start(2, || outcome(Err(Error::out_of_gas())), Some(on_done)).unwrap();
+        while DONE.load(Ordering::SeqCst) != handle {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(is_done(handle).unwrap());
+        match take(handle).unwrap().result {
+            Err(Error::OutOfGas { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn start_catches_panics() {
+        let handle = start(3, || panic!("boom"), None).unwrap();
+        wait(handle);
+        match take(handle).unwrap().result {
+            Err(Error::Panic { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn wait_for_waits_until_the_calls_of_the_cache_are_done() {
+        let (tx, rx) = channel();
+        let handle = start(
+            4,
+            move || {
+                rx.recv().unwrap();
+                outcome(Ok(vec![]))
+            },
+            None,
+        )
+        .unwrap();
+        // other caches are not waited for
+        wait_for(5);
+
+        let waited = Arc::new(AtomicBool::new(false));
+        let waiting = {
+            let waited = waited.clone();
+            thread::spawn(move || {
+                wait_for(4);
+                waited.store(true, Ordering::SeqCst);
+            })
+        };
+        thread::sleep(Duration::from_millis(10));
+        assert!(!waited.load(Ordering::SeqCst));
+        tx.send(()).unwrap();
+        waiting.join().unwrap();
+        assert!(is_done(handle).unwrap());
+        take(handle).unwrap();
+    }
+
+    #[test]
+    fn unknown_handles_fail() {
+        assert!(is_done(0).is_err());
+        assert!(take(u64::MAX).is_err());
+    }
+}
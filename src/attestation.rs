@@ -0,0 +1,292 @@
+//! Live attestation of a node by a verifier outside the network, e.g. a cross-chain bridge.
+//!
+//! The certificate a node registered with may be months old, and says nothing about the enclave running now.
+//! A bridge wanting a fresh proof picks a nonce and asks the node for `attestation_challenge`, which has the
+//! enclave create a quote whose report data is `key_attestation::report_data` of its registration keys and the
+//! nonce. The bridge sends the quote to IAS itself, so it does not rely on the node's connection to IAS, and
+//! checks the answer with `verify_attestation_response`:
+//!
+//! - the enclave checks the signature of the report with the signing certificate, and the certificate with
+//!   the IAS root certificate it pins
+//! - the report is for this quote, is not older than the maximum age, and has a trusted quote status
+//! - the quote commits to the keys the bridge expects and to its nonce, so it cannot be replayed
+//!
+//! The result tells the measurements of the enclave, which the bridge compares with the ones it trusts.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::key_attestation::{self, CHALLENGE_LEN};
+use crate::tcb_recovery;
+
+pub const KEYS_LEN: usize = 64;
+const KEYS_ARG: &str = "keys";
+const QUOTE_ARG: &str = "quote";
+const RESPONSE_ARG: &str = "response";
+
+/// The quote without its signature, as IAS returns it in `isvEnclaveQuoteBody`
+const QUOTE_BODY_LEN: usize = 432;
+/// Offsets into the quote, which is a 48 bytes header followed by the report body of the enclave
+const MRENCLAVE_OFFSET: usize = 112;
+const MRSIGNER_OFFSET: usize = 176;
+const REPORT_DATA_OFFSET: usize = 368;
+
+/// The quote statuses of enclaves which can be trusted. `SW_HARDENING_NEEDED` is accepted like the
+/// registration service does, as the enclave mitigates the advisories in software.
+const TRUSTED_STATUSES: &[&str] = &["OK", "SW_HARDENING_NEEDED"];
+
+/// The answer of IAS to the verification of a quote, with the parts of its HTTP headers
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct IasResponse {
+    /// The body of the response, the attestation report, as it was signed
+    pub report: String,
+    /// The base64 encoded `X-IASReport-Signature` header
+    pub signature: String,
+    /// The PEM certificate of the `X-IASReport-Signing-Certificate` header, URL-decoded
+    pub signing_cert: String,
+}
+
+/// The fields of an attestation report which are checked
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationReport {
+    pub timestamp: String,
+    pub isv_enclave_quote_status: String,
+    /// base64
+    pub isv_enclave_quote_body: String,
+    #[serde(rename = "advisoryIDs", default)]
+    pub advisory_ids: Vec<String>,
+}
+
+/// The result of a successful verification, returned as JSON
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AttestationVerdict {
+    pub quote_status: String,
+    pub advisory_ids: Vec<String>,
+    /// hex
+    pub mrenclave: String,
+    /// hex
+    pub mrsigner: String,
+    /// When IAS verified the quote, in seconds since the unix epoch
+    pub verified_at: u64,
+}
+
+impl IasResponse {
+    pub fn parse(response: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(response).map_err(|e| Error::invalid_arg(RESPONSE_ARG, e))
+    }
+
+    pub fn decoded_signature(&self) -> Result<Vec<u8>, Error> {
+        base64::decode(&self.signature).map_err(|e| Error::invalid_arg(RESPONSE_ARG, e))
+    }
+}
+
+pub fn parse_keys(keys: &[u8]) -> Result<&[u8], Error> {
+    if keys.len() != KEYS_LEN {
+        let msg = format!("expected {} bytes, got {}", KEYS_LEN, keys.len());
+        return Err(Error::invalid_arg(KEYS_ARG, msg));
+    }
+    Ok(keys)
+}
+
+/// Seconds since the unix epoch of a timestamp of IAS like `2020-06-08T12:34:56.123456`, which is in UTC
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let seconds = timestamp.split('.').next()?;
+    let (date, time) = (seconds.get(..10)?, seconds.get(11..)?);
+    if seconds.as_bytes().get(10) != Some(&b'T') {
+        return None;
+    }
+    let date: Vec<u64> = date
+        .split('-')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    let time: Vec<u64> = time
+        .split(':')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    match (date.as_slice(), time.as_slice()) {
+        (&[year, month, day], &[hour, minute, second])
+            if year >= 1970
+                && (1..=12).contains(&month)
+                && (1..=31).contains(&day)
+                && hour < 24
+                && minute < 60
+                && second < 61 =>
+        {
+            let days = days_from_civil(year, month, day);
+            Some(days * 86400 + hour * 3600 + minute * 60 + second)
+        }
+        _ => None,
+    }
+}
+
+/// Days since the unix epoch of a date of the proleptic Gregorian calendar, from year 1970
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Checks the report of `response` against `quote`, the keys and the nonce. The signature of the report must
+/// have been verified by the enclave before. A `max_age` of 0 accepts reports of any age.
+pub fn check_report(
+    response: &IasResponse,
+    quote: &[u8],
+    keys: &[u8],
+    nonce: &[u8; CHALLENGE_LEN],
+    max_age: u64,
+) -> Result<AttestationVerdict, Error> {
+    let invalid = |msg: String| Error::invalid_arg(RESPONSE_ARG, msg);
+    let report: AttestationReport =
+        serde_json::from_str(&response.report).map_err(|e| Error::invalid_arg(RESPONSE_ARG, e))?;
+
+    let verified_at = parse_timestamp(&report.timestamp)
+        .ok_or_else(|| invalid(format!("invalid timestamp {}", report.timestamp)))?;
+    let age = now().saturating_sub(verified_at);
+    if max_age != 0 && age > max_age {
+        return Err(invalid(format!(
+            "the report is {} seconds old, the maximum is {}",
+            age, max_age
+        )));
+    }
+
+    let body = base64::decode(&report.isv_enclave_quote_body)
+        .map_err(|e| Error::invalid_arg(RESPONSE_ARG, e))?;
+    if quote.len() < QUOTE_BODY_LEN {
+        let msg = format!("expected at least {} bytes", QUOTE_BODY_LEN);
+        return Err(Error::invalid_arg(QUOTE_ARG, msg));
+    }
+    if body != quote[..QUOTE_BODY_LEN] {
+        return Err(invalid("the report is for another quote".to_string()));
+    }
+    let report_data = key_attestation::report_data(keys, nonce);
+    if quote[REPORT_DATA_OFFSET..REPORT_DATA_OFFSET + 32] != report_data {
+        return Err(Error::invalid_arg(
+            QUOTE_ARG,
+            "the quote does not commit to the keys and the nonce",
+        ));
+    }
+
+    tcb_recovery::check_status(&report.isv_enclave_quote_status, &report.advisory_ids)?;
+    if !TRUSTED_STATUSES.contains(&report.isv_enclave_quote_status.as_str()) {
+        return Err(invalid(format!(
+            "the quote status {} is not trusted",
+            report.isv_enclave_quote_status
+        )));
+    }
+    Ok(AttestationVerdict {
+        quote_status: report.isv_enclave_quote_status,
+        advisory_ids: report.advisory_ids,
+        mrenclave: hex::encode(&quote[MRENCLAVE_OFFSET..MRENCLAVE_OFFSET + 32]),
+        mrsigner: hex::encode(&quote[MRSIGNER_OFFSET..MRSIGNER_OFFSET + 32]),
+        verified_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const NONCE: [u8; 32] = [3u8; 32];
+
+    fn keys() -> Vec<u8> {
+        [[1u8; 32], [2u8; 32]].concat()
+    }
+
+    fn quote() -> Vec<u8> {
+        let mut quote = vec![0u8; QUOTE_BODY_LEN + 16];
+        quote[MRENCLAVE_OFFSET..MRENCLAVE_OFFSET + 32].copy_from_slice(&[7u8; 32]);
+        quote[MRSIGNER_OFFSET..MRSIGNER_OFFSET + 32].copy_from_slice(&[8u8; 32]);
+        let report_data = key_attestation::report_data(&keys(), &NONCE);
+        quote[REPORT_DATA_OFFSET..REPORT_DATA_OFFSET + 32].copy_from_slice(&report_data);
+        quote
+    }
+
+    fn response(quote: &[u8], status: &str, timestamp: &str) -> IasResponse {
+        let report = json!({
+            "id": "1",
+            "timestamp": timestamp,
+            "version": 4,
+            "isvEnclaveQuoteStatus": status,
+            "isvEnclaveQuoteBody": base64::encode(&quote[..QUOTE_BODY_LEN]),
+        });
+        IasResponse {
+            report: report.to_string(),
+            signature: String::new(),
+            signing_cert: String::new(),
+        }
+    }
+
+    #[test]
+    fn parse_timestamp_works() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00"), Some(0));
+        assert_eq!(
+            parse_timestamp("2020-06-08T12:34:56.123456"),
+            Some(1_591_619_696)
+        );
+        assert_eq!(parse_timestamp("2024-02-29T00:00:00"), Some(1_709_164_800));
+        assert_eq!(parse_timestamp("2020-06-08 12:34:56"), None);
+        assert_eq!(parse_timestamp("2020-13-08T12:34:56"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn check_report_accepts_fresh_reports() {
+        let response = response(
+            &quote(),
+            "SW_HARDENING_NEEDED",
+            "2020-06-08T12:34:56.123456",
+        );
+        let verdict = check_report(&response, &quote(), &keys(), &NONCE, 0).unwrap();
+        assert_eq!(
+            verdict,
+            AttestationVerdict {
+                quote_status: "SW_HARDENING_NEEDED".to_string(),
+                advisory_ids: vec![],
+                mrenclave: hex::encode([7u8; 32]),
+                mrsigner: hex::encode([8u8; 32]),
+                verified_at: 1_591_619_696,
+            }
+        );
+    }
+
+    #[test]
+    fn check_report_rejects_stale_or_foreign_reports() {
+        let old = response(&quote(), "OK", "2020-06-08T12:34:56");
+        assert!(check_report(&old, &quote(), &keys(), &NONCE, 3600).is_err());
+
+        let untrusted = response(&quote(), "GROUP_REVOKED", "2020-06-08T12:34:56");
+        assert!(check_report(&untrusted, &quote(), &keys(), &NONCE, 0).is_err());
+
+        // outdated platforms are told apart, as they can recover
+        let outdated = response(&quote(), "GROUP_OUT_OF_DATE", "2020-06-08T12:34:56");
+        match check_report(&outdated, &quote(), &keys(), &NONCE, 0).unwrap_err() {
+            Error::TcbOutdated { status, .. } => assert_eq!(status, "GROUP_OUT_OF_DATE"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let mut other = quote();
+        other[0] = 1;
+        let foreign = response(&other, "OK", "2020-06-08T12:34:56");
+        assert!(check_report(&foreign, &quote(), &keys(), &NONCE, 0).is_err());
+
+        // the quote must commit to the nonce of the verifier
+        let replayed = response(&quote(), "OK", "2020-06-08T12:34:56");
+        assert!(check_report(&replayed, &quote(), &keys(), &[4u8; 32], 0).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//! An append-only record of the privileged operations of the node, for forensics and compliance.
+//!
+//! Once Go opened the log with `init_audit_log`, the operations creating or replacing the node's secrets and
+//! enclave, and those stopping contracts, append an `AuditEntry` to `audit.log` in the data dir: the bootstrap
+//! of the network, including the shares of a bootstrap ceremony, the initialization of the node, the
+//! replacement of the enclave, refreshing the registration, pausing codes and restoring snapshots. Parameters
+//! are only recorded as a hash, as some of them are secret, e.g. the seed of `init_bootstrap_from_seed`.
+//! Operators compare the hash with the parameters they know were used.
+//!
+//! Entries are JSON lines, and the file is never truncated or rewritten. Failing to append an entry does not
+//! fail the operation, which already happened, but is logged as an error.
+
+use std::fmt::Display;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+use log::error;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+
+const AUDIT_LOG_FILE: &str = "audit.log";
+
+/// A privileged operation, as recorded in the log
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AuditEntry {
+    /// When the operation returned, in milliseconds since the unix epoch
+    pub time_ms: u64,
+    /// The name of the FFI function
+    pub operation: String,
+    /// The hex encoded SHA-256 of the parameters, see `params_hash`
+    pub params_hash: String,
+    /// Why the operation failed, `None` if it succeeded
+    pub error: Option<String>,
+}
+
+struct AuditLog {
+    path: PathBuf,
+    file: File,
+}
+
+impl AuditLog {
+    fn open(data_dir: &Path) -> Result<Self, Error> {
+        fs::create_dir_all(data_dir)?;
+        let path = data_dir.join(AUDIT_LOG_FILE);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(AuditLog { path, file })
+    }
+
+    fn append(&mut self, entry: &AuditEntry) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(entry).map_err(Error::vm_err)?;
+        line.push(b'\n');
+        // one write per entry, so entries of concurrent processes do not interleave
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    fn read(&self) -> Result<Vec<AuditEntry>, Error> {
+        fs::read_to_string(&self.path)?
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_str(line).map_err(Error::vm_err))
+            .collect()
+    }
+}
+
+fn lock(log: &Mutex<Option<AuditLog>>) -> MutexGuard<'_, Option<AuditLog>> {
+    // a panic while holding the lock does not leave the log inconsistent
+    log.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+lazy_static! {
+    static ref LOG: Mutex<Option<AuditLog>> = Mutex::new(None);
+}
+
+/// Appends the entries of later operations to the log in `data_dir`, after the entries already in it
+pub fn init(data_dir: &Path) -> Result<(), Error> {
+    let opened = AuditLog::open(data_dir)?;
+    *lock(&LOG) = Some(opened);
+    Ok(())
+}
+
+/// The entries of the log in the order they were recorded, none if `init` was not called
+pub fn read() -> Result<Vec<AuditEntry>, Error> {
+    match lock(&LOG).as_ref() {
+        Some(log) => log.read(),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The hash of the parameters of an operation. Each is prefixed with its length, so moving bytes from one
+/// parameter to the next changes the hash.
+pub fn params_hash(params: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for param in params {
+        hasher.update((param.len() as u64).to_be_bytes());
+        hasher.update(param);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Records the outcome of `operation` called with `params`, if the log was opened
+pub fn record<T, E: Display>(operation: &str, params: &[&[u8]], result: &Result<T, E>) {
+    let mut log = lock(&LOG);
+    let log = match log.as_mut() {
+        Some(log) => log,
+        None => return,
+    };
+    let time_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    let entry = AuditEntry {
+        time_ms,
+        operation: operation.to_string(),
+        params_hash: params_hash(params),
+        error: result.as_ref().err().map(ToString::to_string),
+    };
+    if let Err(e) = log.append(&entry) {
+        error!("Cannot record {} in the audit log: {}", operation, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn params_hash_separates_params() {
+        assert_eq!(params_hash(&[b"ab", b"c"]), params_hash(&[b"ab", b"c"]));
+        assert_ne!(params_hash(&[b"ab", b"c"]), params_hash(&[b"a", b"bc"]));
+        assert_ne!(params_hash(&[]), params_hash(&[b""]));
+    }
+
+    #[test]
+    fn entries_are_appended() {
+        let dir = TempDir::new().unwrap();
+        let entry = |operation: &str, error: Option<&str>| AuditEntry {
+            time_ms: 1,
+            operation: operation.to_string(),
+            params_hash: params_hash(&[b"seed"]),
+            error: error.map(str::to_string),
+        };
+        let mut log = AuditLog::open(dir.path()).unwrap();
+        log.append(&entry("init_bootstrap", None)).unwrap();
+        drop(log);
+
+        // reopening keeps the entries
+        let mut log = AuditLog::open(dir.path()).unwrap();
+        log.append(&entry("init_node", Some("Enclave error")))
+            .unwrap();
+        assert_eq!(
+            log.read().unwrap(),
+            vec![
+                entry("init_bootstrap", None),
+                entry("init_node", Some("Enclave error"))
+            ]
+        );
+    }
+}
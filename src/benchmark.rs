@@ -0,0 +1,195 @@
+//! Measures the cost of crossing the FFI boundary on the node's own hardware.
+//!
+//! Chains price the Go callbacks in gas. To do so they need to know what a callback costs compared to
+//! wasm execution, which depends on the machine. `run_benchmark` times a number of identical calls of
+//! one kind from inside the library, so the measurement includes everything a contract would pay for.
+
+use std::time::Instant;
+
+use serde::Serialize;
+
+use cosmwasm_std::{HumanAddr, Order};
+
+use crate::api::GoApi;
+use crate::db::DB;
+use crate::error::Error;
+use crate::querier::GoQuerier;
+use crate::vm::{untrusted_health_check, Api, FfiResult, GasInfo, Querier, Storage};
+
+/// The key written and read by the storage benchmarks. It is removed again afterwards.
+const BENCHMARK_KEY: &[u8] = b"__go_cosmwasm_benchmark__";
+const BENCHMARK_VALUE: &[u8] = &[0xab; 32];
+const BENCHMARK_QUERY: &[u8] =
+    br#"{"bank":{"balance":{"address":"benchmark","denom":"benchmark"}}}"#;
+const BENCHMARK_QUERY_GAS_LIMIT: u64 = 1_000_000;
+
+/// What `run_benchmark` measures
+///
+/// cbindgen:prefix-with-name
+#[repr(i32)]
+#[derive(Serialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BenchmarkKind {
+    DbGet = 0,
+    DbSet = 1,
+    IteratorNext = 2,
+    Query = 3,
+    ApiCall = 4,
+    /// The cheapest call into the enclave, which does not touch Go at all
+    EmptyEcall = 5,
+}
+
+impl BenchmarkKind {
+    pub fn from_i32(kind: i32) -> Result<Self, Error> {
+        match kind {
+            0 => Ok(BenchmarkKind::DbGet),
+            1 => Ok(BenchmarkKind::DbSet),
+            2 => Ok(BenchmarkKind::IteratorNext),
+            3 => Ok(BenchmarkKind::Query),
+            4 => Ok(BenchmarkKind::ApiCall),
+            5 => Ok(BenchmarkKind::EmptyEcall),
+            _ => Err(Error::invalid_arg(
+                "kind",
+                format!("unknown benchmark kind {}", kind),
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BenchmarkReport {
+    pub kind: BenchmarkKind,
+    pub iterations: u32,
+    pub total_nanos: u64,
+    pub nanos_per_call: u64,
+    /// The gas reported by the callbacks, on average
+    pub gas_per_call: u64,
+    /// Calls which returned an error. They are timed like the others.
+    pub errors: u32,
+}
+
+#[derive(Default)]
+struct Totals {
+    nanos: u128,
+    gas: u64,
+    errors: u32,
+}
+
+impl Totals {
+    fn time<T>(&mut self, call: impl FnOnce() -> FfiResult<T>) {
+        let start = Instant::now();
+        let (result, gas_info) = call();
+        self.nanos += start.elapsed().as_nanos();
+        self.add(result.is_ok(), gas_info);
+    }
+
+    fn add(&mut self, ok: bool, gas_info: GasInfo) {
+        self.gas = self
+            .gas
+            .saturating_add(gas_info.cost)
+            .saturating_add(gas_info.externally_used);
+        if !ok {
+            self.errors += 1;
+        }
+    }
+}
+
+pub fn run(
+    kind: BenchmarkKind,
+    iterations: u32,
+    mut db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+) -> Result<BenchmarkReport, Error> {
+    if iterations == 0 {
+        return Err(Error::invalid_arg("iterations", "must be greater than 0"));
+    }
+
+    let mut totals = Totals::default();
+    match kind {
+        BenchmarkKind::DbGet => {
+            let _ = db.set(BENCHMARK_KEY, BENCHMARK_VALUE);
+            for _ in 0..iterations {
+                totals.time(|| db.get(BENCHMARK_KEY));
+            }
+            let _ = db.remove(BENCHMARK_KEY);
+        }
+        BenchmarkKind::DbSet => {
+            for _ in 0..iterations {
+                totals.time(|| db.set(BENCHMARK_KEY, BENCHMARK_VALUE));
+            }
+            let _ = db.remove(BENCHMARK_KEY);
+        }
+        BenchmarkKind::IteratorNext => {
+            // Only the steps are timed. Once the store is exhausted, every step still crosses the boundary.
+            let (iter, _) = db.range(None, None, Order::Ascending);
+            let mut iter = iter.map_err(crate::vm::VmError::from)?;
+            for _ in 0..iterations {
+                totals.time(|| iter.next());
+            }
+        }
+        BenchmarkKind::Query => {
+            for _ in 0..iterations {
+                totals.time(|| querier.query_raw(BENCHMARK_QUERY, BENCHMARK_QUERY_GAS_LIMIT));
+            }
+        }
+        BenchmarkKind::ApiCall => {
+            let human = HumanAddr("benchmark".to_string());
+            for _ in 0..iterations {
+                totals.time(|| api.canonical_address(&human));
+            }
+        }
+        BenchmarkKind::EmptyEcall => {
+            for _ in 0..iterations {
+                let start = Instant::now();
+                let ok = untrusted_health_check().is_ok();
+                totals.nanos += start.elapsed().as_nanos();
+                totals.add(ok, GasInfo::free());
+            }
+        }
+    }
+    Ok(report(kind, iterations, &totals))
+}
+
+fn report(kind: BenchmarkKind, iterations: u32, totals: &Totals) -> BenchmarkReport {
+    let total_nanos = totals.nanos.min(u64::MAX as u128) as u64;
+    BenchmarkReport {
+        kind,
+        iterations,
+        total_nanos,
+        nanos_per_call: total_nanos / iterations as u64,
+        gas_per_call: totals.gas / iterations as u64,
+        errors: totals.errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn benchmark_kind_from_i32_works() {
+        assert_eq!(BenchmarkKind::from_i32(0).unwrap(), BenchmarkKind::DbGet);
+        assert_eq!(
+            BenchmarkKind::from_i32(5).unwrap(),
+            BenchmarkKind::EmptyEcall
+        );
+        assert!(BenchmarkKind::from_i32(6).is_err());
+    }
+
+    #[test]
+    fn report_averages_totals() {
+        let totals = Totals {
+            nanos: 1000,
+            gas: 50,
+            errors: 1,
+        };
+        let report = report(BenchmarkKind::Query, 10, &totals);
+        assert_eq!(report.nanos_per_call, 100);
+        assert_eq!(report.gas_per_call, 5);
+        assert_eq!(report.errors, 1);
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.contains(r#""kind":"query""#));
+    }
+}
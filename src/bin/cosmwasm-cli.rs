@@ -0,0 +1,21 @@
+//! Checks, analyzes and dry-runs contracts without a node, see `go_cosmwasm::cli`
+
+use std::env;
+use std::io;
+use std::process;
+
+use go_cosmwasm::cli::{self, Command};
+
+fn main() {
+    let command = match Command::from_args(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = cli::run(command, io::stdout()) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
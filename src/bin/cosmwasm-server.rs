@@ -0,0 +1,20 @@
+//! Runs the VM and the enclave for a node in a separate process, see `go_cosmwasm::server`
+
+use std::env;
+use std::process;
+
+use go_cosmwasm::server::{self, Config};
+
+fn main() {
+    let config = match Config::from_args(env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}\n{}", e, server::USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = server::run(config) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,71 @@
+//! The block a contract call runs in, as seen by the storage callbacks.
+//!
+//! Go passes the block to the contract inside of `params`. Before running a contract we hand it to the
+//! DB as well, so the store layer can implement height-scoped reads or price gas by height without
+//! parsing the env itself. Queries have no env, so Go passes their block to `query` explicitly, and we hand
+//! it to the querier too.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BlockContext {
+    pub height: u64,
+    /// Seconds since the UNIX epoch
+    pub time: u64,
+}
+
+impl BlockContext {
+    /// Reads the block from the env of a contract call. Fields which are missing or malformed are 0.
+    pub fn from_params(params: &[u8]) -> Self {
+        let env: Value = match serde_json::from_slice(params) {
+            Ok(env) => env,
+            Err(_) => return BlockContext::default(),
+        };
+        let block = &env["block"];
+        BlockContext {
+            height: as_u64(&block["height"]),
+            time: as_u64(&block["time"]),
+        }
+    }
+}
+
+/// Numbers may be encoded as JSON strings to keep their precision
+fn as_u64(value: &Value) -> u64 {
+    match value {
+        Value::Number(n) => n.as_u64().unwrap_or_default(),
+        Value::String(s) => s.parse().unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_params_works() {
+        let params =
+            br#"{"block":{"height":12345,"time":1600000000,"chain_id":"x"},"contract":{}}"#;
+        assert_eq!(
+            BlockContext::from_params(params),
+            BlockContext {
+                height: 12345,
+                time: 1600000000,
+            }
+        );
+    }
+
+    #[test]
+    fn from_params_accepts_strings() {
+        let params = br#"{"block":{"height":"12345","time":"1600000000"}}"#;
+        assert_eq!(BlockContext::from_params(params).height, 12345);
+    }
+
+    #[test]
+    fn from_params_defaults_to_zero() {
+        assert_eq!(BlockContext::from_params(b"{}"), BlockContext::default());
+        assert_eq!(BlockContext::from_params(b"foo"), BlockContext::default());
+    }
+}
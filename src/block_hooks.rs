@@ -0,0 +1,128 @@
+//! Contracts which run at the beginning or the end of every block.
+//!
+//! A Go module keeps the contracts registered for a block hook, e.g. auctions settling or tokens rebasing once per
+//! block, and calls `begin_block` or `end_block` for each of them. These run the `begin_block` or `end_block`
+//! export of the contract, which takes the env and no message. Unlike `sudo`, which runs a message of the chain,
+//! block hooks carry no message at all, only the block.
+//!
+//! The block is passed apart from the env as JSON `BlockMeta`, and replaces the block of the env. Block hooks are
+//! sent by no one, so the contract is the sender of its own hooks and sends no funds. They are protocol calls,
+//! so they run with the budget of `handle_system`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::code_stats::EntryPoint;
+use crate::error::Error;
+
+const BLOCK_ARG: &str = "block";
+
+/// The export a block hook runs
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlockHook {
+    BeginBlock,
+    EndBlock,
+}
+
+impl BlockHook {
+    pub fn entry_point(self) -> EntryPoint {
+        match self {
+            BlockHook::BeginBlock => EntryPoint::BeginBlock,
+            BlockHook::EndBlock => EntryPoint::EndBlock,
+        }
+    }
+}
+
+/// The block of a block hook, as in the env of other calls
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BlockMeta {
+    pub height: u64,
+    /// Seconds since the UNIX epoch
+    pub time: u64,
+    pub chain_id: String,
+}
+
+impl BlockMeta {
+    pub fn from_json(block: &[u8]) -> Result<Self, Error> {
+        let block: BlockMeta =
+            serde_json::from_slice(block).map_err(|e| Error::invalid_arg(BLOCK_ARG, e))?;
+        if block.height == 0 {
+            return Err(Error::invalid_arg(BLOCK_ARG, "the height must not be 0"));
+        }
+        if block.chain_id.is_empty() {
+            return Err(Error::invalid_arg(
+                BLOCK_ARG,
+                "the chain id must not be empty",
+            ));
+        }
+        Ok(block)
+    }
+}
+
+/// The env of a block hook in `block`, built from the env `params` of the contract
+pub fn hook_env(params: &[u8], block: &BlockMeta) -> Result<Vec<u8>, Error> {
+    let mut env: Value =
+        serde_json::from_slice(params).map_err(|e| Error::invalid_arg("params", e))?;
+    if !env.is_object() {
+        return Err(Error::invalid_arg("params", "expected a JSON object"));
+    }
+    let contract_addr = env
+        .pointer("/contract/address")
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::invalid_arg("params", "expected a contract address"))?
+        .to_string();
+    env["block"] = serde_json::to_value(block).map_err(Error::vm_err)?;
+    env["message"] = json!({
+        "sender": contract_addr,
+        "sent_funds": [],
+    });
+    serde_json::to_vec(&env).map_err(Error::vm_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block() -> BlockMeta {
+        BlockMeta {
+            height: 12345,
+            time: 1600000000,
+            chain_id: "secret-2".to_string(),
+        }
+    }
+
+    #[test]
+    fn block_meta_from_json_works() {
+        let json = br#"{"height":12345,"time":1600000000,"chain_id":"secret-2"}"#;
+        assert_eq!(BlockMeta::from_json(json).unwrap(), block());
+        assert!(BlockMeta::from_json(br#"{"height":0,"time":1,"chain_id":"x"}"#).is_err());
+        assert!(BlockMeta::from_json(br#"{"height":1,"time":1,"chain_id":""}"#).is_err());
+        assert!(BlockMeta::from_json(br#"{"height":1,"time":1}"#).is_err());
+        assert!(
+            BlockMeta::from_json(br#"{"height":1,"time":1,"chain_id":"x","hash":""}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn hook_env_replaces_block_and_message() {
+        let params = br#"{"block":{"height":1,"time":1,"chain_id":"x"},"message":{"sender":"secret1user","sent_funds":[{"denom":"uscrt","amount":"1"}]},"contract":{"address":"secret1contract"},"contract_key":"a2V5"}"#;
+        let env = hook_env(params, &block()).unwrap();
+        let env: Value = serde_json::from_slice(&env).unwrap();
+        assert_eq!(
+            env,
+            json!({
+                "block": { "height": 12345, "time": 1600000000, "chain_id": "secret-2" },
+                "message": { "sender": "secret1contract", "sent_funds": [] },
+                "contract": { "address": "secret1contract" },
+                "contract_key": "a2V5",
+            })
+        );
+    }
+
+    #[test]
+    fn hook_env_requires_a_contract() {
+        assert!(hook_env(br#"{"contract_key":"a2V5"}"#, &block()).is_err());
+        assert!(hook_env(b"[]", &block()).is_err());
+    }
+}
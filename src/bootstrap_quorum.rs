@@ -0,0 +1,209 @@
+//! Generating the consensus seed in a ceremony of several bootstrap nodes, so no single node chooses it.
+//!
+//! Every participant calls `create_seed_share`, which makes its enclave draw a random contribution and seal
+//! it to the seed exchange keys of the other participants' enclaves. The nodes exchange the shares out of
+//! band, e.g. in the genesis file, and each calls `combine_seed_shares` with at least `threshold` of them.
+//! The enclave derives the consensus seed from the combined contributions and returns the bootstrap public
+//! key, the same on all participants, like `init_bootstrap` does for a single bootstrap node.
+//!
+//! The shares are opaque here. This module only validates the ceremony and frames the shares for the enclave,
+//! which binds every share to the encoded ceremony, so shares of another ceremony are rejected.
+
+use std::collections::HashSet;
+use std::convert::TryInto;
+
+use serde::Deserialize;
+
+use crate::error::Error;
+
+const CEREMONY_ARG: &str = "ceremony";
+const SHARES_ARG: &str = "shares";
+const PUBKEY_LEN: usize = 32;
+
+/// The parameters all participants of a ceremony agree on, passed in as JSON
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Ceremony {
+    /// Distinguishes the ceremony from earlier ones of the same participants, e.g. the chain id
+    pub id: String,
+    /// How many shares are combined into the seed
+    pub threshold: u32,
+    /// The base64 encoded seed exchange public keys of the participating enclaves, see `get_registration_keys`
+    pub participants: Vec<String>,
+}
+
+impl Ceremony {
+    pub fn parse(json: &[u8]) -> Result<Self, Error> {
+        let ceremony: Ceremony =
+            serde_json::from_slice(json).map_err(|e| Error::invalid_arg(CEREMONY_ARG, e))?;
+        ceremony.validate()?;
+        Ok(ceremony)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.id.is_empty() {
+            return Err(Error::invalid_arg(CEREMONY_ARG, "empty ceremony id"));
+        }
+        // with one share, a single node would know the seed
+        if self.threshold < 2 || self.threshold as usize > self.participants.len() {
+            let msg = format!(
+                "threshold must be between 2 and the number of participants ({}), got {}",
+                self.participants.len(),
+                self.threshold
+            );
+            return Err(Error::invalid_arg(CEREMONY_ARG, msg));
+        }
+        let mut seen = HashSet::new();
+        for key in self.participant_keys()? {
+            if !seen.insert(key) {
+                return Err(Error::invalid_arg(CEREMONY_ARG, "duplicate participant"));
+            }
+        }
+        Ok(())
+    }
+
+    fn participant_keys(&self) -> Result<Vec<[u8; PUBKEY_LEN]>, Error> {
+        self.participants
+            .iter()
+            .map(|key| {
+                let raw = base64::decode(key).map_err(|e| Error::invalid_arg(CEREMONY_ARG, e))?;
+                raw.as_slice().try_into().map_err(|_| {
+                    let msg = format!("participant keys must have {} bytes", PUBKEY_LEN);
+                    Error::invalid_arg(CEREMONY_ARG, msg)
+                })
+            })
+            .collect()
+    }
+
+    /// The binary form handed to the enclave: the length of the id as big endian u32, the id, the threshold,
+    /// the number of participants and their keys in ascending order, so every participant encodes the same bytes
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut keys = self.participant_keys()?;
+        keys.sort();
+        let mut out = Vec::new();
+        out.extend_from_slice(&(self.id.len() as u32).to_be_bytes());
+        out.extend_from_slice(self.id.as_bytes());
+        out.extend_from_slice(&self.threshold.to_be_bytes());
+        out.extend_from_slice(&(keys.len() as u32).to_be_bytes());
+        for key in keys {
+            out.extend_from_slice(&key);
+        }
+        Ok(out)
+    }
+}
+
+/// Splits `shares`, each prefixed by its length as big endian u32, failing if there are less than the
+/// threshold of `ceremony` after dropping duplicates. Returns the distinct shares framed the same way.
+pub fn check_shares(ceremony: &Ceremony, shares: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut distinct: Vec<&[u8]> = Vec::new();
+    let mut rest = shares;
+    while !rest.is_empty() {
+        if rest.len() < 4 {
+            return Err(Error::invalid_arg(SHARES_ARG, "truncated share length"));
+        }
+        let (len, tail) = rest.split_at(4);
+        let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+        if len == 0 || tail.len() < len {
+            return Err(Error::invalid_arg(SHARES_ARG, "empty or truncated share"));
+        }
+        let (share, tail) = tail.split_at(len);
+        if !distinct.contains(&share) {
+            distinct.push(share);
+        }
+        rest = tail;
+    }
+    if distinct.len() < ceremony.threshold as usize {
+        let msg = format!(
+            "expected at least {} distinct shares, got {}",
+            ceremony.threshold,
+            distinct.len()
+        );
+        return Err(Error::invalid_arg(SHARES_ARG, msg));
+    }
+
+    let mut out = Vec::with_capacity(shares.len());
+    for share in distinct {
+        out.extend_from_slice(&(share.len() as u32).to_be_bytes());
+        out.extend_from_slice(share);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ceremony(threshold: u32, keys: &[u8]) -> Ceremony {
+        Ceremony {
+            id: "secret-4".to_string(),
+            threshold,
+            participants: keys
+                .iter()
+                .map(|k| base64::encode(&[*k; PUBKEY_LEN]))
+                .collect(),
+        }
+    }
+
+    fn frame(shares: &[&[u8]]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for share in shares {
+            out.extend_from_slice(&(share.len() as u32).to_be_bytes());
+            out.extend_from_slice(share);
+        }
+        out
+    }
+
+    #[test]
+    fn parse_validates_ceremonies() {
+        let json = format!(
+            r#"{{"id":"secret-4","threshold":2,"participants":["{}","{}"]}}"#,
+            base64::encode(&[1u8; 32]),
+            base64::encode(&[2u8; 32])
+        );
+        assert_eq!(
+            Ceremony::parse(json.as_bytes()).unwrap(),
+            ceremony(2, &[1, 2])
+        );
+
+        assert!(ceremony(1, &[1, 2]).validate().is_err());
+        assert!(ceremony(3, &[1, 2]).validate().is_err());
+        assert!(ceremony(2, &[1, 1]).validate().is_err());
+        let mut short_key = ceremony(2, &[1, 2]);
+        short_key.participants[0] = base64::encode(&[1u8; 31]);
+        assert!(short_key.validate().is_err());
+        let mut no_id = ceremony(2, &[1, 2]);
+        no_id.id.clear();
+        assert!(no_id.validate().is_err());
+    }
+
+    #[test]
+    fn encode_ignores_participant_order() {
+        let encoded = ceremony(2, &[2, 1, 3]).encode().unwrap();
+        assert_eq!(encoded, ceremony(2, &[1, 3, 2]).encode().unwrap());
+        assert_eq!(&encoded[..4], &[0, 0, 0, 8]);
+        assert_eq!(&encoded[4..12], b"secret-4");
+        assert_eq!(&encoded[12..20], &[0, 0, 0, 2, 0, 0, 0, 3]);
+        assert_eq!(&encoded[20..52], &[1u8; 32]);
+        assert_eq!(encoded.len(), 20 + 3 * 32);
+    }
+
+    #[test]
+    fn check_shares_requires_threshold() {
+        let ceremony = ceremony(2, &[1, 2, 3]);
+        let shares = frame(&[b"first", b"second"]);
+        assert_eq!(check_shares(&ceremony, &shares).unwrap(), shares);
+
+        // duplicates do not count
+        let res = check_shares(&ceremony, &frame(&[b"first", b"first"]));
+        assert!(res.is_err());
+        assert_eq!(
+            check_shares(&ceremony, &frame(&[b"first", b"second", b"first"])).unwrap(),
+            shares
+        );
+
+        let mut truncated = shares.clone();
+        truncated.pop();
+        assert!(check_shares(&ceremony, &truncated).is_err());
+        assert!(check_shares(&ceremony, &frame(&[b"first", b""])).is_err());
+    }
+}
@@ -0,0 +1,690 @@
+use std::cell::{RefCell, UnsafeCell};
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, ThreadId};
+
+use log::*;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::analysis;
+use crate::api::GoApi;
+use crate::code_stats::{CodeStats, EntryPoint};
+use crate::consensus_params::ConsensusParams;
+use crate::db::DB;
+use crate::error::Error;
+use crate::gas_report;
+use crate::integrity::VerifiedCodes;
+use crate::module_format::{ModuleFormat, Modules};
+use crate::msg_checks;
+use crate::plaintext::PlaintextCodes;
+use crate::querier::GoQuerier;
+use crate::query_cache::QueryCache;
+use crate::query_routes::QueryRoutes;
+#[cfg(feature = "shadow")]
+use crate::shadow::Shadow;
+use crate::shared_region::SharedRegion;
+use crate::snapshot::WASM_DIR;
+use crate::usage_journal::{UsageJournal, JOURNAL_CAPACITY};
+use crate::vm::{
+    set_instance_memory_limit, set_nan_canonicalization, set_wasm_cost_table, Checksum, CosmCache,
+    Extern, Instance,
+};
+use crate::wasm_costs::{self, WasmCostTable, WasmCosts, WasmSchedules};
+
+/// Node level settings, passed to `init_cache` as JSON. The settings which decide the results of calls are
+/// not options of the node, see `consensus_params`.
+///
+/// Every field has a default, so an empty buffer (or `{}`) selects the default behaviour.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheOptions {
+    /// Run plaintext calls in the upstream VM too and report divergences, see `shadow`.
+    /// Requires the `shadow` feature.
+    pub shadow_execution: bool,
+    /// Notify Go whenever the gas a call charged through its callbacks grew by this much, see
+    /// `gas_report::set_gas_sync`. 0 disables the notifications.
+    pub gas_sync_interval: u64,
+    /// Hash the stored wasm of a code against its checksum before loading it, see `integrity`
+    pub verify_wasm_on_load: bool,
+    /// Let `query` run without a gas limit when asked to, for tooling on archive nodes. Validators must not set
+    /// this, as unmetered queries run for as long as the contract likes.
+    pub allow_unmetered_queries: bool,
+    /// The gas the VM charges per wasm instruction, see `wasm_costs`
+    pub wasm_costs: WasmCosts,
+    /// Let the VM map compiled modules from disk instead of deserializing them into memory, if its backend
+    /// supports it, see `module_format`
+    pub mmap_modules: bool,
+    /// How many of the most recently used codes `init_cache` preloads in the background, 0 for none, see
+    /// `usage_journal`
+    pub warm_up_modules: usize,
+    /// How many bytes of query results are reused within a block, 0 for none, see `query_cache`
+    pub query_cache_size: usize,
+}
+
+impl CacheOptions {
+    pub fn from_json(options: &[u8]) -> Result<Self, Error> {
+        if options.is_empty() {
+            return Ok(CacheOptions::default());
+        }
+        let options: CacheOptions =
+            serde_json::from_slice(options).map_err(Error::invalid_options)?;
+        if options.shadow_execution && !cfg!(feature = "shadow") {
+            return Err(Error::invalid_options(
+                "shadow_execution requires a build with the `shadow` feature",
+            ));
+        }
+        if options.warm_up_modules > JOURNAL_CAPACITY {
+            return Err(Error::invalid_options(format!(
+                "warm_up_modules must be at most {}",
+                JOURNAL_CAPACITY
+            )));
+        }
+        WasmCostTable::new(&options.wasm_costs)?;
+        wasm_costs::check_vm_support(&options.wasm_costs)?;
+        Ok(options)
+    }
+
+    /// Opens the compiled modules in `data_dir` in `format`, see `Modules::open`, asking the VM to map them
+    /// if `mmap_modules` is set
+    pub fn open_modules(&self, data_dir: &Path, format: ModuleFormat) -> Result<Modules, Error> {
+        let mut modules = Modules::open(data_dir, format)?;
+        if self.mmap_modules {
+            modules.enable_mmap();
+        }
+        Ok(modules)
+    }
+
+    /// The hex checksums of the codes to warm up when the cache is created, the `warm_up_modules` codes of
+    /// `usage` used most recently
+    pub fn warm_up_codes(&self, usage: &UsageJournal) -> Vec<String> {
+        usage.most_recent(self.warm_up_modules)
+    }
+
+    /// The cache of query results, which keeps `query_cache_size` bytes of them
+    pub fn query_cache(&self) -> QueryCache {
+        QueryCache::new(self.query_cache_size)
+    }
+
+    /// Fails for an unmetered query unless `allow_unmetered_queries` is set
+    pub fn check_unmetered(&self, unmetered: bool) -> Result<(), Error> {
+        if unmetered && !self.allow_unmetered_queries {
+            return Err(Error::call_denied(
+                EntryPoint::Query.name(),
+                "unmetered queries are not allowed by the cache options",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks the stored wasm of `code_id` in `data_dir` before it is loaded, if `verify_wasm_on_load` is set
+    pub fn verify_code(
+        &self,
+        verified: &mut VerifiedCodes,
+        data_dir: &Path,
+        code_id: &Checksum,
+    ) -> Result<(), Error> {
+        if !self.verify_wasm_on_load {
+            return Ok(());
+        }
+        verified.verify(data_dir, code_id)
+    }
+}
+
+/// The state behind a `*cache_t`: the VM's cache plus everything we track on top of it
+pub struct Cache {
+    pub inner: CosmCache<DB, GoApi, GoQuerier>,
+    pub options: CacheOptions,
+    /// The settings of the chain, None until Go sets them, see `consensus_params`
+    pub consensus_params: Option<Arc<ConsensusParams>>,
+    pub data_dir: PathBuf,
+    /// The features contracts may require, see `update_supported_features`
+    pub supported_features: HashSet<String>,
+    /// Memory registered by Go for passing large results, see `register_shared_region`
+    pub shared_region: Option<SharedRegion>,
+    /// Execution statistics per code, see `get_code_stats`
+    pub code_stats: CodeStats,
+    /// The codes whose wasm passed the checks of `verify_wasm_on_load`
+    pub verified_codes: VerifiedCodes,
+    /// The initial memory of the codes checked by `check_memory_limit`, in pages
+    pub memory_pages: HashMap<Checksum, u32>,
+    /// Whether the codes checked by `check_float_policy` use floats, None if they cannot be analyzed
+    pub float_codes: HashMap<Checksum, Option<bool>>,
+    /// The wasm cost tables by activation height, see `register_gas_schedule`
+    pub wasm_schedules: WasmSchedules,
+    /// The compiled modules of the VM, see `module_format`
+    pub modules: Modules,
+    /// The codes used most recently, see `usage_journal`
+    pub usage: UsageJournal,
+    /// The codes whose contracts run without encryption, see `plaintext`
+    pub plaintext_codes: PlaintextCodes,
+    /// The codes whose contracts may not run, see `set_code_paused`
+    pub paused_codes: HashSet<Checksum>,
+    /// The queries of `query_by_alias`, see `set_query_routes`
+    pub query_routes: QueryRoutes,
+    /// The results of the queries of the current height, see `query_cache`
+    pub query_cache: QueryCache,
+    /// The upstream VM, if `shadow_execution` is enabled
+    #[cfg(feature = "shadow")]
+    pub shadow: Option<Shadow>,
+}
+
+impl Cache {
+    /// Stores a code, after checking that the cache supports the features it requires and that the float
+    /// policy allows it
+    pub fn save_wasm(&mut self, wasm: &[u8]) -> Result<Checksum, Error> {
+        let analysis = analysis::analyze(wasm).ok();
+        // The VM only reports that features are missing, not which ones. Codes we cannot analyze are left to its checks.
+        if let Some(analysis) = &analysis {
+            let required: HashSet<String> = analysis.required_features.iter().cloned().collect();
+            if !required.is_subset(&self.supported_features) {
+                return Err(Error::unsupported_features(
+                    &required,
+                    &self.supported_features,
+                ));
+            }
+        }
+        let uses_floats = analysis.map(|analysis| analysis.uses_floats);
+        self.consensus_params()?.float_policy.check(uses_floats)?;
+        let code_id = self.inner.save_wasm(wasm)?;
+        self.float_codes.insert(code_id, uses_floats);
+        Ok(code_id)
+    }
+
+    /// Stores a code like `save_wasm`, as a plaintext code if `plaintext` is set. Fails if the code is stored
+    /// already in the other mode.
+    pub fn save_code(&mut self, wasm: &[u8], plaintext: bool) -> Result<Checksum, Error> {
+        let checksum = hex::encode(Sha256::digest(wasm));
+        let stored = self.data_dir.join(WASM_DIR).join(&checksum).exists();
+        let stored_plaintext = self.plaintext_codes.contains_hex(&checksum);
+        if stored && stored_plaintext != plaintext {
+            let msg = format!("code {} is stored already in the other mode", checksum);
+            return Err(Error::invalid_arg("plaintext", msg));
+        }
+        let code_id = self.save_wasm(wasm)?;
+        if plaintext {
+            self.plaintext_codes.insert(&code_id)?;
+        }
+        Ok(code_id)
+    }
+
+    /// Pauses or resumes all contracts of `code_id`. Returns whether this changed anything.
+    pub fn set_code_paused(&mut self, code_id: Checksum, paused: bool) -> bool {
+        let changed = if paused {
+            self.paused_codes.insert(code_id)
+        } else {
+            self.paused_codes.remove(&code_id)
+        };
+        if changed {
+            // paused contracts must fail their queries from now on, not answer from the cache
+            self.query_cache.clear();
+        }
+        changed
+    }
+
+    /// An instance of `code_id` for a call with `deps`, unless the code is paused
+    pub fn get_instance(
+        &mut self,
+        code_id: &Checksum,
+        deps: Extern<DB, GoApi, GoQuerier>,
+        gas_limit: u64,
+    ) -> Result<Instance<DB, GoApi, GoQuerier>, Error> {
+        if self.paused_codes.contains(code_id) {
+            return Err(Error::contract_paused(code_id.to_hex()));
+        }
+        self.modules.record_load(code_id);
+        self.usage.record(code_id);
+        Ok(self.inner.get_instance(code_id, deps, gas_limit)?)
+    }
+
+    /// Fails if `msg` of a call of `code_id` is malformed, see `msg_checks`
+    pub fn check_msg(&self, code_id: &Checksum, msg: &[u8]) -> Result<(), Error> {
+        let plaintext = self.plaintext_codes.contains(code_id);
+        msg_checks::check(msg, plaintext, self.consensus_params()?.max_msg_size)
+    }
+
+    /// Checks the stored wasm of `code_id` before it is loaded, if the `verify_wasm_on_load` option is set
+    pub fn verify_code(&mut self, code_id: &Checksum) -> Result<(), Error> {
+        self.options
+            .verify_code(&mut self.verified_codes, &self.data_dir, code_id)
+    }
+
+    /// Fails if `code_id` declares more initial memory than `max_memory_pages`, before it is instantiated.
+    ///
+    /// The VM only bounds the growth of an instance's memory, see `set_instance_memory_limit`. Codes are
+    /// analyzed once, and codes we cannot analyze are left to the VM.
+    pub fn check_memory_limit(&mut self, code_id: &Checksum) -> Result<(), Error> {
+        if !self.memory_pages.contains_key(code_id) {
+            self.analyze_stored(code_id)?;
+        }
+        let pages = self.memory_pages.get(code_id).copied().unwrap_or(0);
+        let max_pages = self.consensus_params()?.max_memory_pages;
+        if pages > max_pages {
+            return Err(Error::memory_limit_exceeded(pages, max_pages));
+        }
+        Ok(())
+    }
+
+    /// Fails if the float policy rejects `code_id`, before it is instantiated. Catches codes stored before the
+    /// policy changed. Codes are analyzed once.
+    pub fn check_float_policy(&mut self, code_id: &Checksum) -> Result<(), Error> {
+        if !self.float_codes.contains_key(code_id) {
+            self.analyze_stored(code_id)?;
+        }
+        let uses_floats = self.float_codes.get(code_id).copied().flatten();
+        self.consensus_params()?.float_policy.check(uses_floats)
+    }
+
+    /// Loads the stored wasm of `code_id` once for all checks before its first instantiation, which keeps
+    /// cold starts from reading every code several times
+    fn analyze_stored(&mut self, code_id: &Checksum) -> Result<(), Error> {
+        let wasm = self.inner.load_wasm(code_id)?;
+        let analysis = analysis::analyze(&wasm).ok();
+        let pages = analysis
+            .as_ref()
+            .and_then(|analysis| analysis.memory_pages)
+            .unwrap_or(0);
+        self.memory_pages.insert(*code_id, pages);
+        self.float_codes
+            .insert(*code_id, analysis.map(|analysis| analysis.uses_floats));
+        Ok(())
+    }
+
+    /// The consensus params of the chain, which a call takes when it starts. Fails until Go set them.
+    pub fn consensus_params(&self) -> Result<Arc<ConsensusParams>, Error> {
+        self.consensus_params
+            .clone()
+            .ok_or_else(Error::consensus_params_unset)
+    }
+
+    /// Replaces the consensus params of the chain. Returns whether this changed anything.
+    ///
+    /// If the params change whether NaNs are canonicalized, the modules compiled the other way are removed, and
+    /// the VM's cache reopened on the same directory, see `update_supported_features`.
+    pub fn set_consensus_params(&mut self, params: ConsensusParams) -> Result<bool, Error> {
+        if self.consensus_params.as_deref() == Some(&params) {
+            return Ok(false);
+        }
+        let canonicalize_nans = params.float_policy.canonicalize_nans();
+        if canonicalize_nans != self.modules.canonicalize_nans() {
+            self.reopen(canonicalize_nans)?;
+        }
+        info!("Updated the consensus params to {:?}", params);
+        self.consensus_params = Some(Arc::new(params));
+        // results of queries which may not be the same under the new params must not be answered from the cache
+        self.query_cache.clear();
+        Ok(true)
+    }
+
+    /// Reopens the VM's cache after `reload_enclave` replaced the enclave. The modules compiled by the old
+    /// enclave are removed if the new one has another MRENCLAVE, and the ones compiled in memory are dropped, so
+    /// no call runs a module of the old enclave.
+    pub fn enclave_reloaded(&mut self) -> Result<(), Error> {
+        self.reopen(self.modules.canonicalize_nans())?;
+        self.query_cache.clear();
+        Ok(())
+    }
+
+    /// Removes the modules not compiled in the current format, see `module_format`, and reopens the VM's cache
+    /// on the same directory
+    fn reopen(&mut self, canonicalize_nans: bool) -> Result<(), Error> {
+        let format = ModuleFormat::current(canonicalize_nans)?;
+        let modules = self.options.open_modules(&self.data_dir, format)?;
+        self.inner =
+            unsafe { CosmCache::new(self.data_dir.clone(), self.supported_features.clone()) }?;
+        self.modules = modules;
+        Ok(())
+    }
+
+    /// Hands the settings of this cache to the VM before a call in the block at `height`, `executes` being
+    /// false for queries, with the consensus params the call took. The VM keeps them process-wide, so they are
+    /// set again for every call, under the lock of the cache, see `CacheCell`.
+    pub fn configure_vm(&mut self, params: &ConsensusParams, height: u64, executes: bool) {
+        set_instance_memory_limit(params.max_memory_pages);
+        set_nan_canonicalization(params.float_policy.canonicalize_nans());
+        self.select_wasm_costs(height, executes);
+    }
+
+    /// Charges the call with the wasm cost table of the block at `height`, and reports its version in the gas
+    /// report of the call, see `wasm_costs`. The table of the VM is process-wide like the other settings of
+    /// `configure_vm`. Queries of past heights switch it back and forth, so the switches are only logged at
+    /// debug level.
+    fn select_wasm_costs(&mut self, height: u64, executes: bool) {
+        if let Some(table) = self.wasm_schedules.select(height, executes) {
+            debug!(
+                "Charging wasm execution with gas schedule {:016x} at height {}",
+                table.version(),
+                height
+            );
+        }
+        set_wasm_cost_table(self.wasm_schedules.active().costs());
+        gas_report::set_schedule_version(self.wasm_schedules.version());
+    }
+
+    /// Replaces the features contracts may require, e.g. when a chain upgrade enables a new capability.
+    ///
+    /// The VM only takes the features when opening its cache, so this reopens it on the same directory. Stored
+    /// codes stay available, but the modules compiled in memory are dropped, so the next call of every code is
+    /// slower. Does nothing if the features did not change.
+    pub fn update_supported_features(&mut self, features: HashSet<String>) -> Result<(), Error> {
+        if features == self.supported_features {
+            return Ok(());
+        }
+        let inner = unsafe { CosmCache::new(self.data_dir.clone(), features.clone()) }?;
+        #[cfg(feature = "shadow")]
+        {
+            if let Some(shadow) = &mut self.shadow {
+                shadow.update_supported_features(&self.data_dir, features.clone())?;
+            }
+        }
+        info!(
+            "Updated the supported features from {:?} to {:?}",
+            self.supported_features, features
+        );
+        self.inner = inner;
+        self.supported_features = features;
+        Ok(())
+    }
+}
+
+/// The `Cache` behind a `*cache_t`, which the library calls of all threads take in turn.
+///
+/// Go calls the library from any number of goroutines, and the `executor` and the `server` run calls on threads
+/// of their own, but a cache may only be used by one call at a time. The calls of other threads wait until the
+/// running one is done. A query which the querier of the running call nests in it through cgo runs on the same
+/// thread and takes the cache right away, as the running call is suspended in the callback, see `reentrancy`.
+/// The client of a server connection sends the queries it nests on connections of their own instead, so while a
+/// call waits for its client in a callback, see `park`, the queries of other threads may take the cache.
+pub struct CacheCell {
+    holders: Mutex<Vec<Holder>>,
+    released: Condvar,
+    cache: UnsafeCell<Cache>,
+}
+
+// The cache is only reached through `lock`, which hands it to one thread at a time
+unsafe impl Sync for CacheCell {}
+
+/// A thread whose call holds a cache. Only the innermost holder of a cache may use it.
+struct Holder {
+    thread: ThreadId,
+    /// Whether the call waits for the client of a server connection in a callback
+    parked: bool,
+}
+
+thread_local! {
+    /// The caches the calls on this thread hold
+    static HELD: RefCell<Vec<&'static CacheCell>> = RefCell::new(Vec::new());
+}
+
+impl CacheCell {
+    pub fn new(cache: Cache) -> Self {
+        CacheCell {
+            holders: Mutex::new(Vec::new()),
+            released: Condvar::new(),
+            cache: UnsafeCell::new(cache),
+        }
+    }
+
+    /// Waits until no call of another thread uses the cache, and holds it until the guard is dropped. A `query`
+    /// may also take the cache while the call holding it is parked.
+    pub fn lock(&'static self, query: bool) -> CacheGuard {
+        if HELD.with(|held| held.borrow().iter().any(|cell| std::ptr::eq(*cell, self))) {
+            return CacheGuard {
+                cell: self,
+                outermost: false,
+            };
+        }
+        let mut holders = self.holders();
+        while !match holders.last() {
+            Some(holder) => query && holder.parked,
+            None => true,
+        } {
+            holders = self
+                .released
+                .wait(holders)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        holders.push(Holder {
+            thread: thread::current().id(),
+            parked: false,
+        });
+        HELD.with(|held| held.borrow_mut().push(self));
+        CacheGuard {
+            cell: self,
+            outermost: true,
+        }
+    }
+
+    fn holders(&self) -> MutexGuard<'_, Vec<Holder>> {
+        // nothing panics while holding the lock, but a poisoned one is still consistent
+        self.holders.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Marks the call of this thread as parked, returning whether it was before
+    #[cfg(feature = "server")]
+    fn set_parked(&self, parked: bool) -> bool {
+        let me = thread::current().id();
+        let mut holders = self.holders();
+        // a query which took the cache while this call was parked must be done before it continues
+        while holders.last().map(|holder| holder.thread) != Some(me) {
+            holders = self
+                .released
+                .wait(holders)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        let holder = holders.last_mut().unwrap();
+        let was_parked = std::mem::replace(&mut holder.parked, parked);
+        self.released.notify_all();
+        was_parked
+    }
+}
+
+/// The cache of a `CacheCell`, held by the calls of this thread
+pub struct CacheGuard {
+    cell: &'static CacheCell,
+    /// Whether this is the guard of the outermost call of this thread, which releases the cache
+    outermost: bool,
+}
+
+impl Deref for CacheGuard {
+    type Target = Cache;
+
+    fn deref(&self) -> &Cache {
+        unsafe { &*self.cell.cache.get() }
+    }
+}
+
+impl DerefMut for CacheGuard {
+    fn deref_mut(&mut self) -> &mut Cache {
+        unsafe { &mut *self.cell.cache.get() }
+    }
+}
+
+impl Drop for CacheGuard {
+    fn drop(&mut self) {
+        if self.outermost {
+            HELD.with(|held| {
+                held.borrow_mut()
+                    .retain(|cell| !std::ptr::eq(*cell, self.cell))
+            });
+            let me = thread::current().id();
+            self.cell.holders().retain(|holder| holder.thread != me);
+            self.cell.released.notify_all();
+        }
+    }
+}
+
+/// Parks the calls of this thread until the returned value is dropped, so queries of other threads may take
+/// their caches. The server parks a call while it waits for the client in a callback.
+#[cfg(feature = "server")]
+pub fn park() -> Parked {
+    let cells = HELD.with(|held| held.borrow().clone());
+    let previous = cells
+        .into_iter()
+        .map(|cell| (cell, cell.set_parked(true)))
+        .collect();
+    Parked { previous }
+}
+
+#[cfg(feature = "server")]
+pub struct Parked {
+    /// The parked caches, and whether they were parked before
+    previous: Vec<(&'static CacheCell, bool)>,
+}
+
+#[cfg(feature = "server")]
+impl Drop for Parked {
+    fn drop(&mut self) {
+        for (cell, was_parked) in self.previous.drain(..) {
+            cell.set_parked(was_parked);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query_cache::QueryKey;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn options_default_for_empty_input() {
+        assert_eq!(
+            CacheOptions::from_json(b"").unwrap(),
+            CacheOptions::default()
+        );
+        assert_eq!(
+            CacheOptions::from_json(b"{}").unwrap(),
+            CacheOptions::default()
+        );
+    }
+
+    #[test]
+    fn options_parse_wasm_costs() {
+        assert_eq!(CacheOptions::default().wasm_costs, WasmCosts::default());
+        let options = CacheOptions::from_json(br#"{"wasm_costs":{"instructions":{"i64.mul":3}}}"#);
+        assert_eq!(options.is_ok(), cfg!(feature = "sgx"));
+        assert!(
+            CacheOptions::from_json(br#"{"wasm_costs":{"instructions":{"i64.pow":3}}}"#).is_err()
+        );
+        assert!(CacheOptions::from_json(br#"{"wasm_costs":{"default":0}}"#).is_err());
+    }
+
+    #[test]
+    fn options_map_modules_if_the_vm_can() {
+        let dir = TempDir::new().unwrap();
+        let format = ModuleFormat {
+            version: 1,
+            library_version: "0.0.0".to_string(),
+            backend: "vanilla".to_string(),
+            enclave: None,
+            canonicalize_nans: false,
+        };
+        let modules = CacheOptions::default()
+            .open_modules(dir.path(), format.clone())
+            .unwrap();
+        assert!(!modules.stats().mmap);
+        if cfg!(feature = "vanilla") {
+            // the vanilla backend cannot map modules, so the option falls back to deserializing them
+            let options = CacheOptions::from_json(br#"{"mmap_modules":true}"#).unwrap();
+            let modules = options.open_modules(dir.path(), format).unwrap();
+            assert!(!modules.stats().mmap);
+        }
+    }
+
+    #[test]
+    fn options_warm_up_the_most_recent_codes() {
+        let dir = TempDir::new().unwrap();
+        let mut usage = UsageJournal::load(dir.path());
+        let (one, two, three) = (
+            Checksum::generate(b"one"),
+            Checksum::generate(b"two"),
+            Checksum::generate(b"three"),
+        );
+        for code_id in &[&one, &two, &three] {
+            usage.record(code_id);
+        }
+        assert!(CacheOptions::default().warm_up_codes(&usage).is_empty());
+        let options = CacheOptions::from_json(br#"{"warm_up_modules":2}"#).unwrap();
+        assert_eq!(
+            options.warm_up_codes(&usage),
+            vec![three.to_hex(), two.to_hex()]
+        );
+        assert!(CacheOptions::from_json(br#"{"warm_up_modules":257}"#).is_err());
+    }
+
+    #[test]
+    fn options_size_the_query_cache() {
+        let key = || QueryKey::new("contract", b"code", b"{}");
+        let mut query_cache = CacheOptions::default().query_cache();
+        query_cache.insert(1, key(), b"result", 10);
+        assert_eq!(query_cache.get(1, &key(), 100).unwrap(), None);
+
+        let options = CacheOptions::from_json(br#"{"query_cache_size":1048576}"#).unwrap();
+        let mut query_cache = options.query_cache();
+        query_cache.insert(1, key(), b"result", 10);
+        assert_eq!(
+            query_cache.get(1, &key(), 100).unwrap(),
+            Some((b"result".to_vec(), 10))
+        );
+    }
+
+    #[test]
+    fn options_deny_unmetered_queries_by_default() {
+        let options = CacheOptions::default();
+        options.check_unmetered(false).unwrap();
+        match options.check_unmetered(true).unwrap_err() {
+            Error::CallDenied { .. } => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let options = CacheOptions::from_json(br#"{"allow_unmetered_queries":true}"#).unwrap();
+        options.check_unmetered(true).unwrap();
+    }
+
+    #[test]
+    fn options_verify_wasm_on_load() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(WASM_DIR)).unwrap();
+        let code_id = Checksum::generate(b"code");
+        fs::write(
+            dir.path().join(WASM_DIR).join(code_id.to_hex()),
+            b"tampered",
+        )
+        .unwrap();
+
+        let mut verified = VerifiedCodes::default();
+        CacheOptions::default()
+            .verify_code(&mut verified, dir.path(), &code_id)
+            .unwrap();
+        let options = CacheOptions::from_json(br#"{"verify_wasm_on_load":true}"#).unwrap();
+        assert!(options
+            .verify_code(&mut verified, dir.path(), &code_id)
+            .is_err());
+    }
+
+    #[test]
+    fn options_reject_unknown_fields() {
+        let error = CacheOptions::from_json(br#"{"max_depth":3}"#).unwrap_err();
+        match error {
+            Error::InvalidOptions { .. } => {}
+            _ => panic!("expect different error"),
+        }
+        // consensus params of old configs fail instead of being ignored
+        assert!(CacheOptions::from_json(br#"{"float_policy":"reject"}"#).is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "shadow"))]
+    fn options_reject_shadow_execution_without_feature() {
+        let error = CacheOptions::from_json(br#"{"shadow_execution":true}"#).unwrap_err();
+        match error {
+            Error::InvalidOptions { .. } => {}
+            _ => panic!("expect different error"),
+        }
+        let options = CacheOptions::from_json(br#"{"shadow_execution":false}"#).unwrap();
+        assert_eq!(options, CacheOptions::default());
+    }
+}
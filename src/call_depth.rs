@@ -0,0 +1,85 @@
+//! Tracks how deeply contract calls are nested.
+//!
+//! Go tells us the depth of every call it makes (0 for calls coming from a transaction, n + 1 for
+//! messages dispatched by a contract at depth n). Additionally, a call that starts while another one
+//! is still running on the same thread (e.g. a contract querying another contract) is nested in it,
+//! whatever Go says. Calls deeper than `ConsensusParams::max_call_depth` fail with
+//! `Error::CallDepthExceeded`, so re-entrancy chains are cut at the same point on every node.
+
+use std::cell::Cell;
+
+use crate::error::Error;
+
+thread_local! {
+    static CURRENT_DEPTH: Cell<Option<u32>> = Cell::new(None);
+}
+
+/// Marks a contract call as running on this thread until it is dropped
+pub struct DepthGuard {
+    depth: u32,
+    previous: Option<u32>,
+}
+
+impl DepthGuard {
+    /// Enters a call which Go reported at `call_depth`.
+    pub fn enter(call_depth: u32, max_call_depth: u32) -> Result<Self, Error> {
+        let previous = CURRENT_DEPTH.with(|current| current.get());
+        let depth = match previous {
+            Some(outer) => call_depth.max(outer.saturating_add(1)),
+            None => call_depth,
+        };
+        if depth > max_call_depth {
+            return Err(Error::call_depth_exceeded(depth, max_call_depth));
+        }
+        CURRENT_DEPTH.with(|current| current.set(Some(depth)));
+        Ok(DepthGuard { depth, previous })
+    }
+
+    /// The effective depth of this call
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        CURRENT_DEPTH.with(|current| current.set(self.previous));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enter_uses_depth_from_go() {
+        let guard = DepthGuard::enter(3, 10).unwrap();
+        assert_eq!(guard.depth(), 3);
+    }
+
+    #[test]
+    fn nested_calls_are_deeper() {
+        let outer = DepthGuard::enter(2, 10).unwrap();
+        let inner = DepthGuard::enter(0, 10).unwrap();
+        assert_eq!(inner.depth(), outer.depth() + 1);
+        drop(inner);
+        let sibling = DepthGuard::enter(0, 10).unwrap();
+        assert_eq!(sibling.depth(), 3);
+    }
+
+    #[test]
+    fn enter_fails_beyond_max_depth() {
+        assert!(DepthGuard::enter(5, 5).is_ok());
+        let error = DepthGuard::enter(6, 5).err().unwrap();
+        match error {
+            Error::CallDepthExceeded { depth, max, .. } => {
+                assert_eq!(depth, 6);
+                assert_eq!(max, 5);
+            }
+            _ => panic!("expect different error"),
+        }
+
+        let _outer = DepthGuard::enter(5, 5).unwrap();
+        assert!(DepthGuard::enter(0, 5).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Hooks of the chain around contract calls.
+//!
+//! Before a contract runs, we ask the DB of the call whether it may run, through the optional `authorize_call`
+//! callback. Go gets the code id, the sender of the message and the entry point, so chains can implement
+//! allowlists, denylists or circuit breakers for codes without forking this library. A denied call fails with
+//! `Error::CallDenied` before the contract is loaded.
+//!
+//! Queries have no sender, so Go gets an empty one. Internal calls are authorized on the DB of the callee,
+//! with the calling contract as the sender.
+//!
+//! After a successful `init`, `handle` or `migrate`, including internal calls, we hand the result to the DB
+//! through the optional `call_result` callback, so indexers running next to the node can consume results
+//! without executing the calls again or parsing block events. Errors returned by the contract are results as
+//! well. Results of plaintext codes are plaintext JSON, all others are encrypted for the sender by the enclave
+//! and are passed on as they are. A result is reported as soon as its call ran, so Go must discard it if the
+//! transaction fails later on.
+//!
+//! The callback cannot change the outcome of the call, so all nodes agree on it whether they run indexers or
+//! not: we only log its errors.
+
+use log::*;
+use serde_json::Value;
+
+use crate::code_stats::EntryPoint;
+use crate::db::DB;
+use crate::error::Error;
+use crate::vm::{Checksum, VmError};
+
+/// The sender of the message in the env `params`
+fn sender(params: &[u8]) -> Result<String, Error> {
+    let env: Value = serde_json::from_slice(params).map_err(|e| Error::invalid_arg("params", e))?;
+    env.pointer("/message/sender")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| Error::invalid_arg("params", "expected a message sender"))
+}
+
+/// Fails with `Error::CallDenied` if Go denies the call of `entry_point` of `code_id` with the env `params`.
+/// Queries have no env.
+pub fn authorize(
+    db: &DB,
+    code_id: &Checksum,
+    entry_point: EntryPoint,
+    params: Option<&[u8]>,
+) -> Result<(), Error> {
+    // most chains have no hook, and the env need not be parsed
+    if db.vtable.authorize_call.is_none() {
+        return Ok(());
+    }
+    let sender = match params {
+        Some(params) => sender(params)?,
+        None => String::new(),
+    };
+    let code_id: Vec<u8> = (*code_id).into();
+    let denied = db
+        .authorize_call(&code_id, &sender, entry_point.name())
+        .map_err(VmError::from)?;
+    match denied {
+        Some(reason) => Err(Error::call_denied(entry_point.name(), reason)),
+        None => Ok(()),
+    }
+}
+
+/// Hands Go the `result` of a successful call of `entry_point` of `code_id`, which is `plaintext` JSON or
+/// encrypted for the sender
+pub fn report_result(
+    db: &DB,
+    code_id: &Checksum,
+    entry_point: EntryPoint,
+    result: &[u8],
+    plaintext: bool,
+) {
+    let code_id: Vec<u8> = (*code_id).into();
+    if let Err(e) = db.report_call_result(&code_id, entry_point.name(), result, plaintext) {
+        warn!("Ignoring failed report of a call result: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::convert::TryFrom;
+
+    use crate::db::db_t;
+    use crate::error::GoResult;
+    use crate::memory::Buffer;
+    use crate::testing::failing_db;
+
+    thread_local! {
+        /// The sender and entry point of the calls Go was asked about
+        static ASKED: RefCell<Vec<(String, String)>> = RefCell::new(Vec::new());
+        /// The entry points, results and modes Go got
+        static REPORTED: RefCell<Vec<(String, Vec<u8>, bool)>> = RefCell::new(Vec::new());
+    }
+
+    /// Denies the code 0x02..02 and fails for the code 0x03..03
+    extern "C" fn authorize_call(
+        _: *mut db_t,
+        code_id: Buffer,
+        sender: Buffer,
+        entry_point: Buffer,
+        allowed: *mut bool,
+        err: *mut Buffer,
+    ) -> i32 {
+        let read = |buf: Buffer| unsafe { buf.read() }.unwrap_or_default().to_vec();
+        let sender = String::from_utf8(read(sender)).unwrap();
+        let entry_point = String::from_utf8(read(entry_point)).unwrap();
+        ASKED.with(|asked| asked.borrow_mut().push((sender, entry_point)));
+        match read(code_id)[0] {
+            2 => unsafe {
+                *allowed = false;
+                *err = Buffer::from_vec(b"the code is paused".to_vec());
+            },
+            3 => return GoResult::Other as i32,
+            _ => {}
+        }
+        GoResult::Ok as i32
+    }
+
+    /// Records the results and fails for the code 0x03..03
+    extern "C" fn call_result(
+        _: *mut db_t,
+        code_id: Buffer,
+        entry_point: Buffer,
+        result: Buffer,
+        plaintext: bool,
+        _: *mut Buffer,
+    ) -> i32 {
+        let read = |buf: Buffer| unsafe { buf.read() }.unwrap_or_default().to_vec();
+        let entry_point = String::from_utf8(read(entry_point)).unwrap();
+        REPORTED.with(|reported| {
+            reported
+                .borrow_mut()
+                .push((entry_point, read(result), plaintext))
+        });
+        if read(code_id)[0] == 3 {
+            return GoResult::Other as i32;
+        }
+        GoResult::Ok as i32
+    }
+
+    fn db(hooks: bool) -> DB {
+        let mut db = failing_db();
+        if hooks {
+            db.vtable.authorize_call = Some(authorize_call);
+            db.vtable.call_result = Some(call_result);
+        }
+        db
+    }
+
+    fn checksum(byte: u8) -> Checksum {
+        Checksum::try_from(&[byte; 32][..]).unwrap()
+    }
+
+    const PARAMS: &[u8] = br#"{"message":{"sender":"secret1sender","sent_funds":[]}}"#;
+
+    #[test]
+    fn authorize_asks_go() {
+        authorize(&db(true), &checksum(1), EntryPoint::Handle, Some(PARAMS)).unwrap();
+        authorize(&db(true), &checksum(1), EntryPoint::Query, None).unwrap();
+        assert_eq!(
+            ASKED.with(|asked| asked.borrow().clone()),
+            vec![
+                ("secret1sender".to_string(), "handle".to_string()),
+                (String::new(), "query".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn authorize_fails_for_denied_calls() {
+        let err = authorize(&db(true), &checksum(2), EntryPoint::Init, Some(PARAMS)).unwrap_err();
+        match &err {
+            Error::CallDenied { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert_eq!(err.to_string(), "Call of init denied: the code is paused");
+
+        // a failing callback is not a denial
+        let err = authorize(&db(true), &checksum(3), EntryPoint::Init, Some(PARAMS)).unwrap_err();
+        assert!(!matches!(err, Error::CallDenied { .. }));
+    }
+
+    #[test]
+    fn authorize_without_hook() {
+        authorize(&db(false), &checksum(2), EntryPoint::Migrate, Some(b"foo")).unwrap();
+        assert!(authorize(&db(true), &checksum(1), EntryPoint::Migrate, Some(b"{}")).is_err());
+    }
+
+    #[test]
+    fn report_result_hands_results_to_go() {
+        report_result(&db(true), &checksum(1), EntryPoint::Init, b"{}", true);
+        // failures are ignored
+        report_result(&db(true), &checksum(3), EntryPoint::Handle, b"\x01", false);
+        report_result(&db(false), &checksum(1), EntryPoint::Handle, b"{}", true);
+        assert_eq!(
+            REPORTED.with(|reported| reported.borrow().clone()),
+            vec![
+                ("init".to_string(), b"{}".to_vec(), true),
+                ("handle".to_string(), b"\x01".to_vec(), false),
+            ]
+        );
+    }
+}
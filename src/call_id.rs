@@ -0,0 +1,97 @@
+//! Correlation ids of contract calls, so the logs of a call on both sides of the FFI can be joined.
+//!
+//! Go may pass a `call_id` to `instantiate`, `handle`, `migrate` and `query`, e.g. the hash of the transaction
+//! or the id of the gRPC request it runs the call for. While the call runs, it is the current call id of its
+//! thread: the library's logger adds it to every log line, and enclave log lines and crash reports carry it.
+//! Calls nested in it, like queries of other contracts, keep the id of the outer call unless they have one.
+//!
+//! The id is not added to error messages, as they end up on chain and must be the same on every node.
+
+use std::cell::RefCell;
+
+use crate::error::Error;
+
+const CALL_ID_ARG: &str = "call_id";
+const MAX_CALL_ID_LEN: usize = 128;
+
+thread_local! {
+    static CURRENT_CALL_ID: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Makes a call id the current one of this thread until it is dropped
+pub struct CallIdScope {
+    previous: Option<String>,
+}
+
+impl CallIdScope {
+    /// Enters the call with `call_id`. Without an id, or with an empty one, the current id is kept.
+    pub fn enter(call_id: Option<&[u8]>) -> Result<Self, Error> {
+        let call_id = match call_id {
+            Some(call_id) if !call_id.is_empty() => Some(validate(call_id)?),
+            _ => None,
+        };
+        let previous = CURRENT_CALL_ID.with(|current| {
+            let previous = current.borrow().clone();
+            if call_id.is_some() {
+                *current.borrow_mut() = call_id;
+            }
+            previous
+        });
+        Ok(CallIdScope { previous })
+    }
+}
+
+impl Drop for CallIdScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT_CALL_ID.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Printable ASCII without spaces, so an id cannot forge or break up log lines
+fn validate(call_id: &[u8]) -> Result<String, Error> {
+    if call_id.len() > MAX_CALL_ID_LEN {
+        let msg = format!("must not be longer than {} bytes", MAX_CALL_ID_LEN);
+        return Err(Error::invalid_arg(CALL_ID_ARG, msg));
+    }
+    if !call_id.iter().all(u8::is_ascii_graphic) {
+        return Err(Error::invalid_arg(
+            CALL_ID_ARG,
+            "must be printable ASCII without spaces",
+        ));
+    }
+    Ok(String::from_utf8_lossy(call_id).into_owned())
+}
+
+/// The id of the call running on this thread, if it has one
+pub fn current() -> Option<String> {
+    CURRENT_CALL_ID.with(|current| current.borrow().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enter_validates_ids() {
+        assert!(CallIdScope::enter(Some(b"tx-4f2a:0")).is_ok());
+        assert!(CallIdScope::enter(Some(b"two words")).is_err());
+        assert!(CallIdScope::enter(Some(b"line\nbreak")).is_err());
+        assert!(CallIdScope::enter(Some(&[b'a'; MAX_CALL_ID_LEN + 1])).is_err());
+        assert_eq!(current(), None);
+    }
+
+    #[test]
+    fn nested_calls_keep_the_outer_id() {
+        let outer = CallIdScope::enter(Some(b"outer")).unwrap();
+        {
+            let _nested = CallIdScope::enter(None).unwrap();
+            assert_eq!(current().as_deref(), Some("outer"));
+            let _own = CallIdScope::enter(Some(b"inner")).unwrap();
+            assert_eq!(current().as_deref(), Some("inner"));
+        }
+        assert_eq!(current().as_deref(), Some("outer"));
+        drop(outer);
+        assert_eq!(current(), None);
+    }
+}
@@ -0,0 +1,282 @@
+//! Which host capabilities the contracts of a code may use.
+//!
+//! Chains run classes of contracts they only partly trust, e.g. codes uploaded without governance, which
+//! should not iterate over storage or query other contracts. The chain restricts codes in the
+//! `code_capabilities` of its consensus params, see `consensus_params`, either allowing only the listed
+//! capabilities or denying them. The restrictions of a code are taken when an instance of it is created, and
+//! every execution keeps its own, so a query nesting in a call runs with the capabilities of its code. A denied
+//! host import fails in its callback without reaching Go, and the call then fails with
+//! `Error::CapabilityDenied`, whatever the contract did with the failed callback.
+//!
+//! Storage reads are not a capability, as every contract needs them.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::vm::{Checksum, FfiError};
+
+const CAPABILITIES_ARG: &str = "code_capabilities";
+
+/// A group of host imports which can be denied together
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Writing and removing storage entries
+    StorageWrite,
+    /// Opening storage iterators
+    Iterators,
+    /// Querying the chain, other contracts included
+    Queries,
+    /// Canonicalizing and humanizing addresses
+    AddressApi,
+}
+
+impl Capability {
+    fn bit(self) -> u8 {
+        match self {
+            Capability::StorageWrite => 1,
+            Capability::Iterators => 1 << 1,
+            Capability::Queries => 1 << 2,
+            Capability::AddressApi => 1 << 3,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::StorageWrite => "storage_write",
+            Capability::Iterators => "iterators",
+            Capability::Queries => "queries",
+            Capability::AddressApi => "address_api",
+        }
+    }
+}
+
+/// A set of capabilities
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+    const ALL: Capabilities = Capabilities(0b1111);
+
+    fn of(list: &[Capability]) -> Self {
+        Capabilities(
+            list.iter()
+                .fold(0, |bits, capability| bits | capability.bit()),
+        )
+    }
+
+    pub fn contains(self, capability: Capability) -> bool {
+        self.0 & capability.bit() != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// The restriction of a code as the chain sets it. At most one of the lists may be given, and none lifts the
+/// restrictions.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Restriction {
+    /// The only capabilities the code may use
+    allow: Option<Vec<Capability>>,
+    /// The capabilities the code may not use
+    deny: Option<Vec<Capability>>,
+}
+
+impl Restriction {
+    /// The capabilities the restriction denies
+    fn denied(self) -> Result<Capabilities, String> {
+        match (self.allow, self.deny) {
+            (Some(_), Some(_)) => Err("allow and deny cannot be combined".to_string()),
+            (Some(allow), None) => Ok(Capabilities(
+                Capabilities::ALL.0 & !Capabilities::of(&allow).0,
+            )),
+            (None, Some(deny)) => Ok(Capabilities::of(&deny)),
+            (None, None) => Ok(Capabilities::default()),
+        }
+    }
+}
+
+/// The capabilities denied to codes by their hex checksum, parsed from a JSON object of `Restriction`s
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(try_from = "HashMap<String, Restriction>")]
+pub struct CodeCapabilities(HashMap<String, Capabilities>);
+
+impl TryFrom<HashMap<String, Restriction>> for CodeCapabilities {
+    type Error = Error;
+
+    fn try_from(restrictions: HashMap<String, Restriction>) -> Result<Self, Error> {
+        let mut denied = HashMap::new();
+        for (checksum, restriction) in restrictions {
+            let invalid =
+                |msg: &str| Error::invalid_arg(CAPABILITIES_ARG, format!("{}: {}", checksum, msg));
+            let code_id = match hex::decode(&checksum) {
+                Ok(code_id) if code_id.len() == 32 => hex::encode(code_id),
+                _ => return Err(invalid("the checksum must be 32 hex encoded bytes")),
+            };
+            let capabilities = restriction.denied().map_err(|msg| invalid(&msg))?;
+            if !capabilities.is_empty() {
+                denied.insert(code_id, capabilities);
+            }
+        }
+        Ok(CodeCapabilities(denied))
+    }
+}
+
+impl CodeCapabilities {
+    pub fn denied(&self, code_id: &Checksum) -> Capabilities {
+        self.0.get(&code_id.to_hex()).copied().unwrap_or_default()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct Restricted {
+    denied: Capabilities,
+    /// The first denied capability the execution tried to use
+    violation: Option<Capability>,
+}
+
+thread_local! {
+    /// The restriction of the innermost execution on this thread
+    static CURRENT: Cell<Option<Restricted>> = Cell::new(None);
+}
+
+/// Restricts the host imports of an execution of a code until it is dropped
+pub struct CapabilityScope {
+    code_id: Checksum,
+    /// The restriction of the execution this one nests in
+    previous: Option<Restricted>,
+}
+
+impl CapabilityScope {
+    pub fn enter(code_id: Checksum, denied: Capabilities) -> Self {
+        let restricted = Restricted {
+            denied,
+            violation: None,
+        };
+        let previous = CURRENT.with(|current| current.replace(Some(restricted)));
+        CapabilityScope { code_id, previous }
+    }
+
+    /// Ends the restriction, failing with `Error::CapabilityDenied` if the execution tried to use a denied
+    /// capability. The callbacks which follow the execution, e.g. of its internal calls, are not restricted.
+    pub fn check(self) -> Result<(), Error> {
+        match CURRENT
+            .with(Cell::get)
+            .and_then(|restricted| restricted.violation)
+        {
+            Some(capability) => Err(Error::capability_denied(
+                self.code_id.to_hex(),
+                capability.name(),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for CapabilityScope {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.previous));
+    }
+}
+
+/// Fails if the innermost execution on this thread may not use `capability`. Outside of executions, everything
+/// is allowed.
+pub fn require(capability: Capability) -> Result<(), FfiError> {
+    CURRENT.with(|current| match current.get() {
+        Some(mut restricted) if restricted.denied.contains(capability) => {
+            restricted.violation = restricted.violation.or(Some(capability));
+            current.set(Some(restricted));
+            Err(FfiError::unknown(format!(
+                "the capability {} is denied",
+                capability.name()
+            )))
+        }
+        _ => Ok(()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn denied(restriction: &str) -> Result<Capabilities, String> {
+        serde_json::from_str::<Restriction>(restriction)
+            .map_err(|e| e.to_string())?
+            .denied()
+    }
+
+    #[test]
+    fn restrictions_parse_from_json() {
+        let denied_caps = denied(r#"{"deny":["iterators","queries"]}"#).unwrap();
+        assert!(denied_caps.contains(Capability::Iterators));
+        assert!(denied_caps.contains(Capability::Queries));
+        assert!(!denied_caps.contains(Capability::StorageWrite));
+
+        let denied_caps = denied(r#"{"allow":["storage_write"]}"#).unwrap();
+        assert!(!denied_caps.contains(Capability::StorageWrite));
+        assert!(denied_caps.contains(Capability::Iterators));
+        assert!(denied_caps.contains(Capability::AddressApi));
+        assert_eq!(denied(r#"{"allow":[]}"#).unwrap(), Capabilities::ALL);
+
+        assert!(denied("{}").unwrap().is_empty());
+        assert!(denied(r#"{"allow":null,"deny":null}"#).unwrap().is_empty());
+        assert!(denied(r#"{"allow":[],"deny":[]}"#).is_err());
+        assert!(denied(r#"{"deny":["storage_read"]}"#).is_err());
+    }
+
+    #[test]
+    fn code_capabilities_parse_by_checksum() {
+        let code_id = Checksum::generate(b"one");
+        let json = format!(
+            r#"{{"{}":{{"deny":["queries"]}},"{}":{{}}}}"#,
+            code_id.to_hex().to_uppercase(),
+            Checksum::generate(b"two").to_hex()
+        );
+        let codes: CodeCapabilities = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            codes.denied(&code_id),
+            Capabilities::of(&[Capability::Queries])
+        );
+        assert!(codes.denied(&Checksum::generate(b"two")).is_empty());
+        assert!(codes.denied(&Checksum::generate(b"three")).is_empty());
+
+        assert!(serde_json::from_str::<CodeCapabilities>(r#"{"aabb":{}}"#).is_err());
+        let both = format!(r#"{{"{}":{{"allow":[],"deny":[]}}}}"#, code_id.to_hex());
+        assert!(serde_json::from_str::<CodeCapabilities>(&both).is_err());
+    }
+
+    #[test]
+    fn require_fails_for_denied_capabilities() {
+        let scope = CapabilityScope::enter(
+            Checksum::generate(b"one"),
+            Capabilities::of(&[Capability::Iterators]),
+        );
+        require(Capability::StorageWrite).unwrap();
+        assert!(require(Capability::Iterators).is_err());
+        assert!(require(Capability::Queries).is_ok());
+        match scope.check() {
+            Err(Error::CapabilityDenied { capability, .. }) => assert_eq!(capability, "iterators"),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn scopes_nest() {
+        let outer = CapabilityScope::enter(Checksum::generate(b"one"), Capabilities::ALL);
+        let inner = CapabilityScope::enter(Checksum::generate(b"two"), Capabilities::default());
+        require(Capability::Queries).unwrap();
+        inner.check().unwrap();
+        assert!(require(Capability::Queries).is_err());
+        assert!(outer.check().is_err());
+
+        // outside of executions everything is allowed
+        require(Capability::Queries).unwrap();
+    }
+}
@@ -0,0 +1,320 @@
+//! The contract developer CLI, which checks, analyzes and dry-runs contracts without a node.
+//!
+//! Dry runs store the code in a scratch cache and call it through the extern "C" functions with the mocks
+//! of `testing`, so a contract fails here the way it would on chain. Nodes with SGX only accept encrypted
+//! messages, so dry runs with plaintext JSON messages need a `vanilla` build.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::analysis;
+use crate::error::Error;
+use crate::testing::{Harness, Outcome};
+use crate::GasReport;
+
+pub const USAGE: &str = "usage: cosmwasm-cli check <wasm> [--features <csv>]
+       cosmwasm-cli analyze <wasm>
+       cosmwasm-cli run <wasm> --init <json> [--handle <json>]... [--query <json>]...
+                        [--sender <address>] [--funds <json>] [--gas-limit <n>] [--features <csv>]";
+
+/// A command line of the `cosmwasm-cli` binary
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Stores the code in a scratch cache, which runs the same checks as storing it on chain
+    Check { wasm: PathBuf, features: String },
+    /// Prints the `Analysis` of the code
+    Analyze { wasm: PathBuf },
+    /// Instantiates the code, then runs the calls in order against the same mock storage
+    Run(DryRun),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DryRun {
+    pub wasm: PathBuf,
+    pub features: String,
+    pub init: String,
+    pub calls: Vec<Call>,
+    pub sender: String,
+    /// The JSON encoded coins sent with every call
+    pub funds: String,
+    pub gas_limit: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Call {
+    Handle(String),
+    Query(String),
+}
+
+impl Command {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, Error> {
+        let mut args = args.into_iter();
+        let command = args.next().ok_or_else(|| Error::empty_arg("command"))?;
+        let wasm = args
+            .next()
+            .map(PathBuf::from)
+            .ok_or_else(|| Error::empty_arg("wasm"))?;
+        let mut run = DryRun {
+            wasm,
+            features: "staking".to_string(),
+            init: String::new(),
+            calls: Vec::new(),
+            sender: "creator".to_string(),
+            funds: "[]".to_string(),
+            gas_limit: 100_000_000,
+        };
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| Error::invalid_arg(flag.as_str(), "missing value"))?;
+            match flag.as_str() {
+                "--features" => run.features = value,
+                "--init" => run.init = value,
+                "--handle" => run.calls.push(Call::Handle(value)),
+                "--query" => run.calls.push(Call::Query(value)),
+                "--sender" => run.sender = value,
+                "--funds" => run.funds = value,
+                "--gas-limit" => {
+                    run.gas_limit = value
+                        .parse()
+                        .map_err(|e| Error::invalid_arg(flag.as_str(), e))?
+                }
+                _ => return Err(Error::invalid_arg(flag.as_str(), "unknown flag")),
+            }
+        }
+        match command.as_str() {
+            "check" => Ok(Command::Check {
+                wasm: run.wasm,
+                features: run.features,
+            }),
+            "analyze" => Ok(Command::Analyze { wasm: run.wasm }),
+            "run" if run.init.is_empty() => Err(Error::empty_arg("--init")),
+            "run" => Ok(Command::Run(run)),
+            _ => Err(Error::invalid_arg("command", "unknown command")),
+        }
+    }
+}
+
+/// Runs `command`, writing its results to `out`
+pub fn run<W: Write>(command: Command, mut out: W) -> Result<(), Error> {
+    match command {
+        Command::Check { wasm, features } => {
+            let wasm = read_wasm(&wasm)?;
+            let (mut harness, data_dir) = scratch_harness(&features)?;
+            let res = harness.store_code(&wasm);
+            drop(harness);
+            let _ = fs::remove_dir_all(data_dir);
+            let code_id = res.map_err(Error::vm_err)?;
+            writeln!(out, "ok: code id {}", hex::encode(code_id)).map_err(Error::vm_err)
+        }
+        Command::Analyze { wasm } => {
+            let analysis = analysis::analyze(&read_wasm(&wasm)?)?;
+            print_json(&mut out, &analysis)
+        }
+        Command::Run(dry_run) => {
+            let wasm = read_wasm(&dry_run.wasm)?;
+            let (mut harness, data_dir) = scratch_harness(&dry_run.features)?;
+            let res = run_calls(&mut harness, &wasm, &dry_run, &mut out);
+            drop(harness);
+            let _ = fs::remove_dir_all(data_dir);
+            res
+        }
+    }
+}
+
+/// The result of one call of a dry run
+#[derive(Serialize)]
+struct Step<'a> {
+    call: &'a str,
+    /// The contract's response, decoded from JSON if possible
+    result: Option<Value>,
+    /// The error of the library, if the call failed before the contract could respond
+    error: Option<String>,
+    gas_used: u64,
+    gas_report: GasReport,
+}
+
+fn run_calls<W: Write>(
+    harness: &mut Harness,
+    wasm: &[u8],
+    dry_run: &DryRun,
+    out: &mut W,
+) -> Result<(), Error> {
+    harness.gas_limit = dry_run.gas_limit;
+    let code_id = harness.store_code(wasm).map_err(Error::vm_err)?;
+    let params = mock_params(&dry_run.sender, &dry_run.funds)?;
+
+    let init = harness.instantiate(&code_id, &params, dry_run.init.as_bytes());
+    if !print_step(out, "init", init)? {
+        return Err(Error::vm_err("init failed"));
+    }
+    for call in &dry_run.calls {
+        let (name, res) = match call {
+            Call::Handle(msg) => ("handle", harness.handle(&code_id, &params, msg.as_bytes())),
+            Call::Query(msg) => ("query", harness.query(&code_id, msg.as_bytes())),
+        };
+        print_step(out, name, res)?;
+    }
+    Ok(())
+}
+
+/// Prints the step and returns whether the call succeeded
+fn print_step<W: Write>(
+    out: &mut W,
+    call: &str,
+    res: Result<Outcome, String>,
+) -> Result<bool, Error> {
+    let step = match res {
+        Ok(outcome) => Step {
+            call,
+            result: Some(
+                serde_json::from_slice(&outcome.data)
+                    .unwrap_or_else(|_| Value::String(base64::encode(&outcome.data))),
+            ),
+            error: None,
+            gas_used: outcome.gas_used,
+            gas_report: outcome.gas_report,
+        },
+        Err(e) => Step {
+            call,
+            result: None,
+            error: Some(e),
+            gas_used: 0,
+            gas_report: GasReport::default(),
+        },
+    };
+    let ok = step.error.is_none();
+    print_json(out, &step)?;
+    Ok(ok)
+}
+
+fn mock_params(sender: &str, funds: &str) -> Result<Vec<u8>, Error> {
+    let funds: Value = serde_json::from_str(funds).map_err(|e| Error::invalid_arg("--funds", e))?;
+    let env = json!({
+        "block": { "height": 12345, "time": 1571797419, "chain_id": "cosmwasm-cli" },
+        "message": { "sender": sender, "sent_funds": funds },
+        "contract": { "address": "contract" },
+        "contract_key": "",
+    });
+    serde_json::to_vec(&env).map_err(Error::vm_err)
+}
+
+fn read_wasm(path: &Path) -> Result<Vec<u8>, Error> {
+    fs::read(path).map_err(|e| Error::invalid_arg("wasm", format!("{}: {}", path.display(), e)))
+}
+
+/// Opens a cache in a fresh directory, which the caller removes when done
+fn scratch_harness(features: &str) -> Result<(Harness, PathBuf), Error> {
+    let data_dir = std::env::temp_dir().join(format!("cosmwasm-cli-{}", process::id()));
+    fs::create_dir_all(&data_dir).map_err(Error::vm_err)?;
+    let dir = data_dir.to_string_lossy().into_owned();
+    match Harness::new(&dir, features, b"") {
+        Ok(harness) => Ok((harness, data_dir)),
+        Err(e) => {
+            let _ = fs::remove_dir_all(&data_dir);
+            Err(Error::vm_err(e))
+        }
+    }
+}
+
+fn print_json<W: Write, T: Serialize>(out: &mut W, value: &T) -> Result<(), Error> {
+    serde_json::to_writer_pretty(&mut *out, value).map_err(Error::vm_err)?;
+    writeln!(out).map_err(Error::vm_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn command_from_args_works() {
+        assert_eq!(
+            Command::from_args(args(&["analyze", "contract.wasm"])).unwrap(),
+            Command::Analyze {
+                wasm: PathBuf::from("contract.wasm")
+            }
+        );
+
+        let command = Command::from_args(args(&[
+            "run",
+            "contract.wasm",
+            "--init",
+            "{}",
+            "--query",
+            r#"{"count":{}}"#,
+            "--handle",
+            r#"{"increment":{}}"#,
+            "--gas-limit",
+            "5000",
+        ]))
+        .unwrap();
+        match command {
+            Command::Run(dry_run) => {
+                assert_eq!(dry_run.init, "{}");
+                assert_eq!(
+                    dry_run.calls,
+                    vec![
+                        Call::Query(r#"{"count":{}}"#.to_string()),
+                        Call::Handle(r#"{"increment":{}}"#.to_string()),
+                    ]
+                );
+                assert_eq!(dry_run.gas_limit, 5000);
+                assert_eq!(dry_run.sender, "creator");
+            }
+            c => panic!("unexpected command: {:?}", c),
+        }
+    }
+
+    #[test]
+    fn command_from_args_rejects_bad_input() {
+        match Command::from_args(args(&["run", "contract.wasm"])).unwrap_err() {
+            Error::EmptyArg { name, .. } => assert_eq!(name, "--init"),
+            e => panic!("unexpected error: {}", e),
+        }
+        match Command::from_args(args(&["deploy", "contract.wasm"])).unwrap_err() {
+            Error::InvalidArg { name, .. } => assert_eq!(name, "command"),
+            e => panic!("unexpected error: {}", e),
+        }
+        match Command::from_args(args(&["check"])).unwrap_err() {
+            Error::EmptyArg { name, .. } => assert_eq!(name, "wasm"),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn mock_params_includes_sender_and_funds() {
+        let params = mock_params("fred", r#"[{"denom":"uscrt","amount":"5"}]"#).unwrap();
+        let params: Value = serde_json::from_slice(&params).unwrap();
+        assert_eq!(params["message"]["sender"], "fred");
+        assert_eq!(params["message"]["sent_funds"][0]["amount"], "5");
+        assert!(mock_params("fred", "5uscrt").is_err());
+    }
+
+    #[test]
+    fn print_step_decodes_results() {
+        let mut out = Vec::new();
+        let outcome = Outcome {
+            data: br#"{"Ok":{"messages":[]}}"#.to_vec(),
+            gas_used: 42,
+            gas_report: GasReport::default(),
+        };
+        assert!(print_step(&mut out, "handle", Ok(outcome)).unwrap());
+        let step: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(step["result"]["Ok"]["messages"], json!([]));
+        assert_eq!(step["gas_used"], 42);
+
+        let mut out = Vec::new();
+        assert!(!print_step(&mut out, "query", Err("no such code".to_string())).unwrap());
+        let step: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(step["error"], "no such code");
+    }
+}
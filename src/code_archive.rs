@@ -0,0 +1,242 @@
+//! Bulk import and export of codes, for chains which store thousands of codes at genesis.
+//!
+//! An archive is the magic `ARCHIVE_MAGIC` followed by one entry per code: its 32 byte checksum, the length
+//! of its wasm as big endian u32, and the wasm. `export_codes` produces archives of the stored codes, e.g. for
+//! genesis export or moving a node's data dir, and `import_codes` verifies the checksums of all entries on
+//! several threads before it stores anything, so a corrupt archive leaves the cache untouched. Codes which are
+//! already stored are skipped.
+//!
+//! The VM compiles a code when it is first instantiated, and offers no way to compile it ahead of that.
+
+use std::convert::TryInto;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::cache::Cache;
+use crate::error::Error;
+use crate::snapshot::{stored_codes, WASM_DIR};
+use crate::vm::Checksum;
+
+pub const ARCHIVE_MAGIC: &[u8] = b"cwcodes\x01";
+
+const ARCHIVE_ARG: &str = "archive";
+const FILTER_ARG: &str = "checksums_filter";
+const CHECKSUM_LEN: usize = 32;
+/// The most threads verifying the checksums of an archive
+const MAX_HASH_THREADS: usize = 8;
+
+/// The result of `import_codes`, returned as JSON
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ImportSummary {
+    /// The hex checksums of the codes stored by the import
+    pub imported: Vec<String>,
+    /// The hex checksums of the codes which were stored already
+    pub existing: Vec<String>,
+}
+
+/// Where an entry's wasm is in the archive
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Entry {
+    checksum: [u8; CHECKSUM_LEN],
+    start: usize,
+    end: usize,
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::invalid_arg(ARCHIVE_ARG, msg)
+}
+
+/// Splits an archive into its entries, without verifying them
+fn parse(archive: &[u8]) -> Result<Vec<Entry>, Error> {
+    if !archive.starts_with(ARCHIVE_MAGIC) {
+        return Err(invalid("not a code archive"));
+    }
+    let mut entries = Vec::new();
+    let mut pos = ARCHIVE_MAGIC.len();
+    while pos < archive.len() {
+        if archive.len() - pos < CHECKSUM_LEN + 4 {
+            return Err(invalid("truncated entry header"));
+        }
+        let mut checksum = [0u8; CHECKSUM_LEN];
+        checksum.copy_from_slice(&archive[pos..pos + CHECKSUM_LEN]);
+        pos += CHECKSUM_LEN;
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&archive[pos..pos + 4]);
+        pos += 4;
+        let len = u32::from_be_bytes(len) as usize;
+        if archive.len() - pos < len {
+            return Err(invalid("truncated wasm"));
+        }
+        entries.push(Entry {
+            checksum,
+            start: pos,
+            end: pos + len,
+        });
+        pos += len;
+    }
+    Ok(entries)
+}
+
+/// Fails with the checksum of the first entry whose wasm does not hash to it
+fn verify(archive: Arc<Vec<u8>>, entries: &[Entry]) -> Result<(), Error> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let threads = entries.len().min(MAX_HASH_THREADS);
+    let chunk_size = (entries.len() + threads - 1) / threads;
+    let handles: Vec<_> = entries
+        .chunks(chunk_size)
+        .map(|chunk| {
+            let archive = Arc::clone(&archive);
+            let chunk = chunk.to_vec();
+            thread::spawn(move || {
+                chunk.into_iter().find(|entry| {
+                    Sha256::digest(&archive[entry.start..entry.end])[..] != entry.checksum[..]
+                })
+            })
+        })
+        .collect();
+    let mut mismatch = None;
+    for handle in handles {
+        let found = handle.join().map_err(|_| Error::panic())?;
+        mismatch = mismatch.or(found);
+    }
+    match mismatch {
+        Some(entry) => {
+            let msg = format!(
+                "wasm does not match checksum {}",
+                hex::encode(entry.checksum)
+            );
+            Err(Error::invalid_arg(ARCHIVE_ARG, msg))
+        }
+        None => Ok(()),
+    }
+}
+
+fn is_stored(data_dir: &Path, checksum: &str) -> bool {
+    data_dir.join(WASM_DIR).join(checksum).exists()
+}
+
+/// Stores all codes of `archive` in `cache`
+pub fn import_codes(cache: &mut Cache, archive: &[u8]) -> Result<ImportSummary, Error> {
+    let entries = parse(archive)?;
+    // the hashing threads need an owned copy, which is cheap compared to hashing it
+    let archive = Arc::new(archive.to_vec());
+    verify(Arc::clone(&archive), &entries)?;
+
+    let mut summary = ImportSummary::default();
+    for entry in entries {
+        let checksum = hex::encode(entry.checksum);
+        if is_stored(&cache.data_dir, &checksum) {
+            summary.existing.push(checksum);
+            continue;
+        }
+        cache.save_wasm(&archive[entry.start..entry.end])?;
+        summary.imported.push(checksum);
+    }
+    Ok(summary)
+}
+
+fn write_entry(archive: &mut Vec<u8>, checksum: &[u8], wasm: &[u8]) -> Result<(), Error> {
+    let len: u32 = wasm
+        .len()
+        .try_into()
+        .map_err(|_| Error::vm_err("wasm too large for an archive"))?;
+    archive.extend_from_slice(checksum);
+    archive.extend_from_slice(&len.to_be_bytes());
+    archive.extend_from_slice(wasm);
+    Ok(())
+}
+
+/// Produces an archive of the codes with the checksums in `filter`, a concatenation of 32 byte checksums,
+/// or of all stored codes if there is no filter. The codes are in the order of the filter, or sorted by checksum.
+pub fn export_codes(cache: &mut Cache, filter: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+    let checksums: Vec<Vec<u8>> = match filter {
+        Some(filter) => {
+            if filter.len() % CHECKSUM_LEN != 0 {
+                let msg = format!("expected a multiple of {} bytes", CHECKSUM_LEN);
+                return Err(Error::invalid_arg(FILTER_ARG, msg));
+            }
+            filter.chunks(CHECKSUM_LEN).map(<[u8]>::to_vec).collect()
+        }
+        None => stored_codes(&cache.data_dir)?
+            .iter()
+            .map(|checksum| hex::decode(checksum).map_err(Error::vm_err))
+            .collect::<Result<_, _>>()?,
+    };
+
+    let mut archive = ARCHIVE_MAGIC.to_vec();
+    for raw in checksums {
+        let checksum: Checksum = raw.as_slice().try_into()?;
+        cache.verify_code(&checksum)?;
+        let wasm = cache.inner.load_wasm(&checksum)?;
+        write_entry(&mut archive, &raw, &wasm)?;
+    }
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(codes: &[&[u8]]) -> Vec<u8> {
+        let mut out = ARCHIVE_MAGIC.to_vec();
+        for code in codes {
+            write_entry(&mut out, &Sha256::digest(code), code).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn parse_splits_entries() {
+        let data = archive(&[b"first", b"", b"third"]);
+        let entries = parse(&data).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(&data[entries[0].start..entries[0].end], b"first");
+        assert_eq!(entries[1].start, entries[1].end);
+        assert_eq!(&data[entries[2].start..entries[2].end], b"third");
+        assert_eq!(entries[2].checksum[..], Sha256::digest(b"third")[..]);
+
+        assert!(parse(ARCHIVE_MAGIC).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_rejects_invalid_archives() {
+        assert!(parse(b"some invalid data").is_err());
+        let mut data = archive(&[b"first"]);
+        data.pop();
+        assert!(parse(&data).is_err());
+        let mut data = archive(&[b"first"]);
+        data.extend_from_slice(&[0; 10]);
+        assert!(parse(&data).is_err());
+    }
+
+    #[test]
+    fn verify_checks_all_entries() {
+        let codes: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i; 100]).collect();
+        let refs: Vec<&[u8]> = codes.iter().map(Vec::as_slice).collect();
+        let data = archive(&refs);
+        let entries = parse(&data).unwrap();
+        verify(Arc::new(data.clone()), &entries).unwrap();
+
+        let mut corrupt = data;
+        let last = entries[19];
+        corrupt[last.start] ^= 1;
+        match verify(Arc::new(corrupt), &entries).unwrap_err() {
+            Error::InvalidArg { msg, .. } => {
+                assert_eq!(
+                    msg,
+                    format!(
+                        "wasm does not match checksum {}",
+                        hex::encode(last.checksum)
+                    )
+                )
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
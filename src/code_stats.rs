@@ -0,0 +1,351 @@
+//! Execution statistics per code, so chains can find the contracts which dominate block time.
+//!
+//! The cache counts every execution of a contract, including the internal calls of `internal_calls`, by code
+//! id and entry point. `get_code_stats` returns the counters as JSON, sorted by the total execution time.
+//!
+//! The peak memory of every execution is reported in the gas report of its call as well, so chains can spot
+//! contracts approaching `max_memory_pages` before they start failing.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::api::GoApi;
+use crate::db::DB;
+use crate::gas_report;
+use crate::host_calls::HostCallCounts;
+use crate::open_iterators;
+use crate::querier::GoQuerier;
+use crate::vm::{Checksum, Instance};
+
+/// The size of a wasm memory page in bytes
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+/// The entry points of a contract
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryPoint {
+    Init,
+    Handle,
+    Migrate,
+    Query,
+    BeginBlock,
+    EndBlock,
+}
+
+impl EntryPoint {
+    /// The name of the entry point, as in the JSON of the statistics
+    pub fn name(self) -> &'static str {
+        match self {
+            EntryPoint::Init => "init",
+            EntryPoint::Handle => "handle",
+            EntryPoint::Migrate => "migrate",
+            EntryPoint::Query => "query",
+            EntryPoint::BeginBlock => "begin_block",
+            EntryPoint::EndBlock => "end_block",
+        }
+    }
+}
+
+/// Measures one execution of a contract, from loading the instance until it is recycled
+pub struct Execution {
+    started: Instant,
+    /// The gas the callbacks reported before the execution
+    used_externally: u64,
+    /// The host imports the call invoked before the execution
+    host_calls: HostCallCounts,
+    /// The memory of the instance, in pages
+    memory_pages: u32,
+}
+
+impl Execution {
+    pub fn start() -> Self {
+        let report = gas_report::current_report();
+        Execution {
+            started: Instant::now(),
+            used_externally: report.used_externally,
+            host_calls: report.host_calls,
+            memory_pages: 0,
+        }
+    }
+
+    /// Measures the memory of `instance` before it is recycled, and reports it in the gas report of the call.
+    /// Wasm memories never shrink, so this is the peak of the execution.
+    pub fn measure_memory(&mut self, instance: &Instance<DB, GoApi, GoQuerier>) {
+        self.memory_pages = (instance.get_memory_size() / WASM_PAGE_SIZE) as u32;
+        gas_report::record_memory_pages(self.memory_pages);
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct Counters {
+    calls: u64,
+    failures: u64,
+    gas: u64,
+    duration: Duration,
+    max_open_iterators: u32,
+    max_memory_pages: u32,
+    host_calls: HostCallCounts,
+}
+
+/// The statistics of one entry point of one code, returned by `get_code_stats`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CodeStatsEntry {
+    /// The code id in hex
+    pub code_id: String,
+    pub entry_point: EntryPoint,
+    pub calls: u64,
+    /// The executions which failed in the VM, e.g. because they ran out of gas or panicked.
+    /// Errors returned by the contract itself are not counted.
+    pub failures: u64,
+    pub failure_rate: f64,
+    /// The average of the gas used in wasm plus the gas reported by the storage and querier callbacks
+    pub average_gas: u64,
+    pub average_duration_us: u64,
+    pub total_duration_us: u64,
+    /// The most storage iterators an execution had open at once, see `open_iterators`
+    pub max_open_iterators: u32,
+    /// The most memory an execution's instance had, in 64 KiB pages, see `max_memory_pages`
+    pub max_memory_pages: u32,
+    /// How often all executions together invoked each host import, see `host_calls`
+    pub host_calls: HostCallCounts,
+}
+
+/// The counters of a cache
+#[derive(Default)]
+pub struct CodeStats {
+    counters: HashMap<(String, EntryPoint), Counters>,
+}
+
+impl CodeStats {
+    /// Counts an `execution` of `code_id` which used `gas_used` gas in wasm
+    pub fn record(
+        &mut self,
+        code_id: &Checksum,
+        entry_point: EntryPoint,
+        execution: Execution,
+        gas_used: u64,
+        succeeded: bool,
+    ) {
+        let report = gas_report::current_report();
+        let used_externally = report
+            .used_externally
+            .saturating_sub(execution.used_externally);
+        self.add(
+            code_id.to_hex(),
+            entry_point,
+            execution.started.elapsed(),
+            gas_used.saturating_add(used_externally),
+            open_iterators::high_water(),
+            execution.memory_pages,
+            &report.host_calls.since(&execution.host_calls),
+            succeeded,
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn add(
+        &mut self,
+        code_id: String,
+        entry_point: EntryPoint,
+        duration: Duration,
+        gas: u64,
+        open_iterators: u32,
+        memory_pages: u32,
+        host_calls: &HostCallCounts,
+        succeeded: bool,
+    ) {
+        let counters = self.counters.entry((code_id, entry_point)).or_default();
+        counters.calls += 1;
+        if !succeeded {
+            counters.failures += 1;
+        }
+        counters.gas = counters.gas.saturating_add(gas);
+        counters.duration += duration;
+        counters.max_open_iterators = counters.max_open_iterators.max(open_iterators);
+        counters.max_memory_pages = counters.max_memory_pages.max(memory_pages);
+        counters.host_calls.add(host_calls);
+    }
+
+    /// The statistics of all codes, the ones which took the most time first
+    pub fn entries(&self) -> Vec<CodeStatsEntry> {
+        let mut entries: Vec<CodeStatsEntry> = self
+            .counters
+            .iter()
+            .map(|((code_id, entry_point), counters)| {
+                let total_duration_us = counters.duration.as_micros() as u64;
+                CodeStatsEntry {
+                    code_id: code_id.clone(),
+                    entry_point: *entry_point,
+                    calls: counters.calls,
+                    failures: counters.failures,
+                    failure_rate: counters.failures as f64 / counters.calls as f64,
+                    average_gas: counters.gas / counters.calls,
+                    average_duration_us: total_duration_us / counters.calls,
+                    total_duration_us,
+                    max_open_iterators: counters.max_open_iterators,
+                    max_memory_pages: counters.max_memory_pages,
+                    host_calls: counters.host_calls,
+                }
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            b.total_duration_us
+                .cmp(&a.total_duration_us)
+                .then_with(|| a.code_id.cmp(&b.code_id))
+                .then_with(|| a.entry_point.cmp(&b.entry_point))
+        });
+        entries
+    }
+
+    /// Forgets all counters, e.g. to collect the statistics of every block on its own
+    pub fn reset(&mut self) {
+        self.counters.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reads(db_read: u64) -> HostCallCounts {
+        HostCallCounts {
+            db_read,
+            ..HostCallCounts::default()
+        }
+    }
+
+    #[test]
+    fn entry_point_names_match_the_json() {
+        for entry_point in &[
+            EntryPoint::Init,
+            EntryPoint::Handle,
+            EntryPoint::Migrate,
+            EntryPoint::Query,
+            EntryPoint::BeginBlock,
+            EntryPoint::EndBlock,
+        ] {
+            assert_eq!(
+                serde_json::to_value(entry_point).unwrap(),
+                entry_point.name()
+            );
+        }
+    }
+
+    #[test]
+    fn entries_average_the_executions() {
+        let mut stats = CodeStats::default();
+        stats.add(
+            "aa".to_string(),
+            EntryPoint::Handle,
+            Duration::from_micros(100),
+            1000,
+            3,
+            17,
+            &reads(2),
+            true,
+        );
+        stats.add(
+            "aa".to_string(),
+            EntryPoint::Handle,
+            Duration::from_micros(300),
+            2000,
+            1,
+            20,
+            &reads(3),
+            false,
+        );
+        stats.add(
+            "aa".to_string(),
+            EntryPoint::Query,
+            Duration::from_micros(50),
+            10,
+            0,
+            1,
+            &HostCallCounts::default(),
+            true,
+        );
+
+        let entries = stats.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0],
+            CodeStatsEntry {
+                code_id: "aa".to_string(),
+                entry_point: EntryPoint::Handle,
+                calls: 2,
+                failures: 1,
+                failure_rate: 0.5,
+                average_gas: 1500,
+                average_duration_us: 200,
+                total_duration_us: 400,
+                max_open_iterators: 3,
+                max_memory_pages: 20,
+                host_calls: HostCallCounts {
+                    db_read: 5,
+                    ..HostCallCounts::default()
+                },
+            }
+        );
+        assert_eq!(entries[1].entry_point, EntryPoint::Query);
+        assert_eq!(entries[1].failure_rate, 0.0);
+    }
+
+    #[test]
+    fn entries_sort_by_total_duration() {
+        let mut stats = CodeStats::default();
+        stats.add(
+            "aa".to_string(),
+            EntryPoint::Init,
+            Duration::from_micros(10),
+            1,
+            0,
+            0,
+            &HostCallCounts::default(),
+            true,
+        );
+        stats.add(
+            "bb".to_string(),
+            EntryPoint::Handle,
+            Duration::from_micros(30),
+            1,
+            0,
+            0,
+            &HostCallCounts::default(),
+            true,
+        );
+        stats.add(
+            "cc".to_string(),
+            EntryPoint::Handle,
+            Duration::from_micros(20),
+            1,
+            0,
+            0,
+            &HostCallCounts::default(),
+            true,
+        );
+        let order: Vec<String> = stats.entries().into_iter().map(|e| e.code_id).collect();
+        assert_eq!(order, vec!["bb", "cc", "aa"]);
+
+        stats.reset();
+        assert!(stats.entries().is_empty());
+    }
+
+    #[test]
+    fn entries_serialize_entry_points_in_snake_case() {
+        let mut stats = CodeStats::default();
+        stats.add(
+            "aa".to_string(),
+            EntryPoint::Migrate,
+            Duration::from_micros(1),
+            1,
+            0,
+            0,
+            &HostCallCounts::default(),
+            true,
+        );
+        let json = serde_json::to_string(&stats.entries()).unwrap();
+        assert!(json.contains(r#""entry_point":"migrate""#), "{}", json);
+    }
+}
@@ -0,0 +1,239 @@
+//! The settings of the VM which decide the results of contract calls.
+//!
+//! Every node passes its own `CacheOptions` to `init_cache`, so nodes configured differently would disagree on
+//! the gas, the errors and the results of calls, and split the chain. The settings affecting those are
+//! therefore not cache options: Go reads them from the state of the chain, where governance changes them, and
+//! hands them to `set_consensus_params` once the cache is created and whenever they change. Every call takes the
+//! params set when it starts. Calls fail until the params are set, so a node which forgets them after a restart
+//! stops instead of running with defaults.
+//!
+//! The float policy also decides how modules are compiled, see `module_format`. When it changes whether NaNs are
+//! canonicalized, the compiled modules are removed and compiled again on their next use.
+
+use serde::Deserialize;
+
+use crate::capabilities::CodeCapabilities;
+use crate::error::Error;
+use crate::events::{self, EventLimits};
+use crate::float_policy::FloatPolicy;
+use crate::gas_schedule::{FallbackGasSchedule, GasPolicy};
+
+const CONSENSUS_PARAMS_ARG: &str = "consensus_params";
+
+/// The most pages a wasm32 memory can have, 4 GiB
+const WASM_MAX_PAGES: u32 = 65536;
+
+/// Chain level settings, passed to `set_consensus_params` as JSON.
+///
+/// Every field has a default, so `{}` selects the default behaviour.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConsensusParams {
+    /// The deepest nesting of contract calls we execute. The outermost call has depth 0.
+    pub max_call_depth: u32,
+    /// The largest result of `handle` or `query`, in bytes, we pass back to Go
+    pub max_response_size: usize,
+    /// How the gas reported by the Go callbacks is charged
+    pub gas_policy: GasPolicy,
+    /// The gas charged for callbacks which do not meter themselves, depending on `gas_policy`
+    pub fallback_gas: FallbackGasSchedule,
+    /// The most memory an instance of a contract may have, in 64 KiB pages. Codes declaring more initial
+    /// memory fail to instantiate, and instances cannot grow their memory beyond it.
+    pub max_memory_pages: u32,
+    /// Check the events of contract results and return them in canonical form, see `events`
+    pub normalize_events: bool,
+    /// Caps on the events of contract results, see `events`
+    pub event_limits: EventLimits,
+    /// The gas budget of a call of `handle_system`, which replaces the gas limit of the caller
+    pub system_gas_limit: u64,
+    /// The most storage iterators one execution of a contract may have open at once, 0 for no limit, see
+    /// `open_iterators`
+    pub max_open_iterators: u32,
+    /// The largest message of a call, in bytes, 0 for no limit, see `msg_checks`
+    pub max_msg_size: usize,
+    /// Whether contracts may use floats, see `float_policy`
+    pub float_policy: FloatPolicy,
+    /// The capabilities the contracts of codes may use, a JSON object of restrictions by hex checksum, see
+    /// `capabilities`
+    pub code_capabilities: CodeCapabilities,
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        ConsensusParams {
+            max_call_depth: 10,
+            max_response_size: 4 * 1024 * 1024,
+            gas_policy: GasPolicy::default(),
+            fallback_gas: FallbackGasSchedule::default(),
+            // 16 MiB
+            max_memory_pages: 256,
+            normalize_events: false,
+            event_limits: EventLimits::default(),
+            // the default block gas limit of the SDK, times the wasm gas multiplier
+            system_gas_limit: 10_000_000_000,
+            max_open_iterators: 0,
+            max_msg_size: 0,
+            float_policy: FloatPolicy::default(),
+            code_capabilities: CodeCapabilities::default(),
+        }
+    }
+}
+
+impl ConsensusParams {
+    pub fn from_json(params: &[u8]) -> Result<Self, Error> {
+        let params: ConsensusParams = serde_json::from_slice(params)
+            .map_err(|e| Error::invalid_arg(CONSENSUS_PARAMS_ARG, e))?;
+        if params.max_memory_pages == 0 || params.max_memory_pages > WASM_MAX_PAGES {
+            return Err(Error::invalid_arg(
+                CONSENSUS_PARAMS_ARG,
+                format!("max_memory_pages must be between 1 and {}", WASM_MAX_PAGES),
+            ));
+        }
+        if params.float_policy.canonicalize_nans() && cfg!(feature = "vanilla") {
+            return Err(Error::invalid_arg(
+                CONSENSUS_PARAMS_ARG,
+                "float_policy canonicalize_nan requires the enclave VM, which canonicalizes NaNs",
+            ));
+        }
+        if params.system_gas_limit == 0 {
+            return Err(Error::invalid_arg(
+                CONSENSUS_PARAMS_ARG,
+                "system_gas_limit must be greater than 0",
+            ));
+        }
+        Ok(params)
+    }
+
+    /// Normalizes the events of the result of `instantiate`, `handle` or `migrate` if `normalize_events` is set,
+    /// and checks them against `event_limits`
+    pub fn canonical_response(&self, response: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let response = if self.normalize_events {
+            events::normalize(response)?
+        } else {
+            response
+        };
+        events::check_limits(&response, &self.event_limits)?;
+        Ok(response)
+    }
+
+    /// Fails if a contract response of `size` bytes exceeds `max_response_size`
+    pub fn check_response_size(&self, size: usize) -> Result<(), Error> {
+        if size > self.max_response_size {
+            return Err(Error::response_too_large(size, self.max_response_size));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capabilities::Capability;
+    use crate::vm::Checksum;
+
+    #[test]
+    fn params_default_for_empty_object() {
+        assert_eq!(
+            ConsensusParams::from_json(b"{}").unwrap(),
+            ConsensusParams::default()
+        );
+        assert!(ConsensusParams::from_json(b"").is_err());
+    }
+
+    #[test]
+    fn params_parse_from_json() {
+        let params = ConsensusParams::from_json(
+            br#"{"max_call_depth":3,"gas_policy":"max_of_both","fallback_gas":{"read":5},"max_open_iterators":16}"#,
+        )
+        .unwrap();
+        assert_eq!(params.max_call_depth, 3);
+        assert_eq!(params.gas_policy, GasPolicy::MaxOfBoth);
+        assert_eq!(params.fallback_gas.read, 5);
+        assert_eq!(
+            params.fallback_gas.write,
+            FallbackGasSchedule::default().write
+        );
+        assert_eq!(params.max_open_iterators, 16);
+        assert_eq!(
+            params.max_response_size,
+            ConsensusParams::default().max_response_size
+        );
+
+        assert!(ConsensusParams::from_json(br#"{"gas_policy":"trust_me"}"#).is_err());
+        assert!(ConsensusParams::from_json(br#"{"event_limits":{"max_logs":1}}"#).is_err());
+        // options of the node are rejected, not ignored
+        assert!(ConsensusParams::from_json(br#"{"mmap_modules":true}"#).is_err());
+    }
+
+    #[test]
+    fn params_check_their_bounds() {
+        assert!(ConsensusParams::from_json(br#"{"max_memory_pages":1024}"#).is_ok());
+        assert!(ConsensusParams::from_json(br#"{"max_memory_pages":0}"#).is_err());
+        assert!(ConsensusParams::from_json(br#"{"max_memory_pages":65537}"#).is_err());
+        assert!(ConsensusParams::from_json(br#"{"system_gas_limit":0}"#).is_err());
+        let canonicalize = ConsensusParams::from_json(br#"{"float_policy":"canonicalize_nan"}"#);
+        assert_eq!(canonicalize.is_ok(), cfg!(feature = "sgx"));
+        assert!(ConsensusParams::from_json(br#"{"float_policy":"deny"}"#).is_err());
+        assert!(ConsensusParams::from_json(br#"{"code_capabilities":{"aa":{}}}"#).is_err());
+    }
+
+    #[test]
+    fn params_restrict_codes() {
+        let code_id = Checksum::generate(b"code");
+        let json = format!(
+            r#"{{"code_capabilities":{{"{}":{{"allow":["storage_write"]}}}}}}"#,
+            code_id.to_hex()
+        );
+        let params = ConsensusParams::from_json(json.as_bytes()).unwrap();
+        let denied = params.code_capabilities.denied(&code_id);
+        assert!(denied.contains(Capability::Queries));
+        assert!(!denied.contains(Capability::StorageWrite));
+        assert!(params
+            .code_capabilities
+            .denied(&Checksum::generate(b"other"))
+            .is_empty());
+    }
+
+    #[test]
+    fn check_response_size_works() {
+        let params = ConsensusParams::from_json(br#"{"max_response_size":4}"#).unwrap();
+        params.check_response_size(4).unwrap();
+        match params.check_response_size(5).unwrap_err() {
+            Error::ResponseTooLarge { size, max, .. } => {
+                assert_eq!(size, 5);
+                assert_eq!(max, 4);
+            }
+            _ => panic!("expect different error"),
+        }
+    }
+
+    #[test]
+    fn canonical_response_requires_normalize_events() {
+        let response =
+            br#"{"Ok":{"log":[{"key":"a","value":"1"},{"key":"a","value":"1"}]}}"#.to_vec();
+        let params = ConsensusParams::default();
+        assert_eq!(
+            params.canonical_response(response.clone()).unwrap(),
+            response
+        );
+        let params = ConsensusParams::from_json(br#"{"normalize_events":true}"#).unwrap();
+        assert_eq!(
+            params.canonical_response(response).unwrap(),
+            br#"{"Ok":{"log":[{"key":"a","value":"1"}]}}"#.to_vec()
+        );
+    }
+
+    #[test]
+    fn canonical_response_checks_event_limits() {
+        let response =
+            br#"{"Ok":{"log":[{"key":"a","value":"1"},{"key":"a","value":"1"}]}}"#.to_vec();
+        let params =
+            ConsensusParams::from_json(br#"{"event_limits":{"max_attributes":1}}"#).unwrap();
+        assert!(params.canonical_response(response.clone()).is_err());
+        let params = ConsensusParams::from_json(
+            br#"{"normalize_events":true,"event_limits":{"max_attributes":1}}"#,
+        )
+        .unwrap();
+        assert!(params.canonical_response(response).is_ok());
+    }
+}
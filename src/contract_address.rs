@@ -0,0 +1,183 @@
+//! Predictable contract addresses, derived like `instantiate2` of wasmd.
+//!
+//! The address of a contract instantiated with a salt only depends on the code's checksum, the canonical
+//! address of the creator, the salt and, optionally, the init message. Factories and deploy scripts can
+//! compute it before the contract exists. It is the 32 bytes long module address of ADR-028:
+//!
+//! `sha256(sha256("module") || "wasm" || 0x00 || key)`, where `key` is the concatenation of the checksum,
+//! creator, salt and message, each prefixed with its length as big endian u64.
+//!
+//! `instantiate2` enforces it: the env Go passes must have the address derived from the salt, the code and
+//! the sender of the message, so a contract can only be instantiated at its predicted address.
+
+use cosmwasm_std::HumanAddr;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::vm::{Api, VmError};
+
+const PARAMS_ARG: &str = "params";
+
+const MODULE_TYPE: &[u8] = b"module";
+const MODULE_NAME: &[u8] = b"wasm";
+
+pub const CHECKSUM_LEN: usize = 32;
+/// Salts are between 1 and 64 bytes, as in wasmd
+pub const MAX_SALT_LEN: usize = 64;
+/// The longest canonical address of a creator
+const MAX_CREATOR_LEN: usize = 255;
+
+/// The address of the contract `creator` instantiates from the code `checksum` with `salt`. With
+/// `msg`, the address also depends on the init message, so it can only be used with exactly this message.
+pub fn derive(
+    checksum: &[u8],
+    creator: &[u8],
+    salt: &[u8],
+    msg: Option<&[u8]>,
+) -> Result<Vec<u8>, Error> {
+    if checksum.len() != CHECKSUM_LEN {
+        let msg = format!("expected {} bytes, got {}", CHECKSUM_LEN, checksum.len());
+        return Err(Error::invalid_arg("checksum", msg));
+    }
+    if creator.is_empty() || creator.len() > MAX_CREATOR_LEN {
+        let msg = format!("expected 1 to {} bytes", MAX_CREATOR_LEN);
+        return Err(Error::invalid_arg("creator", msg));
+    }
+    if salt.is_empty() || salt.len() > MAX_SALT_LEN {
+        let msg = format!("expected 1 to {} bytes", MAX_SALT_LEN);
+        return Err(Error::invalid_arg("salt", msg));
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(Sha256::digest(MODULE_TYPE));
+    hasher.update(MODULE_NAME);
+    hasher.update([0u8]);
+    for part in &[checksum, creator, salt, msg.unwrap_or_default()] {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+/// The canonical form of the address at `pointer` in the env `env`
+fn canonical_address<A: Api>(api: &A, env: &Value, pointer: &str) -> Result<Vec<u8>, Error> {
+    let human = env
+        .pointer(pointer)
+        .and_then(Value::as_str)
+        .ok_or_else(|| {
+            let msg = format!("expected an address at {}", pointer);
+            Error::invalid_arg(PARAMS_ARG, msg)
+        })?;
+    let (canonical, _gas_info) = api.canonical_address(&HumanAddr::from(human));
+    Ok(canonical.map_err(VmError::from)?.as_slice().to_vec())
+}
+
+/// Fails unless the contract address in the env `params` is the one its message sender derives from
+/// `checksum` and `salt`, with the init message `msg` if it is fixed
+pub fn verify<A: Api>(
+    api: &A,
+    params: &[u8],
+    checksum: &[u8],
+    salt: &[u8],
+    msg: Option<&[u8]>,
+) -> Result<(), Error> {
+    let env: Value =
+        serde_json::from_slice(params).map_err(|e| Error::invalid_arg(PARAMS_ARG, e))?;
+    let creator = canonical_address(api, &env, "/message/sender")?;
+    let contract = canonical_address(api, &env, "/contract/address")?;
+    if contract != derive(checksum, &creator, salt, msg)? {
+        return Err(Error::invalid_arg(
+            PARAMS_ARG,
+            "the contract address is not the one derived from the salt",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::CanonicalAddr;
+
+    use crate::vm::{FfiError, FfiResult, GasInfo};
+
+    /// Addresses in hex
+    #[derive(Copy, Clone)]
+    struct HexApi;
+
+    impl Api for HexApi {
+        fn canonical_address(&self, human: &HumanAddr) -> FfiResult<CanonicalAddr> {
+            let canonical = hex::decode(human.as_str())
+                .map(|bytes| CanonicalAddr(bytes.into()))
+                .map_err(FfiError::unknown);
+            (canonical, GasInfo::free())
+        }
+
+        fn human_address(&self, canonical: &CanonicalAddr) -> FfiResult<HumanAddr> {
+            (
+                Ok(HumanAddr(hex::encode(canonical.as_slice()))),
+                GasInfo::free(),
+            )
+        }
+    }
+
+    const CHECKSUM: &str = "13a1fc994cc6d1c81b746ee0c0ff6f90043875e0bf1d9be6b7d779fc978dc2a5";
+    const CREATOR: &str = "9999999999aaaaaaaaaabbbbbbbbbbcccccccccc";
+
+    fn derive_hex(salt: &[u8], msg: Option<&[u8]>) -> String {
+        let checksum = hex::decode(CHECKSUM).unwrap();
+        let creator = hex::decode(CREATOR).unwrap();
+        hex::encode(derive(&checksum, &creator, salt, msg).unwrap())
+    }
+
+    // the first is a vector of cosmwasm-std's instantiate2_address
+    #[test]
+    fn derive_matches_wasmd() {
+        assert_eq!(
+            derive_hex(b"a", None),
+            "5e865d3e45ad3e961f77fd77d46543417ced44d924dc3e079b5415ff6775f847"
+        );
+        assert_eq!(
+            derive_hex(b"a", Some(b"{}")),
+            "0995499608947a5281e2c7ebd71bdb26a1ad981946dad57f6c4d3ee35de77835"
+        );
+        // an empty message is like none
+        assert_eq!(derive_hex(b"a", Some(b"")), derive_hex(b"a", None));
+    }
+
+    #[test]
+    fn derive_validates_args() {
+        let checksum = hex::decode(CHECKSUM).unwrap();
+        let creator = hex::decode(CREATOR).unwrap();
+        assert!(derive(&checksum[1..], &creator, b"a", None).is_err());
+        assert!(derive(&checksum, b"", b"a", None).is_err());
+        assert!(derive(&checksum, &creator, b"", None).is_err());
+        assert!(derive(&checksum, &creator, &[0; MAX_SALT_LEN + 1], None).is_err());
+        assert!(derive(&checksum, &creator, &[0; MAX_SALT_LEN], None).is_ok());
+    }
+
+    #[test]
+    fn verify_checks_the_contract_address() {
+        let checksum = hex::decode(CHECKSUM).unwrap();
+        let env = |contract: &str| {
+            format!(
+                r#"{{"message":{{"sender":"{}","sent_funds":[]}},"contract":{{"address":"{}"}}}}"#,
+                CREATOR, contract
+            )
+        };
+        let derived = derive_hex(b"a", None);
+        verify(&HexApi, env(&derived).as_bytes(), &checksum, b"a", None).unwrap();
+        assert!(verify(&HexApi, env(&derived).as_bytes(), &checksum, b"b", None).is_err());
+        assert!(verify(
+            &HexApi,
+            env(&derived).as_bytes(),
+            &checksum,
+            b"a",
+            Some(b"{}")
+        )
+        .is_err());
+        assert!(verify(&HexApi, env("not hex").as_bytes(), &checksum, b"a", None).is_err());
+        assert!(verify(&HexApi, b"{}", &checksum, b"a", None).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+//! Diagnostics of unexpected failures, which node operators can attach to bug reports.
+//!
+//! When an enclave call fails in the SGX SDK, or the VM fails a contract execution for another reason than
+//! running out of gas, a `CrashReport` is recorded: what was called, the gas state, whether the enclave still
+//! passes a health check, and the last log lines before the failure. Errors returned by contracts or by the
+//! enclave itself are expected and not recorded. `get_last_crash_report` returns the latest report as JSON.
+//!
+//! The library's logger, installed by `init_logger`, keeps the log lines. Reports are kept in memory only,
+//! the last `MAX_REPORTS` of them, and all messages are sanitized like error messages.
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use serde::Serialize;
+
+use crate::call_id;
+use crate::code_stats::EntryPoint;
+use crate::error::{sanitize, Error};
+use crate::gas_report::{self, GasReport};
+use crate::vm::{untrusted_health_check, Checksum, VmError};
+
+/// The log lines kept for the next report
+const MAX_LOG_LINES: usize = 64;
+/// Longer log lines are cut off
+const MAX_LINE_LEN: usize = 512;
+const MAX_REPORTS: usize = 8;
+
+/// The diagnostics of one failure, returned by `get_last_crash_report`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CrashReport {
+    /// When the failure happened, in milliseconds since the unix epoch
+    pub time_ms: u64,
+    /// The failed call, e.g. `handle` or `get_encrypted_seed`
+    pub call: String,
+    /// The id Go passed for the contract call, see `call_id`
+    pub call_id: Option<String>,
+    /// The code id in hex, if a contract failed
+    pub code_id: Option<String>,
+    pub gas_limit: Option<u64>,
+    /// The gas used in wasm until the failure
+    pub gas_used: Option<u64>,
+    /// The gas reported by the callbacks until the failure
+    pub gas_report: GasReport,
+    pub error: String,
+    /// `ok`, or why the health check of the enclave failed after the failure
+    pub enclave_status: String,
+    /// The last log lines before the failure, oldest first
+    pub log: Vec<String>,
+}
+
+#[derive(Default)]
+struct Buffers {
+    lines: VecDeque<String>,
+    reports: VecDeque<CrashReport>,
+}
+
+lazy_static! {
+    static ref BUFFERS: Mutex<Buffers> = Mutex::new(Buffers::default());
+}
+
+fn buffers() -> MutexGuard<'static, Buffers> {
+    // a panic while holding the lock does not leave the buffers inconsistent
+    BUFFERS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn truncate(mut line: String) -> String {
+    if line.len() > MAX_LINE_LEN {
+        let mut end = MAX_LINE_LEN;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        line.truncate(end);
+    }
+    line
+}
+
+fn push_line(line: String) {
+    let line = truncate(sanitize(&line).into_owned());
+    let mut buffers = buffers();
+    if buffers.lines.len() == MAX_LOG_LINES {
+        buffers.lines.pop_front();
+    }
+    buffers.lines.push_back(line);
+}
+
+/// Prints log lines to stdout, and keeps the last ones for the crash reports
+struct Logger {
+    level: LevelFilter,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = match call_id::current() {
+            Some(call_id) => format!(
+                "{} {:<5} [{}] [{}] {}",
+                now_ms(),
+                record.level(),
+                record.target(),
+                call_id,
+                record.args()
+            ),
+            None => format!(
+                "{} {:<5} [{}] {}",
+                now_ms(),
+                record.level(),
+                record.target(),
+                record.args()
+            ),
+        };
+        println!("{}", line);
+        push_line(line);
+    }
+
+    fn flush(&self) {
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Installs the logger of the library. Fails if the host installed a logger already.
+pub fn init_logger(level: Level) -> Result<(), SetLoggerError> {
+    let level = level.to_level_filter();
+    log::set_boxed_logger(Box::new(Logger { level }))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+fn enclave_status() -> String {
+    match untrusted_health_check() {
+        Ok(_) => "ok".to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+fn record(mut report: CrashReport) {
+    report.enclave_status = enclave_status();
+    let mut buffers = buffers();
+    report.log = buffers.lines.iter().cloned().collect();
+    if buffers.reports.len() == MAX_REPORTS {
+        buffers.reports.pop_front();
+    }
+    buffers.reports.push_back(report);
+}
+
+fn report(call: &str, error: &str) -> CrashReport {
+    CrashReport {
+        time_ms: now_ms(),
+        call: call.to_string(),
+        call_id: call_id::current(),
+        code_id: None,
+        gas_limit: None,
+        gas_used: None,
+        gas_report: gas_report::current_report(),
+        error: sanitize(error).into_owned(),
+        enclave_status: String::new(),
+        log: Vec::new(),
+    }
+}
+
+/// Records the failure of the enclave call `call` in the SGX SDK, returning it as an error
+pub fn sdk_failure<E: Display>(call: &str, e: E) -> Error {
+    let msg = e.to_string();
+    record(report(call, &msg));
+    Error::enclave_err(msg)
+}
+
+/// Records a failed execution of a contract, unless it ran out of gas
+pub fn execution_failure(
+    entry_point: EntryPoint,
+    code_id: &Checksum,
+    gas_limit: u64,
+    gas_used: u64,
+    e: &VmError,
+) {
+    if let VmError::GasDepletion = e {
+        return;
+    }
+    let mut report = report(entry_point.name(), &e.to_string());
+    report.code_id = Some(code_id.to_hex());
+    report.gas_limit = Some(gas_limit);
+    report.gas_used = Some(gas_used);
+    record(report);
+}
+
+/// The most recent report, if there is any
+pub fn last_report() -> Option<CrashReport> {
+    buffers().reports.back().cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_respects_char_boundaries() {
+        assert_eq!(truncate("short".to_string()), "short");
+        let long = "ä".repeat(MAX_LINE_LEN);
+        let cut = truncate(long);
+        assert!(cut.len() <= MAX_LINE_LEN);
+        assert_eq!(cut.len() % 2, 0);
+    }
+
+    // the buffers are global, so all cases share one test. Other tests may record reports meanwhile.
+    #[test]
+    fn reports_keep_the_last_lines() {
+        for i in 0..MAX_LOG_LINES + 1 {
+            push_line(format!("line {}", i));
+        }
+        push_line("reading /home/node/.sgx_secrets/seed.sealed".to_string());
+        let e = sdk_failure("crash_report_test", "SGX_ERROR_ENCLAVE_LOST");
+        assert_eq!(
+            e.to_string(),
+            Error::enclave_err("SGX_ERROR_ENCLAVE_LOST").to_string()
+        );
+
+        let report = buffers()
+            .reports
+            .iter()
+            .find(|report| report.call == "crash_report_test")
+            .cloned()
+            .unwrap();
+        assert_eq!(report.error, "SGX_ERROR_ENCLAVE_LOST");
+        assert_eq!(report.code_id, None);
+        assert_eq!(report.log.len(), MAX_LOG_LINES);
+        assert_eq!(report.log[0], "line 2");
+        assert_eq!(report.log[MAX_LOG_LINES - 1], "reading <path>");
+
+        for _ in 0..MAX_REPORTS + 1 {
+            sdk_failure("key_gen", "SGX_ERROR_UNEXPECTED");
+        }
+        assert_eq!(buffers().reports.len(), MAX_REPORTS);
+        assert!(last_report().is_some());
+    }
+}
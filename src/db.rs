@@ -1,9 +1,19 @@
-use cosmwasm_sgx_vm::{FfiResult, GasInfo, Storage, StorageIterator};
+use std::borrow::Cow;
 
+use crate::block_context::BlockContext;
+use crate::capabilities::{self, Capability};
 use crate::error::GoResult;
 use crate::gas_meter::gas_meter_t;
-use crate::iterator::GoIter;
+use crate::gas_report::{self, GasCategory};
+use crate::host_calls::HostCall;
+use crate::iterator::{prefix_end, GoIter, IteratorMode, LimitedIter, SeekableIterator};
 use crate::memory::Buffer;
+use crate::namespace::{self, NamespacedIter};
+use crate::open_iterators::{self, CountedIter, OpenIterator};
+use crate::resume_token::{IteratorPosition, ResumableIter};
+#[cfg(feature = "shadow")]
+use crate::shadow;
+use crate::vm::{FfiError, FfiResult, GasInfo, Storage, StorageIterator};
 
 // this represents something passed in from the caller side of FFI
 #[repr(C)]
@@ -14,6 +24,7 @@ pub struct db_t {
 // These functions should return GoResult but because we don't trust them here, we treat the return value as i32
 // and then check it when converting to GoResult manually
 #[repr(C)]
+#[derive(Copy, Clone)]
 pub struct DB_vtable {
     pub read_db: extern "C" fn(
         *mut db_t,
@@ -27,6 +38,7 @@ pub struct DB_vtable {
         extern "C" fn(*mut db_t, *mut gas_meter_t, *mut u64, Buffer, Buffer, *mut Buffer) -> i32,
     pub remove_db: extern "C" fn(*mut db_t, *mut gas_meter_t, *mut u64, Buffer, *mut Buffer) -> i32,
     // order -> Ascending = 1, Descending = 2
+    // mode -> see IteratorMode
     // Note: we cannot set gas_meter on the returned GoIter due to cgo memory safety.
     // Since we have the pointer in rust already, we must set that manually
     pub scan_db: extern "C" fn(
@@ -36,41 +48,253 @@ pub struct DB_vtable {
         Buffer,
         Buffer,
         i32,
+        i32,
         *mut GoIter,
         *mut Buffer,
     ) -> i32,
+    // Optional. Looks up another contract by address and returns its code id, contract key and a DB for its storage.
+    // If the pointer is null, or Go leaves the returned DB state null, the contract is treated as unresolvable.
+    // Note: as with `scan_db`, we set the gas_meter of the returned DB manually.
+    pub resolve_contract: Option<
+        extern "C" fn(
+            *mut db_t,
+            *mut gas_meter_t,
+            *mut u64,
+            Buffer,
+            *mut Buffer,
+            *mut Buffer,
+            *mut DB,
+            *mut Buffer,
+        ) -> i32,
+    >,
+    // Optional. Opens an iterator over the keys starting with `prefix`, yielding at most `limit` items
+    // (0 means no limit). Go can pass the prefix down to the store instead of us constructing the bounds.
+    // order and mode as for `scan_db`
+    pub scan_prefix: Option<
+        extern "C" fn(
+            *mut db_t,
+            *mut gas_meter_t,
+            *mut u64,
+            Buffer,
+            u32,
+            i32,
+            i32,
+            *mut GoIter,
+            *mut Buffer,
+        ) -> i32,
+    >,
+    // Optional. Removes all keys in [start, end), where null bounds are unbounded, in a single call.
+    pub delete_range: Option<
+        extern "C" fn(*mut db_t, *mut gas_meter_t, *mut u64, Buffer, Buffer, *mut Buffer) -> i32,
+    >,
+    // Optional. Tells Go the block of the contract call about to run on this DB, before any other callback.
+    pub set_block_context: Option<extern "C" fn(*mut db_t, BlockContext, *mut Buffer) -> i32>,
+    // Optional. Tells Go the gas the running call has charged through its callbacks so far, see
+    // `CacheOptions::gas_sync_interval`. Go returns OutOfGas to end the call early.
+    pub gas_consumed: Option<extern "C" fn(*mut db_t, *mut gas_meter_t, u64, *mut Buffer) -> i32>,
+    // Optional. Asks Go whether a contract call may run on this DB, before it starts. The arguments are the code id,
+    // the sender and the entry point. Go sets `allowed` to false to deny the call, with the reason in the error buffer.
+    pub authorize_call:
+        Option<extern "C" fn(*mut db_t, Buffer, Buffer, Buffer, *mut bool, *mut Buffer) -> i32>,
+    // Optional. Hands Go the result of a successful contract call on this DB, e.g. for indexers. The arguments are
+    // the code id, the entry point, the result and whether it is plaintext rather than encrypted for the sender.
+    pub call_result:
+        Option<extern "C" fn(*mut db_t, Buffer, Buffer, Buffer, bool, *mut Buffer) -> i32>,
 }
 
 #[repr(C)]
+#[derive(Copy, Clone)]
 pub struct DB {
     pub gas_meter: *mut gas_meter_t,
     pub state: *mut db_t,
     pub vtable: DB_vtable,
+    /// Optional. If set, `state` is the parent store and we keep all keys inside of this prefix
+    pub namespace: Buffer,
 }
 
-impl Storage for DB {
-    fn get(&self, key: &[u8]) -> FfiResult<Option<Vec<u8>>> {
-        let key_buf = Buffer::from_vec(key.to_vec());
-        let mut result_buf = Buffer::default();
+/// Another contract's storage, as resolved by `DB::resolve_contract`
+pub struct ResolvedContract {
+    pub code_id: Vec<u8>,
+    pub contract_key: Vec<u8>,
+    pub db: DB,
+}
+
+impl DB {
+    /// Asks Go for the code id, contract key and storage of the contract at `contract_addr`.
+    ///
+    /// Returns `None` if the Go side does not support resolving contracts, or does not know this address.
+    pub fn resolve_contract(&self, contract_addr: &str) -> FfiResult<Option<ResolvedContract>> {
+        let resolve_contract = match self.vtable.resolve_contract {
+            Some(f) => f,
+            None => return (Ok(None), GasInfo::free()),
+        };
+
+        let addr_buf = Buffer::temp(contract_addr.as_bytes());
+        let mut code_id_buf = Buffer::default();
+        let mut key_buf = Buffer::default();
+        let mut db = DB {
+            gas_meter: self.gas_meter,
+            state: std::ptr::null_mut(),
+            vtable: self.vtable,
+            namespace: Buffer::default(),
+        };
         let mut err = Buffer::default();
         let mut used_gas = 0_u64;
-        let go_result: GoResult = (self.vtable.read_db)(
+        let go_result: GoResult = (resolve_contract)(
             self.state,
             self.gas_meter,
             &mut used_gas as *mut u64,
-            key_buf,
-            &mut result_buf as *mut Buffer,
+            addr_buf,
+            &mut code_id_buf as *mut Buffer,
+            &mut key_buf as *mut Buffer,
+            &mut db as *mut DB,
+            &mut err as *mut Buffer,
+        )
+        .into();
+        // resolving is charged like a storage read
+        let gas_info = gas_report::record(GasCategory::Read, used_gas);
+        unsafe { addr_buf.release_temp() };
+
+        // return complete error message (reading from buffer for GoResult::Other)
+        let default = || format!("Failed to resolve the contract: {}", contract_addr);
+        unsafe {
+            if let Err(err) = go_result.into_ffi_result(err, default) {
+                return (Err(err), gas_info);
+            }
+        }
+
+        // We initialize the outputs with null pointers. If they are not null,
+        // that means they were initialized by the go code, with values generated by `memory::allocate_rust`.
+        let code_id = unsafe { code_id_buf.consume() };
+        let contract_key = unsafe { key_buf.consume() };
+        if db.state.is_null() || code_id.is_empty() {
+            return (Ok(None), gas_info);
+        }
+        // The gas meter can not be set from Go, so make sure the new DB reports to the same one we do.
+        db.gas_meter = self.gas_meter;
+        let resolved = ResolvedContract {
+            code_id,
+            contract_key,
+            db,
+        };
+        (Ok(Some(resolved)), gas_info)
+    }
+
+    // The scans below and `remove_range` go beyond the `Storage` trait, which is all the VM calls for contracts,
+    // so contracts cannot reach them. They serve the host side, e.g. `state_export`.
+
+    /// Like `range`, but the values of the returned items are empty
+    pub fn range_keys(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: cosmwasm_std::Order,
+    ) -> FfiResult<Box<dyn StorageIterator>> {
+        let (result, gas_info) = self.scan(start, end, order, IteratorMode::KeysOnly);
+        (result.map(|iter| self.storage_iter(iter)), gas_info)
+    }
+
+    /// Like `range`, but the returned iterator can `seek` within the range
+    pub fn range_seekable(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: cosmwasm_std::Order,
+    ) -> FfiResult<Box<dyn SeekableIterator>> {
+        let (result, gas_info) = self.scan(start, end, order, IteratorMode::KeysAndValues);
+        (result.map(|iter| self.seekable_iter(iter)), gas_info)
+    }
+
+    /// Like `range`, but the returned iterator hands out tokens to resume it in a later call, see `resume_token`
+    pub fn range_resumable(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: cosmwasm_std::Order,
+    ) -> FfiResult<ResumableIter> {
+        self.open_resumable(IteratorPosition {
+            start: start.map(<[u8]>::to_vec),
+            end: end.map(<[u8]>::to_vec),
+            order,
+            last_key: None,
+        })
+    }
+
+    /// Opens the rest of the range of a `ResumableIter` from one of its tokens
+    pub fn resume_range(&self, token: &[u8]) -> FfiResult<ResumableIter> {
+        match IteratorPosition::from_token(token) {
+            Ok(position) => self.open_resumable(position),
+            Err(e) => (Err(e), GasInfo::free()),
+        }
+    }
+
+    fn open_resumable(&self, position: IteratorPosition) -> FfiResult<ResumableIter> {
+        let (start, end) = position.remaining_bounds();
+        let (result, gas_info) = self.scan(
+            start.as_deref(),
+            end.as_deref(),
+            position.order,
+            IteratorMode::KeysAndValues,
+        );
+        let result = result.map(|iter| ResumableIter::new(self.storage_iter(iter), position));
+        (result, gas_info)
+    }
+
+    /// Iterates over the items whose keys start with `prefix`, yielding at most `limit` of them.
+    /// A `limit` of 0 means no limit.
+    pub fn range_prefix(
+        &self,
+        prefix: &[u8],
+        limit: u32,
+        order: cosmwasm_std::Order,
+    ) -> FfiResult<Box<dyn StorageIterator>> {
+        let scan_prefix = match self.vtable.scan_prefix {
+            Some(f) => f,
+            None => {
+                // older Go wrappers only know ranges
+                let end = prefix_end(prefix);
+                let (result, gas_info) = self.scan(
+                    Some(prefix),
+                    end.as_deref(),
+                    order,
+                    IteratorMode::KeysAndValues,
+                );
+                let result = result.map(|iter| LimitedIter::wrap(self.storage_iter(iter), limit));
+                return (result, gas_info);
+            }
+        };
+
+        let open = match capabilities::require(Capability::Iterators)
+            .and_then(|()| open_iterators::open())
+        {
+            Ok(open) => open,
+            Err(e) => return (Err(e), GasInfo::free()),
+        };
+        let full_prefix = self.full_key(prefix);
+        let prefix_buf = Buffer::temp(&full_prefix);
+        let mut err = Buffer::default();
+        let mut iter = GoIter::new(self.gas_meter);
+        let mut used_gas = 0_u64;
+        let go_result: GoResult = (scan_prefix)(
+            self.state,
+            self.gas_meter,
+            &mut used_gas as *mut u64,
+            prefix_buf,
+            limit,
+            order.into(),
+            IteratorMode::KeysAndValues as i32,
+            &mut iter as *mut GoIter,
             &mut err as *mut Buffer,
         )
         .into();
-        let gas_info = GasInfo::with_externally_used(used_gas);
-        let _key = unsafe { key_buf.consume() };
+        let gas_info = gas_report::record_host_call(HostCall::DbScan, used_gas);
+        unsafe { prefix_buf.release_temp() };
 
         // return complete error message (reading from buffer for GoResult::Other)
         let default = || {
             format!(
-                "Failed to read a key in the db: {}",
-                String::from_utf8_lossy(key)
+                "Failed to scan the keys with prefix {:?}",
+                String::from_utf8_lossy(prefix)
             )
         };
         unsafe {
@@ -78,35 +302,243 @@ impl Storage for DB {
                 return (Err(err), gas_info);
             }
         }
+        (Ok(self.storage_iter((iter, open))), gas_info)
+    }
 
-        // We initialize `result_buf` with a null pointer. If it is not null, that means
-        // it was initialized by the go code, with values generated by `memory::allocate_rust`.
-        let value = if result_buf.ptr.is_null() {
-            None
-        } else {
-            Some(unsafe { result_buf.consume() })
+    /// The prefix of all keys of this DB, if Go asked us to enforce it
+    fn namespace(&self) -> Option<&[u8]> {
+        unsafe { self.namespace.read() }.filter(|namespace| !namespace.is_empty())
+    }
+
+    /// `key` as stored in the Go store
+    fn full_key<'k>(&self, key: &'k [u8]) -> Cow<'k, [u8]> {
+        match self.namespace() {
+            Some(namespace) => Cow::Owned(namespace::apply(namespace, key)),
+            None => Cow::Borrowed(key),
+        }
+    }
+
+    /// The bounds of a range as passed to the Go store
+    fn full_bounds(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Bounds {
+        match self.namespace() {
+            Some(namespace) => {
+                let (start, end) = namespace::apply_bounds(namespace, start, end);
+                (Some(start), end)
+            }
+            None => (start.map(<[u8]>::to_vec), end.map(<[u8]>::to_vec)),
+        }
+    }
+
+    fn storage_iter(&self, (iter, open): OpenedIter) -> Box<dyn StorageIterator> {
+        let iter = iter.into_storage_iterator();
+        let iter: Box<dyn StorageIterator> = match self.namespace() {
+            Some(namespace) => Box::new(NamespacedIter::new(iter, namespace)),
+            None => iter,
         };
-        (Ok(value), gas_info)
+        Box::new(CountedIter::new(iter, open))
     }
 
-    /// Allows iteration over a set of key/value pairs, either forwards or backwards.
-    ///
-    /// The bound `start` is inclusive and `end` is exclusive.
-    ///
-    /// If `start` is lexicographically greater than or equal to `end`, an empty range is described, mo matter of the order.
-    fn range<'a>(
-        &'a self,
+    fn seekable_iter(&self, (iter, open): OpenedIter) -> Box<dyn SeekableIterator> {
+        let iter = iter.into_seekable_iterator();
+        let iter: Box<dyn SeekableIterator> = match self.namespace() {
+            Some(namespace) => Box::new(NamespacedIter::new(iter, namespace)),
+            None => iter,
+        };
+        Box::new(CountedIter::new(iter, open))
+    }
+
+    /// Hands the block of the upcoming contract call to Go, if it wants to know
+    pub fn set_block_context(&self, block: BlockContext) -> Result<(), FfiError> {
+        let set_block_context = match self.vtable.set_block_context {
+            Some(f) => f,
+            None => return Ok(()),
+        };
+        let mut err = Buffer::default();
+        let go_result: GoResult =
+            (set_block_context)(self.state, block, &mut err as *mut Buffer).into();
+        let default = || "Failed to set the block context".to_string();
+        unsafe { go_result.into_ffi_result(err, default) }
+    }
+
+    /// Tells Go the gas the running call has charged so far.
+    /// Returns whether the call may go on, which Go denies by returning OutOfGas.
+    pub fn notify_gas_consumed(&self, total: u64) -> Result<bool, FfiError> {
+        let gas_consumed = match self.vtable.gas_consumed {
+            Some(f) => f,
+            None => return Ok(true),
+        };
+        let mut err = Buffer::default();
+        let go_result: GoResult =
+            (gas_consumed)(self.state, self.gas_meter, total, &mut err as *mut Buffer).into();
+        if let GoResult::OutOfGas = go_result {
+            return Ok(false);
+        }
+        let default = || "Failed to notify the consumed gas".to_string();
+        unsafe { go_result.into_ffi_result(err, default) }.map(|()| true)
+    }
+
+    /// Asks Go whether the call of `entry_point` of `code_id` by `sender` may run.
+    /// Returns the reason if Go denies it.
+    pub fn authorize_call(
+        &self,
+        code_id: &[u8],
+        sender: &str,
+        entry_point: &str,
+    ) -> Result<Option<String>, FfiError> {
+        let authorize_call = match self.vtable.authorize_call {
+            Some(f) => f,
+            None => return Ok(None),
+        };
+        let code_id_buf = Buffer::temp(code_id);
+        let sender_buf = Buffer::temp(sender.as_bytes());
+        let entry_point_buf = Buffer::temp(entry_point.as_bytes());
+        let mut allowed = true;
+        let mut err = Buffer::default();
+        let go_result: GoResult = (authorize_call)(
+            self.state,
+            code_id_buf,
+            sender_buf,
+            entry_point_buf,
+            &mut allowed as *mut bool,
+            &mut err as *mut Buffer,
+        )
+        .into();
+        unsafe {
+            code_id_buf.release_temp();
+            sender_buf.release_temp();
+            entry_point_buf.release_temp();
+        }
+        if go_result == GoResult::Ok && !allowed {
+            // We initialize `err` with a null pointer. If it is not null, Go wrote the reason to it.
+            let reason = if err.ptr.is_null() {
+                String::new()
+            } else {
+                String::from_utf8_lossy(&unsafe { err.consume() }).into()
+            };
+            return Ok(Some(reason));
+        }
+        let default = || "Failed to authorize the call".to_string();
+        unsafe { go_result.into_ffi_result(err, default) }.map(|()| None)
+    }
+
+    /// Hands Go the `result` of a successful call of `entry_point` of `code_id`, if it wants to know
+    pub fn report_call_result(
+        &self,
+        code_id: &[u8],
+        entry_point: &str,
+        result: &[u8],
+        plaintext: bool,
+    ) -> Result<(), FfiError> {
+        let call_result = match self.vtable.call_result {
+            Some(f) => f,
+            None => return Ok(()),
+        };
+        let code_id_buf = Buffer::temp(code_id);
+        let entry_point_buf = Buffer::temp(entry_point.as_bytes());
+        let result_buf = Buffer::temp(result);
+        let mut err = Buffer::default();
+        let go_result: GoResult = (call_result)(
+            self.state,
+            code_id_buf,
+            entry_point_buf,
+            result_buf,
+            plaintext,
+            &mut err as *mut Buffer,
+        )
+        .into();
+        unsafe {
+            code_id_buf.release_temp();
+            entry_point_buf.release_temp();
+            result_buf.release_temp();
+        }
+        let default = || "Failed to report the call result".to_string();
+        unsafe { go_result.into_ffi_result(err, default) }
+    }
+
+    /// Removes all keys between `start` (inclusive) and `end` (exclusive), e.g. to clear a namespace
+    pub fn remove_range(&mut self, start: Option<&[u8]>, end: Option<&[u8]>) -> FfiResult<()> {
+        let delete_range = match self.vtable.delete_range {
+            Some(f) => f,
+            None => return self.remove_range_by_key(start, end),
+        };
+        if let Err(e) = capabilities::require(Capability::StorageWrite) {
+            return (Err(e), GasInfo::free());
+        }
+
+        let bounds = self.full_bounds(start, end);
+        let (start_buf, end_buf) = bounds_buffers(&bounds);
+        let mut err = Buffer::default();
+        let mut used_gas = 0_u64;
+        let go_result: GoResult = (delete_range)(
+            self.state,
+            self.gas_meter,
+            &mut used_gas as *mut u64,
+            start_buf,
+            end_buf,
+            &mut err as *mut Buffer,
+        )
+        .into();
+        let gas_info = gas_report::record_host_call(HostCall::DbRemoveRange, used_gas);
+        unsafe { start_buf.release_temp() };
+        unsafe { end_buf.release_temp() };
+
+        let default = || {
+            format!(
+                "Failed to delete the keys between {:?} and {:?}",
+                start.map(String::from_utf8_lossy),
+                end.map(String::from_utf8_lossy),
+            )
+        };
+        let result = unsafe { go_result.into_ffi_result(err, default) };
+        (result, gas_info)
+    }
+
+    /// `remove_range` for Go wrappers without `delete_range`
+    fn remove_range_by_key(&mut self, start: Option<&[u8]>, end: Option<&[u8]>) -> FfiResult<()> {
+        let (iter, mut gas_info) = self.range_keys(start, end, cosmwasm_std::Order::Ascending);
+        let mut iter = match iter {
+            Ok(iter) => iter,
+            Err(err) => return (Err(err), gas_info),
+        };
+        // the keys are collected first, as the store may not support removing while iterating
+        let mut keys = vec![];
+        loop {
+            let (item, item_gas) = iter.next();
+            add_gas(&mut gas_info, item_gas);
+            match item {
+                Ok(Some((key, _))) => keys.push(key),
+                Ok(None) => break,
+                Err(err) => return (Err(err), gas_info),
+            }
+        }
+        drop(iter);
+
+        for key in keys {
+            let (result, remove_gas) = self.remove(&key);
+            add_gas(&mut gas_info, remove_gas);
+            if let Err(err) = result {
+                return (Err(err), gas_info);
+            }
+        }
+        (Ok(()), gas_info)
+    }
+
+    fn scan(
+        &self,
         start: Option<&[u8]>,
         end: Option<&[u8]>,
         order: cosmwasm_std::Order,
-    ) -> FfiResult<Box<dyn StorageIterator + 'a>> {
+        mode: IteratorMode,
+    ) -> FfiResult<OpenedIter> {
+        let open = match capabilities::require(Capability::Iterators)
+            .and_then(|()| open_iterators::open())
+        {
+            Ok(open) => open,
+            Err(e) => return (Err(e), GasInfo::free()),
+        };
+        let bounds = self.full_bounds(start, end);
         // returns nul pointer in Buffer in none, otherwise proper buffer
-        let start_buf = start
-            .map(|s| Buffer::from_vec(s.to_vec()))
-            .unwrap_or_default();
-        let end_buf = end
-            .map(|e| Buffer::from_vec(e.to_vec()))
-            .unwrap_or_default();
+        let (start_buf, end_buf) = bounds_buffers(&bounds);
         let mut err = Buffer::default();
         let mut iter = GoIter::new(self.gas_meter);
         let mut used_gas = 0_u64;
@@ -117,13 +549,14 @@ impl Storage for DB {
             start_buf,
             end_buf,
             order.into(),
+            mode as i32,
             &mut iter as *mut GoIter,
             &mut err as *mut Buffer,
         )
         .into();
-        let gas_info = GasInfo::with_externally_used(used_gas);
-        let _start = unsafe { start_buf.consume() };
-        let _end = unsafe { end_buf.consume() };
+        let gas_info = gas_report::record_host_call(HostCall::DbScan, used_gas);
+        unsafe { start_buf.release_temp() };
+        unsafe { end_buf.release_temp() };
 
         // return complete error message (reading from buffer for GoResult::Other)
         let default = || {
@@ -138,12 +571,96 @@ impl Storage for DB {
                 return (Err(err), gas_info);
             }
         }
-        (Ok(Box::new(iter)), gas_info)
+        (Ok((iter, open)), gas_info)
+    }
+}
+
+/// An iterator Go opened, counted as open by `open_iterators`
+type OpenedIter = (GoIter, OpenIterator);
+
+type Bounds = (Option<Vec<u8>>, Option<Vec<u8>>);
+
+fn bounds_buffers(bounds: &Bounds) -> (Buffer, Buffer) {
+    (
+        bounds.0.as_deref().map(Buffer::temp).unwrap_or_default(),
+        bounds.1.as_deref().map(Buffer::temp).unwrap_or_default(),
+    )
+}
+
+fn add_gas(total: &mut GasInfo, gas: GasInfo) {
+    total.cost = total.cost.saturating_add(gas.cost);
+    total.externally_used = total.externally_used.saturating_add(gas.externally_used);
+}
+
+impl Storage for DB {
+    fn get(&self, key: &[u8]) -> FfiResult<Option<Vec<u8>>> {
+        let full_key = self.full_key(key);
+        let key_buf = Buffer::temp(&full_key);
+        let mut result_buf = Buffer::default();
+        let mut err = Buffer::default();
+        let mut used_gas = 0_u64;
+        let go_result: GoResult = (self.vtable.read_db)(
+            self.state,
+            self.gas_meter,
+            &mut used_gas as *mut u64,
+            key_buf,
+            &mut result_buf as *mut Buffer,
+            &mut err as *mut Buffer,
+        )
+        .into();
+        let gas_info = gas_report::record_host_call(HostCall::DbRead, used_gas);
+        unsafe { key_buf.release_temp() };
+
+        // return complete error message (reading from buffer for GoResult::Other)
+        let default = || {
+            format!(
+                "Failed to read a key in the db: {}",
+                String::from_utf8_lossy(key)
+            )
+        };
+        unsafe {
+            if let Err(err) = go_result.into_ffi_result(err, default) {
+                return (Err(err), gas_info);
+            }
+        }
+
+        // We initialize `result_buf` with a null pointer. If it is not null, that means
+        // it was initialized by the go code, with values generated by `memory::allocate_rust`.
+        let value = if result_buf.ptr.is_null() {
+            None
+        } else {
+            Some(unsafe { result_buf.consume() })
+        };
+        #[cfg(feature = "shadow")]
+        shadow::record_read(key, &value);
+        (Ok(value), gas_info)
+    }
+
+    /// Allows iteration over a set of key/value pairs, either forwards or backwards.
+    ///
+    /// The bound `start` is inclusive and `end` is exclusive.
+    ///
+    /// If `start` is lexicographically greater than or equal to `end`, an empty range is described, mo matter of the order.
+    fn range<'a>(
+        &'a self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: cosmwasm_std::Order,
+    ) -> FfiResult<Box<dyn StorageIterator + 'a>> {
+        let (result, gas_info) = self.scan(start, end, order, IteratorMode::KeysAndValues);
+        let result = result.map(|iter| self.storage_iter(iter));
+        #[cfg(feature = "shadow")]
+        let result = result.map(|iter| shadow::record_range(start, end, order, iter));
+        (result, gas_info)
     }
 
     fn set(&mut self, key: &[u8], value: &[u8]) -> FfiResult<()> {
-        let key_buf = Buffer::from_vec(key.to_vec());
-        let value_buf = Buffer::from_vec(value.to_vec());
+        if let Err(e) = capabilities::require(Capability::StorageWrite) {
+            return (Err(e), GasInfo::free());
+        }
+        let full_key = self.full_key(key);
+        let key_buf = Buffer::temp(&full_key);
+        let value_buf = Buffer::temp(value);
         let mut err = Buffer::default();
         let mut used_gas = 0_u64;
         let go_result: GoResult = (self.vtable.write_db)(
@@ -155,9 +672,9 @@ impl Storage for DB {
             &mut err as *mut Buffer,
         )
         .into();
-        let gas_info = GasInfo::with_externally_used(used_gas);
-        let _key = unsafe { key_buf.consume() };
-        let _value = unsafe { value_buf.consume() };
+        let gas_info = gas_report::record_host_call(HostCall::DbWrite, used_gas);
+        unsafe { key_buf.release_temp() };
+        unsafe { value_buf.release_temp() };
         // return complete error message (reading from buffer for GoResult::Other)
         let default = || {
             format!(
@@ -170,11 +687,17 @@ impl Storage for DB {
                 return (Err(err), gas_info);
             }
         }
+        #[cfg(feature = "shadow")]
+        shadow::record_write(key, Some(value));
         (Ok(()), gas_info)
     }
 
     fn remove(&mut self, key: &[u8]) -> FfiResult<()> {
-        let key_buf = Buffer::from_vec(key.to_vec());
+        if let Err(e) = capabilities::require(Capability::StorageWrite) {
+            return (Err(e), GasInfo::free());
+        }
+        let full_key = self.full_key(key);
+        let key_buf = Buffer::temp(&full_key);
         let mut err = Buffer::default();
         let mut used_gas = 0_u64;
         let go_result: GoResult = (self.vtable.remove_db)(
@@ -185,8 +708,8 @@ impl Storage for DB {
             &mut err as *mut Buffer,
         )
         .into();
-        let gas_info = GasInfo::with_externally_used(used_gas);
-        let _key = unsafe { key_buf.consume() };
+        let gas_info = gas_report::record_host_call(HostCall::DbRemove, used_gas);
+        unsafe { key_buf.release_temp() };
         let default = || {
             format!(
                 "Failed to delete a key in the db: {}",
@@ -198,6 +721,8 @@ impl Storage for DB {
                 return (Err(err), gas_info);
             }
         }
+        #[cfg(feature = "shadow")]
+        shadow::record_write(key, None);
         (Ok(()), gas_info)
     }
 }
@@ -0,0 +1,251 @@
+//! Timeouts and retries for the enclave calls which talk to services outside the node.
+//!
+//! Creating an attestation report and exchanging the seed go through the AESM service and IAS, and
+//! `init_bootstrap` through the platform's trusted time and randomness. Any of them can hang or fail
+//! temporarily. With a `CallPolicy`, set by Go through `set_enclave_call_policy`, such a call runs on a
+//! worker thread and fails with `Error::Timeout` if it does not return in time. Calls failing in the SGX SDK
+//! are retried with exponential backoff. Errors returned by the enclave itself are final.
+//!
+//! An ecall cannot be interrupted, so a timed out call keeps running in the background. It is not retried
+//! here, as a second attempt could race the first one; instead, calls of the same kind fail with a timeout
+//! until it returned. Go can retry them later. Without a timeout, calls run on the calling thread and wait
+//! for each other like any other enclave call.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use log::*;
+use serde::Deserialize;
+
+use crate::error::Error;
+
+/// How the enclave calls of this module are run, see `set_enclave_call_policy`
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CallPolicy {
+    /// How long a call may take, in milliseconds. 0 waits forever.
+    pub timeout_ms: u64,
+    /// How often a call failing in the SGX SDK is retried
+    pub retries: u32,
+    /// The wait before the first retry, in milliseconds. It doubles with every retry.
+    pub backoff_ms: u64,
+}
+
+static TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+static RETRIES: AtomicU32 = AtomicU32::new(0);
+static BACKOFF_MS: AtomicU64 = AtomicU64::new(0);
+
+/// The enclave calls covered by the policy. Each has a flag telling whether a call of the kind is running.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EnclaveCall {
+    CreateAttestationReport,
+    GetEncryptedSeed,
+    InitBootstrap,
+    CreateKeyAttestation,
+    AttestationChallenge,
+}
+
+static RUNNING: [AtomicBool; 5] = [
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+];
+
+impl EnclaveCall {
+    fn name(self) -> &'static str {
+        match self {
+            EnclaveCall::CreateAttestationReport => "create_attestation_report",
+            EnclaveCall::GetEncryptedSeed => "get_encrypted_seed",
+            EnclaveCall::InitBootstrap => "init_bootstrap",
+            EnclaveCall::CreateKeyAttestation => "create_key_attestation",
+            EnclaveCall::AttestationChallenge => "attestation_challenge",
+        }
+    }
+
+    fn running(self) -> &'static AtomicBool {
+        &RUNNING[self as usize]
+    }
+}
+
+/// How an attempt of an enclave call failed
+pub enum Failure {
+    /// The SGX SDK failed, e.g. because the AESM service was unavailable. Worth another attempt.
+    Sdk(Error),
+    /// The enclave returned an error
+    Enclave(Error),
+}
+
+impl Failure {
+    fn into_error(self) -> Error {
+        match self {
+            Failure::Sdk(e) | Failure::Enclave(e) => e,
+        }
+    }
+}
+
+pub fn set_policy(policy: CallPolicy) {
+    TIMEOUT_MS.store(policy.timeout_ms, Ordering::SeqCst);
+    RETRIES.store(policy.retries, Ordering::SeqCst);
+    BACKOFF_MS.store(policy.backoff_ms, Ordering::SeqCst);
+}
+
+pub fn policy() -> CallPolicy {
+    CallPolicy {
+        timeout_ms: TIMEOUT_MS.load(Ordering::SeqCst),
+        retries: RETRIES.load(Ordering::SeqCst),
+        backoff_ms: BACKOFF_MS.load(Ordering::SeqCst),
+    }
+}
+
+/// Runs `attempt` according to the current policy
+pub fn run<T, F>(call: EnclaveCall, attempt: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: Fn() -> Result<T, Failure> + Send + Sync + Clone + 'static,
+{
+    let policy = policy();
+    let mut backoff = Duration::from_millis(policy.backoff_ms);
+    let mut retries = policy.retries;
+    loop {
+        match run_once(call, policy.timeout_ms, attempt.clone())? {
+            Ok(value) => return Ok(value),
+            Err(Failure::Sdk(e)) if retries > 0 => {
+                warn!(
+                    "Retrying {} in {:?} after it failed: {}",
+                    call.name(),
+                    backoff,
+                    e
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+                retries -= 1;
+            }
+            Err(failure) => return Err(failure.into_error()),
+        }
+    }
+}
+
+/// Marks a call of its kind as running until it is dropped, also if the attempt panics
+struct Running(&'static AtomicBool);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Runs one attempt, on a worker thread if there is a timeout. Fails only if the attempt timed out or panicked.
+fn run_once<T, F>(
+    call: EnclaveCall,
+    timeout_ms: u64,
+    attempt: F,
+) -> Result<Result<T, Failure>, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Failure> + Send + 'static,
+{
+    if timeout_ms == 0 {
+        return Ok(attempt());
+    }
+    let running = call.running();
+    if running.swap(true, Ordering::SeqCst) {
+        // only a timed out call can still be running, as Go does not make these calls concurrently
+        return Err(Error::timeout(call.name(), timeout_ms));
+    }
+    let running = Running(running);
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let result = attempt();
+        drop(running);
+        // the receiver is gone if the call timed out
+        let _ = sender.send(result);
+    });
+    receiver
+        .recv_timeout(Duration::from_millis(timeout_ms))
+        .map_err(|e| match e {
+            RecvTimeoutError::Timeout => Error::timeout(call.name(), timeout_ms),
+            // the sender is dropped without a result if the attempt panicked
+            RecvTimeoutError::Disconnected => Error::panic(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    // the policy is global, so all cases share one test
+    #[test]
+    fn run_applies_the_policy() {
+        set_policy(CallPolicy {
+            timeout_ms: 0,
+            retries: 2,
+            backoff_ms: 1,
+        });
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&attempts);
+        let res = run(EnclaveCall::InitBootstrap, move || {
+            if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(Failure::Sdk(Error::enclave_err(
+                    "SGX_ERROR_SERVICE_UNAVAILABLE",
+                )))
+            } else {
+                Ok(42)
+            }
+        });
+        assert_eq!(res.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        // enclave errors are not retried
+        attempts.store(0, Ordering::SeqCst);
+        let counter = Arc::clone(&attempts);
+        let res: Result<(), Error> = run(EnclaveCall::InitBootstrap, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Err(Failure::Enclave(Error::enclave_err("invalid certificate")))
+        });
+        assert!(res.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        set_policy(CallPolicy {
+            timeout_ms: 20,
+            retries: 2,
+            backoff_ms: 1,
+        });
+        let (release, wait) = mpsc::channel::<()>();
+        let wait = Arc::new(std::sync::Mutex::new(wait));
+        let res: Result<(), Error> = run(EnclaveCall::GetEncryptedSeed, move || {
+            let _ = wait.lock().unwrap().recv();
+            Ok(())
+        });
+        match res.unwrap_err() {
+            Error::Timeout { call, .. } => assert_eq!(call, "get_encrypted_seed"),
+            e => panic!("unexpected error: {}", e),
+        }
+        // the timed out call still runs, so the next one fails right away
+        let res = run(EnclaveCall::GetEncryptedSeed, || Ok(()));
+        assert!(res.is_err());
+
+        release.send(()).unwrap();
+        while EnclaveCall::GetEncryptedSeed
+            .running()
+            .load(Ordering::SeqCst)
+        {
+            thread::sleep(Duration::from_millis(1));
+        }
+        run(EnclaveCall::GetEncryptedSeed, || Ok(())).unwrap();
+
+        // a panicking call does not leave its kind marked as running
+        let res: Result<(), Error> = run(EnclaveCall::CreateKeyAttestation, || panic!("boom"));
+        match res.unwrap_err() {
+            Error::Panic { .. } => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        run(EnclaveCall::CreateKeyAttestation, || Ok(())).unwrap();
+        set_policy(CallPolicy::default());
+    }
+}
@@ -0,0 +1,252 @@
+//! A buffer for the debug output of the enclave, so it does not end up in the node's stdout.
+//!
+//! Without `init_enclave_log`, the VM prints the log lines of the enclave to stdout, interleaved with the
+//! node's logs. Once it is called, the VM hands them to `receive` instead, which keeps the lines of the
+//! configured level and above in a ring buffer. Go fetches them with `drain_enclave_log`, e.g. to write them
+//! to a separate file. When the buffer is full, the oldest lines are dropped and counted.
+
+use std::collections::VecDeque;
+use std::ptr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::Level;
+use serde::{Deserialize, Serialize};
+
+use crate::call_id;
+use crate::error::Error;
+use crate::vm::set_enclave_log_sink;
+
+const OPTIONS_ARG: &str = "options";
+
+/// How the enclave's log lines are buffered, passed to `init_enclave_log` as JSON
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnclaveLogOptions {
+    /// The least severe level kept, one of `error`, `warn`, `info`, `debug` and `trace`
+    pub level: String,
+    /// How many lines the buffer holds until the oldest are dropped
+    pub max_lines: usize,
+    /// Longer lines are cut off, in bytes
+    pub max_line_len: usize,
+}
+
+impl Default for EnclaveLogOptions {
+    fn default() -> Self {
+        EnclaveLogOptions {
+            level: "info".to_string(),
+            max_lines: 1000,
+            max_line_len: 1024,
+        }
+    }
+}
+
+/// A log line of the enclave
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EnclaveLogLine {
+    /// When the line was received, in milliseconds since the unix epoch
+    pub time_ms: u64,
+    /// The level in lower case
+    pub level: String,
+    pub message: String,
+    /// The id of the contract call which logged the line, see `call_id`
+    pub call_id: Option<String>,
+}
+
+/// The result of `drain_enclave_log`, returned as JSON
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct EnclaveLogDrain {
+    /// The buffered lines, oldest first
+    pub lines: Vec<EnclaveLogLine>,
+    /// The lines dropped since the last drain because the buffer was full
+    pub dropped: u64,
+}
+
+struct Buffer {
+    level: Level,
+    max_lines: usize,
+    max_line_len: usize,
+    lines: VecDeque<EnclaveLogLine>,
+    dropped: u64,
+}
+
+impl Buffer {
+    fn push(&mut self, level: Level, message: &str, call_id: Option<String>) {
+        if level > self.level {
+            return;
+        }
+        let mut end = message.len().min(self.max_line_len);
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        let time_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis() as u64)
+            .unwrap_or_default();
+        self.lines.push_back(EnclaveLogLine {
+            time_ms,
+            level: level.to_string().to_lowercase(),
+            message: message[..end].to_string(),
+            call_id,
+        });
+        while self.lines.len() > self.max_lines {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    /// Takes the options of `other`, keeping the buffered lines
+    fn apply(&mut self, other: Buffer) {
+        self.level = other.level;
+        self.max_lines = other.max_lines;
+        self.max_line_len = other.max_line_len;
+        while self.lines.len() > self.max_lines {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    fn drain(&mut self) -> EnclaveLogDrain {
+        let drained = EnclaveLogDrain {
+            lines: self.lines.drain(..).collect(),
+            dropped: self.dropped,
+        };
+        self.dropped = 0;
+        drained
+    }
+}
+
+/// Set by the first `init_enclave_log` and never freed, as the VM may call `receive` at any time
+static BUFFER: AtomicPtr<Mutex<Buffer>> = AtomicPtr::new(ptr::null_mut());
+
+fn lock(buffer: &Mutex<Buffer>) -> MutexGuard<'_, Buffer> {
+    // a panic while holding the lock does not leave the buffer inconsistent
+    buffer.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn buffer() -> Option<&'static Mutex<Buffer>> {
+    let buffer = BUFFER.load(Ordering::SeqCst);
+    if buffer.is_null() {
+        None
+    } else {
+        Some(unsafe { &*buffer })
+    }
+}
+
+/// The sink the VM hands the enclave's log lines to
+fn receive(level: Level, message: &str) {
+    if let Some(buffer) = buffer() {
+        lock(buffer).push(level, message, call_id::current());
+    }
+}
+
+impl EnclaveLogOptions {
+    pub fn parse(json: &[u8]) -> Result<Self, Error> {
+        if json.is_empty() {
+            return Ok(EnclaveLogOptions::default());
+        }
+        let options: EnclaveLogOptions =
+            serde_json::from_slice(json).map_err(|e| Error::invalid_arg(OPTIONS_ARG, e))?;
+        options.level()?;
+        if options.max_lines == 0 || options.max_line_len == 0 {
+            return Err(Error::invalid_arg(
+                OPTIONS_ARG,
+                "max_lines and max_line_len must be greater than 0",
+            ));
+        }
+        Ok(options)
+    }
+
+    fn level(&self) -> Result<Level, Error> {
+        Level::from_str(&self.level).map_err(|_| {
+            let msg = format!("unknown level {:?}", self.level);
+            Error::invalid_arg(OPTIONS_ARG, msg)
+        })
+    }
+
+    fn new_buffer(&self) -> Result<Buffer, Error> {
+        Ok(Buffer {
+            level: self.level()?,
+            max_lines: self.max_lines,
+            max_line_len: self.max_line_len,
+            lines: VecDeque::new(),
+            dropped: 0,
+        })
+    }
+}
+
+/// Starts buffering the enclave's log lines. Calling it again applies the new options to the buffer.
+pub fn init(options: &EnclaveLogOptions) -> Result<(), Error> {
+    let new = Box::into_raw(Box::new(Mutex::new(options.new_buffer()?)));
+    match BUFFER.compare_exchange(ptr::null_mut(), new, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(_) => set_enclave_log_sink(Some(receive)),
+        Err(current) => {
+            let new = unsafe { Box::from_raw(new) };
+            let new = (*new).into_inner().unwrap_or_else(PoisonError::into_inner);
+            lock(unsafe { &*current }).apply(new);
+        }
+    }
+    Ok(())
+}
+
+/// Takes the buffered lines. Empty if `init` was not called.
+pub fn drain() -> EnclaveLogDrain {
+    match buffer() {
+        Some(buffer) => lock(buffer).drain(),
+        None => EnclaveLogDrain::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer_of(level: Level, max_lines: usize, max_line_len: usize) -> Buffer {
+        Buffer {
+            level,
+            max_lines,
+            max_line_len,
+            lines: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    #[test]
+    fn parse_validates_options() {
+        assert_eq!(
+            EnclaveLogOptions::parse(b"").unwrap(),
+            EnclaveLogOptions::default()
+        );
+        let options = EnclaveLogOptions::parse(br#"{"level":"DEBUG","max_lines":10}"#).unwrap();
+        assert_eq!(options.level().unwrap(), Level::Debug);
+        assert_eq!(options.max_lines, 10);
+        assert_eq!(options.max_line_len, 1024);
+
+        assert!(EnclaveLogOptions::parse(br#"{"level":"verbose"}"#).is_err());
+        assert!(EnclaveLogOptions::parse(br#"{"max_lines":0}"#).is_err());
+        assert!(EnclaveLogOptions::parse(br#"{"size":10}"#).is_err());
+    }
+
+    #[test]
+    fn push_filters_and_bounds_lines() {
+        let mut buffer = buffer_of(Level::Info, 2, 4);
+        buffer.push(Level::Debug, "hidden", None);
+        buffer.push(Level::Info, "first", None);
+        buffer.push(Level::Error, "äää", None);
+        buffer.push(Level::Warn, "third", Some("tx-1".to_string()));
+        let drained = buffer.drain();
+        assert_eq!(drained.dropped, 1);
+        let lines: Vec<(&str, &str)> = drained
+            .lines
+            .iter()
+            .map(|line| (line.level.as_str(), line.message.as_str()))
+            .collect();
+        assert_eq!(lines, vec![("error", "ää"), ("warn", "thir")]);
+        assert_eq!(drained.lines[1].call_id.as_deref(), Some("tx-1"));
+
+        let drained = buffer.drain();
+        assert_eq!(drained, EnclaveLogDrain::default());
+    }
+}
@@ -0,0 +1,151 @@
+//! Checks that the loaded enclave is the one this library was built with.
+//!
+//! The library and the enclave `.so` are installed separately, and a stale enclave fails in confusing ways
+//! long after startup, e.g. when a contract key does not verify. The Makefile passes the MRENCLAVE of the
+//! enclave it builds in `ENCLAVE_MRENCLAVE`, and `init_cache` and `init_node` compare it to the MRENCLAVE
+//! the loaded enclave reports. Libraries built without the variable skip the check, and so does an enclave
+//! loaded by `reload_enclave`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::*;
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::vm::untrusted_get_mrenclave;
+
+const MRENCLAVE_LEN: usize = 32;
+/// The MRENCLAVE this library expects, in hex
+const EXPECTED_MRENCLAVE: Option<&str> = option_env!("ENCLAVE_MRENCLAVE");
+
+/// The enclave cannot change while the process runs, so it is only checked until it matched once
+static VERIFIED: AtomicBool = AtomicBool::new(false);
+
+/// The MRENCLAVEs of this library and the loaded enclave, returned by `get_enclave_pairing` as JSON
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EnclavePairing {
+    /// The hex MRENCLAVE this library was built for, if any
+    pub expected: Option<String>,
+    /// The hex MRENCLAVE of the loaded enclave
+    pub actual: String,
+    /// Whether the enclave is the expected one. Always true if nothing is expected.
+    pub matches: bool,
+}
+
+impl EnclavePairing {
+    fn new(expected: Option<&str>, actual: &[u8]) -> Result<Self, Error> {
+        let expected = match expected.map(str::trim).filter(|hex| !hex.is_empty()) {
+            Some(hex) => {
+                let raw = hex::decode(hex).map_err(|e| {
+                    Error::vm_err(format!("Invalid ENCLAVE_MRENCLAVE in this build: {}", e))
+                })?;
+                if raw.len() != MRENCLAVE_LEN {
+                    return Err(Error::vm_err(
+                        "Invalid ENCLAVE_MRENCLAVE in this build: expected 32 bytes",
+                    ));
+                }
+                Some(hex::encode(raw))
+            }
+            None => None,
+        };
+        let actual = hex::encode(actual);
+        let matches = match &expected {
+            Some(expected) => *expected == actual,
+            None => true,
+        };
+        Ok(EnclavePairing {
+            expected,
+            actual,
+            matches,
+        })
+    }
+
+    /// Fails with `Error::EnclaveMismatch` if the loaded enclave is not the expected one
+    fn check(self) -> Result<(), Error> {
+        match self.expected {
+            Some(expected) if !self.matches => Err(Error::enclave_mismatch(expected, self.actual)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Asks the loaded enclave for its MRENCLAVE
+pub fn pairing() -> Result<EnclavePairing, Error> {
+    let actual = untrusted_get_mrenclave().map_err(|e| Error::enclave_err(e.to_string()))?;
+    EnclavePairing::new(EXPECTED_MRENCLAVE, &actual)
+}
+
+/// Fails if the loaded enclave is not the one this library was built with
+pub fn check() -> Result<(), Error> {
+    // builds without SGX have no enclave to check
+    if cfg!(feature = "vanilla") || EXPECTED_MRENCLAVE.is_none() {
+        return Ok(());
+    }
+    if VERIFIED.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    pairing()?.check()?;
+    VERIFIED.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Accepts the enclave loaded by `reload_enclave`. Loading another enclave than the one this library was built
+/// for is the point of a hot swap, so a mismatch is only logged.
+pub fn reloaded() -> Result<(), Error> {
+    if cfg!(feature = "vanilla") {
+        return Ok(());
+    }
+    let pairing = pairing()?;
+    if !pairing.matches {
+        warn!(
+            "The reloaded enclave has MRENCLAVE {}, this library was built for {}",
+            pairing.actual,
+            pairing.expected.unwrap_or_default()
+        );
+    }
+    VERIFIED.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MRENCLAVE: &str = "9a3e2d76d8d82a9e6fb1a9efc2fd28a4ddfa3c6d0b2d4b8d32f10e8e4e5d9c01";
+
+    #[test]
+    fn new_compares_mrenclaves() {
+        let actual = hex::decode(MRENCLAVE).unwrap();
+        let pairing = EnclavePairing::new(Some(MRENCLAVE), &actual).unwrap();
+        assert!(pairing.matches);
+        pairing.check().unwrap();
+
+        let pairing = EnclavePairing::new(Some(&MRENCLAVE.to_uppercase()), &actual).unwrap();
+        assert!(pairing.matches);
+
+        let pairing = EnclavePairing::new(None, &actual).unwrap();
+        assert_eq!(pairing.expected, None);
+        assert!(pairing.matches);
+    }
+
+    #[test]
+    fn check_reports_both_mrenclaves() {
+        let pairing = EnclavePairing::new(Some(MRENCLAVE), &[1u8; 32]).unwrap();
+        assert!(!pairing.matches);
+        match pairing.check().unwrap_err() {
+            Error::EnclaveMismatch {
+                expected, actual, ..
+            } => {
+                assert_eq!(expected, MRENCLAVE);
+                assert_eq!(actual, hex::encode([1u8; 32]));
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn new_rejects_invalid_expectations() {
+        assert!(EnclavePairing::new(Some("xyz"), &[1u8; 32]).is_err());
+        assert!(EnclavePairing::new(Some("abcd"), &[1u8; 32]).is_err());
+    }
+}
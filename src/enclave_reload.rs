@@ -0,0 +1,170 @@
+//! Replacing the enclave of a running node, see `reload_enclave`.
+//!
+//! Calls into the enclave hold an `EnclaveGuard` while they run. A reload stops new calls from starting,
+//! waits until the running ones returned, and then has the VM destroy the enclave and load the new binary.
+//! The new enclave unseals the node's keys and seed from the sealed files on its first use, like after a
+//! restart, so it must be signed with the same key as the old one, and it gets the unlock key of the node.
+//! The cache is reopened, so it drops the modules compiled by the old enclave, see `Cache::enclave_reloaded`.
+//!
+//! Calls arriving during a reload fail right away instead of waiting, as Go may hold locks the reload waits
+//! for. Registration and bootstrap calls are not guarded, so Go must not reload the enclave while registering.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::*;
+
+use crate::enclave_pairing;
+use crate::error::Error;
+use crate::unlock_key;
+use crate::vm::untrusted_reload_enclave;
+
+static RELOADING: AtomicBool = AtomicBool::new(false);
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The guards held by this thread. Nested calls, e.g. queries of other contracts, always enter.
+    static HELD: Cell<u32> = Cell::new(0);
+}
+
+/// How often a reload checks whether the running calls returned
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(1);
+/// How long a reload waits for the running calls
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn reloading() -> Error {
+    Error::enclave_err("The enclave is being reloaded, retry the call")
+}
+
+/// Keeps the enclave from being reloaded until it is dropped
+pub struct EnclaveGuard {}
+
+impl EnclaveGuard {
+    pub fn enter() -> Result<Self, Error> {
+        if HELD.with(|held| held.get()) == 0 {
+            if RELOADING.load(Ordering::SeqCst) {
+                return Err(reloading());
+            }
+            IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+            // a reload may have started between the check and the increment, and not seen the increment
+            if RELOADING.load(Ordering::SeqCst) {
+                IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+                return Err(reloading());
+            }
+        }
+        HELD.with(|held| held.set(held.get() + 1));
+        Ok(EnclaveGuard {})
+    }
+}
+
+impl Drop for EnclaveGuard {
+    fn drop(&mut self) {
+        let held = HELD.with(|held| {
+            held.set(held.get() - 1);
+            held.get()
+        });
+        if held == 0 {
+            IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Stops new calls from entering and waits up to `timeout` for the running ones. Runs `reload` if they all
+/// returned, and lets calls enter again afterwards in any case.
+fn drain_and_run<T, F>(timeout: Duration, reload: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    if HELD.with(|held| held.get()) > 0 {
+        return Err(Error::enclave_err(
+            "Cannot reload the enclave from within an enclave call",
+        ));
+    }
+    if RELOADING.swap(true, Ordering::SeqCst) {
+        return Err(Error::enclave_err("The enclave is already being reloaded"));
+    }
+    let started = Instant::now();
+    let res = loop {
+        let in_flight = IN_FLIGHT.load(Ordering::SeqCst);
+        if in_flight == 0 {
+            break reload();
+        }
+        if started.elapsed() >= timeout {
+            let msg = format!(
+                "Cannot reload the enclave: {} calls still running after {:?}",
+                in_flight, timeout
+            );
+            break Err(Error::enclave_err(msg));
+        }
+        thread::sleep(DRAIN_POLL_INTERVAL);
+    };
+    RELOADING.store(false, Ordering::SeqCst);
+    res
+}
+
+/// Replaces the enclave with the signed enclave binary at `path`, once all running calls returned. Runs
+/// `reloaded`, e.g. to reopen the cache, before calls may enter again.
+pub fn reload<F>(path: &str, reloaded: F) -> Result<(), Error>
+where
+    F: FnOnce() -> Result<(), Error>,
+{
+    drain_and_run(DRAIN_TIMEOUT, || {
+        info!("Reloading the enclave from {}", path);
+        untrusted_reload_enclave(path).map_err(|e| Error::enclave_err(e.to_string()))?;
+        unlock_key::reapply()?;
+        enclave_pairing::reloaded()?;
+        reloaded()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    // the gate is global, so all cases share one test
+    #[test]
+    fn reload_drains_calls() {
+        // nested guards count once
+        let outer = EnclaveGuard::enter().unwrap();
+        let inner = EnclaveGuard::enter().unwrap();
+        assert_eq!(IN_FLIGHT.load(Ordering::SeqCst), 1);
+        assert!(drain_and_run(Duration::from_millis(1), || Ok(())).is_err());
+        drop(inner);
+        drop(outer);
+        assert_eq!(IN_FLIGHT.load(Ordering::SeqCst), 0);
+
+        // a call running on another thread delays the reload until it returns
+        let (entered, wait_entered) = mpsc::channel();
+        let (release, wait_release) = mpsc::channel::<()>();
+        let call = thread::spawn(move || {
+            let _guard = EnclaveGuard::enter().unwrap();
+            entered.send(()).unwrap();
+            wait_release.recv().unwrap();
+        });
+        wait_entered.recv().unwrap();
+        let res = drain_and_run(Duration::from_millis(10), || Ok(()));
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("1 calls still running"));
+
+        let reload = thread::spawn(|| {
+            drain_and_run(Duration::from_secs(10), || {
+                // new calls are refused while reloading
+                assert!(thread::spawn(|| EnclaveGuard::enter().is_err())
+                    .join()
+                    .unwrap());
+                Ok(42)
+            })
+        });
+        thread::sleep(Duration::from_millis(10));
+        release.send(()).unwrap();
+        call.join().unwrap();
+        assert_eq!(reload.join().unwrap().unwrap(), 42);
+
+        let _guard = EnclaveGuard::enter().unwrap();
+    }
+}
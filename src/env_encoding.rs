@@ -0,0 +1,399 @@
+//! The encodings of the env `params` of contract calls.
+//!
+//! Go wrappers built on amino pass the env as amino JSON, which quotes 64 bit integers, e.g. `"height":"123"`.
+//! Wrappers migrating to protobuf pass a binary `Env` message instead. While chains migrate, the library takes
+//! both, and `normalize` turns either into the JSON env of the VM, so everything after it sees one encoding.
+//!
+//! JSON starts with `{`, possibly after whitespace, and a protobuf `Env` with the tag of its first field, 0x0a.
+//! The protobuf schema mirrors `types.Env` of the Go side:
+//!
+//! ```protobuf
+//! message Env {
+//!   BlockInfo block = 1;
+//!   MessageInfo message = 2;
+//!   ContractInfo contract = 3;
+//!   string contract_key = 4;
+//! }
+//! message BlockInfo { uint64 height = 1; uint64 time = 2; string chain_id = 3; }
+//! message MessageInfo { string sender = 1; repeated Coin sent_funds = 2; }
+//! message Coin { string denom = 1; string amount = 2; }
+//! message ContractInfo { string address = 1; }
+//! ```
+//!
+//! Unknown fields are skipped, and missing ones take their protobuf defaults.
+//!
+//! The fields of the env are versioned, see `ENV_SCHEMA_VERSIONS`. A JSON env names its version in the
+//! `schema_version` field, and envs without one, like all protobuf envs, are version 1. Go learns the versions
+//! this library accepts from `env_schema_versions` and sends the newest one both sides know, so the wrapper and
+//! the library can be upgraded one after the other. Envs of other versions fail. Fields of the env this library
+//! does not know are dropped before the env reaches the VM, except the ones the library sets itself, which the
+//! checks of the call reject.
+
+use std::borrow::Cow;
+
+use log::*;
+use serde_json::{json, Map, Value};
+
+use crate::error::Error;
+use crate::migrate_auth::MIGRATE_AUTH_FIELD;
+use crate::plaintext::PLAINTEXT_FIELD;
+use crate::state_keys::KEY_ALIASES_FIELD;
+
+const PARAMS_ARG: &str = "params";
+/// The first byte of a protobuf `Env`, the tag of `block`
+const PROTOBUF_TAG: u8 = 0x0a;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+const TRUNCATED: &str = "the protobuf env is truncated";
+
+/// The versions of the env schema we accept, oldest first
+pub const ENV_SCHEMA_VERSIONS: [u32; 1] = [1];
+const SCHEMA_VERSION_FIELD: &str = "schema_version";
+/// The fields of the env and the fields of their objects
+const SCHEMA: &[(&str, &[&str])] = &[
+    ("block", &["height", "time", "chain_id"]),
+    ("message", &["sender", "sent_funds"]),
+    ("contract", &["address"]),
+    ("contract_key", &[]),
+];
+/// The fields of the env set by the library
+const RESERVED_FIELDS: &[&str] = &[PLAINTEXT_FIELD, KEY_ALIASES_FIELD, MIGRATE_AUTH_FIELD];
+
+/// The JSON env of the VM for `params` in any of the supported encodings
+pub fn normalize(params: &[u8]) -> Result<Cow<'_, [u8]>, Error> {
+    let json = params.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'{');
+    let protobuf = !json && params.first() == Some(&PROTOBUF_TAG);
+    let mut env = if protobuf {
+        decode_env(params).map_err(|reason| Error::invalid_arg(PARAMS_ARG, reason))?
+    } else {
+        match serde_json::from_slice(params) {
+            Ok(env) => env,
+            // malformed envs are rejected by the checks of the call
+            Err(_) => return Ok(Cow::Borrowed(params)),
+        }
+    };
+    let env_object = match env.as_object_mut() {
+        Some(env) => env,
+        None => return Ok(Cow::Borrowed(params)),
+    };
+    let unquoted = unquote_integers(env_object)?;
+    let fitted = fit_schema(env_object)?;
+    // envs which are plain JSON of the schema already are passed on as they are
+    if !protobuf && !unquoted && !fitted {
+        return Ok(Cow::Borrowed(params));
+    }
+    let env = serde_json::to_vec(&env).map_err(Error::vm_err)?;
+    Ok(Cow::Owned(env))
+}
+
+/// Replaces the quoted integers of amino JSON with numbers, and returns whether there were any
+fn unquote_integers(env: &mut Map<String, Value>) -> Result<bool, Error> {
+    let block = match env.get_mut("block").and_then(Value::as_object_mut) {
+        Some(block) => block,
+        None => return Ok(false),
+    };
+    let mut quoted = false;
+    for field in &["height", "time"] {
+        let value = match block.get(*field) {
+            Some(Value::String(value)) => value.parse::<u64>(),
+            _ => continue,
+        };
+        let value = value.map_err(|_| {
+            Error::invalid_arg(PARAMS_ARG, format!("the block {} is not an integer", field))
+        })?;
+        block.insert(field.to_string(), Value::from(value));
+        quoted = true;
+    }
+    Ok(quoted)
+}
+
+/// Checks the schema version of `env` and drops the version and unknown fields. Returns whether it dropped any.
+fn fit_schema(env: &mut Map<String, Value>) -> Result<bool, Error> {
+    let mut dropped = false;
+    if let Some(version) = env.remove(SCHEMA_VERSION_FIELD) {
+        let supported = match version.as_u64() {
+            Some(version) => ENV_SCHEMA_VERSIONS.iter().any(|v| u64::from(*v) == version),
+            None => false,
+        };
+        if !supported {
+            return Err(Error::invalid_arg(
+                PARAMS_ARG,
+                format!("unsupported env schema version {}", version),
+            ));
+        }
+        dropped = true;
+    }
+    dropped |= drop_unknown(env, "", |field| {
+        SCHEMA.iter().any(|(known, _)| *known == field) || RESERVED_FIELDS.contains(&field)
+    });
+    for (field, subfields) in SCHEMA {
+        if let Some(Value::Object(object)) = env.get_mut(*field) {
+            dropped |= drop_unknown(object, field, |subfield| subfields.contains(&subfield));
+        }
+    }
+    Ok(dropped)
+}
+
+/// Drops the fields of `object` which are not `known`, and returns whether there were any
+fn drop_unknown<F>(object: &mut Map<String, Value>, path: &str, known: F) -> bool
+where
+    F: Fn(&str) -> bool,
+{
+    let unknown: Vec<String> = object.keys().filter(|f| !known(f)).cloned().collect();
+    for field in &unknown {
+        debug!("Dropping the unknown env field {}/{}", path, field);
+        object.remove(field);
+    }
+    !unknown.is_empty()
+}
+
+/// Reads protobuf fields
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+        if self.0.len() < len {
+            return Err(TRUNCATED);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<u64, &'static str> {
+        let mut value = 0_u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("a protobuf varint is too long")
+    }
+
+    /// The number and wire type of the next field
+    fn key(&mut self) -> Result<(u64, u8), &'static str> {
+        let key = self.varint()?;
+        Ok((key >> 3, (key & 0x07) as u8))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], &'static str> {
+        let len = self.varint()?;
+        if len > self.0.len() as u64 {
+            return Err(TRUNCATED);
+        }
+        self.take(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String, &'static str> {
+        String::from_utf8(self.bytes()?.to_vec())
+            .map_err(|_| "a string of the protobuf env is not valid UTF-8")
+    }
+
+    fn skip(&mut self, wire_type: u8) -> Result<(), &'static str> {
+        match wire_type {
+            WIRE_VARINT => self.varint().map(drop),
+            WIRE_FIXED64 => self.take(8).map(drop),
+            WIRE_LEN => self.bytes().map(drop),
+            WIRE_FIXED32 => self.take(4).map(drop),
+            _ => Err("the protobuf env has an unsupported wire type"),
+        }
+    }
+}
+
+fn decode_env(data: &[u8]) -> Result<Value, &'static str> {
+    let mut env = json!({
+        "block": decode_block(&[])?,
+        "message": decode_message(&[])?,
+        "contract": decode_contract(&[])?,
+        "contract_key": "",
+    });
+    let mut reader = Reader(data);
+    while !reader.is_empty() {
+        match reader.key()? {
+            (1, WIRE_LEN) => env["block"] = decode_block(reader.bytes()?)?,
+            (2, WIRE_LEN) => env["message"] = decode_message(reader.bytes()?)?,
+            (3, WIRE_LEN) => env["contract"] = decode_contract(reader.bytes()?)?,
+            (4, WIRE_LEN) => env["contract_key"] = Value::from(reader.string()?),
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+    Ok(env)
+}
+
+fn decode_block(data: &[u8]) -> Result<Value, &'static str> {
+    let (mut height, mut time, mut chain_id) = (0, 0, String::new());
+    let mut reader = Reader(data);
+    while !reader.is_empty() {
+        match reader.key()? {
+            (1, WIRE_VARINT) => height = reader.varint()?,
+            (2, WIRE_VARINT) => time = reader.varint()?,
+            (3, WIRE_LEN) => chain_id = reader.string()?,
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+    Ok(json!({ "height": height, "time": time, "chain_id": chain_id }))
+}
+
+fn decode_message(data: &[u8]) -> Result<Value, &'static str> {
+    let (mut sender, mut sent_funds) = (String::new(), Vec::new());
+    let mut reader = Reader(data);
+    while !reader.is_empty() {
+        match reader.key()? {
+            (1, WIRE_LEN) => sender = reader.string()?,
+            (2, WIRE_LEN) => sent_funds.push(decode_coin(reader.bytes()?)?),
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+    Ok(json!({ "sender": sender, "sent_funds": sent_funds }))
+}
+
+fn decode_coin(data: &[u8]) -> Result<Value, &'static str> {
+    let (mut denom, mut amount) = (String::new(), String::new());
+    let mut reader = Reader(data);
+    while !reader.is_empty() {
+        match reader.key()? {
+            (1, WIRE_LEN) => denom = reader.string()?,
+            (2, WIRE_LEN) => amount = reader.string()?,
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+    Ok(json!({ "denom": denom, "amount": amount }))
+}
+
+fn decode_contract(data: &[u8]) -> Result<Value, &'static str> {
+    let mut address = String::new();
+    let mut reader = Reader(data);
+    while !reader.is_empty() {
+        match reader.key()? {
+            (1, WIRE_LEN) => address = reader.string()?,
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+    Ok(json!({ "address": address }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint_field(number: u8, mut value: u64) -> Vec<u8> {
+        let mut field = vec![number << 3 | WIRE_VARINT];
+        while value >= 0x80 {
+            field.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        field.push(value as u8);
+        field
+    }
+
+    fn len_field(number: u8, data: &[u8]) -> Vec<u8> {
+        [&[number << 3 | WIRE_LEN, data.len() as u8][..], data].concat()
+    }
+
+    fn protobuf_env() -> Vec<u8> {
+        let block = [
+            varint_field(1, 12345),
+            varint_field(2, 1_600_000_000),
+            len_field(3, b"secret-2"),
+        ]
+        .concat();
+        let coin = [len_field(1, b"uscrt"), len_field(2, b"100")].concat();
+        let message = [len_field(1, b"secret1sender"), len_field(2, &coin)].concat();
+        [
+            len_field(1, &block),
+            len_field(2, &message),
+            len_field(3, &len_field(1, b"secret1contract")),
+            // an unknown field
+            varint_field(9, 1),
+            len_field(4, b"a2V5"),
+        ]
+        .concat()
+    }
+
+    fn parse(params: &[u8]) -> Value {
+        serde_json::from_slice(&normalize(params).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn normalize_passes_json_on() {
+        let params = br#"{"block":{"height":1,"time":2,"chain_id":"x"},"contract_key":"a2V5"}"#;
+        match normalize(params).unwrap() {
+            Cow::Borrowed(env) => assert_eq!(env, &params[..]),
+            Cow::Owned(_) => panic!("expected the params as they are"),
+        }
+        // whitespace is not a protobuf tag
+        let params = b"\n {\"block\":{}}";
+        assert_eq!(&*normalize(params).unwrap(), &params[..]);
+    }
+
+    #[test]
+    fn normalize_unquotes_amino_integers() {
+        let params = br#"{"block":{"height":"12345","time":"1600000000","chain_id":"x"}}"#;
+        assert_eq!(
+            parse(params),
+            json!({ "block": { "height": 12345, "time": 1600000000, "chain_id": "x" } })
+        );
+        assert!(normalize(br#"{"block":{"height":"-1"}}"#).is_err());
+    }
+
+    #[test]
+    fn normalize_decodes_protobuf() {
+        assert_eq!(
+            parse(&protobuf_env()),
+            json!({
+                "block": { "height": 12345, "time": 1600000000, "chain_id": "secret-2" },
+                "message": {
+                    "sender": "secret1sender",
+                    "sent_funds": [{ "denom": "uscrt", "amount": "100" }],
+                },
+                "contract": { "address": "secret1contract" },
+                "contract_key": "a2V5",
+            })
+        );
+        // missing fields take their defaults
+        let env = parse(&len_field(1, &varint_field(1, 7)));
+        assert_eq!(env["block"]["height"], 7);
+        assert_eq!(env["message"]["sent_funds"], json!([]));
+        assert_eq!(env["contract_key"], "");
+    }
+
+    #[test]
+    fn normalize_checks_the_schema_version() {
+        let env = parse(br#"{"schema_version":1,"contract":{"address":"x"}}"#);
+        assert_eq!(env, json!({ "contract": { "address": "x" } }));
+        assert!(normalize(br#"{"schema_version":2,"contract":{"address":"x"}}"#).is_err());
+        assert!(normalize(br#"{"schema_version":"1"}"#).is_err());
+    }
+
+    #[test]
+    fn normalize_drops_unknown_fields() {
+        let params = br#"{"block":{"height":1,"time":2,"chain_id":"x","proposer":"y"},"contract":{"address":"z"},"fees":[],"plaintext":true}"#;
+        assert_eq!(
+            parse(params),
+            json!({
+                "block": { "height": 1, "time": 2, "chain_id": "x" },
+                "contract": { "address": "z" },
+                // left for the checks of the call
+                "plaintext": true,
+            })
+        );
+    }
+
+    #[test]
+    fn normalize_rejects_malformed_protobuf() {
+        let env = protobuf_env();
+        assert!(normalize(&env[..env.len() - 1]).is_err());
+        assert!(normalize(&[PROTOBUF_TAG, 0x02, 0x1a, 0x05]).is_err());
+        assert!(normalize(&[PROTOBUF_TAG, 0x00, 0x0f]).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! The encryption envelope of messages sent to secret contracts.
+//!
+//! A client picks an ephemeral x25519 key pair and a random nonce, and derives the tx encryption key
+//! from the x25519 shared secret with the node's public key and the nonce. The message is encrypted with
+//! AES-SIV and sent as `nonce || ephemeral public key || ciphertext`. The contract's response is
+//! encrypted with the same key, so the client can decrypt it using the envelope it sent.
+//!
+//! The answers of queries are encrypted the same way, but the enclave encrypts the base64 of the answer.
+//! `decrypt_query_response` decodes it like clients do, for gateways which keep the ephemeral secret and nonce
+//! of the queries they relay instead of the whole envelope.
+//!
+//! This mirrors the scheme implemented in the enclave, so that Go clients, relayers and tests don't have
+//! to reimplement it.
+
+use aes_siv::siv::Aes128Siv;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::error::Error;
+
+pub const NONCE_LEN: usize = 32;
+pub const PUBKEY_LEN: usize = 32;
+
+/// The HKDF salt used by the enclave when deriving tx encryption keys
+const HKDF_SALT: [u8; 32] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x4b, 0xea, 0xd8, 0xdf, 0x69, 0x99,
+    0x08, 0x52, 0xc2, 0x02, 0xdb, 0x0e, 0x00, 0x97, 0xc1, 0xa1, 0x2e, 0xa6, 0x37, 0xd7, 0xe9, 0x6d,
+];
+
+/// An encrypted message, as sent to the chain
+pub struct Envelope<'a> {
+    pub nonce: &'a [u8],
+    pub pubkey: &'a [u8],
+    pub ciphertext: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        if data.len() < NONCE_LEN + PUBKEY_LEN {
+            return Err(Error::invalid_arg(
+                "envelope",
+                format!("expected at least {} bytes", NONCE_LEN + PUBKEY_LEN),
+            ));
+        }
+        let (nonce, rest) = data.split_at(NONCE_LEN);
+        let (pubkey, ciphertext) = rest.split_at(PUBKEY_LEN);
+        Ok(Envelope {
+            nonce,
+            pubkey,
+            ciphertext,
+        })
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        [self.nonce, self.pubkey, self.ciphertext].concat()
+    }
+}
+
+fn to_array(name: &str, data: &[u8]) -> Result<[u8; 32], Error> {
+    if data.len() != 32 {
+        let msg = format!("expected 32 bytes, got {}", data.len());
+        return Err(Error::invalid_arg(name, msg));
+    }
+    let mut out = [0u8; 32];
+    out.copy_from_slice(data);
+    Ok(out)
+}
+
+/// Derives the key used to encrypt a message and its response
+fn tx_encryption_key(
+    node_pubkey: &[u8],
+    ephemeral_secret: &[u8],
+    nonce: &[u8],
+) -> Result<[u8; 32], Error> {
+    let node_pubkey = PublicKey::from(to_array("node_pubkey", node_pubkey)?);
+    let secret = StaticSecret::from(to_array("ephemeral_secret", ephemeral_secret)?);
+    let nonce = to_array("nonce", nonce)?;
+
+    let shared = secret.diffie_hellman(&node_pubkey);
+    let ikm = [shared.as_bytes(), &nonce[..]].concat();
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&HKDF_SALT), &ikm)
+        .expand(&[], &mut key)
+        .map_err(|_| Error::vm_err("Cannot derive tx encryption key"))?;
+    Ok(key)
+}
+
+/// Encrypts `plaintext` for the node with `node_pubkey` and returns the envelope
+pub fn encrypt_msg(
+    node_pubkey: &[u8],
+    ephemeral_secret: &[u8],
+    nonce: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let key = tx_encryption_key(node_pubkey, ephemeral_secret, nonce)?;
+    let ciphertext = Aes128Siv::new(key.into())
+        .encrypt(&[[0u8; 0]], plaintext)
+        .map_err(|_| Error::vm_err("Cannot encrypt message"))?;
+
+    let pubkey = PublicKey::from(&StaticSecret::from(to_array(
+        "ephemeral_secret",
+        ephemeral_secret,
+    )?));
+    Ok(Envelope {
+        nonce,
+        pubkey: pubkey.as_bytes(),
+        ciphertext: &ciphertext,
+    }
+    .to_vec())
+}
+
+/// Decrypts `ciphertext`, which a contract returned in response to the message sent in `envelope`
+pub fn decrypt_response(
+    node_pubkey: &[u8],
+    ephemeral_secret: &[u8],
+    envelope: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let envelope = Envelope::parse(envelope)?;
+    decrypt(node_pubkey, ephemeral_secret, envelope.nonce, ciphertext)
+}
+
+/// Decrypts the answer of a query sent with `ephemeral_secret` and `nonce`, see the module docs
+pub fn decrypt_query_response(
+    node_pubkey: &[u8],
+    ephemeral_secret: &[u8],
+    nonce: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let encoded = decrypt(node_pubkey, ephemeral_secret, nonce, ciphertext)?;
+    base64::decode(&encoded).map_err(|_| Error::vm_err("Query response is not base64 encoded"))
+}
+
+fn decrypt(
+    node_pubkey: &[u8],
+    ephemeral_secret: &[u8],
+    nonce: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let key = tx_encryption_key(node_pubkey, ephemeral_secret, nonce)?;
+    Aes128Siv::new(key.into())
+        .decrypt(&[[0u8; 0]], ciphertext)
+        .map_err(|_| Error::vm_err("Cannot decrypt response"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NODE_SECRET: [u8; 32] = [7u8; 32];
+    const CLIENT_SECRET: [u8; 32] = [9u8; 32];
+    const NONCE: [u8; 32] = [1u8; 32];
+
+    fn node_pubkey() -> [u8; 32] {
+        *PublicKey::from(&StaticSecret::from(NODE_SECRET)).as_bytes()
+    }
+
+    #[test]
+    fn encrypt_msg_produces_envelope() {
+        let envelope = encrypt_msg(&node_pubkey(), &CLIENT_SECRET, &NONCE, b"{}").unwrap();
+        let parsed = Envelope::parse(&envelope).unwrap();
+        assert_eq!(parsed.nonce, &NONCE[..]);
+        assert_eq!(
+            parsed.pubkey,
+            PublicKey::from(&StaticSecret::from(CLIENT_SECRET)).as_bytes()
+        );
+        assert_ne!(parsed.ciphertext, b"{}");
+    }
+
+    #[test]
+    fn node_and_client_derive_same_key() {
+        let client_pubkey = PublicKey::from(&StaticSecret::from(CLIENT_SECRET));
+        let client_key = tx_encryption_key(&node_pubkey(), &CLIENT_SECRET, &NONCE).unwrap();
+        let node_key = tx_encryption_key(client_pubkey.as_bytes(), &NODE_SECRET, &NONCE).unwrap();
+        assert_eq!(client_key, node_key);
+    }
+
+    #[test]
+    fn decrypt_response_works() {
+        let envelope = encrypt_msg(&node_pubkey(), &CLIENT_SECRET, &NONCE, b"{}").unwrap();
+        // the response is encrypted just like the message
+        let response = Envelope::parse(&envelope).unwrap().ciphertext.to_vec();
+        let plaintext =
+            decrypt_response(&node_pubkey(), &CLIENT_SECRET, &envelope, &response).unwrap();
+        assert_eq!(plaintext, b"{}");
+    }
+
+    #[test]
+    fn decrypt_query_response_decodes_the_answer() {
+        let answer = br#"{"balance":{"amount":"42"}}"#;
+        let encoded = base64::encode(&answer[..]);
+        let envelope =
+            encrypt_msg(&node_pubkey(), &CLIENT_SECRET, &NONCE, encoded.as_bytes()).unwrap();
+        let response = Envelope::parse(&envelope).unwrap().ciphertext.to_vec();
+        let plaintext =
+            decrypt_query_response(&node_pubkey(), &CLIENT_SECRET, &NONCE, &response).unwrap();
+        assert_eq!(plaintext, &answer[..]);
+
+        // a wrong nonce derives another key
+        let result = decrypt_query_response(&node_pubkey(), &CLIENT_SECRET, &[2u8; 32], &response);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_rejects_short_envelopes() {
+        let error = Envelope::parse(&[0u8; 63]).err().unwrap();
+        match error {
+            Error::InvalidArg { name, .. } => assert_eq!(name, "envelope"),
+            _ => panic!("expect different error"),
+        }
+    }
+
+    #[test]
+    fn encrypt_msg_checks_key_length() {
+        let error = encrypt_msg(&[0u8; 31], &CLIENT_SECRET, &NONCE, b"{}").unwrap_err();
+        match error {
+            Error::InvalidArg { name, .. } => assert_eq!(name, "node_pubkey"),
+            _ => panic!("expect different error"),
+        }
+    }
+}
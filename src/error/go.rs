@@ -1,6 +1,6 @@
-use cosmwasm_sgx_vm::FfiError;
 use std::fmt;
 
+use crate::vm::FfiError;
 use crate::Buffer;
 
 /// This enum gives names to the status codes returned from Go callbacks to Rust.
@@ -1,5 +1,7 @@
 mod go;
 mod rust;
+mod sanitize;
 
 pub use go::GoResult;
-pub use rust::{clear_error, handle_c_error, set_error, Error};
+pub use rust::{clear_error, handle_c_error, set_error, Error, ERROR_TEMPLATES_VERSION};
+pub use sanitize::sanitize;
@@ -1,9 +1,15 @@
+use std::collections::HashSet;
+use std::io;
+
 use errno::{set_errno, Errno};
 
-use cosmwasm_sgx_vm::VmError;
+use log::*;
 use snafu::Snafu;
 
 use crate::memory::Buffer;
+use crate::vm::VmError;
+
+use super::sanitize::sanitize;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -19,6 +25,13 @@ pub enum Error {
         msg: String,
         backtrace: snafu::Backtrace,
     },
+    #[snafu(display("Invalid argument {}: {}", name, msg))]
+    InvalidArg {
+        name: String,
+        msg: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
     #[snafu(display("Ran out of gas"))]
     OutOfGas {
         #[cfg(feature = "backtraces")]
@@ -41,6 +54,156 @@ pub enum Error {
         #[cfg(feature = "backtraces")]
         backtrace: snafu::Backtrace,
     },
+    #[snafu(display("Invalid query permit: {}", msg))]
+    InvalidPermit {
+        msg: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Invalid cache options: {}", msg))]
+    InvalidOptions {
+        msg: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Maximum call depth exceeded: {} > {}", depth, max))]
+    CallDepthExceeded {
+        depth: u32,
+        max: u32,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Response too large: {} bytes > {} bytes", size, max))]
+    ResponseTooLarge {
+        size: usize,
+        max: usize,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    /// Go parses the feature lists out of this message, see `types.UnsupportedFeaturesError`
+    #[snafu(display(
+        "Contract requires unsupported features: {} (required: {}; supported: {})",
+        missing.join(", "),
+        required.join(", "),
+        supported.join(", ")
+    ))]
+    UnsupportedFeatures {
+        missing: Vec<String>,
+        required: Vec<String>,
+        supported: Vec<String>,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display(
+        "Enclave mismatch: the loaded enclave has MRENCLAVE {}, but this library was built for {}. Install the enclave released with this library",
+        actual,
+        expected
+    ))]
+    EnclaveMismatch {
+        expected: String,
+        actual: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Enclave call {} timed out after {} ms", call, timeout_ms))]
+    Timeout {
+        call: String,
+        timeout_ms: u64,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Contract memory exceeds the limit: {} pages > {} pages", pages, max))]
+    MemoryLimitExceeded {
+        pages: u32,
+        max: u32,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Contract uses floats, which the float policy of this node rejects"))]
+    FloatsRejected {
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Invalid contract events: {}", msg))]
+    InvalidEvents {
+        msg: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Event limit exceeded: {} {} > {}", limit, value, max))]
+    EventLimitExceeded {
+        limit: String,
+        value: usize,
+        max: usize,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Query throttled: {}", reason))]
+    Throttled {
+        reason: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Contract paused: the code {} may not run", code_id))]
+    ContractPaused {
+        code_id: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Call of {} denied: {}", entry_point, reason))]
+    CallDenied {
+        entry_point: String,
+        reason: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Illegal reentrancy: {} called from within {}", call, active))]
+    IllegalReentrancy {
+        call: String,
+        active: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Invalid message: {}", reason))]
+    InvalidMsg {
+        reason: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    #[snafu(display("Iterator limit exceeded: more than {} open iterators", max))]
+    IteratorLimitExceeded {
+        max: u32,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    /// The platform must be updated and the node registered again, see `tcb_recovery`
+    #[snafu(display(
+        "TCB out of date: the quote status is {} (advisories: {}). Update the microcode and BIOS, then refresh the registration",
+        status,
+        advisory_ids.join(", ")
+    ))]
+    TcbOutdated {
+        status: String,
+        advisory_ids: Vec<String>,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    /// A contract used a host capability its code is denied, see `capabilities`
+    #[snafu(display("Capability denied: the code {} may not use {}", code_id, capability))]
+    CapabilityDenied {
+        code_id: String,
+        capability: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
+    /// Go did not hand the consensus params of the chain to the cache yet, see `consensus_params`
+    #[snafu(display(
+        "Consensus params not set: call set_consensus_params with the params of the chain first"
+    ))]
+    ConsensusParamsUnset {
+        #[cfg(feature = "backtraces")]
+        backtrace: snafu::Backtrace,
+    },
 }
 
 impl Error {
@@ -55,6 +218,14 @@ impl Error {
         .build()
     }
 
+    pub fn invalid_arg<T: Into<String>, S: ToString>(name: T, msg: S) -> Self {
+        InvalidArg {
+            name: name.into(),
+            msg: msg.to_string(),
+        }
+        .build()
+    }
+
     pub fn panic() -> Self {
         Panic {}.build()
     }
@@ -76,12 +247,184 @@ impl Error {
     pub fn out_of_gas() -> Self {
         OutOfGas {}.build()
     }
+
+    pub fn invalid_permit<S: ToString>(msg: S) -> Self {
+        InvalidPermit {
+            msg: msg.to_string(),
+        }
+        .build()
+    }
+
+    pub fn invalid_options<S: ToString>(msg: S) -> Self {
+        InvalidOptions {
+            msg: msg.to_string(),
+        }
+        .build()
+    }
+
+    pub fn call_depth_exceeded(depth: u32, max: u32) -> Self {
+        CallDepthExceeded { depth, max }.build()
+    }
+
+    pub fn response_too_large(size: usize, max: usize) -> Self {
+        ResponseTooLarge { size, max }.build()
+    }
+
+    /// A code requiring the features `required` cannot be stored in a cache supporting `supported`
+    pub fn unsupported_features(required: &HashSet<String>, supported: &HashSet<String>) -> Self {
+        let sorted = |features: &HashSet<String>| {
+            let mut features: Vec<String> = features.iter().cloned().collect();
+            features.sort();
+            features
+        };
+        UnsupportedFeatures {
+            missing: sorted(&required.difference(supported).cloned().collect()),
+            required: sorted(required),
+            supported: sorted(supported),
+        }
+        .build()
+    }
+
+    pub fn enclave_mismatch<S: Into<String>>(expected: S, actual: S) -> Self {
+        EnclaveMismatch {
+            expected: expected.into(),
+            actual: actual.into(),
+        }
+        .build()
+    }
+
+    pub fn timeout<T: Into<String>>(call: T, timeout_ms: u64) -> Self {
+        Timeout {
+            call: call.into(),
+            timeout_ms,
+        }
+        .build()
+    }
+
+    pub fn memory_limit_exceeded(pages: u32, max: u32) -> Self {
+        MemoryLimitExceeded { pages, max }.build()
+    }
+
+    pub fn floats_rejected() -> Self {
+        FloatsRejected {}.build()
+    }
+
+    pub fn invalid_events<S: ToString>(msg: S) -> Self {
+        InvalidEvents {
+            msg: msg.to_string(),
+        }
+        .build()
+    }
+
+    pub fn event_limit_exceeded<S: Into<String>>(limit: S, value: usize, max: usize) -> Self {
+        EventLimitExceeded {
+            limit: limit.into(),
+            value,
+            max,
+        }
+        .build()
+    }
+
+    pub fn throttled<S: Into<String>>(reason: S) -> Self {
+        Throttled {
+            reason: reason.into(),
+        }
+        .build()
+    }
+
+    pub fn contract_paused<S: Into<String>>(code_id: S) -> Self {
+        ContractPaused {
+            code_id: code_id.into(),
+        }
+        .build()
+    }
+
+    pub fn call_denied<S: Into<String>, R: Into<String>>(entry_point: S, reason: R) -> Self {
+        CallDenied {
+            entry_point: entry_point.into(),
+            reason: reason.into(),
+        }
+        .build()
+    }
+
+    pub fn illegal_reentrancy<S: Into<String>, A: Into<String>>(call: S, active: A) -> Self {
+        IllegalReentrancy {
+            call: call.into(),
+            active: active.into(),
+        }
+        .build()
+    }
+
+    pub fn invalid_msg<S: Into<String>>(reason: S) -> Self {
+        InvalidMsg {
+            reason: reason.into(),
+        }
+        .build()
+    }
+
+    pub fn iterator_limit_exceeded(max: u32) -> Self {
+        IteratorLimitExceeded { max }.build()
+    }
+
+    pub fn tcb_outdated<S: Into<String>>(status: S, advisory_ids: &[String]) -> Self {
+        TcbOutdated {
+            status: status.into(),
+            advisory_ids: advisory_ids.to_vec(),
+        }
+        .build()
+    }
+
+    pub fn capability_denied<S: Into<String>, C: Into<String>>(code_id: S, capability: C) -> Self {
+        CapabilityDenied {
+            code_id: code_id.into(),
+            capability: capability.into(),
+        }
+        .build()
+    }
+
+    pub fn consensus_params_unset() -> Self {
+        ConsensusParamsUnset {}.build()
+    }
+}
+
+/// The version of the fixed messages below. Error messages can end up in transaction results and so in
+/// the app hash, so changing any of them is consensus breaking and must come with a new version.
+///
+/// Version 2 added the reasons of `Error::InvalidMsg`, see `msg_checks`.
+pub const ERROR_TEMPLATES_VERSION: u32 = 2;
+
+/// A fixed message for every kind of I/O error. The messages of OS errors come from the C library,
+/// so they differ between platforms (e.g. glibc and musl builds).
+fn io_error_template(err: &io::Error) -> &'static str {
+    match err.kind() {
+        io::ErrorKind::NotFound => "I/O error: not found",
+        io::ErrorKind::PermissionDenied => "I/O error: permission denied",
+        io::ErrorKind::AlreadyExists => "I/O error: already exists",
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => "I/O error: invalid data",
+        io::ErrorKind::UnexpectedEof => "I/O error: unexpected end of file",
+        io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+            "I/O error: interrupted"
+        }
+        _ => "I/O error",
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        debug!("Normalizing I/O error: {}", source);
+        Error::vm_err(io_error_template(&source))
+    }
 }
 
 impl From<VmError> for Error {
     fn from(source: VmError) -> Self {
         match source {
             VmError::GasDepletion => Error::out_of_gas(),
+            // the VM wraps the I/O errors of its cache
+            VmError::CacheErr { .. } => {
+                debug!("Normalizing cache error: {}", source);
+                Error::vm_err("Cache error: the code could not be stored or loaded")
+            }
             _ => Error::vm_err(source),
         }
     }
@@ -105,19 +448,53 @@ enum ErrnoValue {
     Success = 0,
     Other = 1,
     OutOfGas = 2,
+    CallDepthExceeded = 3,
+    ResponseTooLarge = 4,
+    UnsupportedFeatures = 5,
+    Timeout = 6,
+    Throttled = 7,
+    InvalidEvents = 8,
+    EventLimitExceeded = 9,
+    CallDenied = 10,
+    ContractPaused = 11,
+    IllegalReentrancy = 12,
+    IteratorLimitExceeded = 13,
+    InvalidMsg = 14,
+    TcbOutdated = 15,
+    CapabilityDenied = 16,
 }
 
 pub fn clear_error() {
     set_errno(Errno(ErrnoValue::Success as i32));
 }
 
+/// Writes the message of `err` to `errout` and sets errno. Host paths and source locations are
+/// replaced in the message (see `sanitize`), so only the log has the full message.
 pub fn set_error(err: Error, errout: Option<&mut Buffer>) {
     let msg = err.to_string();
     if let Some(mb) = errout {
-        *mb = Buffer::from_vec(msg.into_bytes());
+        let sanitized = sanitize(&msg);
+        if sanitized != msg {
+            info!("Returning sanitized error, original: {}", msg);
+        }
+        *mb = Buffer::from_vec(sanitized.into_owned().into_bytes());
     }
     let errno = match err {
         Error::OutOfGas { .. } => ErrnoValue::OutOfGas,
+        Error::CallDepthExceeded { .. } => ErrnoValue::CallDepthExceeded,
+        Error::ResponseTooLarge { .. } => ErrnoValue::ResponseTooLarge,
+        Error::UnsupportedFeatures { .. } => ErrnoValue::UnsupportedFeatures,
+        Error::Timeout { .. } => ErrnoValue::Timeout,
+        Error::Throttled { .. } => ErrnoValue::Throttled,
+        Error::InvalidEvents { .. } => ErrnoValue::InvalidEvents,
+        Error::EventLimitExceeded { .. } => ErrnoValue::EventLimitExceeded,
+        Error::CallDenied { .. } => ErrnoValue::CallDenied,
+        Error::ContractPaused { .. } => ErrnoValue::ContractPaused,
+        Error::IllegalReentrancy { .. } => ErrnoValue::IllegalReentrancy,
+        Error::IteratorLimitExceeded { .. } => ErrnoValue::IteratorLimitExceeded,
+        Error::InvalidMsg { .. } => ErrnoValue::InvalidMsg,
+        Error::TcbOutdated { .. } => ErrnoValue::TcbOutdated,
+        Error::CapabilityDenied { .. } => ErrnoValue::CapabilityDenied,
         _ => ErrnoValue::Other,
     } as i32;
     set_errno(Errno(errno));
@@ -144,7 +521,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cosmwasm_sgx_vm::FfiError;
+    use crate::vm::FfiError;
     use std::str;
 
     #[test]
@@ -214,8 +591,257 @@ mod tests {
         }
     }
 
+    #[test]
+    fn invalid_arg_works() {
+        let error = Error::invalid_arg("entropy", "must be 32 bytes long");
+        match error {
+            Error::InvalidArg { name, msg, .. } => {
+                assert_eq!(name, "entropy");
+                assert_eq!(msg, "must be 32 bytes long");
+            }
+            _ => panic!("expect different error"),
+        }
+    }
+
+    #[test]
+    fn call_depth_exceeded_works() {
+        let error = Error::call_depth_exceeded(11, 10);
+        match error {
+            Error::CallDepthExceeded { depth, max, .. } => {
+                assert_eq!(depth, 11);
+                assert_eq!(max, 10);
+            }
+            _ => panic!("expect different error"),
+        }
+    }
+
+    #[test]
+    fn response_too_large_works() {
+        let error = Error::response_too_large(2048, 1024);
+        match error {
+            Error::ResponseTooLarge { size, max, .. } => {
+                assert_eq!(size, 2048);
+                assert_eq!(max, 1024);
+            }
+            _ => panic!("expect different error"),
+        }
+    }
+
+    #[test]
+    fn memory_limit_exceeded_works() {
+        let error = Error::memory_limit_exceeded(300, 256);
+        assert_eq!(
+            error.to_string(),
+            "Contract memory exceeds the limit: 300 pages > 256 pages"
+        );
+    }
+
+    #[test]
+    fn floats_rejected_works() {
+        let error = Error::floats_rejected();
+        assert_eq!(
+            error.to_string(),
+            "Contract uses floats, which the float policy of this node rejects"
+        );
+    }
+
+    #[test]
+    fn unsupported_features_works() {
+        let set = |features: &[&str]| features.iter().map(|f| f.to_string()).collect();
+        let error = Error::unsupported_features(
+            &set(&["stargate", "staking", "ibc"]),
+            &set(&["staking", "iterator"]),
+        );
+        assert_eq!(
+            error.to_string(),
+            "Contract requires unsupported features: ibc, stargate (required: ibc, staking, stargate; supported: iterator, staking)"
+        );
+
+        let mut buf = Buffer::default();
+        set_error(error, Some(&mut buf));
+        unsafe { buf.consume() };
+        assert_eq!(errno::errno().0, ErrnoValue::UnsupportedFeatures as i32);
+    }
+
+    #[test]
+    fn timeout_works() {
+        let error = Error::timeout("init_bootstrap", 5000);
+        assert_eq!(
+            error.to_string(),
+            "Enclave call init_bootstrap timed out after 5000 ms"
+        );
+
+        let mut buf = Buffer::default();
+        set_error(error, Some(&mut buf));
+        unsafe { buf.consume() };
+        assert_eq!(errno::errno().0, ErrnoValue::Timeout as i32);
+    }
+
+    #[test]
+    fn throttled_works() {
+        let error = Error::throttled("more than 10 concurrent queries");
+        assert_eq!(
+            error.to_string(),
+            "Query throttled: more than 10 concurrent queries"
+        );
+
+        let mut buf = Buffer::default();
+        set_error(error, Some(&mut buf));
+        unsafe { buf.consume() };
+        assert_eq!(errno::errno().0, ErrnoValue::Throttled as i32);
+    }
+
+    #[test]
+    fn invalid_events_works() {
+        let error = Error::invalid_events("attribute 3 has no string key");
+        assert_eq!(
+            error.to_string(),
+            "Invalid contract events: attribute 3 has no string key"
+        );
+
+        let mut buf = Buffer::default();
+        set_error(error, Some(&mut buf));
+        unsafe { buf.consume() };
+        assert_eq!(errno::errno().0, ErrnoValue::InvalidEvents as i32);
+    }
+
+    #[test]
+    fn event_limit_exceeded_works() {
+        let error = Error::event_limit_exceeded("attributes", 120, 100);
+        assert_eq!(
+            error.to_string(),
+            "Event limit exceeded: attributes 120 > 100"
+        );
+
+        let mut buf = Buffer::default();
+        set_error(error, Some(&mut buf));
+        unsafe { buf.consume() };
+        assert_eq!(errno::errno().0, ErrnoValue::EventLimitExceeded as i32);
+    }
+
+    #[test]
+    fn contract_paused_works() {
+        let error = Error::contract_paused("aabb");
+        assert_eq!(
+            error.to_string(),
+            "Contract paused: the code aabb may not run"
+        );
+
+        let mut buf = Buffer::default();
+        set_error(error, Some(&mut buf));
+        unsafe { buf.consume() };
+        assert_eq!(errno::errno().0, ErrnoValue::ContractPaused as i32);
+    }
+
+    #[test]
+    fn call_denied_works() {
+        let error = Error::call_denied("handle", "the code is not on the allowlist");
+        assert_eq!(
+            error.to_string(),
+            "Call of handle denied: the code is not on the allowlist"
+        );
+
+        let mut buf = Buffer::default();
+        set_error(error, Some(&mut buf));
+        unsafe { buf.consume() };
+        assert_eq!(errno::errno().0, ErrnoValue::CallDenied as i32);
+    }
+
+    #[test]
+    fn illegal_reentrancy_works() {
+        let error = Error::illegal_reentrancy("handle", "query");
+        assert_eq!(
+            error.to_string(),
+            "Illegal reentrancy: handle called from within query"
+        );
+
+        let mut buf = Buffer::default();
+        set_error(error, Some(&mut buf));
+        unsafe { buf.consume() };
+        assert_eq!(errno::errno().0, ErrnoValue::IllegalReentrancy as i32);
+    }
+
+    #[test]
+    fn iterator_limit_exceeded_works() {
+        let error = Error::iterator_limit_exceeded(32);
+        assert_eq!(
+            error.to_string(),
+            "Iterator limit exceeded: more than 32 open iterators"
+        );
+
+        let mut buf = Buffer::default();
+        set_error(error, Some(&mut buf));
+        unsafe { buf.consume() };
+        assert_eq!(errno::errno().0, ErrnoValue::IteratorLimitExceeded as i32);
+    }
+
+    #[test]
+    fn invalid_msg_works() {
+        let error = Error::invalid_msg("the message is empty");
+        assert_eq!(error.to_string(), "Invalid message: the message is empty");
+
+        let mut buf = Buffer::default();
+        set_error(error, Some(&mut buf));
+        unsafe { buf.consume() };
+        assert_eq!(errno::errno().0, ErrnoValue::InvalidMsg as i32);
+    }
+
+    #[test]
+    fn tcb_outdated_works() {
+        let advisories = vec!["INTEL-SA-00219".to_string(), "INTEL-SA-00334".to_string()];
+        let error = Error::tcb_outdated("GROUP_OUT_OF_DATE", &advisories);
+        assert_eq!(
+            error.to_string(),
+            "TCB out of date: the quote status is GROUP_OUT_OF_DATE (advisories: INTEL-SA-00219, INTEL-SA-00334). Update the microcode and BIOS, then refresh the registration"
+        );
+
+        let mut buf = Buffer::default();
+        set_error(error, Some(&mut buf));
+        unsafe { buf.consume() };
+        assert_eq!(errno::errno().0, ErrnoValue::TcbOutdated as i32);
+    }
+
+    #[test]
+    fn capability_denied_works() {
+        let error = Error::capability_denied("2a2a", "iterators");
+        assert_eq!(
+            error.to_string(),
+            "Capability denied: the code 2a2a may not use iterators"
+        );
+
+        let mut buf = Buffer::default();
+        set_error(error, Some(&mut buf));
+        unsafe { buf.consume() };
+        assert_eq!(errno::errno().0, ErrnoValue::CapabilityDenied as i32);
+    }
+
+    #[test]
+    fn set_error_sanitizes_messages() {
+        let mut buf = Buffer::default();
+        let error = Error::vm_err("Error opening /home/node/.secretd/.compute/abc: not found");
+        set_error(error, Some(&mut buf));
+        let msg = unsafe { buf.consume() };
+        assert_eq!(
+            str::from_utf8(&msg).unwrap(),
+            "Execution error: Error opening <path>: not found"
+        );
+        assert_eq!(errno::errno().0, ErrnoValue::Other as i32);
+    }
+
     // Tests of `impl From<X> for Error` converters
 
+    #[test]
+    fn from_io_error_uses_templates() {
+        let os_error = io::Error::from_raw_os_error(2);
+        let error: Error = os_error.into();
+        match error {
+            Error::VmErr { msg, .. } => assert_eq!(msg, "I/O error: not found"),
+            _ => panic!("expect different error"),
+        }
+        let error: Error = io::Error::new(io::ErrorKind::Other, "disk on fire").into();
+        assert_eq!(error.to_string(), "Execution error: I/O error");
+    }
+
     #[test]
     fn from_std_str_utf8error_works() {
         let error: Error = str::from_utf8(b"Hello \xF0\x90\x80World")
@@ -0,0 +1,128 @@
+//! Scrubbing of host specific details from error messages.
+//!
+//! Error messages end up in transaction results, so everything in them becomes chain visible and must be the
+//! same on every validator. Host paths (data directories, the location of the enclave) and source locations
+//! of this library or the enclave reveal the node's setup and differ between builds, so `set_error` replaces
+//! them before the message crosses the FFI boundary. The original message is logged.
+
+use std::borrow::Cow;
+
+pub const PATH_PLACEHOLDER: &str = "<path>";
+pub const SOURCE_PLACEHOLDER: &str = "<source>";
+
+/// Characters which end a word besides whitespace, as they commonly surround paths in messages
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace()
+        || matches!(
+            c,
+            '"' | '\'' | '`' | '(' | ')' | '[' | ']' | '{' | '}' | ',' | ';'
+        )
+}
+
+/// Replaces the host paths and source locations in `msg`
+pub fn sanitize(msg: &str) -> Cow<'_, str> {
+    let mut out = String::new();
+    let mut rest = msg;
+    let mut changed = false;
+    while !rest.is_empty() {
+        let start = rest.find(|c: char| !is_delimiter(c)).unwrap_or(rest.len());
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(is_delimiter).unwrap_or(rest.len());
+        let word = &rest[..end];
+        rest = &rest[end..];
+
+        // keep punctuation ending a sentence or introducing the next part of the message
+        let trimmed = word.trim_end_matches(&[':', '.'][..]);
+        let replacement = if is_path(trimmed) {
+            Some(PATH_PLACEHOLDER)
+        } else if is_source_location(trimmed) {
+            Some(SOURCE_PLACEHOLDER)
+        } else {
+            None
+        };
+        match replacement {
+            Some(replacement) => {
+                changed = true;
+                out.push_str(replacement);
+                out.push_str(&word[trimmed.len()..]);
+            }
+            None => out.push_str(word),
+        }
+    }
+    if changed {
+        Cow::Owned(out)
+    } else {
+        Cow::Borrowed(msg)
+    }
+}
+
+/// Absolute and home relative paths with at least two components. A single `/` is too common in
+/// messages (e.g. "1/2" or "read/write") to be a path on its own.
+fn is_path(word: &str) -> bool {
+    match word.strip_prefix('/') {
+        Some(rest) => rest.contains('/'),
+        None => word.starts_with("~/") && word.len() > 2,
+    }
+}
+
+/// Rust source locations, like the ones in panic messages: `src/lib.rs:42` or `lib.rs:42:5`
+fn is_source_location(word: &str) -> bool {
+    match word.find(".rs:") {
+        Some(pos) => {
+            let line = &word[pos + 4..];
+            !line.is_empty() && line.chars().all(|c| c.is_ascii_digit() || c == ':')
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_replaces_paths() {
+        assert_eq!(
+            sanitize("Error opening /home/node/.secretd/.compute/state/wasm/abc: No such file"),
+            "Error opening <path>: No such file"
+        );
+        assert_eq!(
+            sanitize("failed to load enclave (\"/opt/secret/.sgx_secrets/librust_cosmwasm_enclave.signed.so\")"),
+            "failed to load enclave (\"<path>\")"
+        );
+        assert_eq!(
+            sanitize("cannot read ~/config.toml."),
+            "cannot read <path>."
+        );
+    }
+
+    #[test]
+    fn sanitize_replaces_source_locations() {
+        assert_eq!(
+            sanitize("panicked at 'overflow', src/gas_report.rs:42:5"),
+            "panicked at 'overflow', <source>"
+        );
+        assert_eq!(
+            sanitize("Enclave error at enclave/src/contract_operations.rs:118: bad key"),
+            "Enclave error at <source>: bad key"
+        );
+    }
+
+    #[test]
+    fn sanitize_keeps_other_messages() {
+        for msg in &[
+            "Invalid argument wasm: not a wasm module",
+            "read/write conflict in 1/2 of the calls",
+            "see https://docs.scrt.network/dev/contract.html",
+            "expected value at line 1 column 5",
+            "Ran out of gas",
+            "",
+        ] {
+            match sanitize(msg) {
+                Cow::Borrowed(same) => assert_eq!(same, *msg),
+                Cow::Owned(changed) => panic!("changed {:?} to {:?}", msg, changed),
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! A canonical form of the events contracts return, and limits on their size.
+//!
+//! Contracts return their events as the `log` of a successful result, a list of attributes with a `key` and
+//! a `value`. Without the `normalize_events` consensus param they reach Go as the contract wrote them, and every chain has to sanitize
+//! them on its own. With it, the log of `instantiate`, `handle` and `migrate` results is checked and
+//! rewritten here, the same way on every node:
+//!
+//! - The result must be UTF-8, and every attribute an object with a string `key` and `value`. Keys must not
+//!   be empty or contain control characters.
+//! - Attributes equal to an earlier one in all fields are dropped. The others keep the contract's order.
+//! - The result is serialized again, with the fields of every object in sorted order.
+//!
+//! Violations fail the call with `Error::InvalidEvents`. Failed results and results which are not JSON are
+//! returned untouched.
+//!
+//! Independently, the `event_limits` consensus param caps the number and size of the events in a result, after
+//! normalization. Every attribute with the key `contract_address`, like the ones internal calls insert before
+//! the log of a callee, starts a new event. Results over a limit fail with `Error::EventLimitExceeded`.
+
+use std::collections::HashSet;
+use std::str;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::Error;
+
+/// The key which starts the attributes of another contract in a log
+const CONTRACT_ADDRESS_KEY: &str = "contract_address";
+
+/// Caps on the events of a contract result. 0 disables a cap.
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventLimits {
+    /// How many events a result may have
+    pub max_events: usize,
+    /// How many attributes a result may have, in all its events
+    pub max_attributes: usize,
+    /// The longest key of an attribute, in bytes
+    pub max_key_len: usize,
+    /// The longest value of an attribute, in bytes
+    pub max_value_len: usize,
+}
+
+impl EventLimits {
+    fn is_unlimited(&self) -> bool {
+        *self == EventLimits::default()
+    }
+}
+
+/// The attributes in the log of the successful result `response`, if it is one
+fn log_of(response: &[u8]) -> Option<Vec<Value>> {
+    let result: Value = serde_json::from_slice(response).ok()?;
+    result.get("Ok")?.get("log")?.as_array().cloned()
+}
+
+fn check_limit(what: &str, value: usize, max: usize) -> Result<(), Error> {
+    if max != 0 && value > max {
+        return Err(Error::event_limit_exceeded(what, value, max));
+    }
+    Ok(())
+}
+
+/// Fails if the log of the contract result `response` exceeds `limits`
+pub fn check_limits(response: &[u8], limits: &EventLimits) -> Result<(), Error> {
+    if limits.is_unlimited() {
+        return Ok(());
+    }
+    let log = match log_of(response) {
+        Some(log) => log,
+        None => return Ok(()),
+    };
+    let mut events = 1;
+    for attribute in &log {
+        let key = attribute
+            .get("key")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let value = attribute
+            .get("value")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if key == CONTRACT_ADDRESS_KEY {
+            events += 1;
+        }
+        check_limit("key length", key.len(), limits.max_key_len)?;
+        check_limit("value length", value.len(), limits.max_value_len)?;
+    }
+    check_limit("events", events, limits.max_events)?;
+    check_limit("attributes", log.len(), limits.max_attributes)
+}
+
+/// Normalizes the log of the contract result `response`
+pub fn normalize(response: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if str::from_utf8(&response).is_err() {
+        return Err(Error::invalid_events("the result is not valid UTF-8"));
+    }
+    let mut result: Value = match serde_json::from_slice(&response) {
+        Ok(result) => result,
+        Err(_) => return Ok(response),
+    };
+    match result.get_mut("Ok").and_then(|ok| ok.get_mut("log")) {
+        Some(log) => *log = Value::Array(normalize_log(log)?),
+        None => return Ok(response),
+    }
+    serde_json::to_vec(&result).map_err(Error::vm_err)
+}
+
+fn normalize_log(log: &Value) -> Result<Vec<Value>, Error> {
+    let attributes = match log {
+        Value::Null => return Ok(Vec::new()),
+        Value::Array(attributes) => attributes,
+        _ => return Err(Error::invalid_events("the log is not a list of attributes")),
+    };
+    let mut seen = HashSet::new();
+    let mut normalized = Vec::with_capacity(attributes.len());
+    for (index, attribute) in attributes.iter().enumerate() {
+        check_attribute(index, attribute)?;
+        // objects serialize with sorted fields, so equal attributes serialize equally
+        if seen.insert(attribute.to_string()) {
+            normalized.push(attribute.clone());
+        }
+    }
+    Ok(normalized)
+}
+
+fn check_attribute(index: usize, attribute: &Value) -> Result<(), Error> {
+    let field = |name: &str| {
+        attribute.get(name).and_then(Value::as_str).ok_or_else(|| {
+            let msg = format!("attribute {} has no string {}", index, name);
+            Error::invalid_events(msg)
+        })
+    };
+    let key = field("key")?;
+    field("value")?;
+    if key.is_empty() || key.chars().any(char::is_control) {
+        let msg = format!(
+            "attribute {} has an empty key or one with control characters",
+            index
+        );
+        return Err(Error::invalid_events(msg));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(response: &str) -> Result<String, Error> {
+        normalize(response.as_bytes().to_vec()).map(|out| String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn normalize_drops_repeated_attributes() {
+        let response = r#"{"Ok":{"messages":[],"log":[{"value":"transfer","key":"action"},{"key":"amount","value":"5"},{"key":"action","value":"transfer"}],"data":null}}"#;
+        assert_eq!(
+            normalized(response).unwrap(),
+            r#"{"Ok":{"data":null,"log":[{"key":"action","value":"transfer"},{"key":"amount","value":"5"}],"messages":[]}}"#
+        );
+    }
+
+    #[test]
+    fn normalize_passes_other_results() {
+        let failed = r#"{"Err":{"generic_err":{"msg":"no"}}}"#;
+        assert_eq!(normalized(failed).unwrap(), failed);
+        assert_eq!(normalized("not json").unwrap(), "not json");
+        assert!(normalize(vec![b'"', 0xff, b'"']).is_err());
+    }
+
+    #[test]
+    fn normalize_rejects_invalid_attributes() {
+        let cases = [
+            r#"{"Ok":{"log":{"key":"action"}}}"#,
+            r#"{"Ok":{"log":[{"key":"action","value":7}]}}"#,
+            r#"{"Ok":{"log":[{"key":"","value":"transfer"}]}}"#,
+            r#"{"Ok":{"log":[{"key":"act\nion","value":"transfer"}]}}"#,
+        ];
+        for case in &cases {
+            match normalized(case).unwrap_err() {
+                Error::InvalidEvents { .. } => {}
+                e => panic!("unexpected error {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn check_limits_works() {
+        let response = br#"{"Ok":{"log":[{"key":"action","value":"transfer"},{"key":"contract_address","value":"secret1x"},{"key":"amount","value":"12345"}]}}"#;
+        let limits = |max_events, max_attributes, max_key_len, max_value_len| EventLimits {
+            max_events,
+            max_attributes,
+            max_key_len,
+            max_value_len,
+        };
+        check_limits(response, &EventLimits::default()).unwrap();
+        check_limits(response, &limits(2, 3, 16, 8)).unwrap();
+
+        let exceeded =
+            |limits: EventLimits| check_limits(response, &limits).unwrap_err().to_string();
+        assert_eq!(
+            exceeded(limits(1, 0, 0, 0)),
+            "Event limit exceeded: events 2 > 1"
+        );
+        assert_eq!(
+            exceeded(limits(0, 2, 0, 0)),
+            "Event limit exceeded: attributes 3 > 2"
+        );
+        assert_eq!(
+            exceeded(limits(0, 0, 8, 0)),
+            "Event limit exceeded: key length 16 > 8"
+        );
+        assert_eq!(
+            exceeded(limits(0, 0, 0, 4)),
+            "Event limit exceeded: value length 8 > 4"
+        );
+
+        let failed = br#"{"Err":{"generic_err":{"msg":"no"}}}"#;
+        check_limits(failed, &limits(1, 1, 1, 1)).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+//! The thread running the calls started by `handle_async` and `query_async`.
+//!
+//! Calls are queued and run by a single worker thread, one at a time and in the order they were started, so Go
+//! need not block a thread of its own for each of them. The calls of a cache could not run at the same time
+//! anyway, as they take its lock, see `CacheCell`, and set the VM settings of their own. A call must not wait for
+//! another call of the executor, which only starts once it is done.
+//!
+//! Go sets the queue limit with `set_executor_options`. Calls over the limit fail at once with `Error::Throttled`.
+//! `get_executor_stats` returns the depth of the queue and the calls run so far, for the metrics of the node.
+
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// How calls are queued, see `set_executor_options`
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutorOptions {
+    /// How many calls may wait for the worker, 0 for no limit
+    pub max_queued: u32,
+}
+
+impl ExecutorOptions {
+    pub fn from_json(options: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(options).map_err(Error::invalid_options)
+    }
+}
+
+/// The state of the executor, see `get_executor_stats`
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ExecutorStats {
+    /// The calls being run, at most 1
+    pub running: u32,
+    /// The calls waiting for the worker
+    pub queued: u32,
+    /// The calls run since the library was loaded
+    pub completed: u64,
+    /// The calls which failed because the queue was full
+    pub rejected: u64,
+}
+
+#[derive(Default)]
+struct State {
+    options: ExecutorOptions,
+    /// Whether the worker thread was started
+    started: bool,
+    running: bool,
+    /// The queued calls, oldest first
+    queue: VecDeque<Job>,
+    completed: u64,
+    rejected: u64,
+}
+
+impl State {
+    fn push(&mut self, job: Job) -> Result<(), Error> {
+        let max_queued = self.options.max_queued;
+        if max_queued != 0 && self.queue.len() >= max_queued as usize {
+            self.rejected += 1;
+            return Err(Error::throttled(format!(
+                "more than {} queued calls",
+                max_queued
+            )));
+        }
+        self.queue.push_back(job);
+        Ok(())
+    }
+
+    fn stats(&self) -> ExecutorStats {
+        ExecutorStats {
+            running: self.running as u32,
+            queued: self.queue.len() as u32,
+            completed: self.completed,
+            rejected: self.rejected,
+        }
+    }
+}
+
+struct Executor {
+    state: Mutex<State>,
+    /// Signalled when a call is queued
+    ready: Condvar,
+}
+
+impl Executor {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // a panic while holding the lock does not leave the state inconsistent
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn submit(&'static self, job: Job) -> Result<(), Error> {
+        let mut state = self.lock();
+        if !state.started {
+            // the worker waits for the lock, and finds the call queued
+            thread::Builder::new()
+                .name("executor".to_string())
+                .spawn(move || self.work())?;
+            state.started = true;
+        }
+        state.push(job)?;
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    fn work(&self) {
+        let mut state = self.lock();
+        loop {
+            let job = match state.queue.pop_front() {
+                Some(job) => job,
+                None => {
+                    state = self
+                        .ready
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                    continue;
+                }
+            };
+            state.running = true;
+            drop(state);
+            // jobs catch their own panics, this only keeps the worker alive
+            let _ = catch_unwind(AssertUnwindSafe(job));
+            state = self.lock();
+            state.running = false;
+            state.completed += 1;
+        }
+    }
+}
+
+lazy_static! {
+    static ref EXECUTOR: Executor = Executor {
+        state: Mutex::new(State::default()),
+        ready: Condvar::new(),
+    };
+}
+
+/// Queues `job` behind the calls started before, or fails with `Error::Throttled` if the queue is full
+pub fn submit<F>(job: F) -> Result<(), Error>
+where
+    F: FnOnce() + Send + 'static,
+{
+    EXECUTOR.submit(Box::new(job))
+}
+
+pub fn set_options(options: ExecutorOptions) {
+    EXECUTOR.lock().options = options;
+}
+
+pub fn stats() -> ExecutorStats {
+    EXECUTOR.lock().stats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    fn state(max_queued: u32) -> State {
+        State {
+            options: ExecutorOptions { max_queued },
+            ..State::default()
+        }
+    }
+
+    #[test]
+    fn options_from_json() {
+        assert_eq!(
+            ExecutorOptions::from_json(br#"{"max_queued":16}"#).unwrap(),
+            ExecutorOptions { max_queued: 16 }
+        );
+        assert_eq!(
+            ExecutorOptions::from_json(b"{}").unwrap(),
+            ExecutorOptions::default()
+        );
+        assert!(ExecutorOptions::from_json(br#"{"workers":4}"#).is_err());
+    }
+
+    #[test]
+    fn state_rejects_calls_over_the_limit() {
+        let mut state = state(2);
+        state.push(Box::new(|| {})).unwrap();
+        state.push(Box::new(|| {})).unwrap();
+        match state.push(Box::new(|| {})) {
+            Err(Error::Throttled { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert_eq!(state.stats().rejected, 1);
+        assert_eq!(state.stats().queued, 2);
+    }
+
+    #[test]
+    fn submit_runs_jobs_in_order() {
+        let (tx, rx) = channel();
+        for i in 0..10 {
+            let tx = tx.clone();
+            submit(move || tx.send(i).unwrap()).unwrap();
+        }
+        let done: Vec<i32> = rx.iter().take(10).collect();
+        assert_eq!(done, (0..10).collect::<Vec<_>>());
+
+        // a panicking job does not take the worker down
+        submit(|| panic!("boom")).unwrap();
+        submit(move || tx.send(10).unwrap()).unwrap();
+        assert_eq!(rx.recv().unwrap(), 10);
+        assert!(stats().completed >= 11);
+    }
+}
@@ -0,0 +1,81 @@
+//! Whether contracts may use floats.
+//!
+//! Float operations are deterministic in wasm except for the bits of the NaNs they produce, which differ
+//! between CPUs and compilers. Chains settle this differently: some reject codes using floats, some let the
+//! VM replace every NaN an operation produces by the canonical one, and some accept the risk. The chain picks
+//! its policy with the `float_policy` consensus param. Codes are checked when they are stored, and again before
+//! they are instantiated, as codes stored under another policy stay in the cache.
+
+use serde::Deserialize;
+
+use crate::error::Error;
+
+/// How contracts using floats are treated
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FloatPolicy {
+    /// Reject codes with any float value or operation
+    Reject,
+    /// Allow floats, with the VM canonicalizing the NaNs float operations produce. Requires the enclave VM.
+    CanonicalizeNan,
+    /// Allow floats as they are, leaving the bits of NaNs to the CPU
+    Allow,
+}
+
+impl Default for FloatPolicy {
+    fn default() -> Self {
+        FloatPolicy::Allow
+    }
+}
+
+impl FloatPolicy {
+    /// Fails if the policy rejects a code. `uses_floats` is the result of analyzing the code, see `analysis`,
+    /// or None if it cannot be analyzed. Such codes are rejected by `Reject`, as they might use floats.
+    pub fn check(self, uses_floats: Option<bool>) -> Result<(), Error> {
+        if self != FloatPolicy::Reject {
+            return Ok(());
+        }
+        match uses_floats {
+            Some(false) => Ok(()),
+            Some(true) => Err(Error::floats_rejected()),
+            None => Err(Error::invalid_arg(
+                "wasm",
+                "cannot tell whether the code uses floats, as it cannot be analyzed",
+            )),
+        }
+    }
+
+    /// Whether the VM must canonicalize NaNs
+    pub fn canonicalize_nans(self) -> bool {
+        self == FloatPolicy::CanonicalizeNan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_works() {
+        assert!(FloatPolicy::Reject.check(Some(false)).is_ok());
+        match FloatPolicy::Reject.check(Some(true)).unwrap_err() {
+            Error::FloatsRejected { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert!(FloatPolicy::Reject.check(None).is_err());
+
+        for policy in &[FloatPolicy::CanonicalizeNan, FloatPolicy::Allow] {
+            assert!(policy.check(Some(true)).is_ok());
+            assert!(policy.check(None).is_ok());
+        }
+    }
+
+    #[test]
+    fn policy_parses_from_json() {
+        let policy: FloatPolicy = serde_json::from_str(r#""canonicalize_nan""#).unwrap();
+        assert_eq!(policy, FloatPolicy::CanonicalizeNan);
+        assert!(policy.canonicalize_nans());
+        assert!(!FloatPolicy::default().canonicalize_nans());
+        assert!(serde_json::from_str::<FloatPolicy>(r#""deny""#).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+//! Gas checkpoints, which carry the gas of a transaction across the contract calls of its submessages.
+//!
+//! The gas a call consumed is the `gas_used` returned by the library plus the gas its storage and querier
+//! callbacks reported (`GasReport::used_externally`), which the VM subtracted from the limit too. Go folds
+//! every finished call into an opaque checkpoint token with `export_gas_checkpoint`, and reads the limit for
+//! the next call of the same transaction from it with `import_gas_checkpoint`, instead of computing it by hand.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::gas_report::GasReport;
+
+/// The version of the token format. Tokens of other versions are rejected.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+const TOKEN_ARG: &str = "checkpoint";
+
+/// What Go learns from a checkpoint token
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct GasCheckpointInfo {
+    /// The gas limit of the whole transaction, as passed to the first call
+    pub gas_limit: u64,
+    /// The gas consumed by all calls folded into the checkpoint
+    pub consumed: u64,
+    /// The gas limit for the next call
+    pub remaining: u64,
+    /// The number of calls folded into the checkpoint
+    pub calls: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Checkpoint {
+    version: u32,
+    gas_limit: u64,
+    consumed: u64,
+    calls: u32,
+    /// The sum of the reports of all calls
+    report: GasReport,
+}
+
+impl GasReport {
+    fn merge(&mut self, other: &GasReport) {
+        self.used_externally = self.used_externally.saturating_add(other.used_externally);
+        self.refundable = self.refundable.saturating_add(other.refundable);
+        self.reads = self.reads.saturating_add(other.reads);
+        self.writes = self.writes.saturating_add(other.writes);
+        self.iterator_steps = self.iterator_steps.saturating_add(other.iterator_steps);
+        self.queries = self.queries.saturating_add(other.queries);
+        self.api_calls = self.api_calls.saturating_add(other.api_calls);
+        self.fallback = self.fallback.saturating_add(other.fallback);
+        self.schedule_version = other.schedule_version;
+        self.peak_memory_pages = self.peak_memory_pages.max(other.peak_memory_pages);
+        self.host_calls.add(&other.host_calls);
+    }
+}
+
+fn parse(token: &[u8]) -> Result<Checkpoint, Error> {
+    let checkpoint: Checkpoint =
+        serde_json::from_slice(token).map_err(|e| Error::invalid_arg(TOKEN_ARG, e))?;
+    if checkpoint.version != CHECKPOINT_VERSION {
+        let msg = format!("unsupported version {}", checkpoint.version);
+        return Err(Error::invalid_arg(TOKEN_ARG, msg));
+    }
+    Ok(checkpoint)
+}
+
+/// Adds a finished call to `previous`, or starts a checkpoint for a transaction with `gas_limit`
+/// if there is none. Fails with out of gas once the transaction consumed more than its limit.
+pub fn export(
+    previous: Option<&[u8]>,
+    gas_limit: u64,
+    gas_used: u64,
+    report: &GasReport,
+) -> Result<Vec<u8>, Error> {
+    let mut checkpoint = match previous {
+        Some(token) => parse(token)?,
+        None => Checkpoint {
+            version: CHECKPOINT_VERSION,
+            gas_limit,
+            consumed: 0,
+            calls: 0,
+            report: GasReport::default(),
+        },
+    };
+    checkpoint.consumed = checkpoint
+        .consumed
+        .saturating_add(gas_used)
+        .saturating_add(report.used_externally);
+    checkpoint.calls = checkpoint.calls.saturating_add(1);
+    checkpoint.report.merge(report);
+    if checkpoint.consumed > checkpoint.gas_limit {
+        return Err(Error::out_of_gas());
+    }
+    serde_json::to_vec(&checkpoint).map_err(Error::vm_err)
+}
+
+pub fn import(token: &[u8]) -> Result<GasCheckpointInfo, Error> {
+    let checkpoint = parse(token)?;
+    Ok(GasCheckpointInfo {
+        gas_limit: checkpoint.gas_limit,
+        consumed: checkpoint.consumed,
+        remaining: checkpoint.gas_limit - checkpoint.consumed.min(checkpoint.gas_limit),
+        calls: checkpoint.calls,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(used_externally: u64, api_calls: u64) -> GasReport {
+        GasReport {
+            used_externally,
+            api_calls,
+            ..GasReport::default()
+        }
+    }
+
+    #[test]
+    fn checkpoints_accumulate_calls() {
+        let token = export(None, 1000, 100, &report(50, 7)).unwrap();
+        let info = import(&token).unwrap();
+        assert_eq!(
+            info,
+            GasCheckpointInfo {
+                gas_limit: 1000,
+                consumed: 150,
+                remaining: 850,
+                calls: 1,
+            }
+        );
+
+        // the limit of later calls is ignored, the transaction keeps its limit
+        let token = export(Some(&token), 850, 200, &report(100, 3)).unwrap();
+        let info = import(&token).unwrap();
+        assert_eq!(info.gas_limit, 1000);
+        assert_eq!(info.consumed, 450);
+        assert_eq!(info.remaining, 550);
+        assert_eq!(info.calls, 2);
+        assert_eq!(parse(&token).unwrap().report.api_calls, 10);
+    }
+
+    #[test]
+    fn export_fails_beyond_the_limit() {
+        let token = export(None, 1000, 900, &report(100, 0)).unwrap();
+        assert_eq!(import(&token).unwrap().remaining, 0);
+        match export(Some(&token), 0, 1, &GasReport::default()).unwrap_err() {
+            Error::OutOfGas { .. } => {}
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn import_rejects_invalid_tokens() {
+        match import(b"{}").unwrap_err() {
+            Error::InvalidArg { name, .. } => assert_eq!(name, TOKEN_ARG),
+            e => panic!("unexpected error: {}", e),
+        }
+        let token = export(None, 1000, 1, &GasReport::default()).unwrap();
+        let token = String::from_utf8(token)
+            .unwrap()
+            .replace("\"version\":1", "\"version\":2");
+        match import(token.as_bytes()).unwrap_err() {
+            Error::InvalidArg { msg, .. } => assert_eq!(msg, "unsupported version 2"),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
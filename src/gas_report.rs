@@ -0,0 +1,410 @@
+//! Breaks down the gas used by the Go callbacks of a contract call.
+//!
+//! The VM only knows the sum of the gas reported by the callbacks. Every callback additionally records its
+//! gas here, in the report of the innermost call running on this thread, which is handed to Go at the end
+//! of the call. This is also where the node's `GasPolicy` is applied to the reported gas, and where Go is
+//! notified of the gas a call has charged so far (see `set_gas_sync`).
+
+use std::cell::Cell;
+
+use serde::{Deserialize, Serialize};
+
+use log::*;
+
+use crate::db::DB;
+use crate::gas_schedule::{self, FallbackGasSchedule, GasPolicy};
+use crate::host_calls::{HostCall, HostCallCounts};
+use crate::vm::GasInfo;
+
+/// Details of the gas used by a contract call, on top of the total reported in `gas_used`
+#[repr(C)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+pub struct GasReport {
+    /// The gas reported by the storage and querier callbacks
+    pub used_externally: u64,
+    /// The part of `used_externally` spent on deleting storage entries, which the chain may refund
+    pub refundable: u64,
+    /// The part of `used_externally` spent on reading storage entries
+    pub reads: u64,
+    /// The part of `used_externally` spent on writing storage entries
+    pub writes: u64,
+    /// The part of `used_externally` spent on opening and advancing storage iterators
+    pub iterator_steps: u64,
+    /// The part of `used_externally` spent on queries to other modules and contracts
+    pub queries: u64,
+    /// The gas reported by the address conversion callbacks. The VM charges it like wasm execution,
+    /// so it is not part of `used_externally`.
+    pub api_calls: u64,
+    /// The gas charged from the fallback schedule on top of the gas reported by the callbacks.
+    /// It is part of `gas_used`.
+    pub fallback: u64,
+    /// The version of the wasm cost table the VM charged execution with, see `wasm_costs`
+    #[serde(default)]
+    pub schedule_version: u64,
+    /// The most memory an instance of the call had, in 64 KiB pages, including the instances of its internal
+    /// calls, see `max_memory_pages`
+    #[serde(default)]
+    pub peak_memory_pages: u32,
+    /// How often the call and its internal calls invoked each host import, see `host_calls`
+    #[serde(default)]
+    pub host_calls: HostCallCounts,
+}
+
+/// The kind of callback that reported gas
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GasCategory {
+    Read,
+    Write,
+    Remove,
+    Iterator,
+    Query,
+    Api,
+}
+
+/// Where and how often the gas charged by a call is reported to Go
+#[derive(Copy, Clone)]
+struct GasSync {
+    db: DB,
+    interval: u64,
+    /// The total of the last notification
+    notified: u64,
+}
+
+/// The state of the contract call running on this thread
+#[derive(Copy, Clone, Default)]
+struct CallGas {
+    report: GasReport,
+    policy: GasPolicy,
+    schedule: FallbackGasSchedule,
+    sync: Option<GasSync>,
+}
+
+thread_local! {
+    static CURRENT_CALL: Cell<Option<CallGas>> = Cell::new(None);
+}
+
+/// Collects the gas report of a contract call on this thread until it is dropped
+pub struct GasTracker {
+    previous: Option<CallGas>,
+}
+
+impl GasTracker {
+    pub fn start() -> Self {
+        let previous = CURRENT_CALL.with(|current| current.replace(Some(CallGas::default())));
+        GasTracker { previous }
+    }
+
+    /// The report collected so far
+    pub fn report(&self) -> GasReport {
+        current_report()
+    }
+
+    /// Writes the collected report to `out`, if Go asked for it
+    pub fn write_to(self, out: Option<&mut GasReport>) {
+        if let Some(out) = out {
+            *out = self.report();
+        }
+    }
+}
+
+/// The report collected so far by the contract call running on this thread, if any
+pub fn current_report() -> GasReport {
+    CURRENT_CALL
+        .with(|current| current.get())
+        .map(|call| call.report)
+        .unwrap_or_default()
+}
+
+impl Drop for GasTracker {
+    fn drop(&mut self) {
+        CURRENT_CALL.with(|current| current.set(self.previous));
+    }
+}
+
+impl GasReport {
+    /// The gas charged to the call through its callbacks: the storage and querier gas, the gas of the
+    /// api calls and the fallback gas
+    pub fn charged(&self) -> u64 {
+        self.used_externally
+            .saturating_add(self.api_calls)
+            .saturating_add(self.fallback)
+    }
+
+    fn add(&mut self, category: GasCategory, used_gas: u64) {
+        let total = match category {
+            GasCategory::Read => &mut self.reads,
+            GasCategory::Write => &mut self.writes,
+            GasCategory::Remove => &mut self.refundable,
+            GasCategory::Iterator => &mut self.iterator_steps,
+            GasCategory::Query => &mut self.queries,
+            GasCategory::Api => {
+                self.api_calls = self.api_calls.saturating_add(used_gas);
+                return;
+            }
+        };
+        *total = total.saturating_add(used_gas);
+        self.used_externally = self.used_externally.saturating_add(used_gas);
+    }
+}
+
+/// Charges the running call according to `policy` from now on
+pub fn set_gas_policy(policy: GasPolicy, schedule: FallbackGasSchedule) {
+    CURRENT_CALL.with(|current| {
+        if let Some(mut call) = current.get() {
+            call.policy = policy;
+            call.schedule = schedule;
+            current.set(Some(call));
+        }
+    });
+}
+
+/// Reports the version of the wasm cost table the running call is charged with, see `wasm_costs`
+pub fn set_schedule_version(version: u64) {
+    CURRENT_CALL.with(|current| {
+        if let Some(mut call) = current.get() {
+            call.report.schedule_version = version;
+            current.set(Some(call));
+        }
+    });
+}
+
+/// Counts an invocation of a host import in the running call, and records the gas it reported like `record`
+pub fn record_host_call(call: HostCall, used_gas: u64) -> GasInfo {
+    CURRENT_CALL.with(|current| {
+        if let Some(mut running) = current.get() {
+            running.report.host_calls.count(call);
+            current.set(Some(running));
+        }
+    });
+    record(call.category(), used_gas)
+}
+
+/// Reports the memory of an instance of the running call, keeping the largest, see `code_stats`
+pub fn record_memory_pages(pages: u32) {
+    CURRENT_CALL.with(|current| {
+        if let Some(mut call) = current.get() {
+            call.report.peak_memory_pages = call.report.peak_memory_pages.max(pages);
+            current.set(Some(call));
+        }
+    });
+}
+
+/// Notifies Go through the `gas_consumed` callback of `db` whenever the gas charged by the running call
+/// grew by `interval` since the last notification. An interval of 0 disables the notifications.
+///
+/// The VM does not expose the gas of wasm execution while it runs, so only the gas charged through the
+/// callbacks is reported. It still lets Go follow long running calls, and stop calls whose callbacks
+/// would exhaust its gas meter before they return.
+pub fn set_gas_sync(db: DB, interval: u64) {
+    if interval == 0 || db.vtable.gas_consumed.is_none() {
+        return;
+    }
+    CURRENT_CALL.with(|current| {
+        if let Some(mut call) = current.get() {
+            call.sync = Some(GasSync {
+                db,
+                interval,
+                notified: 0,
+            });
+            current.set(Some(call));
+        }
+    });
+}
+
+/// Records gas reported by a callback in the report of the running call, if any,
+/// and returns the gas info to charge for the callback
+pub fn record(category: GasCategory, used_gas: u64) -> GasInfo {
+    let (surcharge, sync) = CURRENT_CALL.with(|current| match current.get() {
+        Some(mut call) => {
+            let surcharge = call.policy.surcharge(&call.schedule, category, used_gas);
+            call.report.add(category, used_gas);
+            call.report.fallback = call.report.fallback.saturating_add(surcharge);
+            // notify outside of this closure, as Go may start another call on this thread
+            let total = call.report.charged();
+            let sync = match &mut call.sync {
+                Some(sync) if total.saturating_sub(sync.notified) >= sync.interval => {
+                    sync.notified = total;
+                    Some((sync.db, total))
+                }
+                _ => None,
+            };
+            current.set(Some(call));
+            (surcharge, sync)
+        }
+        None => (0, None),
+    });
+    let surcharge = match sync {
+        Some((db, total)) => match db.notify_gas_consumed(total) {
+            Ok(true) => surcharge,
+            // charge everything, so the VM fails the call with out of gas as soon as the callback returns
+            Ok(false) => u64::MAX,
+            Err(e) => {
+                warn!("Ignoring failed gas notification: {}", e);
+                surcharge
+            }
+        },
+        None => surcharge,
+    };
+    gas_schedule::gas_info(category, used_gas, surcharge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::db::db_t;
+    use crate::error::GoResult;
+    use crate::gas_meter::gas_meter_t;
+    use crate::memory::Buffer;
+    use crate::testing::failing_db;
+
+    #[test]
+    fn nothing_is_recorded_outside_of_calls() {
+        record(GasCategory::Read, 5);
+        let tracker = GasTracker::start();
+        assert_eq!(tracker.report(), GasReport::default());
+    }
+
+    #[test]
+    fn removals_are_refundable() {
+        let tracker = GasTracker::start();
+        record(GasCategory::Write, 5);
+        record(GasCategory::Remove, 3);
+        let report = tracker.report();
+        assert_eq!(report.used_externally, 8);
+        assert_eq!(report.refundable, 3);
+    }
+
+    #[test]
+    fn categories_are_tracked_separately() {
+        let tracker = GasTracker::start();
+        record(GasCategory::Read, 1);
+        record(GasCategory::Read, 1);
+        record(GasCategory::Write, 3);
+        record(GasCategory::Iterator, 4);
+        record(GasCategory::Query, 5);
+        record(GasCategory::Api, 6);
+        assert_eq!(
+            tracker.report(),
+            GasReport {
+                used_externally: 14,
+                refundable: 0,
+                reads: 2,
+                writes: 3,
+                iterator_steps: 4,
+                queries: 5,
+                api_calls: 6,
+                fallback: 0,
+                schedule_version: 0,
+                peak_memory_pages: 0,
+                host_calls: HostCallCounts::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn host_calls_are_counted() {
+        let tracker = GasTracker::start();
+        record_host_call(HostCall::DbRead, 3);
+        record_host_call(HostCall::DbRead, 3);
+        record_host_call(HostCall::DbScan, 1);
+        let report = tracker.report();
+        assert_eq!(report.reads, 6);
+        assert_eq!(report.iterator_steps, 1);
+        assert_eq!(report.host_calls.db_read, 2);
+        assert_eq!(report.host_calls.db_scan, 1);
+    }
+
+    #[test]
+    fn peak_memory_keeps_the_largest_instance() {
+        let tracker = GasTracker::start();
+        record_memory_pages(17);
+        record_memory_pages(40);
+        record_memory_pages(2);
+        assert_eq!(tracker.report().peak_memory_pages, 40);
+    }
+
+    #[test]
+    fn nested_calls_have_own_report() {
+        let outer = GasTracker::start();
+        record(GasCategory::Remove, 3);
+        let inner = GasTracker::start();
+        set_gas_policy(GasPolicy::MaxOfBoth, FallbackGasSchedule::default());
+        record(GasCategory::Read, 5);
+        assert_eq!(inner.report().used_externally, 5);
+        drop(inner);
+        assert_eq!(outer.report().used_externally, 3);
+        assert_eq!(outer.report().fallback, 0);
+    }
+
+    #[test]
+    fn record_applies_gas_policy() {
+        let tracker = GasTracker::start();
+        assert_eq!(record(GasCategory::Read, 0).cost, 0);
+        set_gas_policy(GasPolicy::Fallback, FallbackGasSchedule::default());
+        let info = record(GasCategory::Read, 0);
+        assert_eq!(info.cost, 1000);
+        assert_eq!(info.externally_used, 0);
+        assert_eq!(tracker.report().fallback, 1000);
+    }
+
+    thread_local! {
+        static NOTIFIED: std::cell::RefCell<Vec<u64>> = std::cell::RefCell::new(Vec::new());
+    }
+
+    /// Records the notifications and runs out of gas above 100
+    extern "C" fn gas_consumed(
+        _: *mut db_t,
+        _: *mut gas_meter_t,
+        total: u64,
+        _: *mut Buffer,
+    ) -> i32 {
+        NOTIFIED.with(|notified| notified.borrow_mut().push(total));
+        if total > 100 {
+            GoResult::OutOfGas as i32
+        } else {
+            GoResult::Ok as i32
+        }
+    }
+
+    fn notifying_db() -> DB {
+        let mut db = failing_db();
+        db.vtable.gas_consumed = Some(gas_consumed);
+        db
+    }
+
+    #[test]
+    fn gas_sync_notifies_at_intervals() {
+        let _tracker = GasTracker::start();
+        set_gas_sync(notifying_db(), 30);
+        record(GasCategory::Read, 20);
+        record(GasCategory::Write, 20);
+        record(GasCategory::Api, 5);
+        record(GasCategory::Read, 10);
+        let info = record(GasCategory::Read, 25);
+        assert_eq!(info.cost, 0);
+        assert_eq!(NOTIFIED.with(|n| n.borrow().clone()), vec![40, 80]);
+
+        // Go ends the call by running out of gas
+        let info = record(GasCategory::Write, 40);
+        assert_eq!(info.cost, u64::MAX);
+        assert_eq!(info.externally_used, 40);
+    }
+
+    #[test]
+    fn gas_sync_can_be_disabled() {
+        let _tracker = GasTracker::start();
+        set_gas_sync(notifying_db(), 0);
+        record(GasCategory::Write, 500);
+        assert!(NOTIFIED.with(|n| n.borrow().is_empty()));
+    }
+
+    #[test]
+    fn write_to_works() {
+        let tracker = GasTracker::start();
+        record(GasCategory::Remove, 2);
+        let mut report = GasReport::default();
+        tracker.write_to(Some(&mut report));
+        assert_eq!(report.refundable, 2);
+    }
+}
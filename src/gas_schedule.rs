@@ -0,0 +1,147 @@
+//! Gas for callbacks which do not meter themselves.
+//!
+//! Older Go wrappers report zero gas from their callbacks, so contracts could read and write storage
+//! for free. The node decides at `init_cache` whether to trust the gas reported by Go, or to charge
+//! from the fallback schedule below. Gas charged on top of what Go reported is added to the gas used
+//! by the contract, so Go charges it along with the execution costs.
+
+use serde::Deserialize;
+
+use crate::gas_report::GasCategory;
+use crate::vm::GasInfo;
+
+/// How the gas reported by Go callbacks is charged
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GasPolicy {
+    /// Charge exactly what Go reports, even if that is nothing
+    TrustGo,
+    /// Charge from the fallback schedule whenever Go reports zero gas
+    Fallback,
+    /// Charge the larger of the Go reported gas and the fallback schedule
+    MaxOfBoth,
+}
+
+impl Default for GasPolicy {
+    fn default() -> Self {
+        GasPolicy::TrustGo
+    }
+}
+
+/// The gas charged per callback by the `Fallback` and `MaxOfBoth` policies
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FallbackGasSchedule {
+    pub read: u64,
+    pub write: u64,
+    pub remove: u64,
+    pub iterator_step: u64,
+    pub query: u64,
+    pub api_call: u64,
+}
+
+impl Default for FallbackGasSchedule {
+    /// The flat costs of the Cosmos SDK's default KV store gas config
+    fn default() -> Self {
+        FallbackGasSchedule {
+            read: 1000,
+            write: 2000,
+            remove: 1000,
+            iterator_step: 30,
+            query: 1000,
+            api_call: 1000,
+        }
+    }
+}
+
+impl FallbackGasSchedule {
+    fn cost(&self, category: GasCategory) -> u64 {
+        match category {
+            GasCategory::Read => self.read,
+            GasCategory::Write => self.write,
+            GasCategory::Remove => self.remove,
+            GasCategory::Iterator => self.iterator_step,
+            GasCategory::Query => self.query,
+            GasCategory::Api => self.api_call,
+        }
+    }
+}
+
+impl GasPolicy {
+    /// The gas to charge on top of the `used_gas` reported by Go
+    pub fn surcharge(
+        self,
+        schedule: &FallbackGasSchedule,
+        category: GasCategory,
+        used_gas: u64,
+    ) -> u64 {
+        match self {
+            GasPolicy::TrustGo => 0,
+            GasPolicy::Fallback if used_gas == 0 => schedule.cost(category),
+            GasPolicy::Fallback => 0,
+            GasPolicy::MaxOfBoth => schedule.cost(category).saturating_sub(used_gas),
+        }
+    }
+}
+
+/// The gas info of a callback which reported `used_gas`, plus `surcharge`
+pub fn gas_info(category: GasCategory, used_gas: u64, surcharge: u64) -> GasInfo {
+    match category {
+        // The VM charges the gas of api calls like execution
+        GasCategory::Api => GasInfo::with_cost(used_gas.saturating_add(surcharge)),
+        _ => GasInfo {
+            cost: surcharge,
+            externally_used: used_gas,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trust_go_never_surcharges() {
+        let schedule = FallbackGasSchedule::default();
+        assert_eq!(
+            GasPolicy::TrustGo.surcharge(&schedule, GasCategory::Read, 0),
+            0
+        );
+    }
+
+    #[test]
+    fn fallback_applies_to_unmetered_callbacks() {
+        let schedule = FallbackGasSchedule::default();
+        assert_eq!(
+            GasPolicy::Fallback.surcharge(&schedule, GasCategory::Write, 0),
+            2000
+        );
+        assert_eq!(
+            GasPolicy::Fallback.surcharge(&schedule, GasCategory::Write, 1),
+            0
+        );
+    }
+
+    #[test]
+    fn max_of_both_tops_up() {
+        let schedule = FallbackGasSchedule::default();
+        assert_eq!(
+            GasPolicy::MaxOfBoth.surcharge(&schedule, GasCategory::Read, 400),
+            600
+        );
+        assert_eq!(
+            GasPolicy::MaxOfBoth.surcharge(&schedule, GasCategory::Read, 4000),
+            0
+        );
+    }
+
+    #[test]
+    fn gas_info_charges_surcharge_as_cost() {
+        let info = gas_info(GasCategory::Read, 5, 7);
+        assert_eq!(info.cost, 7);
+        assert_eq!(info.externally_used, 5);
+        let info = gas_info(GasCategory::Api, 5, 7);
+        assert_eq!(info.cost, 12);
+        assert_eq!(info.externally_used, 0);
+    }
+}
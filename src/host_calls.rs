@@ -0,0 +1,149 @@
+//! How often a contract call invoked each host import.
+//!
+//! Gas schedules price every import on its own, but the gas report only breaks the gas down by kind of
+//! callback. Every callback therefore also counts its import in the gas report of the running call, see
+//! `gas_report::record_host_call`, and `code_stats` sums the counts per code, so chains can calibrate their
+//! schedules against real traffic and developers can see which imports their contracts lean on.
+
+use serde::{Deserialize, Serialize};
+
+use crate::gas_report::GasCategory;
+
+/// The host imports of a contract which reach Go
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HostCall {
+    DbRead,
+    DbWrite,
+    DbRemove,
+    /// Removing a range of storage entries at once
+    DbRemoveRange,
+    /// Opening an iterator, by range or by prefix
+    DbScan,
+    DbNext,
+    DbSeek,
+    QueryChain,
+    CanonicalizeAddress,
+    HumanizeAddress,
+}
+
+impl HostCall {
+    /// The kind of gas the import reports
+    pub fn category(self) -> GasCategory {
+        match self {
+            HostCall::DbRead => GasCategory::Read,
+            HostCall::DbWrite => GasCategory::Write,
+            HostCall::DbRemove | HostCall::DbRemoveRange => GasCategory::Remove,
+            HostCall::DbScan | HostCall::DbNext | HostCall::DbSeek => GasCategory::Iterator,
+            HostCall::QueryChain => GasCategory::Query,
+            HostCall::CanonicalizeAddress | HostCall::HumanizeAddress => GasCategory::Api,
+        }
+    }
+}
+
+/// The number of invocations of each import
+#[repr(C)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct HostCallCounts {
+    pub db_read: u64,
+    pub db_write: u64,
+    pub db_remove: u64,
+    /// Only the scans of this library remove ranges or seek, so these stay 0 for contracts, see `DB::range_keys`
+    pub db_remove_range: u64,
+    pub db_scan: u64,
+    pub db_next: u64,
+    pub db_seek: u64,
+    pub query_chain: u64,
+    pub canonicalize_address: u64,
+    pub humanize_address: u64,
+}
+
+impl HostCallCounts {
+    pub fn count(&mut self, call: HostCall) {
+        let counter = match call {
+            HostCall::DbRead => &mut self.db_read,
+            HostCall::DbWrite => &mut self.db_write,
+            HostCall::DbRemove => &mut self.db_remove,
+            HostCall::DbRemoveRange => &mut self.db_remove_range,
+            HostCall::DbScan => &mut self.db_scan,
+            HostCall::DbNext => &mut self.db_next,
+            HostCall::DbSeek => &mut self.db_seek,
+            HostCall::QueryChain => &mut self.query_chain,
+            HostCall::CanonicalizeAddress => &mut self.canonicalize_address,
+            HostCall::HumanizeAddress => &mut self.humanize_address,
+        };
+        *counter = counter.saturating_add(1);
+    }
+
+    /// Adds the counts of `other`, e.g. of another call
+    pub fn add(&mut self, other: &HostCallCounts) {
+        self.db_read = self.db_read.saturating_add(other.db_read);
+        self.db_write = self.db_write.saturating_add(other.db_write);
+        self.db_remove = self.db_remove.saturating_add(other.db_remove);
+        self.db_remove_range = self.db_remove_range.saturating_add(other.db_remove_range);
+        self.db_scan = self.db_scan.saturating_add(other.db_scan);
+        self.db_next = self.db_next.saturating_add(other.db_next);
+        self.db_seek = self.db_seek.saturating_add(other.db_seek);
+        self.query_chain = self.query_chain.saturating_add(other.query_chain);
+        self.canonicalize_address = self
+            .canonicalize_address
+            .saturating_add(other.canonicalize_address);
+        self.humanize_address = self.humanize_address.saturating_add(other.humanize_address);
+    }
+
+    /// The counts since `earlier`, a snapshot of the same counters
+    pub fn since(&self, earlier: &HostCallCounts) -> HostCallCounts {
+        HostCallCounts {
+            db_read: self.db_read.saturating_sub(earlier.db_read),
+            db_write: self.db_write.saturating_sub(earlier.db_write),
+            db_remove: self.db_remove.saturating_sub(earlier.db_remove),
+            db_remove_range: self.db_remove_range.saturating_sub(earlier.db_remove_range),
+            db_scan: self.db_scan.saturating_sub(earlier.db_scan),
+            db_next: self.db_next.saturating_sub(earlier.db_next),
+            db_seek: self.db_seek.saturating_sub(earlier.db_seek),
+            query_chain: self.query_chain.saturating_sub(earlier.query_chain),
+            canonicalize_address: self
+                .canonicalize_address
+                .saturating_sub(earlier.canonicalize_address),
+            humanize_address: self
+                .humanize_address
+                .saturating_sub(earlier.humanize_address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_add_up() {
+        let mut counts = HostCallCounts::default();
+        counts.count(HostCall::DbRead);
+        counts.count(HostCall::DbRead);
+        counts.count(HostCall::QueryChain);
+        let snapshot = counts;
+        counts.count(HostCall::DbRead);
+        counts.count(HostCall::HumanizeAddress);
+
+        let delta = counts.since(&snapshot);
+        assert_eq!(
+            delta,
+            HostCallCounts {
+                db_read: 1,
+                humanize_address: 1,
+                ..HostCallCounts::default()
+            }
+        );
+        let mut total = snapshot;
+        total.add(&delta);
+        assert_eq!(total, counts);
+    }
+
+    #[test]
+    fn counts_parse_without_fields() {
+        let counts: HostCallCounts = serde_json::from_str(r#"{"db_next":4}"#).unwrap();
+        assert_eq!(counts.db_next, 4);
+        assert_eq!(counts.db_read, 0);
+    }
+}
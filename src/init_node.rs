@@ -0,0 +1,99 @@
+use enclave_ffi_types::NodeAuthResult;
+
+/// The outcome of `init_node`, telling the node operator what to fix if registration failed.
+///
+/// cbindgen:prefix-with-name
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InitNodeResult {
+    Success = 0,
+    /// The master certificate or the encrypted seed is malformed
+    InvalidInput = 1,
+    /// The master certificate is not a valid certificate of the network
+    InvalidMasterCert = 2,
+    /// The master certificate was issued by a different enclave build
+    MrEnclaveMismatch = 3,
+    /// The master certificate was issued by an enclave signed with a different key
+    MrSignerMismatch = 4,
+    /// The encrypted seed was not encrypted to this node's registration key
+    SeedDecryptionFailed = 5,
+    /// The sealed node files were created by another enclave or on another machine
+    SealedFileMismatch = 6,
+    /// Any other failure of the enclave or the SGX sdk
+    EnclaveError = 7,
+    /// The loaded enclave is not the one this library was built with
+    LibraryMismatch = 8,
+}
+
+impl From<NodeAuthResult> for InitNodeResult {
+    fn from(result: NodeAuthResult) -> Self {
+        match result {
+            NodeAuthResult::Success => InitNodeResult::Success,
+            NodeAuthResult::InvalidInput => InitNodeResult::InvalidInput,
+            NodeAuthResult::InvalidCert | NodeAuthResult::SignatureInvalid => {
+                InitNodeResult::InvalidMasterCert
+            }
+            NodeAuthResult::MrEnclaveMismatch => InitNodeResult::MrEnclaveMismatch,
+            NodeAuthResult::MrSignerMismatch => InitNodeResult::MrSignerMismatch,
+            NodeAuthResult::SeedDecryptionFailed => InitNodeResult::SeedDecryptionFailed,
+            NodeAuthResult::SealedFileMismatch => InitNodeResult::SealedFileMismatch,
+            _ => InitNodeResult::EnclaveError,
+        }
+    }
+}
+
+impl InitNodeResult {
+    /// Explains the failure in terms of what the node operator should do
+    pub fn message(self) -> &'static str {
+        match self {
+            InitNodeResult::Success => "Success",
+            InitNodeResult::InvalidInput => {
+                "The master certificate or the encrypted seed is malformed. Check that you passed the files of the network you are joining"
+            }
+            InitNodeResult::InvalidMasterCert => {
+                "The master certificate is invalid. Make sure you use the certificate published for the network you are joining"
+            }
+            InitNodeResult::MrEnclaveMismatch => {
+                "The master certificate was issued by a different enclave version. Run the enclave version used by the network"
+            }
+            InitNodeResult::MrSignerMismatch => {
+                "The master certificate was issued by an enclave signed with a different key. Use the official enclave release"
+            }
+            InitNodeResult::SeedDecryptionFailed => {
+                "Cannot decrypt the encrypted seed. It must be fetched for this node's registration key: register the node again"
+            }
+            InitNodeResult::SealedFileMismatch => {
+                "The sealed node files were created by another enclave or machine. Remove them and register the node again"
+            }
+            InitNodeResult::EnclaveError => "The enclave failed to initialize the node",
+            InitNodeResult::LibraryMismatch => {
+                "The loaded enclave was not built with this library. Install the enclave released with this library"
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_node_auth_result_works() {
+        assert_eq!(
+            InitNodeResult::from(NodeAuthResult::Success),
+            InitNodeResult::Success
+        );
+        assert_eq!(
+            InitNodeResult::from(NodeAuthResult::SignatureInvalid),
+            InitNodeResult::InvalidMasterCert
+        );
+        assert_eq!(
+            InitNodeResult::from(NodeAuthResult::MrSignerMismatch),
+            InitNodeResult::MrSignerMismatch
+        );
+        assert_eq!(
+            InitNodeResult::from(NodeAuthResult::Panic),
+            InitNodeResult::EnclaveError
+        );
+    }
+}
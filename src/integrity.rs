@@ -0,0 +1,116 @@
+//! Re-verification of stored codes before they run, see the `verify_wasm_on_load` cache option.
+//!
+//! The VM trusts the wasm files in its data dir, so a file corrupted on disk or replaced by an attacker would
+//! run without anyone noticing. With the option, the file of a code is hashed against its checksum before
+//! the code is loaded. Hashing a large code on every call is expensive, so verified files are remembered by
+//! size and modification time and only hashed again when either changes.
+//!
+//! Only the wasm files are checked. Modules compiled from them are kept in memory or in the VM's own cache.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use log::*;
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::snapshot::WASM_DIR;
+use crate::vm::Checksum;
+
+/// What we remember of a verified file, to notice when it changes
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// The codes of a cache whose files were verified
+#[derive(Default)]
+pub struct VerifiedCodes {
+    stamps: HashMap<String, FileStamp>,
+}
+
+impl VerifiedCodes {
+    /// Fails if the stored wasm of `code_id` in the cache at `data_dir` does not hash to `code_id`
+    pub fn verify(&mut self, data_dir: &Path, code_id: &Checksum) -> Result<(), Error> {
+        self.verify_file(data_dir, &code_id.to_hex())
+    }
+
+    fn verify_file(&mut self, data_dir: &Path, checksum: &str) -> Result<(), Error> {
+        let path = data_dir.join(WASM_DIR).join(checksum);
+        let metadata = fs::metadata(&path)?;
+        let stamp = FileStamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        };
+        if self.stamps.get(checksum) == Some(&stamp) {
+            return Ok(());
+        }
+
+        let wasm = fs::read(&path)?;
+        if hex::encode(Sha256::digest(&wasm)) != checksum {
+            self.stamps.remove(checksum);
+            error!(
+                "The stored wasm of code {} does not match its checksum",
+                checksum
+            );
+            return Err(Error::vm_err(
+                "Integrity error: the stored code does not match its checksum",
+            ));
+        }
+        self.stamps.insert(checksum.to_string(), stamp);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn store(dir: &TempDir, wasm: &[u8]) -> String {
+        let checksum = hex::encode(Sha256::digest(wasm));
+        fs::create_dir_all(dir.path().join(WASM_DIR)).unwrap();
+        fs::write(dir.path().join(WASM_DIR).join(&checksum), wasm).unwrap();
+        checksum
+    }
+
+    #[test]
+    fn verify_accepts_intact_codes() {
+        let dir = TempDir::new().unwrap();
+        let checksum = store(&dir, b"\0asm some code");
+        let mut verified = VerifiedCodes::default();
+        verified.verify_file(dir.path(), &checksum).unwrap();
+        assert!(verified.stamps.contains_key(&checksum));
+        verified.verify_file(dir.path(), &checksum).unwrap();
+    }
+
+    #[test]
+    fn verify_rejects_changed_codes() {
+        let dir = TempDir::new().unwrap();
+        let checksum = store(&dir, b"\0asm some code");
+        let mut verified = VerifiedCodes::default();
+        verified.verify_file(dir.path(), &checksum).unwrap();
+
+        // a different size invalidates the remembered stamp
+        fs::write(
+            dir.path().join(WASM_DIR).join(&checksum),
+            b"\0asm tampered code",
+        )
+        .unwrap();
+        match verified.verify_file(dir.path(), &checksum).unwrap_err() {
+            Error::VmErr { msg, .. } => assert!(msg.starts_with("Integrity error"), "{}", msg),
+            e => panic!("unexpected error: {}", e),
+        }
+        assert!(verified.stamps.is_empty());
+    }
+
+    #[test]
+    fn verify_fails_for_missing_codes() {
+        let dir = TempDir::new().unwrap();
+        let mut verified = VerifiedCodes::default();
+        assert!(verified.verify_file(dir.path(), "aa").is_err());
+    }
+}
@@ -0,0 +1,266 @@
+//! Opt-in fast path for contract-to-contract calls.
+//!
+//! Normally, when contract A returns a `wasm.execute` message targeting contract B, the response is
+//! returned to Go, which dispatches the message and calls back into this library to execute B.
+//! When the Go store implements contract resolution (see `DB_vtable.resolve_contract`), we instead
+//! execute such messages right here, saving the round trip through Go for every hop.
+//!
+//! Only the leading run of fund-less `wasm.execute` messages is executed internally, so messages are
+//! still processed in exactly the order Go would have used. Anything else is returned to Go untouched.
+//! Internal calls count towards the call depth just like the ones dispatched by Go.
+
+use std::convert::TryInto;
+
+use serde_json::{json, Value};
+
+use crate::api::GoApi;
+use crate::block_context::BlockContext;
+use crate::cache::Cache;
+use crate::call_depth::DepthGuard;
+use crate::call_hooks;
+use crate::code_stats::EntryPoint;
+use crate::db::DB;
+use crate::error::Error;
+use crate::plaintext;
+use crate::querier::GoQuerier;
+use crate::run::{run_contract, ContractCall};
+use crate::vm::{call_handle_raw, Checksum, Extern};
+
+/// A `wasm.execute` message that can be executed without going through Go
+struct InternalCall {
+    contract_addr: String,
+    callback_code_hash: String,
+    msg: Vec<u8>,
+}
+
+impl InternalCall {
+    /// Returns `Some` if `msg` is a `wasm.execute` message which does not send any funds
+    fn from_msg(msg: &Value) -> Option<Self> {
+        let execute = msg.get("wasm")?.get("execute")?;
+        let sends_funds = match execute.get("send") {
+            None | Some(Value::Null) => false,
+            Some(Value::Array(coins)) => !coins.is_empty(),
+            Some(_) => true,
+        };
+        if sends_funds {
+            // Moving funds requires the bank module, so this message has to go through Go
+            return None;
+        }
+        Some(InternalCall {
+            contract_addr: execute.get("contract_addr")?.as_str()?.to_string(),
+            callback_code_hash: execute.get("callback_code_hash")?.as_str()?.to_string(),
+            msg: base64::decode(execute.get("msg")?.as_str()?).ok()?,
+        })
+    }
+}
+
+/// Executes the leading fund-less `wasm.execute` messages of `response` internally.
+///
+/// `response` is the raw (successful or not) result of the caller, `env` the raw env it was called with
+/// and `call_depth` its depth. The returned response has the executed messages replaced by the messages
+/// of the callees, and their logs appended to the caller's log. If a callee fails, its error is returned
+/// as the response.
+pub fn execute_internal_calls(
+    cache: &mut Cache,
+    deps: &Extern<DB, GoApi, GoQuerier>,
+    env: &[u8],
+    response: Vec<u8>,
+    gas_limit: u64,
+    call_depth: u32,
+    gas_used: &mut u64,
+) -> Result<Vec<u8>, Error> {
+    if deps.storage.vtable.resolve_contract.is_none() {
+        return Ok(response);
+    }
+    let mut result: Value = match serde_json::from_slice(&response) {
+        Ok(result) => result,
+        Err(_) => return Ok(response),
+    };
+    let env: Value = serde_json::from_slice(env).map_err(Error::vm_err)?;
+    if !env.is_object() {
+        // we build the callees' env from it below
+        return Err(Error::invalid_arg("params", "expected a JSON object"));
+    }
+
+    let mut executed_calls = 0;
+    // the gas Go charged for resolving the callees, which counts against their gas limit
+    let mut external_gas = 0_u64;
+    // the depths of the leading messages which were returned by internal calls.
+    // All other messages were returned by the caller.
+    let mut depths: Vec<u32> = Vec::new();
+    if let Some(ok) = result.get_mut("Ok") {
+        while let Some(call) = ok["messages"].get(0).and_then(InternalCall::from_msg) {
+            let depth = depths
+                .first()
+                .copied()
+                .unwrap_or(call_depth.saturating_add(1));
+            let (resolved, gas_info) = deps.storage.resolve_contract(&call.contract_addr);
+            *gas_used = gas_used.saturating_add(gas_info.cost);
+            external_gas = external_gas.saturating_add(gas_info.externally_used);
+            let resolved = match resolved.map_err(crate::vm::VmError::from)? {
+                Some(resolved) => resolved,
+                None => break,
+            };
+            if hex::encode(&resolved.code_id) != call.callback_code_hash.to_lowercase() {
+                // Let Go produce the canonical error for a code hash mismatch
+                break;
+            }
+            let code_id: Checksum = resolved.code_id.as_slice().try_into()?;
+
+            let mut callee_env = env.clone();
+            callee_env["message"] = json!({
+                "sender": env["contract"]["address"],
+                "sent_funds": [],
+            });
+            callee_env["contract"] = json!({ "address": call.contract_addr });
+            callee_env["contract_key"] =
+                json!(String::from_utf8(resolved.contract_key).map_err(Error::invalid_utf8)?);
+            plaintext::mark(&mut callee_env, cache.plaintext_codes.contains(&code_id))?;
+            cache.check_msg(&code_id, &call.msg)?;
+            cache.plaintext_codes.check_msg(&code_id, &call.msg)?;
+            let callee_env = serde_json::to_vec(&callee_env).map_err(Error::vm_err)?;
+
+            let gas_left = gas_limit
+                .saturating_sub(*gas_used)
+                .saturating_sub(external_gas);
+            if gas_left == 0 {
+                return Err(Error::out_of_gas());
+            }
+            let context = BlockContext::from_params(&callee_env);
+            resolved
+                .db
+                .set_block_context(context)
+                .map_err(crate::vm::VmError::from)?;
+            call_hooks::authorize(
+                &resolved.db,
+                &code_id,
+                EntryPoint::Handle,
+                Some(&callee_env),
+            )?;
+            let callee_deps = Extern {
+                storage: resolved.db,
+                api: deps.api,
+                querier: deps.querier.clone(),
+            };
+            let consensus = cache.consensus_params()?;
+            let guard = DepthGuard::enter(depth, consensus.max_call_depth)?;
+            let callee = ContractCall {
+                code_id,
+                entry_point: EntryPoint::Handle,
+                gas_limit: gas_left,
+                height: context.height,
+                executes: true,
+            };
+            let run = run_contract(cache, &consensus, &callee, callee_deps, |instance| {
+                call_handle_raw(instance, &callee_env, &call.msg)
+            })?;
+            *gas_used += run.gas_used;
+            drop(guard);
+            executed_calls += 1;
+
+            let res = run.finish()?;
+            consensus.check_response_size(res.len())?;
+            let plaintext = cache.plaintext_codes.contains(&code_id);
+            call_hooks::report_result(&resolved.db, &code_id, EntryPoint::Handle, &res, plaintext);
+            let callee_result: Value = serde_json::from_slice(&res).map_err(Error::vm_err)?;
+            let callee_ok = match callee_result.get("Ok") {
+                Some(callee_ok) => callee_ok,
+                // The whole call fails if a callee fails, so its error becomes the response
+                None => return serde_json::to_vec(&callee_result).map_err(Error::vm_err),
+            };
+
+            let mut messages: Vec<Value> = callee_ok["messages"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            let mut callee_depths = vec![depth.saturating_add(1); messages.len()];
+            callee_depths.extend(depths.into_iter().skip(1));
+            depths = callee_depths;
+            if let Some(remaining) = ok["messages"].as_array() {
+                messages.extend(remaining.iter().skip(1).cloned());
+            }
+            ok["messages"] = Value::Array(messages);
+
+            let mut log = ok["log"].as_array().cloned().unwrap_or_default();
+            log.push(json!({ "key": "contract_address", "value": call.contract_addr }));
+            if let Some(callee_log) = callee_ok["log"].as_array() {
+                log.extend(callee_log.iter().cloned());
+            }
+            ok["log"] = Value::Array(log);
+        }
+    }
+
+    if executed_calls == 0 {
+        return Ok(response);
+    }
+    serde_json::to_vec(&result).map_err(Error::vm_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::db::db_t;
+    use crate::error::GoResult;
+    use crate::gas_meter::gas_meter_t;
+    use crate::gas_report::{self, GasTracker};
+    use crate::gas_schedule::{FallbackGasSchedule, GasPolicy};
+    use crate::memory::Buffer;
+    use crate::testing::failing_db;
+
+    fn execute(send: Value) -> Value {
+        json!({"wasm": {"execute": {
+            "contract_addr": "secret1callee",
+            "callback_code_hash": "AB",
+            "msg": base64::encode(b"{}"),
+            "send": send,
+        }}})
+    }
+
+    #[test]
+    fn only_fundless_executes_are_internal() {
+        let call = InternalCall::from_msg(&execute(Value::Null)).unwrap();
+        assert_eq!(call.contract_addr, "secret1callee");
+        assert_eq!(call.callback_code_hash, "AB");
+        assert_eq!(call.msg, b"{}");
+        assert!(InternalCall::from_msg(&execute(json!([]))).is_some());
+
+        let funds = json!([{"denom": "uscrt", "amount": "1"}]);
+        assert!(InternalCall::from_msg(&execute(funds)).is_none());
+        assert!(InternalCall::from_msg(&json!({"bank": {"send": {}}})).is_none());
+        let incomplete = json!({"wasm": {"execute": {"contract_addr": "secret1callee"}}});
+        assert!(InternalCall::from_msg(&incomplete).is_none());
+    }
+
+    /// Knows no contract, and reports no gas for looking it up
+    extern "C" fn resolve_nothing(
+        _: *mut db_t,
+        _: *mut gas_meter_t,
+        used_gas: *mut u64,
+        _: Buffer,
+        _: *mut Buffer,
+        _: *mut Buffer,
+        _: *mut DB,
+        _: *mut Buffer,
+    ) -> i32 {
+        unsafe { *used_gas = 0 };
+        GoResult::Ok as i32
+    }
+
+    fn resolving_db() -> DB {
+        let mut db = failing_db();
+        db.vtable.resolve_contract = Some(resolve_nothing);
+        db
+    }
+
+    #[test]
+    fn resolving_is_charged_like_a_read() {
+        let tracker = GasTracker::start();
+        let schedule = FallbackGasSchedule::default();
+        gas_report::set_gas_policy(GasPolicy::Fallback, schedule);
+        let (resolved, gas_info) = resolving_db().resolve_contract("secret1callee");
+        assert!(resolved.unwrap().is_none());
+        assert_eq!(gas_info.cost, schedule.read);
+        assert_eq!(tracker.report().fallback, schedule.read);
+    }
+}
@@ -1,9 +1,17 @@
-use cosmwasm_sgx_vm::{FfiError, FfiResult, GasInfo, StorageIterator};
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Bound::{Excluded, Unbounded};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
 use cosmwasm_std::KV;
+use lazy_static::lazy_static;
 
 use crate::error::GoResult;
 use crate::gas_meter::gas_meter_t;
+use crate::gas_report;
+use crate::host_calls::HostCall;
 use crate::memory::Buffer;
+use crate::vm::{FfiError, FfiResult, GasInfo, StorageIterator};
 
 // Iterator maintains integer references to some tables on the Go side
 #[repr(C)]
@@ -13,6 +21,17 @@ pub struct iterator_t {
     pub iterator_index: u64,
 }
 
+/// What the items of an iterator contain
+///
+/// cbindgen:prefix-with-name
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IteratorMode {
+    KeysAndValues = 0,
+    /// Values are not transported and come back empty, for scans which only need the keys
+    KeysOnly = 1,
+}
+
 // These functions should return GoResult but because we don't trust them here, we treat the return value as i32
 // and then check it when converting to GoResult manually
 #[repr(C)]
@@ -28,6 +47,24 @@ pub struct Iterator_vtable {
             *mut Buffer,
         ) -> i32,
     >,
+    /// Like `next_db`, but must not touch the gas meter and may be called from another thread.
+    /// Go reports the gas of the item instead, which is only charged once the contract consumes it.
+    /// Iterators which set this are read ahead on a background thread, see `PrefetchIter`.
+    pub prefetch_db:
+        Option<extern "C" fn(iterator_t, *mut u64, *mut Buffer, *mut Buffer, *mut Buffer) -> i32>,
+    /// Optional. Continues the iteration at the given key, see `SeekableIterator::seek`.
+    pub seek_db:
+        Option<extern "C" fn(iterator_t, *mut gas_meter_t, *mut u64, Buffer, *mut Buffer) -> i32>,
+}
+
+/// An iterator which can move within its range without being reopened.
+///
+/// Only the scans of this library can seek, see `DB::range_seekable`. The VM hands contracts the iterators of
+/// `Storage::range`, which it only advances, so a paginated query still opens a new iterator at its cursor.
+pub trait SeekableIterator: StorageIterator {
+    /// Continues the iteration at the first item at or after `key` in iteration order,
+    /// without leaving the range the iterator was opened with
+    fn seek(&mut self, key: &[u8]) -> FfiResult<()>;
 }
 
 #[repr(C)]
@@ -45,6 +82,54 @@ impl GoIter {
             vtable: Iterator_vtable::default(),
         }
     }
+
+    /// Reads ahead on the prefetcher's thread if Go supports it for this iterator, see `PrefetchIter`
+    pub fn into_storage_iterator(self) -> Box<dyn StorageIterator> {
+        match self.vtable.prefetch_db {
+            Some(prefetch_db) => match PrefetchIter::start(self, prefetch_db) {
+                Ok(prefetching) => Box::new(prefetching),
+                Err(iter) => Box::new(iter),
+            },
+            None => Box::new(self),
+        }
+    }
+
+    /// Like `into_storage_iterator`, but keeps access to `seek`
+    pub fn into_seekable_iterator(self) -> Box<dyn SeekableIterator> {
+        match self.vtable.prefetch_db {
+            Some(prefetch_db) => match PrefetchIter::start(self, prefetch_db) {
+                Ok(prefetching) => Box::new(prefetching),
+                Err(iter) => Box::new(iter),
+            },
+            None => Box::new(self),
+        }
+    }
+}
+
+/// Converts the outputs of `next_db` or `prefetch_db` into the next item
+///
+/// # Safety
+///
+/// The buffers must be empty or allocated by `allocate_rust`
+unsafe fn read_item(
+    go_result: GoResult,
+    key_buf: Buffer,
+    value_buf: Buffer,
+    err: Buffer,
+) -> Result<Option<KV>, FfiError> {
+    // return complete error message (reading from buffer for GoResult::Other)
+    let default = || "Failed to fetch next item from iterator".to_string();
+    go_result.into_ffi_result(err, default)?;
+
+    if key_buf.ptr.is_null() {
+        return Ok(None);
+    }
+    if value_buf.ptr.is_null() {
+        return Err(FfiError::unknown(
+            "Failed to read value while reading the next key in the db",
+        ));
+    }
+    Ok(Some((key_buf.consume(), value_buf.consume())))
 }
 
 impl StorageIterator for GoIter {
@@ -70,30 +155,513 @@ impl StorageIterator for GoIter {
             &mut err as *mut Buffer,
         )
         .into();
-        let gas_info = GasInfo::with_externally_used(used_gas);
+        let gas_info = gas_report::record_host_call(HostCall::DbNext, used_gas);
+        let result = unsafe { read_item(go_result, key_buf, value_buf, err) };
+        (result, gas_info)
+    }
+}
+
+impl SeekableIterator for GoIter {
+    fn seek(&mut self, key: &[u8]) -> FfiResult<()> {
+        let seek_db = match self.vtable.seek_db {
+            Some(f) => f,
+            None => {
+                let result = Err(FfiError::unknown("iterator does not support seek"));
+                return (result, GasInfo::free());
+            }
+        };
+
+        let key_buf = Buffer::temp(key);
+        let mut err = Buffer::default();
+        let mut used_gas = 0_u64;
+        let go_result: GoResult = (seek_db)(
+            self.state,
+            self.gas_meter,
+            &mut used_gas as *mut u64,
+            key_buf,
+            &mut err as *mut Buffer,
+        )
+        .into();
+        let gas_info = gas_report::record_host_call(HostCall::DbSeek, used_gas);
+        unsafe { key_buf.release_temp() };
+
+        let default = || {
+            format!(
+                "Failed to seek iterator to {:?}",
+                String::from_utf8_lossy(key)
+            )
+        };
+        let result = unsafe { go_result.into_ffi_result(err, default) };
+        (result, gas_info)
+    }
+}
+
+/// The smallest key greater than all keys starting with `prefix`, or `None` if there is none
+pub fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+/// Stops after `limit` items, or never if `limit` is 0
+pub struct LimitedIter {
+    inner: Box<dyn StorageIterator>,
+    remaining: Option<u32>,
+}
 
-        // return complete error message (reading from buffer for GoResult::Other)
-        let default = || "Failed to fetch next item from iterator".to_string();
-        unsafe {
-            if let Err(err) = go_result.into_ffi_result(err, default) {
-                return (Err(err), gas_info);
+impl LimitedIter {
+    pub fn wrap(inner: Box<dyn StorageIterator>, limit: u32) -> Box<dyn StorageIterator> {
+        if limit == 0 {
+            return inner;
+        }
+        Box::new(LimitedIter {
+            inner,
+            remaining: Some(limit),
+        })
+    }
+}
+
+impl StorageIterator for LimitedIter {
+    fn next(&mut self) -> FfiResult<Option<KV>> {
+        match self.remaining {
+            Some(0) => (Ok(None), GasInfo::free()),
+            Some(remaining) => {
+                self.remaining = Some(remaining - 1);
+                self.inner.next()
             }
+            None => self.inner.next(),
         }
+    }
+}
 
-        let okey = unsafe { key_buf.read() };
-        let result = match okey {
-            Some(key) => {
-                let value = unsafe { value_buf.read() };
-                if let Some(value) = value {
-                    Ok(Some((key.into(), value.into())))
-                } else {
-                    Err(FfiError::unknown(
-                        "Failed to read value while reading the next key in the db",
-                    ))
+/// How many items a prefetching iterator reads ahead of the contract
+const PREFETCH_DEPTH: usize = 32;
+
+type PrefetchFn = extern "C" fn(iterator_t, *mut u64, *mut Buffer, *mut Buffer, *mut Buffer) -> i32;
+
+/// An item read ahead by the prefetcher, with the gas Go reported for it
+type Prefetched = (Result<Option<KV>, FfiError>, u64);
+
+/// The scan of a `PrefetchIter`, which the prefetcher reads ahead
+struct Scan {
+    state: iterator_t,
+    prefetch_db: PrefetchFn,
+    items: VecDeque<Prefetched>,
+    /// The prefetcher read the last item or an error
+    done: bool,
+    /// The iterator seeks or is dropped, so the prefetcher must not read the scan
+    paused: bool,
+    /// The prefetcher is reading an item of the scan
+    reading: bool,
+}
+
+impl Scan {
+    fn wants_items(&self) -> bool {
+        !self.done && !self.paused && !self.reading && self.items.len() < PREFETCH_DEPTH
+    }
+}
+
+#[derive(Default)]
+struct Scans {
+    next_id: u64,
+    open: BTreeMap<u64, Scan>,
+    /// The scan read last. The prefetcher reads the scans in turn, so a long scan does not hold up the others.
+    last: u64,
+    /// Whether the thread of the prefetcher runs
+    running: bool,
+}
+
+/// The background thread reading ahead the items of all prefetching iterators of the process
+struct Prefetcher {
+    scans: Mutex<Scans>,
+    /// Notified whenever a scan gains or loses an item or is paused
+    changed: Condvar,
+}
+
+impl Prefetcher {
+    fn lock(&self) -> MutexGuard<'_, Scans> {
+        self.scans.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait<'a>(&self, scans: MutexGuard<'a, Scans>) -> MutexGuard<'a, Scans> {
+        self.changed
+            .wait(scans)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Reads one item at a time of the next scan which has room for it, outside of the lock
+    fn run(&self) {
+        let mut scans = self.lock();
+        loop {
+            let last = scans.last;
+            let next = scans
+                .open
+                .range((Excluded(last), Unbounded))
+                .chain(scans.open.range(..=last))
+                .find(|(_, scan)| scan.wants_items())
+                .map(|(id, scan)| (*id, scan.state, scan.prefetch_db));
+            let (id, state, prefetch_db) = match next {
+                Some(next) => next,
+                None => {
+                    scans = self.wait(scans);
+                    continue;
                 }
+            };
+            scans.last = id;
+            if let Some(scan) = scans.open.get_mut(&id) {
+                scan.reading = true;
+            }
+            drop(scans);
+            let item = prefetch(state, prefetch_db);
+            scans = self.lock();
+            // a scan is only closed once it is not read
+            if let Some(scan) = scans.open.get_mut(&id) {
+                scan.reading = false;
+                scan.done = !matches!(item.0, Ok(Some(_)));
+                scan.items.push_back(item);
             }
-            None => Ok(None),
+            self.changed.notify_all();
+        }
+    }
+}
+
+lazy_static! {
+    static ref PREFETCHER: Prefetcher = Prefetcher {
+        scans: Mutex::new(Scans::default()),
+        changed: Condvar::new(),
+    };
+}
+
+/// An iterator whose items are read by a background thread while the contract processes the previous ones.
+///
+/// One thread, the prefetcher, reads ahead for all iterators, so the number of threads does not grow with the
+/// scans of the contracts. It reads the store concurrently with the contract, which may write to the store
+/// between two items, so Go must open prefetch iterators on a snapshot of the store, see `PrefetchStore`.
+/// Otherwise an item would depend on how far the prefetcher got when the contract wrote.
+///
+/// Gas is charged when the contract consumes an item, not when it is read, so the gas used does not
+/// depend on how far the prefetcher got either.
+pub struct PrefetchIter {
+    iter: GoIter,
+    id: u64,
+}
+
+impl PrefetchIter {
+    /// Hands back `iter` if the thread of the prefetcher cannot be started
+    fn start(iter: GoIter, prefetch_db: PrefetchFn) -> Result<Self, GoIter> {
+        let prefetcher = &*PREFETCHER;
+        let mut scans = prefetcher.lock();
+        if !scans.running {
+            let spawned = thread::Builder::new()
+                .name("prefetch".to_string())
+                .spawn(move || prefetcher.run());
+            if spawned.is_err() {
+                return Err(iter);
+            }
+            scans.running = true;
+        }
+        let id = scans.next_id;
+        scans.next_id += 1;
+        let scan = Scan {
+            state: iter.state,
+            prefetch_db,
+            items: VecDeque::new(),
+            done: false,
+            paused: false,
+            reading: false,
         };
-        (result, gas_info)
+        scans.open.insert(id, scan);
+        prefetcher.changed.notify_all();
+        Ok(PrefetchIter { iter, id })
+    }
+
+    /// Keeps the prefetcher from reading the scan, waiting for the item it is reading
+    fn pause(&self) -> MutexGuard<'static, Scans> {
+        let prefetcher = &*PREFETCHER;
+        let mut scans = prefetcher.lock();
+        loop {
+            match scans.open.get_mut(&self.id) {
+                Some(scan) if scan.reading => scan.paused = true,
+                Some(scan) => {
+                    scan.paused = true;
+                    return scans;
+                }
+                None => return scans,
+            }
+            scans = prefetcher.wait(scans);
+        }
+    }
+}
+
+fn prefetch(state: iterator_t, prefetch_db: PrefetchFn) -> Prefetched {
+    let mut key_buf = Buffer::default();
+    let mut value_buf = Buffer::default();
+    let mut err = Buffer::default();
+    let mut used_gas = 0_u64;
+    let go_result: GoResult = (prefetch_db)(
+        state,
+        &mut used_gas as *mut u64,
+        &mut key_buf as *mut Buffer,
+        &mut value_buf as *mut Buffer,
+        &mut err as *mut Buffer,
+    )
+    .into();
+    let result = unsafe { read_item(go_result, key_buf, value_buf, err) };
+    (result, used_gas)
+}
+
+impl StorageIterator for PrefetchIter {
+    fn next(&mut self) -> FfiResult<Option<KV>> {
+        let prefetcher = &*PREFETCHER;
+        let mut scans = prefetcher.lock();
+        let item = loop {
+            if let Some(scan) = scans.open.get_mut(&self.id) {
+                match scan.items.pop_front() {
+                    Some(item) => break Some(item),
+                    None if !scan.done => {}
+                    // the prefetcher stops after the last item
+                    None => break None,
+                }
+            }
+            scans = prefetcher.wait(scans);
+        };
+        drop(scans);
+        // the scan has room for another item
+        prefetcher.changed.notify_all();
+        match item {
+            Some((result, used_gas)) => {
+                let gas_info = gas_report::record_host_call(HostCall::DbNext, used_gas);
+                // Go did not charge its gas meter for prefetched items
+                let gas_info =
+                    GasInfo::with_cost(gas_info.cost.saturating_add(gas_info.externally_used));
+                (result, gas_info)
+            }
+            None => (Ok(None), GasInfo::free()),
+        }
+    }
+}
+
+impl SeekableIterator for PrefetchIter {
+    /// Items read ahead of the old position were never charged, so they are simply dropped
+    fn seek(&mut self, key: &[u8]) -> FfiResult<()> {
+        let mut scans = self.pause();
+        if let Some(scan) = scans.open.get_mut(&self.id) {
+            scan.items.clear();
+            scan.done = false;
+        }
+        drop(scans);
+        let result = self.iter.seek(key);
+        let prefetcher = &*PREFETCHER;
+        if let Some(scan) = prefetcher.lock().open.get_mut(&self.id) {
+            scan.paused = false;
+        }
+        prefetcher.changed.notify_all();
+        result
+    }
+}
+
+impl Drop for PrefetchIter {
+    /// Waits until the prefetcher is done with the scan, as Go closes the iterator at the end of the contract call
+    fn drop(&mut self) {
+        self.pause().open.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::allocate_rust;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// The position of the iterators with `db_counter` 0 to 3
+    static READ: [AtomicU64; 4] = [
+        AtomicU64::new(0),
+        AtomicU64::new(0),
+        AtomicU64::new(0),
+        AtomicU64::new(0),
+    ];
+
+    /// Yields `state.iterator_index` items with a gas of 10 each
+    extern "C" fn prefetch_db(
+        state: iterator_t,
+        used_gas: *mut u64,
+        key: *mut Buffer,
+        value: *mut Buffer,
+        _err: *mut Buffer,
+    ) -> i32 {
+        let read = READ[state.db_counter as usize].fetch_add(1, Ordering::SeqCst);
+        if read < state.iterator_index {
+            let data = read.to_be_bytes();
+            unsafe {
+                *used_gas = 10;
+                *key = allocate_rust(data.as_ptr(), data.len());
+                *value = allocate_rust(data.as_ptr(), data.len());
+            }
+        }
+        GoResult::Ok as i32
+    }
+
+    /// Moves to the position given as a big endian key, with a gas of 5
+    extern "C" fn seek_db(
+        state: iterator_t,
+        _gas_meter: *mut gas_meter_t,
+        used_gas: *mut u64,
+        key: Buffer,
+        _err: *mut Buffer,
+    ) -> i32 {
+        let mut position = [0u8; 8];
+        position.copy_from_slice(unsafe { key.read() }.unwrap());
+        READ[state.db_counter as usize].store(u64::from_be_bytes(position), Ordering::SeqCst);
+        unsafe { *used_gas = 5 };
+        GoResult::Ok as i32
+    }
+
+    /// The writes to the store of `snapshot_db`, each of which sets all of its values to their number
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+
+    /// Yields the 8 items of the store of `WRITES`, from the snapshot taken after `state.iterator_index` writes,
+    /// as Go opens prefetch iterators
+    extern "C" fn snapshot_db(
+        state: iterator_t,
+        used_gas: *mut u64,
+        key: *mut Buffer,
+        value: *mut Buffer,
+        _err: *mut Buffer,
+    ) -> i32 {
+        let read = READ[state.db_counter as usize].fetch_add(1, Ordering::SeqCst);
+        if read < 8 {
+            let (key_data, value_data) = (read.to_be_bytes(), state.iterator_index.to_be_bytes());
+            unsafe {
+                *used_gas = 10;
+                *key = allocate_rust(key_data.as_ptr(), key_data.len());
+                *value = allocate_rust(value_data.as_ptr(), value_data.len());
+            }
+        }
+        GoResult::Ok as i32
+    }
+
+    fn prefetching(iter: GoIter, prefetch_db: PrefetchFn) -> PrefetchIter {
+        match PrefetchIter::start(iter, prefetch_db) {
+            Ok(prefetching) => prefetching,
+            Err(_) => panic!("the prefetcher did not start"),
+        }
+    }
+
+    fn go_iter(db_counter: u64, items: u64) -> GoIter {
+        let mut iter = GoIter::new(std::ptr::null_mut());
+        iter.state = iterator_t {
+            db_counter,
+            iterator_index: items,
+        };
+        iter.vtable.seek_db = Some(seek_db);
+        iter
+    }
+
+    #[test]
+    fn prefetch_charges_on_consumption() {
+        let mut iter = prefetching(go_iter(0, 3), prefetch_db);
+        for i in 0..3_u64 {
+            let (result, gas_info) = iter.next();
+            let (key, _) = result.unwrap().unwrap();
+            assert_eq!(key, i.to_be_bytes().to_vec());
+            assert_eq!(gas_info.cost, 10);
+            assert_eq!(gas_info.externally_used, 0);
+        }
+        let (result, gas_info) = iter.next();
+        assert!(result.unwrap().is_none());
+        assert_eq!(gas_info.cost, 0);
+        // the prefetcher stopped reading after the last item
+        let (result, _) = iter.next();
+        assert!(result.unwrap().is_none());
+        drop(iter);
+        assert_eq!(READ[0].load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn prefetch_seek_discards_read_ahead_items() {
+        let mut iter = prefetching(go_iter(1, 100), prefetch_db);
+        let (result, _) = iter.next();
+        assert_eq!(result.unwrap().unwrap().0, 0u64.to_be_bytes().to_vec());
+
+        let (result, gas_info) = iter.seek(&50u64.to_be_bytes());
+        result.unwrap();
+        assert_eq!(gas_info.externally_used, 5);
+        let (result, gas_info) = iter.next();
+        assert_eq!(result.unwrap().unwrap().0, 50u64.to_be_bytes().to_vec());
+        assert_eq!(gas_info.cost, 10);
+    }
+
+    #[test]
+    fn prefetch_scans_interleave_with_writes() {
+        let write = || WRITES.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut first = prefetching(go_iter(2, WRITES.load(Ordering::SeqCst)), snapshot_db);
+        let (result, _) = first.next();
+        assert_eq!(result.unwrap().unwrap().1, 0u64.to_be_bytes().to_vec());
+
+        // a scan opened after a write sees it, the open one does not, however far it was read ahead
+        let mut second = prefetching(go_iter(3, write()), snapshot_db);
+        for i in 1..8_u64 {
+            let (result, _) = first.next();
+            let (key, value) = result.unwrap().unwrap();
+            assert_eq!(key, i.to_be_bytes().to_vec());
+            assert_eq!(value, 0u64.to_be_bytes().to_vec());
+            write();
+            let (result, gas_info) = second.next();
+            let (key, value) = result.unwrap().unwrap();
+            assert_eq!(key, (i - 1).to_be_bytes().to_vec());
+            assert_eq!(value, 1u64.to_be_bytes().to_vec());
+            assert_eq!(gas_info.cost, 10);
+        }
+        assert!(first.next().0.unwrap().is_none());
+        assert_eq!(
+            second.next().0.unwrap().unwrap().0,
+            7u64.to_be_bytes().to_vec()
+        );
+        assert!(second.next().0.unwrap().is_none());
+    }
+
+    #[test]
+    fn prefix_end_works() {
+        assert_eq!(prefix_end(b"ab"), Some(b"ac".to_vec()));
+        assert_eq!(prefix_end(&[1, 255]), Some(vec![2]));
+        assert_eq!(prefix_end(&[255, 255]), None);
+        assert_eq!(prefix_end(b""), None);
+    }
+
+    struct CountingIter(u64);
+
+    impl StorageIterator for CountingIter {
+        fn next(&mut self) -> FfiResult<Option<KV>> {
+            self.0 += 1;
+            (
+                Ok(Some((self.0.to_be_bytes().to_vec(), vec![]))),
+                GasInfo::free(),
+            )
+        }
+    }
+
+    #[test]
+    fn limited_iter_stops_at_limit() {
+        let mut iter = LimitedIter::wrap(Box::new(CountingIter(0)), 2);
+        assert!(iter.next().0.unwrap().is_some());
+        assert!(iter.next().0.unwrap().is_some());
+        assert!(iter.next().0.unwrap().is_none());
+
+        let mut iter = LimitedIter::wrap(Box::new(CountingIter(0)), 0);
+        for _ in 0..5 {
+            assert!(iter.next().0.unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn seek_requires_vtable_support() {
+        let mut iter = go_iter(0, 0);
+        iter.vtable.seek_db = None;
+        assert!(iter.seek(b"foo").0.is_err());
     }
 }
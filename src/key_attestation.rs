@@ -0,0 +1,128 @@
+//! Statements binding the registration keys of a node to an attestation report of its enclave.
+//!
+//! Wallets and bridges sending sensitive payloads to a node want to know they talk to a genuine enclave
+//! holding the node's keys, without trusting the operator. `create_key_attestation` has the enclave create an
+//! attestation report whose report data is `report_data` of its registration keys and of a challenge picked by
+//! the verifier, and sign it into a certificate like the one the node registers with. The challenge keeps the
+//! operator from replaying a statement made for another verifier, or by an enclave which is gone.
+//!
+//! Verifiers check the certificate and the IAS signature of its report like the registration service does,
+//! the MRENCLAVE of the report, and that its report data starts with the `report_data` of the statement, which
+//! they recompute from the keys and their challenge.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::registration::RegistrationKeys;
+
+/// Bumped whenever the serialized format of `KeyAttestation` or the report data changes
+pub const KEY_ATTESTATION_VERSION: u32 = 1;
+
+pub const CHALLENGE_LEN: usize = 32;
+const CHALLENGE_ARG: &str = "challenge";
+
+/// Separates the report data of key attestations from the one of registration
+const REPORT_DATA_DOMAIN: &[u8] = b"secret-key-attestation-v1";
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct KeyAttestation {
+    pub version: u32,
+    pub keys: RegistrationKeys,
+    /// The base64 encoded challenge of the verifier
+    pub challenge: String,
+    /// The hex encoded `report_data` the report of the certificate commits to
+    pub report_data: String,
+    /// The base64 encoded DER certificate signed by the enclave, which contains the attestation report
+    pub certificate: String,
+}
+
+/// The report data of the statement for `keys`, the registration keys as returned by the enclave
+pub fn report_data(keys: &[u8], challenge: &[u8; CHALLENGE_LEN]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(REPORT_DATA_DOMAIN);
+    hasher.update(keys);
+    hasher.update(challenge);
+    hasher.finalize().into()
+}
+
+pub fn parse_challenge(challenge: &[u8]) -> Result<[u8; CHALLENGE_LEN], Error> {
+    if challenge.len() != CHALLENGE_LEN {
+        let msg = format!("expected {} bytes, got {}", CHALLENGE_LEN, challenge.len());
+        return Err(Error::invalid_arg(CHALLENGE_ARG, msg));
+    }
+    let mut out = [0u8; CHALLENGE_LEN];
+    out.copy_from_slice(challenge);
+    Ok(out)
+}
+
+impl KeyAttestation {
+    pub fn new(
+        keys: &[u8],
+        challenge: &[u8; CHALLENGE_LEN],
+        certificate: &[u8],
+    ) -> Result<Self, Error> {
+        Ok(KeyAttestation {
+            version: KEY_ATTESTATION_VERSION,
+            keys: RegistrationKeys::from_enclave(keys)?,
+            challenge: base64::encode(challenge),
+            report_data: hex::encode(report_data(keys, challenge)),
+            certificate: base64::encode(certificate),
+        })
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::vm_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> Vec<u8> {
+        [[1u8; 32], [2u8; 32]].concat()
+    }
+
+    #[test]
+    fn report_data_commits_to_keys_and_challenge() {
+        let data = report_data(&keys(), &[3u8; 32]);
+        // sha256("secret-key-attestation-v1" || 0x01 * 32 || 0x02 * 32 || 0x03 * 32)
+        assert_eq!(
+            hex::encode(data),
+            "d9b5990b63520275bc393cf0eb5afd90df222936904dd1e770ed2bac8d8be2f3"
+        );
+        assert_ne!(data, report_data(&keys(), &[4u8; 32]));
+        assert_ne!(
+            data,
+            report_data(&[[1u8; 32], [1u8; 32]].concat(), &[3u8; 32])
+        );
+    }
+
+    #[test]
+    fn new_encodes_the_statement() {
+        let attestation = KeyAttestation::new(&keys(), &[3u8; 32], b"der").unwrap();
+        assert_eq!(attestation.version, KEY_ATTESTATION_VERSION);
+        assert_eq!(
+            attestation.keys,
+            RegistrationKeys::from_enclave(&keys()).unwrap()
+        );
+        assert_eq!(attestation.challenge, base64::encode(&[3u8; 32]));
+        assert_eq!(
+            attestation.report_data,
+            hex::encode(report_data(&keys(), &[3u8; 32]))
+        );
+        assert_eq!(attestation.certificate, base64::encode(b"der"));
+
+        assert!(KeyAttestation::new(&keys()[1..], &[3u8; 32], b"der").is_err());
+    }
+
+    #[test]
+    fn parse_challenge_checks_length() {
+        assert_eq!(parse_challenge(&[5u8; 32]).unwrap(), [5u8; 32]);
+        match parse_challenge(&[5u8; 31]).unwrap_err() {
+            Error::InvalidArg { name, .. } => assert_eq!(name, CHALLENGE_ARG),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+}
@@ -1,54 +1,240 @@
+mod analysis;
 mod api;
+mod async_calls;
+mod attestation;
+mod audit_log;
+mod benchmark;
+mod block_context;
+mod block_hooks;
+mod bootstrap_quorum;
+mod cache;
+mod call_depth;
+mod call_hooks;
+mod call_id;
+mod capabilities;
+#[cfg(feature = "cli")]
+pub mod cli;
+mod code_archive;
+mod code_stats;
+mod consensus_params;
+mod contract_address;
+mod crash_report;
 mod db;
+mod enclave_calls;
+mod enclave_log;
+mod enclave_pairing;
+mod enclave_reload;
+mod env_encoding;
+mod envelope;
 mod error;
+mod events;
+mod executor;
+mod float_policy;
+mod gas_checkpoint;
 mod gas_meter;
+mod gas_report;
+mod gas_schedule;
+mod host_calls;
+mod init_node;
+mod integrity;
+mod internal_calls;
 mod iterator;
+mod key_attestation;
 mod memory;
+mod migrate_auth;
+mod module_format;
+mod msg_checks;
+mod namespace;
+mod open_iterators;
+mod permit;
+mod plaintext;
 mod querier;
+mod query_batch;
+mod query_cache;
+mod query_limits;
+mod query_routes;
+mod reentrancy;
+mod registration;
+mod resume_token;
+mod run;
+mod seed_exchange;
+#[cfg(feature = "server")]
+pub mod server;
+mod shadow;
+mod shared_region;
+mod snapshot;
+mod state_export;
+mod state_keys;
+mod tcb_recovery;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod tests;
+mod unlock_key;
+mod usage_journal;
+mod vm;
+mod wasm_costs;
 
 pub use api::GoApi;
-pub use db::{db_t, DB};
+pub use benchmark::BenchmarkKind;
+pub use block_context::BlockContext;
+pub use db::{db_t, ResolvedContract, DB};
+pub use gas_checkpoint::GasCheckpointInfo;
+pub use gas_report::GasReport;
+pub use init_node::InitNodeResult;
 pub use memory::{free_rust, Buffer};
 pub use querier::GoQuerier;
+pub use state_export::ExportMode;
 
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
 use std::str::from_utf8;
+use std::sync::Once;
 // use std::Vec;
 
-use crate::error::{clear_error, handle_c_error, set_error, Error};
-
-use cosmwasm_sgx_vm::untrusted_init_bootstrap;
-use cosmwasm_sgx_vm::{
-    call_handle_raw, call_init_raw, call_migrate_raw, call_query_raw, features_from_csv, Checksum,
-    CosmCache, Extern,
+use crate::async_calls::{OnDone, Outcome};
+use crate::attestation::IasResponse;
+use crate::block_hooks::{BlockHook, BlockMeta};
+use crate::bootstrap_quorum::{check_shares, Ceremony};
+use crate::cache::{Cache, CacheCell, CacheGuard, CacheOptions};
+use crate::call_depth::DepthGuard;
+use crate::call_id::CallIdScope;
+use crate::code_stats::{CodeStats, EntryPoint};
+use crate::consensus_params::ConsensusParams;
+use crate::enclave_calls::{EnclaveCall, Failure};
+use crate::enclave_log::EnclaveLogOptions;
+use crate::enclave_reload::EnclaveGuard;
+use crate::env_encoding::ENV_SCHEMA_VERSIONS;
+use crate::error::{clear_error, handle_c_error, set_error, Error, ERROR_TEMPLATES_VERSION};
+use crate::executor::ExecutorOptions;
+use crate::gas_report::GasTracker;
+use crate::integrity::VerifiedCodes;
+use crate::internal_calls::execute_internal_calls;
+use crate::key_attestation::KeyAttestation;
+use crate::memory::ArenaScope;
+use crate::migrate_auth::MigrateAuth;
+use crate::module_format::{ModuleFormat, Modules};
+use crate::plaintext::PlaintextCodes;
+use crate::query_batch::{BatchQuery, QueryOutcome};
+use crate::query_cache::QueryKey;
+use crate::query_limits::{QueryLimits, QueryPermit};
+use crate::query_routes::QueryRoutes;
+use crate::reentrancy::ReentrancyGuard;
+use crate::registration::RegistrationKeys;
+use crate::run::{run_contract, ContractCall};
+use crate::seed_exchange::SeedExchangeVersion;
+#[cfg(feature = "shadow")]
+use crate::shadow::{Recorder, Shadow};
+use crate::shared_region::SharedRegion;
+use crate::state_keys::KeyHistory;
+use crate::tcb_recovery::RegistrationRefresh;
+use crate::usage_journal::UsageJournal;
+use crate::vm::untrusted_init_bootstrap;
+use crate::vm::{
+    call_begin_block_raw, call_end_block_raw, call_handle_raw, call_init_raw, call_migrate_raw,
+    call_query_raw, features_from_csv, set_wasm_cost_table, Checksum, CosmCache, Extern, VmError,
 };
-use cosmwasm_sgx_vm::{
-    create_attestation_report_u, untrusted_get_encrypted_seed, untrusted_health_check,
-    untrusted_init_node, untrusted_key_gen,
+use crate::vm::{
+    create_attestation_report_u, create_attestation_report_v2_u, untrusted_combine_seed_shares,
+    untrusted_create_challenge_quote, untrusted_create_key_attestation,
+    untrusted_create_seed_share, untrusted_get_encrypted_seed_v2, untrusted_get_registration_keys,
+    untrusted_get_trusted_time, untrusted_health_check, untrusted_init_node, untrusted_key_gen,
+    untrusted_refresh_registration, untrusted_set_block_entropy, untrusted_verify_contract_key,
+    untrusted_verify_ias_report,
 };
+use crate::wasm_costs::{GasSchedule, WasmCostTable, WasmSchedules};
+
+#[cfg(feature = "test-keys")]
+use crate::vm::{untrusted_init_bootstrap_from_seed, untrusted_key_gen_from_seed};
 
-use ctor::ctor;
 use log::*;
 
-#[ctor]
-fn init_logger() {
-    simple_logger::init_with_level(log::Level::Info).unwrap();
+#[cfg(all(feature = "mimalloc", feature = "jemalloc"))]
+compile_error!("The features `mimalloc` and `jemalloc` cannot be enabled at the same time");
+
+#[cfg(feature = "mimalloc")]
+#[global_allocator]
+static GLOBAL: mimalloc_allocator::MiMalloc = mimalloc_allocator::MiMalloc;
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: jemallocator::Jemalloc = jemallocator::Jemalloc;
+
+static INIT_LOGGER: Once = Once::new();
+
+/// Sets up logging. Go calls this when loading the library, before calling anything else.
+///
+/// This used to run as a constructor function, but the linker drops those when linking the static library.
+#[no_mangle]
+pub extern "C" fn init_logger() {
+    INIT_LOGGER.call_once(|| {
+        // This fails if the host installed a logger already, which we then log to instead
+        let _ = crash_report::init_logger(log::Level::Info);
+    });
 }
 
 #[repr(C)]
 pub struct cache_t {}
 
-fn to_cache(ptr: *mut cache_t) -> Option<&'static mut CosmCache<DB, GoApi, GoQuerier>> {
+/// The cache behind `ptr`, once no other thread uses it, see `CacheCell`
+fn to_cache(ptr: *mut cache_t) -> Option<CacheGuard> {
+    lock_cache(ptr, false)
+}
+
+/// The cache behind `ptr` for a query, which may also take it while the call holding it is parked
+fn to_query_cache(ptr: *mut cache_t) -> Option<CacheGuard> {
+    lock_cache(ptr, true)
+}
+
+fn lock_cache(ptr: *mut cache_t, query: bool) -> Option<CacheGuard> {
     if ptr.is_null() {
         None
     } else {
-        let c = unsafe { &mut *(ptr as *mut CosmCache<DB, GoApi, GoQuerier>) };
-        Some(c)
+        let cell = unsafe { &*(ptr as *const CacheCell) };
+        Some(cell.lock(query))
     }
 }
 
+/// Hands a result to Go, through the cache's shared region if one is registered
+fn to_result_buffer(cache: *mut cache_t, data: Vec<u8>) -> Buffer {
+    let c = to_cache(cache);
+    match c.as_ref().and_then(|c| c.shared_region.as_ref()) {
+        Some(region) => region.write(data),
+        None => Buffer::from_vec(data),
+    }
+}
+
+// generated by build.rs, together with api/bindings.h
+include!(concat!(env!("OUT_DIR"), "/abi_fingerprint.rs"));
+
+/// A fingerprint of the functions and types exported by this library.
+///
+/// It equals the `ABI_FINGERPRINT` defined in the header generated alongside the library,
+/// so a caller compiled against another header can detect the mismatch before calling anything else.
+#[no_mangle]
+pub extern "C" fn abi_fingerprint() -> u64 {
+    ABI_FINGERPRINT
+}
+
+/// The version of the fixed error messages, e.g. the ones errors from the OS and the cache are mapped to and the
+/// reasons of malformed call messages.
+///
+/// Error messages can end up in transaction results, so all validators must run a library with the same version.
+#[no_mangle]
+pub extern "C" fn error_templates_version() -> u32 {
+    ERROR_TEMPLATES_VERSION
+}
+
+/// The versions of the env schema `params` may name in their `schema_version` field, as a JSON array, oldest
+/// first. See `env_encoding`.
+#[no_mangle]
+pub extern "C" fn env_schema_versions(err: Option<&mut Buffer>) -> Buffer {
+    let r = serde_json::to_vec(&ENV_SCHEMA_VERSIONS).map_err(Error::vm_err);
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
 #[no_mangle]
 pub extern "C" fn get_health_check(err: Option<&mut Buffer>) -> Buffer {
     match untrusted_health_check() {
@@ -63,6 +249,9 @@ pub extern "C" fn get_health_check(err: Option<&mut Buffer>) -> Buffer {
     }
 }
 
+/// Encrypts the consensus seed for the node which made the attestation certificate `cert`.
+///
+/// The version of the seed exchange is taken from the certificate, see `create_attestation_report_v2`.
 #[no_mangle]
 pub extern "C" fn get_encrypted_seed(cert: Buffer, err: Option<&mut Buffer>) -> Buffer {
     debug!("Called get_encrypted_seed");
@@ -73,21 +262,34 @@ pub extern "C" fn get_encrypted_seed(cert: Buffer, err: Option<&mut Buffer>) ->
         }
         Some(r) => r,
     };
-    info!("Hello from right before untrusted_get_encrypted_seed");
-    match untrusted_get_encrypted_seed(cert_slice) {
-        Err(e) => {
+    info!("Hello from right before untrusted_get_encrypted_seed_v2");
+    let cert = cert_slice.to_vec();
+    let res = enclave_calls::run(EnclaveCall::GetEncryptedSeed, move || {
+        let _enclave = EnclaveGuard::enter().map_err(Failure::Enclave)?;
+        match untrusted_get_encrypted_seed_v2(&cert) {
             // An error happened in the SGX sdk.
-            set_error(Error::enclave_err(e.to_string()), err);
-            Buffer::default()
-        }
-        Ok(Err(e)) => {
+            Err(e) => Err(Failure::Sdk(crash_report::sdk_failure(
+                "get_encrypted_seed",
+                e,
+            ))),
             // An error was returned from the enclave.
-            set_error(Error::enclave_err(e.to_string()), err);
+            Ok(Err(e)) => Err(Failure::Enclave(Error::enclave_err(e.to_string()))),
+            Ok(Ok(seed)) => Ok(seed),
+        }
+    })
+    .and_then(|seed| {
+        let version = SeedExchangeVersion::of(&seed).map_err(Error::enclave_err)?;
+        info!("Encrypted the seed with seed exchange {:?}", version);
+        Ok(seed)
+    });
+    match res {
+        Err(e) => {
+            set_error(e, err);
             Buffer::default()
         }
-        Ok(Ok(seed)) => {
+        Ok(seed) => {
             clear_error();
-            Buffer::from_vec(seed.to_vec())
+            Buffer::from_vec(seed)
         }
     }
 }
@@ -95,9 +297,15 @@ pub extern "C" fn get_encrypted_seed(cert: Buffer, err: Option<&mut Buffer>) ->
 #[no_mangle]
 pub extern "C" fn init_bootstrap(err: Option<&mut Buffer>) -> Buffer {
     info!("Hello from right before init_bootstrap");
-    match untrusted_init_bootstrap() {
+    let res = enclave_calls::run(EnclaveCall::InitBootstrap, || {
+        let _enclave = EnclaveGuard::enter().map_err(Failure::Enclave)?;
+        untrusted_init_bootstrap()
+            .map_err(|e| Failure::Sdk(crash_report::sdk_failure("init_bootstrap", e)))
+    });
+    audit_log::record("init_bootstrap", &[], &res);
+    match res {
         Err(e) => {
-            set_error(Error::enclave_err(e.to_string()), err);
+            set_error(e, err);
             Buffer::default()
         }
         Ok(r) => {
@@ -107,383 +315,2806 @@ pub extern "C" fn init_bootstrap(err: Option<&mut Buffer>) -> Buffer {
     }
 }
 
+/// Contributes this node to a bootstrap ceremony of several nodes, see `combine_seed_shares`.
+///
+/// `ceremony` is JSON like `{"id":"secret-4","threshold":2,"participants":["<base64 key>",..]}`, listing the
+/// seed exchange public keys of all participating enclaves. Returns this node's share, sealed to the other
+/// participants, which must be handed to them.
+#[no_mangle]
+pub extern "C" fn create_seed_share(ceremony: Buffer, err: Option<&mut Buffer>) -> Buffer {
+    let r = catch_unwind(|| {
+        let ceremony_bin =
+            unsafe { ceremony.read() }.ok_or_else(|| Error::empty_arg(CEREMONY_ARG))?;
+        let ceremony = Ceremony::parse(ceremony_bin)?.encode()?;
+        let _enclave = EnclaveGuard::enter()?;
+        let result = match untrusted_create_seed_share(&ceremony) {
+            // An error happened in the SGX sdk.
+            Err(e) => Err(crash_report::sdk_failure("create_seed_share", e)),
+            // An error was returned from the enclave.
+            Ok(Err(e)) => Err(Error::enclave_err(e.to_string())),
+            Ok(Ok(share)) => Ok(share),
+        };
+        audit_log::record("create_seed_share", &[ceremony_bin], &result);
+        result
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Derives the consensus seed from the shares of a bootstrap ceremony, in place of `init_bootstrap`.
+///
+/// `shares` are at least `threshold` shares returned by `create_seed_share` on the participants of `ceremony`,
+/// each prefixed with its length as big endian u32. Every participant combining shares of the same ceremony
+/// gets the same seed, and the same bootstrap public key is returned.
+#[no_mangle]
+pub extern "C" fn combine_seed_shares(
+    ceremony: Buffer,
+    shares: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(|| {
+        let ceremony_bin =
+            unsafe { ceremony.read() }.ok_or_else(|| Error::empty_arg(CEREMONY_ARG))?;
+        let shares_bin = unsafe { shares.read() }.ok_or_else(|| Error::empty_arg(SHARES_ARG))?;
+        let ceremony = Ceremony::parse(ceremony_bin)?;
+        let shares = check_shares(&ceremony, shares_bin)?;
+        let _enclave = EnclaveGuard::enter()?;
+        let result = match untrusted_combine_seed_shares(&ceremony.encode()?, &shares) {
+            // An error happened in the SGX sdk.
+            Err(e) => Err(crash_report::sdk_failure("combine_seed_shares", e)),
+            // An error was returned from the enclave.
+            Ok(Err(e)) => Err(Error::enclave_err(e.to_string())),
+            Ok(Ok(pubkey)) => Ok(pubkey.to_vec()),
+        };
+        audit_log::record("combine_seed_shares", &[ceremony_bin, shares_bin], &result);
+        result
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Initializes the node with the network's master certificate and the consensus seed encrypted to it.
+///
+/// On failure, the returned code tells what went wrong and `err` explains how to fix it.
 #[no_mangle]
 pub extern "C" fn init_node(
     master_cert: Buffer,
     encrypted_seed: Buffer,
     err: Option<&mut Buffer>,
-) -> bool {
+) -> InitNodeResult {
     let pk_slice = match unsafe { master_cert.read() } {
         None => {
             set_error(Error::empty_arg("master_cert"), err);
-            return false;
+            return InitNodeResult::InvalidInput;
         }
         Some(r) => r,
     };
     let encrypted_seed_slice = match unsafe { encrypted_seed.read() } {
         None => {
             set_error(Error::empty_arg("encrypted_seed"), err);
-            return false;
+            return InitNodeResult::InvalidInput;
         }
         Some(r) => r,
     };
-
-    match untrusted_init_node(pk_slice, encrypted_seed_slice) {
-        Ok(_) => {
-            clear_error();
-            true
+    match SeedExchangeVersion::of(encrypted_seed_slice) {
+        Ok(version) => info!("Initializing node with seed exchange {:?}", version),
+        Err(e) => {
+            set_error(e, err);
+            return InitNodeResult::InvalidInput;
         }
+    }
+    if let Err(e) = enclave_pairing::check() {
+        let result = match e {
+            Error::EnclaveMismatch { .. } => InitNodeResult::LibraryMismatch,
+            _ => InitNodeResult::EnclaveError,
+        };
+        set_error(e, err);
+        return result;
+    }
+
+    let params = [pk_slice, encrypted_seed_slice];
+    let result = match untrusted_init_node(pk_slice, encrypted_seed_slice) {
+        Ok(result) => InitNodeResult::from(result),
         Err(e) => {
-            set_error(Error::enclave_err(e.to_string()), err);
-            false
+            // An error happened in the SGX sdk.
+            let msg = format!("{}: {}", InitNodeResult::EnclaveError.message(), e);
+            let e = crash_report::sdk_failure("init_node", msg);
+            audit_log::record::<(), _>("init_node", &params, &Err(&e));
+            set_error(e, err);
+            return InitNodeResult::EnclaveError;
         }
+    };
+    let outcome = match result {
+        InitNodeResult::Success => Ok(()),
+        _ => Err(result.message()),
+    };
+    audit_log::record("init_node", &params, &outcome);
+    match result {
+        InitNodeResult::Success => clear_error(),
+        _ => set_error(Error::enclave_err(result.message()), err),
     }
+    result
 }
 
 #[no_mangle]
 pub extern "C" fn create_attestation_report(err: Option<&mut Buffer>) -> bool {
-    if let Err(status) = create_attestation_report_u() {
-        set_error(Error::enclave_err(status.to_string()), err);
+    let res = enclave_calls::run(EnclaveCall::CreateAttestationReport, || {
+        let _enclave = EnclaveGuard::enter().map_err(Failure::Enclave)?;
+        create_attestation_report_u().map_err(|status| {
+            Failure::Sdk(crash_report::sdk_failure(
+                "create_attestation_report",
+                status,
+            ))
+        })
+    });
+    if let Err(e) = res {
+        set_error(e, err);
         return false;
     }
     clear_error();
     true
 }
 
-fn to_extern(storage: DB, api: GoApi, querier: GoQuerier) -> Extern<DB, GoApi, GoQuerier> {
-    Extern {
-        storage,
-        api,
-        querier,
+/// Like `create_attestation_report`, but for version 2 of the seed exchange, which has forward secrecy.
+///
+/// The enclave generates an ephemeral key for this registration and puts it into the certificate, and
+/// the bootstrap node encrypts the seed with an ECDH secret of it instead of the registration key.
+/// The ephemeral key only lives in enclave memory, so `init_node` must run before the node restarts.
+#[no_mangle]
+pub extern "C" fn create_attestation_report_v2(err: Option<&mut Buffer>) -> bool {
+    let res = enclave_calls::run(EnclaveCall::CreateAttestationReport, || {
+        let _enclave = EnclaveGuard::enter().map_err(Failure::Enclave)?;
+        create_attestation_report_v2_u().map_err(|status| {
+            Failure::Sdk(crash_report::sdk_failure(
+                "create_attestation_report_v2",
+                status,
+            ))
+        })
+    });
+    if let Err(e) = res {
+        set_error(e, err);
+        return false;
     }
+    clear_error();
+    true
 }
 
+/// Has the enclave create a new attestation report and returns the JSON-encoded `RegistrationRefresh` the node
+/// registers again with, after its platform was updated, see `tcb_recovery`.
+///
+/// Fails with the `TcbOutdated` errno if IAS still tells that the platform must be updated. Like
+/// `create_attestation_report`, this goes through IAS and follows the policy of `set_enclave_call_policy`.
 #[no_mangle]
-pub extern "C" fn init_cache(
-    data_dir: Buffer,
-    supported_features: Buffer,
-    // TODO: remove unused cache size
-    _cache_size: usize,
+pub extern "C" fn refresh_registration(err: Option<&mut Buffer>) -> Buffer {
+    let r = catch_unwind(|| {
+        let keys = match untrusted_get_registration_keys() {
+            // An error happened in the SGX sdk.
+            Err(e) => return Err(crash_report::sdk_failure("refresh_registration", e)),
+            // An error was returned from the enclave, e.g. because no keys were generated yet.
+            Ok(Err(e)) => return Err(Error::enclave_err(e.to_string())),
+            Ok(Ok(keys)) => keys,
+        };
+        let (certificate, report) =
+            enclave_calls::run(EnclaveCall::CreateAttestationReport, || {
+                let _enclave = EnclaveGuard::enter().map_err(Failure::Enclave)?;
+                match untrusted_refresh_registration() {
+                    // An error happened in the SGX sdk.
+                    Err(e) => Err(Failure::Sdk(crash_report::sdk_failure(
+                        "refresh_registration",
+                        e,
+                    ))),
+                    // An error was returned from the enclave.
+                    Ok(Err(e)) => Err(Failure::Enclave(Error::enclave_err(e.to_string()))),
+                    Ok(Ok(refreshed)) => Ok(refreshed),
+                }
+            })?;
+        let result = RegistrationRefresh::new(&keys, &certificate, &report);
+        audit_log::record("refresh_registration", &[&certificate], &result);
+        result?.to_json()
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Replaces the enclave with the signed enclave binary at `path`, without restarting the node.
+///
+/// Waits until the running contract calls and enclave calls returned, and refuses new ones meanwhile.
+/// The new enclave must be signed with the same key as the old one to unseal the node's keys and seed.
+/// The cache is reopened afterwards, like by `update_supported_features`, so it drops the modules compiled by
+/// the old enclave.
+#[no_mangle]
+pub extern "C" fn reload_enclave(
+    cache: *mut cache_t,
+    path: Buffer,
     err: Option<&mut Buffer>,
-) -> *mut cache_t {
-    let r = catch_unwind(|| do_init_cache(data_dir, supported_features))
-        .unwrap_or_else(|_| Err(Error::panic()));
+) -> bool {
+    let r = catch_unwind(AssertUnwindSafe(|| {
+        if cache.is_null() {
+            return Err(Error::empty_arg(CACHE_ARG));
+        }
+        let path = unsafe { path.read() }.ok_or_else(|| Error::empty_arg(PATH_ARG))?;
+        // the cache is only taken once the running calls returned, as they may hold it while they drain
+        let result = enclave_reload::reload(from_utf8(path)?, || match to_cache(cache) {
+            Some(mut c) => c.enclave_reloaded(),
+            None => Err(Error::empty_arg(CACHE_ARG)),
+        });
+        audit_log::record("reload_enclave", &[path], &result);
+        result
+    }))
+    .unwrap_or_else(|_| Err(Error::panic()));
     match r {
-        Ok(t) => {
+        Ok(()) => {
             clear_error();
-            t as *mut cache_t
+            true
         }
         Err(e) => {
             set_error(e, err);
-            std::ptr::null_mut()
+            false
         }
     }
 }
 
-// store some common string for argument names
-static DATA_DIR_ARG: &str = "data_dir";
-static FEATURES_ARG: &str = "supported_features";
-static CACHE_ARG: &str = "cache";
-static WASM_ARG: &str = "wasm";
-static CODE_ID_ARG: &str = "code_id";
-static MSG_ARG: &str = "msg";
-static PARAMS_ARG: &str = "params";
-static GAS_USED_ARG: &str = "gas_used";
+/// Returns the MRENCLAVE of the loaded enclave and the one this library was built for, as JSON like
+/// `{"expected":"<hex>","actual":"<hex>","matches":true}`. `expected` is null if the build does not check it.
+#[no_mangle]
+pub extern "C" fn get_enclave_pairing(err: Option<&mut Buffer>) -> Buffer {
+    let r = catch_unwind(|| {
+        let pairing = enclave_pairing::pairing()?;
+        serde_json::to_vec(&pairing).map_err(Error::vm_err)
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
 
-fn do_init_cache(
-    data_dir: Buffer,
-    supported_features: Buffer,
-) -> Result<*mut CosmCache<DB, GoApi, GoQuerier>, Error> {
-    let dir = unsafe { data_dir.read() }.ok_or_else(|| Error::empty_arg(DATA_DIR_ARG))?;
-    let dir_str = from_utf8(dir)?;
-    // parse the supported features
-    let features_bin =
-        unsafe { supported_features.read() }.ok_or_else(|| Error::empty_arg(FEATURES_ARG))?;
-    let features_str = from_utf8(features_bin)?;
-    let features = features_from_csv(features_str);
-    let cache = unsafe { CosmCache::new(dir_str, features) }?;
-    let out = Box::new(cache);
-    Ok(Box::into_raw(out))
+/// Returns the diagnostics of the last enclave call failing in the SGX SDK or contract execution failing in
+/// the VM, as JSON-encoded `CrashReport`, for attaching to bug reports. Empty if nothing failed since startup.
+#[no_mangle]
+pub extern "C" fn get_last_crash_report(err: Option<&mut Buffer>) -> Buffer {
+    let r = catch_unwind(|| match crash_report::last_report() {
+        Some(report) => serde_json::to_vec(&report).map_err(Error::vm_err),
+        None => Ok(Vec::new()),
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
 }
 
-/// frees a cache reference
+/// Sets the timeout and retries of `attestation_challenge`, `create_attestation_report`,
+/// `create_key_attestation`, `get_encrypted_seed` and `init_bootstrap`.
 ///
-/// # Safety
+/// `policy` is JSON like `{"timeout_ms":30000,"retries":3,"backoff_ms":500}`, where omitted fields are 0.
+/// A timeout of 0 waits forever. Calls which time out fail with the `Timeout` errno and can be retried.
+#[no_mangle]
+pub extern "C" fn set_enclave_call_policy(policy: Buffer, err: Option<&mut Buffer>) -> bool {
+    let res = unsafe { policy.read() }
+        .ok_or_else(|| Error::empty_arg(POLICY_ARG))
+        .and_then(|policy| {
+            serde_json::from_slice(policy).map_err(|e| Error::invalid_arg(POLICY_ARG, e))
+        });
+    match res {
+        Ok(policy) => {
+            enclave_calls::set_policy(policy);
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+/// Limits the queries started by Go, see `query_limits`.
 ///
-/// This must be called exactly once for any `*cache_t` returned by `init_cache`
-/// and cannot be called on any other pointer.
+/// `limits` is JSON like `{"per_second":100,"burst":200,"max_concurrent":8}`, where omitted fields are 0,
+/// which disables the limit. Queries over the limits fail with the `Throttled` errno.
 #[no_mangle]
-pub extern "C" fn release_cache(cache: *mut cache_t) {
-    if !cache.is_null() {
-        // this will free cache when it goes out of scope
-        let _ = unsafe { Box::from_raw(cache as *mut CosmCache<DB, GoApi, GoQuerier>) };
+pub extern "C" fn set_query_limits(limits: Buffer, err: Option<&mut Buffer>) -> bool {
+    let res = unsafe { limits.read() }
+        .ok_or_else(|| Error::empty_arg(LIMITS_ARG))
+        .and_then(|limits| {
+            serde_json::from_slice::<QueryLimits>(limits)
+                .map_err(|e| Error::invalid_arg(LIMITS_ARG, e))
+        });
+    match res {
+        Ok(limits) => {
+            query_limits::set_limits(limits);
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
     }
 }
 
+/// Sets how the calls of `handle_async` and `query_async` are queued, see `executor`.
+///
+/// `options` is JSON like `{"max_queued":1000}`. The queue limit defaults to none. Calls over the limit fail with
+/// the `Throttled` errno.
 #[no_mangle]
-pub extern "C" fn create(cache: *mut cache_t, wasm: Buffer, err: Option<&mut Buffer>) -> Buffer {
-    let r = match to_cache(cache) {
-        Some(c) => catch_unwind(AssertUnwindSafe(move || do_create(c, wasm)))
-            .unwrap_or_else(|_| Err(Error::panic())),
-        None => Err(Error::empty_arg(CACHE_ARG)),
-    };
+pub extern "C" fn set_executor_options(options: Buffer, err: Option<&mut Buffer>) -> bool {
+    let res = unsafe { options.read() }
+        .ok_or_else(|| Error::empty_arg(OPTIONS_ARG))
+        .and_then(ExecutorOptions::from_json);
+    match res {
+        Ok(options) => {
+            executor::set_options(options);
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+/// Returns the JSON `ExecutorStats` of the calls of `handle_async` and `query_async`: the calls running and
+/// queued, and the calls run and rejected so far
+#[no_mangle]
+pub extern "C" fn get_executor_stats(err: Option<&mut Buffer>) -> Buffer {
+    let r = catch_unwind(|| serde_json::to_vec(&executor::stats()).map_err(Error::vm_err))
+        .unwrap_or_else(|_| Err(Error::panic()));
     let data = handle_c_error(r, err);
     Buffer::from_vec(data)
 }
 
-fn do_create(cache: &mut CosmCache<DB, GoApi, GoQuerier>, wasm: Buffer) -> Result<Checksum, Error> {
-    let wasm = unsafe { wasm.read() }.ok_or_else(|| Error::empty_arg(WASM_ARG))?;
-    let checksum = cache.save_wasm(wasm)?;
-    Ok(checksum)
+/// Buffers the log lines of the enclave instead of printing them to stdout, see `drain_enclave_log`.
+///
+/// `options` is JSON like `{"level":"debug","max_lines":1000,"max_line_len":1024}`, where omitted fields take
+/// these defaults except for `level`, which defaults to `info`. Calling it again changes the options.
+#[no_mangle]
+pub extern "C" fn init_enclave_log(options: Buffer, err: Option<&mut Buffer>) -> bool {
+    let r = catch_unwind(|| {
+        let options = unsafe { options.read() }.ok_or_else(|| Error::empty_arg(OPTIONS_ARG))?;
+        enclave_log::init(&EnclaveLogOptions::parse(options)?)
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    match r {
+        Ok(()) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
 }
 
+/// Takes the enclave's log lines buffered since the last call, as JSON like
+/// `{"lines":[{"time_ms":1600000000000,"level":"info","message":"..","call_id":null}],"dropped":0}`.
+/// `call_id` is the id passed to the contract call which logged the line, and `dropped` counts
+/// the lines lost because the buffer was full. There are no lines if `init_enclave_log` was not called.
 #[no_mangle]
-pub extern "C" fn get_code(cache: *mut cache_t, id: Buffer, err: Option<&mut Buffer>) -> Buffer {
-    let r = match to_cache(cache) {
-        Some(c) => catch_unwind(AssertUnwindSafe(move || do_get_code(c, id)))
-            .unwrap_or_else(|_| Err(Error::panic())),
-        None => Err(Error::empty_arg(CACHE_ARG)),
-    };
+pub extern "C" fn drain_enclave_log(err: Option<&mut Buffer>) -> Buffer {
+    let r = catch_unwind(|| serde_json::to_vec(&enclave_log::drain()).map_err(Error::vm_err))
+        .unwrap_or_else(|_| Err(Error::panic()));
     let data = handle_c_error(r, err);
     Buffer::from_vec(data)
 }
 
-fn do_get_code(cache: &mut CosmCache<DB, GoApi, GoQuerier>, id: Buffer) -> Result<Vec<u8>, Error> {
-    let id: Checksum = unsafe { id.read() }
-        .ok_or_else(|| Error::empty_arg(CACHE_ARG))?
-        .try_into()?;
-    let wasm = cache.load_wasm(&id)?;
-    Ok(wasm)
+/// Records the privileged operations of the node in `audit.log` in `data_dir` from now on, see `audit_log`.
+///
+/// Entries are appended to those already in the file. Calling it again moves the log to another data dir.
+#[no_mangle]
+pub extern "C" fn init_audit_log(data_dir: Buffer, err: Option<&mut Buffer>) -> bool {
+    let r = catch_unwind(|| {
+        let data_dir = unsafe { data_dir.read() }.ok_or_else(|| Error::empty_arg(DATA_DIR_ARG))?;
+        audit_log::init(Path::new(from_utf8(data_dir)?))
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    match r {
+        Ok(()) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
 }
 
+/// Returns all entries of the audit log as a JSON list like
+/// `[{"time_ms":1600000000000,"operation":"init_node","params_hash":"<hex>","error":null}]`, oldest first.
+/// `params_hash` is the SHA-256 of the parameters, each prefixed with its length as big endian u64. The list
+/// is empty if `init_audit_log` was not called.
 #[no_mangle]
-pub extern "C" fn instantiate(
-    cache: *mut cache_t,
-    contract_id: Buffer,
-    params: Buffer,
-    msg: Buffer,
-    db: DB,
-    api: GoApi,
-    querier: GoQuerier,
-    gas_limit: u64,
-    gas_used: Option<&mut u64>,
-    err: Option<&mut Buffer>,
-) -> Buffer {
-    let r = match to_cache(cache) {
-        Some(c) => catch_unwind(AssertUnwindSafe(move || {
-            do_init(
-                c,
-                contract_id,
-                params,
-                msg,
-                db,
-                api,
-                querier,
-                gas_limit,
-                gas_used,
-            )
-        }))
-        .unwrap_or_else(|_| Err(Error::panic())),
-        None => Err(Error::empty_arg(CACHE_ARG)),
-    };
+pub extern "C" fn read_audit_log(err: Option<&mut Buffer>) -> Buffer {
+    let r = catch_unwind(|| serde_json::to_vec(&audit_log::read()?).map_err(Error::vm_err))
+        .unwrap_or_else(|_| Err(Error::panic()));
     let data = handle_c_error(r, err);
     Buffer::from_vec(data)
 }
 
-fn do_init(
-    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
-    code_id: Buffer,
-    params: Buffer,
-    msg: Buffer,
-    db: DB,
-    api: GoApi,
-    querier: GoQuerier,
-    gas_limit: u64,
-    gas_used: Option<&mut u64>,
-) -> Result<Vec<u8>, Error> {
-    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
-    let code_id: Checksum = unsafe { code_id.read() }
-        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
-        .try_into()?;
-    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+/// Sets the unlock key of the operator, which the enclave mixes into the sealing of the node's keys and seed,
+/// see `unlock_key`.
+///
+/// Must be called before `init_node` and before the first contract call, each time the node starts, with at
+/// least 16 bytes. Setting the same key again does nothing, and another key fails until the node restarts.
+#[no_mangle]
+pub extern "C" fn set_unlock_key(key: Buffer, err: Option<&mut Buffer>) -> bool {
+    let r = catch_unwind(|| {
+        let key = unsafe { key.read() }.ok_or_else(|| Error::empty_arg(UNLOCK_KEY_ARG))?;
+        let _enclave = EnclaveGuard::enter()?;
+        unlock_key::set(key)
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    match r {
+        Ok(()) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+// the per-block seed the enclave derives contract randomness from
+const BLOCK_ENTROPY_LEN: usize = 32;
+
+/// Hands the randomness seed of the block at `height` to the enclave.
+///
+/// Must be called before executing the block's first contract call. The enclave derives the random
+/// bytes it gives each contract from this seed, the contract address and the message, so they are
+/// the same on every node but cannot be chosen by the contract or its caller.
+#[no_mangle]
+pub extern "C" fn set_block_entropy(
+    height: u64,
+    entropy: Buffer,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let entropy_slice = match unsafe { entropy.read() } {
+        None => {
+            set_error(Error::empty_arg("entropy"), err);
+            return false;
+        }
+        Some(r) => r,
+    };
+    if entropy_slice.len() != BLOCK_ENTROPY_LEN {
+        let msg = format!(
+            "expected {} bytes, got {}",
+            BLOCK_ENTROPY_LEN,
+            entropy_slice.len()
+        );
+        set_error(Error::invalid_arg("entropy", msg), err);
+        return false;
+    }
+    let _enclave = match EnclaveGuard::enter() {
+        Ok(guard) => guard,
+        Err(e) => {
+            set_error(e, err);
+            return false;
+        }
+    };
+
+    match untrusted_set_block_entropy(height, entropy_slice) {
+        Err(e) => {
+            // An error happened in the SGX sdk.
+            set_error(crash_report::sdk_failure("set_block_entropy", e), err);
+            false
+        }
+        Ok(Err(e)) => {
+            // An error was returned from the enclave.
+            set_error(Error::enclave_err(e.to_string()), err);
+            false
+        }
+        Ok(Ok(())) => {
+            clear_error();
+            true
+        }
+    }
+}
+
+/// Reads the enclave's trusted clock.
+///
+/// Returns the number of seconds since an arbitrary reference point and writes the nonce identifying
+/// that reference point to `time_source`. Two readings may only be compared if their time sources are
+/// equal. Fails if the platform does not provide trusted time.
+#[no_mangle]
+pub extern "C" fn get_trusted_time(
+    time_source: Option<&mut Buffer>,
+    err: Option<&mut Buffer>,
+) -> u64 {
+    let time_source = match time_source {
+        None => {
+            set_error(Error::empty_arg("time_source"), err);
+            return 0;
+        }
+        Some(r) => r,
+    };
+    let _enclave = match EnclaveGuard::enter() {
+        Ok(guard) => guard,
+        Err(e) => {
+            set_error(e, err);
+            return 0;
+        }
+    };
+
+    match untrusted_get_trusted_time() {
+        Err(e) => {
+            // An error happened in the SGX sdk.
+            set_error(crash_report::sdk_failure("get_trusted_time", e), err);
+            0
+        }
+        Ok(Err(e)) => {
+            // An error was returned from the enclave, e.g. because the platform lacks trusted time.
+            set_error(Error::enclave_err(e.to_string()), err);
+            0
+        }
+        Ok(Ok((seconds, source))) => {
+            clear_error();
+            *time_source = Buffer::from_vec(source.to_vec());
+            seconds
+        }
+    }
+}
+
+/// Verifies a query permit for the contract at `contract_addr` and returns the canonical address of its signer.
+///
+/// This performs the same checks as the contracts do: the permit must list `contract_addr` in its
+/// allowed tokens, have a secp256k1 key, and its signature must be valid for the amino sign doc of its params,
+/// with a zero fee in `fee_denom`, the fee denom of the chain.
+#[no_mangle]
+pub extern "C" fn verify_permit(
+    permit: Buffer,
+    contract_addr: Buffer,
+    fee_denom: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(|| do_verify_permit(permit, contract_addr, fee_denom))
+        .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+fn do_verify_permit(
+    permit: Buffer,
+    contract_addr: Buffer,
+    fee_denom: Buffer,
+) -> Result<Vec<u8>, Error> {
+    let permit = unsafe { permit.read() }.ok_or_else(|| Error::empty_arg(PERMIT_ARG))?;
+    let contract_addr =
+        unsafe { contract_addr.read() }.ok_or_else(|| Error::empty_arg(CONTRACT_ADDR_ARG))?;
+    let fee_denom = unsafe { fee_denom.read() }.ok_or_else(|| Error::empty_arg(FEE_DENOM_ARG))?;
+    let _enclave = EnclaveGuard::enter()?;
+    permit::verify_permit(permit, from_utf8(contract_addr)?, from_utf8(fee_denom)?)
+}
+
+/// Checks that `contract_key` was issued by the enclave for the contract at `contract_addr` running
+/// the code with the (32 byte) `code_hash`.
+///
+/// Returns false with no error set if the key is well-formed but does not match.
+#[no_mangle]
+pub extern "C" fn verify_contract_key(
+    contract_addr: Buffer,
+    code_hash: Buffer,
+    contract_key: Buffer,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let r = catch_unwind(|| do_verify_contract_key(contract_addr, code_hash, contract_key))
+        .unwrap_or_else(|_| Err(Error::panic()));
+    match r {
+        Ok(valid) => {
+            clear_error();
+            valid
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+fn do_verify_contract_key(
+    contract_addr: Buffer,
+    code_hash: Buffer,
+    contract_key: Buffer,
+) -> Result<bool, Error> {
+    let contract_addr =
+        unsafe { contract_addr.read() }.ok_or_else(|| Error::empty_arg(CONTRACT_ADDR_ARG))?;
+    let code_hash: Checksum = unsafe { code_hash.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_HASH_ARG))?
+        .try_into()?;
+    let contract_key =
+        unsafe { contract_key.read() }.ok_or_else(|| Error::empty_arg(CONTRACT_KEY_ARG))?;
+    if contract_key.len() != CONTRACT_KEY_LEN {
+        let msg = format!(
+            "expected {} bytes, got {}",
+            CONTRACT_KEY_LEN,
+            contract_key.len()
+        );
+        return Err(Error::invalid_arg(CONTRACT_KEY_ARG, msg));
+    }
+
+    let _enclave = EnclaveGuard::enter()?;
+    match untrusted_verify_contract_key(from_utf8(contract_addr)?, &code_hash, contract_key) {
+        // An error happened in the SGX sdk.
+        Err(e) => Err(crash_report::sdk_failure("verify_contract_key", e)),
+        // An error was returned from the enclave.
+        Ok(Err(e)) => Err(Error::enclave_err(e.to_string())),
+        Ok(Ok(valid)) => Ok(valid),
+    }
+}
+
+/// Adds a finished contract call to the gas checkpoint `previous` of its transaction and returns the new checkpoint.
+///
+/// `gas_used` and `report` are what the call returned. If `previous` is empty, this starts the checkpoint of a
+/// transaction with `gas_limit`, which is ignored otherwise. Fails with out of gas once the calls consumed more
+/// than the limit of the transaction.
+#[no_mangle]
+pub extern "C" fn export_gas_checkpoint(
+    previous: Buffer,
+    gas_limit: u64,
+    gas_used: u64,
+    report: GasReport,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(|| {
+        let previous = unsafe { previous.read() }.filter(|token| !token.is_empty());
+        gas_checkpoint::export(previous, gas_limit, gas_used, &report)
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Reads the gas a transaction consumed so far, and the gas limit for its next contract call, from a gas checkpoint.
+#[no_mangle]
+pub extern "C" fn import_gas_checkpoint(
+    checkpoint: Buffer,
+    err: Option<&mut Buffer>,
+) -> GasCheckpointInfo {
+    let r = catch_unwind(|| {
+        let checkpoint =
+            unsafe { checkpoint.read() }.ok_or_else(|| Error::empty_arg(CHECKPOINT_ARG))?;
+        gas_checkpoint::import(checkpoint)
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    match r {
+        Ok(info) => {
+            clear_error();
+            info
+        }
+        Err(e) => {
+            set_error(e, err);
+            GasCheckpointInfo::default()
+        }
+    }
+}
+
+/// Checks that `wasm` has the (32 byte) checksum `expected`, which is its code id, e.g. when importing codes from genesis.
+///
+/// Returns false with no error set if the checksum does not match.
+#[no_mangle]
+pub extern "C" fn validate_checksum(
+    wasm: Buffer,
+    expected: Buffer,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let r = catch_unwind(|| do_validate_checksum(wasm, expected))
+        .unwrap_or_else(|_| Err(Error::panic()));
+    match r {
+        Ok(valid) => {
+            clear_error();
+            valid
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+fn do_validate_checksum(wasm: Buffer, expected: Buffer) -> Result<bool, Error> {
+    let wasm = unsafe { wasm.read() }.ok_or_else(|| Error::empty_arg(WASM_ARG))?;
+    let expected: Checksum = unsafe { expected.read() }
+        .ok_or_else(|| Error::empty_arg(CHECKSUM_ARG))?
+        .try_into()?;
+    Ok(Checksum::generate(wasm) == expected)
+}
+
+/// Derives the address of the contract `creator` instantiates from the code `checksum` with `salt`, see
+/// `contract_address`.
+///
+/// `creator` is a canonical address and `salt` 1 to 64 bytes. If `msg` is not empty, the address also depends
+/// on it, so the contract can only be instantiated at the address with exactly this init message.
+#[no_mangle]
+pub extern "C" fn derive_contract_address(
+    checksum: Buffer,
+    creator: Buffer,
+    salt: Buffer,
+    msg: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(|| {
+        let checksum = unsafe { checksum.read() }.ok_or_else(|| Error::empty_arg(CHECKSUM_ARG))?;
+        let creator = unsafe { creator.read() }.ok_or_else(|| Error::empty_arg(CREATOR_ARG))?;
+        let salt = unsafe { salt.read() }.ok_or_else(|| Error::empty_arg(SALT_ARG))?;
+        let msg = unsafe { msg.read() };
+        contract_address::derive(checksum, creator, salt, msg)
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Encrypts `plaintext` for the node with `node_pubkey`, the way clients send messages to secret contracts.
+///
+/// `ephemeral_secret` (the client's x25519 secret key) and `nonce` must be 32 random bytes each.
+/// Returns the envelope `nonce || ephemeral public key || ciphertext`.
+#[no_mangle]
+pub extern "C" fn encrypt_msg(
+    node_pubkey: Buffer,
+    ephemeral_secret: Buffer,
+    nonce: Buffer,
+    plaintext: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(|| {
+        let node_pubkey =
+            unsafe { node_pubkey.read() }.ok_or_else(|| Error::empty_arg(NODE_PUBKEY_ARG))?;
+        let ephemeral_secret = unsafe { ephemeral_secret.read() }
+            .ok_or_else(|| Error::empty_arg(EPHEMERAL_SECRET_ARG))?;
+        let nonce = unsafe { nonce.read() }.ok_or_else(|| Error::empty_arg(NONCE_ARG))?;
+        let plaintext = unsafe { plaintext.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+        envelope::encrypt_msg(node_pubkey, ephemeral_secret, nonce, plaintext)
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Decrypts `ciphertext`, which a contract returned in response to the message sent in `envelope`.
+///
+/// `ephemeral_secret` must be the secret used to produce `envelope` with `encrypt_msg`.
+#[no_mangle]
+pub extern "C" fn decrypt_response(
+    node_pubkey: Buffer,
+    ephemeral_secret: Buffer,
+    envelope: Buffer,
+    ciphertext: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(|| {
+        let node_pubkey =
+            unsafe { node_pubkey.read() }.ok_or_else(|| Error::empty_arg(NODE_PUBKEY_ARG))?;
+        let ephemeral_secret = unsafe { ephemeral_secret.read() }
+            .ok_or_else(|| Error::empty_arg(EPHEMERAL_SECRET_ARG))?;
+        let envelope = unsafe { envelope.read() }.ok_or_else(|| Error::empty_arg(ENVELOPE_ARG))?;
+        let ciphertext =
+            unsafe { ciphertext.read() }.ok_or_else(|| Error::empty_arg(CIPHERTEXT_ARG))?;
+        envelope::decrypt_response(node_pubkey, ephemeral_secret, envelope, ciphertext)
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Decrypts the answer of a query for gateways relaying queries of authenticated users, see `envelope`.
+///
+/// `ephemeral_secret` and `nonce` must be the ones the query message was encrypted with. `ciphertext` is the
+/// result of the query, and the answer of the contract is returned in plaintext.
+#[no_mangle]
+pub extern "C" fn decrypt_query_response(
+    node_pubkey: Buffer,
+    ephemeral_secret: Buffer,
+    nonce: Buffer,
+    ciphertext: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(|| {
+        let node_pubkey =
+            unsafe { node_pubkey.read() }.ok_or_else(|| Error::empty_arg(NODE_PUBKEY_ARG))?;
+        let ephemeral_secret = unsafe { ephemeral_secret.read() }
+            .ok_or_else(|| Error::empty_arg(EPHEMERAL_SECRET_ARG))?;
+        let nonce = unsafe { nonce.read() }.ok_or_else(|| Error::empty_arg(NONCE_ARG))?;
+        let ciphertext =
+            unsafe { ciphertext.read() }.ok_or_else(|| Error::empty_arg(CIPHERTEXT_ARG))?;
+        envelope::decrypt_query_response(node_pubkey, ephemeral_secret, nonce, ciphertext)
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+fn to_extern(storage: DB, api: GoApi, querier: GoQuerier) -> Extern<DB, GoApi, GoQuerier> {
+    Extern {
+        storage,
+        api,
+        querier,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn init_cache(
+    data_dir: Buffer,
+    supported_features: Buffer,
+    options: Buffer,
+    // TODO: remove unused cache size
+    _cache_size: usize,
+    err: Option<&mut Buffer>,
+) -> *mut cache_t {
+    let r = catch_unwind(|| do_init_cache(data_dir, supported_features, options))
+        .unwrap_or_else(|_| Err(Error::panic()));
+    match r {
+        Ok(t) => {
+            clear_error();
+            t as *mut cache_t
+        }
+        Err(e) => {
+            set_error(e, err);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+// store some common string for argument names
+static DATA_DIR_ARG: &str = "data_dir";
+static FEATURES_ARG: &str = "supported_features";
+static OPTIONS_ARG: &str = "options";
+static CACHE_ARG: &str = "cache";
+static WASM_ARG: &str = "wasm";
+static ARCHIVE_ARG: &str = "archive";
+static CODE_ID_ARG: &str = "code_id";
+static MSG_ARG: &str = "msg";
+static PARAMS_ARG: &str = "params";
+static GAS_USED_ARG: &str = "gas_used";
+static CHECKPOINT_ARG: &str = "checkpoint";
+static POLICY_ARG: &str = "policy";
+static LIMITS_ARG: &str = "limits";
+static PATH_ARG: &str = "path";
+static CEREMONY_ARG: &str = "ceremony";
+static SHARES_ARG: &str = "shares";
+static PERMIT_ARG: &str = "permit";
+static CONTRACT_ADDR_ARG: &str = "contract_addr";
+static FEE_DENOM_ARG: &str = "fee_denom";
+static CODE_HASH_ARG: &str = "code_hash";
+static CHECKSUM_ARG: &str = "checksum";
+static CONTRACT_KEY_ARG: &str = "contract_key";
+static KEY_HISTORY_ARG: &str = "key_history";
+static NODE_PUBKEY_ARG: &str = "node_pubkey";
+static EPHEMERAL_SECRET_ARG: &str = "ephemeral_secret";
+static NONCE_ARG: &str = "nonce";
+static PAYLOAD_ARG: &str = "payload";
+static ENVELOPE_ARG: &str = "envelope";
+static CIPHERTEXT_ARG: &str = "ciphertext";
+static UNLOCK_KEY_ARG: &str = "unlock_key";
+static CHALLENGE_ARG: &str = "challenge";
+static KEYS_ARG: &str = "keys";
+static QUOTE_ARG: &str = "quote";
+static RESPONSE_ARG: &str = "response";
+static CREATOR_ARG: &str = "creator";
+static SALT_ARG: &str = "salt";
+static BLOCK_ARG: &str = "block";
+static QUERIES_ARG: &str = "queries";
+static ROUTES_ARG: &str = "routes";
+static SCHEDULE_ARG: &str = "schedule";
+static ALIAS_ARG: &str = "alias";
+static ARGS_ARG: &str = "args";
+static CONSENSUS_PARAMS_ARG: &str = "consensus_params";
+#[cfg(feature = "test-keys")]
+static SEED_ARG: &str = "seed";
+
+// the output of `instantiate` starts with the contract key
+const CONTRACT_KEY_LEN: usize = 64;
+
+fn do_init_cache(
+    data_dir: Buffer,
+    supported_features: Buffer,
+    options: Buffer,
+) -> Result<*mut CacheCell, Error> {
+    let dir = unsafe { data_dir.read() }.ok_or_else(|| Error::empty_arg(DATA_DIR_ARG))?;
+    let dir_str = from_utf8(dir)?;
+    // parse the supported features
+    let features_bin =
+        unsafe { supported_features.read() }.ok_or_else(|| Error::empty_arg(FEATURES_ARG))?;
+    let features_str = from_utf8(features_bin)?;
+    let features = features_from_csv(features_str);
+    let options = unsafe { options.read() }.ok_or_else(|| Error::empty_arg(OPTIONS_ARG))?;
+    let options = CacheOptions::from_json(options)?;
+    enclave_pairing::check()?;
+    #[cfg(feature = "shadow")]
+    let shadow = if options.shadow_execution {
+        Some(Shadow::new(dir_str.as_ref(), features.clone())?)
+    } else {
+        None
+    };
+    // keep the modules compiled under the last consensus params, until Go sets the params again
+    let tagged = Modules::tagged(Path::new(dir_str))?;
+    let canonicalize_nans = matches!(
+        tagged,
+        Some(ModuleFormat {
+            canonicalize_nans: true,
+            ..
+        })
+    );
+    let format = ModuleFormat::current(canonicalize_nans)?;
+    let modules = options.open_modules(Path::new(dir_str), format)?;
+    let inner = unsafe { CosmCache::new(dir_str, features.clone()) }?;
+    let costs = WasmCostTable::new(&options.wasm_costs)?;
+    set_wasm_cost_table(costs.costs());
+    let query_cache = options.query_cache();
+    let usage = UsageJournal::load(Path::new(dir_str));
+    usage_journal::warm_up(Path::new(dir_str), options.warm_up_codes(&usage));
+    let out = Box::new(CacheCell::new(Cache {
+        inner,
+        options,
+        consensus_params: None,
+        data_dir: dir_str.into(),
+        supported_features: features,
+        shared_region: None,
+        code_stats: CodeStats::default(),
+        verified_codes: VerifiedCodes::default(),
+        memory_pages: HashMap::new(),
+        float_codes: HashMap::new(),
+        wasm_schedules: WasmSchedules::new(costs),
+        modules,
+        usage,
+        plaintext_codes: PlaintextCodes::load(dir_str.as_ref())?,
+        paused_codes: HashSet::new(),
+        query_routes: QueryRoutes::default(),
+        query_cache,
+        #[cfg(feature = "shadow")]
+        shadow,
+    }));
+    Ok(Box::into_raw(out))
+}
+
+/// frees a cache reference, once the calls of `handle_async` and `query_async` using it are done
+///
+/// # Safety
+///
+/// This must be called exactly once for any `*cache_t` returned by `init_cache`
+/// and cannot be called on any other pointer.
+#[no_mangle]
+pub extern "C" fn release_cache(cache: *mut cache_t) {
+    if let Err(e) = reentrancy::check_outside_calls("release_cache") {
+        // nothing to return the error to, and freeing the cache would pull it from under the running contract
+        error!("Not releasing the cache: {}", e);
+        return;
+    }
+    if !cache.is_null() {
+        // the queued calls take the cache from their own thread
+        async_calls::wait_for(cache as usize);
+        // this will free cache when it goes out of scope
+        let _ = unsafe { Box::from_raw(cache as *mut CacheCell) };
+    }
+}
+
+/// Replaces the features contracts may require, given as comma separated list like in `init_cache`.
+///
+/// Lets a chain upgrade enable a new capability without re-creating the cache. The modules compiled in
+/// memory are dropped, so the next call of every code is slower.
+#[no_mangle]
+pub extern "C" fn update_supported_features(
+    cache: *mut cache_t,
+    supported_features: Buffer,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            reentrancy::check_outside_calls("update_supported_features")?;
+            let features = unsafe { supported_features.read() }
+                .ok_or_else(|| Error::empty_arg(FEATURES_ARG))?;
+            c.update_supported_features(features_from_csv(from_utf8(features)?))
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    match r {
+        Ok(()) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+/// Replaces the consensus params of the chain with the JSON object `params`, see `consensus_params`.
+///
+/// Go must call this with the params in the state of the chain once the cache is created, and again whenever
+/// they change, before the next call. Until then every call fails. Changing whether NaNs are canonicalized drops
+/// the compiled modules, see `update_supported_features`.
+#[no_mangle]
+pub extern "C" fn set_consensus_params(
+    cache: *mut cache_t,
+    params: Buffer,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            reentrancy::check_outside_calls("set_consensus_params")?;
+            let params_bin =
+                unsafe { params.read() }.ok_or_else(|| Error::empty_arg(CONSENSUS_PARAMS_ARG))?;
+            let result = ConsensusParams::from_json(params_bin)
+                .and_then(|params| c.set_consensus_params(params))
+                .map(|_| ());
+            audit_log::record("set_consensus_params", &[params_bin], &result);
+            result
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    match r {
+        Ok(()) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn set_code_paused(
+    cache: *mut cache_t,
+    checksum: Buffer,
+    paused: bool,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            reentrancy::check_outside_calls("set_code_paused")?;
+            let checksum_bin =
+                unsafe { checksum.read() }.ok_or_else(|| Error::empty_arg(CHECKSUM_ARG))?;
+            let checksum: Checksum = checksum_bin.try_into()?;
+            c.set_code_paused(checksum, paused);
+            let result: Result<(), Error> = Ok(());
+            audit_log::record("set_code_paused", &[checksum_bin, &[paused as u8]], &result);
+            result
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    match r {
+        Ok(()) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+/// Registers memory owned by Go, into which large results of `query` and `get_code` are written.
+///
+/// Such results have a capacity of 0 and must be released with `release_shared` instead of `free_rust`.
+/// Pass a null buffer to unregister the region. Go must keep the memory alive, and must not unregister it
+/// while results are unreleased.
+#[no_mangle]
+pub extern "C" fn register_shared_region(
+    cache: *mut cache_t,
+    region: Buffer,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let r = match to_cache(cache) {
+        Some(mut c) => reentrancy::check_outside_calls("register_shared_region").map(|()| {
+            c.shared_region = if region.ptr.is_null() {
+                None
+            } else {
+                Some(unsafe { SharedRegion::new(region.ptr, region.len) })
+            };
+        }),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    match r {
+        Ok(()) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+/// Releases a result which was written into the shared region
+#[no_mangle]
+pub extern "C" fn release_shared(cache: *mut cache_t, buf: Buffer) {
+    let c = to_cache(cache);
+    if let Some(region) = c.as_ref().and_then(|c| c.shared_region.as_ref()) {
+        region.release(&buf);
+    }
+}
+
+/// Returns the shadow execution counters of the cache as JSON, see the `shadow_execution` cache option.
+///
+/// The counters are all zero, and `enabled` is false, if the cache does not run shadow execution.
+#[no_mangle]
+pub extern "C" fn get_shadow_stats(cache: *mut cache_t, err: Option<&mut Buffer>) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            serde_json::to_vec(&shadow::stats(&c)).map_err(Error::vm_err)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Returns the counters of the compiled modules of the cache as JSON: the format they are compiled in, the
+/// modules removed at startup as they were compiled in another format, and how many of them were compiled
+/// again since, see `module_format`
+#[no_mangle]
+pub extern "C" fn get_module_stats(cache: *mut cache_t, err: Option<&mut Buffer>) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            serde_json::to_vec(&c.modules.stats()).map_err(Error::vm_err)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Returns the execution statistics of every code run by the cache as JSON, the codes which took the most time first.
+///
+/// With `reset`, the counters start over afterwards, so every call returns the statistics since the previous one.
+#[no_mangle]
+pub extern "C" fn get_code_stats(
+    cache: *mut cache_t,
+    reset: bool,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            let entries = serde_json::to_vec(&c.code_stats.entries()).map_err(Error::vm_err)?;
+            if reset {
+                c.code_stats.reset();
+            }
+            Ok(entries)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Produces the state-sync payload of this node: the enclave's exportable state, the codes in the cache and
+/// which of them are plaintext codes or paused. Compiled modules are left out, see `snapshot`.
+///
+/// A node restoring the payload with `restore_snapshot` must `create` all of the listed codes first.
+#[no_mangle]
+pub extern "C" fn export_snapshot(cache: *mut cache_t, err: Option<&mut Buffer>) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            let _enclave = EnclaveGuard::enter()?;
+            snapshot::export_snapshot(&c.data_dir, &c.plaintext_codes, &c.paused_codes)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Restores a payload produced by `export_snapshot` on another node, pausing the codes it pauses
+#[no_mangle]
+pub extern "C" fn restore_snapshot(
+    cache: *mut cache_t,
+    payload: Buffer,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            reentrancy::check_outside_calls("restore_snapshot")?;
+            let payload = unsafe { payload.read() }.ok_or_else(|| Error::empty_arg(PAYLOAD_ARG))?;
+            let _enclave = EnclaveGuard::enter()?;
+            let cache = &mut *c;
+            let result =
+                snapshot::restore_snapshot(&cache.data_dir, &mut cache.plaintext_codes, payload)
+                    .map(|paused| {
+                        for code_id in paused {
+                            cache.set_code_paused(code_id, true);
+                        }
+                    });
+            audit_log::record("restore_snapshot", &[payload], &result);
+            result
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    match r {
+        Ok(()) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+/// Exports up to `limit` storage entries of the contract with `contract_key`, in ascending key order.
+///
+/// Returns a JSON-encoded page. Pass its `next` token as `start_after` to get the following page;
+/// `next` is null once the storage is exhausted. Unlike `query`, this bypasses the contract entirely.
+#[no_mangle]
+pub extern "C" fn export_contract_state(
+    contract_key: Buffer,
+    db: DB,
+    start_after: Buffer,
+    limit: u32,
+    mode: i32,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(|| {
+        let contract_key =
+            unsafe { contract_key.read() }.ok_or_else(|| Error::empty_arg(CONTRACT_KEY_ARG))?;
+        let start_after = unsafe { start_after.read() };
+        let mode = ExportMode::from_i32(mode)?;
+        let _enclave = EnclaveGuard::enter()?;
+        state_export::export_state(&db, contract_key, start_after, limit, mode)
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Counts up to `limit` storage entries of the contract with `contract_key` per key version, in ascending key
+/// order. `key_history` is the concatenation of the 32 byte ids of all codes the contract ran, oldest first,
+/// ending with its current code. See `state_keys`.
+///
+/// Returns a JSON-encoded page. Pass its `next` token as `start_after` to audit the following page;
+/// `next` is null once the storage is exhausted.
+#[no_mangle]
+pub extern "C" fn audit_state_keys(
+    contract_key: Buffer,
+    db: DB,
+    key_history: Buffer,
+    start_after: Buffer,
+    limit: u32,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(|| {
+        let contract_key =
+            unsafe { contract_key.read() }.ok_or_else(|| Error::empty_arg(CONTRACT_KEY_ARG))?;
+        let key_history =
+            unsafe { key_history.read() }.ok_or_else(|| Error::empty_arg(KEY_HISTORY_ARG))?;
+        let start_after = unsafe { start_after.read() };
+        let _enclave = EnclaveGuard::enter()?;
+        state_keys::audit_state_keys(&db, contract_key, key_history, start_after, limit)
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Times `iterations` calls of the given kind from inside the library, e.g. `db.get` through the Go
+/// callbacks, and returns a JSON-encoded report.
+///
+/// Chains can use this to calibrate the gas charged for callbacks on their hardware. The storage
+/// benchmarks write and remove a single key, so `db` should be a scratch store.
+#[no_mangle]
+pub extern "C" fn run_benchmark(
+    kind: i32,
+    iterations: u32,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(AssertUnwindSafe(|| {
+        let kind = BenchmarkKind::from_i32(kind)?;
+        let report = benchmark::run(kind, iterations, db, api, querier)?;
+        serde_json::to_vec(&report).map_err(Error::vm_err)
+    }))
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+#[no_mangle]
+pub extern "C" fn create(cache: *mut cache_t, wasm: Buffer, err: Option<&mut Buffer>) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || do_create(&mut c, wasm)))
+            .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Stores a code like `create`, as a plaintext code whose contracts run without encryption, see `plaintext`.
+///
+/// The mode of a code is fixed when it is first stored, so this fails for codes stored with `create` before.
+#[no_mangle]
+pub extern "C" fn create_plaintext(
+    cache: *mut cache_t,
+    wasm: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            reentrancy::check_outside_calls("create_plaintext")?;
+            let wasm = unsafe { wasm.read() }.ok_or_else(|| Error::empty_arg(WASM_ARG))?;
+            c.save_code(wasm, true)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+fn do_create(cache: &mut Cache, wasm: Buffer) -> Result<Checksum, Error> {
+    reentrancy::check_outside_calls("create")?;
+    let wasm = unsafe { wasm.read() }.ok_or_else(|| Error::empty_arg(WASM_ARG))?;
+    cache.save_code(wasm, false)
+}
+
+/// Stores all codes of an archive in one call, e.g. at genesis. See `code_archive` for the format.
+///
+/// Returns JSON listing the checksums of the imported codes and of the ones which were stored already.
+/// Nothing is stored if any checksum of the archive does not match its wasm.
+#[no_mangle]
+pub extern "C" fn import_codes(
+    cache: *mut cache_t,
+    archive: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            reentrancy::check_outside_calls("import_codes")?;
+            let archive = unsafe { archive.read() }.ok_or_else(|| Error::empty_arg(ARCHIVE_ARG))?;
+            let summary = code_archive::import_codes(&mut c, archive)?;
+            serde_json::to_vec(&summary).map_err(Error::vm_err)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Produces an archive of stored codes in the format of `import_codes`, e.g. for genesis export.
+///
+/// `checksums_filter` is a concatenation of 32 byte checksums selecting the codes. If it is empty, the archive
+/// contains all stored codes.
+#[no_mangle]
+pub extern "C" fn export_codes(
+    cache: *mut cache_t,
+    checksums_filter: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            let filter = unsafe { checksums_filter.read() }.filter(|filter| !filter.is_empty());
+            code_archive::export_codes(&mut c, filter)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    to_result_buffer(cache, data)
+}
+
+#[no_mangle]
+pub extern "C" fn get_code(cache: *mut cache_t, id: Buffer, err: Option<&mut Buffer>) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || do_get_code(&mut c, id)))
+            .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    to_result_buffer(cache, data)
+}
+
+fn do_get_code(cache: &mut Cache, id: Buffer) -> Result<Vec<u8>, Error> {
+    let id: Checksum = unsafe { id.read() }
+        .ok_or_else(|| Error::empty_arg(CACHE_ARG))?
+        .try_into()?;
+    cache.verify_code(&id)?;
+    let wasm = cache.inner.load_wasm(&id)?;
+    Ok(wasm)
+}
+
+#[no_mangle]
+pub extern "C" fn instantiate(
+    cache: *mut cache_t,
+    contract_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    call_depth: u32,
+    gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    call_id: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let _call_id = match CallIdScope::enter(unsafe { call_id.read() }) {
+        Ok(scope) => scope,
+        Err(e) => {
+            set_error(e, err);
+            return Buffer::default();
+        }
+    };
+    let tracker = GasTracker::start();
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            do_init(
+                &mut c,
+                contract_id,
+                params,
+                msg,
+                db,
+                api,
+                querier,
+                gas_limit,
+                call_depth,
+                gas_used,
+                None,
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    tracker.write_to(gas_report);
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Like `instantiate`, but the contract address in `params` must be the one derived from `salt`, see
+/// `contract_address`. With `fix_msg`, the address also depends on `msg`.
+///
+/// Go derives the address with `derive_contract_address` beforehand, to set up the contract's store.
+#[no_mangle]
+pub extern "C" fn instantiate2(
+    cache: *mut cache_t,
+    contract_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    call_depth: u32,
+    gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    salt: Buffer,
+    fix_msg: bool,
+    call_id: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let _call_id = match CallIdScope::enter(unsafe { call_id.read() }) {
+        Ok(scope) => scope,
+        Err(e) => {
+            set_error(e, err);
+            return Buffer::default();
+        }
+    };
+    let tracker = GasTracker::start();
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            do_init(
+                &mut c,
+                contract_id,
+                params,
+                msg,
+                db,
+                api,
+                querier,
+                gas_limit,
+                call_depth,
+                gas_used,
+                Some((salt, fix_msg)),
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    tracker.write_to(gas_report);
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// `salt` is the salt and `fix_msg` flag of `instantiate2`
+#[allow(clippy::too_many_arguments)]
+fn do_init(
+    cache: &mut Cache,
+    code_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    call_depth: u32,
+    gas_used: Option<&mut u64>,
+    salt: Option<(Buffer, bool)>,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let _reentrancy = ReentrancyGuard::enter(EntryPoint::Init)?;
+    let consensus = cache.consensus_params()?;
+    let depth = DepthGuard::enter(call_depth, consensus.max_call_depth)?;
+    let _enclave = EnclaveGuard::enter()?;
+    gas_report::set_gas_policy(consensus.gas_policy, consensus.fallback_gas);
+    gas_report::set_gas_sync(db, cache.options.gas_sync_interval);
+    let _arena = ArenaScope::enter();
+    let code_id_bytes = unsafe { code_id.read() }.ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?;
+    let code_id: Checksum = code_id_bytes.try_into()?;
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+    let params = env_encoding::normalize(params)?;
     let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+    cache.check_msg(&code_id, msg)?;
+    let env = cache.plaintext_codes.env_for(&code_id, &params, msg)?;
+    let params: &[u8] = &env;
+    if let Some((salt, fix_msg)) = salt {
+        let salt = unsafe { salt.read() }.ok_or_else(|| Error::empty_arg(SALT_ARG))?;
+        let fixed_msg = if fix_msg { Some(msg) } else { None };
+        contract_address::verify(&api, params, code_id_bytes, salt, fixed_msg)?;
+    }
+    let context = BlockContext::from_params(params);
+    db.set_block_context(context).map_err(VmError::from)?;
+    call_hooks::authorize(&db, &code_id, EntryPoint::Init, Some(params))?;
 
-    let deps = to_extern(db, api, querier);
-    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
-    // We only check this result after reporting gas usage and returning the instance into the cache.
-    let res = call_init_raw(&mut instance, params, msg);
-    *gas_used = instance.create_gas_report().used_internally;
-    instance.recycle();
-    Ok(res?)
+    let deps = to_extern(db, api, querier.clone());
+    let call = ContractCall {
+        code_id,
+        entry_point: EntryPoint::Init,
+        gas_limit,
+        height: context.height,
+        executes: true,
+    };
+    let run = run_contract(cache, &consensus, &call, deps, |instance| {
+        call_init_raw(instance, params, msg)
+    })?;
+    *gas_used = run.gas_used;
+    // The output is prefixed with the 64 bytes long contract key
+    let mut res = run.finish()?;
+    if res.len() < CONTRACT_KEY_LEN {
+        return Ok(res);
+    }
+    let output = res.split_off(CONTRACT_KEY_LEN);
+    let caller_deps = to_extern(db, api, querier);
+    let output = execute_internal_calls(
+        cache,
+        &caller_deps,
+        params,
+        output,
+        gas_limit,
+        depth.depth(),
+        gas_used,
+    )?;
+    let output = consensus.canonical_response(output)?;
+    let plaintext = cache.plaintext_codes.contains(&code_id);
+    call_hooks::report_result(&db, &code_id, EntryPoint::Init, &output, plaintext);
+    res.extend(output);
+    Ok(res)
 }
 
 #[no_mangle]
 pub extern "C" fn handle(
     cache: *mut cache_t,
     code_id: Buffer,
-    params: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    call_depth: u32,
+    gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    call_id: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let _call_id = match CallIdScope::enter(unsafe { call_id.read() }) {
+        Ok(scope) => scope,
+        Err(e) => {
+            set_error(e, err);
+            return Buffer::default();
+        }
+    };
+    let tracker = GasTracker::start();
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            do_handle(
+                &mut c, code_id, params, msg, db, api, querier, gas_limit, call_depth, gas_used,
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    tracker.write_to(gas_report);
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Starts `handle` on the worker thread of the `executor` and returns the handle of the call, see `async_calls`.
+///
+/// The call is done once `on_done` is called with the handle or `poll_async_call` returns true. Go then gets its
+/// result, gas used and gas report from `take_async_result`. Returns 0 and sets `err` if the call cannot start.
+/// The calls of the executor run one at a time in the order they were started, so the state changes of a block
+/// apply in its order as long as Go starts its calls in that order.
+#[no_mangle]
+pub extern "C" fn handle_async(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    call_depth: u32,
+    call_id: Buffer,
+    on_done: Option<OnDone>,
+    err: Option<&mut Buffer>,
+) -> u64 {
+    let r = catch_unwind(AssertUnwindSafe(move || {
+        let code_id = unsafe { code_id.read() }.ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?;
+        let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+        let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+        let (code_id, params, msg) = (code_id.to_vec(), params.to_vec(), msg.to_vec());
+        let call = AsyncCall {
+            cache,
+            query: false,
+            call_id: unsafe { call_id.read() }.map(<[u8]>::to_vec),
+            call: move |c: &mut Cache, gas_used: &mut u64| {
+                let (code_id, params, msg) = (
+                    Buffer::view(&code_id),
+                    Buffer::view(&params),
+                    Buffer::view(&msg),
+                );
+                do_handle(
+                    c,
+                    code_id,
+                    params,
+                    msg,
+                    db,
+                    api,
+                    querier,
+                    gas_limit,
+                    call_depth,
+                    Some(gas_used),
+                )
+            },
+        };
+        call.start(on_done)
+    }))
+    .unwrap_or_else(|_| Err(Error::panic()));
+    match r {
+        Ok(handle) => {
+            clear_error();
+            handle
+        }
+        Err(e) => {
+            set_error(e, err);
+            0
+        }
+    }
+}
+
+/// A call started by `handle_async` or `query_async`. It uses the cache, DB, API and querier from the worker
+/// thread, so Go must keep them alive until the call is done.
+struct AsyncCall<F> {
+    cache: *mut cache_t,
+    /// Whether the call is a query, which takes the cache like `to_query_cache`
+    query: bool,
+    call_id: Option<Vec<u8>>,
+    call: F,
+}
+
+// Go keeps the pointers valid until the call is done, and the cache is only used under its lock
+unsafe impl<F> Send for AsyncCall<F> {}
+
+impl<F> AsyncCall<F>
+where
+    F: FnOnce(&mut Cache, &mut u64) -> Result<Vec<u8>, Error> + 'static,
+{
+    /// Queues the call on the executor
+    fn start(self, on_done: Option<OnDone>) -> Result<u64, Error> {
+        if self.cache.is_null() {
+            return Err(Error::empty_arg(CACHE_ARG));
+        }
+        // invalid call ids fail before the call starts
+        CallIdScope::enter(self.call_id.as_deref())?;
+        async_calls::start(self.cache as usize, move || self.run(), on_done)
+    }
+
+    fn run(self) -> Outcome {
+        let _call_id = CallIdScope::enter(self.call_id.as_deref());
+        let tracker = GasTracker::start();
+        let mut gas_used = 0;
+        let result = match lock_cache(self.cache, self.query) {
+            Some(mut c) => (self.call)(&mut c, &mut gas_used),
+            None => Err(Error::empty_arg(CACHE_ARG)),
+        };
+        Outcome {
+            result,
+            gas_used,
+            gas_report: tracker.report(),
+        }
+    }
+}
+
+/// Executes a chain-owned contract for the chain itself, e.g. from BeginBlock or EndBlock.
+///
+/// Like `handle`, but the call is not limited by the gas a user paid for. It runs at depth 0 with the fixed budget
+/// `system_gas_limit` of the consensus params instead, so the contract stays bounded, and fails with the `OutOfGas`
+/// errno when it exhausts the budget. Go should not charge the gas to any meter, but `gas_used` and `gas_report`
+/// are set as usual.
+#[no_mangle]
+pub extern "C" fn handle_system(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    call_id: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let _call_id = match CallIdScope::enter(unsafe { call_id.read() }) {
+        Ok(scope) => scope,
+        Err(e) => {
+            set_error(e, err);
+            return Buffer::default();
+        }
+    };
+    let tracker = GasTracker::start();
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            let gas_limit = c.consensus_params()?.system_gas_limit;
+            do_handle(
+                &mut c, code_id, params, msg, db, api, querier, gas_limit, 0, gas_used,
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    tracker.write_to(gas_report);
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn do_handle(
+    cache: &mut Cache,
+    code_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    call_depth: u32,
+    gas_used: Option<&mut u64>,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let _reentrancy = ReentrancyGuard::enter(EntryPoint::Handle)?;
+    let consensus = cache.consensus_params()?;
+    let depth = DepthGuard::enter(call_depth, consensus.max_call_depth)?;
+    let _enclave = EnclaveGuard::enter()?;
+    gas_report::set_gas_policy(consensus.gas_policy, consensus.fallback_gas);
+    gas_report::set_gas_sync(db, cache.options.gas_sync_interval);
+    let _arena = ArenaScope::enter();
+    let code_id: Checksum = unsafe { code_id.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
+        .try_into()?;
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+    let params = env_encoding::normalize(params)?;
+    let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+    cache.check_msg(&code_id, msg)?;
+    let env = cache.plaintext_codes.env_for(&code_id, &params, msg)?;
+    let params: &[u8] = &env;
+    let context = BlockContext::from_params(params);
+    db.set_block_context(context).map_err(VmError::from)?;
+    call_hooks::authorize(&db, &code_id, EntryPoint::Handle, Some(params))?;
+
+    let deps = to_extern(db, api, querier.clone());
+    let call = ContractCall {
+        code_id,
+        entry_point: EntryPoint::Handle,
+        gas_limit,
+        height: context.height,
+        executes: true,
+    };
+    #[cfg(feature = "shadow")]
+    let recorder = Recorder::start(cache.shadow.is_some() && shadow::is_plaintext(msg));
+    let run = run_contract(cache, &consensus, &call, deps, |instance| {
+        call_handle_raw(instance, params, msg)
+    })?;
+    *gas_used = run.gas_used;
+    #[cfg(feature = "shadow")]
+    shadow::compare(
+        cache,
+        &code_id,
+        shadow::Call::Handle { params, msg },
+        api,
+        gas_limit,
+        &run.result,
+        run.gas_used,
+        recorder,
+    );
+    let res = run.finish()?;
+    consensus.check_response_size(res.len())?;
+
+    let caller_deps = to_extern(db, api, querier);
+    let res = execute_internal_calls(
+        cache,
+        &caller_deps,
+        params,
+        res,
+        gas_limit,
+        depth.depth(),
+        gas_used,
+    )?;
+    let res = consensus.canonical_response(res)?;
+    consensus.check_response_size(res.len())?;
+    let plaintext = cache.plaintext_codes.contains(&code_id);
+    call_hooks::report_result(&db, &code_id, EntryPoint::Handle, &res, plaintext);
+    Ok(res)
+}
+
+/// Runs the `begin_block` export of a contract registered for it, see `block_hooks`.
+///
+/// `block` is the JSON `BlockMeta` of the block which begins, and replaces the block of `params`. The call runs
+/// with the budget `system_gas_limit` of the consensus params, like `handle_system`.
+#[no_mangle]
+pub extern "C" fn begin_block(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    params: Buffer,
+    block: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    call_id: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    block_hook(
+        cache,
+        BlockHook::BeginBlock,
+        code_id,
+        params,
+        block,
+        db,
+        api,
+        querier,
+        gas_used,
+        gas_report,
+        call_id,
+        err,
+    )
+}
+
+/// Runs the `end_block` export of a contract registered for it, like `begin_block`
+#[no_mangle]
+pub extern "C" fn end_block(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    params: Buffer,
+    block: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    call_id: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    block_hook(
+        cache,
+        BlockHook::EndBlock,
+        code_id,
+        params,
+        block,
+        db,
+        api,
+        querier,
+        gas_used,
+        gas_report,
+        call_id,
+        err,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn block_hook(
+    cache: *mut cache_t,
+    hook: BlockHook,
+    code_id: Buffer,
+    params: Buffer,
+    block: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    call_id: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let _call_id = match CallIdScope::enter(unsafe { call_id.read() }) {
+        Ok(scope) => scope,
+        Err(e) => {
+            set_error(e, err);
+            return Buffer::default();
+        }
+    };
+    let tracker = GasTracker::start();
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            do_block_hook(
+                &mut c, hook, code_id, params, block, db, api, querier, gas_used,
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    tracker.write_to(gas_report);
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn do_block_hook(
+    cache: &mut Cache,
+    hook: BlockHook,
+    code_id: Buffer,
+    params: Buffer,
+    block: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_used: Option<&mut u64>,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let _reentrancy = ReentrancyGuard::enter(hook.entry_point())?;
+    let consensus = cache.consensus_params()?;
+    let depth = DepthGuard::enter(0, consensus.max_call_depth)?;
+    let _enclave = EnclaveGuard::enter()?;
+    gas_report::set_gas_policy(consensus.gas_policy, consensus.fallback_gas);
+    gas_report::set_gas_sync(db, cache.options.gas_sync_interval);
+    let _arena = ArenaScope::enter();
+    let code_id: Checksum = unsafe { code_id.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
+        .try_into()?;
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+    let params = env_encoding::normalize(params)?;
+    let block = unsafe { block.read() }.ok_or_else(|| Error::empty_arg(BLOCK_ARG))?;
+    let env = block_hooks::hook_env(&params, &BlockMeta::from_json(block)?)?;
+    let env = cache.plaintext_codes.env(&code_id, &env)?;
+    let params: &[u8] = &env;
+    let context = BlockContext::from_params(params);
+    db.set_block_context(context).map_err(VmError::from)?;
+    let entry_point = hook.entry_point();
+    call_hooks::authorize(&db, &code_id, entry_point, Some(params))?;
+
+    let gas_limit = consensus.system_gas_limit;
+    let deps = to_extern(db, api, querier.clone());
+    let call = ContractCall {
+        code_id,
+        entry_point,
+        gas_limit,
+        height: context.height,
+        executes: true,
+    };
+    let run = run_contract(cache, &consensus, &call, deps, |instance| match hook {
+        BlockHook::BeginBlock => call_begin_block_raw(instance, params),
+        BlockHook::EndBlock => call_end_block_raw(instance, params),
+    })?;
+    *gas_used = run.gas_used;
+    let res = run.finish()?;
+    consensus.check_response_size(res.len())?;
+
+    let caller_deps = to_extern(db, api, querier);
+    let res = execute_internal_calls(
+        cache,
+        &caller_deps,
+        params,
+        res,
+        gas_limit,
+        depth.depth(),
+        gas_used,
+    )?;
+    let res = consensus.canonical_response(res)?;
+    consensus.check_response_size(res.len())?;
+    let plaintext = cache.plaintext_codes.contains(&code_id);
+    call_hooks::report_result(&db, &code_id, entry_point, &res, plaintext);
+    Ok(res)
+}
+
+/// Migrates a contract to the code `contract_id`.
+///
+/// `key_history` is the concatenation of the 32 byte ids of the codes the contract ran before, oldest first,
+/// so the enclave can read the state encrypted with their keys, see `state_keys`. The contract key in
+/// `params` must have been issued for the last of them. It may be empty for contracts without encrypted state.
+///
+/// `auth` is the JSON `MigrateAuth` of the migration, or empty for a migration by the sender in `params`. The
+/// enclave only runs it if governance runs it or the sender is the admin it recorded, see `migrate_auth`.
+#[no_mangle]
+pub extern "C" fn migrate(
+    cache: *mut cache_t,
+    contract_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    call_depth: u32,
+    gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    key_history: Buffer,
+    auth: Buffer,
+    call_id: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let _call_id = match CallIdScope::enter(unsafe { call_id.read() }) {
+        Ok(scope) => scope,
+        Err(e) => {
+            set_error(e, err);
+            return Buffer::default();
+        }
+    };
+    let tracker = GasTracker::start();
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            do_migrate(
+                &mut c,
+                contract_id,
+                params,
+                msg,
+                db,
+                api,
+                querier,
+                gas_limit,
+                call_depth,
+                gas_used,
+                key_history,
+                auth,
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    tracker.write_to(gas_report);
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn do_migrate(
+    cache: &mut Cache,
+    code_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    call_depth: u32,
+    gas_used: Option<&mut u64>,
+    key_history: Buffer,
+    auth: Buffer,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let _reentrancy = ReentrancyGuard::enter(EntryPoint::Migrate)?;
+    let consensus = cache.consensus_params()?;
+    let _depth = DepthGuard::enter(call_depth, consensus.max_call_depth)?;
+    let _enclave = EnclaveGuard::enter()?;
+    gas_report::set_gas_policy(consensus.gas_policy, consensus.fallback_gas);
+    gas_report::set_gas_sync(db, cache.options.gas_sync_interval);
+    let _arena = ArenaScope::enter();
+    let code_id: Checksum = unsafe { code_id.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
+        .try_into()?;
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+    let params = env_encoding::normalize(params)?;
+    let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+    // an empty history is like none
+    let key_history = KeyHistory::parse(unsafe { key_history.read() }.unwrap_or_default())?;
+    // an empty auth is a migration by the sender, which the enclave allows for the admin
+    let auth = match unsafe { auth.read() } {
+        Some(auth) => MigrateAuth::from_json(auth)?,
+        None => MigrateAuth::default(),
+    };
+    cache.check_msg(&code_id, msg)?;
+    let env = cache.plaintext_codes.env_for(&code_id, &params, msg)?;
+    let env = migrate_auth::env_with_auth(&env, &auth)?;
+    let env = state_keys::migration_env(&env, &key_history)?;
+    let params: &[u8] = &env;
+    let context = BlockContext::from_params(params);
+    db.set_block_context(context).map_err(VmError::from)?;
+    call_hooks::authorize(&db, &code_id, EntryPoint::Migrate, Some(params))?;
+
+    let deps = to_extern(db, api, querier);
+    let call = ContractCall {
+        code_id,
+        entry_point: EntryPoint::Migrate,
+        gas_limit,
+        height: context.height,
+        executes: true,
+    };
+    let run = run_contract(cache, &consensus, &call, deps, |instance| {
+        call_migrate_raw(instance, params, msg)
+    })?;
+    *gas_used = run.gas_used;
+    let res = consensus.canonical_response(run.finish()?)?;
+    let plaintext = cache.plaintext_codes.contains(&code_id);
+    call_hooks::report_result(&db, &code_id, EntryPoint::Migrate, &res, plaintext);
+    Ok(res)
+}
+
+/// `block` is the block whose state the query reads, which Go may pin to a past height for historical queries.
+/// It is handed to the DB and the querier before the contract runs, so the queries the contract makes read the
+/// same state as its storage. A height of 0 means the latest state.
+///
+/// With `unmetered` set, the query runs without a gas limit, ignoring `gas_limit`, and reports no gas: `gas_used`
+/// and `gas_report` are left at 0. Unmetered queries fail with `Error::CallDenied` unless the cache was created
+/// with `allow_unmetered_queries`.
+#[no_mangle]
+pub extern "C" fn query(
+    cache: *mut cache_t,
+    code_id: Buffer,
     msg: Buffer,
     db: DB,
     api: GoApi,
     querier: GoQuerier,
     gas_limit: u64,
+    call_depth: u32,
+    block: BlockContext,
+    unmetered: bool,
     gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    call_id: Buffer,
     err: Option<&mut Buffer>,
 ) -> Buffer {
-    let r = match to_cache(cache) {
-        Some(c) => catch_unwind(AssertUnwindSafe(move || {
-            do_handle(
-                c, code_id, params, msg, db, api, querier, gas_limit, gas_used,
+    let _call_id = match CallIdScope::enter(unsafe { call_id.read() }) {
+        Ok(scope) => scope,
+        Err(e) => {
+            set_error(e, err);
+            return Buffer::default();
+        }
+    };
+    let tracker = GasTracker::start();
+    // queries over the limits fail before waiting for the cache
+    let r = QueryPermit::acquire(call_depth).and_then(|_permit| match to_query_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            do_query(
+                &mut c, code_id, msg, db, api, querier, gas_limit, call_depth, block, unmetered,
+                gas_used,
             )
         }))
         .unwrap_or_else(|_| Err(Error::panic())),
         None => Err(Error::empty_arg(CACHE_ARG)),
-    };
+    });
+    tracker.write_to(if unmetered { None } else { gas_report });
+    let data = handle_c_error(r, err);
+    to_result_buffer(cache, data)
+}
+
+/// Starts `query` on the worker thread of the `executor` and returns the handle of the call, like `handle_async`
+#[no_mangle]
+pub extern "C" fn query_async(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    call_depth: u32,
+    call_id: Buffer,
+    on_done: Option<OnDone>,
+    err: Option<&mut Buffer>,
+) -> u64 {
+    let r = catch_unwind(AssertUnwindSafe(move || {
+        let code_id = unsafe { code_id.read() }.ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?;
+        let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+        let (code_id, msg) = (code_id.to_vec(), msg.to_vec());
+        // the query counts as running while it is queued
+        let permit = QueryPermit::acquire(call_depth)?;
+        let call = AsyncCall {
+            cache,
+            query: true,
+            call_id: unsafe { call_id.read() }.map(<[u8]>::to_vec),
+            call: move |c: &mut Cache, gas_used: &mut u64| {
+                let _permit = permit;
+                let (code_id, msg) = (Buffer::view(&code_id), Buffer::view(&msg));
+                do_query(
+                    c,
+                    code_id,
+                    msg,
+                    db,
+                    api,
+                    querier,
+                    gas_limit,
+                    call_depth,
+                    BlockContext::default(),
+                    false,
+                    Some(gas_used),
+                )
+            },
+        };
+        call.start(on_done)
+    }))
+    .unwrap_or_else(|_| Err(Error::panic()));
+    match r {
+        Ok(handle) => {
+            clear_error();
+            handle
+        }
+        Err(e) => {
+            set_error(e, err);
+            0
+        }
+    }
+}
+
+/// Whether the call `handle` started by `handle_async` or `query_async` is done. Sets `err` for unknown handles.
+#[no_mangle]
+pub extern "C" fn poll_async_call(handle: u64, err: Option<&mut Buffer>) -> bool {
+    let r = catch_unwind(|| async_calls::is_done(handle)).unwrap_or_else(|_| Err(Error::panic()));
+    match r {
+        Ok(done) => {
+            clear_error();
+            done
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+/// Returns the result of the call `handle` once it is done, like `handle` or `query` would, and forgets the handle.
+///
+/// `gas_used` and `gas_report` are set as by the synchronous call. Fails if the call is still running.
+#[no_mangle]
+pub extern "C" fn take_async_result(
+    handle: u64,
+    gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(|| async_calls::take(handle))
+        .unwrap_or_else(|_| Err(Error::panic()))
+        .and_then(|outcome| {
+            if let Some(gas_used) = gas_used {
+                *gas_used = outcome.gas_used;
+            }
+            if let Some(gas_report) = gas_report {
+                *gas_report = outcome.gas_report;
+            }
+            outcome.result
+        });
     let data = handle_c_error(r, err);
     Buffer::from_vec(data)
 }
 
-fn do_handle(
-    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
+/// The gas limit of unmetered queries, more than any query can use
+const UNMETERED_GAS_LIMIT: u64 = u64::MAX;
+
+#[allow(clippy::too_many_arguments)]
+fn do_query(
+    cache: &mut Cache,
     code_id: Buffer,
-    params: Buffer,
     msg: Buffer,
     db: DB,
     api: GoApi,
     querier: GoQuerier,
     gas_limit: u64,
+    call_depth: u32,
+    block: BlockContext,
+    unmetered: bool,
     gas_used: Option<&mut u64>,
 ) -> Result<Vec<u8>, Error> {
     let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    cache.options.check_unmetered(unmetered)?;
+    let gas_limit = if unmetered {
+        UNMETERED_GAS_LIMIT
+    } else {
+        gas_limit
+    };
+    let _reentrancy = ReentrancyGuard::enter(EntryPoint::Query)?;
+    let consensus = cache.consensus_params()?;
+    let _depth = DepthGuard::enter(call_depth, consensus.max_call_depth)?;
+    let _enclave = EnclaveGuard::enter()?;
+    gas_report::set_gas_policy(consensus.gas_policy, consensus.fallback_gas);
+    if !unmetered {
+        gas_report::set_gas_sync(db, cache.options.gas_sync_interval);
+    }
+    let _arena = ArenaScope::enter();
     let code_id: Checksum = unsafe { code_id.read() }
         .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
         .try_into()?;
-    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
     let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+    cache.check_msg(&code_id, msg)?;
+    cache.plaintext_codes.check_msg(&code_id, msg)?;
+    db.set_block_context(block).map_err(VmError::from)?;
+    querier.set_block_context(block).map_err(VmError::from)?;
+    call_hooks::authorize(&db, &code_id, EntryPoint::Query, None)?;
 
     let deps = to_extern(db, api, querier);
-    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
-    // We only check this result after reporting gas usage and returning the instance into the cache.
-    let res = call_handle_raw(&mut instance, params, msg);
-    *gas_used = instance.create_gas_report().used_internally;
-    instance.recycle();
-    Ok(res?)
+    let call = ContractCall {
+        code_id,
+        entry_point: EntryPoint::Query,
+        gas_limit,
+        height: block.height,
+        executes: false,
+    };
+    #[cfg(feature = "shadow")]
+    let recorder = Recorder::start(cache.shadow.is_some() && shadow::is_plaintext(msg));
+    let run = run_contract(cache, &consensus, &call, deps, |instance| {
+        call_query_raw(instance, msg)
+    })?;
+    if !unmetered {
+        *gas_used = run.gas_used;
+    }
+    #[cfg(feature = "shadow")]
+    shadow::compare(
+        cache,
+        &code_id,
+        shadow::Call::Query { msg },
+        api,
+        gas_limit,
+        &run.result,
+        run.gas_used,
+        recorder,
+    );
+    let res = run.finish()?;
+    consensus.check_response_size(res.len())?;
+    Ok(res)
 }
 
+/// Runs `query` on the contract at `contract_addr`, whose code is `code_id`, and keeps its result for queries
+/// with the same contract, code and message at the same height, see `query_cache`. The other arguments are as
+/// for `query`. A reused result sets `gas_used` to the gas its query used, and leaves `gas_report` at 0.
+///
+/// The queries of contracts must use `query`, as their gas is part of consensus and the store charges nothing
+/// for reused results.
 #[no_mangle]
-pub extern "C" fn migrate(
+pub extern "C" fn query_cached(
     cache: *mut cache_t,
-    contract_id: Buffer,
-    params: Buffer,
+    contract_addr: Buffer,
+    code_id: Buffer,
     msg: Buffer,
     db: DB,
     api: GoApi,
     querier: GoQuerier,
     gas_limit: u64,
+    block: BlockContext,
     gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    call_id: Buffer,
     err: Option<&mut Buffer>,
 ) -> Buffer {
-    let r = match to_cache(cache) {
-        Some(c) => catch_unwind(AssertUnwindSafe(move || {
-            do_migrate(
-                c,
-                contract_id,
-                params,
+    let _call_id = match CallIdScope::enter(unsafe { call_id.read() }) {
+        Ok(scope) => scope,
+        Err(e) => {
+            set_error(e, err);
+            return Buffer::default();
+        }
+    };
+    let tracker = GasTracker::start();
+    let r = QueryPermit::acquire(0).and_then(|_permit| match to_query_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+            let contract_addr = unsafe { contract_addr.read() }
+                .ok_or_else(|| Error::empty_arg(CONTRACT_ADDR_ARG))?;
+            let contract_addr = from_utf8(contract_addr).map_err(Error::invalid_utf8)?;
+            let code_id = unsafe { code_id.read() }.ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?;
+            let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+            do_query_cached(
+                &mut c,
+                contract_addr,
+                code_id,
                 msg,
                 db,
                 api,
                 querier,
                 gas_limit,
+                block,
                 gas_used,
             )
         }))
         .unwrap_or_else(|_| Err(Error::panic())),
         None => Err(Error::empty_arg(CACHE_ARG)),
+    });
+    tracker.write_to(gas_report);
+    let data = handle_c_error(r, err);
+    to_result_buffer(cache, data)
+}
+
+/// Runs a query of the contract at `contract_addr`, unless the query cache keeps its result
+#[allow(clippy::too_many_arguments)]
+fn do_query_cached(
+    cache: &mut Cache,
+    contract_addr: &str,
+    code_id: &[u8],
+    msg: &[u8],
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    block: BlockContext,
+    gas_used: &mut u64,
+) -> Result<Vec<u8>, Error> {
+    let key = QueryKey::new(contract_addr, code_id, msg);
+    if let Some((result, used)) = cache.query_cache.get(block.height, &key, gas_limit)? {
+        *gas_used = used;
+        return Ok(result);
+    }
+    let res = do_query(
+        cache,
+        Buffer::view(code_id),
+        Buffer::view(msg),
+        db,
+        api,
+        querier,
+        gas_limit,
+        0,
+        block,
+        false,
+        Some(gas_used),
+    )?;
+    cache.query_cache.insert(block.height, key, &res, *gas_used);
+    Ok(res)
+}
+
+/// Runs the JSON list of `BatchQuery`s against the state of `db`, which must resolve the contracts, and returns
+/// the JSON list of their `QueryOutcome`s, see `query_batch`. A query failing does not fail the batch. `block`
+/// is the block of the snapshot `db` reads, as for `query`. The queries share `gas_limit`, and `gas_used` and
+/// `gas_report` cover all of them.
+#[no_mangle]
+pub extern "C" fn query_batch(
+    cache: *mut cache_t,
+    queries: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    block: BlockContext,
+    gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    call_id: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let _call_id = match CallIdScope::enter(unsafe { call_id.read() }) {
+        Ok(scope) => scope,
+        Err(e) => {
+            set_error(e, err);
+            return Buffer::default();
+        }
     };
+    let tracker = GasTracker::start();
+    // the batch is admitted as one query
+    let r = QueryPermit::acquire(0).and_then(|_permit| match to_query_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            do_query_batch(
+                &mut c, queries, db, api, querier, gas_limit, block, gas_used,
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    });
+    tracker.write_to(gas_report);
     let data = handle_c_error(r, err);
-    Buffer::from_vec(data)
+    to_result_buffer(cache, data)
 }
 
-fn do_migrate(
-    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
-    code_id: Buffer,
-    params: Buffer,
-    msg: Buffer,
+#[allow(clippy::too_many_arguments)]
+fn do_query_batch(
+    cache: &mut Cache,
+    queries: Buffer,
     db: DB,
     api: GoApi,
     querier: GoQuerier,
     gas_limit: u64,
+    block: BlockContext,
     gas_used: Option<&mut u64>,
 ) -> Result<Vec<u8>, Error> {
     let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
-    let code_id: Checksum = unsafe { code_id.read() }
-        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
-        .try_into()?;
-    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
-    let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+    let queries = unsafe { queries.read() }.ok_or_else(|| Error::empty_arg(QUERIES_ARG))?;
+    let queries = query_batch::parse(queries)?;
+    let mut outcomes = Vec::with_capacity(queries.len());
+    for query in &queries {
+        let result = run_batch_query(
+            cache,
+            query,
+            db,
+            api,
+            querier.clone(),
+            gas_limit.saturating_sub(*gas_used),
+            block,
+            gas_used,
+        );
+        outcomes.push(QueryOutcome::from(result));
+    }
+    query_batch::to_json(&outcomes)
+}
 
-    let deps = to_extern(db, api, querier);
-    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
-    // We only check this result after reporting gas usage and returning the instance into the cache.
-    let res = call_migrate_raw(&mut instance, params, msg);
-    *gas_used = instance.create_gas_report().used_internally;
-    instance.recycle();
-    Ok(res?)
+/// Runs one query of a batch, adding the gas it used to `gas_used`
+#[allow(clippy::too_many_arguments)]
+fn run_batch_query(
+    cache: &mut Cache,
+    query: &BatchQuery,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_left: u64,
+    block: BlockContext,
+    gas_used: &mut u64,
+) -> Result<Vec<u8>, Error> {
+    if gas_left == 0 {
+        return Err(Error::out_of_gas());
+    }
+    let (resolved, resolve_gas) = resolve_queried_contract(&db, &query.contract_addr);
+    *gas_used = gas_used.saturating_add(resolve_gas);
+    let resolved = resolved?;
+    let gas_left = gas_left_after(gas_left, resolve_gas)?;
+    let mut used = 0;
+    let res = do_query_cached(
+        cache,
+        &query.contract_addr,
+        &resolved.code_id,
+        &query.msg.0,
+        resolved.db,
+        api,
+        querier,
+        gas_left,
+        block,
+        &mut used,
+    );
+    *gas_used = gas_used.saturating_add(used);
+    res
+}
+
+/// The code and storage of the contract at `contract_addr`, for queries by address, and the gas of resolving
+/// it. The gas is charged like a storage read, as for internal calls, so the caller must add it to the query.
+fn resolve_queried_contract(
+    db: &DB,
+    contract_addr: &str,
+) -> (Result<ResolvedContract, Error>, u64) {
+    let (resolved, gas_info) = db.resolve_contract(contract_addr);
+    let resolved = match resolved.map_err(VmError::from) {
+        Ok(Some(resolved)) => Ok(resolved),
+        Ok(None) => Err(Error::invalid_arg(
+            CONTRACT_ADDR_ARG,
+            format!("unknown contract {}", contract_addr),
+        )),
+        Err(e) => Err(e.into()),
+    };
+    (resolved, gas_info.cost)
+}
+
+/// The gas left for a query by address after resolving its contract for `resolve_gas`
+fn gas_left_after(gas_limit: u64, resolve_gas: u64) -> Result<u64, Error> {
+    match gas_limit.checked_sub(resolve_gas) {
+        Some(gas_left) if gas_left > 0 => Ok(gas_left),
+        _ => Err(Error::out_of_gas()),
+    }
 }
 
+/// Registers a wasm cost table which charges the calls from a block height on, see `wasm_costs`.
+///
+/// `schedule` is JSON like `{"activation_height":1000000,"costs":{"default":1,"instructions":{"i64.div_u":8}}}`.
+/// Schedules are not persisted, so Go must register them again after restarting the node, before blocks at or
+/// above their heights run. Registering a schedule again does nothing.
 #[no_mangle]
-pub extern "C" fn query(
+pub extern "C" fn register_gas_schedule(
     cache: *mut cache_t,
-    code_id: Buffer,
-    msg: Buffer,
+    schedule: Buffer,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            reentrancy::check_outside_calls("register_gas_schedule")?;
+            let schedule =
+                unsafe { schedule.read() }.ok_or_else(|| Error::empty_arg(SCHEDULE_ARG))?;
+            let schedule: GasSchedule = serde_json::from_slice(schedule)
+                .map_err(|e| Error::invalid_arg(SCHEDULE_ARG, e))?;
+            wasm_costs::check_vm_support(&schedule.costs)?;
+            c.wasm_schedules.register(&schedule)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    match r {
+        Ok(()) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+/// Replaces the routes of `query_by_alias` with the JSON object of `QueryRoute`s by alias, see `query_routes`.
+/// Routes are not persisted, so Go must set them again after restarting the node.
+#[no_mangle]
+pub extern "C" fn set_query_routes(
+    cache: *mut cache_t,
+    routes: Buffer,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let r = match to_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            reentrancy::check_outside_calls("set_query_routes")?;
+            let routes = unsafe { routes.read() }.ok_or_else(|| Error::empty_arg(ROUTES_ARG))?;
+            c.query_routes = QueryRoutes::from_json(routes)?;
+            Ok(())
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    match r {
+        Ok(()) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(e, err);
+            false
+        }
+    }
+}
+
+/// Queries the contract of the route `alias` with its message rendered with the JSON object `args`, see
+/// `query_routes`. `db` must resolve the contract, as for `query_batch`, and the other arguments are as for
+/// `query`. Fails with `Error::CallDenied` if the contract no longer runs the code of the route.
+#[no_mangle]
+pub extern "C" fn query_by_alias(
+    cache: *mut cache_t,
+    alias: Buffer,
+    args: Buffer,
     db: DB,
     api: GoApi,
     querier: GoQuerier,
     gas_limit: u64,
+    block: BlockContext,
     gas_used: Option<&mut u64>,
+    gas_report: Option<&mut GasReport>,
+    call_id: Buffer,
     err: Option<&mut Buffer>,
 ) -> Buffer {
-    let r = match to_cache(cache) {
-        Some(c) => catch_unwind(AssertUnwindSafe(move || {
-            do_query(c, code_id, msg, db, api, querier, gas_limit, gas_used)
+    let _call_id = match CallIdScope::enter(unsafe { call_id.read() }) {
+        Ok(scope) => scope,
+        Err(e) => {
+            set_error(e, err);
+            return Buffer::default();
+        }
+    };
+    let tracker = GasTracker::start();
+    let r = QueryPermit::acquire(0).and_then(|_permit| match to_query_cache(cache) {
+        Some(mut c) => catch_unwind(AssertUnwindSafe(move || {
+            do_query_by_alias(
+                &mut c, alias, args, db, api, querier, gas_limit, block, gas_used,
+            )
         }))
         .unwrap_or_else(|_| Err(Error::panic())),
         None => Err(Error::empty_arg(CACHE_ARG)),
-    };
+    });
+    tracker.write_to(gas_report);
     let data = handle_c_error(r, err);
-    Buffer::from_vec(data)
+    to_result_buffer(cache, data)
 }
 
-fn do_query(
-    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
-    code_id: Buffer,
-    msg: Buffer,
+#[allow(clippy::too_many_arguments)]
+fn do_query_by_alias(
+    cache: &mut Cache,
+    alias: Buffer,
+    args: Buffer,
     db: DB,
     api: GoApi,
     querier: GoQuerier,
     gas_limit: u64,
+    block: BlockContext,
     gas_used: Option<&mut u64>,
 ) -> Result<Vec<u8>, Error> {
     let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
-    let code_id: Checksum = unsafe { code_id.read() }
-        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
-        .try_into()?;
-    let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+    let alias = unsafe { alias.read() }.ok_or_else(|| Error::empty_arg(ALIAS_ARG))?;
+    let alias = from_utf8(alias).map_err(Error::invalid_utf8)?;
+    let args = unsafe { args.read() }.ok_or_else(|| Error::empty_arg(ARGS_ARG))?;
+    let (route, msg) = cache.query_routes.resolve(alias, args)?;
+    let (contract_addr, checksum) = (route.contract_addr.clone(), route.checksum.clone());
 
-    let deps = to_extern(db, api, querier);
-    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
-    // We only check this result after reporting gas usage and returning the instance into the cache.
-    let res = call_query_raw(&mut instance, msg);
-    *gas_used = instance.create_gas_report().used_internally;
-    instance.recycle();
-    Ok(res?)
+    let (resolved, resolve_gas) = resolve_queried_contract(&db, &contract_addr);
+    *gas_used = resolve_gas;
+    let resolved = resolved?;
+    if !hex::encode(&resolved.code_id).eq_ignore_ascii_case(&checksum) {
+        return Err(Error::call_denied(
+            EntryPoint::Query.name(),
+            format!(
+                "{} no longer runs the code of the query route {}",
+                contract_addr, alias
+            ),
+        ));
+    }
+    let code_id: Checksum = resolved.code_id.as_slice().try_into()?;
+    if !cache.plaintext_codes.contains(&code_id) {
+        return Err(Error::invalid_arg(
+            ALIAS_ARG,
+            format!("the query route {} does not reach a plaintext code", alias),
+        ));
+    }
+    let mut used = 0;
+    let res = do_query_cached(
+        cache,
+        &contract_addr,
+        &resolved.code_id,
+        &msg,
+        resolved.db,
+        api,
+        querier,
+        gas_left_after(gas_limit, resolve_gas)?,
+        block,
+        &mut used,
+    );
+    *gas_used = gas_used.saturating_add(used);
+    res
+}
+
+// the seed test networks derive their keys from
+#[cfg(feature = "test-keys")]
+const TEST_KEYS_SEED_LEN: usize = 32;
+
+#[cfg(feature = "test-keys")]
+fn read_test_keys_seed(seed: Buffer) -> Result<[u8; TEST_KEYS_SEED_LEN], Error> {
+    let seed = unsafe { seed.read() }.ok_or_else(|| Error::empty_arg(SEED_ARG))?;
+    if seed.len() != TEST_KEYS_SEED_LEN {
+        let msg = format!("expected {} bytes, got {}", TEST_KEYS_SEED_LEN, seed.len());
+        return Err(Error::invalid_arg(SEED_ARG, msg));
+    }
+    let mut out = [0u8; TEST_KEYS_SEED_LEN];
+    out.copy_from_slice(seed);
+    Ok(out)
 }
 
+/// Like `init_bootstrap`, but derives the consensus seed from `seed` instead of the enclave's randomness.
+///
+/// Only available with the `test-keys` feature, for reproducible test networks.
+#[cfg(feature = "test-keys")]
 #[no_mangle]
-pub extern "C" fn key_gen(err: Option<&mut Buffer>) -> Buffer {
-    match untrusted_key_gen() {
+pub extern "C" fn init_bootstrap_from_seed(seed: Buffer, err: Option<&mut Buffer>) -> Buffer {
+    let seed = match read_test_keys_seed(seed) {
+        Ok(seed) => seed,
         Err(e) => {
-            set_error(Error::enclave_err(e.to_string()), err);
+            set_error(e, err);
+            return Buffer::default();
+        }
+    };
+    let res =
+        untrusted_init_bootstrap_from_seed(&seed).map_err(|e| Error::enclave_err(e.to_string()));
+    audit_log::record("init_bootstrap_from_seed", &[&seed[..]], &res);
+    match res {
+        Err(e) => {
+            set_error(e, err);
             Buffer::default()
         }
         Ok(r) => {
@@ -492,3 +3123,160 @@ pub extern "C" fn key_gen(err: Option<&mut Buffer>) -> Buffer {
         }
     }
 }
+
+/// Like `key_gen`, but derives the node's registration key from `seed` instead of the enclave's randomness.
+///
+/// Only available with the `test-keys` feature, for reproducible test networks.
+#[cfg(feature = "test-keys")]
+#[no_mangle]
+pub extern "C" fn key_gen_from_seed(seed: Buffer, err: Option<&mut Buffer>) -> Buffer {
+    let seed = match read_test_keys_seed(seed) {
+        Ok(seed) => seed,
+        Err(e) => {
+            set_error(e, err);
+            return Buffer::default();
+        }
+    };
+    let r = untrusted_key_gen_from_seed(&seed)
+        .map_err(|e| Error::enclave_err(e.to_string()))
+        .and_then(|keys| RegistrationKeys::from_enclave(&keys)?.to_json());
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Generates the node's registration keys and returns their public parts as JSON-encoded `RegistrationKeys`
+#[no_mangle]
+pub extern "C" fn key_gen(err: Option<&mut Buffer>) -> Buffer {
+    let r = untrusted_key_gen()
+        .map_err(|e| crash_report::sdk_failure("key_gen", e))
+        .and_then(|keys| RegistrationKeys::from_enclave(&keys)?.to_json());
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Returns the public registration keys of a node which already ran `key_gen`, as JSON-encoded `RegistrationKeys`
+#[no_mangle]
+pub extern "C" fn get_registration_keys(err: Option<&mut Buffer>) -> Buffer {
+    let r = match untrusted_get_registration_keys() {
+        // An error happened in the SGX sdk.
+        Err(e) => Err(crash_report::sdk_failure("get_registration_keys", e)),
+        // An error was returned from the enclave, e.g. because no keys were generated yet.
+        Ok(Err(e)) => Err(Error::enclave_err(e.to_string())),
+        Ok(Ok(keys)) => RegistrationKeys::from_enclave(&keys).and_then(|keys| keys.to_json()),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Returns a statement of the enclave binding the node's registration keys to an attestation report, as
+/// JSON-encoded `KeyAttestation`, for wallets and bridges verifying they talk to a genuine enclave.
+///
+/// `challenge` must be 32 bytes picked by the verifier, see `key_attestation`. Like
+/// `create_attestation_report`, this goes through IAS and follows the policy of `set_enclave_call_policy`.
+#[no_mangle]
+pub extern "C" fn create_key_attestation(challenge: Buffer, err: Option<&mut Buffer>) -> Buffer {
+    let r = catch_unwind(|| {
+        let challenge =
+            unsafe { challenge.read() }.ok_or_else(|| Error::empty_arg(CHALLENGE_ARG))?;
+        let challenge = key_attestation::parse_challenge(challenge)?;
+        let keys = match untrusted_get_registration_keys() {
+            // An error happened in the SGX sdk.
+            Err(e) => return Err(crash_report::sdk_failure("create_key_attestation", e)),
+            // An error was returned from the enclave, e.g. because no keys were generated yet.
+            Ok(Err(e)) => return Err(Error::enclave_err(e.to_string())),
+            Ok(Ok(keys)) => keys,
+        };
+        let certificate = enclave_calls::run(EnclaveCall::CreateKeyAttestation, move || {
+            let _enclave = EnclaveGuard::enter().map_err(Failure::Enclave)?;
+            match untrusted_create_key_attestation(&challenge) {
+                // An error happened in the SGX sdk.
+                Err(e) => Err(Failure::Sdk(crash_report::sdk_failure(
+                    "create_key_attestation",
+                    e,
+                ))),
+                // An error was returned from the enclave.
+                Ok(Err(e)) => Err(Failure::Enclave(Error::enclave_err(e.to_string()))),
+                Ok(Ok(certificate)) => Ok(certificate),
+            }
+        })?;
+        KeyAttestation::new(&keys, &challenge, &certificate)?.to_json()
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Returns a quote of the enclave committing to the node's registration keys and `nonce`, for verifiers like
+/// bridges which attest the node live, see `attestation`.
+///
+/// `nonce` must be 32 bytes picked by the verifier, who sends the quote to IAS and checks the answer with
+/// `verify_attestation_response`. Creating the quote follows the policy of `set_enclave_call_policy`.
+#[no_mangle]
+pub extern "C" fn attestation_challenge(nonce: Buffer, err: Option<&mut Buffer>) -> Buffer {
+    let r = catch_unwind(|| {
+        let nonce = unsafe { nonce.read() }.ok_or_else(|| Error::empty_arg(NONCE_ARG))?;
+        let nonce = key_attestation::parse_challenge(nonce)?;
+        enclave_calls::run(EnclaveCall::AttestationChallenge, move || {
+            let _enclave = EnclaveGuard::enter().map_err(Failure::Enclave)?;
+            match untrusted_create_challenge_quote(&nonce) {
+                // An error happened in the SGX sdk.
+                Err(e) => Err(Failure::Sdk(crash_report::sdk_failure(
+                    "attestation_challenge",
+                    e,
+                ))),
+                // An error was returned from the enclave.
+                Ok(Err(e)) => Err(Failure::Enclave(Error::enclave_err(e.to_string()))),
+                Ok(Ok(quote)) => Ok(quote),
+            }
+        })
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+/// Checks the answer of IAS to a quote of `attestation_challenge`, see `attestation`.
+///
+/// `keys` are the 64 bytes of the node and seed exchange public keys the verifier expects, and `response` is
+/// JSON like `{"report":"<body>","signature":"<base64>","signing_cert":"<PEM>"}`. Reports older than
+/// `max_age_secs` are rejected, unless it is 0. Returns the JSON-encoded `AttestationVerdict`, which tells the
+/// measurements of the enclave the verifier must compare with the ones it trusts. Reports of platforms lacking
+/// updates fail with the `TcbOutdated` errno.
+#[no_mangle]
+pub extern "C" fn verify_attestation_response(
+    nonce: Buffer,
+    keys: Buffer,
+    quote: Buffer,
+    response: Buffer,
+    max_age_secs: u64,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(|| {
+        let nonce = unsafe { nonce.read() }.ok_or_else(|| Error::empty_arg(NONCE_ARG))?;
+        let nonce = key_attestation::parse_challenge(nonce)?;
+        let keys = unsafe { keys.read() }.ok_or_else(|| Error::empty_arg(KEYS_ARG))?;
+        let keys = attestation::parse_keys(keys)?;
+        let quote = unsafe { quote.read() }.ok_or_else(|| Error::empty_arg(QUOTE_ARG))?;
+        let response = unsafe { response.read() }.ok_or_else(|| Error::empty_arg(RESPONSE_ARG))?;
+        let response = IasResponse::parse(response)?;
+
+        let signature = response.decoded_signature()?;
+        let _enclave = EnclaveGuard::enter()?;
+        match untrusted_verify_ias_report(
+            response.report.as_bytes(),
+            &signature,
+            response.signing_cert.as_bytes(),
+        ) {
+            // An error happened in the SGX sdk.
+            Err(e) => return Err(crash_report::sdk_failure("verify_attestation_response", e)),
+            // The enclave rejected the signature or the certificate.
+            Ok(Err(e)) => return Err(Error::enclave_err(e.to_string())),
+            Ok(Ok(())) => {}
+        }
+        let verdict = attestation::check_report(&response, quote, keys, &nonce, max_age_secs)?;
+        serde_json::to_vec(&verdict).map_err(Error::vm_err)
+    })
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
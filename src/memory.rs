@@ -1,6 +1,9 @@
+use std::cell::RefCell;
 use std::mem;
 use std::slice;
 
+use crate::shared_region;
+
 #[no_mangle]
 pub extern "C" fn allocate_rust(ptr: *const u8, length: usize) -> Buffer {
     // Go doesn't store empty buffers the same way Rust stores empty slices (with NonNull  pointers
@@ -16,6 +19,10 @@ pub extern "C" fn allocate_rust(ptr: *const u8, length: usize) -> Buffer {
 // this frees memory we released earlier
 #[no_mangle]
 pub extern "C" fn free_rust(buf: Buffer) {
+    // results in a shared region were not allocated by us, see `release_shared`
+    if shared_region::is_shared(&buf) {
+        return;
+    }
     unsafe {
         let _ = buf.consume();
     }
@@ -73,6 +80,16 @@ impl Buffer {
             cap: v.capacity(),
         }
     }
+
+    /// Points a `Buffer` at `data` without copying, as Go does for arguments with `sendSlice`.
+    /// The library only reads such buffers, so they must not be consumed.
+    pub fn view(data: &[u8]) -> Self {
+        Buffer {
+            ptr: data.as_ptr() as *mut u8,
+            len: data.len(),
+            cap: data.len(),
+        }
+    }
 }
 
 impl Default for Buffer {
@@ -85,6 +102,119 @@ impl Default for Buffer {
     }
 }
 
+// the size of the chunks the arena allocates from. Larger allocations get a chunk of their own.
+const ARENA_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A bump allocator for the buffers we hand to Go callbacks during a contract call.
+///
+/// Memory is never freed individually. It is reused wholesale once the outermost call ends.
+#[derive(Default)]
+struct Arena {
+    chunks: Vec<Box<[u8]>>,
+    // the chunk we currently allocate from, and the start of its free space
+    current: usize,
+    offset: usize,
+    // the number of active `ArenaScope`s on this thread
+    scopes: usize,
+}
+
+impl Arena {
+    fn alloc(&mut self, data: &[u8]) -> *mut u8 {
+        while self.current < self.chunks.len() {
+            let chunk = &mut self.chunks[self.current];
+            if chunk.len() - self.offset >= data.len() {
+                let dest = &mut chunk[self.offset..self.offset + data.len()];
+                dest.copy_from_slice(data);
+                self.offset += data.len();
+                return dest.as_mut_ptr();
+            }
+            self.current += 1;
+            self.offset = 0;
+        }
+        let size = data.len().max(ARENA_CHUNK_SIZE);
+        self.chunks.push(vec![0u8; size].into_boxed_slice());
+        self.alloc(data)
+    }
+
+    fn contains(&self, ptr: *const u8) -> bool {
+        self.chunks.iter().any(|chunk| {
+            let start = chunk.as_ptr();
+            start <= ptr && ptr < start.wrapping_add(chunk.len())
+        })
+    }
+
+    /// Makes all memory available again, keeping one regular chunk for the next call
+    fn reset(&mut self) {
+        self.chunks.retain(|chunk| chunk.len() == ARENA_CHUNK_SIZE);
+        self.chunks.truncate(1);
+        self.current = 0;
+        self.offset = 0;
+    }
+}
+
+thread_local! {
+    static ARENA: RefCell<Arena> = RefCell::new(Arena::default());
+}
+
+/// Lets `Buffer::temp` allocate from the arena until it is dropped.
+///
+/// Scopes nest, e.g. when a contract queries another one. The arena is only reset when the outermost
+/// scope ends, so every temporary buffer must be released before its scope is dropped.
+pub struct ArenaScope {
+    _private: (),
+}
+
+impl ArenaScope {
+    pub fn enter() -> Self {
+        ARENA.with(|arena| arena.borrow_mut().scopes += 1);
+        ArenaScope { _private: () }
+    }
+}
+
+impl Drop for ArenaScope {
+    fn drop(&mut self) {
+        ARENA.with(|arena| {
+            let mut arena = arena.borrow_mut();
+            arena.scopes -= 1;
+            if arena.scopes == 0 {
+                arena.reset();
+            }
+        });
+    }
+}
+
+impl Buffer {
+    /// Copies `data` into a buffer which only lives while we call into Go.
+    ///
+    /// Within an `ArenaScope` the data is copied into the arena, otherwise onto the heap.
+    /// The buffer must be released with `release_temp`, never with `consume`.
+    pub fn temp(data: &[u8]) -> Self {
+        ARENA.with(|arena| {
+            let mut arena = arena.borrow_mut();
+            if arena.scopes == 0 {
+                return Buffer::from_vec(data.to_vec());
+            }
+            Buffer {
+                ptr: arena.alloc(data),
+                len: data.len(),
+                cap: data.len(),
+            }
+        })
+    }
+
+    /// Releases a buffer created by `temp`. Arena memory is released when its scope ends.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must have been created by `temp` within the current scope, and must not be released twice.
+    pub unsafe fn release_temp(self) {
+        let in_arena = ARENA.with(|arena| arena.borrow().contains(self.ptr));
+        if !in_arena {
+            let _ = self.consume();
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -126,6 +256,18 @@ mod test {
         unsafe { buffer.consume() };
     }
 
+    #[test]
+    fn free_rust_ignores_shared_results() {
+        let mut region = [7u8; 3];
+        let shared = Buffer {
+            ptr: region.as_mut_ptr(),
+            len: region.len(),
+            cap: 0,
+        };
+        free_rust(shared);
+        assert_eq!(region, [7u8; 3]);
+    }
+
     #[test]
     fn from_vec_and_consume_work() {
         let mut original: Vec<u8> = vec![0x00, 0xaa, 0x76];
@@ -176,4 +318,50 @@ mod test {
         assert_eq!(restored.len(), 0);
         assert_eq!(restored.capacity(), 0);
     }
+
+    #[test]
+    fn temp_uses_heap_outside_of_scopes() {
+        let buffer = Buffer::temp(b"foo");
+        assert_eq!(unsafe { buffer.read() }, Some(&b"foo"[..]));
+        assert!(!ARENA.with(|arena| arena.borrow().contains(buffer.ptr)));
+        unsafe { buffer.release_temp() };
+    }
+
+    #[test]
+    fn temp_uses_arena_within_scope() {
+        let scope = ArenaScope::enter();
+        let first = Buffer::temp(b"foo");
+        let second = Buffer::temp(b"bar");
+        assert_eq!(unsafe { first.read() }, Some(&b"foo"[..]));
+        assert_eq!(unsafe { second.read() }, Some(&b"bar"[..]));
+        assert!(ARENA.with(|arena| arena.borrow().contains(second.ptr)));
+        assert_eq!(second.ptr, unsafe { first.ptr.add(3) });
+        unsafe {
+            first.release_temp();
+            second.release_temp();
+        }
+        drop(scope);
+        ARENA.with(|arena| assert_eq!(arena.borrow().offset, 0));
+    }
+
+    #[test]
+    fn arena_handles_large_allocations() {
+        let _scope = ArenaScope::enter();
+        let small = Buffer::temp(b"foo");
+        let large = vec![7u8; ARENA_CHUNK_SIZE + 1];
+        let buffer = Buffer::temp(&large);
+        assert_eq!(unsafe { buffer.read() }, Some(large.as_slice()));
+        assert_eq!(unsafe { small.read() }, Some(&b"foo"[..]));
+    }
+
+    #[test]
+    fn nested_scopes_keep_outer_memory() {
+        let outer = ArenaScope::enter();
+        let buffer = Buffer::temp(b"foo");
+        drop(ArenaScope::enter());
+        ARENA.with(|arena| assert_eq!(arena.borrow().offset, 3));
+        assert_eq!(unsafe { buffer.read() }, Some(&b"foo"[..]));
+        drop(outer);
+        ARENA.with(|arena| assert_eq!(arena.borrow().offset, 0));
+    }
 }
@@ -0,0 +1,109 @@
+//! The authorization of migrations.
+//!
+//! Who may migrate a contract used to be decided by Go alone. Now `migrate` takes a `MigrateAuth`: the admin Go
+//! stores for the contract, and whether a governance proposal runs the migration. The migration only runs if
+//! governance runs it or the sender of the message is the admin, so contracts without an admin can only be
+//! migrated by governance.
+//!
+//! The enclave gets the context as the `migrate_auth` field of the env, which only this library sets, and makes
+//! the decision: it compares the sender with the admin it recorded for the contract, and fails if Go stores
+//! another admin. This library does not check the sender itself, as a host rewriting the auth would skip the
+//! check as well.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::Error;
+
+const AUTH_ARG: &str = "auth";
+/// The field of the env with the authorization
+pub const MIGRATE_AUTH_FIELD: &str = "migrate_auth";
+
+/// Why a migration may run, passed to `migrate` as JSON
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MigrateAuth {
+    /// The admin of the contract, if it has one
+    #[serde(default)]
+    pub admin: Option<String>,
+    /// Whether a governance proposal runs the migration
+    #[serde(default)]
+    pub governance: bool,
+}
+
+impl MigrateAuth {
+    pub fn from_json(auth: &[u8]) -> Result<Self, Error> {
+        let auth: MigrateAuth =
+            serde_json::from_slice(auth).map_err(|e| Error::invalid_arg(AUTH_ARG, e))?;
+        if auth.admin.as_deref() == Some("") {
+            return Err(Error::invalid_arg(
+                AUTH_ARG,
+                "the admin must not be empty, leave it out instead",
+            ));
+        }
+        Ok(auth)
+    }
+}
+
+/// The env of a migration with `auth`, built from the env `params`
+pub fn env_with_auth(params: &[u8], auth: &MigrateAuth) -> Result<Vec<u8>, Error> {
+    let mut env: Value =
+        serde_json::from_slice(params).map_err(|e| Error::invalid_arg("params", e))?;
+    let fields = env
+        .as_object_mut()
+        .ok_or_else(|| Error::invalid_arg("params", "expected a JSON object"))?;
+    if fields.contains_key(MIGRATE_AUTH_FIELD) {
+        let msg = format!("the {} field is set by the library", MIGRATE_AUTH_FIELD);
+        return Err(Error::invalid_arg("params", msg));
+    }
+    env[MIGRATE_AUTH_FIELD] = serde_json::to_value(auth).map_err(Error::vm_err)?;
+    serde_json::to_vec(&env).map_err(Error::vm_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: &[u8] = br#"{"message":{"sender":"secret1admin","sent_funds":[]}}"#;
+
+    fn auth(admin: Option<&str>, governance: bool) -> MigrateAuth {
+        MigrateAuth {
+            admin: admin.map(str::to_string),
+            governance,
+        }
+    }
+
+    #[test]
+    fn from_json_works() {
+        assert_eq!(
+            MigrateAuth::from_json(br#"{"admin":"secret1admin"}"#).unwrap(),
+            auth(Some("secret1admin"), false)
+        );
+        assert_eq!(
+            MigrateAuth::from_json(br#"{"governance":true}"#).unwrap(),
+            auth(None, true)
+        );
+        assert!(MigrateAuth::from_json(br#"{"admin":""}"#).is_err());
+        assert!(MigrateAuth::from_json(br#"{"owner":"secret1admin"}"#).is_err());
+        assert!(MigrateAuth::from_json(b"").is_err());
+    }
+
+    #[test]
+    fn env_with_auth_adds_the_auth() {
+        let env = env_with_auth(PARAMS, &auth(Some("secret1other"), false)).unwrap();
+        let env: Value = serde_json::from_slice(&env).unwrap();
+        assert_eq!(
+            env["migrate_auth"],
+            serde_json::json!({ "admin": "secret1other", "governance": false })
+        );
+        assert_eq!(env["message"]["sender"], "secret1admin");
+    }
+
+    #[test]
+    fn env_with_auth_rejects_invalid_envs() {
+        let governance = auth(None, true);
+        let spoofed = br#"{"message":{"sender":"x"},"migrate_auth":{"governance":true}}"#;
+        assert!(env_with_auth(spoofed, &governance).is_err());
+        assert!(env_with_auth(b"[]", &governance).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Version tags of the compiled modules the VM keeps on disk.
+//!
+//! The VM stores the modules it compiles in the `modules` directory of the cache, and loads them instead of
+//! compiling a code again, without checking what compiled them. After an upgrade of this library or the
+//! enclave, or a change of the float policy, a node would keep running modules compiled differently than the
+//! ones of the nodes it must agree with. `init_cache` therefore tags the directory with the `ModuleFormat` the
+//! modules are compiled in, and removes the modules of another format before the VM opens the directory. The
+//! VM compiles the removed codes again from their wasm on their next use. `get_module_stats` counts both.
+//!
+//! Loading a module deserializes all of it into memory, which makes the first call of every code after a
+//! restart slow, and the memory of nodes with thousands of contracts large. With the `mmap_modules` cache
+//! option the VM maps the module files instead and only reads the parts it runs, if its backend supports it.
+//! Other backends keep deserializing, which `get_module_stats` tells.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::vm::{set_module_mmap, untrusted_get_mrenclave, Checksum};
+
+/// The directory inside the cache's data dir in which the VM stores compiled modules
+pub const MODULES_DIR: &str = "modules";
+const FORMAT_FILE: &str = "format.json";
+
+/// Bumped whenever something else starts to affect how modules are compiled
+pub const MODULE_FORMAT_VERSION: u32 = 1;
+
+/// What the compiled modules depend on besides their wasm
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ModuleFormat {
+    pub version: u32,
+    /// The version of this library, which pins the VM and its compiler
+    pub library_version: String,
+    /// `sgx` or `vanilla`
+    pub backend: String,
+    /// The hex MRENCLAVE of the enclave compiling the modules, if any
+    pub enclave: Option<String>,
+    /// Whether the compiled code canonicalizes NaNs, see `float_policy`
+    pub canonicalize_nans: bool,
+}
+
+impl ModuleFormat {
+    /// The format modules are compiled in by this process
+    pub fn current(canonicalize_nans: bool) -> Result<Self, Error> {
+        let enclave = if cfg!(feature = "vanilla") {
+            None
+        } else {
+            let mrenclave =
+                untrusted_get_mrenclave().map_err(|e| Error::enclave_err(e.to_string()))?;
+            Some(hex::encode(mrenclave))
+        };
+        Ok(ModuleFormat {
+            version: MODULE_FORMAT_VERSION,
+            library_version: env!("CARGO_PKG_VERSION").to_string(),
+            backend: if cfg!(feature = "vanilla") {
+                "vanilla"
+            } else {
+                "sgx"
+            }
+            .to_string(),
+            enclave,
+            canonicalize_nans,
+        })
+    }
+}
+
+/// The counters of `get_module_stats`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ModuleStats {
+    pub format: ModuleFormat,
+    /// Whether the VM maps the modules from disk, see `mmap_modules`
+    pub mmap: bool,
+    /// The modules removed at startup, as they were compiled in another format
+    pub invalidated: u64,
+    /// The removed modules the VM compiled again since
+    pub recompiled: u64,
+}
+
+/// The compiled modules of a cache
+pub struct Modules {
+    format: ModuleFormat,
+    /// The hex checksums of the codes whose modules were removed and not compiled again yet
+    stale: HashSet<String>,
+    invalidated: u64,
+    recompiled: u64,
+    mmap: bool,
+}
+
+impl Modules {
+    /// Removes the modules in `data_dir` unless they were compiled in `format`, and tags the directory with it.
+    /// Must be called before the VM opens the directory.
+    pub fn open(data_dir: &Path, format: ModuleFormat) -> Result<Self, Error> {
+        let dir = data_dir.join(MODULES_DIR);
+        let tagged = Modules::tagged(data_dir)?;
+        let mut stale = HashSet::new();
+        if tagged.as_ref() != Some(&format) && dir.exists() {
+            remove_modules(&dir, &mut stale)?;
+        }
+        let invalidated = stale.len() as u64;
+        if invalidated > 0 {
+            warn!(
+                "Removed {} compiled modules of another format than {:?}, they are compiled again on their next use",
+                invalidated, format
+            );
+        }
+        fs::create_dir_all(&dir)?;
+        let data = serde_json::to_vec(&format).map_err(Error::vm_err)?;
+        fs::write(dir.join(FORMAT_FILE), data)?;
+        Ok(Modules {
+            format,
+            stale,
+            invalidated,
+            recompiled: 0,
+            mmap: false,
+        })
+    }
+
+    /// The format the modules in `data_dir` were compiled in, if they are tagged. An unreadable tag is treated
+    /// like a different format.
+    pub fn tagged(data_dir: &Path) -> Result<Option<ModuleFormat>, Error> {
+        match fs::read(data_dir.join(MODULES_DIR).join(FORMAT_FILE)) {
+            Ok(data) => Ok(serde_json::from_slice(&data).ok()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether the modules are compiled to canonicalize NaNs, see `consensus_params`
+    pub fn canonicalize_nans(&self) -> bool {
+        self.format.canonicalize_nans
+    }
+
+    /// Asks the VM to map the modules from disk. Returns whether its backend does.
+    pub fn enable_mmap(&mut self) -> bool {
+        self.mmap = set_module_mmap(true);
+        if !self.mmap {
+            warn!("The VM backend cannot map modules from disk, it keeps deserializing them");
+        }
+        self.mmap
+    }
+
+    /// Counts the recompilation of `code_id`, if its module was removed, before it is instantiated
+    pub fn record_load(&mut self, code_id: &Checksum) {
+        self.record_load_hex(&code_id.to_hex());
+    }
+
+    fn record_load_hex(&mut self, checksum: &str) {
+        if self.stale.remove(checksum) {
+            self.recompiled += 1;
+        }
+    }
+
+    pub fn stats(&self) -> ModuleStats {
+        ModuleStats {
+            format: self.format.clone(),
+            invalidated: self.invalidated,
+            recompiled: self.recompiled,
+            mmap: self.mmap,
+        }
+    }
+}
+
+/// Removes the files below `dir` but its tag, collecting the names of the modules, which are their checksums
+fn remove_modules(dir: &Path, removed: &mut HashSet<String>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            // the VM keeps the modules of every compiler backend in a directory of its own
+            remove_modules(&path, removed)?;
+            fs::remove_dir(&path)?;
+        } else if entry.file_name() != FORMAT_FILE {
+            fs::remove_file(&path)?;
+            removed.insert(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn format(library_version: &str) -> ModuleFormat {
+        ModuleFormat {
+            version: MODULE_FORMAT_VERSION,
+            library_version: library_version.to_string(),
+            backend: "sgx".to_string(),
+            enclave: Some(hex::encode([1u8; 32])),
+            canonicalize_nans: false,
+        }
+    }
+
+    fn store_module(dir: &TempDir, checksum: &str) {
+        let backend_dir = dir.path().join(MODULES_DIR).join("singlepass");
+        fs::create_dir_all(&backend_dir).unwrap();
+        fs::write(backend_dir.join(checksum), b"compiled").unwrap();
+    }
+
+    #[test]
+    fn open_keeps_modules_of_the_same_format() {
+        let dir = TempDir::new().unwrap();
+        Modules::open(dir.path(), format("0.10.0")).unwrap();
+        store_module(&dir, "aa");
+
+        let modules = Modules::open(dir.path(), format("0.10.0")).unwrap();
+        assert_eq!(modules.stats().invalidated, 0);
+        let module = dir.path().join(MODULES_DIR).join("singlepass").join("aa");
+        assert!(module.exists());
+    }
+
+    #[test]
+    fn open_removes_modules_of_other_formats() {
+        let dir = TempDir::new().unwrap();
+        Modules::open(dir.path(), format("0.10.0")).unwrap();
+        store_module(&dir, "aa");
+        store_module(&dir, "bb");
+
+        let mut modules = Modules::open(dir.path(), format("0.11.0")).unwrap();
+        assert_eq!(modules.stats().invalidated, 2);
+        assert!(!dir.path().join(MODULES_DIR).join("singlepass").exists());
+        let tag = fs::read(dir.path().join(MODULES_DIR).join(FORMAT_FILE)).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<ModuleFormat>(&tag).unwrap(),
+            format("0.11.0")
+        );
+
+        modules.record_load_hex("aa");
+        modules.record_load_hex("aa");
+        modules.record_load_hex("cc");
+        assert_eq!(modules.stats().recompiled, 1);
+    }
+
+    #[test]
+    fn open_removes_untagged_modules() {
+        let dir = TempDir::new().unwrap();
+        store_module(&dir, "aa");
+        let modules = Modules::open(dir.path(), format("0.10.0")).unwrap();
+        assert_eq!(modules.stats().invalidated, 1);
+    }
+}
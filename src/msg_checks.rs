@@ -0,0 +1,69 @@
+//! Checks of the messages of contract calls before they reach the enclave.
+//!
+//! Messages come from transactions, so the error a malformed one fails with ends up in the results of the block.
+//! The errors of serde_json name lines and columns and may change with its version, so the checks fail with
+//! `Error::InvalidMsg` and one of the fixed reasons below instead. They are part of `ERROR_TEMPLATES_VERSION`,
+//! so all nodes reject a malformed message with the same error, and the contract never runs.
+//!
+//! Messages must not be empty and must fit `max_msg_size` of the consensus params. Messages to plaintext codes
+//! must be UTF-8 JSON. Messages to other codes are encrypted, and the enclave checks the JSON inside.
+
+use serde::de::IgnoredAny;
+
+use crate::error::Error;
+
+const EMPTY: &str = "the message is empty";
+const NOT_UTF8: &str = "the message is not valid UTF-8";
+const NOT_JSON: &str = "the message is not valid JSON";
+
+/// Fails with `Error::InvalidMsg` if `msg` is malformed. A `max_size` of 0 means no limit.
+pub fn check(msg: &[u8], plaintext: bool, max_size: usize) -> Result<(), Error> {
+    if msg.is_empty() {
+        return Err(Error::invalid_msg(EMPTY));
+    }
+    if max_size != 0 && msg.len() > max_size {
+        return Err(Error::invalid_msg(format!(
+            "the message of {} bytes exceeds the maximum of {}",
+            msg.len(),
+            max_size
+        )));
+    }
+    if plaintext {
+        let msg = std::str::from_utf8(msg).map_err(|_| Error::invalid_msg(NOT_UTF8))?;
+        serde_json::from_str::<IgnoredAny>(msg).map_err(|_| Error::invalid_msg(NOT_JSON))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(msg: &[u8], plaintext: bool, max_size: usize) -> String {
+        match check(msg, plaintext, max_size) {
+            Err(Error::InvalidMsg { reason, .. }) => reason,
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn check_accepts_well_formed_messages() {
+        check(br#"{"release":{}}"#, true, 0).unwrap();
+        check(br#""unit_variant""#, true, 14).unwrap();
+        // encrypted messages are opaque
+        check(&[0x8a, 0x01, 0xff], false, 0).unwrap();
+    }
+
+    #[test]
+    fn check_uses_fixed_reasons() {
+        assert_eq!(reason(b"", false, 0), EMPTY);
+        assert_eq!(
+            reason(b"{}{}", false, 3),
+            "the message of 4 bytes exceeds the maximum of 3"
+        );
+        assert_eq!(reason(&[b'"', 0xff, b'"'], true, 0), NOT_UTF8);
+        for malformed in &[&b"{"[..], b"{\"a\":}", b"{} {}", b"nul"] {
+            assert_eq!(reason(malformed, true, 0), NOT_JSON);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Optional enforcement of a contract's storage namespace.
+//!
+//! Normally Go hands us a store that is already prefixed with the contract's namespace. Go may instead
+//! pass the parent store along with the namespace in `DB.namespace`. We then prepend the namespace to
+//! every key ourselves, and check that every key coming back from an iterator lies inside of it, so no
+//! bug in the VM can make a contract touch another contract's storage.
+
+use cosmwasm_std::KV;
+
+use crate::iterator::{prefix_end, SeekableIterator};
+use crate::vm::{FfiError, FfiResult, StorageIterator};
+
+/// `key` inside of `namespace`
+pub fn apply(namespace: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut full = Vec::with_capacity(namespace.len() + key.len());
+    full.extend_from_slice(namespace);
+    full.extend_from_slice(key);
+    full
+}
+
+/// The bounds of a range inside of `namespace`. An unbounded end becomes the end of the namespace.
+pub fn apply_bounds(
+    namespace: &[u8],
+    start: Option<&[u8]>,
+    end: Option<&[u8]>,
+) -> (Vec<u8>, Option<Vec<u8>>) {
+    let start = apply(namespace, start.unwrap_or_default());
+    let end = match end {
+        Some(end) => Some(apply(namespace, end)),
+        None => prefix_end(namespace),
+    };
+    (start, end)
+}
+
+/// Removes `namespace` from a key read from the store, failing if the key lies outside of it
+pub fn strip(namespace: &[u8], mut key: Vec<u8>) -> Result<Vec<u8>, FfiError> {
+    if !key.starts_with(namespace) {
+        return Err(FfiError::unknown(
+            "Iterator returned a key outside of the contract's namespace",
+        ));
+    }
+    key.drain(..namespace.len());
+    Ok(key)
+}
+
+/// Strips the namespace from the keys of the items of `inner`
+pub struct NamespacedIter<T: ?Sized> {
+    inner: Box<T>,
+    namespace: Vec<u8>,
+}
+
+impl<T: ?Sized> NamespacedIter<T> {
+    pub fn new(inner: Box<T>, namespace: &[u8]) -> Self {
+        NamespacedIter {
+            inner,
+            namespace: namespace.to_vec(),
+        }
+    }
+}
+
+impl<T: StorageIterator + ?Sized> StorageIterator for NamespacedIter<T> {
+    fn next(&mut self) -> FfiResult<Option<KV>> {
+        let (result, gas_info) = self.inner.next();
+        let result = match result {
+            Ok(Some((key, value))) => strip(&self.namespace, key).map(|key| Some((key, value))),
+            other => other,
+        };
+        (result, gas_info)
+    }
+}
+
+impl<T: SeekableIterator + ?Sized> SeekableIterator for NamespacedIter<T> {
+    fn seek(&mut self, key: &[u8]) -> FfiResult<()> {
+        self.inner.seek(&apply(&self.namespace, key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::GasInfo;
+
+    #[test]
+    fn apply_bounds_stays_in_namespace() {
+        let (start, end) = apply_bounds(b"ns", None, None);
+        assert_eq!(start, b"ns".to_vec());
+        assert_eq!(end, Some(b"nt".to_vec()));
+
+        let (start, end) = apply_bounds(b"ns", Some(b"a"), Some(b"b"));
+        assert_eq!(start, b"nsa".to_vec());
+        assert_eq!(end, Some(b"nsb".to_vec()));
+    }
+
+    #[test]
+    fn strip_rejects_foreign_keys() {
+        assert_eq!(strip(b"ns", b"nskey".to_vec()).unwrap(), b"key".to_vec());
+        assert!(strip(b"ns", b"otherkey".to_vec()).is_err());
+    }
+
+    struct MockIterator(std::vec::IntoIter<KV>);
+
+    impl StorageIterator for MockIterator {
+        fn next(&mut self) -> FfiResult<Option<KV>> {
+            (Ok(self.0.next()), GasInfo::free())
+        }
+    }
+
+    #[test]
+    fn namespaced_iter_strips_keys() {
+        let items = vec![
+            (b"nsa".to_vec(), b"1".to_vec()),
+            (b"xxb".to_vec(), b"2".to_vec()),
+        ];
+        let mut iter = NamespacedIter::new(Box::new(MockIterator(items.into_iter())), b"ns");
+        assert_eq!(iter.next().0.unwrap(), Some((b"a".to_vec(), b"1".to_vec())));
+        assert!(iter.next().0.is_err());
+        assert_eq!(iter.next().0.unwrap(), None);
+    }
+}
@@ -0,0 +1,183 @@
+//! Bounding the storage iterators a contract keeps open at once.
+//!
+//! Every iterator holds a view of the Go store, and a thread if Go lets us read ahead, until the contract drops
+//! it. A contract opening iterators in a loop would make the node hold any number of them. `max_open_iterators`
+//! of the consensus params caps the iterators open at once within one execution of a contract. Opening one more
+//! fails in the storage callback, and the call then fails with `Error::IteratorLimitExceeded`, whatever the
+//! contract did with the failed callback.
+//!
+//! Every execution counts its own iterators, so a query nesting in a call has a cap of its own. The most
+//! iterators an execution had open at once are part of its `get_code_stats` entry.
+
+use std::cell::Cell;
+
+use cosmwasm_std::KV;
+
+use crate::error::Error;
+use crate::iterator::SeekableIterator;
+use crate::vm::{FfiError, FfiResult, StorageIterator};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct Count {
+    open: u32,
+    /// The most iterators open at once so far
+    high_water: u32,
+    /// 0 for no limit
+    max: u32,
+    /// Whether the execution tried to open more than `max` iterators
+    exceeded: bool,
+}
+
+thread_local! {
+    /// The count of the innermost execution on this thread
+    static CURRENT: Cell<Option<Count>> = Cell::new(None);
+}
+
+/// Counts the iterators of an execution until it is dropped
+pub struct IteratorScope {
+    /// The count of the execution this one nests in
+    previous: Option<Count>,
+}
+
+impl IteratorScope {
+    pub fn enter(max_open_iterators: u32) -> Self {
+        let count = Count {
+            max: max_open_iterators,
+            ..Count::default()
+        };
+        let previous = CURRENT.with(|current| current.replace(Some(count)));
+        IteratorScope { previous }
+    }
+
+    /// Fails with `Error::IteratorLimitExceeded` if the execution tried to open more iterators than allowed
+    pub fn check(&self) -> Result<(), Error> {
+        match CURRENT.with(Cell::get) {
+            Some(count) if count.exceeded => Err(Error::iterator_limit_exceeded(count.max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for IteratorScope {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.previous));
+    }
+}
+
+/// The most iterators the innermost execution on this thread had open at once
+pub fn high_water() -> u32 {
+    CURRENT.with(Cell::get).map_or(0, |count| count.high_water)
+}
+
+/// Counts as an open iterator of the innermost execution until it is dropped
+pub struct OpenIterator {
+    _private: (),
+}
+
+/// Counts an iterator which is about to be opened, or fails if the execution has as many open as allowed.
+/// Outside of executions, iterators are not counted.
+pub fn open() -> Result<OpenIterator, FfiError> {
+    CURRENT.with(|current| {
+        if let Some(mut count) = current.get() {
+            if count.max != 0 && count.open >= count.max {
+                count.exceeded = true;
+                current.set(Some(count));
+                return Err(FfiError::unknown(format!(
+                    "more than {} open iterators",
+                    count.max
+                )));
+            }
+            count.open += 1;
+            count.high_water = count.high_water.max(count.open);
+            current.set(Some(count));
+        }
+        Ok(OpenIterator { _private: () })
+    })
+}
+
+impl Drop for OpenIterator {
+    fn drop(&mut self) {
+        CURRENT.with(|current| {
+            if let Some(mut count) = current.get() {
+                count.open = count.open.saturating_sub(1);
+                current.set(Some(count));
+            }
+        });
+    }
+}
+
+/// An iterator which counts as open until it is dropped
+pub struct CountedIter<T: ?Sized> {
+    inner: Box<T>,
+    _open: OpenIterator,
+}
+
+impl<T: ?Sized> CountedIter<T> {
+    pub fn new(inner: Box<T>, open: OpenIterator) -> Self {
+        CountedIter { inner, _open: open }
+    }
+}
+
+impl<T: StorageIterator + ?Sized> StorageIterator for CountedIter<T> {
+    fn next(&mut self) -> FfiResult<Option<KV>> {
+        self.inner.next()
+    }
+}
+
+impl<T: SeekableIterator + ?Sized> SeekableIterator for CountedIter<T> {
+    fn seek(&mut self, key: &[u8]) -> FfiResult<()> {
+        self.inner.seek(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_counts_iterators() {
+        let scope = IteratorScope::enter(2);
+        let first = open().unwrap();
+        let second = open().unwrap();
+        assert_eq!(high_water(), 2);
+        drop(first);
+        let third = open().unwrap();
+        drop(second);
+        drop(third);
+        assert_eq!(high_water(), 2);
+        scope.check().unwrap();
+    }
+
+    #[test]
+    fn open_fails_over_the_limit() {
+        let scope = IteratorScope::enter(1);
+        let first = open().unwrap();
+        assert!(open().is_err());
+        // closing one does not undo the failure
+        drop(first);
+        let _second = open().unwrap();
+        match scope.check() {
+            Err(Error::IteratorLimitExceeded { max, .. }) => assert_eq!(max, 1),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn scopes_nest() {
+        let outer = IteratorScope::enter(1);
+        let _open = open().unwrap();
+        {
+            let inner = IteratorScope::enter(0);
+            let _nested: Vec<OpenIterator> = (0..5).map(|_| open().unwrap()).collect();
+            assert_eq!(high_water(), 5);
+            inner.check().unwrap();
+        }
+        assert_eq!(high_water(), 1);
+        outer.check().unwrap();
+        drop(outer);
+
+        // outside of executions nothing is counted
+        let _free: Vec<OpenIterator> = (0..5).map(|_| open().unwrap()).collect();
+        assert_eq!(high_water(), 0);
+    }
+}
@@ -0,0 +1,201 @@
+//! Query permits: offline signed documents allowing the signer's data to be queried from a set of contracts.
+//!
+//! The signed document is an amino `StdSignDoc` with a single `query_permit` message and zeroed fee,
+//! account number and sequence, so it can be signed by any wallet without being a valid transaction. The zero
+//! fee is in the fee denom of the chain, which the caller passes, as wallets sign it in that denom.
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::crash_report;
+use crate::error::Error;
+use crate::vm::untrusted_verify_permit_signature;
+
+const PERMIT_MSG_TYPE: &str = "query_permit";
+/// The only type of key the enclave verifies permit signatures with
+const SECP256K1_PUBKEY_TYPE: &str = "tendermint/PubKeySecp256k1";
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Permit {
+    pub params: PermitParams,
+    pub signature: PermitSignature,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PermitParams {
+    pub permit_name: String,
+    pub allowed_tokens: Vec<String>,
+    pub chain_id: String,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PermitSignature {
+    pub pub_key: PubKey,
+    /// base64 encoded secp256k1 signature
+    pub signature: String,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PubKey {
+    pub r#type: String,
+    /// base64 encoded compressed secp256k1 public key
+    pub value: String,
+}
+
+impl Permit {
+    pub fn from_json(permit: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(permit).map_err(Error::invalid_permit)
+    }
+
+    /// The canonical (sorted keys, no whitespace) JSON of the amino sign doc the wallet signed, with a zero
+    /// fee in `fee_denom`
+    pub fn sign_bytes(&self, fee_denom: &str) -> Result<Vec<u8>, Error> {
+        let doc = json!({
+            "account_number": "0",
+            "chain_id": self.params.chain_id,
+            "fee": {
+                "amount": [{ "amount": "0", "denom": fee_denom }],
+                "gas": "1",
+            },
+            "memo": "",
+            "msgs": [{
+                "type": PERMIT_MSG_TYPE,
+                "value": {
+                    "allowed_tokens": self.params.allowed_tokens,
+                    "permissions": self.params.permissions,
+                    "permit_name": self.params.permit_name,
+                },
+            }],
+            "sequence": "0",
+        });
+        // `Value` keeps object keys sorted, which makes this the canonical encoding
+        serde_json::to_vec(&doc).map_err(Error::vm_err)
+    }
+
+    pub fn allows(&self, contract_addr: &str) -> bool {
+        self.params
+            .allowed_tokens
+            .iter()
+            .any(|token| token == contract_addr)
+    }
+}
+
+/// Verifies that `permit` is validly signed, with a zero fee in `fee_denom`, and allows querying
+/// `contract_addr`.
+///
+/// Returns the canonical address of the signer.
+pub fn verify_permit(
+    permit: &[u8],
+    contract_addr: &str,
+    fee_denom: &str,
+) -> Result<Vec<u8>, Error> {
+    let permit = Permit::from_json(permit)?;
+    if !permit.allows(contract_addr) {
+        return Err(Error::invalid_permit(format!(
+            "permit does not apply to contract {}",
+            contract_addr
+        )));
+    }
+    if permit.signature.pub_key.r#type != SECP256K1_PUBKEY_TYPE {
+        return Err(Error::invalid_permit(format!(
+            "unsupported public key type {}, expected {}",
+            permit.signature.pub_key.r#type, SECP256K1_PUBKEY_TYPE
+        )));
+    }
+
+    let pubkey = base64::decode(&permit.signature.pub_key.value).map_err(Error::invalid_permit)?;
+    let signature = base64::decode(&permit.signature.signature).map_err(Error::invalid_permit)?;
+
+    let sign_bytes = permit.sign_bytes(fee_denom)?;
+    match untrusted_verify_permit_signature(&sign_bytes, &signature, &pubkey) {
+        // An error happened in the SGX sdk.
+        Err(e) => Err(crash_report::sdk_failure("verify_permit", e)),
+        // The signature is invalid.
+        Ok(Err(e)) => Err(Error::invalid_permit(e)),
+        Ok(Ok(signer)) => Ok(signer.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permit_json(allowed_tokens: &[&str]) -> Vec<u8> {
+        permit_json_with_key(allowed_tokens, SECP256K1_PUBKEY_TYPE)
+    }
+
+    fn permit_json_with_key(allowed_tokens: &[&str], key_type: &str) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "params": {
+                "permit_name": "test",
+                "allowed_tokens": allowed_tokens,
+                "chain_id": "secret-2",
+                "permissions": ["balance"],
+            },
+            "signature": {
+                "pub_key": {
+                    "type": key_type,
+                    "value": "AyZtxhLgis4Ec66OVlKDnuzEZqqV641sm7PBqwDHh2Ho",
+                },
+                "signature": "c2lnbmF0dXJl",
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn sign_bytes_are_canonical() {
+        let permit = Permit::from_json(&permit_json(&["secret1contract"])).unwrap();
+        let expected = r#"{"account_number":"0","chain_id":"secret-2","fee":{"amount":[{"amount":"0","denom":"uscrt"}],"gas":"1"},"memo":"","msgs":[{"type":"query_permit","value":{"allowed_tokens":["secret1contract"],"permissions":["balance"],"permit_name":"test"}}],"sequence":"0"}"#;
+        assert_eq!(
+            String::from_utf8(permit.sign_bytes("uscrt").unwrap()).unwrap(),
+            expected
+        );
+        let other_denom = String::from_utf8(permit.sign_bytes("uatom").unwrap()).unwrap();
+        assert_eq!(other_denom, expected.replace("uscrt", "uatom"));
+    }
+
+    #[test]
+    fn allows_checks_allowed_tokens() {
+        let permit = Permit::from_json(&permit_json(&["secret1a", "secret1b"])).unwrap();
+        assert!(permit.allows("secret1a"));
+        assert!(permit.allows("secret1b"));
+        assert!(!permit.allows("secret1c"));
+    }
+
+    #[test]
+    fn verify_permit_rejects_other_contracts() {
+        let error = verify_permit(&permit_json(&["secret1a"]), "secret1c", "uscrt").unwrap_err();
+        match error {
+            Error::InvalidPermit { msg, .. } => {
+                assert_eq!(msg, "permit does not apply to contract secret1c")
+            }
+            _ => panic!("expect different error"),
+        }
+    }
+
+    #[test]
+    fn verify_permit_rejects_other_key_types() {
+        let permit = permit_json_with_key(&["secret1a"], "tendermint/PubKeyEd25519");
+        match verify_permit(&permit, "secret1a", "uscrt").unwrap_err() {
+            Error::InvalidPermit { msg, .. } => {
+                assert!(msg.starts_with("unsupported public key type tendermint/PubKeyEd25519"))
+            }
+            _ => panic!("expect different error"),
+        }
+    }
+
+    #[test]
+    fn from_json_rejects_malformed_permits() {
+        let error = Permit::from_json(br#"{"params":{}}"#).unwrap_err();
+        match error {
+            Error::InvalidPermit { .. } => {}
+            _ => panic!("expect different error"),
+        }
+    }
+}
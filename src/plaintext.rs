@@ -0,0 +1,247 @@
+//! Plaintext codes, whose contracts run without encryption.
+//!
+//! Some contracts must be transparent even on a privacy chain, e.g. governance or registries. Codes stored
+//! with `create_plaintext` are marked as plaintext, and all their contracts run in the same pipeline as the
+//! others, but the enclave neither encrypts their state nor their messages and results.
+//!
+//! The enclave learns the mode of a call from the `plaintext` field of the env, which only this library
+//! sets: it is `true` for plaintext codes, and an env of another code must not have it. Queries have no env,
+//! so messages to plaintext contracts, queries included, must be plaintext JSON rather than encrypted envelopes.
+//!
+//! The mode of a code is fixed when it is first stored. It is kept in the `plaintext_codes` file of the
+//! cache's data dir, one hex checksum per line, so Go must store plaintext codes with `create_plaintext` again
+//! when it restores a node from the chain's state. State-sync snapshots carry the plaintext codes, see
+//! `snapshot`, so a node restored from one marks the same codes.
+//!
+//! The enclave cannot verify the mode yet: it takes the `plaintext` field from the host, which could mark any
+//! code as plaintext and make the enclave write the state of its contracts unencrypted. Until the enclave
+//! reads the plaintext codes from consensus state it can verify, plaintext codes must only be enabled on
+//! chains which accept this trust in the host.
+
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::de::IgnoredAny;
+use serde_json::Value;
+
+use crate::error::Error;
+use crate::vm::Checksum;
+
+/// The file of the plaintext codes in the cache's data dir
+const PLAINTEXT_CODES_FILE: &str = "plaintext_codes";
+/// The field of the env telling the enclave to skip encryption
+pub const PLAINTEXT_FIELD: &str = "plaintext";
+
+/// The plaintext codes of a cache
+#[derive(Debug, Default)]
+pub struct PlaintextCodes {
+    path: PathBuf,
+    /// Hex checksums
+    codes: BTreeSet<String>,
+}
+
+impl PlaintextCodes {
+    /// Reads the plaintext codes of the cache at `data_dir`
+    pub fn load(data_dir: &Path) -> Result<Self, Error> {
+        let path = data_dir.join(PLAINTEXT_CODES_FILE);
+        let codes = match fs::read_to_string(&path) {
+            Ok(codes) => codes
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+            Err(e) if e.kind() == ErrorKind::NotFound => BTreeSet::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(PlaintextCodes { path, codes })
+    }
+
+    pub fn contains(&self, code_id: &Checksum) -> bool {
+        self.contains_hex(&code_id.to_hex())
+    }
+
+    pub fn contains_hex(&self, checksum: &str) -> bool {
+        self.codes.contains(checksum)
+    }
+
+    /// Marks `code_id` as plaintext, for good
+    pub fn insert(&mut self, code_id: &Checksum) -> Result<(), Error> {
+        self.extend(&[code_id.to_hex()])
+    }
+
+    /// The hex checksums of the plaintext codes, sorted
+    pub fn hex_codes(&self) -> Vec<String> {
+        self.codes.iter().cloned().collect()
+    }
+
+    /// Fails unless the plaintext codes of a snapshot agree with the codes marked here: a code of the
+    /// snapshot, `codes`, which is plaintext here must be in `plaintext` too
+    pub fn check_snapshot(&self, codes: &[String], plaintext: &[String]) -> Result<(), Error> {
+        if let Some(code) = plaintext
+            .iter()
+            .find(|code| hex::decode(code).map_or(true, |raw| raw.len() != 32))
+        {
+            let msg = format!("invalid plaintext code {}", code);
+            return Err(Error::invalid_arg("payload", msg));
+        }
+        if let Some(code) = codes
+            .iter()
+            .find(|code| self.contains_hex(code) && !plaintext.contains(code))
+        {
+            let msg = format!(
+                "the code {} is plaintext here but not in the snapshot",
+                code
+            );
+            return Err(Error::invalid_arg("payload", msg));
+        }
+        Ok(())
+    }
+
+    /// Marks the hex checksums `codes` as plaintext, for good
+    pub fn extend(&mut self, codes: &[String]) -> Result<(), Error> {
+        let previous = self.codes.clone();
+        self.codes.extend(codes.iter().cloned());
+        if self.codes == previous {
+            return Ok(());
+        }
+        let file: String = self.codes.iter().map(|code| code.clone() + "\n").collect();
+        if let Err(e) = fs::write(&self.path, file) {
+            self.codes = previous;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Fails if `msg` is not plaintext JSON but `code_id` is a plaintext code
+    pub fn check_msg(&self, code_id: &Checksum, msg: &[u8]) -> Result<(), Error> {
+        if self.contains(code_id) && serde_json::from_slice::<IgnoredAny>(msg).is_err() {
+            return Err(Error::invalid_arg(
+                "msg",
+                "contracts of plaintext codes take plaintext JSON messages",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The env of a call of `code_id` with the env `params` from Go, and fails if `msg` does not suit the
+    /// mode of the code
+    pub fn env_for<'a>(
+        &self,
+        code_id: &Checksum,
+        params: &'a [u8],
+        msg: &[u8],
+    ) -> Result<Cow<'a, [u8]>, Error> {
+        self.check_msg(code_id, msg)?;
+        self.env(code_id, params)
+    }
+
+    /// The env of a call of `code_id` without a message, e.g. of a block hook
+    pub fn env<'a>(&self, code_id: &Checksum, params: &'a [u8]) -> Result<Cow<'a, [u8]>, Error> {
+        if self.contains(code_id) {
+            let mut env: Value =
+                serde_json::from_slice(params).map_err(|e| Error::invalid_arg("params", e))?;
+            mark(&mut env, true)?;
+            let env = serde_json::to_vec(&env).map_err(Error::vm_err)?;
+            return Ok(Cow::Owned(env));
+        }
+        // most envs do not mention the field at all, and need not be parsed
+        if contains_field(params) {
+            let env: Value =
+                serde_json::from_slice(params).map_err(|e| Error::invalid_arg("params", e))?;
+            check_unmarked(&env)?;
+        }
+        Ok(Cow::Borrowed(params))
+    }
+}
+
+fn contains_field(params: &[u8]) -> bool {
+    let field = format!("\"{}\"", PLAINTEXT_FIELD);
+    params
+        .windows(field.len())
+        .any(|window| window == field.as_bytes())
+}
+
+fn check_unmarked(env: &Value) -> Result<(), Error> {
+    if env.get(PLAINTEXT_FIELD).is_some() {
+        let msg = format!("the {} field is set by the library", PLAINTEXT_FIELD);
+        return Err(Error::invalid_arg("params", msg));
+    }
+    Ok(())
+}
+
+/// Sets the mode of the call with the env `env`, replacing the mode an env it was built from had
+pub fn mark(env: &mut Value, plaintext: bool) -> Result<(), Error> {
+    let env = env
+        .as_object_mut()
+        .ok_or_else(|| Error::invalid_arg("params", "expected a JSON object"))?;
+    if plaintext {
+        env.insert(PLAINTEXT_FIELD.to_string(), Value::Bool(true));
+    } else {
+        env.remove(PLAINTEXT_FIELD);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use tempfile::TempDir;
+
+    fn checksum(byte: u8) -> Checksum {
+        Checksum::try_from(&[byte; 32][..]).unwrap()
+    }
+
+    #[test]
+    fn codes_persist() {
+        let dir = TempDir::new().unwrap();
+        let mut codes = PlaintextCodes::load(dir.path()).unwrap();
+        assert!(!codes.contains(&checksum(1)));
+        codes.insert(&checksum(1)).unwrap();
+        codes.insert(&checksum(1)).unwrap();
+
+        let codes = PlaintextCodes::load(dir.path()).unwrap();
+        assert!(codes.contains(&checksum(1)));
+        assert!(!codes.contains(&checksum(2)));
+        let file = fs::read_to_string(dir.path().join(PLAINTEXT_CODES_FILE)).unwrap();
+        assert_eq!(file, format!("{}\n", checksum(1).to_hex()));
+    }
+
+    #[test]
+    fn env_for_marks_plaintext_codes() {
+        let dir = TempDir::new().unwrap();
+        let mut codes = PlaintextCodes::load(dir.path()).unwrap();
+        codes.insert(&checksum(1)).unwrap();
+        let params = br#"{"block":{"height":1},"contract":{"address":"x"}}"#;
+        let msg = br#"{"register":{}}"#;
+
+        let env = codes.env_for(&checksum(1), params, msg).unwrap();
+        let env: Value = serde_json::from_slice(&env).unwrap();
+        assert_eq!(env["plaintext"], Value::Bool(true));
+        assert_eq!(env["contract"]["address"], "x");
+        assert!(codes
+            .env_for(&checksum(1), params, &[0x8a, 0x01, 0xff])
+            .is_err());
+
+        let env = codes.env_for(&checksum(2), params, msg).unwrap();
+        assert_eq!(&*env, &params[..]);
+        let spoofed = br#"{"block":{"height":1},"plaintext":true}"#;
+        assert!(codes.env_for(&checksum(2), spoofed, msg).is_err());
+        // the word may appear elsewhere
+        let mentioned = br#"{"block":{"height":1},"contract":{"address":"plaintext"}}"#;
+        assert!(codes.env_for(&checksum(2), mentioned, msg).is_ok());
+    }
+
+    #[test]
+    fn mark_replaces_the_mode() {
+        let mut env = serde_json::json!({ "plaintext": true, "block": {} });
+        mark(&mut env, false).unwrap();
+        assert_eq!(env, serde_json::json!({ "block": {} }));
+        mark(&mut env, true).unwrap();
+        assert_eq!(env["plaintext"], Value::Bool(true));
+        assert!(mark(&mut Value::Null, true).is_err());
+    }
+}
@@ -1,8 +1,14 @@
-use cosmwasm_sgx_vm::{FfiResult, GasInfo, Querier};
 use cosmwasm_std::{Binary, StdResult, SystemError, SystemResult};
 
+use crate::block_context::BlockContext;
+use crate::capabilities::{self, Capability};
 use crate::error::GoResult;
+use crate::gas_report;
+use crate::host_calls::HostCall;
 use crate::memory::Buffer;
+#[cfg(feature = "shadow")]
+use crate::shadow;
+use crate::vm::{FfiError, FfiResult, GasInfo, Querier};
 
 // this represents something passed in from the caller side of FFI
 #[repr(C)]
@@ -17,6 +23,10 @@ pub struct Querier_vtable {
     // We return errors through the return buffer, but may return non-zero error codes on panic
     pub query_external:
         extern "C" fn(*const querier_t, u64, *mut u64, Buffer, *mut Buffer, *mut Buffer) -> i32,
+    // Optional. Tells Go the block a query is pinned to before the contract runs, so the queries it makes
+    // read the same state as its storage.
+    pub set_block_context:
+        Option<extern "C" fn(*const querier_t, BlockContext, *mut Buffer) -> i32>,
 }
 
 #[repr(C)]
@@ -29,13 +39,31 @@ pub struct GoQuerier {
 // TODO: check if we can do this safer...
 unsafe impl Send for GoQuerier {}
 
+impl GoQuerier {
+    /// Hands the block of the upcoming query to Go, if it wants to know
+    pub fn set_block_context(&self, block: BlockContext) -> Result<(), FfiError> {
+        let set_block_context = match self.vtable.set_block_context {
+            Some(f) => f,
+            None => return Ok(()),
+        };
+        let mut err = Buffer::default();
+        let go_result: GoResult =
+            (set_block_context)(self.state, block, &mut err as *mut Buffer).into();
+        let default = || "Failed to set the block context of the querier".to_string();
+        unsafe { go_result.into_ffi_result(err, default) }
+    }
+}
+
 impl Querier for GoQuerier {
     fn query_raw(
         &self,
         request: &[u8],
         gas_limit: u64,
     ) -> FfiResult<SystemResult<StdResult<Binary>>> {
-        let request_buf = Buffer::from_vec(request.to_vec());
+        if let Err(e) = capabilities::require(Capability::Queries) {
+            return (Err(e), GasInfo::free());
+        }
+        let request_buf = Buffer::temp(request);
         let mut result_buf = Buffer::default();
         let mut err = Buffer::default();
         let mut used_gas = 0_u64;
@@ -48,8 +76,8 @@ impl Querier for GoQuerier {
             &mut err as *mut Buffer,
         )
         .into();
-        let gas_info = GasInfo::with_externally_used(used_gas);
-        let _request = unsafe { request_buf.consume() };
+        let gas_info = gas_report::record_host_call(HostCall::QueryChain, used_gas);
+        unsafe { request_buf.release_temp() };
 
         // return complete error message (reading from buffer for GoResult::Other)
         let default = || {
@@ -65,12 +93,18 @@ impl Querier for GoQuerier {
         }
 
         let bin_result = unsafe { result_buf.consume() };
-        let result = serde_json::from_slice(&bin_result).or_else(|e| {
-            Ok(Err(SystemError::InvalidResponse {
-                error: format!("Parsing Go response: {}", e),
-                response: bin_result.into(),
-            }))
-        });
-        (result, gas_info)
+        #[cfg(feature = "shadow")]
+        shadow::record_query(request, &bin_result);
+        (Ok(parse_response(bin_result)), gas_info)
     }
 }
+
+/// Parses the response of Go to a query. Responses which do not parse become a `SystemError`.
+pub fn parse_response(response: Vec<u8>) -> SystemResult<StdResult<Binary>> {
+    serde_json::from_slice(&response).unwrap_or_else(|e| {
+        Err(SystemError::InvalidResponse {
+            error: format!("Parsing Go response: {}", e),
+            response: response.into(),
+        })
+    })
+}
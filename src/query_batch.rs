@@ -0,0 +1,94 @@
+//! Queries of several contracts against one state.
+//!
+//! Dashboards aggregating over many contracts would otherwise query them one by one, and the node may commit a
+//! block between two of the queries, so the results would mix the states of different heights. `query_batch`
+//! runs a list of queries in one call instead, on a DB which Go opens on a single snapshot of the state. Every
+//! contract is looked up through `DB_vtable.resolve_contract` of that DB, so its storage comes from the same
+//! snapshot, and the block of the snapshot is handed to the storage of every contract and to the querier.
+//!
+//! Queries fail one by one: the result of a batch is the JSON list of the `QueryOutcome` of every query, in
+//! order. The queries share the gas limit of the batch.
+
+use cosmwasm_std::Binary;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+const QUERIES_ARG: &str = "queries";
+
+/// A query of a batch, as Go sends it
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BatchQuery {
+    pub contract_addr: String,
+    pub msg: Binary,
+}
+
+/// The result of one query of a batch
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryOutcome {
+    Ok(Binary),
+    Error(String),
+}
+
+impl From<Result<Vec<u8>, Error>> for QueryOutcome {
+    fn from(result: Result<Vec<u8>, Error>) -> Self {
+        match result {
+            Ok(data) => QueryOutcome::Ok(Binary(data)),
+            Err(e) => QueryOutcome::Error(e.to_string()),
+        }
+    }
+}
+
+/// Parses the JSON list of the queries of a batch, which must not be empty
+pub fn parse(queries: &[u8]) -> Result<Vec<BatchQuery>, Error> {
+    let queries: Vec<BatchQuery> =
+        serde_json::from_slice(queries).map_err(|e| Error::invalid_arg(QUERIES_ARG, e))?;
+    if queries.is_empty() {
+        return Err(Error::invalid_arg(QUERIES_ARG, "the batch has no queries"));
+    }
+    Ok(queries)
+}
+
+pub fn to_json(outcomes: &[QueryOutcome]) -> Result<Vec<u8>, Error> {
+    serde_json::to_vec(outcomes).map_err(Error::vm_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_works() {
+        let msg = Binary(b"{}".to_vec());
+        let batch = json!([{ "contract_addr": "secret1a", "msg": msg }]);
+        let queries = parse(&serde_json::to_vec(&batch).unwrap()).unwrap();
+        assert_eq!(
+            queries,
+            vec![BatchQuery {
+                contract_addr: "secret1a".to_string(),
+                msg: Binary(b"{}".to_vec()),
+            }]
+        );
+
+        assert!(parse(b"[]").is_err());
+        assert!(parse(br#"[{"contract_addr":"secret1a"}]"#).is_err());
+        let unknown_field = json!([{ "contract_addr": "a", "msg": msg, "gas": 1 }]);
+        assert!(parse(&serde_json::to_vec(&unknown_field).unwrap()).is_err());
+    }
+
+    #[test]
+    fn outcomes_serialize_in_order() {
+        let outcomes = vec![
+            QueryOutcome::from(Ok(b"{}".to_vec())),
+            QueryOutcome::from(Err(Error::out_of_gas())),
+        ];
+        let expected = json!([{ "ok": Binary(b"{}".to_vec()) }, { "error": "Ran out of gas" }]);
+        assert_eq!(
+            to_json(&outcomes).unwrap(),
+            serde_json::to_vec(&expected).unwrap()
+        );
+    }
+}
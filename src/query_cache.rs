@@ -0,0 +1,188 @@
+//! Results of queries by contract address, reused within a block.
+//!
+//! Public queries like token info or exchange rates are asked thousands of times per block with the same
+//! message, and every time the contract runs again. With the `query_cache_size` option, the results of
+//! successful queries that name their contract, `query_cached`, `query_by_alias` and `query_batch`, are kept
+//! for the height they were asked at, keyed by the contract, the checksum of its code and the hash of the
+//! message. The state of a contract cannot change within a height, so a result stays valid until Go asks at
+//! a greater height, which drops all results. Queries at lower heights, like historical queries, and at
+//! height 0, the latest state, bypass the cache. Pausing a code drops the results too.
+//!
+//! A reused result reports the gas its query used when it ran, and fails with `Error::OutOfGas` if that is
+//! more than the gas limit of the new query, so a result depends on the limit like an executed one. The store
+//! and querier are not called, so they charge nothing.
+
+use std::collections::{HashMap, VecDeque};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+
+/// What an entry costs on top of its result, in bytes
+const ENTRY_OVERHEAD: usize = 128;
+
+/// A query: its contract, code and message
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct QueryKey([u8; 32]);
+
+impl QueryKey {
+    pub fn new(contract_addr: &str, code_id: &[u8], msg: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        // the address is prefixed with its length, so it cannot be confused with the checksum
+        hasher.update((contract_addr.len() as u64).to_be_bytes());
+        hasher.update(contract_addr.as_bytes());
+        hasher.update(code_id);
+        hasher.update(Sha256::digest(msg));
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&hasher.finalize());
+        QueryKey(key)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CachedResult {
+    result: Vec<u8>,
+    gas_used: u64,
+}
+
+/// The cached results of the current height, evicted oldest first once they exceed `max_bytes`
+#[derive(Debug, Default)]
+pub struct QueryCache {
+    max_bytes: usize,
+    height: u64,
+    entries: HashMap<QueryKey, CachedResult>,
+    order: VecDeque<QueryKey>,
+    bytes: usize,
+}
+
+impl QueryCache {
+    /// A cache of up to `max_bytes` of results, which caches nothing if it is 0
+    pub fn new(max_bytes: usize) -> Self {
+        QueryCache {
+            max_bytes,
+            ..QueryCache::default()
+        }
+    }
+
+    /// Whether queries at `height` use the cache. Drops the results of lower heights.
+    fn enter(&mut self, height: u64) -> bool {
+        if self.max_bytes == 0 || height == 0 || height < self.height {
+            return false;
+        }
+        if height > self.height {
+            self.clear();
+            self.height = height;
+        }
+        true
+    }
+
+    /// The result of `key` at `height` and the gas its query used, if it is cached. Fails if the query used
+    /// more than `gas_limit`.
+    pub fn get(
+        &mut self,
+        height: u64,
+        key: &QueryKey,
+        gas_limit: u64,
+    ) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        if !self.enter(height) {
+            return Ok(None);
+        }
+        match self.entries.get(key) {
+            Some(cached) if cached.gas_used > gas_limit => Err(Error::out_of_gas()),
+            Some(cached) => Ok(Some((cached.result.clone(), cached.gas_used))),
+            None => Ok(None),
+        }
+    }
+
+    /// Caches the result of a successful query of `key` at `height`. Results larger than the cache are not.
+    pub fn insert(&mut self, height: u64, key: QueryKey, result: &[u8], gas_used: u64) {
+        let size = result.len() + ENTRY_OVERHEAD;
+        if !self.enter(height) || size > self.max_bytes || self.entries.contains_key(&key) {
+            return;
+        }
+        while self.bytes + size > self.max_bytes {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    if let Some(evicted) = self.entries.remove(&oldest) {
+                        self.bytes -= evicted.result.len() + ENTRY_OVERHEAD;
+                    }
+                }
+                None => break,
+            }
+        }
+        let result = result.to_vec();
+        self.entries.insert(key, CachedResult { result, gas_used });
+        self.order.push_back(key);
+        self.bytes += size;
+    }
+
+    /// Drops all results
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(msg: &[u8]) -> QueryKey {
+        QueryKey::new("secret1token", &[7u8; 32], msg)
+    }
+
+    #[test]
+    fn keys_depend_on_all_parts() {
+        assert_eq!(key(b"{}"), key(b"{}"));
+        assert_ne!(key(b"{}"), key(b"[]"));
+        assert_ne!(key(b"{}"), QueryKey::new("secret1other", &[7u8; 32], b"{}"));
+        assert_ne!(key(b"{}"), QueryKey::new("secret1token", &[8u8; 32], b"{}"));
+    }
+
+    #[test]
+    fn results_are_kept_for_their_height() {
+        let mut cache = QueryCache::new(4096);
+        cache.insert(10, key(b"a"), b"answer", 500);
+        assert_eq!(
+            cache.get(10, &key(b"a"), 1000).unwrap(),
+            Some((b"answer".to_vec(), 500))
+        );
+        assert_eq!(cache.get(10, &key(b"b"), 1000).unwrap(), None);
+        match cache.get(10, &key(b"a"), 499).unwrap_err() {
+            Error::OutOfGas { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // lower heights and the latest state bypass the cache
+        assert_eq!(cache.get(9, &key(b"a"), 1000).unwrap(), None);
+        assert_eq!(cache.get(0, &key(b"a"), 1000).unwrap(), None);
+        assert!(cache.get(10, &key(b"a"), 1000).unwrap().is_some());
+
+        assert_eq!(cache.get(11, &key(b"a"), 1000).unwrap(), None);
+        assert_eq!(cache.get(10, &key(b"a"), 1000).unwrap(), None);
+    }
+
+    #[test]
+    fn oldest_results_are_evicted() {
+        let mut cache = QueryCache::new(2 * (ENTRY_OVERHEAD + 10));
+        cache.insert(1, key(b"a"), &[0u8; 10], 1);
+        cache.insert(1, key(b"b"), &[0u8; 10], 1);
+        cache.insert(1, key(b"c"), &[0u8; 10], 1);
+        assert_eq!(cache.get(1, &key(b"a"), 10).unwrap(), None);
+        assert!(cache.get(1, &key(b"b"), 10).unwrap().is_some());
+        assert!(cache.get(1, &key(b"c"), 10).unwrap().is_some());
+
+        // too large for the cache
+        cache.insert(1, key(b"d"), &[0u8; 1000], 1);
+        assert_eq!(cache.get(1, &key(b"d"), 10).unwrap(), None);
+        assert!(cache.get(1, &key(b"c"), 10).unwrap().is_some());
+    }
+
+    #[test]
+    fn disabled_cache_keeps_nothing() {
+        let mut cache = QueryCache::new(0);
+        cache.insert(1, key(b"a"), b"answer", 1);
+        assert_eq!(cache.get(1, &key(b"a"), 10).unwrap(), None);
+    }
+}
@@ -0,0 +1,212 @@
+//! Limits on the queries a node serves, so a flood of queries cannot starve the enclave.
+//!
+//! Nodes serving public gRPC endpoints share their enclave between block execution and queries of anyone.
+//! With `QueryLimits`, set by Go through `set_query_limits`, queries take a token from a bucket refilled at
+//! `per_second`, and at most `max_concurrent` of them are admitted at a time. Queries over the limits fail at
+//! once with `Error::Throttled`, which Go can turn into a "try again later" for the client.
+//!
+//! Queries are admitted before they wait for the cache, whose lock lets only one call run at a time, so
+//! `max_concurrent` caps the queries waiting for the cache or running, and a flood is shed instead of queueing
+//! up behind the lock. A query of `query_async` counts from when it is queued.
+//!
+//! Only queries Go starts are limited. Queries of contracts by other contracts, which have a call depth
+//! greater than 0, are part of the outer call and must not fail differently on different nodes.
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::error::Error;
+
+/// The limits of queries, see `set_query_limits`. 0 disables a limit.
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryLimits {
+    /// How many queries may start per second on average
+    pub per_second: u32,
+    /// How many queries may start at once after a quiet period. 0 allows `per_second`.
+    pub burst: u32,
+    /// How many queries may wait for the cache or run at the same time
+    pub max_concurrent: u32,
+}
+
+impl QueryLimits {
+    fn burst(&self) -> f64 {
+        if self.burst == 0 {
+            f64::from(self.per_second)
+        } else {
+            f64::from(self.burst)
+        }
+    }
+}
+
+struct Limiter {
+    limits: QueryLimits,
+    /// The tokens left, refilled at `per_second` up to the burst
+    tokens: f64,
+    refilled: Instant,
+    running: u32,
+}
+
+impl Limiter {
+    fn new(limits: QueryLimits, now: Instant) -> Self {
+        Limiter {
+            limits,
+            tokens: limits.burst(),
+            refilled: now,
+            running: 0,
+        }
+    }
+
+    fn acquire(&mut self, now: Instant) -> Result<(), Error> {
+        let limits = self.limits;
+        if limits.max_concurrent != 0 && self.running >= limits.max_concurrent {
+            let reason = format!("more than {} concurrent queries", limits.max_concurrent);
+            return Err(Error::throttled(reason));
+        }
+        if limits.per_second != 0 {
+            let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+            self.tokens =
+                (self.tokens + elapsed * f64::from(limits.per_second)).min(limits.burst());
+            self.refilled = now;
+            if self.tokens < 1.0 {
+                let reason = format!("more than {} queries per second", limits.per_second);
+                return Err(Error::throttled(reason));
+            }
+            self.tokens -= 1.0;
+        }
+        self.running += 1;
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        self.running = self.running.saturating_sub(1);
+    }
+
+    /// Takes new limits, keeping the running queries. The bucket starts full.
+    fn set_limits(&mut self, limits: QueryLimits, now: Instant) {
+        let running = self.running;
+        *self = Limiter::new(limits, now);
+        self.running = running;
+    }
+}
+
+fn lock(limiter: &Mutex<Limiter>) -> MutexGuard<'_, Limiter> {
+    // a panic while holding the lock does not leave the limiter inconsistent
+    limiter.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+lazy_static! {
+    static ref LIMITER: Mutex<Limiter> =
+        Mutex::new(Limiter::new(QueryLimits::default(), Instant::now()));
+}
+
+pub fn set_limits(limits: QueryLimits) {
+    lock(&LIMITER).set_limits(limits, Instant::now());
+}
+
+/// A query counted as running until it is dropped
+pub struct QueryPermit<'a> {
+    limiter: &'a Mutex<Limiter>,
+}
+
+impl QueryPermit<'static> {
+    /// Admits a query with `call_depth`, or fails with `Error::Throttled`. Nested queries are always admitted.
+    pub fn acquire(call_depth: u32) -> Result<Option<Self>, Error> {
+        if call_depth > 0 {
+            return Ok(None);
+        }
+        QueryPermit::acquire_from(&LIMITER, Instant::now()).map(Some)
+    }
+}
+
+impl<'a> QueryPermit<'a> {
+    fn acquire_from(limiter: &'a Mutex<Limiter>, now: Instant) -> Result<Self, Error> {
+        lock(limiter).acquire(now)?;
+        Ok(QueryPermit { limiter })
+    }
+}
+
+impl Drop for QueryPermit<'_> {
+    fn drop(&mut self) {
+        lock(self.limiter).release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limiter_of(
+        per_second: u32,
+        burst: u32,
+        max_concurrent: u32,
+        now: Instant,
+    ) -> Mutex<Limiter> {
+        let limits = QueryLimits {
+            per_second,
+            burst,
+            max_concurrent,
+        };
+        Mutex::new(Limiter::new(limits, now))
+    }
+
+    #[test]
+    fn limits_parse() {
+        let limits: QueryLimits = serde_json::from_slice(br#"{"per_second":50}"#).unwrap();
+        assert_eq!(limits.burst(), 50.0);
+        assert_eq!(limits.max_concurrent, 0);
+        assert!(serde_json::from_slice::<QueryLimits>(br#"{"rate":50}"#).is_err());
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let start = Instant::now();
+        let limiter = limiter_of(2, 3, 0, start);
+        let permits: Vec<_> = (0..3)
+            .map(|_| QueryPermit::acquire_from(&limiter, start).unwrap())
+            .collect();
+        let e = QueryPermit::acquire_from(&limiter, start).err().unwrap();
+        assert_eq!(
+            e.to_string(),
+            "Query throttled: more than 2 queries per second"
+        );
+        drop(permits);
+
+        let later = start + Duration::from_millis(500);
+        assert!(QueryPermit::acquire_from(&limiter, later).is_ok());
+        assert!(QueryPermit::acquire_from(&limiter, later).is_err());
+        // the bucket does not fill beyond the burst
+        let much_later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(QueryPermit::acquire_from(&limiter, much_later).is_ok());
+        }
+        assert!(QueryPermit::acquire_from(&limiter, much_later).is_err());
+    }
+
+    #[test]
+    fn concurrent_queries_are_capped() {
+        let now = Instant::now();
+        let limiter = limiter_of(0, 0, 2, now);
+        let first = QueryPermit::acquire_from(&limiter, now).unwrap();
+        let _second = QueryPermit::acquire_from(&limiter, now).unwrap();
+        let e = QueryPermit::acquire_from(&limiter, now).err().unwrap();
+        assert_eq!(
+            e.to_string(),
+            "Query throttled: more than 2 concurrent queries"
+        );
+        drop(first);
+        assert!(QueryPermit::acquire_from(&limiter, now).is_ok());
+
+        lock(&limiter).set_limits(QueryLimits::default(), now);
+        assert_eq!(lock(&limiter).running, 1);
+    }
+
+    #[test]
+    fn nested_queries_are_not_limited() {
+        assert!(QueryPermit::acquire(1).unwrap().is_none());
+    }
+}
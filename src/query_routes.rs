@@ -0,0 +1,173 @@
+//! Well-known queries under stable names, for infrastructure which should not hardcode contract addresses.
+//!
+//! Go configures a table of routes with `set_query_routes`, mapping an alias like `snip20_router` to a
+//! contract, the checksum of the code it must run and a message template. `query_by_alias` renders the
+//! template with the arguments of the caller and queries the contract. A route only reaches a contract which
+//! still runs the code of its checksum, so a migrated contract fails the query instead of answering with
+//! another schema.
+//!
+//! Templates are JSON, in which a string like `"{{owner}}"` is a placeholder replaced by the JSON value of the
+//! argument `owner`. The rendered message is plaintext JSON, so only contracts of plaintext codes can be
+//! reached. Routes are not persisted: Go must configure them again after restarting the node.
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::error::Error;
+
+const ROUTES_ARG: &str = "routes";
+const ALIAS_ARG: &str = "alias";
+const ARGS_ARG: &str = "args";
+
+/// The contract and message of an alias
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct QueryRoute {
+    pub contract_addr: String,
+    /// The hex checksum of the code the contract must run
+    pub checksum: String,
+    /// The message template, see the module docs
+    pub msg: Value,
+}
+
+/// The routes of a cache by alias
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryRoutes(HashMap<String, QueryRoute>);
+
+impl QueryRoutes {
+    /// Parses a JSON object of `QueryRoute`s by alias
+    pub fn from_json(routes: &[u8]) -> Result<Self, Error> {
+        let routes: HashMap<String, QueryRoute> =
+            serde_json::from_slice(routes).map_err(|e| Error::invalid_arg(ROUTES_ARG, e))?;
+        for (alias, route) in routes.iter() {
+            let invalid = |msg: &str| Error::invalid_arg(ROUTES_ARG, format!("{}: {}", alias, msg));
+            if alias.is_empty() {
+                return Err(Error::invalid_arg(ROUTES_ARG, "aliases must not be empty"));
+            }
+            match hex::decode(&route.checksum) {
+                Ok(checksum) if checksum.len() == 32 => {}
+                _ => return Err(invalid("the checksum must be 32 hex encoded bytes")),
+            }
+            if route.contract_addr.is_empty() {
+                return Err(invalid("the contract address must not be empty"));
+            }
+        }
+        Ok(QueryRoutes(routes))
+    }
+
+    /// The route of `alias` and its message rendered with the JSON object `args`
+    pub fn resolve(&self, alias: &str, args: &[u8]) -> Result<(&QueryRoute, Vec<u8>), Error> {
+        let route = self.0.get(alias).ok_or_else(|| {
+            Error::invalid_arg(ALIAS_ARG, format!("no query route for {}", alias))
+        })?;
+        let args: Map<String, Value> =
+            serde_json::from_slice(args).map_err(|e| Error::invalid_arg(ARGS_ARG, e))?;
+        let mut used = BTreeSet::new();
+        let msg = render(&route.msg, &args, &mut used)?;
+        if let Some(unused) = args.keys().find(|name| !used.contains(name.as_str())) {
+            let msg = format!("{} takes no argument {}", alias, unused);
+            return Err(Error::invalid_arg(ARGS_ARG, msg));
+        }
+        let msg = serde_json::to_vec(&msg).map_err(Error::vm_err)?;
+        Ok((route, msg))
+    }
+}
+
+/// The name of the placeholder `value` is, if it is one
+fn placeholder(value: &str) -> Option<&str> {
+    let name = value.strip_prefix("{{")?.strip_suffix("}}")?;
+    if name.is_empty() {
+        return None;
+    }
+    Some(name)
+}
+
+fn render<'a>(
+    template: &'a Value,
+    args: &Map<String, Value>,
+    used: &mut BTreeSet<&'a str>,
+) -> Result<Value, Error> {
+    Ok(match template {
+        Value::String(s) => match placeholder(s) {
+            Some(name) => {
+                used.insert(name);
+                args.get(name).cloned().ok_or_else(|| {
+                    Error::invalid_arg(ARGS_ARG, format!("missing argument {}", name))
+                })?
+            }
+            None => template.clone(),
+        },
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render(item, args, used))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(fields) => {
+            let mut rendered = Map::new();
+            for (key, value) in fields {
+                rendered.insert(key.clone(), render(value, args, used)?);
+            }
+            Value::Object(rendered)
+        }
+        _ => template.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECKSUM: &str = "2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a";
+
+    fn routes() -> QueryRoutes {
+        let routes = format!(
+            r#"{{"balance":{{"contract_addr":"secret1token","checksum":"{}",
+                "msg":{{"balance":{{"address":"{{{{owner}}}}","denoms":["uscrt","{{{{denom}}}}"]}}}}}}}}"#,
+            CHECKSUM
+        );
+        QueryRoutes::from_json(routes.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn resolve_renders_the_template() {
+        let routes = routes();
+        let (route, msg) = routes
+            .resolve("balance", br#"{"owner":"secret1me","denom":{"ibc":7}}"#)
+            .unwrap();
+        assert_eq!(route.contract_addr, "secret1token");
+        let msg: Value = serde_json::from_slice(&msg).unwrap();
+        assert_eq!(
+            msg,
+            serde_json::json!({"balance":{"address":"secret1me","denoms":["uscrt",{"ibc":7}]}})
+        );
+    }
+
+    #[test]
+    fn resolve_checks_the_arguments() {
+        let routes = routes();
+        assert!(routes.resolve("supply", b"{}").is_err());
+        // missing, unknown and malformed arguments
+        assert!(routes.resolve("balance", br#"{"owner":"a"}"#).is_err());
+        let extra = br#"{"owner":"a","denom":"b","limit":3}"#;
+        assert!(routes.resolve("balance", extra).is_err());
+        assert!(routes.resolve("balance", b"[]").is_err());
+    }
+
+    #[test]
+    fn from_json_checks_routes() {
+        assert_eq!(
+            QueryRoutes::from_json(b"{}").unwrap(),
+            QueryRoutes::default()
+        );
+        let short = r#"{"a":{"contract_addr":"secret1a","checksum":"2a2a","msg":{}}}"#;
+        assert!(QueryRoutes::from_json(short.as_bytes()).is_err());
+        let unnamed = format!(
+            r#"{{"":{{"contract_addr":"secret1a","checksum":"{}","msg":{{}}}}}}"#,
+            CHECKSUM
+        );
+        assert!(QueryRoutes::from_json(unnamed.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! Detecting Go callbacks which call back into the library in ways it does not support.
+//!
+//! While a contract runs, the callbacks of its DB, API and querier run on the same thread. The querier may run
+//! a query of another contract through `query`, which nests in the running call. Any other call of the library
+//! from a callback would deadlock or corrupt the call it nests in: an `instantiate`, `handle` or `migrate` would
+//! run a second transaction on the storage the outer call is writing, and functions changing the cache, like
+//! `create` or `release_cache`, would change it under the running contract. These fail with
+//! `Error::IllegalReentrancy` instead, before they use the cache. Most of them take the cache first, which
+//! does not block: the call they nest in holds it on the same thread, and `CacheCell::lock` hands it to the
+//! callbacks of that call right away.
+
+use std::cell::RefCell;
+
+use crate::code_stats::EntryPoint;
+use crate::error::Error;
+
+thread_local! {
+    /// The contract calls running on this thread, innermost last
+    static ACTIVE: RefCell<Vec<EntryPoint>> = RefCell::new(Vec::new());
+}
+
+fn innermost() -> Option<EntryPoint> {
+    ACTIVE.with(|active| active.borrow().last().copied())
+}
+
+/// Marks a contract call as running on this thread until it is dropped
+pub struct ReentrancyGuard {
+    _private: (),
+}
+
+impl ReentrancyGuard {
+    /// Enters a call of `entry_point`. Within another call, only queries may run.
+    pub fn enter(entry_point: EntryPoint) -> Result<Self, Error> {
+        if let Some(active) = innermost() {
+            if entry_point != EntryPoint::Query {
+                return Err(Error::illegal_reentrancy(entry_point.name(), active.name()));
+            }
+        }
+        ACTIVE.with(|active| active.borrow_mut().push(entry_point));
+        Ok(ReentrancyGuard { _private: () })
+    }
+}
+
+impl Drop for ReentrancyGuard {
+    fn drop(&mut self) {
+        ACTIVE.with(|active| active.borrow_mut().pop());
+    }
+}
+
+/// Fails if a contract call runs on this thread, for `function`s which must not run within one
+pub fn check_outside_calls(function: &str) -> Result<(), Error> {
+    match innermost() {
+        Some(active) => Err(Error::illegal_reentrancy(function, active.name())),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_nest_in_calls() {
+        let _handle = ReentrancyGuard::enter(EntryPoint::Handle).unwrap();
+        let _query = ReentrancyGuard::enter(EntryPoint::Query).unwrap();
+        let _nested = ReentrancyGuard::enter(EntryPoint::Query).unwrap();
+    }
+
+    #[test]
+    fn other_calls_do_not_nest() {
+        let query = ReentrancyGuard::enter(EntryPoint::Query).unwrap();
+        for entry_point in &[EntryPoint::Init, EntryPoint::Handle, EntryPoint::Migrate] {
+            match ReentrancyGuard::enter(*entry_point) {
+                Err(Error::IllegalReentrancy { call, active, .. }) => {
+                    assert_eq!(call, entry_point.name());
+                    assert_eq!(active, "query");
+                }
+                _ => panic!("expected an illegal reentrancy"),
+            }
+        }
+        assert!(check_outside_calls("create").is_err());
+
+        // the calls which failed left no trace
+        drop(query);
+        let _handle = ReentrancyGuard::enter(EntryPoint::Handle).unwrap();
+    }
+
+    #[test]
+    fn check_outside_calls_works() {
+        check_outside_calls("create").unwrap();
+        {
+            let _init = ReentrancyGuard::enter(EntryPoint::Init).unwrap();
+            let err = check_outside_calls("release_cache").unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Illegal reentrancy: release_cache called from within init"
+            );
+        }
+        check_outside_calls("create").unwrap();
+    }
+}
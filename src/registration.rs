@@ -0,0 +1,93 @@
+//! The public keys a node registers with the network.
+
+use serde::Serialize;
+
+use crate::error::Error;
+
+/// Bumped whenever the serialized format of `RegistrationKeys` changes
+pub const REGISTRATION_KEYS_VERSION: u32 = 1;
+
+const PUBKEY_LEN: usize = 32;
+const X25519_KEY_TYPE: &str = "x25519";
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PublicKey {
+    pub r#type: String,
+    /// base64 encoded key
+    pub value: String,
+}
+
+impl PublicKey {
+    fn x25519(key: &[u8]) -> Self {
+        PublicKey {
+            r#type: X25519_KEY_TYPE.to_string(),
+            value: base64::encode(key),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RegistrationKeys {
+    pub version: u32,
+    /// The key transactions are encrypted to
+    pub node_pubkey: PublicKey,
+    /// The key the bootstrap node encrypts the consensus seed to when the node registers
+    pub seed_exchange_pubkey: PublicKey,
+}
+
+impl RegistrationKeys {
+    /// Parses the keys as returned by the enclave: the node public key followed by the seed exchange public key
+    pub fn from_enclave(keys: &[u8]) -> Result<Self, Error> {
+        if keys.len() != 2 * PUBKEY_LEN {
+            return Err(Error::enclave_err(format!(
+                "Expected {} bytes of registration keys, got {}",
+                2 * PUBKEY_LEN,
+                keys.len()
+            )));
+        }
+        let (node_pubkey, seed_exchange_pubkey) = keys.split_at(PUBKEY_LEN);
+        Ok(RegistrationKeys {
+            version: REGISTRATION_KEYS_VERSION,
+            node_pubkey: PublicKey::x25519(node_pubkey),
+            seed_exchange_pubkey: PublicKey::x25519(seed_exchange_pubkey),
+        })
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::vm_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_enclave_splits_keys() {
+        let mut keys = vec![1u8; 32];
+        keys.extend_from_slice(&[2u8; 32]);
+        let keys = RegistrationKeys::from_enclave(&keys).unwrap();
+        assert_eq!(keys.version, REGISTRATION_KEYS_VERSION);
+        assert_eq!(keys.node_pubkey, PublicKey::x25519(&[1u8; 32]));
+        assert_eq!(keys.seed_exchange_pubkey, PublicKey::x25519(&[2u8; 32]));
+    }
+
+    #[test]
+    fn from_enclave_checks_length() {
+        assert!(RegistrationKeys::from_enclave(&[0u8; 63]).is_err());
+    }
+
+    #[test]
+    fn to_json_works() {
+        let keys = RegistrationKeys::from_enclave(&[0u8; 64]).unwrap();
+        let zeros = base64::encode(&[0u8; 32]);
+        let expected = format!(
+            r#"{{"version":1,"node_pubkey":{{"type":"x25519","value":"{0}"}},"seed_exchange_pubkey":{{"type":"x25519","value":"{0}"}}}}"#,
+            zeros
+        );
+        assert_eq!(
+            String::from_utf8(keys.to_json().unwrap()).unwrap(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Positions of storage iterators which outlive the iterator, for paging through large ranges.
+//!
+//! A `ResumableIter` remembers the range it was opened with and the last key it returned. Its `token` is an
+//! opaque encoding of that position, which can be handed to Go and passed back to `DB::resume_range` in a later
+//! call. The resumed iterator is opened right after the last key, so the store seeks to it instead of the caller
+//! scanning the range from the start on every page.
+//!
+//! Contracts cannot page with tokens: the VM only opens iterators through `Storage::range`, and has no import
+//! handing out or taking a token. Resumable iterators are for the scans of this library and of Go.
+//!
+//! Keys in tokens are relative to the namespace of the DB, like all keys of its API, so a token cannot reach
+//! outside the storage of the contract it is resumed in. Decoding checks the last key lies within the range.
+
+use std::convert::TryInto;
+
+use cosmwasm_std::{Order, KV};
+
+use crate::state_export::key_after;
+use crate::vm::{FfiError, FfiResult, StorageIterator};
+
+/// The version of the token encoding, its first byte
+const TOKEN_VERSION: u8 = 1;
+
+/// The position of an iterator in its range
+#[derive(Clone, Debug, PartialEq)]
+pub struct IteratorPosition {
+    /// The inclusive start of the range
+    pub start: Option<Vec<u8>>,
+    /// The exclusive end of the range
+    pub end: Option<Vec<u8>>,
+    pub order: Order,
+    /// The last key returned, `None` before the first one
+    pub last_key: Option<Vec<u8>>,
+}
+
+impl IteratorPosition {
+    /// The bounds of the rest of the range, after `last_key` in iteration order
+    pub fn remaining_bounds(&self) -> (Option<Vec<u8>>, Option<Vec<u8>>) {
+        match (&self.last_key, self.order) {
+            (None, _) => (self.start.clone(), self.end.clone()),
+            (Some(last), Order::Ascending) => (Some(key_after(last)), self.end.clone()),
+            (Some(last), Order::Descending) => (self.start.clone(), Some(last.clone())),
+        }
+    }
+
+    pub fn to_token(&self) -> Vec<u8> {
+        let mut token = vec![TOKEN_VERSION, i32::from(self.order) as u8];
+        for field in &[&self.start, &self.end, &self.last_key] {
+            match field {
+                Some(key) => {
+                    token.push(1);
+                    token.extend_from_slice(&(key.len() as u32).to_be_bytes());
+                    token.extend_from_slice(key);
+                }
+                None => token.push(0),
+            }
+        }
+        token
+    }
+
+    pub fn from_token(token: &[u8]) -> Result<Self, FfiError> {
+        let invalid = || FfiError::user_err("Invalid iterator resume token");
+        let mut reader = TokenReader(token);
+        if reader.byte().ok_or_else(invalid)? != TOKEN_VERSION {
+            return Err(invalid());
+        }
+        let order = match reader.byte().ok_or_else(invalid)? {
+            1 => Order::Ascending,
+            2 => Order::Descending,
+            _ => return Err(invalid()),
+        };
+        let start = reader.key().ok_or_else(invalid)?;
+        let end = reader.key().ok_or_else(invalid)?;
+        let last_key = reader.key().ok_or_else(invalid)?;
+        if !reader.0.is_empty() {
+            return Err(invalid());
+        }
+        if let Some(last) = &last_key {
+            let before_start = matches!(&start, Some(start) if last < start);
+            let after_end = matches!(&end, Some(end) if last >= end);
+            if before_start || after_end {
+                return Err(invalid());
+            }
+        }
+        Ok(IteratorPosition {
+            start,
+            end,
+            order,
+            last_key,
+        })
+    }
+}
+
+/// Reads the fields of a token, `None` meaning it is cut short
+struct TokenReader<'a>(&'a [u8]);
+
+impl<'a> TokenReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.take(1).map(|taken| taken[0])
+    }
+
+    fn key(&mut self) -> Option<Option<Vec<u8>>> {
+        match self.byte()? {
+            0 => Some(None),
+            1 => {
+                let len = u32::from_be_bytes(self.take(4)?.try_into().ok()?);
+                Some(Some(self.take(len as usize)?.to_vec()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An iterator which knows its position, see `token`
+pub struct ResumableIter {
+    inner: Box<dyn StorageIterator>,
+    position: IteratorPosition,
+    done: bool,
+}
+
+impl ResumableIter {
+    pub fn new(inner: Box<dyn StorageIterator>, position: IteratorPosition) -> Self {
+        ResumableIter {
+            inner,
+            position,
+            done: false,
+        }
+    }
+
+    /// The token to resume after the last item returned, or `None` once the range is exhausted
+    pub fn token(&self) -> Option<Vec<u8>> {
+        if self.done {
+            return None;
+        }
+        Some(self.position.to_token())
+    }
+}
+
+impl StorageIterator for ResumableIter {
+    fn next(&mut self) -> FfiResult<Option<KV>> {
+        let (result, gas_info) = self.inner.next();
+        match &result {
+            Ok(Some((key, _))) => self.position.last_key = Some(key.clone()),
+            Ok(None) => self.done = true,
+            Err(_) => {}
+        }
+        (result, gas_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::GasInfo;
+
+    struct MockIterator(std::vec::IntoIter<KV>);
+
+    impl StorageIterator for MockIterator {
+        fn next(&mut self) -> FfiResult<Option<KV>> {
+            (Ok(self.0.next()), GasInfo::free())
+        }
+    }
+
+    fn position(order: Order, last_key: Option<&[u8]>) -> IteratorPosition {
+        IteratorPosition {
+            start: Some(b"b".to_vec()),
+            end: Some(b"y".to_vec()),
+            order,
+            last_key: last_key.map(<[u8]>::to_vec),
+        }
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        for position in &[
+            position(Order::Ascending, None),
+            position(Order::Descending, Some(b"k")),
+            IteratorPosition {
+                start: None,
+                end: None,
+                order: Order::Ascending,
+                last_key: Some(Vec::new()),
+            },
+        ] {
+            let token = position.to_token();
+            assert_eq!(&IteratorPosition::from_token(&token).unwrap(), position);
+        }
+    }
+
+    #[test]
+    fn from_token_rejects_invalid_tokens() {
+        let token = position(Order::Ascending, Some(b"k")).to_token();
+        assert!(IteratorPosition::from_token(&token[..token.len() - 1]).is_err());
+        assert!(IteratorPosition::from_token(&[token.clone(), vec![0]].concat()).is_err());
+        assert!(IteratorPosition::from_token(&[&[2u8][..], &token[1..]].concat()).is_err());
+        assert!(IteratorPosition::from_token(b"").is_err());
+        // the last key must lie within the range
+        let outside = position(Order::Ascending, Some(b"z")).to_token();
+        assert!(IteratorPosition::from_token(&outside).is_err());
+    }
+
+    #[test]
+    fn remaining_bounds_follow_the_order() {
+        let (start, end) = position(Order::Ascending, None).remaining_bounds();
+        assert_eq!((start, end), (Some(b"b".to_vec()), Some(b"y".to_vec())));
+        let (start, end) = position(Order::Ascending, Some(b"k")).remaining_bounds();
+        assert_eq!((start, end), (Some(b"k\0".to_vec()), Some(b"y".to_vec())));
+        let (start, end) = position(Order::Descending, Some(b"k")).remaining_bounds();
+        assert_eq!((start, end), (Some(b"b".to_vec()), Some(b"k".to_vec())));
+    }
+
+    #[test]
+    fn resumable_iter_tracks_its_position() {
+        let items = vec![
+            (b"c".to_vec(), b"1".to_vec()),
+            (b"d".to_vec(), b"2".to_vec()),
+        ];
+        let inner = Box::new(MockIterator(items.into_iter()));
+        let mut iter = ResumableIter::new(inner, position(Order::Ascending, None));
+        iter.next().0.unwrap();
+        let token = iter.token().unwrap();
+        assert_eq!(
+            IteratorPosition::from_token(&token).unwrap(),
+            position(Order::Ascending, Some(b"c"))
+        );
+        iter.next().0.unwrap();
+        assert!(iter.token().is_some());
+        assert_eq!(iter.next().0.unwrap(), None);
+        assert_eq!(iter.token(), None);
+    }
+}
@@ -0,0 +1,91 @@
+//! The execution of a contract, shared by the calls of Go and the internal calls of `internal_calls`.
+//!
+//! Every execution checks its code against the cache before it is instantiated, limits the iterators and
+//! capabilities of the contract, and is counted in the code stats, whichever entry point it runs.
+
+use crate::api::GoApi;
+use crate::cache::Cache;
+use crate::capabilities::CapabilityScope;
+use crate::code_stats::{EntryPoint, Execution};
+use crate::consensus_params::ConsensusParams;
+use crate::crash_report;
+use crate::db::DB;
+use crate::error::Error;
+use crate::open_iterators::IteratorScope;
+use crate::querier::GoQuerier;
+use crate::vm::{Checksum, Extern, Instance, VmResult};
+
+/// An execution of a contract for `run_contract`
+pub struct ContractCall {
+    pub code_id: Checksum,
+    pub entry_point: EntryPoint,
+    pub gas_limit: u64,
+    /// The height of the block the call runs in, which selects the wasm costs
+    pub height: u64,
+    /// Whether the call executes a transaction rather than a query, see `Cache::configure_vm`
+    pub executes: bool,
+}
+
+/// A finished execution of a contract. The execution stays restricted until `finish` is called.
+pub struct ContractRun {
+    /// The result of the contract, before the restrictions are checked
+    pub result: VmResult<Vec<u8>>,
+    /// The gas the contract used, as the VM charged it
+    pub gas_used: u64,
+    iterators: IteratorScope,
+    capabilities: CapabilityScope,
+}
+
+impl ContractRun {
+    /// The result of the contract, failing if it exceeded the restrictions of its execution
+    pub fn finish(self) -> Result<Vec<u8>, Error> {
+        self.iterators.check()?;
+        self.capabilities.check()?;
+        Ok(self.result?)
+    }
+}
+
+/// Runs `execute` on an instance of the code of `call` with `deps`. Fails without running the contract if its code
+/// may not run.
+pub fn run_contract<F>(
+    cache: &mut Cache,
+    consensus: &ConsensusParams,
+    call: &ContractCall,
+    deps: Extern<DB, GoApi, GoQuerier>,
+    execute: F,
+) -> Result<ContractRun, Error>
+where
+    F: FnOnce(&mut Instance<DB, GoApi, GoQuerier>) -> VmResult<Vec<u8>>,
+{
+    let code_id = &call.code_id;
+    cache.verify_code(code_id)?;
+    cache.check_memory_limit(code_id)?;
+    cache.check_float_policy(code_id)?;
+    cache.configure_vm(consensus, call.height, call.executes);
+    let iterators = IteratorScope::enter(consensus.max_open_iterators);
+    let capabilities =
+        CapabilityScope::enter(*code_id, consensus.code_capabilities.denied(code_id));
+    let mut execution = Execution::start();
+    let mut instance = cache.get_instance(code_id, deps, call.gas_limit)?;
+    // We only check this result after reporting gas usage and returning the instance into the cache.
+    let result = execute(&mut instance);
+    let gas_used = instance.create_gas_report().used_internally;
+    execution.measure_memory(&instance);
+    instance.recycle();
+    cache.code_stats.record(
+        code_id,
+        call.entry_point,
+        execution,
+        gas_used,
+        result.is_ok(),
+    );
+    if let Err(e) = &result {
+        crash_report::execution_failure(call.entry_point, code_id, call.gas_limit, gas_used, e);
+    }
+    Ok(ContractRun {
+        result,
+        gas_used,
+        iterators,
+        capabilities,
+    })
+}
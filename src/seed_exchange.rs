@@ -0,0 +1,70 @@
+//! The versions of the handshake in which a bootstrap node sends the consensus seed to a registering node.
+//!
+//! In version 1, the seed is encrypted to the registering node's long-term registration key, so anyone who
+//! later obtains that key can decrypt every seed exchanged with it. In version 2, the node registers with a
+//! certificate made by `create_attestation_report_v2`, whose payload carries a fresh ephemeral key and the
+//! version. The bootstrap enclave answers such certificates with an ephemeral key of its own and the seed
+//! encrypted to the ECDH secret of both, which the registering enclave discards after `init_node`.
+//!
+//! The bootstrap enclave picks the version from the certificate, so nodes of both versions can register.
+//! The encrypted seed tells the versions apart: version 1 is the bare ciphertext, version 2 starts with the
+//! version byte and the bootstrap's ephemeral public key.
+
+use crate::error::Error;
+
+const ENCRYPTED_SEED_ARG: &str = "encrypted_seed";
+/// The length of the encrypted seed of version 1
+const CIPHERTEXT_LEN: usize = 48;
+const EPHEMERAL_PUBKEY_LEN: usize = 32;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SeedExchangeVersion {
+    V1 = 1,
+    V2 = 2,
+}
+
+impl SeedExchangeVersion {
+    /// The version of the handshake `encrypted_seed` was produced by
+    pub fn of(encrypted_seed: &[u8]) -> Result<Self, Error> {
+        match encrypted_seed.len() {
+            CIPHERTEXT_LEN => Ok(SeedExchangeVersion::V1),
+            len if len == 1 + EPHEMERAL_PUBKEY_LEN + CIPHERTEXT_LEN
+                && encrypted_seed[0] == SeedExchangeVersion::V2 as u8 =>
+            {
+                Ok(SeedExchangeVersion::V2)
+            }
+            len => {
+                let msg = format!("not an encrypted seed of a known version ({} bytes)", len);
+                Err(Error::invalid_arg(ENCRYPTED_SEED_ARG, msg))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn of_tells_versions_apart() {
+        assert_eq!(
+            SeedExchangeVersion::of(&[7u8; 48]).unwrap(),
+            SeedExchangeVersion::V1
+        );
+        let mut v2 = vec![2u8];
+        v2.extend_from_slice(&[7u8; 80]);
+        assert_eq!(
+            SeedExchangeVersion::of(&v2).unwrap(),
+            SeedExchangeVersion::V2
+        );
+    }
+
+    #[test]
+    fn of_rejects_unknown_seeds() {
+        assert!(SeedExchangeVersion::of(&[]).is_err());
+        assert!(SeedExchangeVersion::of(&[7u8; 47]).is_err());
+        let mut v3 = vec![3u8];
+        v3.extend_from_slice(&[7u8; 80]);
+        assert!(SeedExchangeVersion::of(&v3).is_err());
+    }
+}
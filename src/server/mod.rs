@@ -0,0 +1,352 @@
+//! The execution server, which runs the VM and the enclave in a separate process from the node.
+//!
+//! The node connects over a Unix domain socket and sends the requests it would otherwise make through
+//! the extern "C" functions (`create`, `get_code`, `set_consensus_params`, `instantiate`, `handle`, `migrate`
+//! and `query`). Storage, address and querier callbacks travel back over the same connection, see `protocol`.
+//! Every connection is served on its own thread, but the calls take the cache in turn, as the calls of Go do,
+//! see `CacheCell`. A call waiting for its client in a callback lets the queries of other connections run, so a
+//! contract can query other contracts through the client. A crashing enclave only takes down this process,
+//! which the node can restart independently, after which the node must set the consensus params again.
+
+pub mod protocol;
+mod remote;
+
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use cosmwasm_std::Binary;
+use log::*;
+
+use crate::error::Error;
+use crate::memory::Buffer;
+use crate::{
+    cache_t, create, get_code, handle, init_cache, init_logger, instantiate, migrate, query,
+    set_consensus_params, GasReport,
+};
+
+use protocol::{read_frame, write_frame, ClientMessage, Request, Response, ServerMessage};
+use remote::Connection;
+
+/// The command line of the `cosmwasm-server` binary
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub socket: PathBuf,
+    pub data_dir: String,
+    /// Comma separated, as passed to `init_cache`
+    pub features: String,
+    /// JSON encoded `CacheOptions`
+    pub options: String,
+    pub cache_size: usize,
+}
+
+pub const USAGE: &str = "usage: cosmwasm-server --socket <path> --data-dir <dir> \
+                         [--features <csv>] [--options <json>] [--cache-size <n>]";
+
+impl Config {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, Error> {
+        let mut socket = None;
+        let mut data_dir = None;
+        let mut config = Config {
+            socket: PathBuf::new(),
+            data_dir: String::new(),
+            features: "staking".to_string(),
+            options: String::new(),
+            cache_size: 0,
+        };
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| Error::invalid_arg(flag.as_str(), "missing value"))?;
+            match flag.as_str() {
+                "--socket" => socket = Some(PathBuf::from(value)),
+                "--data-dir" => data_dir = Some(value),
+                "--features" => config.features = value,
+                "--options" => config.options = value,
+                "--cache-size" => {
+                    config.cache_size = value
+                        .parse()
+                        .map_err(|e| Error::invalid_arg(flag.as_str(), e))?
+                }
+                _ => return Err(Error::invalid_arg(flag.as_str(), "unknown flag")),
+            }
+        }
+        config.socket = socket.ok_or_else(|| Error::empty_arg("--socket"))?;
+        config.data_dir = data_dir.ok_or_else(|| Error::empty_arg("--data-dir"))?;
+        Ok(config)
+    }
+}
+
+/// Opens the cache and serves connections on the socket until accepting one fails
+pub fn run(config: Config) -> Result<(), Error> {
+    init_logger();
+    let mut err = Buffer::default();
+    let cache = init_cache(
+        Buffer::view(config.data_dir.as_bytes()),
+        Buffer::view(config.features.as_bytes()),
+        Buffer::view(config.options.as_bytes()),
+        config.cache_size,
+        Some(&mut err),
+    );
+    if cache.is_null() {
+        let msg = unsafe { err.consume() };
+        return Err(Error::vm_err(String::from_utf8_lossy(&msg)));
+    }
+    remove_stale_socket(&config.socket)?;
+    let listener = UnixListener::bind(&config.socket).map_err(Error::vm_err)?;
+    info!("Serving contract calls on {}", config.socket.display());
+    serve(listener, cache).map_err(Error::vm_err)
+}
+
+/// Removes the socket file a previous run left behind, which would make binding fail. Fails if `socket` is
+/// another kind of file, so a mistyped path does not delete it.
+fn remove_stale_socket(socket: &Path) -> Result<(), Error> {
+    match std::fs::symlink_metadata(socket) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            std::fs::remove_file(socket).map_err(Error::vm_err)
+        }
+        Ok(_) => Err(Error::vm_err(format!(
+            "{} exists and is not a socket",
+            socket.display()
+        ))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Error::vm_err(e)),
+    }
+}
+
+/// Serves every connection to `listener` on its own thread
+pub fn serve(listener: UnixListener, cache: *mut cache_t) -> io::Result<()> {
+    // raw pointers are not Send. The calls of all threads lock the cache, see `CacheCell`.
+    let cache = cache as usize;
+    for stream in listener.incoming() {
+        let stream = stream?;
+        thread::spawn(move || {
+            if let Err(e) = serve_connection(stream, cache as *mut cache_t) {
+                warn!("Closed a client connection: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Serves the requests of one client until it disconnects
+pub fn serve_connection(stream: UnixStream, cache: *mut cache_t) -> io::Result<()> {
+    let conn = Connection::new(stream);
+    loop {
+        let request = match read_frame(&conn.stream) {
+            Ok(ClientMessage::Request(request)) => request,
+            Ok(ClientMessage::Reply(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "received a reply without a callback",
+                ))
+            }
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let response = execute(&conn, cache, request);
+        write_frame(&conn.stream, &ServerMessage::Response(response))?;
+    }
+}
+
+fn view_call_id(call_id: &Option<String>) -> Buffer {
+    match call_id {
+        Some(call_id) => Buffer::view(call_id.as_bytes()),
+        None => Buffer::default(),
+    }
+}
+
+fn view_binary(binary: &Option<Binary>) -> Buffer {
+    match binary {
+        Some(binary) => Buffer::view(&binary.0),
+        None => Buffer::default(),
+    }
+}
+
+/// Runs `request` through the extern "C" function Go would call
+fn execute(conn: &Connection, cache: *mut cache_t, request: Request) -> Response {
+    let mut gas_used = 0u64;
+    let mut gas_report = GasReport::default();
+    let mut err = Buffer::default();
+    let data = match &request {
+        Request::Create { wasm } => create(cache, Buffer::view(&wasm.0), Some(&mut err)),
+        Request::GetCode { code_id } => get_code(cache, Buffer::view(&code_id.0), Some(&mut err)),
+        Request::SetConsensusParams { params } => {
+            set_consensus_params(cache, Buffer::view(&params.0), Some(&mut err));
+            Buffer::default()
+        }
+        Request::Instantiate(call) => instantiate(
+            cache,
+            Buffer::view(&call.code_id.0),
+            Buffer::view(&call.params.0),
+            Buffer::view(&call.msg.0),
+            conn.db(),
+            conn.api(),
+            conn.querier(),
+            call.gas_limit,
+            call.call_depth,
+            Some(&mut gas_used),
+            Some(&mut gas_report),
+            view_call_id(&call.call_id),
+            Some(&mut err),
+        ),
+        Request::Handle(call) => handle(
+            cache,
+            Buffer::view(&call.code_id.0),
+            Buffer::view(&call.params.0),
+            Buffer::view(&call.msg.0),
+            conn.db(),
+            conn.api(),
+            conn.querier(),
+            call.gas_limit,
+            call.call_depth,
+            Some(&mut gas_used),
+            Some(&mut gas_report),
+            view_call_id(&call.call_id),
+            Some(&mut err),
+        ),
+        Request::Migrate(call) => migrate(
+            cache,
+            Buffer::view(&call.code_id.0),
+            Buffer::view(&call.params.0),
+            Buffer::view(&call.msg.0),
+            conn.db(),
+            conn.api(),
+            conn.querier(),
+            call.gas_limit,
+            call.call_depth,
+            Some(&mut gas_used),
+            Some(&mut gas_report),
+            view_binary(&call.key_history),
+            view_binary(&call.auth),
+            view_call_id(&call.call_id),
+            Some(&mut err),
+        ),
+        Request::Query {
+            code_id,
+            msg,
+            gas_limit,
+            call_depth,
+            call_id,
+            block,
+            unmetered,
+        } => query(
+            cache,
+            Buffer::view(&code_id.0),
+            Buffer::view(&msg.0),
+            conn.db(),
+            conn.api(),
+            conn.querier(),
+            *gas_limit,
+            *call_depth,
+            *block,
+            *unmetered,
+            Some(&mut gas_used),
+            Some(&mut gas_report),
+            view_call_id(call_id),
+            Some(&mut err),
+        ),
+    };
+    // read errno before anything else can change it
+    let errno = errno::errno().0;
+    let data = unsafe { data.consume() };
+    let error = if err.ptr.is_null() {
+        None
+    } else {
+        let msg = unsafe { err.consume() };
+        Some(String::from_utf8_lossy(&msg).into_owned())
+    };
+    Response {
+        data: match error {
+            None => Some(Binary(data)),
+            Some(_) => None,
+        },
+        error,
+        errno,
+        gas_used,
+        gas_report,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn config_from_args_works() {
+        let config = Config::from_args(args(&[
+            "--socket",
+            "/tmp/cw.sock",
+            "--data-dir",
+            "/data",
+            "--cache-size",
+            "100",
+        ]))
+        .unwrap();
+        assert_eq!(config.socket, PathBuf::from("/tmp/cw.sock"));
+        assert_eq!(config.data_dir, "/data");
+        assert_eq!(config.features, "staking");
+        assert_eq!(config.cache_size, 100);
+    }
+
+    #[test]
+    fn config_from_args_rejects_bad_input() {
+        match Config::from_args(args(&["--data-dir", "/data"])).unwrap_err() {
+            Error::EmptyArg { name, .. } => assert_eq!(name, "--socket"),
+            e => panic!("unexpected error: {}", e),
+        }
+        match Config::from_args(args(&["--socket", "/s", "--verbose", "1"])).unwrap_err() {
+            Error::InvalidArg { name, .. } => assert_eq!(name, "--verbose"),
+            e => panic!("unexpected error: {}", e),
+        }
+        match Config::from_args(args(&["--socket"])).unwrap_err() {
+            Error::InvalidArg { name, .. } => assert_eq!(name, "--socket"),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn serve_connection_answers_requests() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let thread = thread::spawn(move || serve_connection(server, std::ptr::null_mut()));
+
+        let request = ClientMessage::Request(Request::GetCode {
+            code_id: Binary(vec![1; 32]),
+        });
+        write_frame(&client, &request).unwrap();
+        match read_frame(&client).unwrap() {
+            ServerMessage::Response(response) => {
+                assert_eq!(response.data, None);
+                assert!(response.error.unwrap().contains("cache"));
+            }
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+
+        drop(client);
+        thread.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn remove_stale_socket_keeps_other_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("cw.sock");
+        remove_stale_socket(&socket).unwrap();
+
+        let listener = UnixListener::bind(&socket).unwrap();
+        drop(listener);
+        remove_stale_socket(&socket).unwrap();
+        assert!(!socket.exists());
+
+        let file = dir.path().join("node.db");
+        std::fs::write(&file, b"state").unwrap();
+        assert!(remove_stale_socket(&file).is_err());
+        assert!(file.exists());
+    }
+}
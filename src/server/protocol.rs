@@ -0,0 +1,214 @@
+//! The wire format of the execution server.
+//!
+//! Every message is a JSON document preceded by its length as a big-endian `u32`. Byte strings are base64
+//! encoded, like `Binary` everywhere else. The client sends a `Request`, then answers every `Callback` the
+//! server sends with a `Reply`, until the server sends the `Response`.
+
+use std::io::{self, Read, Write};
+
+use cosmwasm_std::Binary;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::block_context::BlockContext;
+use crate::gas_report::GasReport;
+
+/// The largest message we accept, which leaves room for the largest wasm codes
+pub const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+pub fn write_frame<W: Write, T: Serialize>(mut writer: W, msg: &T) -> io::Result<()> {
+    let data = serde_json::to_vec(msg)?;
+    if data.len() > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("message of {} bytes exceeds the frame limit", data.len()),
+        ));
+    }
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(&data)?;
+    writer.flush()
+}
+
+pub fn read_frame<R: Read, T: DeserializeOwned>(mut reader: R) -> io::Result<T> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes exceeds the frame limit", len),
+        ));
+    }
+    let mut data = vec![0u8; len];
+    reader.read_exact(&mut data)?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// The messages a client sends
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientMessage {
+    Request(Request),
+    Reply(Reply),
+}
+
+/// The messages the server sends
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerMessage {
+    Callback(Callback),
+    Response(Response),
+}
+
+/// The operations of the library a client can run, with the arguments of the extern "C" functions
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Request {
+    Create {
+        wasm: Binary,
+    },
+    GetCode {
+        code_id: Binary,
+    },
+    /// Replaces the consensus params of the server's cache, see `set_consensus_params`
+    SetConsensusParams {
+        params: Binary,
+    },
+    Instantiate(ContractCall),
+    Handle(ContractCall),
+    Migrate(ContractCall),
+    Query {
+        code_id: Binary,
+        msg: Binary,
+        gas_limit: u64,
+        call_depth: u32,
+        #[serde(default)]
+        call_id: Option<String>,
+        /// The block the query reads the state of, see `query`
+        #[serde(default)]
+        block: BlockContext,
+        /// Run without a gas limit, see `query`
+        #[serde(default)]
+        unmetered: bool,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ContractCall {
+    pub code_id: Binary,
+    pub params: Binary,
+    pub msg: Binary,
+    pub gas_limit: u64,
+    pub call_depth: u32,
+    /// The codes a migrated contract ran before, see `migrate`. Unused by other calls.
+    #[serde(default)]
+    pub key_history: Option<Binary>,
+    /// The JSON `MigrateAuth` of a migration, see `migrate`. Unused by other calls.
+    #[serde(default)]
+    pub auth: Option<Binary>,
+    /// The correlation id of the call, see `call_id`
+    #[serde(default)]
+    pub call_id: Option<String>,
+}
+
+/// The result of a `Request`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Response {
+    /// The returned data, if the operation succeeded
+    pub data: Option<Binary>,
+    /// The error message, if the operation failed
+    pub error: Option<String>,
+    /// The errno the extern "C" function set, see `ErrnoValue`
+    pub errno: i32,
+    pub gas_used: u64,
+    pub gas_report: GasReport,
+}
+
+/// A callback into the node, made while a contract runs
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Callback {
+    Read {
+        key: Binary,
+    },
+    Write {
+        key: Binary,
+        value: Binary,
+    },
+    Remove {
+        key: Binary,
+    },
+    /// Opens an iterator, see `IteratorMode` for `mode`
+    Scan {
+        start: Option<Binary>,
+        end: Option<Binary>,
+        order: i32,
+        mode: i32,
+    },
+    Next {
+        iterator: u64,
+    },
+    Humanize {
+        canonical: Binary,
+    },
+    Canonicalize {
+        human: Binary,
+    },
+    Query {
+        request: Binary,
+        gas_limit: u64,
+    },
+}
+
+/// The answer to a `Callback`. Unused fields may be left out.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Reply {
+    /// The `GoResult` of the callback
+    pub result: i32,
+    /// The error message of a failed callback
+    pub error: Option<String>,
+    pub gas_used: u64,
+    /// The key of the item returned by `Next`
+    pub key: Option<Binary>,
+    /// The value read, the value of the item returned by `Next`, the converted address or the query response
+    pub value: Option<Binary>,
+    /// The iterator opened by `Scan`
+    pub iterator: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        let msg = ClientMessage::Request(Request::Create {
+            wasm: Binary(vec![0, 97, 115, 109]),
+        });
+        let mut data = Vec::new();
+        write_frame(&mut data, &msg).unwrap();
+        assert_eq!(&data[..4], &(data.len() as u32 - 4).to_be_bytes());
+        let read: ClientMessage = read_frame(&data[..]).unwrap();
+        assert_eq!(read, msg);
+    }
+
+    #[test]
+    fn read_frame_rejects_oversized_frames() {
+        let data = (MAX_FRAME_SIZE as u32 + 1).to_be_bytes();
+        let err = read_frame::<_, ClientMessage>(&data[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reply_fields_default() {
+        let reply: ClientMessage = serde_json::from_slice(br#"{"reply":{"gas_used":5}}"#).unwrap();
+        assert_eq!(
+            reply,
+            ClientMessage::Reply(Reply {
+                gas_used: 5,
+                ..Reply::default()
+            })
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! Callbacks which forward to the client of a connection instead of calling into Go
+
+use std::os::unix::net::UnixStream;
+
+use cosmwasm_std::Binary;
+
+use crate::api::{api_t, GoApi, GoApi_vtable};
+use crate::cache;
+use crate::db::{db_t, DB_vtable, DB};
+use crate::error::GoResult;
+use crate::gas_meter::gas_meter_t;
+use crate::iterator::{iterator_t, GoIter, Iterator_vtable};
+use crate::memory::Buffer;
+use crate::querier::{querier_t, GoQuerier, Querier_vtable};
+
+use super::protocol::{read_frame, write_frame, Callback, ClientMessage, Reply, ServerMessage};
+
+/// A client connection. The `DB`, `GoApi` and `GoQuerier` it builds point to it, so it must
+/// outlive every call they are passed to.
+pub struct Connection {
+    pub stream: UnixStream,
+}
+
+impl Connection {
+    pub fn new(stream: UnixStream) -> Self {
+        Connection { stream }
+    }
+
+    pub fn db(&self) -> DB {
+        DB {
+            gas_meter: std::ptr::null_mut(),
+            state: self as *const Connection as *mut db_t,
+            vtable: DB_vtable {
+                read_db: remote_read,
+                write_db: remote_write,
+                remove_db: remote_remove,
+                scan_db: remote_scan,
+                resolve_contract: None,
+                scan_prefix: None,
+                delete_range: None,
+                set_block_context: None,
+                gas_consumed: None,
+                authorize_call: None,
+                call_result: None,
+            },
+            namespace: Buffer::default(),
+        }
+    }
+
+    pub fn api(&self) -> GoApi {
+        GoApi {
+            state: self as *const Connection as *const api_t,
+            vtable: GoApi_vtable {
+                humanize_address: remote_humanize,
+                canonicalize_address: remote_canonicalize,
+            },
+        }
+    }
+
+    pub fn querier(&self) -> GoQuerier {
+        GoQuerier {
+            state: self as *const Connection as *const querier_t,
+            vtable: Querier_vtable {
+                query_external: remote_query,
+                set_block_context: None,
+            },
+        }
+    }
+
+    /// Sends `callback` to the client and waits for its reply. The call is parked meanwhile, as the client sends
+    /// the queries it nests in the call on other connections.
+    fn call(&self, callback: Callback) -> Result<Reply, String> {
+        let _parked = cache::park();
+        write_frame(&self.stream, &ServerMessage::Callback(callback))
+            .map_err(|e| format!("Sending a callback to the client: {}", e))?;
+        match read_frame(&self.stream) {
+            Ok(ClientMessage::Reply(reply)) => Ok(reply),
+            Ok(ClientMessage::Request(_)) => {
+                Err("The client sent a request instead of a reply to a callback".to_string())
+            }
+            Err(e) => Err(format!("Receiving a reply from the client: {}", e)),
+        }
+    }
+}
+
+unsafe fn connection<'a, T>(ptr: *const T) -> &'a Connection {
+    &*(ptr as *const Connection)
+}
+
+fn binary(buf: Buffer) -> Binary {
+    Binary(unsafe { buf.read() }.unwrap_or_default().to_vec())
+}
+
+/// Forwards `callback` and hands the reply to `write` if the client reports success
+unsafe fn forward<F: FnOnce(Reply)>(
+    conn: &Connection,
+    callback: Callback,
+    used_gas: *mut u64,
+    err: *mut Buffer,
+    write: F,
+) -> i32 {
+    let reply = match conn.call(callback) {
+        Ok(reply) => reply,
+        Err(msg) => {
+            *err = Buffer::from_vec(msg.into_bytes());
+            return GoResult::Other as i32;
+        }
+    };
+    *used_gas = reply.gas_used;
+    if reply.result != GoResult::Ok as i32 {
+        if let Some(msg) = reply.error {
+            *err = Buffer::from_vec(msg.into_bytes());
+        }
+        return reply.result;
+    }
+    write(reply);
+    GoResult::Ok as i32
+}
+
+extern "C" fn remote_read(
+    ptr: *mut db_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    key: Buffer,
+    value: *mut Buffer,
+    err: *mut Buffer,
+) -> i32 {
+    let callback = Callback::Read { key: binary(key) };
+    unsafe {
+        forward(connection(ptr), callback, used_gas, err, |reply| {
+            // a missing value means the key does not exist, as with a null buffer from Go
+            if let Some(found) = reply.value {
+                *value = Buffer::from_vec(found.0);
+            }
+        })
+    }
+}
+
+extern "C" fn remote_write(
+    ptr: *mut db_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    key: Buffer,
+    value: Buffer,
+    err: *mut Buffer,
+) -> i32 {
+    let callback = Callback::Write {
+        key: binary(key),
+        value: binary(value),
+    };
+    unsafe { forward(connection(ptr), callback, used_gas, err, |_| {}) }
+}
+
+extern "C" fn remote_remove(
+    ptr: *mut db_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    key: Buffer,
+    err: *mut Buffer,
+) -> i32 {
+    let callback = Callback::Remove { key: binary(key) };
+    unsafe { forward(connection(ptr), callback, used_gas, err, |_| {}) }
+}
+
+extern "C" fn remote_scan(
+    ptr: *mut db_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    start: Buffer,
+    end: Buffer,
+    order: i32,
+    mode: i32,
+    out: *mut GoIter,
+    err: *mut Buffer,
+) -> i32 {
+    let callback = Callback::Scan {
+        start: unsafe { start.read() }.map(|s| Binary(s.to_vec())),
+        end: unsafe { end.read() }.map(|e| Binary(e.to_vec())),
+        order,
+        mode,
+    };
+    unsafe {
+        forward(connection(ptr), callback, used_gas, err, |reply| {
+            (*out).state = iterator_t {
+                db_counter: ptr as u64,
+                iterator_index: reply.iterator,
+            };
+            (*out).vtable = Iterator_vtable {
+                next_db: Some(remote_next),
+                prefetch_db: None,
+                seek_db: None,
+            };
+        })
+    }
+}
+
+extern "C" fn remote_next(
+    state: iterator_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    key: *mut Buffer,
+    value: *mut Buffer,
+    err: *mut Buffer,
+) -> i32 {
+    let callback = Callback::Next {
+        iterator: state.iterator_index,
+    };
+    unsafe {
+        let conn = connection(state.db_counter as *const db_t);
+        forward(conn, callback, used_gas, err, |reply| {
+            // a missing key ends the iteration
+            if let Some(k) = reply.key {
+                *key = Buffer::from_vec(k.0);
+                *value = Buffer::from_vec(reply.value.unwrap_or_default().0);
+            }
+        })
+    }
+}
+
+extern "C" fn remote_humanize(
+    ptr: *const api_t,
+    canon: Buffer,
+    human: *mut Buffer,
+    err: *mut Buffer,
+    used_gas: *mut u64,
+) -> i32 {
+    let callback = Callback::Humanize {
+        canonical: binary(canon),
+    };
+    unsafe {
+        forward(connection(ptr), callback, used_gas, err, |reply| {
+            *human = Buffer::from_vec(reply.value.unwrap_or_default().0);
+        })
+    }
+}
+
+extern "C" fn remote_canonicalize(
+    ptr: *const api_t,
+    human: Buffer,
+    canon: *mut Buffer,
+    err: *mut Buffer,
+    used_gas: *mut u64,
+) -> i32 {
+    let callback = Callback::Canonicalize {
+        human: binary(human),
+    };
+    unsafe {
+        forward(connection(ptr), callback, used_gas, err, |reply| {
+            *canon = Buffer::from_vec(reply.value.unwrap_or_default().0);
+        })
+    }
+}
+
+extern "C" fn remote_query(
+    ptr: *const querier_t,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    request: Buffer,
+    result: *mut Buffer,
+    err: *mut Buffer,
+) -> i32 {
+    let callback = Callback::Query {
+        request: binary(request),
+        gas_limit,
+    };
+    unsafe {
+        forward(connection(ptr), callback, used_gas, err, |reply| {
+            *result = Buffer::from_vec(reply.value.unwrap_or_default().0);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{Api, Storage};
+    use cosmwasm_std::{HumanAddr, Order};
+    use std::collections::BTreeMap;
+    use std::thread;
+
+    /// Answers callbacks from a map until the connection closes
+    fn mock_client(stream: UnixStream) {
+        let mut data = BTreeMap::<Vec<u8>, Vec<u8>>::new();
+        let mut iterators = Vec::<std::vec::IntoIter<(Vec<u8>, Vec<u8>)>>::new();
+        while let Ok(ServerMessage::Callback(callback)) = read_frame(&stream) {
+            let mut reply = Reply {
+                gas_used: 1,
+                ..Reply::default()
+            };
+            match callback {
+                Callback::Read { key } => reply.value = data.get(&key.0).cloned().map(Binary),
+                Callback::Write { key, value } => {
+                    data.insert(key.0, value.0);
+                }
+                Callback::Remove { key } => {
+                    data.remove(&key.0);
+                }
+                Callback::Scan { .. } => {
+                    let items: Vec<_> = data.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+                    iterators.push(items.into_iter());
+                    reply.iterator = iterators.len() as u64 - 1;
+                }
+                Callback::Next { iterator } => {
+                    if let Some((k, v)) = iterators[iterator as usize].next() {
+                        reply.key = Some(Binary(k));
+                        reply.value = Some(Binary(v));
+                    }
+                }
+                Callback::Canonicalize { human } => {
+                    reply.result = GoResult::User as i32;
+                    reply.error = Some(format!("invalid address {}", human.0.len()));
+                }
+                Callback::Humanize { .. } | Callback::Query { .. } => {
+                    reply.result = GoResult::Other as i32
+                }
+            }
+            write_frame(&stream, &ClientMessage::Reply(reply)).unwrap();
+        }
+    }
+
+    #[test]
+    fn storage_callbacks_are_forwarded() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let thread = thread::spawn(move || mock_client(client));
+        let conn = Connection::new(server);
+
+        let mut db = conn.db();
+        let (result, gas_info) = db.set(b"a", b"1");
+        result.unwrap();
+        assert_eq!(gas_info.externally_used, 1);
+        db.set(b"b", b"2").0.unwrap();
+        assert_eq!(db.get(b"a").0.unwrap(), Some(b"1".to_vec()));
+        db.remove(b"a").0.unwrap();
+        assert_eq!(db.get(b"a").0.unwrap(), None);
+
+        let mut iter = db.range(None, None, Order::Ascending).0.unwrap();
+        assert_eq!(iter.next().0.unwrap(), Some((b"b".to_vec(), b"2".to_vec())));
+        assert_eq!(iter.next().0.unwrap(), None);
+        drop(iter);
+
+        let err = conn
+            .api()
+            .canonical_address(&HumanAddr("foo".to_string()))
+            .0
+            .unwrap_err();
+        assert!(err.to_string().contains("invalid address 3"));
+
+        drop(conn);
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn closed_connections_fail_callbacks() {
+        let (client, server) = UnixStream::pair().unwrap();
+        drop(client);
+        let conn = Connection::new(server);
+        let err = conn.db().get(b"a").0.unwrap_err();
+        assert!(err.to_string().contains("client"));
+    }
+}
@@ -0,0 +1,49 @@
+//! Shadow execution, for canary nodes.
+//!
+//! With the `shadow_execution` cache option, plaintext `handle` and `query` calls run a second time in the
+//! upstream VM once the enclave finished them. The second run does not touch the node: it replays the storage
+//! reads and query responses the enclave's run observed, and its writes only end up in the comparison.
+//! Divergent results, gas or writes are logged and counted, so enclave or VM bugs show up on canaries before
+//! they split consensus.
+//!
+//! Recording and replay need the `shadow` feature, which links the upstream VM next to the enclave.
+
+#[cfg(feature = "shadow")]
+mod record;
+#[cfg(feature = "shadow")]
+mod replay;
+
+use serde::Serialize;
+
+use crate::cache::Cache;
+
+#[cfg(feature = "shadow")]
+pub use record::{
+    is_plaintext, record_query, record_range, record_read, record_write, RecordedRange, Recorder,
+    Recording,
+};
+#[cfg(feature = "shadow")]
+pub use replay::{compare, Call, Shadow};
+
+/// Counters of shadow execution, returned by `get_shadow_stats` as JSON
+#[derive(Serialize, Copy, Clone, Debug, Default, PartialEq)]
+pub struct ShadowStats {
+    /// Whether the cache runs shadow execution at all
+    pub enabled: bool,
+    /// The calls which ran in both VMs
+    pub calls: u64,
+    /// The calls which ended differently in the upstream VM
+    pub divergences: u64,
+    /// The calls which could not run in the upstream VM, e.g. because it rejected the code
+    pub skipped: u64,
+}
+
+#[cfg(feature = "shadow")]
+pub fn stats(cache: &Cache) -> ShadowStats {
+    cache.shadow.as_ref().map(Shadow::stats).unwrap_or_default()
+}
+
+#[cfg(not(feature = "shadow"))]
+pub fn stats(_cache: &Cache) -> ShadowStats {
+    ShadowStats::default()
+}
@@ -0,0 +1,233 @@
+//! Records what the enclave's run of a call observed through the callbacks
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{Order, KV};
+
+use crate::vm::{FfiResult, StorageIterator};
+
+/// Everything a call observed through the storage and querier callbacks
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+    /// The first value read of every key the call had not written before
+    pub reads: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    /// The final value of every key the call wrote, `None` for removed keys
+    pub writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    /// The iterators the call opened, in order
+    pub ranges: Vec<RecordedRange>,
+    /// The raw responses of Go to the queries of the call, by request
+    pub queries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// An iterator opened by the call and the items it yielded
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedRange {
+    pub start: Option<Vec<u8>>,
+    pub end: Option<Vec<u8>>,
+    pub order: Order,
+    pub items: Vec<KV>,
+    /// Whether the call iterated past the last item
+    pub exhausted: bool,
+}
+
+thread_local! {
+    /// One frame per `handle` or `query` running on this thread, innermost last. Calls which are not
+    /// recorded push `None`, so contracts they call never end up in the recording of an outer call.
+    static FRAMES: RefCell<Vec<Option<Recording>>> = RefCell::new(Vec::new());
+}
+
+fn with_recording<F: FnOnce(&mut Recording)>(f: F) {
+    FRAMES.with(|frames| {
+        if let Some(Some(recording)) = frames.borrow_mut().last_mut() {
+            f(recording);
+        }
+    });
+}
+
+/// Records the callbacks of a call from `start` until `finish` or until it is dropped
+pub struct Recorder {
+    _private: (),
+}
+
+impl Recorder {
+    pub fn start(enabled: bool) -> Self {
+        let frame = if enabled {
+            Some(Recording::default())
+        } else {
+            None
+        };
+        FRAMES.with(|frames| frames.borrow_mut().push(frame));
+        Recorder { _private: () }
+    }
+
+    /// Stops recording and returns what the call observed, if it was recorded
+    pub fn finish(self) -> Option<Recording> {
+        FRAMES.with(|frames| frames.borrow_mut().last_mut().and_then(Option::take))
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        FRAMES.with(|frames| frames.borrow_mut().pop());
+    }
+}
+
+/// Whether `msg` is plaintext JSON. Encrypted messages are binary envelopes only the enclave can open.
+pub fn is_plaintext(msg: &[u8]) -> bool {
+    serde_json::from_slice::<serde::de::IgnoredAny>(msg).is_ok()
+}
+
+pub fn record_read(key: &[u8], value: &Option<Vec<u8>>) {
+    with_recording(|recording| {
+        if !recording.writes.contains_key(key) && !recording.reads.contains_key(key) {
+            recording.reads.insert(key.to_vec(), value.clone());
+        }
+    });
+}
+
+/// Records a write of `value`, or a removal if it is `None`
+pub fn record_write(key: &[u8], value: Option<&[u8]>) {
+    with_recording(|recording| {
+        recording
+            .writes
+            .insert(key.to_vec(), value.map(<[u8]>::to_vec));
+    });
+}
+
+pub fn record_query(request: &[u8], response: &[u8]) {
+    with_recording(|recording| {
+        recording
+            .queries
+            .entry(request.to_vec())
+            .or_insert_with(|| response.to_vec());
+    });
+}
+
+/// Records a range opened by the call. The items are recorded as the returned iterator yields them.
+pub fn record_range<'a>(
+    start: Option<&[u8]>,
+    end: Option<&[u8]>,
+    order: Order,
+    iter: Box<dyn StorageIterator + 'a>,
+) -> Box<dyn StorageIterator + 'a> {
+    let mut index = None;
+    with_recording(|recording| {
+        index = Some(recording.ranges.len());
+        recording.ranges.push(RecordedRange {
+            start: start.map(<[u8]>::to_vec),
+            end: end.map(<[u8]>::to_vec),
+            order,
+            items: Vec::new(),
+            exhausted: false,
+        });
+    });
+    match index {
+        Some(index) => Box::new(RecordingIter { inner: iter, index }),
+        None => iter,
+    }
+}
+
+struct RecordingIter<'a> {
+    inner: Box<dyn StorageIterator + 'a>,
+    index: usize,
+}
+
+impl StorageIterator for RecordingIter<'_> {
+    fn next(&mut self) -> FfiResult<Option<KV>> {
+        let (result, gas_info) = self.inner.next();
+        if let Ok(item) = &result {
+            let index = self.index;
+            with_recording(|recording| {
+                if let Some(range) = recording.ranges.get_mut(index) {
+                    match item {
+                        Some(kv) => range.items.push(kv.clone()),
+                        None => range.exhausted = true,
+                    }
+                }
+            });
+        }
+        (result, gas_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vm::GasInfo;
+
+    struct VecIter(std::vec::IntoIter<KV>);
+
+    impl StorageIterator for VecIter {
+        fn next(&mut self) -> FfiResult<Option<KV>> {
+            (Ok(self.0.next()), GasInfo::free())
+        }
+    }
+
+    #[test]
+    fn recorder_records_first_reads_and_final_writes() {
+        let recorder = Recorder::start(true);
+        record_read(b"a", &Some(b"1".to_vec()));
+        record_read(b"a", &Some(b"2".to_vec()));
+        record_write(b"b", Some(b"3"));
+        record_read(b"b", &Some(b"3".to_vec()));
+        record_write(b"b", None);
+        record_query(b"request", b"response");
+        let recording = recorder.finish().unwrap();
+
+        assert_eq!(recording.reads.len(), 1);
+        assert_eq!(recording.reads[&b"a".to_vec()], Some(b"1".to_vec()));
+        assert_eq!(recording.writes[&b"b".to_vec()], None);
+        assert_eq!(
+            recording.queries[&b"request".to_vec()],
+            b"response".to_vec()
+        );
+    }
+
+    #[test]
+    fn recorder_records_consumed_items() {
+        let recorder = Recorder::start(true);
+        let items = vec![
+            (b"a".to_vec(), b"1".to_vec()),
+            (b"b".to_vec(), b"2".to_vec()),
+        ];
+        let mut iter = record_range(
+            Some(b"a"),
+            None,
+            Order::Ascending,
+            Box::new(VecIter(items.clone().into_iter())),
+        );
+        iter.next().0.unwrap();
+        drop(iter);
+        let recording = recorder.finish().unwrap();
+
+        assert_eq!(recording.ranges.len(), 1);
+        assert_eq!(recording.ranges[0].start, Some(b"a".to_vec()));
+        assert_eq!(recording.ranges[0].items, items[..1].to_vec());
+        assert!(!recording.ranges[0].exhausted);
+    }
+
+    #[test]
+    fn nested_calls_are_not_recorded_by_their_caller() {
+        let outer = Recorder::start(true);
+        {
+            let inner = Recorder::start(false);
+            record_read(b"inner", &None);
+            assert_eq!(inner.finish(), None);
+        }
+        record_read(b"outer", &None);
+        let recording = outer.finish().unwrap();
+        assert_eq!(recording.reads.len(), 1);
+        assert!(recording.reads.contains_key(&b"outer".to_vec()));
+
+        // nothing is recorded without a recorder
+        record_read(b"none", &None);
+    }
+
+    #[test]
+    fn is_plaintext_works() {
+        assert!(is_plaintext(br#"{"release":{}}"#));
+        assert!(!is_plaintext(&[0x8a, 0x01, 0xff, 0x00]));
+    }
+}
@@ -0,0 +1,452 @@
+//! Runs recorded calls in the upstream VM and compares them with the enclave's run
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use cosmwasm_std::{Binary, CanonicalAddr, HumanAddr, Order, StdResult, SystemResult, KV};
+use cosmwasm_vm::{
+    call_handle_raw, call_query_raw, Api, Checksum, CosmCache, Extern, FfiError, FfiResult,
+    GasInfo, Querier, Storage, StorageIterator, VmResult,
+};
+use log::*;
+
+use crate::api::GoApi;
+use crate::cache::Cache;
+use crate::db::DB;
+use crate::error::Error;
+use crate::gas_report::{self, GasTracker};
+use crate::gas_schedule::GasPolicy;
+use crate::querier::{parse_response, GoQuerier};
+use crate::vm;
+
+use super::{RecordedRange, Recorder, Recording, ShadowStats};
+
+/// The upstream VM of a cache with shadow execution
+pub struct Shadow {
+    cache: CosmCache<ShadowStorage, ShadowApi, ShadowQuerier>,
+    /// The checksums of the codes stored in `cache`, by the hex checksum of the enclave's cache
+    codes: HashMap<String, Checksum>,
+    stats: ShadowStats,
+}
+
+/// A call covered by shadow execution
+#[derive(Copy, Clone, Debug)]
+pub enum Call<'a> {
+    Handle { params: &'a [u8], msg: &'a [u8] },
+    Query { msg: &'a [u8] },
+}
+
+impl Call<'_> {
+    fn name(&self) -> &'static str {
+        match self {
+            Call::Handle { .. } => "handle",
+            Call::Query { .. } => "query",
+        }
+    }
+}
+
+/// Compares the call recorded by `recorder` with a run in the upstream VM, if it was recorded.
+///
+/// `result` and `gas_used` are the outcome of the enclave's run.
+#[allow(clippy::too_many_arguments)]
+pub fn compare(
+    cache: &mut Cache,
+    code_id: &vm::Checksum,
+    call: Call,
+    api: GoApi,
+    gas_limit: u64,
+    result: &vm::VmResult<Vec<u8>>,
+    gas_used: u64,
+    recorder: Recorder,
+) {
+    let recording = match recorder.finish() {
+        Some(recording) => recording,
+        None => return,
+    };
+    let Cache {
+        inner,
+        consensus_params,
+        shadow,
+        ..
+    } = cache;
+    if let Some(shadow) = shadow {
+        // The upstream VM charges the callbacks nothing, which matches the enclave's run
+        // only if it charged exactly the gas reported by Go
+        let gas_policy = consensus_params.as_ref().map(|params| params.gas_policy);
+        let gas_used = match gas_policy {
+            Some(GasPolicy::TrustGo) => Some(gas_used),
+            _ => None,
+        };
+        let primary = Outcome {
+            result: result.as_ref().ok().cloned(),
+            gas_used,
+        };
+        shadow.compare(inner, code_id, call, api, gas_limit, primary, recording);
+    }
+}
+
+/// How a call ended. Errors are not compared, since their messages differ between the VMs.
+struct Outcome {
+    result: Option<Vec<u8>>,
+    gas_used: Option<u64>,
+}
+
+impl Shadow {
+    /// Opens the upstream VM's cache in the `shadow` subdirectory of `data_dir`
+    pub fn new(data_dir: &Path, features: HashSet<String>) -> Result<Self, Error> {
+        let cache =
+            unsafe { CosmCache::new(data_dir.join("shadow"), features) }.map_err(Error::vm_err)?;
+        Ok(Shadow {
+            cache,
+            codes: HashMap::new(),
+            stats: ShadowStats {
+                enabled: true,
+                ..ShadowStats::default()
+            },
+        })
+    }
+
+    /// Reopens the upstream VM's cache with `features`, see `Cache::update_supported_features`
+    pub fn update_supported_features(
+        &mut self,
+        data_dir: &Path,
+        features: HashSet<String>,
+    ) -> Result<(), Error> {
+        self.cache =
+            unsafe { CosmCache::new(data_dir.join("shadow"), features) }.map_err(Error::vm_err)?;
+        Ok(())
+    }
+
+    pub fn stats(&self) -> ShadowStats {
+        self.stats
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn compare(
+        &mut self,
+        primary: &vm::CosmCache<DB, GoApi, GoQuerier>,
+        code_id: &vm::Checksum,
+        call: Call,
+        api: GoApi,
+        gas_limit: u64,
+        expected: Outcome,
+        recording: Recording,
+    ) {
+        let Recording {
+            reads,
+            writes,
+            ranges,
+            queries,
+        } = recording;
+        let deps = Extern {
+            storage: ShadowStorage::new(reads, ranges),
+            api: ShadowApi(api),
+            querier: ShadowQuerier::new(queries),
+        };
+        let (result, gas_used, storage) = match self.run(primary, code_id, call, deps, gas_limit) {
+            Ok(run) => run,
+            Err(e) => {
+                self.stats.skipped += 1;
+                warn!(
+                    "Skipped shadow execution of {} on code {}: {}",
+                    call.name(),
+                    code_id.to_hex(),
+                    e
+                );
+                return;
+            }
+        };
+        self.stats.calls += 1;
+
+        let mut divergences = Vec::new();
+        match (&expected.result, &result) {
+            (Some(expected_result), Ok(result)) => {
+                if expected_result != result {
+                    divergences.push("the results differ".to_string());
+                }
+                if let Some(expected_gas) = expected.gas_used {
+                    if expected_gas != gas_used {
+                        divergences.push(format!(
+                            "the enclave used {} gas, the upstream VM {}",
+                            expected_gas, gas_used
+                        ));
+                    }
+                }
+                if storage.writes != writes {
+                    divergences.push("the storage writes differ".to_string());
+                }
+                divergences.extend(storage.misses.into_inner());
+            }
+            (Some(_), Err(e)) => divergences.push(format!("only the upstream VM failed: {}", e)),
+            (None, Ok(_)) => divergences.push("only the enclave failed".to_string()),
+            (None, Err(_)) => {}
+        }
+        if !divergences.is_empty() {
+            self.stats.divergences += 1;
+            error!(
+                "Shadow execution of {} on code {} diverged: {}",
+                call.name(),
+                code_id.to_hex(),
+                divergences.join("; ")
+            );
+        }
+    }
+
+    /// Runs `call` and returns its result, the gas it used and the storage it left behind
+    fn run(
+        &mut self,
+        primary: &vm::CosmCache<DB, GoApi, GoQuerier>,
+        code_id: &vm::Checksum,
+        call: Call,
+        deps: Extern<ShadowStorage, ShadowApi, ShadowQuerier>,
+        gas_limit: u64,
+    ) -> Result<(VmResult<Vec<u8>>, u64, ShadowStorage), Error> {
+        let checksum = self.checksum(primary, code_id)?;
+        // keep the address conversions of this run out of the enclave run's gas report
+        let _tracker = GasTracker::start();
+        gas_report::set_gas_policy(GasPolicy::TrustGo, Default::default());
+        let mut instance = self
+            .cache
+            .get_instance(&checksum, deps, gas_limit)
+            .map_err(Error::vm_err)?;
+        let result = match call {
+            Call::Handle { params, msg } => call_handle_raw(&mut instance, params, msg),
+            Call::Query { msg } => call_query_raw(&mut instance, msg),
+        };
+        let gas_used = instance.create_gas_report().used_internally;
+        let deps = instance
+            .recycle()
+            .ok_or_else(|| Error::vm_err("the upstream VM did not return the storage"))?;
+        Ok((result, gas_used, deps.storage))
+    }
+
+    /// Stores the code in the upstream VM the first time it is called
+    fn checksum(
+        &mut self,
+        primary: &vm::CosmCache<DB, GoApi, GoQuerier>,
+        code_id: &vm::Checksum,
+    ) -> Result<Checksum, Error> {
+        let key = code_id.to_hex();
+        if let Some(checksum) = self.codes.get(&key) {
+            return Ok(*checksum);
+        }
+        let wasm = primary.load_wasm(code_id)?;
+        let checksum = self.cache.save_wasm(&wasm).map_err(Error::vm_err)?;
+        self.codes.insert(key, checksum);
+        Ok(checksum)
+    }
+}
+
+/// Serves the storage reads of the enclave's run and keeps all writes to itself
+struct ShadowStorage {
+    reads: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    ranges: Vec<RecordedRange>,
+    next_range: Cell<usize>,
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    /// Accesses which the enclave's run did not make, so they cannot be replayed
+    misses: RefCell<Vec<String>>,
+}
+
+impl ShadowStorage {
+    fn new(reads: BTreeMap<Vec<u8>, Option<Vec<u8>>>, ranges: Vec<RecordedRange>) -> Self {
+        ShadowStorage {
+            reads,
+            ranges,
+            next_range: Cell::new(0),
+            writes: BTreeMap::new(),
+            misses: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn miss(&self, msg: String) {
+        self.misses.borrow_mut().push(msg);
+    }
+}
+
+impl Storage for ShadowStorage {
+    fn get(&self, key: &[u8]) -> FfiResult<Option<Vec<u8>>> {
+        let value = match self.writes.get(key).or_else(|| self.reads.get(key)) {
+            Some(value) => value.clone(),
+            None => {
+                self.miss(format!(
+                    "the enclave did not read the key {}",
+                    String::from_utf8_lossy(key)
+                ));
+                None
+            }
+        };
+        (Ok(value), GasInfo::free())
+    }
+
+    fn range<'a>(
+        &'a self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> FfiResult<Box<dyn StorageIterator + 'a>> {
+        let index = self.next_range.get();
+        self.next_range.set(index + 1);
+        let items = match self.ranges.get(index) {
+            Some(range)
+                if range.start.as_deref() == start
+                    && range.end.as_deref() == end
+                    && range.order == order =>
+            {
+                ReplayIter {
+                    items: &range.items,
+                    position: 0,
+                    exhausted: range.exhausted,
+                    storage: self,
+                }
+            }
+            _ => {
+                self.miss(format!(
+                    "the enclave did not open the same range #{}",
+                    index
+                ));
+                ReplayIter {
+                    items: &[],
+                    position: 0,
+                    exhausted: true,
+                    storage: self,
+                }
+            }
+        };
+        (Ok(Box::new(items)), GasInfo::free())
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) -> FfiResult<()> {
+        self.writes.insert(key.to_vec(), Some(value.to_vec()));
+        (Ok(()), GasInfo::free())
+    }
+
+    fn remove(&mut self, key: &[u8]) -> FfiResult<()> {
+        self.writes.insert(key.to_vec(), None);
+        (Ok(()), GasInfo::free())
+    }
+}
+
+/// Yields the items the enclave's run consumed from a range
+struct ReplayIter<'a> {
+    items: &'a [KV],
+    position: usize,
+    exhausted: bool,
+    storage: &'a ShadowStorage,
+}
+
+impl StorageIterator for ReplayIter<'_> {
+    fn next(&mut self) -> FfiResult<Option<KV>> {
+        let item = self.items.get(self.position).cloned();
+        if item.is_some() {
+            self.position += 1;
+        } else if !self.exhausted {
+            self.exhausted = true;
+            self.storage
+                .miss("the enclave consumed fewer items of a range".to_string());
+        }
+        (Ok(item), GasInfo::free())
+    }
+}
+
+/// The address conversions of Go, which have no side effects and can run again
+#[derive(Copy, Clone)]
+struct ShadowApi(GoApi);
+
+impl Api for ShadowApi {
+    fn canonical_address(&self, human: &HumanAddr) -> FfiResult<CanonicalAddr> {
+        convert(vm::Api::canonical_address(&self.0, human))
+    }
+
+    fn human_address(&self, canonical: &CanonicalAddr) -> FfiResult<HumanAddr> {
+        convert(vm::Api::human_address(&self.0, canonical))
+    }
+}
+
+/// Converts a result of the enclave's VM interface to the upstream one
+fn convert<T>((result, gas_info): vm::FfiResult<T>) -> FfiResult<T> {
+    let gas_info = GasInfo {
+        cost: gas_info.cost,
+        externally_used: gas_info.externally_used,
+    };
+    (result.map_err(FfiError::unknown), gas_info)
+}
+
+/// Answers the queries of the enclave's run with the responses Go gave then
+struct ShadowQuerier {
+    responses: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl ShadowQuerier {
+    fn new(responses: BTreeMap<Vec<u8>, Vec<u8>>) -> Self {
+        ShadowQuerier { responses }
+    }
+}
+
+impl Querier for ShadowQuerier {
+    fn query_raw(
+        &self,
+        request: &[u8],
+        _gas_limit: u64,
+    ) -> FfiResult<SystemResult<StdResult<Binary>>> {
+        let result = match self.responses.get(request) {
+            Some(response) => Ok(parse_response(response.clone())),
+            None => Err(FfiError::unknown(format!(
+                "the enclave did not make the query {}",
+                String::from_utf8_lossy(request)
+            ))),
+        };
+        (result, GasInfo::free())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage() -> ShadowStorage {
+        let mut reads = BTreeMap::new();
+        reads.insert(b"a".to_vec(), Some(b"1".to_vec()));
+        reads.insert(b"b".to_vec(), None);
+        let ranges = vec![RecordedRange {
+            start: None,
+            end: None,
+            order: Order::Ascending,
+            items: vec![(b"a".to_vec(), b"1".to_vec())],
+            exhausted: false,
+        }];
+        ShadowStorage::new(reads, ranges)
+    }
+
+    #[test]
+    fn storage_replays_reads_and_keeps_writes() {
+        let mut storage = storage();
+        assert_eq!(storage.get(b"a").0.unwrap(), Some(b"1".to_vec()));
+        assert_eq!(storage.get(b"b").0.unwrap(), None);
+        storage.set(b"b", b"2").0.unwrap();
+        storage.remove(b"a").0.unwrap();
+        assert_eq!(storage.get(b"a").0.unwrap(), None);
+        assert_eq!(storage.get(b"b").0.unwrap(), Some(b"2".to_vec()));
+        assert!(storage.misses.borrow().is_empty());
+
+        assert_eq!(storage.get(b"c").0.unwrap(), None);
+        assert_eq!(storage.misses.borrow().len(), 1);
+    }
+
+    #[test]
+    fn storage_replays_ranges() {
+        let storage = storage();
+        let mut iter = storage.range(None, None, Order::Ascending).0.unwrap();
+        assert_eq!(iter.next().0.unwrap(), Some((b"a".to_vec(), b"1".to_vec())));
+        // the enclave did not look further
+        assert_eq!(iter.next().0.unwrap(), None);
+        drop(iter);
+        assert_eq!(storage.misses.borrow().len(), 1);
+
+        // a second range was never opened
+        let mut iter = storage.range(None, None, Order::Ascending).0.unwrap();
+        assert_eq!(iter.next().0.unwrap(), None);
+        drop(iter);
+        assert_eq!(storage.misses.borrow().len(), 2);
+    }
+}
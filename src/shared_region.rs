@@ -0,0 +1,162 @@
+//! An optional transport for large results.
+//!
+//! Go may register a region of memory it allocated outside of its heap. Large results are then
+//! written into that region instead of a fresh `Vec`, so Go can use them in place instead of copying
+//! them out and calling `free_rust`. Results in the region are marked by a capacity of 0, which no
+//! `Buffer::from_vec` produces for non-empty data, and must be released with `release_shared`.
+//!
+//! Space is handed out by a bump allocator, which starts over once all results are released.
+//! Results which do not fit into the free space go onto the heap as before.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use crate::memory::Buffer;
+
+/// Results smaller than this are cheaper to copy than to track in the region
+pub const MIN_SHARED_RESULT_LEN: usize = 64 * 1024;
+
+#[derive(Default)]
+struct RegionState {
+    offset: usize,
+    /// The offsets of the results not released yet. Releasing a result twice or one of another region must
+    /// not free the space of the others.
+    live: HashSet<usize>,
+}
+
+pub struct SharedRegion {
+    ptr: *mut u8,
+    len: usize,
+    state: Mutex<RegionState>,
+}
+
+impl SharedRegion {
+    /// # Safety
+    ///
+    /// `ptr` must point to `len` bytes of writable memory, which stay valid until the region is unregistered.
+    pub unsafe fn new(ptr: *mut u8, len: usize) -> Self {
+        SharedRegion {
+            ptr,
+            len,
+            state: Mutex::new(RegionState::default()),
+        }
+    }
+
+    /// Moves `data` into the region if there is space, and onto the heap otherwise
+    pub fn write(&self, data: Vec<u8>) -> Buffer {
+        if data.len() < MIN_SHARED_RESULT_LEN {
+            return Buffer::from_vec(data);
+        }
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return Buffer::from_vec(data),
+        };
+        if self.len - state.offset < data.len() {
+            return Buffer::from_vec(data);
+        }
+        let ptr = unsafe { self.ptr.add(state.offset) };
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len()) };
+        let offset = state.offset;
+        state.live.insert(offset);
+        state.offset += data.len();
+        Buffer {
+            ptr,
+            len: data.len(),
+            cap: 0,
+        }
+    }
+
+    pub fn contains(&self, buf: &Buffer) -> bool {
+        buf.ptr >= self.ptr && buf.ptr < self.ptr.wrapping_add(self.len)
+    }
+
+    /// Returns the space of a result written by `write`. Releasing it again does nothing.
+    pub fn release(&self, buf: &Buffer) {
+        if !is_shared(buf) || !self.contains(buf) {
+            return;
+        }
+        if let Ok(mut state) = self.state.lock() {
+            let offset = buf.ptr as usize - self.ptr as usize;
+            if state.live.remove(&offset) && state.live.is_empty() {
+                state.offset = 0;
+            }
+        }
+    }
+}
+
+/// Whether `buf` was written into a shared region rather than allocated by `Buffer::from_vec`
+pub fn is_shared(buf: &Buffer) -> bool {
+    buf.cap == 0 && buf.len > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(memory: &mut [u8]) -> SharedRegion {
+        unsafe { SharedRegion::new(memory.as_mut_ptr(), memory.len()) }
+    }
+
+    #[test]
+    fn write_uses_region_for_large_results() {
+        let mut memory = vec![0u8; 3 * MIN_SHARED_RESULT_LEN];
+        let region = region(&mut memory);
+        let data = vec![7u8; MIN_SHARED_RESULT_LEN];
+        let buf = region.write(data.clone());
+        assert!(is_shared(&buf));
+        assert!(region.contains(&buf));
+        assert_eq!(unsafe { buf.read() }, Some(data.as_slice()));
+    }
+
+    #[test]
+    fn write_uses_heap_for_small_results() {
+        let mut memory = vec![0u8; MIN_SHARED_RESULT_LEN];
+        let region = region(&mut memory);
+        let buf = region.write(b"foo".to_vec());
+        assert!(!is_shared(&buf));
+        assert!(!region.contains(&buf));
+        unsafe { buf.consume() };
+    }
+
+    #[test]
+    fn write_falls_back_to_heap_when_full() {
+        let mut memory = vec![0u8; MIN_SHARED_RESULT_LEN];
+        let region = region(&mut memory);
+        let first = region.write(vec![1u8; MIN_SHARED_RESULT_LEN]);
+        assert!(is_shared(&first));
+        let second = region.write(vec![2u8; MIN_SHARED_RESULT_LEN]);
+        assert!(!is_shared(&second));
+        unsafe { second.consume() };
+
+        // space is reused once all results are released
+        region.release(&first);
+        let third = region.write(vec![3u8; MIN_SHARED_RESULT_LEN]);
+        assert!(is_shared(&third));
+        assert_eq!(third.ptr, first.ptr);
+    }
+
+    #[test]
+    fn release_counts_each_result_once() {
+        let mut memory = vec![0u8; 3 * MIN_SHARED_RESULT_LEN];
+        let region = region(&mut memory);
+        let first = region.write(vec![1u8; MIN_SHARED_RESULT_LEN]);
+        let second = region.write(vec![2u8; MIN_SHARED_RESULT_LEN]);
+        region.release(&first);
+        region.release(&first);
+
+        // the second result is still live, so its space is not handed out again
+        let third = region.write(vec![3u8; MIN_SHARED_RESULT_LEN]);
+        assert!(is_shared(&third));
+        assert_ne!(third.ptr, first.ptr);
+        assert_ne!(third.ptr, second.ptr);
+        assert_eq!(
+            unsafe { second.read() },
+            Some(&[2u8; MIN_SHARED_RESULT_LEN][..])
+        );
+
+        region.release(&second);
+        region.release(&third);
+        let fourth = region.write(vec![4u8; MIN_SHARED_RESULT_LEN]);
+        assert_eq!(fourth.ptr, first.ptr);
+    }
+}
@@ -0,0 +1,275 @@
+//! State-sync support: everything a fresh node needs on top of the application state.
+//!
+//! The snapshot payload contains the enclave's exportable state, the checksums of all codes in the cache and
+//! which of them are plaintext codes, see `plaintext`, or paused, see `set_code_paused`. Wasm blobs are not part
+//! of the app state, so the restoring node must `create` every listed code (e.g. from the code chunks of the same
+//! snapshot) before it can restore the payload, which then marks the plaintext and paused codes.
+//!
+//! Compiled modules are not part of the snapshot: the restoring node compiles every code on its first use, see
+//! `module_format`.
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::crash_report;
+use crate::error::Error;
+use crate::plaintext::PlaintextCodes;
+use crate::vm::{untrusted_export_enclave_state, untrusted_import_enclave_state, Checksum};
+
+/// Bumped whenever the format of `SnapshotPayload` changes
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+// the directory inside the cache's data dir in which the VM stores wasm blobs, named by hex checksum
+pub const WASM_DIR: &str = "wasm";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SnapshotPayload {
+    pub version: u32,
+    /// base64 encoded, opaque state exported by the enclave
+    pub enclave_state: String,
+    /// hex encoded checksums of the codes stored in the cache, sorted
+    pub codes: Vec<String>,
+    /// hex encoded checksums of the plaintext codes among `codes`, sorted
+    pub plaintext_codes: Vec<String>,
+    /// hex encoded checksums of the paused codes among `codes`, sorted
+    pub paused_codes: Vec<String>,
+}
+
+/// Whether `name` is a checksum as the VM names the wasm files, i.e. 32 bytes in lowercase hex
+fn is_checksum(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Lists the hex checksums of all codes stored in the cache at `data_dir`, sorted. Other files, e.g. temporary
+/// files left by an interrupted write, are skipped.
+pub fn stored_codes(data_dir: &Path) -> Result<Vec<String>, Error> {
+    let wasm_dir = data_dir.join(WASM_DIR);
+    if !wasm_dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut codes = vec![];
+    for entry in fs::read_dir(wasm_dir)? {
+        let name = entry?.file_name();
+        match name.to_str() {
+            Some(name) if is_checksum(name) => codes.push(name.to_string()),
+            _ => {}
+        }
+    }
+    codes.sort();
+    Ok(codes)
+}
+
+pub fn export_snapshot(
+    data_dir: &Path,
+    plaintext: &PlaintextCodes,
+    paused: &HashSet<Checksum>,
+) -> Result<Vec<u8>, Error> {
+    let enclave_state = match untrusted_export_enclave_state() {
+        // An error happened in the SGX sdk.
+        Err(e) => return Err(crash_report::sdk_failure("export_snapshot", e)),
+        // An error was returned from the enclave.
+        Ok(Err(e)) => return Err(Error::enclave_err(e.to_string())),
+        Ok(Ok(state)) => state,
+    };
+    let mut paused_codes: Vec<String> = paused.iter().map(Checksum::to_hex).collect();
+    paused_codes.sort();
+    let payload = SnapshotPayload {
+        version: SNAPSHOT_VERSION,
+        enclave_state: base64::encode(&enclave_state),
+        codes: stored_codes(data_dir)?,
+        plaintext_codes: plaintext.hex_codes(),
+        paused_codes,
+    };
+    serde_json::to_vec(&payload).map_err(Error::vm_err)
+}
+
+/// Restores `payload` and returns the codes it pauses, which the caller must pause
+pub fn restore_snapshot(
+    data_dir: &Path,
+    plaintext: &mut PlaintextCodes,
+    payload: &[u8],
+) -> Result<Vec<Checksum>, Error> {
+    let payload: SnapshotPayload =
+        serde_json::from_slice(payload).map_err(|e| Error::invalid_arg("payload", e))?;
+    if payload.version != SNAPSHOT_VERSION {
+        let msg = format!("unsupported snapshot version {}", payload.version);
+        return Err(Error::invalid_arg("payload", msg));
+    }
+
+    let stored = stored_codes(data_dir)?;
+    let missing: Vec<&str> = payload
+        .codes
+        .iter()
+        .filter(|code| stored.binary_search(code).is_err())
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        let msg = format!("codes missing from the cache: {}", missing.join(", "));
+        return Err(Error::invalid_arg("payload", msg));
+    }
+    if let Some(code) = payload
+        .plaintext_codes
+        .iter()
+        .find(|code| payload.codes.binary_search(code).is_err())
+    {
+        let msg = format!("plaintext code {} is not a code of the snapshot", code);
+        return Err(Error::invalid_arg("payload", msg));
+    }
+    let mut paused = vec![];
+    for code in &payload.paused_codes {
+        if payload.codes.binary_search(code).is_err() {
+            let msg = format!("paused code {} is not a code of the snapshot", code);
+            return Err(Error::invalid_arg("payload", msg));
+        }
+        let checksum = hex::decode(code).map_err(|e| Error::invalid_arg("payload", e))?;
+        paused.push(Checksum::try_from(checksum.as_slice())?);
+    }
+    plaintext.check_snapshot(&payload.codes, &payload.plaintext_codes)?;
+
+    let enclave_state =
+        base64::decode(&payload.enclave_state).map_err(|e| Error::invalid_arg("payload", e))?;
+    match untrusted_import_enclave_state(&enclave_state) {
+        // An error happened in the SGX sdk.
+        Err(e) => Err(crash_report::sdk_failure("restore_snapshot", e)),
+        // An error was returned from the enclave.
+        Ok(Err(e)) => Err(Error::enclave_err(e.to_string())),
+        Ok(Ok(())) => {
+            plaintext.extend(&payload.plaintext_codes)?;
+            Ok(paused)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn stored_codes_lists_sorted_checksums() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(stored_codes(dir.path()).unwrap(), Vec::<String>::new());
+
+        fs::create_dir(dir.path().join(WASM_DIR)).unwrap();
+        let (aa, bb) = ("aa".repeat(32), "bb".repeat(32));
+        for name in &[
+            &bb,
+            &aa,
+            &"aa".to_string(),
+            &"AA".repeat(32),
+            &format!("{}.tmp", aa),
+        ] {
+            fs::write(dir.path().join(WASM_DIR).join(name), b"").unwrap();
+        }
+        assert_eq!(stored_codes(dir.path()).unwrap(), vec![aa, bb]);
+    }
+
+    #[test]
+    fn restore_snapshot_requires_codes() {
+        let dir = TempDir::new().unwrap();
+        let payload = SnapshotPayload {
+            version: SNAPSHOT_VERSION,
+            enclave_state: String::new(),
+            codes: vec!["aa".to_string()],
+            plaintext_codes: vec![],
+            paused_codes: vec![],
+        };
+        let mut plaintext = PlaintextCodes::load(dir.path()).unwrap();
+        let error = restore_snapshot(
+            dir.path(),
+            &mut plaintext,
+            &serde_json::to_vec(&payload).unwrap(),
+        )
+        .unwrap_err();
+        match error {
+            Error::InvalidArg { msg, .. } => assert_eq!(msg, "codes missing from the cache: aa"),
+            _ => panic!("expect different error"),
+        }
+    }
+
+    #[test]
+    fn restore_snapshot_checks_version() {
+        let dir = TempDir::new().unwrap();
+        let mut plaintext = PlaintextCodes::load(dir.path()).unwrap();
+        let error = restore_snapshot(
+            dir.path(),
+            &mut plaintext,
+            br#"{"version":2,"enclave_state":"","codes":[],"plaintext_codes":[],"paused_codes":[]}"#,
+        )
+        .unwrap_err();
+        match error {
+            Error::InvalidArg { msg, .. } => assert_eq!(msg, "unsupported snapshot version 2"),
+            _ => panic!("expect different error"),
+        }
+    }
+
+    #[test]
+    fn restore_snapshot_checks_plaintext_codes() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(WASM_DIR)).unwrap();
+        let (one, two) = ("01".repeat(32), "02".repeat(32));
+        fs::write(dir.path().join(WASM_DIR).join(&one), b"").unwrap();
+        fs::write(dir.path().join(WASM_DIR).join(&two), b"").unwrap();
+        let mut plaintext = PlaintextCodes::load(dir.path()).unwrap();
+        plaintext.extend(&[two.clone()]).unwrap();
+
+        let restore = |plaintext: &mut PlaintextCodes, plaintext_codes: &[&String]| {
+            let payload = SnapshotPayload {
+                version: SNAPSHOT_VERSION,
+                enclave_state: String::new(),
+                codes: vec![one.clone(), two.clone()],
+                plaintext_codes: plaintext_codes
+                    .iter()
+                    .map(|code| code.to_string())
+                    .collect(),
+                paused_codes: vec![],
+            };
+            restore_snapshot(
+                dir.path(),
+                plaintext,
+                &serde_json::to_vec(&payload).unwrap(),
+            )
+        };
+        let three = "03".repeat(32);
+        match restore(&mut plaintext, &[&two, &three]).unwrap_err() {
+            Error::InvalidArg { msg, .. } => assert!(msg.contains("not a code of the snapshot")),
+            e => panic!("unexpected error: {}", e),
+        }
+        // the snapshot disagrees on the mode of a code of this node
+        match restore(&mut plaintext, &[&one]).unwrap_err() {
+            Error::InvalidArg { msg, .. } => assert!(msg.contains("plaintext here")),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn restore_snapshot_checks_paused_codes() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(WASM_DIR)).unwrap();
+        let one = "01".repeat(32);
+        fs::write(dir.path().join(WASM_DIR).join(&one), b"").unwrap();
+        let mut plaintext = PlaintextCodes::load(dir.path()).unwrap();
+        let payload = SnapshotPayload {
+            version: SNAPSHOT_VERSION,
+            enclave_state: String::new(),
+            codes: vec![one],
+            plaintext_codes: vec![],
+            paused_codes: vec!["02".repeat(32)],
+        };
+        let error = restore_snapshot(
+            dir.path(),
+            &mut plaintext,
+            &serde_json::to_vec(&payload).unwrap(),
+        )
+        .unwrap_err();
+        match error {
+            Error::InvalidArg { msg, .. } => assert!(msg.starts_with("paused code 0202")),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
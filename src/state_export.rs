@@ -0,0 +1,190 @@
+//! Paginated export of a contract's full storage, e.g. for `export-genesis`.
+//!
+//! Entries are returned in ascending key order. Every page ends with a token which, passed as
+//! `start_after` of the next call, continues right after the last returned entry.
+
+use serde::Serialize;
+
+use cosmwasm_std::Order;
+
+use crate::crash_report;
+use crate::db::DB;
+use crate::error::Error;
+use crate::vm::{untrusted_reencrypt_state_entry, Storage, StorageIterator, VmError};
+
+/// How the exported entries are encoded
+///
+/// cbindgen:prefix-with-name
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExportMode {
+    /// Entries are exported as stored, i.e. encrypted with the contract's current state key
+    Ciphertext = 0,
+    /// The enclave re-encrypts every entry before it leaves the library
+    Reencrypt = 1,
+    /// Only the keys are exported, with empty values, e.g. to count or list the entries
+    Keys = 2,
+}
+
+impl ExportMode {
+    pub fn from_i32(mode: i32) -> Result<Self, Error> {
+        match mode {
+            0 => Ok(ExportMode::Ciphertext),
+            1 => Ok(ExportMode::Reencrypt),
+            2 => Ok(ExportMode::Keys),
+            _ => Err(Error::invalid_arg(
+                "mode",
+                format!("unknown export mode {}", mode),
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct StateEntry {
+    /// base64 encoded
+    pub key: String,
+    /// base64 encoded
+    pub value: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct StatePage {
+    pub entries: Vec<StateEntry>,
+    /// base64 encoded token for the next page, or null if this was the last page
+    pub next: Option<String>,
+}
+
+/// The smallest key greater than `key`
+pub fn key_after(key: &[u8]) -> Vec<u8> {
+    let mut next = key.to_vec();
+    next.push(0);
+    next
+}
+
+/// Reads up to `limit` entries from `iter` into a page, passing each one through `encode`
+pub fn collect_page<F>(
+    iter: &mut dyn StorageIterator,
+    limit: usize,
+    mut encode: F,
+) -> Result<StatePage, Error>
+where
+    F: FnMut(Vec<u8>, Vec<u8>) -> Result<(Vec<u8>, Vec<u8>), Error>,
+{
+    let mut entries = Vec::with_capacity(limit);
+    let mut last_key = None;
+    while entries.len() < limit {
+        let (key, value) = match iter.next().0.map_err(VmError::from)? {
+            Some(kv) => kv,
+            None => {
+                return Ok(StatePage {
+                    entries,
+                    next: None,
+                })
+            }
+        };
+        last_key = Some(base64::encode(&key));
+        let (key, value) = encode(key, value)?;
+        entries.push(StateEntry {
+            key: base64::encode(&key),
+            value: base64::encode(&value),
+        });
+    }
+    // There may be more entries, but we only find out on the next call.
+    // The token is the raw storage key, even if the entry was re-encrypted.
+    Ok(StatePage {
+        entries,
+        next: last_key,
+    })
+}
+
+/// Exports a page of the storage of the contract with `contract_key`.
+///
+/// `start_after` is the `next` token of the previous page, or `None` for the first page.
+pub fn export_state(
+    db: &DB,
+    contract_key: &[u8],
+    start_after: Option<&[u8]>,
+    limit: u32,
+    mode: ExportMode,
+) -> Result<Vec<u8>, Error> {
+    if limit == 0 {
+        return Err(Error::invalid_arg("limit", "must be greater than 0"));
+    }
+    let start = start_after.map(key_after);
+
+    let iter = match mode {
+        ExportMode::Keys => db.range_keys(start.as_deref(), None, Order::Ascending),
+        _ => db.range(start.as_deref(), None, Order::Ascending),
+    };
+    let mut iter = iter.0.map_err(VmError::from)?;
+    let page = collect_page(iter.as_mut(), limit as usize, |key, value| match mode {
+        ExportMode::Ciphertext | ExportMode::Keys => Ok((key, value)),
+        ExportMode::Reencrypt => {
+            match untrusted_reencrypt_state_entry(contract_key, &key, &value) {
+                // An error happened in the SGX sdk.
+                Err(e) => Err(crash_report::sdk_failure("export_contract_state", e)),
+                // An error was returned from the enclave.
+                Ok(Err(e)) => Err(Error::enclave_err(e.to_string())),
+                Ok(Ok(entry)) => Ok(entry),
+            }
+        }
+    })?;
+    serde_json::to_vec(&page).map_err(Error::vm_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{FfiResult, GasInfo};
+    use cosmwasm_std::KV;
+
+    struct MockIterator(std::vec::IntoIter<KV>);
+
+    impl StorageIterator for MockIterator {
+        fn next(&mut self) -> FfiResult<Option<KV>> {
+            (Ok(self.0.next()), GasInfo::free())
+        }
+    }
+
+    fn mock_iter(keys: &[&[u8]]) -> MockIterator {
+        let entries: Vec<KV> = keys.iter().map(|k| (k.to_vec(), b"v".to_vec())).collect();
+        MockIterator(entries.into_iter())
+    }
+
+    #[test]
+    fn collect_page_paginates() {
+        let mut iter = mock_iter(&[b"a", b"b", b"c"]);
+        let page = collect_page(&mut iter, 2, |k, v| Ok((k, v))).unwrap();
+        assert_eq!(page.entries.len(), 2);
+        assert_eq!(page.entries[1].key, base64::encode(b"b"));
+        assert_eq!(page.next, Some(base64::encode(b"b")));
+
+        let page = collect_page(&mut iter, 2, |k, v| Ok((k, v))).unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn collect_page_token_is_raw_key() {
+        let mut iter = mock_iter(&[b"a"]);
+        let page = collect_page(&mut iter, 1, |_, v| Ok((b"encrypted".to_vec(), v))).unwrap();
+        assert_eq!(page.entries[0].key, base64::encode(b"encrypted"));
+        assert_eq!(page.next, Some(base64::encode(b"a")));
+    }
+
+    #[test]
+    fn key_after_works() {
+        assert_eq!(key_after(b"a"), b"a\0".to_vec());
+        assert!(key_after(b"a").as_slice() > &b"a"[..]);
+        assert!(key_after(b"a").as_slice() < &b"aa"[..]);
+    }
+
+    #[test]
+    fn export_mode_from_i32_works() {
+        assert_eq!(ExportMode::from_i32(0).unwrap(), ExportMode::Ciphertext);
+        assert_eq!(ExportMode::from_i32(1).unwrap(), ExportMode::Reencrypt);
+        assert_eq!(ExportMode::from_i32(2).unwrap(), ExportMode::Keys);
+        assert!(ExportMode::from_i32(3).is_err());
+    }
+}
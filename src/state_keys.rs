@@ -0,0 +1,264 @@
+//! The keys of a contract's encrypted state across migrations.
+//!
+//! The enclave encrypts the state of a contract with a key derived from its contract key, which was issued for
+//! the code the contract was instantiated with. After `migrate` switches the contract to another code, the
+//! entries written before remain encrypted with the keys of the earlier codes. Every code a contract ran is a
+//! key version, numbered from 0 for the code it was instantiated with.
+//!
+//! Go passes the codes the contract ran before, oldest first, as the `key_history` of `migrate`. The contract
+//! key must have been issued for the last of them, the code the contract is migrated from. The enclave gets the
+//! history as the `key_aliases` field of the env, which only this library sets: it reads entries with the keys
+//! of all versions and re-encrypts every entry it writes with the key of the new code.
+//!
+//! `audit_state_keys` counts the entries of a contract's state per key version, e.g. to find out whether the
+//! entries of an old version were all rewritten.
+
+use std::convert::TryInto;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use cosmwasm_std::Order;
+
+use crate::crash_report;
+use crate::db::DB;
+use crate::error::Error;
+use crate::state_export::key_after;
+use crate::vm::{
+    untrusted_get_state_key_version, untrusted_verify_contract_key, Checksum, Storage,
+    StorageIterator, VmError,
+};
+
+const KEY_HISTORY_ARG: &str = "key_history";
+/// The field of the env with the key history
+pub const KEY_ALIASES_FIELD: &str = "key_aliases";
+/// The longest history we accept
+pub const MAX_KEY_VERSIONS: usize = 64;
+const CODE_ID_LEN: usize = 32;
+
+/// The codes a contract ran, oldest first
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KeyHistory(Vec<Vec<u8>>);
+
+impl KeyHistory {
+    /// Parses a concatenation of 32 byte code ids
+    pub fn parse(history: &[u8]) -> Result<Self, Error> {
+        let code_ids = history.chunks_exact(CODE_ID_LEN);
+        if !code_ids.remainder().is_empty() {
+            let msg = format!("expected a multiple of {} bytes", CODE_ID_LEN);
+            return Err(Error::invalid_arg(KEY_HISTORY_ARG, msg));
+        }
+        if code_ids.len() > MAX_KEY_VERSIONS {
+            let msg = format!(
+                "{} versions exceed the maximum of {}",
+                code_ids.len(),
+                MAX_KEY_VERSIONS
+            );
+            return Err(Error::invalid_arg(KEY_HISTORY_ARG, msg));
+        }
+        Ok(KeyHistory(code_ids.map(<[u8]>::to_vec).collect()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn to_json(&self) -> Value {
+        Value::Array(
+            self.0
+                .iter()
+                .map(|code_id| Value::String(hex::encode(code_id)))
+                .collect(),
+        )
+    }
+}
+
+/// The contract key in the env `env`. The env carries it base64 encoded.
+fn contract_key(env: &Value) -> Result<Vec<u8>, Error> {
+    let key = env
+        .get("contract_key")
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::invalid_arg("params", "expected a contract_key"))?;
+    base64::decode(key).map_err(|e| Error::invalid_arg("params", e))
+}
+
+/// The env of a migration of the contract with `history`, built from the env `params` from Go. An empty
+/// history leaves the env as it is.
+pub fn migration_env(params: &[u8], history: &KeyHistory) -> Result<Vec<u8>, Error> {
+    let mut env: Value =
+        serde_json::from_slice(params).map_err(|e| Error::invalid_arg("params", e))?;
+    let fields = env
+        .as_object()
+        .ok_or_else(|| Error::invalid_arg("params", "expected a JSON object"))?;
+    if fields.contains_key(KEY_ALIASES_FIELD) {
+        let msg = format!("the {} field is set by the library", KEY_ALIASES_FIELD);
+        return Err(Error::invalid_arg("params", msg));
+    }
+    let previous = match history.0.last() {
+        Some(previous) => previous,
+        None => return Ok(params.to_vec()),
+    };
+    let previous: Checksum = previous.as_slice().try_into()?;
+    let contract_addr = env
+        .pointer("/contract/address")
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::invalid_arg("params", "expected a contract address"))?;
+    let key = contract_key(&env)?;
+    match untrusted_verify_contract_key(contract_addr, &previous, &key) {
+        // An error happened in the SGX sdk.
+        Err(e) => return Err(crash_report::sdk_failure("migrate", e)),
+        // An error was returned from the enclave.
+        Ok(Err(e)) => return Err(Error::enclave_err(e.to_string())),
+        Ok(Ok(true)) => {}
+        Ok(Ok(false)) => {
+            return Err(Error::invalid_arg(
+                KEY_HISTORY_ARG,
+                "the contract key was not issued for the last code of the history",
+            ))
+        }
+    }
+    env[KEY_ALIASES_FIELD] = history.to_json();
+    serde_json::to_vec(&env).map_err(Error::vm_err)
+}
+
+/// The entries of a contract's state per key version, returned by `audit_state_keys` as JSON
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct KeyAudit {
+    /// The number of entries encrypted with the key of each code of the history
+    pub versions: Vec<u64>,
+    /// The entries no key of the history decrypts
+    pub unknown: u64,
+    /// base64 encoded token for the next page, or null if this was the last page
+    pub next: Option<String>,
+}
+
+/// Counts up to `limit` entries of `iter`, whose key version `version_of` tells
+fn count_page<F>(
+    iter: &mut dyn StorageIterator,
+    versions: usize,
+    limit: usize,
+    mut version_of: F,
+) -> Result<KeyAudit, Error>
+where
+    F: FnMut(&[u8], &[u8]) -> Result<Option<u32>, Error>,
+{
+    let mut audit = KeyAudit {
+        versions: vec![0; versions],
+        ..KeyAudit::default()
+    };
+    for _ in 0..limit {
+        let (key, value) = match iter.next().0.map_err(VmError::from)? {
+            Some(kv) => kv,
+            None => {
+                audit.next = None;
+                return Ok(audit);
+            }
+        };
+        match version_of(&key, &value)? {
+            Some(version) if (version as usize) < versions => audit.versions[version as usize] += 1,
+            _ => audit.unknown += 1,
+        }
+        audit.next = Some(base64::encode(&key));
+    }
+    // There may be more entries, but we only find out on the next call.
+    Ok(audit)
+}
+
+/// Audits a page of the storage of the contract with `contract_key` which ran the codes of `history`, the last
+/// being its current code.
+///
+/// `start_after` is the `next` token of the previous page, or `None` for the first page.
+pub fn audit_state_keys(
+    db: &DB,
+    contract_key: &[u8],
+    history: &[u8],
+    start_after: Option<&[u8]>,
+    limit: u32,
+) -> Result<Vec<u8>, Error> {
+    if limit == 0 {
+        return Err(Error::invalid_arg("limit", "must be greater than 0"));
+    }
+    let parsed = KeyHistory::parse(history)?;
+    if parsed.is_empty() {
+        return Err(Error::invalid_arg(
+            KEY_HISTORY_ARG,
+            "expected at least the current code",
+        ));
+    }
+    let start = start_after.map(key_after);
+    let mut iter = db
+        .range(start.as_deref(), None, Order::Ascending)
+        .0
+        .map_err(VmError::from)?;
+    let audit = count_page(
+        iter.as_mut(),
+        parsed.0.len(),
+        limit as usize,
+        |key, value| match untrusted_get_state_key_version(contract_key, history, key, value) {
+            // An error happened in the SGX sdk.
+            Err(e) => Err(crash_report::sdk_failure("audit_state_keys", e)),
+            // An error was returned from the enclave.
+            Ok(Err(e)) => Err(Error::enclave_err(e.to_string())),
+            Ok(Ok(version)) => Ok(version),
+        },
+    )?;
+    serde_json::to_vec(&audit).map_err(Error::vm_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{FfiResult, GasInfo};
+    use cosmwasm_std::KV;
+
+    struct MockIterator(std::vec::IntoIter<KV>);
+
+    impl StorageIterator for MockIterator {
+        fn next(&mut self) -> FfiResult<Option<KV>> {
+            (Ok(self.0.next()), GasInfo::free())
+        }
+    }
+
+    #[test]
+    fn history_parses() {
+        let history = KeyHistory::parse(&[[1u8; 32], [2u8; 32]].concat()).unwrap();
+        assert_eq!(history.0, vec![vec![1u8; 32], vec![2u8; 32]]);
+        assert_eq!(history.to_json()[1], Value::String("02".repeat(32)));
+        assert!(KeyHistory::parse(&[]).unwrap().is_empty());
+        assert!(KeyHistory::parse(&[1u8; 33]).is_err());
+        assert!(KeyHistory::parse(&vec![1u8; 32 * (MAX_KEY_VERSIONS + 1)]).is_err());
+    }
+
+    #[test]
+    fn migration_env_without_history() {
+        let params = br#"{"contract":{"address":"x"},"contract_key":"a2V5"}"#;
+        let env = migration_env(params, &KeyHistory::default()).unwrap();
+        assert_eq!(env, params.to_vec());
+
+        let spoofed = br#"{"contract":{"address":"x"},"key_aliases":[]}"#;
+        assert!(migration_env(spoofed, &KeyHistory::default()).is_err());
+        assert!(migration_env(b"[]", &KeyHistory::default()).is_err());
+    }
+
+    #[test]
+    fn count_page_counts_versions() {
+        let entries: Vec<KV> = [b"a", b"b", b"c", b"d"]
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (k.to_vec(), vec![i as u8]))
+            .collect();
+        let mut iter = MockIterator(entries.into_iter());
+        // the value tells the version, 3 is unknown
+        let version_of = |_: &[u8], value: &[u8]| Ok(Some(u32::from(value[0] % 4)));
+
+        let audit = count_page(&mut iter, 2, 3, version_of).unwrap();
+        assert_eq!(audit.versions, vec![1, 1]);
+        assert_eq!(audit.unknown, 1);
+        assert_eq!(audit.next, Some(base64::encode(b"c")));
+
+        let audit = count_page(&mut iter, 2, 3, version_of).unwrap();
+        assert_eq!(audit.versions, vec![0, 0]);
+        assert_eq!(audit.unknown, 1);
+        assert_eq!(audit.next, None);
+    }
+}
@@ -0,0 +1,125 @@
+//! Recovering a node whose platform TCB went out of date.
+//!
+//! When Intel releases microcode and BIOS updates for new advisories, IAS answers quotes of platforms which
+//! lack them with a status like `GROUP_OUT_OF_DATE`, and the registration service and verifiers stop trusting
+//! the node. Such statuses fail with `Error::TcbOutdated` instead of a generic error, so Go can tell the
+//! operator which advisories to patch rather than retrying.
+//!
+//! Once the platform is updated, `refresh_registration` has the enclave create a new attestation report and
+//! returns everything needed to register the node again in one call: the keys and the new certificate. The
+//! keys of the node stay the same, only the report they are attested with changes.
+
+use serde::Serialize;
+
+use crate::attestation::AttestationReport;
+use crate::error::Error;
+use crate::registration::RegistrationKeys;
+
+/// Bumped whenever the serialized format of `RegistrationRefresh` changes
+pub const REGISTRATION_REFRESH_VERSION: u32 = 1;
+
+/// The quote statuses of platforms which are genuine but lack updates, and are trusted again once updated
+const OUTDATED_STATUSES: &[&str] = &[
+    "GROUP_OUT_OF_DATE",
+    "CONFIGURATION_NEEDED",
+    "CONFIGURATION_AND_SW_HARDENING_NEEDED",
+];
+
+/// Fails with `Error::TcbOutdated` if `status` tells that the platform must be updated
+pub fn check_status(status: &str, advisory_ids: &[String]) -> Result<(), Error> {
+    if OUTDATED_STATUSES.contains(&status) {
+        return Err(Error::tcb_outdated(status, advisory_ids));
+    }
+    Ok(())
+}
+
+/// The payload the node registers again with
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RegistrationRefresh {
+    pub version: u32,
+    pub keys: RegistrationKeys,
+    /// The base64 encoded DER certificate signed by the enclave, which contains the new attestation report
+    pub certificate: String,
+    /// The status IAS gave the quote of the new report
+    pub quote_status: String,
+    pub advisory_ids: Vec<String>,
+}
+
+impl RegistrationRefresh {
+    /// `report` is the attestation report IAS returned for the certificate, as the enclave received it
+    pub fn new(keys: &[u8], certificate: &[u8], report: &[u8]) -> Result<Self, Error> {
+        let report: AttestationReport = serde_json::from_slice(report)
+            .map_err(|e| Error::enclave_err(format!("Invalid attestation report: {}", e)))?;
+        check_status(&report.isv_enclave_quote_status, &report.advisory_ids)?;
+        Ok(RegistrationRefresh {
+            version: REGISTRATION_REFRESH_VERSION,
+            keys: RegistrationKeys::from_enclave(keys)?,
+            certificate: base64::encode(certificate),
+            quote_status: report.isv_enclave_quote_status,
+            advisory_ids: report.advisory_ids,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::vm_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn keys() -> Vec<u8> {
+        [[1u8; 32], [2u8; 32]].concat()
+    }
+
+    fn report(status: &str) -> Vec<u8> {
+        let report = json!({
+            "timestamp": "2020-06-08T12:34:56.123456",
+            "isvEnclaveQuoteStatus": status,
+            "isvEnclaveQuoteBody": "",
+            "advisoryIDs": ["INTEL-SA-00334"],
+        });
+        report.to_string().into_bytes()
+    }
+
+    #[test]
+    fn new_accepts_updated_platforms() {
+        let refresh =
+            RegistrationRefresh::new(&keys(), b"der", &report("SW_HARDENING_NEEDED")).unwrap();
+        assert_eq!(
+            refresh,
+            RegistrationRefresh {
+                version: REGISTRATION_REFRESH_VERSION,
+                keys: RegistrationKeys::from_enclave(&keys()).unwrap(),
+                certificate: base64::encode(b"der"),
+                quote_status: "SW_HARDENING_NEEDED".to_string(),
+                advisory_ids: vec!["INTEL-SA-00334".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn new_rejects_outdated_platforms() {
+        match RegistrationRefresh::new(&keys(), b"der", &report("GROUP_OUT_OF_DATE")).unwrap_err() {
+            Error::TcbOutdated {
+                status,
+                advisory_ids,
+                ..
+            } => {
+                assert_eq!(status, "GROUP_OUT_OF_DATE");
+                assert_eq!(advisory_ids, vec!["INTEL-SA-00334".to_string()]);
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert!(RegistrationRefresh::new(&keys(), b"der", b"not json").is_err());
+    }
+
+    #[test]
+    fn check_status_only_rejects_outdated_statuses() {
+        assert!(check_status("OK", &[]).is_ok());
+        assert!(check_status("GROUP_REVOKED", &[]).is_ok());
+        assert!(check_status("CONFIGURATION_NEEDED", &[]).is_err());
+    }
+}
@@ -0,0 +1,635 @@
+//! In-Rust mocks of the Go side, for crates and fuzzers which exercise this library without a Go host.
+//!
+//! `MockStorage`, `MockApi` and `MockQuerier` own the data; `DB`, `GoApi` and `GoQuerier` built from them
+//! only hold pointers, so the mocks must outlive every call they are passed to. The gas reported by the
+//! mock callbacks matches the mocks of the Go tests.
+//!
+//! `Harness` goes one step further and drives contracts through the extern "C" functions, the way
+//! the Go host does.
+
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use cosmwasm_std::{Binary, KV};
+
+use crate::api::{api_t, GoApi, GoApi_vtable};
+use crate::db::{db_t, DB_vtable, DB};
+use crate::error::GoResult;
+use crate::gas_meter::gas_meter_t;
+use crate::iterator::{iterator_t, GoIter, Iterator_vtable};
+use crate::memory::Buffer;
+use crate::querier::{querier_t, GoQuerier, Querier_vtable};
+use crate::{
+    cache_t, create, get_code, handle, init_cache, instantiate, migrate, query, release_cache,
+    set_consensus_params, BlockContext, GasReport,
+};
+
+pub const GET_PRICE: u64 = 99000;
+pub const SET_PRICE: u64 = 187000;
+pub const REMOVE_PRICE: u64 = 142000;
+pub const RANGE_PRICE: u64 = 261000;
+pub const CANONICAL_LENGTH: usize = 32;
+pub const COST_CANONICAL: u64 = 440;
+pub const COST_HUMAN: u64 = 550;
+
+/// Copies `data` into a `Buffer` owned by this library, as Go would with `allocate_rust`
+pub fn buffer(data: &[u8]) -> Buffer {
+    Buffer::from_vec(data.to_vec())
+}
+
+/// Points a `Buffer` at `data` without copying, as Go does for arguments with `sendSlice`.
+/// The library only reads such buffers, so they must not be consumed.
+pub fn view(data: &[u8]) -> Buffer {
+    Buffer::view(data)
+}
+
+/// Takes back the contents of a `Buffer` returned by this library
+///
+/// # Safety
+///
+/// `buf` must have been returned by this library, and not consumed before
+pub unsafe fn consume_buffer(buf: Buffer) -> Vec<u8> {
+    buf.consume()
+}
+
+/// A key value store backed by a `BTreeMap`
+#[derive(Default)]
+pub struct MockStorage {
+    pub data: BTreeMap<Vec<u8>, Vec<u8>>,
+    // snapshots of the ranges scanned, indexed by `iterator_index - 1`
+    iterators: Vec<std::vec::IntoIter<KV>>,
+}
+
+impl MockStorage {
+    pub fn new() -> Self {
+        MockStorage::default()
+    }
+
+    /// A `DB` operating on this storage
+    pub fn db(&mut self) -> DB {
+        DB {
+            gas_meter: std::ptr::null_mut(),
+            state: self as *mut MockStorage as *mut db_t,
+            vtable: DB_vtable {
+                read_db: mock_read,
+                write_db: mock_write,
+                remove_db: mock_remove,
+                scan_db: mock_scan,
+                resolve_contract: None,
+                scan_prefix: None,
+                delete_range: None,
+                set_block_context: None,
+                gas_consumed: None,
+                authorize_call: None,
+                call_result: None,
+            },
+            namespace: Buffer::default(),
+        }
+    }
+}
+
+/// A `DB` without storage whose callbacks all fail, for the unit tests of the optional callbacks, which they
+/// set in its vtable
+#[cfg(test)]
+pub fn failing_db() -> DB {
+    DB {
+        gas_meter: std::ptr::null_mut(),
+        state: std::ptr::null_mut(),
+        vtable: DB_vtable {
+            read_db: fail_read,
+            write_db: fail_write,
+            remove_db: fail_remove,
+            scan_db: fail_scan,
+            resolve_contract: None,
+            scan_prefix: None,
+            delete_range: None,
+            set_block_context: None,
+            gas_consumed: None,
+            authorize_call: None,
+            call_result: None,
+        },
+        namespace: Buffer::default(),
+    }
+}
+
+#[cfg(test)]
+extern "C" fn fail_read(
+    _: *mut db_t,
+    _: *mut gas_meter_t,
+    _: *mut u64,
+    _: Buffer,
+    _: *mut Buffer,
+    _: *mut Buffer,
+) -> i32 {
+    GoResult::Other as i32
+}
+
+#[cfg(test)]
+extern "C" fn fail_write(
+    _: *mut db_t,
+    _: *mut gas_meter_t,
+    _: *mut u64,
+    _: Buffer,
+    _: Buffer,
+    _: *mut Buffer,
+) -> i32 {
+    GoResult::Other as i32
+}
+
+#[cfg(test)]
+extern "C" fn fail_remove(
+    _: *mut db_t,
+    _: *mut gas_meter_t,
+    _: *mut u64,
+    _: Buffer,
+    _: *mut Buffer,
+) -> i32 {
+    GoResult::Other as i32
+}
+
+#[cfg(test)]
+extern "C" fn fail_scan(
+    _: *mut db_t,
+    _: *mut gas_meter_t,
+    _: *mut u64,
+    _: Buffer,
+    _: Buffer,
+    _: i32,
+    _: i32,
+    _: *mut GoIter,
+    _: *mut Buffer,
+) -> i32 {
+    GoResult::Other as i32
+}
+
+unsafe fn storage<'a>(ptr: *mut db_t) -> &'a mut MockStorage {
+    &mut *(ptr as *mut MockStorage)
+}
+
+extern "C" fn mock_read(
+    ptr: *mut db_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    key: Buffer,
+    value: *mut Buffer,
+    _err: *mut Buffer,
+) -> i32 {
+    unsafe {
+        let key = key.read().unwrap_or_default();
+        if let Some(found) = storage(ptr).data.get(key) {
+            *value = buffer(found);
+        }
+        *used_gas = GET_PRICE;
+    }
+    GoResult::Ok as i32
+}
+
+extern "C" fn mock_write(
+    ptr: *mut db_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    key: Buffer,
+    value: Buffer,
+    _err: *mut Buffer,
+) -> i32 {
+    unsafe {
+        let key = key.read().unwrap_or_default().to_vec();
+        let value = value.read().unwrap_or_default().to_vec();
+        storage(ptr).data.insert(key, value);
+        *used_gas = SET_PRICE;
+    }
+    GoResult::Ok as i32
+}
+
+extern "C" fn mock_remove(
+    ptr: *mut db_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    key: Buffer,
+    _err: *mut Buffer,
+) -> i32 {
+    unsafe {
+        let key = key.read().unwrap_or_default();
+        storage(ptr).data.remove(key);
+        *used_gas = REMOVE_PRICE;
+    }
+    GoResult::Ok as i32
+}
+
+extern "C" fn mock_scan(
+    ptr: *mut db_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    start: Buffer,
+    end: Buffer,
+    order: i32,
+    _mode: i32,
+    out: *mut GoIter,
+    _err: *mut Buffer,
+) -> i32 {
+    let store = unsafe { storage(ptr) };
+    let start = unsafe { start.read() };
+    let end = unsafe { end.read() };
+    let mut items: Vec<KV> = match (start, end) {
+        (Some(start), Some(end)) if start >= end => vec![],
+        _ => {
+            let lower = start.map_or(Bound::Unbounded, |s| Bound::Included(s.to_vec()));
+            let upper = end.map_or(Bound::Unbounded, |e| Bound::Excluded(e.to_vec()));
+            store
+                .data
+                .range((lower, upper))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect()
+        }
+    };
+    match order {
+        1 => {}
+        2 => items.reverse(),
+        _ => return GoResult::BadArgument as i32,
+    }
+    store.iterators.push(items.into_iter());
+    unsafe {
+        (*out).state = iterator_t {
+            db_counter: ptr as u64,
+            iterator_index: store.iterators.len() as u64,
+        };
+        (*out).vtable = Iterator_vtable {
+            next_db: Some(mock_next),
+            prefetch_db: None,
+            seek_db: None,
+        };
+        *used_gas = RANGE_PRICE;
+    }
+    GoResult::Ok as i32
+}
+
+extern "C" fn mock_next(
+    state: iterator_t,
+    _gas_meter: *mut gas_meter_t,
+    _used_gas: *mut u64,
+    key: *mut Buffer,
+    value: *mut Buffer,
+    _err: *mut Buffer,
+) -> i32 {
+    let store = unsafe { storage(state.db_counter as *mut db_t) };
+    let iter = match store.iterators.get_mut(state.iterator_index as usize - 1) {
+        Some(iter) => iter,
+        None => return GoResult::BadArgument as i32,
+    };
+    if let Some((k, v)) = iter.next() {
+        unsafe {
+            *key = buffer(&k);
+            *value = buffer(&v);
+        }
+    }
+    GoResult::Ok as i32
+}
+
+/// Canonical addresses are the human address, zero padded to `CANONICAL_LENGTH` bytes
+#[derive(Default)]
+pub struct MockApi {}
+
+impl MockApi {
+    pub fn api(&self) -> GoApi {
+        GoApi {
+            state: self as *const MockApi as *const api_t,
+            vtable: GoApi_vtable {
+                humanize_address: mock_humanize,
+                canonicalize_address: mock_canonicalize,
+            },
+        }
+    }
+}
+
+fn set_error_message(err: *mut Buffer, msg: &str) -> i32 {
+    unsafe { *err = buffer(msg.as_bytes()) };
+    GoResult::Other as i32
+}
+
+extern "C" fn mock_canonicalize(
+    _ptr: *const api_t,
+    human: Buffer,
+    canon: *mut Buffer,
+    err: *mut Buffer,
+    used_gas: *mut u64,
+) -> i32 {
+    let human = unsafe { human.read() }.unwrap_or_default();
+    if human.len() > CANONICAL_LENGTH {
+        return set_error_message(err, "human encoding too long");
+    }
+    let mut out = human.to_vec();
+    out.resize(CANONICAL_LENGTH, 0);
+    unsafe {
+        *canon = Buffer::from_vec(out);
+        *used_gas = COST_CANONICAL;
+    }
+    GoResult::Ok as i32
+}
+
+extern "C" fn mock_humanize(
+    _ptr: *const api_t,
+    canon: Buffer,
+    human: *mut Buffer,
+    err: *mut Buffer,
+    used_gas: *mut u64,
+) -> i32 {
+    let canon = unsafe { canon.read() }.unwrap_or_default();
+    if canon.len() != CANONICAL_LENGTH {
+        return set_error_message(err, "wrong canonical length");
+    }
+    let cut = canon
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(CANONICAL_LENGTH);
+    unsafe {
+        *human = buffer(&canon[..cut]);
+        *used_gas = COST_HUMAN;
+    }
+    GoResult::Ok as i32
+}
+
+/// Answers queries from a fixed table of raw requests and responses
+#[derive(Default)]
+pub struct MockQuerier {
+    pub responses: BTreeMap<Vec<u8>, Binary>,
+}
+
+impl MockQuerier {
+    pub fn new() -> Self {
+        MockQuerier::default()
+    }
+
+    pub fn querier(&self) -> GoQuerier {
+        GoQuerier {
+            state: self as *const MockQuerier as *const querier_t,
+            vtable: Querier_vtable {
+                query_external: mock_query,
+                set_block_context: None,
+            },
+        }
+    }
+}
+
+extern "C" fn mock_query(
+    ptr: *const querier_t,
+    _gas_limit: u64,
+    _used_gas: *mut u64,
+    request: Buffer,
+    result: *mut Buffer,
+    err: *mut Buffer,
+) -> i32 {
+    let querier = unsafe { &*(ptr as *const MockQuerier) };
+    let request = unsafe { request.read() }.unwrap_or_default();
+    let response = match querier.responses.get(request) {
+        Some(response) => response.clone(),
+        None => return set_error_message(err, "no mock response for this request"),
+    };
+    let response: cosmwasm_std::SystemResult<cosmwasm_std::StdResult<Binary>> = Ok(Ok(response));
+    match serde_json::to_vec(&response) {
+        Ok(data) => {
+            unsafe { *result = Buffer::from_vec(data) };
+            GoResult::Ok as i32
+        }
+        Err(e) => set_error_message(err, &e.to_string()),
+    }
+}
+
+/// The result of a contract call made through `Harness`
+#[derive(Debug)]
+pub struct Outcome {
+    pub data: Vec<u8>,
+    /// The gas used inside of the VM, as reported through `gas_used`
+    pub gas_used: u64,
+    pub gas_report: GasReport,
+}
+
+/// A cache and mock environment, driving contracts through the extern "C" functions
+pub struct Harness {
+    cache: *mut cache_t,
+    pub storage: MockStorage,
+    pub api: MockApi,
+    pub querier: MockQuerier,
+    pub gas_limit: u64,
+}
+
+/// Reads the error written by a failed call, if there is one
+fn take_error(err: Buffer) -> Result<(), String> {
+    if err.ptr.is_null() {
+        return Ok(());
+    }
+    let msg = unsafe { err.consume() };
+    Err(String::from_utf8_lossy(&msg).into_owned())
+}
+
+impl Harness {
+    /// Opens a cache in `data_dir` with the given comma separated features and JSON encoded `CacheOptions`,
+    /// under the default `ConsensusParams`
+    pub fn new(data_dir: &str, features: &str, options: &[u8]) -> Result<Self, String> {
+        let mut err = Buffer::default();
+        let cache = init_cache(
+            view(data_dir.as_bytes()),
+            view(features.as_bytes()),
+            view(options),
+            0,
+            Some(&mut err),
+        );
+        take_error(err)?;
+        let mut harness = Harness {
+            cache,
+            storage: MockStorage::new(),
+            api: MockApi::default(),
+            querier: MockQuerier::new(),
+            gas_limit: 100_000_000,
+        };
+        harness.set_consensus_params(b"{}")?;
+        Ok(harness)
+    }
+
+    /// Replaces the consensus params with the JSON encoded `params`
+    pub fn set_consensus_params(&mut self, params: &[u8]) -> Result<(), String> {
+        let mut err = Buffer::default();
+        set_consensus_params(self.cache, view(params), Some(&mut err));
+        take_error(err)
+    }
+
+    /// Stores `wasm` and returns its code id
+    pub fn store_code(&mut self, wasm: &[u8]) -> Result<Vec<u8>, String> {
+        let mut err = Buffer::default();
+        let id = create(self.cache, view(wasm), Some(&mut err));
+        take_error(err)?;
+        Ok(unsafe { id.consume() })
+    }
+
+    pub fn get_code(&mut self, code_id: &[u8]) -> Result<Vec<u8>, String> {
+        let mut err = Buffer::default();
+        let code = get_code(self.cache, view(code_id), Some(&mut err));
+        take_error(err)?;
+        Ok(unsafe { code.consume() })
+    }
+
+    pub fn instantiate(
+        &mut self,
+        code_id: &[u8],
+        params: &[u8],
+        msg: &[u8],
+    ) -> Result<Outcome, String> {
+        self.call(|h, gas_used, gas_report, err| {
+            instantiate(
+                h.cache,
+                view(code_id),
+                view(params),
+                view(msg),
+                h.storage.db(),
+                h.api.api(),
+                h.querier.querier(),
+                h.gas_limit,
+                0,
+                Some(gas_used),
+                Some(gas_report),
+                Buffer::default(),
+                Some(err),
+            )
+        })
+    }
+
+    pub fn handle(&mut self, code_id: &[u8], params: &[u8], msg: &[u8]) -> Result<Outcome, String> {
+        self.call(|h, gas_used, gas_report, err| {
+            handle(
+                h.cache,
+                view(code_id),
+                view(params),
+                view(msg),
+                h.storage.db(),
+                h.api.api(),
+                h.querier.querier(),
+                h.gas_limit,
+                0,
+                Some(gas_used),
+                Some(gas_report),
+                Buffer::default(),
+                Some(err),
+            )
+        })
+    }
+
+    /// Migrates with the JSON `MigrateAuth` `auth`, e.g. `{"governance":true}`
+    pub fn migrate(
+        &mut self,
+        code_id: &[u8],
+        params: &[u8],
+        msg: &[u8],
+        auth: &[u8],
+    ) -> Result<Outcome, String> {
+        self.call(|h, gas_used, gas_report, err| {
+            migrate(
+                h.cache,
+                view(code_id),
+                view(params),
+                view(msg),
+                h.storage.db(),
+                h.api.api(),
+                h.querier.querier(),
+                h.gas_limit,
+                0,
+                Some(gas_used),
+                Some(gas_report),
+                Buffer::default(),
+                view(auth),
+                Buffer::default(),
+                Some(err),
+            )
+        })
+    }
+
+    pub fn query(&mut self, code_id: &[u8], msg: &[u8]) -> Result<Outcome, String> {
+        self.call(|h, gas_used, gas_report, err| {
+            query(
+                h.cache,
+                view(code_id),
+                view(msg),
+                h.storage.db(),
+                h.api.api(),
+                h.querier.querier(),
+                h.gas_limit,
+                0,
+                BlockContext::default(),
+                false,
+                Some(gas_used),
+                Some(gas_report),
+                Buffer::default(),
+                Some(err),
+            )
+        })
+    }
+
+    fn call<F>(&mut self, f: F) -> Result<Outcome, String>
+    where
+        F: FnOnce(&mut Self, &mut u64, &mut GasReport, &mut Buffer) -> Buffer,
+    {
+        let mut gas_used = 0u64;
+        let mut gas_report = GasReport::default();
+        let mut err = Buffer::default();
+        let data = f(self, &mut gas_used, &mut gas_report, &mut err);
+        let data = unsafe { data.consume() };
+        take_error(err)?;
+        Ok(Outcome {
+            data,
+            gas_used,
+            gas_report,
+        })
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        release_cache(self.cache);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{Api, Querier, Storage};
+    use cosmwasm_std::{HumanAddr, Order};
+
+    #[test]
+    fn mock_storage_works() {
+        let mut storage = MockStorage::new();
+        let mut db = storage.db();
+        db.set(b"a", b"1").0.unwrap();
+        db.set(b"b", b"2").0.unwrap();
+        db.set(b"c", b"3").0.unwrap();
+        assert_eq!(db.get(b"b").0.unwrap(), Some(b"2".to_vec()));
+        db.remove(b"b").0.unwrap();
+        assert_eq!(db.get(b"b").0.unwrap(), None);
+
+        let mut iter = db.range(None, None, Order::Descending).0.unwrap();
+        assert_eq!(iter.next().0.unwrap().unwrap().0, b"c".to_vec());
+        assert_eq!(iter.next().0.unwrap().unwrap().0, b"a".to_vec());
+        assert!(iter.next().0.unwrap().is_none());
+    }
+
+    #[test]
+    fn mock_api_round_trips() {
+        let mock = MockApi::default();
+        let api = mock.api();
+        let human = HumanAddr("foobar".to_string());
+        let canon = api.canonical_address(&human).0.unwrap();
+        assert_eq!(canon.as_slice().len(), CANONICAL_LENGTH);
+        assert_eq!(api.human_address(&canon).0.unwrap(), human);
+
+        let too_long = HumanAddr("x".repeat(CANONICAL_LENGTH + 1));
+        assert!(api.canonical_address(&too_long).0.is_err());
+    }
+
+    #[test]
+    fn mock_querier_answers_known_requests() {
+        let mut mock = MockQuerier::new();
+        mock.responses
+            .insert(b"ping".to_vec(), Binary(b"pong".to_vec()));
+        let querier = mock.querier();
+        let result = querier.query_raw(b"ping", 1000).0.unwrap();
+        assert_eq!(result, Ok(Ok(Binary(b"pong".to_vec()))));
+        assert!(querier.query_raw(b"other", 1000).0.is_err());
+    }
+}
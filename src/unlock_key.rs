@@ -0,0 +1,145 @@
+//! A secret of the operator mixed into the sealing of the node's keys and seed.
+//!
+//! The enclave seals the node's secrets to the CPU, so anyone who can run the enclave on the node's machine,
+//! e.g. from a copy of its disk, can unseal them and stand up a node with the same identity. With an unlock
+//! key, set by Go through `set_unlock_key` before `init_node` or the first contract call, the enclave also
+//! mixes a factor derived from it into the sealing key. Secrets sealed this way cannot be unsealed without the
+//! key, which the operator keeps off the disk, e.g. in a keyfile on removable media or as a password.
+//!
+//! The key is set once per process. It is kept in memory and handed to the enclave again after
+//! `reload_enclave`, since the new enclave unseals the secrets on its first use.
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use hkdf::Hkdf;
+use lazy_static::lazy_static;
+use sha2::Sha256;
+
+use crate::crash_report;
+use crate::error::Error;
+use crate::vm::untrusted_set_unlock_factor;
+
+const UNLOCK_KEY_ARG: &str = "unlock_key";
+
+/// Shorter keys are rejected, as the factor is not stretched like a password hash
+pub const MIN_UNLOCK_KEY_LEN: usize = 16;
+
+/// Separates the factor from other keys derived from the same secret
+const FACTOR_INFO: &[u8] = b"secret-network-unlock-factor-v1";
+
+pub type UnlockFactor = [u8; 32];
+
+/// The factor the enclave mixes into the sealing key, derived from the key of the operator
+fn derive_factor(key: &[u8]) -> Result<UnlockFactor, Error> {
+    if key.len() < MIN_UNLOCK_KEY_LEN {
+        let msg = format!(
+            "expected at least {} bytes, got {}",
+            MIN_UNLOCK_KEY_LEN,
+            key.len()
+        );
+        return Err(Error::invalid_arg(UNLOCK_KEY_ARG, msg));
+    }
+    let mut factor = [0u8; 32];
+    Hkdf::<Sha256>::new(None, key)
+        .expand(FACTOR_INFO, &mut factor)
+        .map_err(|_| Error::vm_err("Cannot derive the unlock factor"))?;
+    Ok(factor)
+}
+
+fn apply(factor: &UnlockFactor) -> Result<(), Error> {
+    match untrusted_set_unlock_factor(factor) {
+        // An error happened in the SGX sdk.
+        Err(e) => Err(crash_report::sdk_failure("set_unlock_key", e)),
+        // An error was returned from the enclave.
+        Ok(Err(e)) => Err(Error::enclave_err(e.to_string())),
+        Ok(Ok(())) => Ok(()),
+    }
+}
+
+/// Sets the factor of `key` in `slot`, handing it to the enclave with `apply` the first time
+fn set_in<F>(slot: &mut Option<UnlockFactor>, key: &[u8], apply: F) -> Result<(), Error>
+where
+    F: FnOnce(&UnlockFactor) -> Result<(), Error>,
+{
+    let factor = derive_factor(key)?;
+    match slot {
+        Some(set) if *set == factor => Ok(()),
+        Some(_) => Err(Error::invalid_arg(
+            UNLOCK_KEY_ARG,
+            "another unlock key is set already, restart the node to change it",
+        )),
+        None => {
+            apply(&factor)?;
+            *slot = Some(factor);
+            Ok(())
+        }
+    }
+}
+
+fn lock(slot: &Mutex<Option<UnlockFactor>>) -> MutexGuard<'_, Option<UnlockFactor>> {
+    // a panic while holding the lock does not leave the slot inconsistent
+    slot.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+lazy_static! {
+    static ref FACTOR: Mutex<Option<UnlockFactor>> = Mutex::new(None);
+}
+
+/// Sets the unlock key of this process. Setting the same key again does nothing.
+pub fn set(key: &[u8]) -> Result<(), Error> {
+    set_in(&mut lock(&FACTOR), key, apply)
+}
+
+/// Hands the factor to a reloaded enclave, if a key was set
+pub fn reapply() -> Result<(), Error> {
+    match lock(&FACTOR).as_ref() {
+        Some(factor) => apply(factor),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"correct horse battery staple";
+
+    #[test]
+    fn derive_factor_works() {
+        assert_eq!(derive_factor(KEY).unwrap(), derive_factor(KEY).unwrap());
+        assert_ne!(
+            derive_factor(KEY).unwrap(),
+            derive_factor(b"correct horse battery stapler").unwrap()
+        );
+        match derive_factor(b"hunter2").unwrap_err() {
+            Error::InvalidArg { name, .. } => assert_eq!(name, UNLOCK_KEY_ARG),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn set_in_applies_the_key_once() {
+        let mut slot = None;
+        let mut applied = Vec::new();
+        set_in(&mut slot, KEY, |factor| {
+            applied.push(*factor);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(applied, vec![derive_factor(KEY).unwrap()]);
+
+        // the same key again is fine, another one is not
+        set_in(&mut slot, KEY, |_| panic!("applied twice")).unwrap();
+        let other = b"another long enough key";
+        assert!(set_in(&mut slot, other, |_| panic!("applied twice")).is_err());
+        assert_eq!(slot, Some(derive_factor(KEY).unwrap()));
+    }
+
+    #[test]
+    fn set_in_keeps_rejected_keys_unset() {
+        let mut slot = None;
+        let rejected = set_in(&mut slot, KEY, |_| Err(Error::enclave_err("sealed")));
+        assert!(rejected.is_err());
+        assert_eq!(slot, None);
+    }
+}
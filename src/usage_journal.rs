@@ -0,0 +1,209 @@
+//! The codes used most recently, to warm the module cache up after a restart.
+//!
+//! After a restart the VM holds no module in memory, and the modules removed by `module_format` are not even
+//! compiled, so the first block stalls on loading or compiling every code it runs. The cache keeps the
+//! `JOURNAL_CAPACITY` codes it instantiated most recently in the `usage.json` file of its data dir, and with
+//! the `warm_up_modules` option `init_cache` hands the most recent ones to the VM on a background thread,
+//! while Go goes on with its startup.
+//!
+//! The journal is written every `SAVE_INTERVAL` changes of its order and when the cache is released, so a
+//! crash loses at most the latest changes, which only makes the warm-up less accurate.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Instant;
+
+use log::*;
+
+use crate::snapshot::WASM_DIR;
+use crate::vm::{preload_module, Checksum};
+
+const USAGE_FILE: &str = "usage.json";
+/// The most codes the journal keeps
+pub const JOURNAL_CAPACITY: usize = 256;
+/// How many changes of the order are written at once
+const SAVE_INTERVAL: u32 = 32;
+
+/// The codes of a cache, the most recently instantiated first
+#[derive(Debug)]
+pub struct UsageJournal {
+    path: PathBuf,
+    /// Hex checksums
+    recent: Vec<String>,
+    unsaved: u32,
+}
+
+impl UsageJournal {
+    /// Reads the journal of the cache at `data_dir`. An unreadable journal is started again, as it is only a
+    /// hint.
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(USAGE_FILE);
+        let recent = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                warn!("Ignoring the unreadable usage journal {:?}: {}", path, e);
+                vec![]
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => {
+                warn!("Ignoring the unreadable usage journal {:?}: {}", path, e);
+                vec![]
+            }
+        };
+        UsageJournal {
+            path,
+            recent,
+            unsaved: 0,
+        }
+    }
+
+    /// Moves `code_id` to the front, when it is instantiated
+    pub fn record(&mut self, code_id: &Checksum) {
+        self.record_hex(code_id.to_hex());
+    }
+
+    fn record_hex(&mut self, checksum: String) {
+        if self.recent.first() == Some(&checksum) {
+            return;
+        }
+        self.recent.retain(|code| *code != checksum);
+        self.recent.insert(0, checksum);
+        self.recent.truncate(JOURNAL_CAPACITY);
+        self.unsaved += 1;
+        if self.unsaved >= SAVE_INTERVAL {
+            self.save();
+        }
+    }
+
+    /// The hex checksums of the `count` most recently used codes
+    pub fn most_recent(&self, count: usize) -> Vec<String> {
+        self.recent.iter().take(count).cloned().collect()
+    }
+
+    /// Writes the journal, logging failures, as the calls recording in it must not fail because of it
+    pub fn save(&mut self) {
+        if let Err(e) = self.write() {
+            warn!("Failed to write the usage journal {:?}: {}", self.path, e);
+        }
+        self.unsaved = 0;
+    }
+
+    fn write(&self) -> std::io::Result<()> {
+        let data = serde_json::to_vec(&self.recent)?;
+        // written to a temporary file first, so a crash does not leave a truncated journal
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+impl Drop for UsageJournal {
+    fn drop(&mut self) {
+        if self.unsaved > 0 {
+            self.save();
+        }
+    }
+}
+
+/// Hands the stored wasm of `checksums` to the VM on a background thread, which loads or compiles their
+/// modules ahead of their first use. Codes whose wasm is missing or does not match its checksum are skipped.
+pub fn warm_up(data_dir: &Path, checksums: Vec<String>) {
+    if checksums.is_empty() {
+        return;
+    }
+    let wasm_dir = data_dir.join(WASM_DIR);
+    let modules_dir = data_dir.to_path_buf();
+    let spawned = thread::Builder::new()
+        .name("module-warm-up".to_string())
+        .spawn(move || {
+            let start = Instant::now();
+            let preloaded = checksums
+                .iter()
+                .filter(|checksum| preload(&modules_dir, &wasm_dir, checksum))
+                .count();
+            info!(
+                "Preloaded {} of {} recently used modules in {:?}",
+                preloaded,
+                checksums.len(),
+                start.elapsed()
+            );
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start the module warm-up: {}", e);
+    }
+}
+
+fn preload(data_dir: &Path, wasm_dir: &Path, checksum: &str) -> bool {
+    let wasm = match fs::read(wasm_dir.join(checksum)) {
+        Ok(wasm) => wasm,
+        Err(e) => {
+            debug!("Not preloading code {}: {}", checksum, e);
+            return false;
+        }
+    };
+    let code_id = Checksum::generate(&wasm);
+    if code_id.to_hex() != checksum {
+        warn!(
+            "Not preloading code {}, its stored wasm does not match",
+            checksum
+        );
+        return false;
+    }
+    match preload_module(data_dir, &code_id, &wasm) {
+        Ok(preloaded) => preloaded,
+        Err(e) => {
+            warn!("Failed to preload code {}: {}", checksum, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn checksum(byte: u8) -> String {
+        hex::encode([byte; 32])
+    }
+
+    #[test]
+    fn record_keeps_the_most_recent_first() {
+        let dir = TempDir::new().unwrap();
+        let mut journal = UsageJournal::load(dir.path());
+        journal.record_hex(checksum(1));
+        journal.record_hex(checksum(2));
+        journal.record_hex(checksum(1));
+        journal.record_hex(checksum(3));
+        assert_eq!(journal.most_recent(2), vec![checksum(3), checksum(1)]);
+        assert_eq!(journal.most_recent(10).len(), 3);
+
+        for byte in 0..=255 {
+            journal.record_hex(checksum(byte));
+        }
+        journal.record_hex(hex::encode([7u8; 31]));
+        assert_eq!(journal.most_recent(1000).len(), JOURNAL_CAPACITY);
+        assert_eq!(journal.most_recent(1), vec![hex::encode([7u8; 31])]);
+    }
+
+    #[test]
+    fn journal_survives_restarts() {
+        let dir = TempDir::new().unwrap();
+        {
+            let mut journal = UsageJournal::load(dir.path());
+            journal.record_hex(checksum(1));
+            journal.record_hex(checksum(2));
+        }
+        let journal = UsageJournal::load(dir.path());
+        assert_eq!(journal.most_recent(5), vec![checksum(2), checksum(1)]);
+    }
+
+    #[test]
+    fn unreadable_journal_starts_empty() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join(USAGE_FILE), b"[1,").unwrap();
+        let journal = UsageJournal::load(dir.path());
+        assert!(journal.most_recent(5).is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+//! The VM backend contracts run in.
+//!
+//! With the `sgx` feature (the default) this is `cosmwasm-sgx-vm`, which runs contracts in the enclave.
+//! With the `vanilla` feature it is the upstream `cosmwasm-vm`, so tools like simulators, indexers and CLI
+//! utilities can use this library and its Go bindings on machines without SGX. Both backends share the VM
+//! interface, and everything else in this crate imports it from here. The enclave-only functions are
+//! replaced by functions failing with `NoEnclave` in vanilla builds.
+
+#[cfg(all(feature = "sgx", feature = "vanilla"))]
+compile_error!(
+    "The features `sgx` and `vanilla` cannot be enabled at the same time (use `--no-default-features`)"
+);
+
+#[cfg(not(any(feature = "sgx", feature = "vanilla")))]
+compile_error!("One of the features `sgx` and `vanilla` must be enabled");
+
+#[cfg(feature = "sgx")]
+pub use cosmwasm_sgx_vm::*;
+
+#[cfg(feature = "vanilla")]
+pub use cosmwasm_vm::*;
+
+#[cfg(feature = "vanilla")]
+pub use no_enclave::*;
+
+#[cfg(feature = "vanilla")]
+mod no_enclave {
+    use std::fmt;
+
+    use enclave_ffi_types::NodeAuthResult;
+
+    use super::{Api, Checksum, FfiError, Instance, Querier, Storage, VmError, VmResult};
+
+    /// The error of all enclave functions in builds without SGX
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct NoEnclave;
+
+    impl fmt::Display for NoEnclave {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "This library was built without SGX support")
+        }
+    }
+
+    pub type SgxResult<T> = Result<T, NoEnclave>;
+
+    /// Like the enclave functions which can fail in the SDK or in the enclave
+    pub type EnclaveResult<T> = SgxResult<Result<T, NoEnclave>>;
+
+    pub fn untrusted_health_check() -> SgxResult<u32> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_init_bootstrap() -> SgxResult<[u8; 32]> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_get_encrypted_seed_v2(_cert: &[u8]) -> EnclaveResult<Vec<u8>> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_create_seed_share(_ceremony: &[u8]) -> EnclaveResult<Vec<u8>> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_combine_seed_shares(
+        _ceremony: &[u8],
+        _shares: &[u8],
+    ) -> EnclaveResult<[u8; 32]> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_init_node(_cert: &[u8], _seed: &[u8]) -> SgxResult<NodeAuthResult> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_reload_enclave(_path: &str) -> SgxResult<()> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_get_mrenclave() -> SgxResult<[u8; 32]> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_key_gen() -> SgxResult<[u8; 64]> {
+        Err(NoEnclave)
+    }
+
+    pub fn create_attestation_report_u() -> SgxResult<()> {
+        Err(NoEnclave)
+    }
+
+    pub fn create_attestation_report_v2_u() -> SgxResult<()> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_get_registration_keys() -> EnclaveResult<[u8; 64]> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_set_block_entropy(_height: u64, _entropy: &[u8]) -> EnclaveResult<()> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_create_key_attestation(_challenge: &[u8; 32]) -> EnclaveResult<Vec<u8>> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_refresh_registration() -> EnclaveResult<(Vec<u8>, Vec<u8>)> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_create_challenge_quote(_nonce: &[u8; 32]) -> EnclaveResult<Vec<u8>> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_verify_ias_report(
+        _report: &[u8],
+        _signature: &[u8],
+        _signing_cert: &[u8],
+    ) -> EnclaveResult<()> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_set_unlock_factor(_factor: &[u8; 32]) -> EnclaveResult<()> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_get_trusted_time() -> EnclaveResult<(u64, [u8; 32])> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_verify_permit_signature(
+        _sign_bytes: &[u8],
+        _signature: &[u8],
+        _pubkey: &[u8],
+    ) -> EnclaveResult<[u8; 20]> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_verify_contract_key(
+        _contract_addr: &str,
+        _code_hash: &Checksum,
+        _contract_key: &[u8],
+    ) -> EnclaveResult<bool> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_export_enclave_state() -> EnclaveResult<Vec<u8>> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_import_enclave_state(_state: &[u8]) -> EnclaveResult<()> {
+        Err(NoEnclave)
+    }
+
+    /// There is no enclave whose log lines could be passed on
+    pub fn set_enclave_log_sink(_sink: Option<fn(log::Level, &str)>) {}
+
+    /// The upstream VM keeps the memory limit it was built with. Codes declaring more initial memory than
+    /// `max_memory_pages` are still rejected, see `Cache::check_memory_limit`.
+    pub fn set_instance_memory_limit(_pages: u32) {}
+
+    /// The upstream VM cannot canonicalize NaNs, so `ConsensusParams` rejects the policy asking for it
+    pub fn set_nan_canonicalization(_enabled: bool) {}
+
+    /// The upstream VM charges fixed costs, so `CacheOptions` rejects other ones
+    pub fn set_wasm_cost_table(_costs: &[u64]) {}
+
+    /// The upstream VM always deserializes modules into memory
+    pub fn set_module_mmap(_enabled: bool) -> bool {
+        false
+    }
+
+    /// The upstream VM compiles a code on its first instantiation only, so nothing is preloaded
+    pub fn preload_module(
+        _data_dir: &std::path::Path,
+        _code_id: &Checksum,
+        _wasm: &[u8],
+    ) -> VmResult<bool> {
+        Ok(false)
+    }
+
+    pub fn untrusted_reencrypt_state_entry(
+        _contract_key: &[u8],
+        _key: &[u8],
+        _value: &[u8],
+    ) -> EnclaveResult<(Vec<u8>, Vec<u8>)> {
+        Err(NoEnclave)
+    }
+
+    pub fn untrusted_get_state_key_version(
+        _contract_key: &[u8],
+        _key_history: &[u8],
+        _key: &[u8],
+        _value: &[u8],
+    ) -> EnclaveResult<Option<u32>> {
+        Err(NoEnclave)
+    }
+
+    /// The upstream VM only calls the entry points of CosmWasm, so block hooks need the enclave's VM
+    pub fn call_begin_block_raw<S, A, Q>(
+        _instance: &mut Instance<S, A, Q>,
+        _env: &[u8],
+    ) -> VmResult<Vec<u8>>
+    where
+        S: Storage + 'static,
+        A: Api + 'static,
+        Q: Querier + 'static,
+    {
+        Err(VmError::from(FfiError::unknown(NoEnclave.to_string())))
+    }
+
+    pub fn call_end_block_raw<S, A, Q>(
+        _instance: &mut Instance<S, A, Q>,
+        _env: &[u8],
+    ) -> VmResult<Vec<u8>>
+    where
+        S: Storage + 'static,
+        A: Api + 'static,
+        Q: Querier + 'static,
+    {
+        Err(VmError::from(FfiError::unknown(NoEnclave.to_string())))
+    }
+}
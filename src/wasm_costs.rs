@@ -0,0 +1,487 @@
+//! The gas the VM charges for wasm execution, per instruction.
+//!
+//! The VM meters contracts by charging every instruction it executes the cost of its opcode. By default
+//! every instruction costs 1, like in the upstream VM. The `wasm_costs` cache option replaces the costs, so
+//! governance can retune the price of execution after benchmarking new hardware, without a release of this
+//! library. The table is handed to the VM with `set_wasm_cost_table` before every call.
+//!
+//! Every table has a schedule version, derived from the hash of its costs, which is reported in the gas
+//! report of every call. Nodes charging with different tables, which would disagree on the gas of a
+//! transaction, can be told apart by it.
+//!
+//! To reprice at an upgrade, Go registers further tables with the block height they activate at, see
+//! `register_gas_schedule`. Every call selects the table of the block it runs in before the contract is
+//! instantiated, so all nodes switch at the same block, however they were upgraded. Queries of past blocks are
+//! charged with the table of their block too, and queries of the latest state, which have no height, with the
+//! table of the highest block executed. Queries never count as executing their block.
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+
+const SCHEDULE_ARG: &str = "schedule";
+
+/// The instructions by their name in the text format. Prefixed instructions, like the saturating truncations
+/// and the bulk memory instructions, are charged the default cost.
+const INSTRUCTIONS: &[(&str, u8)] = &[
+    ("unreachable", 0x00),
+    ("nop", 0x01),
+    ("block", 0x02),
+    ("loop", 0x03),
+    ("if", 0x04),
+    ("else", 0x05),
+    ("end", 0x0b),
+    ("br", 0x0c),
+    ("br_if", 0x0d),
+    ("br_table", 0x0e),
+    ("return", 0x0f),
+    ("call", 0x10),
+    ("call_indirect", 0x11),
+    ("drop", 0x1a),
+    ("select", 0x1b),
+    ("select", 0x1c),
+    ("local.get", 0x20),
+    ("local.set", 0x21),
+    ("local.tee", 0x22),
+    ("global.get", 0x23),
+    ("global.set", 0x24),
+    ("table.get", 0x25),
+    ("table.set", 0x26),
+    ("i32.load", 0x28),
+    ("i64.load", 0x29),
+    ("f32.load", 0x2a),
+    ("f64.load", 0x2b),
+    ("i32.load8_s", 0x2c),
+    ("i32.load8_u", 0x2d),
+    ("i32.load16_s", 0x2e),
+    ("i32.load16_u", 0x2f),
+    ("i64.load8_s", 0x30),
+    ("i64.load8_u", 0x31),
+    ("i64.load16_s", 0x32),
+    ("i64.load16_u", 0x33),
+    ("i64.load32_s", 0x34),
+    ("i64.load32_u", 0x35),
+    ("i32.store", 0x36),
+    ("i64.store", 0x37),
+    ("f32.store", 0x38),
+    ("f64.store", 0x39),
+    ("i32.store8", 0x3a),
+    ("i32.store16", 0x3b),
+    ("i64.store8", 0x3c),
+    ("i64.store16", 0x3d),
+    ("i64.store32", 0x3e),
+    ("memory.size", 0x3f),
+    ("memory.grow", 0x40),
+    ("i32.const", 0x41),
+    ("i64.const", 0x42),
+    ("f32.const", 0x43),
+    ("f64.const", 0x44),
+    ("i32.eqz", 0x45),
+    ("i32.eq", 0x46),
+    ("i32.ne", 0x47),
+    ("i32.lt_s", 0x48),
+    ("i32.lt_u", 0x49),
+    ("i32.gt_s", 0x4a),
+    ("i32.gt_u", 0x4b),
+    ("i32.le_s", 0x4c),
+    ("i32.le_u", 0x4d),
+    ("i32.ge_s", 0x4e),
+    ("i32.ge_u", 0x4f),
+    ("i64.eqz", 0x50),
+    ("i64.eq", 0x51),
+    ("i64.ne", 0x52),
+    ("i64.lt_s", 0x53),
+    ("i64.lt_u", 0x54),
+    ("i64.gt_s", 0x55),
+    ("i64.gt_u", 0x56),
+    ("i64.le_s", 0x57),
+    ("i64.le_u", 0x58),
+    ("i64.ge_s", 0x59),
+    ("i64.ge_u", 0x5a),
+    ("f32.eq", 0x5b),
+    ("f32.ne", 0x5c),
+    ("f32.lt", 0x5d),
+    ("f32.gt", 0x5e),
+    ("f32.le", 0x5f),
+    ("f32.ge", 0x60),
+    ("f64.eq", 0x61),
+    ("f64.ne", 0x62),
+    ("f64.lt", 0x63),
+    ("f64.gt", 0x64),
+    ("f64.le", 0x65),
+    ("f64.ge", 0x66),
+    ("i32.clz", 0x67),
+    ("i32.ctz", 0x68),
+    ("i32.popcnt", 0x69),
+    ("i32.add", 0x6a),
+    ("i32.sub", 0x6b),
+    ("i32.mul", 0x6c),
+    ("i32.div_s", 0x6d),
+    ("i32.div_u", 0x6e),
+    ("i32.rem_s", 0x6f),
+    ("i32.rem_u", 0x70),
+    ("i32.and", 0x71),
+    ("i32.or", 0x72),
+    ("i32.xor", 0x73),
+    ("i32.shl", 0x74),
+    ("i32.shr_s", 0x75),
+    ("i32.shr_u", 0x76),
+    ("i32.rotl", 0x77),
+    ("i32.rotr", 0x78),
+    ("i64.clz", 0x79),
+    ("i64.ctz", 0x7a),
+    ("i64.popcnt", 0x7b),
+    ("i64.add", 0x7c),
+    ("i64.sub", 0x7d),
+    ("i64.mul", 0x7e),
+    ("i64.div_s", 0x7f),
+    ("i64.div_u", 0x80),
+    ("i64.rem_s", 0x81),
+    ("i64.rem_u", 0x82),
+    ("i64.and", 0x83),
+    ("i64.or", 0x84),
+    ("i64.xor", 0x85),
+    ("i64.shl", 0x86),
+    ("i64.shr_s", 0x87),
+    ("i64.shr_u", 0x88),
+    ("i64.rotl", 0x89),
+    ("i64.rotr", 0x8a),
+    ("f32.abs", 0x8b),
+    ("f32.neg", 0x8c),
+    ("f32.ceil", 0x8d),
+    ("f32.floor", 0x8e),
+    ("f32.trunc", 0x8f),
+    ("f32.nearest", 0x90),
+    ("f32.sqrt", 0x91),
+    ("f32.add", 0x92),
+    ("f32.sub", 0x93),
+    ("f32.mul", 0x94),
+    ("f32.div", 0x95),
+    ("f32.min", 0x96),
+    ("f32.max", 0x97),
+    ("f32.copysign", 0x98),
+    ("f64.abs", 0x99),
+    ("f64.neg", 0x9a),
+    ("f64.ceil", 0x9b),
+    ("f64.floor", 0x9c),
+    ("f64.trunc", 0x9d),
+    ("f64.nearest", 0x9e),
+    ("f64.sqrt", 0x9f),
+    ("f64.add", 0xa0),
+    ("f64.sub", 0xa1),
+    ("f64.mul", 0xa2),
+    ("f64.div", 0xa3),
+    ("f64.min", 0xa4),
+    ("f64.max", 0xa5),
+    ("f64.copysign", 0xa6),
+    ("i32.wrap_i64", 0xa7),
+    ("i32.trunc_f32_s", 0xa8),
+    ("i32.trunc_f32_u", 0xa9),
+    ("i32.trunc_f64_s", 0xaa),
+    ("i32.trunc_f64_u", 0xab),
+    ("i64.extend_i32_s", 0xac),
+    ("i64.extend_i32_u", 0xad),
+    ("i64.trunc_f32_s", 0xae),
+    ("i64.trunc_f32_u", 0xaf),
+    ("i64.trunc_f64_s", 0xb0),
+    ("i64.trunc_f64_u", 0xb1),
+    ("f32.convert_i32_s", 0xb2),
+    ("f32.convert_i32_u", 0xb3),
+    ("f32.convert_i64_s", 0xb4),
+    ("f32.convert_i64_u", 0xb5),
+    ("f32.demote_f64", 0xb6),
+    ("f64.convert_i32_s", 0xb7),
+    ("f64.convert_i32_u", 0xb8),
+    ("f64.convert_i64_s", 0xb9),
+    ("f64.convert_i64_u", 0xba),
+    ("f64.promote_f32", 0xbb),
+    ("i32.reinterpret_f32", 0xbc),
+    ("i64.reinterpret_f64", 0xbd),
+    ("f32.reinterpret_i32", 0xbe),
+    ("f64.reinterpret_i64", 0xbf),
+    ("i32.extend8_s", 0xc0),
+    ("i32.extend16_s", 0xc1),
+    ("i64.extend8_s", 0xc2),
+    ("i64.extend16_s", 0xc3),
+    ("i64.extend32_s", 0xc4),
+    ("ref.null", 0xd0),
+    ("ref.is_null", 0xd1),
+    ("ref.func", 0xd2),
+];
+
+/// The costs of the `wasm_costs` cache option
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WasmCosts {
+    /// The cost of the instructions without a cost in `instructions`
+    pub default: u64,
+    /// Costs by instruction name, like `i64.div_u`
+    pub instructions: BTreeMap<String, u64>,
+}
+
+impl Default for WasmCosts {
+    fn default() -> Self {
+        WasmCosts {
+            default: 1,
+            instructions: BTreeMap::new(),
+        }
+    }
+}
+
+/// The cost of every opcode, as the VM takes it
+#[derive(Clone, Debug, PartialEq)]
+pub struct WasmCostTable {
+    costs: Vec<u64>,
+    version: u64,
+}
+
+impl WasmCostTable {
+    /// Fails for unknown instructions, and if the default is 0, as execution would be free
+    pub fn new(costs: &WasmCosts) -> Result<Self, Error> {
+        if costs.default == 0 {
+            return Err(Error::invalid_options(
+                "wasm_costs: the default cost must be greater than 0",
+            ));
+        }
+        let mut table = vec![costs.default; 256];
+        for (name, cost) in &costs.instructions {
+            let mut opcodes = INSTRUCTIONS
+                .iter()
+                .filter(|(instruction, _)| instruction == name)
+                .peekable();
+            if opcodes.peek().is_none() {
+                let msg = format!("wasm_costs: unknown instruction {}", name);
+                return Err(Error::invalid_options(msg));
+            }
+            for (_, opcode) in opcodes {
+                table[usize::from(*opcode)] = *cost;
+            }
+        }
+        let mut hasher = Sha256::new();
+        for cost in &table {
+            hasher.update(cost.to_le_bytes());
+        }
+        let hash = hasher.finalize();
+        let version = u64::from_be_bytes(hash[..8].try_into().expect("hash is 32 bytes"));
+        Ok(WasmCostTable {
+            costs: table,
+            version,
+        })
+    }
+
+    /// The costs indexed by opcode
+    pub fn costs(&self) -> &[u64] {
+        &self.costs
+    }
+
+    /// The schedule version, the first 8 bytes of the sha256 of the costs as little endian integers
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+/// Fails for costs other than the defaults in builds with the upstream VM, which charges fixed costs
+pub fn check_vm_support(costs: &WasmCosts) -> Result<(), Error> {
+    if cfg!(feature = "vanilla") && *costs != WasmCosts::default() {
+        return Err(Error::invalid_options(
+            "wasm_costs requires the enclave VM, as the upstream VM charges fixed costs",
+        ));
+    }
+    Ok(())
+}
+
+/// A table of `register_gas_schedule`
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GasSchedule {
+    /// The first block charged with the table
+    pub activation_height: u64,
+    pub costs: WasmCosts,
+}
+
+/// The tables of a cache by activation height
+#[derive(Clone, Debug, PartialEq)]
+pub struct WasmSchedules {
+    tables: BTreeMap<u64, WasmCostTable>,
+    /// The activation height of the table the VM charges with
+    active: u64,
+    /// The highest block a call other than a query ran in
+    highest: u64,
+}
+
+impl WasmSchedules {
+    /// Starts with `initial`, the table of the `wasm_costs` cache option, from height 0
+    pub fn new(initial: WasmCostTable) -> Self {
+        let mut tables = BTreeMap::new();
+        tables.insert(0, initial);
+        WasmSchedules {
+            tables,
+            active: 0,
+            highest: 0,
+        }
+    }
+
+    /// Adds the table of `schedule`. Registering the same table again does nothing, so Go can register its
+    /// schedules on every start. Fails if another table activates at the height, or if a block at or above
+    /// it ran already, as that would change the gas of executed blocks.
+    pub fn register(&mut self, schedule: &GasSchedule) -> Result<(), Error> {
+        let height = schedule.activation_height;
+        let table = WasmCostTable::new(&schedule.costs)?;
+        match self.tables.get(&height) {
+            Some(existing) if *existing == table => return Ok(()),
+            Some(_) => {
+                let msg = format!("another gas schedule activates at height {}", height);
+                return Err(Error::invalid_arg(SCHEDULE_ARG, msg));
+            }
+            None => {}
+        }
+        if height <= self.highest {
+            let msg = format!(
+                "cannot activate a gas schedule at height {}, as block {} ran already",
+                height, self.highest
+            );
+            return Err(Error::invalid_arg(SCHEDULE_ARG, msg));
+        }
+        self.tables.insert(height, table);
+        Ok(())
+    }
+
+    /// Selects the table for a call in the block at `height`, 0 for a query of the latest state. `executes` is
+    /// false for queries, which leave the executed blocks alone. Returns the table if it differs from the one
+    /// selected before.
+    pub fn select(&mut self, height: u64, executes: bool) -> Option<&WasmCostTable> {
+        if executes {
+            self.highest = self.highest.max(height);
+        }
+        let height = if height == 0 { self.highest } else { height };
+        let (&activation_height, table) = self.tables.range(..=height).next_back()?;
+        if activation_height == self.active {
+            return None;
+        }
+        self.active = activation_height;
+        Some(table)
+    }
+
+    /// The table the VM charges with
+    pub fn active(&self) -> &WasmCostTable {
+        &self.tables[&self.active]
+    }
+
+    /// The version of the table the VM charges with
+    pub fn version(&self) -> u64 {
+        self.active().version()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_applies_costs() {
+        let costs: WasmCosts =
+            serde_json::from_str(r#"{"default":2,"instructions":{"i64.div_u":40,"select":3}}"#)
+                .unwrap();
+        let table = WasmCostTable::new(&costs).unwrap();
+        assert_eq!(table.costs().len(), 256);
+        assert_eq!(table.costs()[0x80], 40);
+        assert_eq!(table.costs()[0x1b], 3);
+        assert_eq!(table.costs()[0x1c], 3);
+        assert_eq!(table.costs()[0x6a], 2);
+        assert_eq!(table.costs()[0xfc], 2);
+    }
+
+    #[test]
+    fn new_rejects_invalid_costs() {
+        let mut costs = WasmCosts::default();
+        costs.instructions.insert("i64.frobnicate".to_string(), 1);
+        assert!(WasmCostTable::new(&costs).is_err());
+        let free = WasmCosts {
+            default: 0,
+            instructions: BTreeMap::new(),
+        };
+        assert!(WasmCostTable::new(&free).is_err());
+    }
+
+    #[test]
+    fn version_depends_on_costs_only() {
+        let default = WasmCostTable::new(&WasmCosts::default()).unwrap();
+        // an explicit cost equal to the default charges the same
+        let mut same = WasmCosts::default();
+        same.instructions.insert("nop".to_string(), 1);
+        assert_eq!(
+            WasmCostTable::new(&same).unwrap().version(),
+            default.version()
+        );
+
+        let mut other = WasmCosts::default();
+        other.instructions.insert("nop".to_string(), 0);
+        assert_ne!(
+            WasmCostTable::new(&other).unwrap().version(),
+            default.version()
+        );
+    }
+
+    fn schedule(activation_height: u64, default: u64) -> GasSchedule {
+        GasSchedule {
+            activation_height,
+            costs: WasmCosts {
+                default,
+                instructions: BTreeMap::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn schedules_activate_at_their_height() {
+        let initial = WasmCostTable::new(&WasmCosts::default()).unwrap();
+        let mut schedules = WasmSchedules::new(initial.clone());
+        schedules.register(&schedule(100, 2)).unwrap();
+        schedules.register(&schedule(200, 3)).unwrap();
+        let second = WasmCostTable::new(&schedule(100, 2).costs).unwrap();
+
+        assert_eq!(schedules.select(99, true), None);
+        assert_eq!(schedules.version(), initial.version());
+        assert_eq!(schedules.select(100, true), Some(&second));
+        assert_eq!(schedules.select(150, true), None);
+        assert_eq!(schedules.version(), second.version());
+        assert_eq!(schedules.select(250, true).unwrap().costs()[0], 3);
+        // queries of older blocks are charged with the table of their block
+        assert_eq!(schedules.select(120, false), Some(&second));
+        assert_eq!(schedules.select(0, false).unwrap().costs()[0], 3);
+        assert_eq!(schedules.select(99, false), Some(&initial));
+    }
+
+    #[test]
+    fn queries_do_not_execute_blocks() {
+        let initial = WasmCostTable::new(&WasmCosts::default()).unwrap();
+        let mut schedules = WasmSchedules::new(initial.clone());
+        schedules.select(90, true);
+        // a query of a future block, e.g. one Go has not committed yet
+        schedules.select(150, false);
+        schedules.register(&schedule(100, 2)).unwrap();
+        // queries of the latest state use the highest executed block
+        assert_eq!(schedules.select(0, false), None);
+        assert_eq!(schedules.version(), initial.version());
+    }
+
+    #[test]
+    fn register_protects_executed_blocks() {
+        let initial = WasmCostTable::new(&WasmCosts::default()).unwrap();
+        let mut schedules = WasmSchedules::new(initial);
+        schedules.register(&schedule(100, 2)).unwrap();
+        // the same schedule again, e.g. after a restart
+        schedules.register(&schedule(100, 2)).unwrap();
+        assert!(schedules.register(&schedule(100, 3)).is_err());
+        assert!(schedules.register(&schedule(0, 3)).is_err());
+
+        schedules.select(150, true);
+        assert!(schedules.register(&schedule(150, 3)).is_err());
+        schedules.register(&schedule(151, 3)).unwrap();
+        assert!(schedules.register(&schedule(100, 2)).is_ok());
+    }
+}
@@ -0,0 +1,144 @@
+//! Runs contracts through the extern "C" functions, with the mocks of the `testing` feature standing in for Go.
+
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+use go_cosmwasm::testing::{view, Harness, MockApi, MockQuerier, MockStorage, SET_PRICE};
+use go_cosmwasm::{instantiate, Buffer, GasReport};
+
+static HACKATOM: &[u8] = include_bytes!("../api/testdata/hackatom.wasm");
+
+const FEATURES: &str = "staking";
+
+fn harness() -> (Harness, TempDir) {
+    let tmp_dir = TempDir::new().unwrap();
+    let harness = Harness::new(tmp_dir.path().to_str().unwrap(), FEATURES, b"").unwrap();
+    (harness, tmp_dir)
+}
+
+fn mock_params(sender: &str) -> Vec<u8> {
+    let env = json!({
+        "block": { "height": 123, "time": 1578939743, "chain_id": "foobar" },
+        "message": { "sender": sender, "sent_funds": [{ "denom": "ATOM", "amount": "100" }] },
+        "contract": { "address": "contract" },
+        "contract_key": "",
+    });
+    serde_json::to_vec(&env).unwrap()
+}
+
+fn query_verifier(harness: &mut Harness, code_id: &[u8]) -> Value {
+    let res = harness.query(code_id, br#"{"verifier":{}}"#).unwrap();
+    let res: Value = serde_json::from_slice(&res.data).unwrap();
+    let ok = base64::decode(res["Ok"].as_str().unwrap()).unwrap();
+    serde_json::from_slice(&ok).unwrap()
+}
+
+#[test]
+fn store_and_get_code() {
+    let (mut harness, _tmp_dir) = harness();
+    let code_id = harness.store_code(HACKATOM).unwrap();
+    assert_eq!(code_id.len(), 32);
+    assert_eq!(harness.get_code(&code_id).unwrap(), HACKATOM);
+}
+
+#[test]
+fn store_code_rejects_invalid_wasm() {
+    let (mut harness, _tmp_dir) = harness();
+    assert!(harness.store_code(b"some invalid data").is_err());
+}
+
+#[test]
+fn init_cache_rejects_invalid_options() {
+    let tmp_dir = TempDir::new().unwrap();
+    let dir = tmp_dir.path().to_str().unwrap();
+    assert!(Harness::new(dir, FEATURES, b"{not json").is_err());
+}
+
+#[test]
+fn set_consensus_params_rejects_invalid_params() {
+    let (mut harness, _tmp_dir) = harness();
+    harness
+        .set_consensus_params(br#"{"max_call_depth":3}"#)
+        .unwrap();
+    assert!(harness
+        .set_consensus_params(br#"{"max_memory_pages":0}"#)
+        .is_err());
+    // options of the node are no consensus params
+    assert!(harness
+        .set_consensus_params(br#"{"mmap_modules":true}"#)
+        .is_err());
+}
+
+#[test]
+fn calls_without_cache_fail() {
+    let mut storage = MockStorage::new();
+    let api = MockApi::default();
+    let querier = MockQuerier::new();
+    let mut gas_used = 0u64;
+    let mut gas_report = GasReport::default();
+    let mut err = Buffer::default();
+    let res = instantiate(
+        std::ptr::null_mut(),
+        view(&[0u8; 32]),
+        view(&mock_params("creator")),
+        view(b"{}"),
+        storage.db(),
+        api.api(),
+        querier.querier(),
+        100_000_000,
+        0,
+        Some(&mut gas_used),
+        Some(&mut gas_report),
+        Some(&mut err),
+    );
+    assert_eq!(unsafe { res.consume() }, Vec::<u8>::new());
+    let err = String::from_utf8(unsafe { err.consume() }).unwrap();
+    assert!(err.contains("cache"), "{}", err);
+}
+
+#[test]
+fn unknown_code_id_fails() {
+    let (mut harness, _tmp_dir) = harness();
+    let res = harness.query(&[0u8; 32], br#"{"verifier":{}}"#);
+    assert!(res.is_err());
+}
+
+// Like the Go tests of the contract lifecycle, this needs the messages encrypted for the node's key
+#[test]
+#[ignore]
+fn hackatom_lifecycle() {
+    let (mut harness, _tmp_dir) = harness();
+    let code_id = harness.store_code(HACKATOM).unwrap();
+
+    let init = harness
+        .instantiate(
+            &code_id,
+            &mock_params("creator"),
+            br#"{"verifier": "fred", "beneficiary": "bob"}"#,
+        )
+        .unwrap();
+    assert!(init.gas_used > 0);
+    assert!(init.gas_report.writes >= SET_PRICE);
+    assert!(!harness.storage.data.is_empty());
+    assert_eq!(
+        query_verifier(&mut harness, &code_id),
+        json!({ "verifier": "fred" })
+    );
+
+    let res = harness
+        .handle(&code_id, &mock_params("fred"), br#"{"release":{}}"#)
+        .unwrap();
+    assert!(res.gas_used > 0);
+    let res: Value = serde_json::from_slice(&res.data).unwrap();
+    let send = &res["Ok"]["messages"][0]["bank"]["send"];
+    assert_eq!(send["to_address"], "bob");
+    assert_eq!(send["from_address"], "contract");
+
+    harness
+        .migrate(&code_id, &mock_params("fred"), br#"{"verifier":"alice"}"#)
+        .unwrap();
+    assert_eq!(
+        query_verifier(&mut harness, &code_id),
+        json!({ "verifier": "alice" })
+    );
+}
@@ -8,10 +8,12 @@ package types
 //
 // Env are json encoded to a byte slice before passing to the wasm contract.
 type Env struct {
-	Block    BlockInfo    `json:"block"`
-	Message  MessageInfo  `json:"message"`
-	Contract ContractInfo `json:"contract"`
-	Key      ContractKey  `json:"contract_key"`
+	// SchemaVersion is the version of the fields of the env, see api.EnvSchemaVersions. 0 means version 1.
+	SchemaVersion uint32       `json:"schema_version,omitempty"`
+	Block         BlockInfo    `json:"block"`
+	Message       MessageInfo  `json:"message"`
+	Contract      ContractInfo `json:"contract"`
+	Key           ContractKey  `json:"contract_key"`
 }
 
 type ContractKey string
@@ -2,7 +2,9 @@ package types
 
 import (
 	"encoding/json"
+	"fmt"
 	"strconv"
+	"strings"
 )
 
 // HumanAddress is a printable (typically bech32 encoded) address string. Just use it as a label for developers.
@@ -57,3 +59,201 @@ var _ error = OutOfGasError{}
 func (o OutOfGasError) Error() string {
 	return "Out of gas"
 }
+
+// InitNodeCode tells why initializing a node failed
+type InitNodeCode int32
+
+// These values match the InitNodeResult enum of the Rust library
+const (
+	InitNodeInvalidInput         InitNodeCode = 1
+	InitNodeInvalidMasterCert    InitNodeCode = 2
+	InitNodeMrEnclaveMismatch    InitNodeCode = 3
+	InitNodeMrSignerMismatch     InitNodeCode = 4
+	InitNodeSeedDecryptionFailed InitNodeCode = 5
+	InitNodeSealedFileMismatch   InitNodeCode = 6
+	InitNodeEnclaveError         InitNodeCode = 7
+	InitNodeLibraryMismatch      InitNodeCode = 8
+)
+
+// InitNodeError is returned when the enclave refuses to initialize the node.
+// Msg explains what the node operator should do.
+type InitNodeError struct {
+	Code InitNodeCode
+	Msg  string
+}
+
+var _ error = InitNodeError{}
+
+func (e InitNodeError) Error() string {
+	return fmt.Sprintf("failed to initialize node (code %d): %s", e.Code, e.Msg)
+}
+
+// CallDepthExceededError is returned when a contract call is nested deeper than the configured maximum
+type CallDepthExceededError struct {
+	Msg string
+}
+
+var _ error = CallDepthExceededError{}
+
+func (e CallDepthExceededError) Error() string {
+	return e.Msg
+}
+
+// ResponseTooLargeError is returned when a contract's response exceeds the configured maximum size
+type ResponseTooLargeError struct {
+	Msg string
+}
+
+var _ error = ResponseTooLargeError{}
+
+func (e ResponseTooLargeError) Error() string {
+	return e.Msg
+}
+
+// UnsupportedFeaturesError is returned when storing a code which requires features the cache does not support,
+// see api.UpdateSupportedFeatures
+type UnsupportedFeaturesError struct {
+	// Missing are the required features which are not supported
+	Missing   []string
+	Required  []string
+	Supported []string
+}
+
+var _ error = UnsupportedFeaturesError{}
+
+func (e UnsupportedFeaturesError) Error() string {
+	return fmt.Sprintf("Contract requires unsupported features: %s (required: %s; supported: %s)",
+		strings.Join(e.Missing, ", "), strings.Join(e.Required, ", "), strings.Join(e.Supported, ", "))
+}
+
+// TimeoutError is returned when an enclave call did not finish within the timeout of api.SetEnclaveCallPolicy.
+// The call may be retried once the timed out one has finished.
+type TimeoutError struct {
+	Msg string
+}
+
+var _ error = TimeoutError{}
+
+func (e TimeoutError) Error() string {
+	return e.Msg
+}
+
+// ThrottledError is returned when a query exceeded the limits of api.SetQueryLimits. The query was not run
+// and may be retried later.
+type ThrottledError struct {
+	Msg string
+}
+
+var _ error = ThrottledError{}
+
+func (e ThrottledError) Error() string {
+	return e.Msg
+}
+
+// InvalidEventsError is returned when a contract returned log attributes which api.ConsensusParams.NormalizeEvents
+// rejects, e.g. ones without a key. It fails the call the same way on every node.
+type InvalidEventsError struct {
+	Msg string
+}
+
+var _ error = InvalidEventsError{}
+
+func (e InvalidEventsError) Error() string {
+	return e.Msg
+}
+
+// EventLimitExceededError is returned when a contract result exceeded api.ConsensusParams.EventLimits
+type EventLimitExceededError struct {
+	Msg string
+}
+
+var _ error = EventLimitExceededError{}
+
+func (e EventLimitExceededError) Error() string {
+	return e.Msg
+}
+
+// CallDeniedError is returned when the api.CallAuthorizer of the store denied a contract call. The contract did
+// not run.
+type CallDeniedError struct {
+	Msg string
+}
+
+var _ error = CallDeniedError{}
+
+func (e CallDeniedError) Error() string {
+	return e.Msg
+}
+
+// ContractPausedError is returned when a contract call runs a code paused with api.SetCodePaused. The contract
+// did not run.
+type ContractPausedError struct {
+	Msg string
+}
+
+var _ error = ContractPausedError{}
+
+func (e ContractPausedError) Error() string {
+	return e.Msg
+}
+
+// IllegalReentrancyError is returned when a Go callback of a running contract calls into the library in a way it
+// does not support, e.g. a handle from within a query. Only queries may nest in a running call.
+type IllegalReentrancyError struct {
+	Msg string
+}
+
+var _ error = IllegalReentrancyError{}
+
+func (e IllegalReentrancyError) Error() string {
+	return e.Msg
+}
+
+// IteratorLimitExceededError is returned when a contract had more storage iterators open at once than
+// api.ConsensusParams.MaxOpenIterators allows
+type IteratorLimitExceededError struct {
+	Msg string
+}
+
+var _ error = IteratorLimitExceededError{}
+
+func (e IteratorLimitExceededError) Error() string {
+	return e.Msg
+}
+
+// InvalidMsgError is returned when the message of a call is malformed, e.g. not JSON for a plaintext code or
+// larger than api.ConsensusParams.MaxMsgSize. The message is the same on all nodes and the contract did not run.
+type InvalidMsgError struct {
+	Msg string
+}
+
+var _ error = InvalidMsgError{}
+
+func (e InvalidMsgError) Error() string {
+	return e.Msg
+}
+
+// TcbOutdatedError is returned when IAS tells that the platform of an enclave lacks microcode or BIOS updates,
+// e.g. with the quote status GROUP_OUT_OF_DATE. Once the platform is updated, the node registers again with
+// the payload of api.RefreshRegistration.
+type TcbOutdatedError struct {
+	Msg string
+}
+
+var _ error = TcbOutdatedError{}
+
+func (e TcbOutdatedError) Error() string {
+	return e.Msg
+}
+
+// CapabilityDeniedError is returned when a contract used a host capability its code is denied in
+// api.ConsensusParams.CodeCapabilities, e.g. storage iterators. The call fails on all nodes alike.
+type CapabilityDeniedError struct {
+	Msg string
+}
+
+var _ error = CapabilityDeniedError{}
+
+func (e CapabilityDeniedError) Error() string {
+	return e.Msg
+}